@@ -0,0 +1,857 @@
+//! Canonical ABI exports of the core module.
+//!
+//! With [`CodegenOptions::component`](crate::CodegenOptions::component) set,
+//! the core module also exports one function per function of the space's
+//! WIT interface (see [`crate::wit`]), named `<interface>#<function>`, and a
+//! `cabi_realloc` for the host to allocate argument strings and lists.  They
+//! convert between value cells and the canonical ABI — flat core values
+//! for parameters and scalar results, the component model's memory layout
+//! for everything else — and call the untyped entry points.
+//! [`crate::component`] lifts them into component functions.
+//!
+//! Rendered surfaces are nested node records; `render-*` flattens them into
+//! a node list in pre-order, children referring to nodes by index, with
+//! every prop value as JSON except action references.
+
+use wasm_encoder::{Function, Instruction, ValType};
+
+use crate::compiler::FuncContext;
+use crate::runtime::{memarg, RT_VAL_LIST, RT_VAL_RECORD, RT_VAL_STRING, RT_VAL_VARIANT};
+use crate::stdlib::asm::Asm;
+use crate::types::*;
+use crate::wit::{DefKind, Entry, SpaceWorld, WitType};
+
+type I = Instruction<'static>;
+
+/// Flat parameters beyond this are passed in memory.
+const MAX_FLAT_PARAMS: usize = 16;
+
+/// Indices of the untyped entry points the exports call.
+pub(crate) struct SpaceEntries {
+    pub init: u32,
+    pub dispatch: u32,
+    pub render: u32,
+    pub get_state: u32,
+    pub serialize_state: u32,
+    pub restore_state: u32,
+    pub update: Option<u32>,
+    pub handle_event: Option<u32>,
+}
+
+enum Kind {
+    /// A function of the `space` interface, by index into `world.funcs`.
+    Export(usize),
+    Realloc,
+    /// `$surface_walk(value, nodes, ids)`, shared by the `render-*` exports.
+    SurfaceWalk,
+}
+
+/// The extra functions of a module compiled for a component.
+pub(crate) struct CanonExports {
+    world: SpaceWorld,
+    /// Core signatures the functions need, placed after [`TYPE_COUNT`].
+    signatures: Vec<(Vec<ValType>, Vec<ValType>)>,
+    funcs: Vec<(Kind, u32)>,
+}
+
+impl CanonExports {
+    pub(crate) fn new(world: SpaceWorld) -> Self {
+        let mut exports = Self {
+            world,
+            signatures: Vec::new(),
+            funcs: Vec::new(),
+        };
+        for i in 0..exports.world.funcs.len() {
+            let func = &exports.world.funcs[i];
+            let mut params: Vec<ValType> = func
+                .params
+                .iter()
+                .flat_map(|(_, ty)| exports.world.flat(ty))
+                .collect();
+            if params.len() > MAX_FLAT_PARAMS {
+                params = vec![ValType::I32];
+            }
+            let mut results = func
+                .result
+                .as_ref()
+                .map(|ty| exports.world.flat(ty))
+                .unwrap_or_default();
+            if results.len() > 1 {
+                results = vec![ValType::I32];
+            }
+            let ty = exports.signature(params, results);
+            exports.funcs.push((Kind::Export(i), ty));
+        }
+        let realloc = exports.signature(vec![ValType::I32; 4], vec![ValType::I32]);
+        exports.funcs.push((Kind::Realloc, realloc));
+        if exports.world.surface.is_some() {
+            exports.funcs.push((Kind::SurfaceWalk, TYPE_I32X3_VOID));
+        }
+        exports
+    }
+
+    fn signature(&mut self, params: Vec<ValType>, results: Vec<ValType>) -> u32 {
+        let sig = (params, results);
+        let pos = match self.signatures.iter().position(|s| *s == sig) {
+            Some(pos) => pos,
+            None => {
+                self.signatures.push(sig);
+                self.signatures.len() - 1
+            }
+        };
+        TYPE_COUNT + pos as u32
+    }
+
+    pub(crate) fn signatures(&self) -> &[(Vec<ValType>, Vec<ValType>)] {
+        &self.signatures
+    }
+
+    pub(crate) fn len(&self) -> u32 {
+        self.funcs.len() as u32
+    }
+
+    /// Type index of the `k`th function.
+    pub(crate) fn type_of(&self, k: u32) -> u32 {
+        self.funcs[k as usize].1
+    }
+
+    /// Export name of the `k`th function, if it is exported.
+    pub(crate) fn export_name(&self, k: u32) -> Option<String> {
+        match self.funcs[k as usize].0 {
+            Kind::Export(i) => Some(export_name(&self.world, i)),
+            Kind::Realloc => Some("cabi_realloc".into()),
+            Kind::SurfaceWalk => None,
+        }
+    }
+
+    /// Body of the `k`th function; the functions are placed from index
+    /// `first` on.
+    pub(crate) fn emit(
+        &self,
+        k: u32,
+        first: u32,
+        entries: &SpaceEntries,
+        strings_base: u32,
+        ctx: &mut FuncContext,
+    ) -> Function {
+        let walk = first + self.len() - 1;
+        let ty = self.type_of(k);
+        let params = if ty < TYPE_COUNT {
+            3
+        } else {
+            self.signatures[(ty - TYPE_COUNT) as usize].0.len() as u32
+        };
+        let mut a = Asm::new(params, strings_base);
+        let mut lower = Lower {
+            world: &self.world,
+            ctx,
+        };
+        match self.funcs[k as usize].0 {
+            Kind::Export(i) => lower.export(&mut a, i, entries, walk),
+            Kind::Realloc => realloc(&mut a),
+            Kind::SurfaceWalk => lower.surface_walk(&mut a, walk),
+        }
+        a.finish()
+    }
+}
+
+/// Core export name of the `i`th function of the `space` interface.
+pub(crate) fn export_name(world: &SpaceWorld, i: usize) -> String {
+    format!("{}#{}", world.interface_name(), world.funcs[i].name)
+}
+
+/// `cabi_realloc(old, old_size, align, new_size) -> ptr`.  The heap never
+/// frees: every call takes a fresh block and copies what fits.
+fn realloc(a: &mut Asm) {
+    let p = a.i32_local();
+    a.get(3).get(2).op(I::I32Add).i32(1).op(I::I32Sub).alloc();
+    align_up(a, 2);
+    a.set(p);
+    a.get(0).if_(|a| {
+        // min(old_size, new_size)
+        a.get(p)
+            .get(0)
+            .get(1)
+            .get(3)
+            .get(1)
+            .get(3)
+            .op(I::I32LtU)
+            .op(I::Select);
+        a.op(I::MemoryCopy {
+            src_mem: 0,
+            dst_mem: 0,
+        });
+    });
+    a.get(p);
+}
+
+/// Round the pointer on the stack up to the alignment in local `align`.
+fn align_up(a: &mut Asm, align: u32) {
+    a.get(align)
+        .op(I::I32Add)
+        .i32(1)
+        .op(I::I32Sub)
+        .i32(0)
+        .get(align)
+        .op(I::I32Sub)
+        .op(I::I32And);
+}
+
+/// Allocate `size` bytes (on the stack) aligned to `align`.
+fn alloc_aligned(a: &mut Asm, align: u32) {
+    if align > 1 {
+        a.i32(align as i32 - 1).op(I::I32Add).alloc();
+        a.i32(align as i32 - 1)
+            .op(I::I32Add)
+            .i32(-(align as i32))
+            .op(I::I32And);
+    } else {
+        a.alloc();
+    }
+}
+
+/// Where a canonical value is read from: memory at `local + offset`, or the
+/// `index`th flat parameter, spilled to 8-byte slots at `local`.
+#[derive(Clone, Copy)]
+enum Src {
+    Mem(u32, u32),
+    Flat(u32, u32),
+}
+
+struct Lower<'w, 'c> {
+    world: &'w SpaceWorld,
+    ctx: &'c mut FuncContext,
+}
+
+impl Lower<'_, '_> {
+    // ── Exports ──────────────────────────────────────────────────────────
+
+    fn export(&mut self, a: &mut Asm, i: usize, entries: &SpaceEntries, walk: u32) {
+        let func = &self.world.funcs[i];
+        let tys: Vec<WitType> = func.params.iter().map(|(_, ty)| ty.clone()).collect();
+        match func.entry {
+            Entry::Init => {
+                a.op(I::Call(entries.init));
+            }
+            Entry::Action(id) => {
+                let fields = self.params(a, &tys);
+                let arr = a.i32_local();
+                a.i32(4 * tys.len() as i32).alloc().set(arr);
+                for (j, (ty, src)) in tys.iter().zip(fields).enumerate() {
+                    a.get(arr);
+                    self.lift(a, ty, src);
+                    a.store(4 * j as u64);
+                }
+                a.i32(id as i32)
+                    .get(arr)
+                    .i32(tys.len() as i32)
+                    .rt(RT_VAL_LIST)
+                    .i32(0)
+                    .op(I::Call(entries.dispatch));
+            }
+            Entry::GetState => {
+                let cell = a.i32_local();
+                a.op(I::Call(entries.get_state)).set(cell);
+                let ty = func.result.clone().expect("get-state returns the state");
+                self.ret(a, &ty, cell);
+            }
+            Entry::SerializeState => {
+                let (ptr, len, r) = (a.i32_local(), a.i32_local(), a.i32_local());
+                a.op(I::Call(entries.serialize_state)).set(len).set(ptr);
+                a.i32(8);
+                alloc_aligned(a, 4);
+                a.tee(r).get(ptr).store(0);
+                a.get(r).get(len).store(4);
+                a.get(r);
+            }
+            Entry::RestoreState => {
+                a.get(0).get(1).op(I::Call(entries.restore_state));
+            }
+            Entry::Render(view) => {
+                let (nodes, ids, r) = (a.i32_local(), a.i32_local(), a.i32_local());
+                a.call("$sb_new").set(nodes);
+                a.call("$sb_new").set(ids);
+                a.i32(view as i32)
+                    .op(I::Call(entries.render))
+                    .get(nodes)
+                    .get(ids)
+                    .op(I::Call(walk));
+                a.i32(16);
+                alloc_aligned(a, 4);
+                a.set(r);
+                finish_list(a, ids, 4, r, 0);
+                finish_list(a, nodes, NODE_SIZE, r, 8);
+                a.get(r);
+            }
+            Entry::Update => {
+                let update = entries.update.expect("space has update");
+                a.get(0).number().op(I::Call(update));
+            }
+            Entry::HandleEvent => {
+                let handle_event = entries.handle_event.expect("space has handleEvent");
+                let (cur, v) = (a.i32_local(), a.i32_local());
+                a.i32(8).alloc().set(cur);
+                a.get(cur).get(0).store(0);
+                a.get(cur).get(0).get(1).op(I::I32Add).store(4);
+                a.get(cur)
+                    .call("$json_value")
+                    .tee(v)
+                    .op(I::I32Eqz)
+                    .if_(|a| {
+                        a.i32(0).ret();
+                    });
+                a.get(v).op(I::Call(handle_event));
+                a.i32(1);
+            }
+        }
+    }
+
+    /// Spill the parameters of a `tys` tuple, returning where each is read.
+    fn params(&mut self, a: &mut Asm, tys: &[WitType]) -> Vec<Src> {
+        let flat: Vec<ValType> = tys.iter().flat_map(|t| self.world.flat(t)).collect();
+        if flat.len() > MAX_FLAT_PARAMS {
+            let (offsets, _) = self.world.struct_layout(tys);
+            return offsets.into_iter().map(|o| Src::Mem(0, o)).collect();
+        }
+        let base = a.i32_local();
+        a.i32(8 * flat.len().max(1) as i32).alloc().set(base);
+        for (k, vt) in flat.iter().enumerate() {
+            a.get(base).get(k as u32);
+            let offset = 8 * k as u64;
+            match vt {
+                ValType::F64 => a.f64_store(offset),
+                ValType::I64 => a.op(I::I64Store(memarg(offset, 3))),
+                _ => a.store(offset),
+            };
+        }
+        let mut index = 0;
+        tys.iter()
+            .map(|t| {
+                let src = Src::Flat(base, index);
+                index += self.world.flat(t).len() as u32;
+                src
+            })
+            .collect()
+    }
+
+    /// Return the cell in `cell` as a `ty` result.
+    fn ret(&mut self, a: &mut Asm, ty: &WitType, cell: u32) {
+        let flat = self.world.flat(ty);
+        let r = a.i32_local();
+        a.i32(self.world.size(ty).max(8) as i32);
+        alloc_aligned(a, 8);
+        a.set(r);
+        self.store(a, ty, cell, r, 0);
+        a.get(r);
+        if flat.len() == 1 {
+            match (flat[0], self.world.size(ty)) {
+                (ValType::F64, _) => a.f64_load(0),
+                (ValType::I64, _) => a.op(I::I64Load(memarg(0, 3))),
+                (_, 1) => a.load8(0),
+                (_, 2) => a.op(I::I32Load16U(memarg(0, 1))),
+                _ => a.load(0),
+            };
+        }
+    }
+
+    // ── Canonical → cell ─────────────────────────────────────────────────
+
+    fn read_i32(a: &mut Asm, src: Src, extra: u32) {
+        match src {
+            Src::Mem(base, offset) => a.get(base).load((offset + extra) as u64),
+            Src::Flat(base, index) => a.get(base).load(8 * (index + extra / 4) as u64),
+        };
+    }
+
+    /// Read a discriminant of `size` bytes.
+    fn read_disc(a: &mut Asm, src: Src, size: u32) {
+        match (src, size) {
+            (Src::Mem(base, offset), 1) => a.get(base).load8(offset as u64),
+            (Src::Mem(base, offset), 2) => a.get(base).op(I::I32Load16U(memarg(offset as u64, 1))),
+            _ => {
+                Self::read_i32(a, src, 0);
+                a
+            }
+        };
+    }
+
+    /// Source of the `j`th of `items`, laid out as a tuple from `src`.
+    fn field(&self, src: Src, items: &[WitType], j: usize) -> Src {
+        match src {
+            Src::Mem(base, offset) => Src::Mem(base, offset + self.world.struct_layout(items).0[j]),
+            Src::Flat(base, index) => {
+                let skip: usize = items[..j].iter().map(|t| self.world.flat(t).len()).sum();
+                Src::Flat(base, index + skip as u32)
+            }
+        }
+    }
+
+    /// Source of the payload of a variant-like `ty`.
+    fn payload(&self, src: Src, ty: &WitType) -> Src {
+        match src {
+            Src::Mem(base, offset) => Src::Mem(base, offset + self.world.variant_layout(ty).0),
+            Src::Flat(base, index) => Src::Flat(base, index + 1),
+        }
+    }
+
+    /// Push a cell holding the canonical `ty` at `src`.
+    fn lift(&mut self, a: &mut Asm, ty: &WitType, src: Src) {
+        match ty {
+            WitType::F64 => {
+                match src {
+                    Src::Mem(base, offset) => a.get(base).f64_load(offset as u64),
+                    Src::Flat(base, index) => a.get(base).f64_load(8 * index as u64),
+                };
+                a.number();
+            }
+            WitType::Bool => {
+                Self::read_disc(a, src, 1);
+                a.bool();
+            }
+            WitType::U32 => {
+                Self::read_i32(a, src, 0);
+                a.op(I::F64ConvertI32U).number();
+            }
+            WitType::String => {
+                Self::read_i32(a, src, 0);
+                Self::read_i32(a, src, 4);
+                a.rt(RT_VAL_STRING);
+            }
+            WitType::List(item) => {
+                let (p, n, arr, i, e) = (
+                    a.i32_local(),
+                    a.i32_local(),
+                    a.i32_local(),
+                    a.i32_local(),
+                    a.i32_local(),
+                );
+                Self::read_i32(a, src, 0);
+                a.set(p);
+                Self::read_i32(a, src, 4);
+                a.set(n);
+                a.get(n).i32(4).op(I::I32Mul).alloc().set(arr);
+                let size = self.world.size(item) as i32;
+                a.for_(i, n, |a| {
+                    a.get(p).get(i).i32(size).op(I::I32Mul).op(I::I32Add).set(e);
+                    a.slot(arr, i);
+                    self.lift(a, item, Src::Mem(e, 0));
+                    a.store(0);
+                });
+                a.get(arr).get(n).rt(RT_VAL_LIST);
+            }
+            WitType::Tuple(_) => unreachable!("tuples are only variant payloads"),
+            WitType::Def(idx) => {
+                if let DefKind::Record(fields) = &self.world.types[*idx].kind {
+                    let tys: Vec<WitType> = fields.iter().map(|f| f.ty.clone()).collect();
+                    let entries = a.i32_local();
+                    a.i32(12 * fields.len() as i32).alloc().set(entries);
+                    for (j, field) in fields.iter().enumerate() {
+                        let (kp, kl) = self.ctx.intern_string(&field.key);
+                        let at = 12 * j as u64;
+                        a.get(entries).i32(kp as i32).store(at);
+                        a.get(entries).i32(kl as i32).store(at + 4);
+                        a.get(entries);
+                        let field_src = self.field(src, &tys, j);
+                        self.lift(a, &field.ty, field_src);
+                        a.store(at + 8);
+                    }
+                    a.get(entries).i32(fields.len() as i32).rt(RT_VAL_RECORD);
+                } else {
+                    self.lift_variant(a, ty, src);
+                }
+            }
+            WitType::Option(_) | WitType::Result(..) => self.lift_variant(a, ty, src),
+        }
+    }
+
+    fn lift_variant(&mut self, a: &mut Asm, ty: &WitType, src: Src) {
+        let cases = self.world.cases(ty);
+        let (d, r) = (a.i32_local(), a.i32_local());
+        Self::read_disc(a, src, crate::wit::discriminant_size(cases.len()));
+        a.set(d);
+        let payload_src = self.payload(src, ty);
+        let ids = self.case_ids(ty);
+        for (k, payload) in cases.iter().enumerate() {
+            a.get(d).i32(k as i32).op(I::I32Eq).if_(|a| {
+                match (ty, payload) {
+                    (WitType::Option(_), None) => {
+                        a.nil();
+                    }
+                    (WitType::Option(_), Some(item)) => self.lift(a, item, payload_src),
+                    (WitType::Result(..), _) => {
+                        a.i32(ids[k] as i32);
+                        match payload {
+                            Some(item) => self.lift(a, item, payload_src),
+                            None => {
+                                a.nil();
+                            }
+                        }
+                        a.rt(RT_VAL_VARIANT);
+                    }
+                    _ => {
+                        let fields = self.case_fields(ty, k);
+                        let arr = a.i32_local();
+                        a.i32(ids[k] as i32);
+                        a.i32(4 * fields.len() as i32).alloc().set(arr);
+                        for j in 0..fields.len() {
+                            let field_src = if fields.len() == 1 {
+                                payload_src
+                            } else {
+                                self.field(payload_src, &fields, j)
+                            };
+                            a.get(arr);
+                            self.lift(a, &fields[j], field_src);
+                            a.store(4 * j as u64);
+                        }
+                        a.get(arr).i32(fields.len() as i32).rt(RT_VAL_LIST);
+                        a.rt(RT_VAL_VARIANT);
+                    }
+                }
+                a.set(r);
+            });
+        }
+        a.get(r);
+    }
+
+    /// Runtime variant id of each case of `ty`.
+    fn case_ids(&self, ty: &WitType) -> Vec<u32> {
+        match ty {
+            WitType::Result(..) => vec![VARIANT_OK, VARIANT_ERR],
+            WitType::Def(idx) => match &self.world.types[*idx].kind {
+                DefKind::Variant(cases) | DefKind::Enum(cases) => {
+                    cases.iter().map(|c| c.id).collect()
+                }
+                DefKind::Record(_) => Vec::new(),
+            },
+            _ => vec![0, 1],
+        }
+    }
+
+    /// Payload fields of case `k` of a user sum type.
+    fn case_fields(&self, ty: &WitType, k: usize) -> Vec<WitType> {
+        match ty {
+            WitType::Def(idx) => match &self.world.types[*idx].kind {
+                DefKind::Variant(cases) | DefKind::Enum(cases) => cases[k].fields.clone(),
+                DefKind::Record(_) => Vec::new(),
+            },
+            _ => Vec::new(),
+        }
+    }
+
+    // ── Cell → canonical ─────────────────────────────────────────────────
+
+    /// Write the cell in local `cell` as a canonical `ty` at `base + offset`.
+    fn store(&mut self, a: &mut Asm, ty: &WitType, cell: u32, base: u32, offset: u32) {
+        let at = offset as u64;
+        match ty {
+            WitType::F64 => {
+                a.get(base).num(cell).f64_store(at);
+            }
+            WitType::Bool => {
+                a.get(base).w1(cell).store8(at);
+            }
+            WitType::U32 => {
+                a.get(base).num(cell).op(I::I32TruncSatF64U).store(at);
+            }
+            WitType::String => {
+                a.get(base).w1(cell).store(at);
+                a.get(base).w2(cell).store(at + 4);
+            }
+            WitType::List(item) => {
+                let (n, cells, buf, i, e, c) = (
+                    a.i32_local(),
+                    a.i32_local(),
+                    a.i32_local(),
+                    a.i32_local(),
+                    a.i32_local(),
+                    a.i32_local(),
+                );
+                let size = self.world.size(item) as i32;
+                a.w2(cell).set(n).w1(cell).set(cells);
+                a.get(n).i32(size).op(I::I32Mul);
+                alloc_aligned(a, self.world.align(item));
+                a.set(buf);
+                a.for_(i, n, |a| {
+                    a.get(buf)
+                        .get(i)
+                        .i32(size)
+                        .op(I::I32Mul)
+                        .op(I::I32Add)
+                        .set(e);
+                    a.slot(cells, i).load(0).set(c);
+                    self.store(a, item, c, e, 0);
+                });
+                a.get(base).get(buf).store(at);
+                a.get(base).get(n).store(at + 4);
+            }
+            WitType::Option(item) => {
+                let payload = offset + self.world.variant_layout(ty).0;
+                // A missing field (0) or `nil` is `none`
+                a.get(cell).select_i32(
+                    |a| {
+                        a.tag(cell).i32(TAG_NIL).op(I::I32Eq);
+                    },
+                    |a| {
+                        a.i32(1);
+                    },
+                );
+                a.if_else(
+                    wasm_encoder::BlockType::Empty,
+                    |a| {
+                        a.get(base).i32(0).store8(at);
+                    },
+                    |a| {
+                        a.get(base).i32(1).store8(at);
+                        self.store(a, item, cell, base, payload);
+                    },
+                );
+            }
+            WitType::Result(ok, err) => {
+                let payload = offset + self.world.variant_layout(ty).0;
+                let p = a.i32_local();
+                a.w2(cell).set(p);
+                a.get(base)
+                    .w1(cell)
+                    .i32(VARIANT_OK as i32)
+                    .op(I::I32Ne)
+                    .store8(at);
+                if let Some(ok) = ok {
+                    a.w1(cell).i32(VARIANT_OK as i32).op(I::I32Eq).if_(|a| {
+                        self.store(a, ok, p, base, payload);
+                    });
+                }
+                if let Some(err) = err {
+                    a.w1(cell).i32(VARIANT_OK as i32).op(I::I32Ne).if_(|a| {
+                        self.store(a, err, p, base, payload);
+                    });
+                }
+            }
+            WitType::Tuple(_) => unreachable!("tuples are only variant payloads"),
+            WitType::Def(idx) => match &self.world.types[*idx].kind {
+                DefKind::Record(fields) => {
+                    let tys: Vec<WitType> = fields.iter().map(|f| f.ty.clone()).collect();
+                    let (offsets, _) = self.world.struct_layout(&tys);
+                    let (e, v) = (a.i32_local(), a.i32_local());
+                    for (field, field_offset) in fields.iter().zip(offsets) {
+                        let (kp, kl) = self.ctx.intern_string(&field.key);
+                        a.get(cell)
+                            .i32(kp as i32)
+                            .i32(kl as i32)
+                            .call("$record_find")
+                            .tee(e);
+                        a.select_i32(
+                            |a| {
+                                a.get(e).load(8);
+                            },
+                            |a| {
+                                a.i32(0);
+                            },
+                        );
+                        a.set(v);
+                        self.store(a, &field.ty, v, base, offset + field_offset);
+                    }
+                }
+                DefKind::Variant(cases) | DefKind::Enum(cases) => {
+                    let (payload, _, _) = self.world.variant_layout(ty);
+                    let disc = crate::wit::discriminant_size(cases.len());
+                    let (d, list, f) = (a.i32_local(), a.i32_local(), a.i32_local());
+                    a.w1(cell).i32(cases[0].id as i32).op(I::I32Sub).set(d);
+                    a.get(base).get(d);
+                    match disc {
+                        1 => a.store8(at),
+                        2 => a.op(I::I32Store16(memarg(at, 1))),
+                        _ => a.store(at),
+                    };
+                    a.w2(cell).set(list);
+                    for (k, case) in cases.iter().enumerate() {
+                        if case.fields.is_empty() {
+                            continue;
+                        }
+                        let (field_offsets, _) = self.world.struct_layout(&case.fields);
+                        a.get(d).i32(k as i32).op(I::I32Eq).if_(|a| {
+                            for (j, field) in case.fields.iter().enumerate() {
+                                a.w1(list).load(4 * j as u64).set(f);
+                                self.store(a, field, f, base, offset + payload + field_offsets[j]);
+                            }
+                        });
+                    }
+                }
+            },
+        }
+    }
+
+    // ── Surfaces ─────────────────────────────────────────────────────────
+
+    /// `$surface_walk(value, nodes, ids)`: append the nodes of a rendered
+    /// value to the `nodes` byte builder (one canonical `node` each) and
+    /// their indices to `ids`, descending into lists.
+    fn surface_walk(&mut self, a: &mut Asm, walk: u32) {
+        let (n, i, id, e, props, buf, v, sb, kids) = (
+            a.i32_local(),
+            a.i32_local(),
+            a.i32_local(),
+            a.i32_local(),
+            a.i32_local(),
+            a.i32_local(),
+            a.i32_local(),
+            a.i32_local(),
+            a.i32_local(),
+        );
+        let component = self.ctx.intern_string("component");
+        let props_key = self.ctx.intern_string("props");
+        let children = self.ctx.intern_string("children");
+
+        a.tag(0).i32(TAG_LIST).op(I::I32Eq).if_(|a| {
+            a.w2(0).set(n);
+            a.for_(i, n, |a| {
+                a.w1(0)
+                    .get(i)
+                    .i32(4)
+                    .op(I::I32Mul)
+                    .op(I::I32Add)
+                    .load(0)
+                    .get(1)
+                    .get(2)
+                    .op(I::Call(walk));
+            });
+            a.ret();
+        });
+        a.tag(0).i32(TAG_RECORD).op(I::I32Ne).if_(|a| {
+            a.ret();
+        });
+
+        // id = nodes.len / NODE_SIZE; reserve the node and record its id
+        a.get(1)
+            .load(4)
+            .i32(NODE_SIZE as i32)
+            .op(I::I32DivU)
+            .set(id);
+        a.get(1)
+            .i32(NODE_SIZE as i32)
+            .call("$sb_reserve")
+            .op(I::Drop);
+        a.get(2).i32(4).call("$sb_reserve").get(id).store(0);
+
+        // node.component; the builder may move as it grows, so the node's
+        // address is recomputed for every write
+        let node = |a: &mut Asm| {
+            a.get(1)
+                .load(0)
+                .get(id)
+                .i32(NODE_SIZE as i32)
+                .op(I::I32Mul)
+                .op(I::I32Add);
+        };
+        node(a);
+        a.i32(0).store(0);
+        node(a);
+        a.i32(0).store(4);
+        a.get(0)
+            .i32(component.0 as i32)
+            .i32(component.1 as i32)
+            .call("$record_find")
+            .tee(e)
+            .if_(|a| {
+                a.get(e).load(8).set(v);
+                node(a);
+                a.w1(v).store(0);
+                node(a);
+                a.w2(v).store(4);
+            });
+
+        // node.props: name and JSON value (or action id) per prop
+        a.i32(0).set(n).i32(0).set(buf);
+        a.get(0)
+            .i32(props_key.0 as i32)
+            .i32(props_key.1 as i32)
+            .call("$record_find")
+            .tee(e)
+            .if_(|a| {
+                a.get(e)
+                    .load(8)
+                    .tee(props)
+                    .load(0)
+                    .i32(TAG_RECORD)
+                    .op(I::I32Eq)
+                    .if_(|a| {
+                        a.w2(props).set(n);
+                        a.get(n).i32(PROP_SIZE as i32).op(I::I32Mul);
+                        alloc_aligned(a, 4);
+                        a.set(buf);
+                        a.for_(i, n, |a| {
+                            a.w1(props)
+                                .get(i)
+                                .i32(12)
+                                .op(I::I32Mul)
+                                .op(I::I32Add)
+                                .set(e);
+                            a.get(buf)
+                                .get(i)
+                                .i32(PROP_SIZE as i32)
+                                .op(I::I32Mul)
+                                .op(I::I32Add);
+                            a.set(kids);
+                            a.get(kids).get(e).load(0).store(0);
+                            a.get(kids).get(e).load(4).store(4);
+                            a.get(e).load(8).set(v);
+                            a.tag(v).i32(TAG_ACTION_REF).op(I::I32Eq).if_else(
+                                wasm_encoder::BlockType::Empty,
+                                |a| {
+                                    a.get(kids).i32(0).store8(8);
+                                    a.get(kids).w1(v).store(12);
+                                },
+                                |a| {
+                                    a.call("$sb_new").set(sb);
+                                    a.get(sb).get(v).call("$json_into");
+                                    a.get(kids).i32(1).store8(8);
+                                    a.get(kids).get(sb).load(0).store(12);
+                                    a.get(kids).get(sb).load(4).store(16);
+                                },
+                            );
+                        });
+                    });
+            });
+        node(a);
+        a.get(buf).store(8);
+        node(a);
+        a.get(n).store(12);
+
+        // node.children, walked after the node so they get later ids
+        a.call("$sb_new").set(kids);
+        a.get(0)
+            .i32(children.0 as i32)
+            .i32(children.1 as i32)
+            .call("$record_find")
+            .tee(e)
+            .if_(|a| {
+                a.get(e).load(8).get(1).get(kids).op(I::Call(walk));
+            });
+        node(a);
+        a.set(buf);
+        finish_list(a, kids, 4, buf, 16);
+    }
+}
+
+/// Canonical size of a surface `node` record.
+const NODE_SIZE: u32 = 24;
+/// Canonical size of a `prop` record.
+const PROP_SIZE: u32 = 20;
+
+/// Store the elements of byte builder `sb` as a canonical list of
+/// `elem_size`-byte elements at `base + offset`, copied to aligned memory.
+fn finish_list(a: &mut Asm, sb: u32, elem_size: u32, base: u32, offset: u64) {
+    let (len, buf) = (a.i32_local(), a.i32_local());
+    a.get(sb).load(4).set(len);
+    a.get(len);
+    alloc_aligned(a, 4);
+    a.tee(buf).get(sb).load(0).get(len).op(I::MemoryCopy {
+        src_mem: 0,
+        dst_mem: 0,
+    });
+    a.get(base).get(buf).store(offset);
+    a.get(base)
+        .get(len)
+        .i32(elem_size as i32)
+        .op(I::I32DivU)
+        .store(offset + 4);
+}
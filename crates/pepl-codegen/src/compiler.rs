@@ -9,8 +9,11 @@
 //! 6. Validate with `wasmparser`
 
 use std::collections::HashMap;
+use std::rc::Rc;
 
 use pepl_types::ast::*;
+use pepl_types::derived::RecomputePlan;
+use pepl_types::{ErrorCode, FrameKind, RuntimeFrame, Span};
 use wasm_encoder::{
     CodeSection, ConstExpr, CustomSection, DataSection, ElementSection, Elements,
     EntityType, ExportKind, ExportSection, Function, FunctionSection, GlobalSection,
@@ -18,13 +21,22 @@ use wasm_encoder::{
     RefType, TableSection, TableType, TypeSection, ValType,
 };
 
+use crate::canon::{CanonExports, SpaceEntries};
 use crate::error::{CodegenError, CodegenResult};
+use crate::layout::RecordLayouts;
+use crate::optimize::{self, GasCarry, OptLevel};
 use crate::runtime::{
     self, memarg, rt_func_idx, DataSegmentTracker, RT_FUNC_COUNT, RT_VAL_LIST_GET,
-    RT_VAL_RECORD_GET,
 };
-use crate::source_map::{FuncKind, SourceMap};
+use crate::source_map::{FuncKind, SourceMap, TrapSite};
+use crate::stdlib::{self, STDLIB_FUNC_COUNT};
 use crate::types::*;
+use crate::unbox::{Scalar, ScalarTypes};
+use crate::wit::SpaceWorld;
+
+/// Index of the first space-level function (`init`): the imports, runtime
+/// helpers and compiled stdlib come before it.
+const SPACE_FUNC_BASE: u32 = IMPORT_COUNT + RT_FUNC_COUNT + STDLIB_FUNC_COUNT;
 
 // ══════════════════════════════════════════════════════════════════════════════
 // Public API
@@ -35,16 +47,65 @@ use crate::types::*;
 /// Returns the raw bytes of a valid WebAssembly module on success, or a
 /// [`CodegenError`] describing what went wrong.
 pub fn compile(program: &Program) -> CodegenResult<Vec<u8>> {
-    let mut compiler = Compiler::new(program);
-    let (wasm, _source_map) = compiler.compile()?;
+    let (wasm, _source_map) = compile_with_options(program, CodegenOptions::default())?;
     Ok(wasm)
 }
 
 /// Compile a validated PEPL [`Program`] and return both the WASM binary
 /// and a [`SourceMap`] mapping WASM function indices to PEPL source spans.
 pub fn compile_with_source_map(program: &Program) -> CodegenResult<(Vec<u8>, SourceMap)> {
-    let mut compiler = Compiler::new(program);
-    compiler.compile()
+    compile_with_options(program, CodegenOptions::default())
+}
+
+/// Knobs for [`compile_with_options`].  The default reproduces
+/// [`compile_with_source_map`].
+#[derive(Debug, Clone, Default)]
+pub struct CodegenOptions {
+    /// Optimisation level (see [`crate::optimize`]).
+    pub opt_level: OptLevel,
+    /// Checked scalar types of expressions; expressions proved to be
+    /// `number` / `bool` stay unboxed (see [`crate::unbox`]).
+    pub scalar_types: ScalarTypes,
+    /// Checked record layouts; field reads and nested `set`s on them use
+    /// fixed slots (see [`crate::layout`]).
+    pub record_layouts: RecordLayouts,
+    /// Also export the canonical ABI functions of the space's WIT world
+    /// (see [`crate::canon`]), so the module can be wrapped with
+    /// [`crate::component::wrap`].
+    pub component: bool,
+    /// Extra custom sections `(name, contents)`, appended after the source
+    /// map in order.
+    pub custom_sections: Vec<(String, Vec<u8>)>,
+}
+
+/// Compile a validated PEPL [`Program`] with explicit [`CodegenOptions`],
+/// returning the WASM binary and its [`SourceMap`].
+pub fn compile_with_options(
+    program: &Program,
+    options: CodegenOptions,
+) -> CodegenResult<(Vec<u8>, SourceMap)> {
+    let canon = if options.component {
+        Some(CanonExports::new(SpaceWorld::new(program)?))
+    } else {
+        None
+    };
+    // Planned before folding, which may drop `set`s and reads: the
+    // evaluator recomputes (and charges for) what the source says
+    let recompute = RecomputePlan::new(&program.space.body);
+    let opt_level = options.opt_level;
+    let (wasm, mut source_map) = match opt_level {
+        OptLevel::O0 => {
+            Compiler::new(program, options, GasCarry::default(), canon, recompute).compile()?
+        }
+        OptLevel::O1 => {
+            let (folded, carry) = optimize::fold_program_with_gas(program);
+            Compiler::new(&folded, options, carry, canon, recompute).compile()?
+        }
+    };
+    // O0 keeps every runtime helper, but not stdlib functions nothing calls
+    let wasm = optimize::shake_runtime(&wasm, &mut source_map, opt_level == OptLevel::O1)?;
+    wasmparser::validate(&wasm).map_err(|e| CodegenError::ValidationFailed(format!("{e}")))?;
+    Ok((wasm, source_map))
 }
 
 // ══════════════════════════════════════════════════════════════════════════════
@@ -79,21 +140,51 @@ struct Compiler<'a> {
     num_test_funcs: u32,
     /// Source map built during codegen.
     source_map: SourceMap,
+    /// Checked scalar types of expressions (empty → everything boxed).
+    scalars: Rc<ScalarTypes>,
+    /// Checked record layouts (empty → every field read is a key lookup).
+    layouts: Rc<RecordLayouts>,
+    /// Module-wide string cache: strings interned by earlier functions are
+    /// reused instead of emitted again, so each record key has one address.
+    string_cache: HashMap<String, (u32, u32)>,
+    /// Gas of the nodes the optimiser folded away (empty at `O0`).
+    gas_carry: Rc<GasCarry>,
+    /// Canonical ABI exports, when compiling for a component.
+    canon: Option<CanonExports>,
+    /// Index of the first canonical ABI function.
+    canon_base: u32,
+    /// Caller-supplied custom sections (see [`CodegenOptions::custom_sections`]).
+    custom_sections: Vec<(String, Vec<u8>)>,
+    /// Derived fields each entry point recomputes.
+    recompute: RecomputePlan,
 }
 
 /// A lambda body collected during expression codegen for deferred compilation.
 #[derive(Clone)]
 pub struct LambdaBody {
-    pub params: Vec<pepl_types::ast::Param>,
+    pub params: Vec<pepl_types::ast::LambdaParam>,
     pub body: pepl_types::ast::Block,
     pub captured: Vec<String>,
+    /// Enclosing frames at the definition site, ending with the lambda's own.
+    pub frames: Vec<RuntimeFrame>,
 }
 
 impl<'a> Compiler<'a> {
-    fn new(program: &'a Program) -> Self {
+    fn new(
+        program: &'a Program,
+        options: CodegenOptions,
+        gas_carry: GasCarry,
+        canon: Option<CanonExports>,
+        recompute: RecomputePlan,
+    ) -> Self {
+        let data = DataSegmentTracker::new();
+        let mut string_cache = HashMap::new();
+        string_cache.insert("true".to_string(), (data.true_ptr, data.true_len));
+        string_cache.insert("false".to_string(), (data.false_ptr, data.false_len));
+        string_cache.insert("nil".to_string(), (data.nil_ptr, data.nil_len));
         Self {
             program,
-            data: DataSegmentTracker::new(),
+            data,
             user_data: Vec::new(),
             state_field_names: Vec::new(),
             action_names: Vec::new(),
@@ -104,6 +195,14 @@ impl<'a> Compiler<'a> {
             num_space_funcs: 0,
             num_test_funcs: 0,
             source_map: SourceMap::new(),
+            scalars: Rc::new(options.scalar_types),
+            layouts: Rc::new(options.record_layouts),
+            string_cache,
+            gas_carry: Rc::new(gas_carry),
+            canon,
+            canon_base: 0,
+            custom_sections: options.custom_sections,
+            recompute,
         }
     }
 
@@ -159,11 +258,19 @@ impl<'a> Compiler<'a> {
         // 9b. Custom section (source map)
         let sm_bytes = self.source_map.to_json();
         let sm_custom = CustomSection {
-            name: std::borrow::Cow::Borrowed("pepl_source_map"),
+            name: std::borrow::Cow::Borrowed(optimize::SOURCE_MAP_SECTION),
             data: std::borrow::Cow::Owned(sm_bytes),
         };
         module.section(&sm_custom);
 
+        // 9c. Caller-supplied custom sections
+        for (name, data) in &self.custom_sections {
+            module.section(&CustomSection {
+                name: std::borrow::Cow::Borrowed(name),
+                data: std::borrow::Cow::Borrowed(data),
+            });
+        }
+
         let wasm_bytes = module.finish();
 
         // 10. Validate
@@ -211,6 +318,10 @@ impl<'a> Compiler<'a> {
                 }
             }
         }
+
+        // Results produced by the stdlib (`json.parse`, `convert.*`)
+        self.variant_ids.entry("Ok".to_string()).or_insert(VARIANT_OK);
+        self.variant_ids.entry("Err".to_string()).or_insert(VARIANT_ERR);
     }
 
     // ── Type section ─────────────────────────────────────────────────────
@@ -250,6 +361,30 @@ impl<'a> Compiler<'a> {
             vec![ValType::I32, ValType::I32, ValType::I32],
             vec![],
         );
+        // TYPE_F64_F64: (f64) -> f64
+        types.ty().function(vec![ValType::F64], vec![ValType::F64]);
+        // TYPE_F64X2_F64: (f64, f64) -> f64
+        types
+            .ty()
+            .function(vec![ValType::F64, ValType::F64], vec![ValType::F64]);
+        // TYPE_I32X2_F64: (i32, i32) -> f64
+        types
+            .ty()
+            .function(vec![ValType::I32, ValType::I32], vec![ValType::F64]);
+        // TYPE_I32_I64_I32_VOID: (i32, i64, i32) -> ()
+        types.ty().function(
+            vec![ValType::I32, ValType::I64, ValType::I32],
+            vec![],
+        );
+        // TYPE_VOID_I32X2: () -> (i32, i32)
+        types.ty().function(vec![], vec![ValType::I32, ValType::I32]);
+
+        // Signatures of the canonical ABI exports
+        if let Some(canon) = &self.canon {
+            for (params, results) in canon.signatures() {
+                types.ty().function(params.clone(), results.clone());
+            }
+        }
 
         types
     }
@@ -290,14 +425,16 @@ impl<'a> Compiler<'a> {
     fn emit_globals(&self) -> GlobalSection {
         let mut globals = GlobalSection::new();
 
-        // GLOBAL_HEAP_PTR — starts after data segment
+        // GLOBAL_HEAP_PTR — starts after data segment (the stdlib strings and
+        // a large set of user literals can outgrow the reserved HEAP_START)
+        let heap_start = HEAP_START.max((self.data.next_offset + 7) & !7);
         globals.global(
             GlobalType {
                 val_type: ValType::I32,
                 mutable: true,
                 shared: false,
             },
-            &ConstExpr::i32_const(HEAP_START as i32),
+            &ConstExpr::i32_const(heap_start as i32),
         );
 
         // GLOBAL_GAS
@@ -317,7 +454,7 @@ impl<'a> Compiler<'a> {
                 mutable: true,
                 shared: false,
             },
-            &ConstExpr::i32_const(1_000_000),
+            &ConstExpr::i32_const(pepl_types::gas::DEFAULT_LIMIT as i32),
         );
 
         // GLOBAL_STATE_PTR
@@ -330,6 +467,16 @@ impl<'a> Compiler<'a> {
             &ConstExpr::i32_const(0),
         );
 
+        // GLOBAL_TRAP_SITE
+        globals.global(
+            GlobalType {
+                val_type: ValType::I32,
+                mutable: true,
+                shared: false,
+            },
+            &ConstExpr::i32_const(0),
+        );
+
         globals
     }
 
@@ -461,17 +608,24 @@ impl<'a> Compiler<'a> {
         func_section.function(TYPE_I32X3_I32);
         code_section.function(&runtime::emit_memcmp());
 
+        // ── Compiled stdlib ──────────────────────────────────────────────
+        for (ty, func) in stdlib::emit_all(self.data.stdlib_ptr) {
+            func_section.function(ty);
+            code_section.function(&func);
+        }
+
         // ── Space-level functions ────────────────────────────────────────
         let body = &self.program.space.body;
 
         // init() -> void  (parameterless — gas limit set to default constant)
-        let init_idx = IMPORT_COUNT + RT_FUNC_COUNT;
+        let init_idx = SPACE_FUNC_BASE;
         func_section.function(TYPE_VOID_VOID);
         let mut init_scratch = Function::new(vec![]);
         let mut init_ctx = self.make_func_context(0); // 0 params
         crate::space::emit_init(
             &body.state,
             body.derived.as_ref(),
+            &self.recompute.all,
             &mut init_ctx,
             &mut init_scratch,
         )?;
@@ -488,6 +642,7 @@ impl<'a> Compiler<'a> {
             &body.actions,
             &body.invariants,
             body.derived.as_ref(),
+            &self.recompute.actions,
             &mut dispatch_ctx,
             &mut dispatch_scratch,
         )?;
@@ -533,8 +688,43 @@ impl<'a> Compiler<'a> {
         dealloc_func.instruction(&Instruction::End);
         code_section.function(&dealloc_func);
 
+        // serialize_state() -> (i32, i32)
+        let serialize_idx = dealloc_idx + 1;
+        func_section.function(TYPE_VOID_I32X2);
+        let mut serialize_scratch = Function::new(vec![]);
+        let mut serialize_ctx = self.make_func_context(0);
+        crate::snapshot::emit_serialize_state(body, &mut serialize_ctx, &mut serialize_scratch);
+        self.merge_user_data(&serialize_ctx);
+        code_section.function(&Self::finalize_function(serialize_scratch, &serialize_ctx));
+        self.source_map.push(
+            serialize_idx,
+            "serialize_state",
+            FuncKind::SpaceInfra,
+            body.state.span,
+        );
+
+        // restore_state(ptr: i32, len: i32) -> i32
+        let restore_idx = serialize_idx + 1;
+        func_section.function(TYPE_I32X2_I32);
+        let mut restore_scratch = Function::new(vec![]);
+        let mut restore_ctx = self.make_func_context(2);
+        crate::snapshot::emit_restore_state(
+            body,
+            &self.recompute.all,
+            &mut restore_ctx,
+            &mut restore_scratch,
+        )?;
+        self.merge_user_data(&restore_ctx);
+        code_section.function(&Self::finalize_function(restore_scratch, &restore_ctx));
+        self.source_map.push(
+            restore_idx,
+            "restore_state",
+            FuncKind::SpaceInfra,
+            body.state.span,
+        );
+
         // Conditionally: update(dt_ptr: i32)
-        let mut next_idx = dealloc_idx + 1;
+        let mut next_idx = restore_idx + 1;
         if let Some(update_decl) = &body.update {
             self.function_table
                 .insert("update".to_string(), next_idx);
@@ -544,6 +734,7 @@ impl<'a> Compiler<'a> {
             crate::space::emit_update(
                 update_decl,
                 body.derived.as_ref(),
+                &self.recompute.update,
                 &mut update_ctx,
                 &mut update_scratch,
             )?;
@@ -563,6 +754,7 @@ impl<'a> Compiler<'a> {
             crate::space::emit_handle_event(
                 handle_event_decl,
                 body.derived.as_ref(),
+                &self.recompute.handle_event,
                 &mut he_ctx,
                 &mut he_scratch,
             )?;
@@ -573,7 +765,7 @@ impl<'a> Compiler<'a> {
         }
 
         // Track how many space-level functions we emitted
-        self.num_space_funcs = next_idx - (IMPORT_COUNT + RT_FUNC_COUNT);
+        self.num_space_funcs = next_idx - SPACE_FUNC_BASE;
 
         // invoke_lambda(lambda_ptr: i32, arg_ptr: i32) -> i32
         // This function reads the LAMBDA value, extracts table index + env,
@@ -607,21 +799,30 @@ impl<'a> Compiler<'a> {
 
         // Now compile deferred lambda bodies
         // Each lambda has signature: (env_ptr: i32, arg_ptr: i32) -> i32
-        let lambda_bodies = self.lambda_bodies.clone();
-        for lb in &lambda_bodies {
+        // Lambdas nested in a lambda body are appended while compiling it,
+        // so walk by index until the list stops growing.
+        let mut lambda_slot = 0;
+        while lambda_slot < self.lambda_bodies.len() {
+            let lb = self.lambda_bodies[lambda_slot].clone();
+            lambda_slot += 1;
             func_section.function(TYPE_I32X2_I32);
             let mut lam_scratch = Function::new(vec![]);
             // Lambda function params: local 0 = env_ptr, local 1 = arg_ptr
             let mut lam_ctx = self.make_func_context(2);
+            lam_ctx.frames = lb.frames.clone();
+
+            // Save the caller's trap site; restored on normal return so a
+            // later trap in the caller is not attributed to the lambda.
+            let saved_site = lam_ctx.alloc_local(ValType::I32);
+            lam_scratch.instruction(&Instruction::GlobalGet(GLOBAL_TRAP_SITE));
+            lam_scratch.instruction(&Instruction::LocalSet(saved_site));
 
-            // Bind captured variables from env (a RECORD at local 0)
-            for cap_name in &lb.captured {
+            // Bind captured variables from env (a RECORD at local 0, one
+            // slot per capture in order)
+            for (slot, cap_name) in lb.captured.iter().enumerate() {
                 let cap_local = lam_ctx.alloc_local(ValType::I32);
-                let (cap_key_ptr, cap_key_len) = lam_ctx.intern_string(cap_name);
                 lam_scratch.instruction(&Instruction::LocalGet(0)); // env_ptr
-                lam_scratch.instruction(&Instruction::I32Const(cap_key_ptr as i32));
-                lam_scratch.instruction(&Instruction::I32Const(cap_key_len as i32));
-                lam_scratch.instruction(&Instruction::Call(rt_func_idx(RT_VAL_RECORD_GET)));
+                crate::layout::emit_fixed_load(slot as u32, &mut lam_scratch);
                 lam_scratch.instruction(&Instruction::LocalSet(cap_local));
                 lam_ctx.push_local(cap_name, cap_local);
             }
@@ -647,6 +848,9 @@ impl<'a> Compiler<'a> {
 
             // Emit lambda body (block of statements → last expr value)
             crate::expr::emit_block_as_expr(&lb.body, &mut lam_ctx, &mut lam_scratch)?;
+            crate::gas::flush(&mut lam_ctx, &mut lam_scratch);
+            lam_scratch.instruction(&Instruction::LocalGet(saved_site));
+            lam_scratch.instruction(&Instruction::GlobalSet(GLOBAL_TRAP_SITE));
             lam_scratch.instruction(&Instruction::End);
 
             self.merge_user_data(&lam_ctx);
@@ -672,22 +876,48 @@ impl<'a> Compiler<'a> {
                 .map(|(i, name)| (name.clone(), i as u32))
                 .collect();
 
-            let init_func_idx = IMPORT_COUNT + RT_FUNC_COUNT; // init is the first space func
+            let init_func_idx = SPACE_FUNC_BASE; // init is the first space func
             let dispatch_func_idx = init_func_idx + 1;
 
+            // Surface helpers follow __test_count so test export indices stay put
+            let views_map: std::collections::HashMap<String, u32> = self
+                .program
+                .space
+                .body
+                .views
+                .iter()
+                .enumerate()
+                .map(|(i, v)| (v.name.name.clone(), i as u32))
+                .collect();
+            let surface_count_idx = _next_idx + all_cases.len() as u32 + 1;
+            let count_components_idx = surface_count_idx + 1;
+            let test_funcs = crate::test_codegen::TestFuncs {
+                views: &views_map,
+                render_func_idx: init_func_idx + 2,
+                surface_count_idx,
+                update_func_idx: self.function_table.get("update").copied(),
+                handle_event_func_idx: self.function_table.get("handle_event").copied(),
+            };
+
             for (ti, tc) in all_cases.iter().enumerate() {
                 let test_func_idx = _next_idx;
                 func_section.function(TYPE_VOID_VOID);
                 let mut test_scratch = Function::new(vec![]);
                 let mut test_ctx = self.make_func_context(0);
+                test_ctx.frames = vec![RuntimeFrame::new(FrameKind::Test, &tc.description, tc.span)];
+                test_ctx
+                    .function_table
+                    .insert("count_components".to_string(), count_components_idx);
                 crate::test_codegen::emit_test_body(
                     &tc.body,
                     &actions_map,
                     dispatch_func_idx,
                     init_func_idx,
+                    &test_funcs,
                     &mut test_ctx,
                     &mut test_scratch,
                 )?;
+                crate::gas::flush(&mut test_ctx, &mut test_scratch);
                 test_scratch.instruction(&Instruction::End);
                 self.merge_user_data(&test_ctx);
                 code_section.function(&Self::finalize_function(test_scratch, &test_ctx));
@@ -701,9 +931,51 @@ impl<'a> Compiler<'a> {
             self.source_map.push(_next_idx, "__test_count", FuncKind::TestCount, self.program.space.span);
             _next_idx += 1;
 
+            // __surface_count(nodes, name, props) -> i32
+            func_section.function(TYPE_I32X3_I32);
+            let mut helper_ctx = self.make_func_context(3);
+            let surface_count =
+                crate::test_codegen::emit_surface_count(surface_count_idx, &mut helper_ctx);
+            self.merge_user_data(&helper_ctx);
+            code_section.function(&surface_count);
+            self.source_map.push(_next_idx, "__surface_count", FuncKind::TestHelper, self.program.space.span);
+            _next_idx += 1;
+
+            // count_components(surface, name) -> number
+            func_section.function(TYPE_I32X2_I32);
+            code_section.function(&crate::test_codegen::emit_count_components(surface_count_idx));
+            self.source_map.push(_next_idx, "count_components", FuncKind::TestHelper, self.program.space.span);
+            _next_idx += 1;
+
             self.num_test_funcs = all_cases.len() as u32 + 1; // +1 for __test_count
         }
 
+        // ── Canonical ABI exports ────────────────────────────────────────
+        if let Some(canon) = self.canon.take() {
+            let entries = SpaceEntries {
+                init: SPACE_FUNC_BASE,
+                dispatch: SPACE_FUNC_BASE + 1,
+                render: SPACE_FUNC_BASE + 2,
+                get_state: SPACE_FUNC_BASE + 3,
+                serialize_state: SPACE_FUNC_BASE + 5,
+                restore_state: SPACE_FUNC_BASE + 6,
+                update: self.function_table.get("update").copied(),
+                handle_event: self.function_table.get("handle_event").copied(),
+            };
+            self.canon_base = _next_idx;
+            for k in 0..canon.len() {
+                func_section.function(canon.type_of(k));
+                let mut ctx = self.make_func_context(0);
+                let func = canon.emit(k, self.canon_base, &entries, self.data.stdlib_ptr, &mut ctx);
+                self.merge_user_data(&ctx);
+                code_section.function(&func);
+                let name = canon.export_name(k).unwrap_or_else(|| "$surface_walk".into());
+                self.source_map.push(_next_idx, name, FuncKind::SpaceInfra, self.program.space.span);
+                _next_idx += 1;
+            }
+            self.canon = Some(canon);
+        }
+
         Ok((func_section, code_section))
     }
 
@@ -711,15 +983,19 @@ impl<'a> Compiler<'a> {
 
     fn emit_exports(&self) -> ExportSection {
         let mut exports = ExportSection::new();
-        let base = IMPORT_COUNT + RT_FUNC_COUNT;
+        let base = SPACE_FUNC_BASE;
 
         exports.export("init", ExportKind::Func, base);
         exports.export("dispatch_action", ExportKind::Func, base + 1);
         exports.export("render", ExportKind::Func, base + 2);
         exports.export("get_state", ExportKind::Func, base + 3);
         exports.export("dealloc", ExportKind::Func, base + 4);
+        exports.export("serialize_state", ExportKind::Func, base + 5);
+        exports.export("restore_state", ExportKind::Func, base + 6);
         exports.export("alloc", ExportKind::Func, IMPORT_COUNT + runtime::RT_ALLOC);
         exports.export("memory", ExportKind::Memory, 0);
+        exports.export("__trap_site", ExportKind::Global, GLOBAL_TRAP_SITE);
+        exports.export("__gas", ExportKind::Global, GLOBAL_GAS);
 
         // Conditional exports
         if self.function_table.contains_key("update") {
@@ -750,10 +1026,17 @@ impl<'a> Compiler<'a> {
             exports.export("__indirect_function_table", ExportKind::Table, 0);
         }
 
+        if let Some(canon) = &self.canon {
+            for k in 0..canon.len() {
+                if let Some(name) = canon.export_name(k) {
+                    exports.export(&name, ExportKind::Func, self.canon_base + k);
+                }
+            }
+        }
+
         // Test function exports: __test_0, __test_1, … and __test_count
         if self.num_test_funcs > 0 {
-            let test_base = IMPORT_COUNT
-                + RT_FUNC_COUNT
+            let test_base = SPACE_FUNC_BASE
                 + self.num_space_funcs
                 + 1 // invoke_lambda
                 + self.lambda_bodies.len() as u32;
@@ -808,8 +1091,7 @@ impl<'a> Compiler<'a> {
         let mut elem_sec = ElementSection::new();
         if !self.lambda_bodies.is_empty() {
             // Populate table at offset 0 with lambda function indices
-            let lambda_base =
-                IMPORT_COUNT + RT_FUNC_COUNT + self.num_space_funcs + 1; // +1 for invoke_lambda
+            let lambda_base = SPACE_FUNC_BASE + self.num_space_funcs + 1; // +1 for invoke_lambda
             let func_indices: Vec<u32> = (0..self.lambda_bodies.len() as u32)
                 .map(|i| lambda_base + i)
                 .collect();
@@ -845,11 +1127,17 @@ impl<'a> Compiler<'a> {
             function_table: self.function_table.clone(),
             data: self.data.clone_tracker(),
             user_data: Vec::new(),
-            string_cache: HashMap::new(),
+            string_cache: self.string_cache.clone(),
             lambda_bodies: Vec::new(),
-            lambda_base_idx: IMPORT_COUNT + RT_FUNC_COUNT + self.num_space_funcs
-                + 1 // +1 for invoke_lambda
-                + self.lambda_bodies.len() as u32,
+            lambda_base_idx: self.lambda_bodies.len() as u32,
+            frames: Vec::new(),
+            sites: Vec::new(),
+            next_site_id: self.source_map.sites.len() as u32 + 1,
+            scalars: Rc::clone(&self.scalars),
+            layouts: Rc::clone(&self.layouts),
+            unboxed_locals: HashMap::new(),
+            gas_pending: 0,
+            gas_carry: Rc::clone(&self.gas_carry),
         }
     }
 
@@ -858,8 +1146,11 @@ impl<'a> Compiler<'a> {
         self.user_data.extend_from_slice(&ctx.user_data);
         // Update data tracker offset
         self.data.next_offset = ctx.data.next_offset;
+        self.string_cache.extend(ctx.string_cache.iter().map(|(s, &at)| (s.clone(), at)));
         // Collect lambda bodies registered during this function's codegen
         self.lambda_bodies.extend(ctx.lambda_bodies.clone());
+        // Trap sites recorded while emitting this function
+        self.source_map.sites.extend(ctx.sites.iter().cloned());
     }
 
     /// Finalize a scratch function: rebuild with correct local declarations.
@@ -868,6 +1159,7 @@ impl<'a> Compiler<'a> {
     /// a single 0x00 byte (LEB128 zero).  We strip that byte and prepend the
     /// actual locals from `ctx`.
     fn finalize_function(scratch: Function, ctx: &FuncContext) -> Function {
+        debug_assert_eq!(ctx.gas_pending, 0, "gas charged but never flushed");
         let raw = scratch.into_raw_body();
         // raw[0] == 0x00 (the "0 local declarations" byte).  Everything after
         // that is instruction bytes we want to keep.
@@ -906,8 +1198,25 @@ pub struct FuncContext {
     pub string_cache: HashMap<String, (u32, u32)>,
     /// Lambda bodies collected during codegen (deferred compilation).
     pub lambda_bodies: Vec<LambdaBody>,
-    /// Base function index for lambda functions in the WASM table.
+    /// Table slot of the first lambda this function registers (the count
+    /// of lambdas registered before it).
     pub lambda_base_idx: u32,
+    /// Lexically enclosing PEPL frames (action, view, derived field, ...).
+    pub frames: Vec<RuntimeFrame>,
+    /// Trap sites recorded in this function.
+    pub sites: Vec<TrapSite>,
+    /// Id for the next trap site (unique across the module).
+    pub next_site_id: u32,
+    /// Checked scalar types of expressions, shared across functions.
+    pub scalars: Rc<ScalarTypes>,
+    /// Checked record layouts, shared across functions.
+    pub layouts: Rc<RecordLayouts>,
+    /// Locals holding a native `f64` / `i32` instead of a value pointer.
+    pub unboxed_locals: HashMap<u32, Scalar>,
+    /// Gas charged since the last [`gas::flush`](crate::gas::flush).
+    pub gas_pending: u64,
+    /// Gas of the nodes the optimiser folded away, shared across functions.
+    pub(crate) gas_carry: Rc<GasCarry>,
 }
 
 impl FuncContext {
@@ -941,11 +1250,31 @@ impl FuncContext {
             .and_then(|stack| stack.last().copied())
     }
 
+    /// Allocate a local holding an unboxed scalar.
+    pub fn alloc_unboxed_local(&mut self, scalar: Scalar) -> u32 {
+        let idx = self.alloc_local(scalar.val_type());
+        self.unboxed_locals.insert(idx, scalar);
+        idx
+    }
+
+    /// The scalar held by `local`, if it is unboxed.
+    pub fn unboxed_local(&self, local: u32) -> Option<Scalar> {
+        self.unboxed_locals.get(&local).copied()
+    }
+
     /// Check if a name is a state field.
     pub fn is_state_field(&self, name: &str) -> bool {
         self.state_field_names.iter().any(|s| s == name)
     }
 
+    /// Slot of a state or derived field in the state record.
+    pub fn state_slot(&self, name: &str) -> Option<u32> {
+        self.state_field_names
+            .iter()
+            .position(|s| s == name)
+            .map(|i| i as u32)
+    }
+
     /// Get the action_id for a name.
     pub fn get_action_id(&self, name: &str) -> Option<usize> {
         self.action_names.iter().position(|a| a == name)
@@ -973,66 +1302,70 @@ impl FuncContext {
         (ptr, len)
     }
 
+    /// Address the next [`FuncContext::intern_words`] call will return.
+    pub fn next_word_address(&self) -> u32 {
+        (self.data.next_offset + 3) & !3
+    }
+
+    /// Place little-endian words in the data segment, 4-byte aligned,
+    /// returning their address.
+    pub fn intern_words(&mut self, words: &[u32]) -> u32 {
+        let ptr = self.next_word_address();
+        self.user_data.resize(
+            self.user_data.len() + (ptr - self.data.next_offset) as usize,
+            0,
+        );
+        self.user_data
+            .extend(words.iter().flat_map(|w| w.to_le_bytes()));
+        self.data.next_offset = ptr + 4 * words.len() as u32;
+        ptr
+    }
+
     /// Register a lambda body for deferred compilation.
-    /// Returns the WASM function index for this lambda.
+    /// Returns the lambda's slot in the indirect function table.
     pub fn register_lambda(
         &mut self,
-        params: Vec<pepl_types::ast::Param>,
+        params: Vec<pepl_types::ast::LambdaParam>,
         body: pepl_types::ast::Block,
         captured: Vec<String>,
     ) -> u32 {
         let lambda_idx = self.lambda_bodies.len() as u32;
+        let mut frames = self.frames.clone();
+        frames.push(RuntimeFrame::new(FrameKind::Lambda, "<lambda>", body.span));
         self.lambda_bodies.push(LambdaBody {
             params,
             body,
             captured,
+            frames,
         });
-        // Function index = lambda_base_idx + lambda_idx
+        // Table slot = lambdas registered before this function + lambda_idx
         self.lambda_base_idx + lambda_idx
     }
 
+    /// Record a trap site for `span` and emit `global.set __trap_site`.
+    ///
+    /// `code` is the error to report for traps that can only come from this
+    /// site and carry no well-known message.
+    pub fn mark_site(&mut self, span: Span, code: Option<ErrorCode>, f: &mut Function) {
+        let id = self.next_site_id;
+        self.next_site_id += 1;
+        self.sites.push(TrapSite {
+            id,
+            span,
+            frames: self.frames.clone(),
+            code,
+        });
+        f.instruction(&Instruction::I32Const(id as i32));
+        f.instruction(&Instruction::GlobalSet(GLOBAL_TRAP_SITE));
+    }
+
     /// Resolve a qualified call to (module_id, function_id).
     ///
     /// For capability modules this returns the capability/function IDs.
-    /// For pure stdlib modules, we use synthetic IDs starting at 100.
+    /// Other modules use synthetic IDs starting at 100; for the pure stdlib
+    /// these are only reached by calls [`crate::stdlib`] does not implement.
     pub fn resolve_qualified_call(&self, module: &str, function: &str) -> (u32, u32) {
-        match module {
-            "http" => {
-                let fn_id = match function {
-                    "get" => 1,
-                    "post" => 2,
-                    "put" => 3,
-                    "patch" => 4,
-                    "delete" => 5,
-                    _ => 0,
-                };
-                (1, fn_id)
-            }
-            "storage" => {
-                let fn_id = match function {
-                    "get" => 1,
-                    "set" => 2,
-                    "delete" => 3,
-                    "keys" => 4,
-                    _ => 0,
-                };
-                (2, fn_id)
-            }
-            "location" => (3, if function == "current" { 1 } else { 0 }),
-            "notifications" => (4, if function == "send" { 1 } else { 0 }),
-            "credential" => (5, if function == "get" { 1 } else { 0 }),
-            // Pure stdlib: use IDs 100+
-            "math" => (100, self.stdlib_fn_id(function)),
-            "string" => (101, self.stdlib_fn_id(function)),
-            "list" => (102, self.stdlib_fn_id(function)),
-            "record" => (103, self.stdlib_fn_id(function)),
-            "json" => (104, self.stdlib_fn_id(function)),
-            "convert" => (105, self.stdlib_fn_id(function)),
-            "time" => (106, self.stdlib_fn_id(function)),
-            "timer" => (107, self.stdlib_fn_id(function)),
-            "core" => (108, self.stdlib_fn_id(function)),
-            _ => (0, 0),
-        }
+        host_call_ids(module, function)
     }
 
     /// Resolve a method call to (module_id, function_id).
@@ -1045,14 +1378,61 @@ impl FuncContext {
 
     /// Assign a numeric ID to a stdlib function name.
     fn stdlib_fn_id(&self, function: &str) -> u32 {
-        // Simple hash-based ID assignment for stdlib functions.
-        // This is deterministic across compilations.
-        let mut hash: u32 = 5381;
-        for b in function.bytes() {
-            hash = hash.wrapping_mul(33).wrapping_add(b as u32);
+        stdlib_fn_id(function)
+    }
+}
+
+/// `(cap_id, fn_id)` of the `env.host_call` for `module.function` (see
+/// [`FuncContext::resolve_qualified_call`]).
+pub(crate) fn host_call_ids(module: &str, function: &str) -> (u32, u32) {
+    match module {
+        "http" => {
+            let fn_id = match function {
+                "get" => 1,
+                "post" => 2,
+                "put" => 3,
+                "patch" => 4,
+                "delete" => 5,
+                _ => 0,
+            };
+            (1, fn_id)
+        }
+        "storage" => {
+            let fn_id = match function {
+                "get" => 1,
+                "set" => 2,
+                "delete" => 3,
+                "keys" => 4,
+                _ => 0,
+            };
+            (2, fn_id)
         }
-        hash & 0xFFFF
+        "location" => (3, if function == "current" { 1 } else { 0 }),
+        "notifications" => (4, if function == "send" { 1 } else { 0 }),
+        "credential" => (5, if function == "get" { 1 } else { 0 }),
+        // Non-capability modules: use IDs 100+
+        "math" => (100, stdlib_fn_id(function)),
+        "string" => (101, stdlib_fn_id(function)),
+        "list" => (102, stdlib_fn_id(function)),
+        "record" => (103, stdlib_fn_id(function)),
+        "json" => (104, stdlib_fn_id(function)),
+        "convert" => (105, stdlib_fn_id(function)),
+        "time" => (106, stdlib_fn_id(function)),
+        "timer" => (107, stdlib_fn_id(function)),
+        "core" => (108, stdlib_fn_id(function)),
+        _ => (0, 0),
+    }
+}
+
+/// Deterministic numeric ID of a stdlib function name.
+fn stdlib_fn_id(function: &str) -> u32 {
+    // Simple hash-based ID assignment for stdlib functions.
+    // This is deterministic across compilations.
+    let mut hash: u32 = 5381;
+    for b in function.bytes() {
+        hash = hash.wrapping_mul(33).wrapping_add(b as u32);
     }
+    hash & 0xFFFF
 }
 
 // ══════════════════════════════════════════════════════════════════════════════
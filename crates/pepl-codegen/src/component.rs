@@ -0,0 +1,839 @@
+//! Component wrapper for a compiled space.
+//!
+//! [`wrap`] turns a core module compiled with
+//! [`CodegenOptions::component`](crate::CodegenOptions::component) into a
+//! WebAssembly component implementing the space's world (see [`crate::wit`]):
+//!
+//! - the typed `pepl:host` imports are lowered into the core module's `env`
+//!   imports; `env.host_call` becomes an adapter module that reads the
+//!   argument cells, calls the typed import and boxes its result in a cell
+//! - the canonical ABI exports (see [`crate::canon`]) are lifted into the
+//!   `pepl:<space>/space` interface
+//!
+//! The adapter needs the core module's memory, which only exists once the
+//! core module is instantiated, so the core module's `env` functions are
+//! trampolines through a table filled in afterwards.
+//!
+//! A trap poisons a component instance.  Hosts that relied on the core
+//! module rolling back and carrying on re-instantiate instead, restoring
+//! the last `serialize-state` snapshot with `restore-state`.
+
+use std::borrow::Cow;
+
+use pepl_types::ast::Program;
+use wasm_encoder::{
+    Alias, CanonicalFunctionSection, CanonicalOption, CodeSection, Component,
+    ComponentAliasSection, ComponentExportKind, ComponentExportSection, ComponentImportSection,
+    ComponentInstanceSection, ComponentSectionId, ComponentTypeRef, ComponentTypeSection,
+    ComponentValType, ConstExpr, ElementSection, Elements, EntityType, ExportKind, ExportSection,
+    Function, FunctionSection, ImportSection, InstanceSection, InstanceType, Instruction,
+    MemoryType, Module, ModuleArg, ModuleSection, PrimitiveValType, RawSection, RefType,
+    TableSection, TableType, TypeBounds, TypeSection, ValType,
+};
+
+use crate::canon::export_name;
+use crate::compiler::host_call_ids;
+use crate::error::{CodegenError, CodegenResult};
+use crate::runtime::memarg;
+use crate::types::*;
+use crate::wit::{DefKind, HostInterface, HostType, Route, SpaceWorld, WitType, HOST_VERSION};
+
+type I = Instruction<'static>;
+
+/// Wrap `core`, compiled from `program` with
+/// [`CodegenOptions::component`](crate::CodegenOptions::component), into a
+/// component.  The result is validated with `wasmparser`.
+pub fn wrap(program: &Program, core: &[u8]) -> CodegenResult<Vec<u8>> {
+    let world = SpaceWorld::new(program)?;
+    let bytes = Assembler::default().assemble(&world, core);
+    wasmparser::validate(&bytes).map_err(|e| CodegenError::ValidationFailed(format!("{e}")))?;
+    Ok(bytes)
+}
+
+// ══════════════════════════════════════════════════════════════════════════════
+// Component assembly
+// ══════════════════════════════════════════════════════════════════════════════
+
+/// Builds the component one item per section, tracking the index spaces.
+#[derive(Default)]
+struct Assembler {
+    component: Component,
+    types: u32,
+    funcs: u32,
+    instances: u32,
+    modules: u32,
+    core_funcs: u32,
+    core_instances: u32,
+    core_tables: u32,
+    core_memories: u32,
+}
+
+impl Assembler {
+    fn assemble(mut self, world: &SpaceWorld, core: &[u8]) -> Vec<u8> {
+        // ── Imports ──────────────────────────────────────────────────────
+        let imports: Vec<(&HostInterface, u32)> = world
+            .imports
+            .iter()
+            .map(|&interface| {
+                let ty = host_instance_type(interface);
+                let mut types = ComponentTypeSection::new();
+                types.instance(&ty);
+                let ty = self.type_index(&types);
+                let mut section = ComponentImportSection::new();
+                section.import(
+                    &format!("pepl:host/{}@{HOST_VERSION}", interface.name),
+                    ComponentTypeRef::Instance(ty),
+                );
+                self.component.section(&section);
+                self.instances += 1;
+                (interface, self.instances - 1)
+            })
+            .collect();
+        let runtime = imports
+            .iter()
+            .find(|(i, _)| i.name == "runtime")
+            .map(|&(_, idx)| idx)
+            .expect("runtime is always imported");
+
+        // ── Modules ──────────────────────────────────────────────────────
+        let shim = self.module(&shim_module());
+        self.component.section(&RawSection {
+            id: ComponentSectionId::CoreModule as u8,
+            data: core,
+        });
+        self.modules += 1;
+        let space_module = self.modules - 1;
+        let routes = host_routes(&imports);
+        let adapter = self.module(&adapter_module(&routes));
+        let fixup = self.module(&fixup_module());
+
+        // ── Core instances ───────────────────────────────────────────────
+        let shim = self.instantiate(shim, vec![]);
+        let shim_funcs = ["host_call", "log", "trap"].map(|name| self.core_func(shim, name));
+        let table = self.core_alias(shim, ExportKind::Table, "$imports");
+        let timestamp = self.func(runtime, "timestamp");
+        let timestamp = self.lower(timestamp, vec![]);
+        let env = self.core_instance(vec![
+            ("host_call", ExportKind::Func, shim_funcs[0]),
+            ("log", ExportKind::Func, shim_funcs[1]),
+            ("trap", ExportKind::Func, shim_funcs[2]),
+            ("get_timestamp", ExportKind::Func, timestamp),
+        ]);
+        let space = self.instantiate(space_module, vec![("env", env)]);
+        let memory = self.core_alias(space, ExportKind::Memory, "memory");
+        let realloc = self.core_func(space, "cabi_realloc");
+        let options = || {
+            vec![
+                CanonicalOption::Memory(memory),
+                CanonicalOption::Realloc(realloc),
+                CanonicalOption::UTF8,
+            ]
+        };
+
+        let mut args = vec![("space".to_string(), space)];
+        for &(interface, instance) in &imports {
+            let items: Vec<(&str, ExportKind, u32)> = interface
+                .funcs
+                .iter()
+                .filter(|f| matches!(f.route, Route::HostCall(..)))
+                .map(|f| {
+                    let func = self.func(instance, f.name);
+                    (f.name, ExportKind::Func, self.lower(func, options()))
+                })
+                .collect();
+            if !items.is_empty() {
+                args.push((interface.name.to_string(), self.core_instance(items)));
+            }
+        }
+        let args = args.iter().map(|(name, i)| (name.as_str(), *i)).collect();
+        let adapter = self.instantiate(adapter, args);
+        let host_call = self.core_func(adapter, "host_call");
+        let log = self.func(runtime, "log");
+        let log = self.lower(log, options());
+        let trap = self.func(runtime, "trap");
+        let trap = self.lower(trap, options());
+        let fixup_args = self.core_instance(vec![
+            ("$imports", ExportKind::Table, table),
+            ("0", ExportKind::Func, host_call),
+            ("1", ExportKind::Func, log),
+            ("2", ExportKind::Func, trap),
+        ]);
+        self.instantiate(fixup, vec![("", fixup_args)]);
+
+        // ── Exports ──────────────────────────────────────────────────────
+        let mut defs = Vec::with_capacity(world.types.len());
+        for def in &world.types {
+            let idx = match &def.kind {
+                DefKind::Record(fields) => {
+                    let fields: Vec<_> = fields
+                        .iter()
+                        .map(|f| (f.name.clone(), self.val(&f.ty, &defs)))
+                        .collect();
+                    self.define(|types| {
+                        types
+                            .defined_type()
+                            .record(fields.iter().map(|(n, t)| (n.as_str(), *t)));
+                    })
+                }
+                DefKind::Variant(cases) => {
+                    let cases: Vec<_> = cases
+                        .iter()
+                        .map(|c| (c.name.clone(), c.payload().map(|p| self.val(&p, &defs))))
+                        .collect();
+                    self.define(|types| {
+                        types
+                            .defined_type()
+                            .variant(cases.iter().map(|(n, t)| (n.as_str(), *t, None)));
+                    })
+                }
+                DefKind::Enum(cases) => self.define(|types| {
+                    types
+                        .defined_type()
+                        .enum_type(cases.iter().map(|c| c.name.as_str()));
+                }),
+            };
+            defs.push(idx);
+        }
+
+        let mut funcs = Vec::with_capacity(world.funcs.len());
+        for (i, func) in world.funcs.iter().enumerate() {
+            let params: Vec<_> = func
+                .params
+                .iter()
+                .map(|(n, t)| (n.clone(), self.val(t, &defs)))
+                .collect();
+            let result = func.result.as_ref().map(|t| self.val(t, &defs));
+            let ty = self.define(|types| {
+                let mut f = types.function();
+                f.params(params.iter().map(|(n, t)| (n.as_str(), *t)));
+                match result {
+                    Some(result) => f.result(result),
+                    None => f.results(Vec::<(&str, ComponentValType)>::new()),
+                };
+            });
+            let core = self.core_func(space, &export_name(world, i));
+            funcs.push(self.lift(core, ty, options()));
+        }
+
+        let items: Vec<(&str, ComponentExportKind, u32)> = world
+            .types
+            .iter()
+            .zip(&defs)
+            .map(|(def, &idx)| (def.name.as_str(), ComponentExportKind::Type, idx))
+            .chain(
+                world
+                    .funcs
+                    .iter()
+                    .zip(&funcs)
+                    .map(|(f, &idx)| (f.name.as_str(), ComponentExportKind::Func, idx)),
+            )
+            .collect();
+        let mut instances = ComponentInstanceSection::new();
+        instances.export_items(items);
+        self.component.section(&instances);
+        self.instances += 1;
+        let mut exports = ComponentExportSection::new();
+        exports.export(
+            &world.interface_name(),
+            ComponentExportKind::Instance,
+            self.instances - 1,
+            None,
+        );
+        self.component.section(&exports);
+
+        self.component.finish()
+    }
+
+    fn type_index(&mut self, types: &ComponentTypeSection) -> u32 {
+        self.component.section(types);
+        self.types += 1;
+        self.types - 1
+    }
+
+    fn define(&mut self, f: impl FnOnce(wasm_encoder::ComponentTypeEncoder<'_>)) -> u32 {
+        let mut types = ComponentTypeSection::new();
+        f(types.ty());
+        self.type_index(&types)
+    }
+
+    /// The component value type of `ty`, defining anonymous types as needed.
+    fn val(&mut self, ty: &WitType, defs: &[u32]) -> ComponentValType {
+        let primitive = |p| ComponentValType::Primitive(p);
+        match ty {
+            WitType::U32 => primitive(PrimitiveValType::U32),
+            WitType::F64 => primitive(PrimitiveValType::F64),
+            WitType::Bool => primitive(PrimitiveValType::Bool),
+            WitType::String => primitive(PrimitiveValType::String),
+            WitType::Def(i) => ComponentValType::Type(defs[*i]),
+            WitType::List(item) => {
+                let item = self.val(item, defs);
+                ComponentValType::Type(self.define(|t| t.defined_type().list(item)))
+            }
+            WitType::Option(inner) => {
+                let inner = self.val(inner, defs);
+                ComponentValType::Type(self.define(|t| t.defined_type().option(inner)))
+            }
+            WitType::Result(ok, err) => {
+                let ok = ok.as_ref().map(|t| self.val(t, defs));
+                let err = err.as_ref().map(|t| self.val(t, defs));
+                ComponentValType::Type(self.define(|t| t.defined_type().result(ok, err)))
+            }
+            WitType::Tuple(items) => {
+                let items: Vec<_> = items.iter().map(|t| self.val(t, defs)).collect();
+                ComponentValType::Type(self.define(|t| t.defined_type().tuple(items)))
+            }
+        }
+    }
+
+    fn module(&mut self, module: &Module) -> u32 {
+        self.component.section(&ModuleSection(module));
+        self.modules += 1;
+        self.modules - 1
+    }
+
+    fn instantiate(&mut self, module: u32, args: Vec<(&str, u32)>) -> u32 {
+        let mut instances = InstanceSection::new();
+        instances.instantiate(
+            module,
+            args.into_iter()
+                .map(|(name, i)| (name, ModuleArg::Instance(i))),
+        );
+        self.component.section(&instances);
+        self.core_instances += 1;
+        self.core_instances - 1
+    }
+
+    fn core_instance(&mut self, items: Vec<(&str, ExportKind, u32)>) -> u32 {
+        let mut instances = InstanceSection::new();
+        instances.export_items(items);
+        self.component.section(&instances);
+        self.core_instances += 1;
+        self.core_instances - 1
+    }
+
+    fn core_alias(&mut self, instance: u32, kind: ExportKind, name: &str) -> u32 {
+        let mut aliases = ComponentAliasSection::new();
+        aliases.alias(Alias::CoreInstanceExport {
+            instance,
+            kind,
+            name,
+        });
+        self.component.section(&aliases);
+        let counter = match kind {
+            ExportKind::Func => &mut self.core_funcs,
+            ExportKind::Table => &mut self.core_tables,
+            ExportKind::Memory => &mut self.core_memories,
+            _ => unreachable!("only functions, tables and memories are aliased"),
+        };
+        *counter += 1;
+        *counter - 1
+    }
+
+    fn core_func(&mut self, instance: u32, name: &str) -> u32 {
+        self.core_alias(instance, ExportKind::Func, name)
+    }
+
+    /// Alias function `name` of the imported instance `instance`.
+    fn func(&mut self, instance: u32, name: &str) -> u32 {
+        let mut aliases = ComponentAliasSection::new();
+        aliases.alias(Alias::InstanceExport {
+            instance,
+            kind: ComponentExportKind::Func,
+            name,
+        });
+        self.component.section(&aliases);
+        self.funcs += 1;
+        self.funcs - 1
+    }
+
+    fn lower(&mut self, func: u32, options: Vec<CanonicalOption>) -> u32 {
+        let mut canon = CanonicalFunctionSection::new();
+        canon.lower(func, options);
+        self.component.section(&canon);
+        self.core_funcs += 1;
+        self.core_funcs - 1
+    }
+
+    fn lift(&mut self, core: u32, ty: u32, options: Vec<CanonicalOption>) -> u32 {
+        let mut canon = CanonicalFunctionSection::new();
+        canon.lift(core, ty, options);
+        self.component.section(&canon);
+        self.funcs += 1;
+        self.funcs - 1
+    }
+}
+
+/// The instance type of a `pepl:host` interface.
+fn host_instance_type(interface: &HostInterface) -> InstanceType {
+    let mut ty = InstanceType::new();
+    let mut position = None;
+    if interface
+        .funcs
+        .iter()
+        .any(|f| f.result == HostType::Position)
+    {
+        let record = ty.type_count();
+        ty.ty().defined_type().record([
+            ("lat", ComponentValType::Primitive(PrimitiveValType::F64)),
+            ("lon", ComponentValType::Primitive(PrimitiveValType::F64)),
+        ]);
+        position = Some(ty.type_count());
+        ty.export("position", ComponentTypeRef::Type(TypeBounds::Eq(record)));
+    }
+    for func in interface.funcs {
+        let params: Vec<_> = func
+            .params
+            .iter()
+            .map(|&(name, p)| {
+                (
+                    name,
+                    host_val(&mut ty, p, position).expect("unit parameter"),
+                )
+            })
+            .collect();
+        let result = host_val(&mut ty, func.result, position);
+        let idx = ty.type_count();
+        let mut f = ty.ty().function();
+        f.params(params);
+        match result {
+            Some(result) => f.result(result),
+            None => f.results(Vec::<(&str, ComponentValType)>::new()),
+        };
+        ty.export(func.name, ComponentTypeRef::Func(idx));
+    }
+    ty
+}
+
+fn host_val(
+    ty: &mut InstanceType,
+    host: HostType,
+    position: Option<u32>,
+) -> Option<ComponentValType> {
+    let string = ComponentValType::Primitive(PrimitiveValType::String);
+    let mut define = |f: &dyn Fn(wasm_encoder::ComponentDefinedTypeEncoder<'_>)| {
+        let idx = ty.type_count();
+        f(ty.ty().defined_type());
+        ComponentValType::Type(idx)
+    };
+    Some(match host {
+        HostType::Unit => return None,
+        HostType::F64 => ComponentValType::Primitive(PrimitiveValType::F64),
+        HostType::S64 => ComponentValType::Primitive(PrimitiveValType::S64),
+        HostType::Bool => ComponentValType::Primitive(PrimitiveValType::Bool),
+        HostType::String => string,
+        HostType::OptionString => define(&|t| t.option(string)),
+        HostType::ListString => define(&|t| t.list(string)),
+        HostType::ResultString => define(&|t| t.result(Some(string), Some(string))),
+        HostType::Position => ComponentValType::Type(position.expect("position is exported")),
+    })
+}
+
+// ══════════════════════════════════════════════════════════════════════════════
+// Core modules
+// ══════════════════════════════════════════════════════════════════════════════
+
+/// Core type of `env.host_call`.
+const HOST_CALL: u32 = 0;
+/// Core type of `env.log` / `env.trap`.
+const MESSAGE: u32 = 1;
+
+fn env_types() -> TypeSection {
+    let mut types = TypeSection::new();
+    types
+        .ty()
+        .function([ValType::I32, ValType::I32, ValType::I32], [ValType::I32]);
+    types.ty().function([ValType::I32, ValType::I32], []);
+    types
+}
+
+fn imports_table() -> TableType {
+    TableType {
+        element_type: RefType::FUNCREF,
+        table64: false,
+        minimum: 3,
+        maximum: Some(3),
+        shared: false,
+    }
+}
+
+/// `host_call`, `log` and `trap` calling through the `$imports` table.
+fn shim_module() -> Module {
+    let mut module = Module::new();
+    module.section(&env_types());
+    let mut funcs = FunctionSection::new();
+    funcs
+        .function(HOST_CALL)
+        .function(MESSAGE)
+        .function(MESSAGE);
+    module.section(&funcs);
+    let mut tables = TableSection::new();
+    tables.table(imports_table());
+    module.section(&tables);
+    let mut exports = ExportSection::new();
+    exports
+        .export("host_call", ExportKind::Func, 0)
+        .export("log", ExportKind::Func, 1)
+        .export("trap", ExportKind::Func, 2)
+        .export("$imports", ExportKind::Table, 0);
+    module.section(&exports);
+    let mut code = CodeSection::new();
+    for (slot, ty, params) in [(0, HOST_CALL, 3), (1, MESSAGE, 2), (2, MESSAGE, 2)] {
+        let mut f = Function::new([]);
+        for p in 0..params {
+            f.instruction(&I::LocalGet(p));
+        }
+        f.instruction(&I::I32Const(slot));
+        f.instruction(&I::CallIndirect {
+            type_index: ty,
+            table_index: 0,
+        });
+        f.instruction(&I::End);
+        code.function(&f);
+    }
+    module.section(&code);
+    module
+}
+
+/// Fills the `$imports` table once the adapter exists.
+fn fixup_module() -> Module {
+    let mut module = Module::new();
+    module.section(&env_types());
+    let mut imports = ImportSection::new();
+    imports
+        .import("", "$imports", EntityType::Table(imports_table()))
+        .import("", "0", EntityType::Function(HOST_CALL))
+        .import("", "1", EntityType::Function(MESSAGE))
+        .import("", "2", EntityType::Function(MESSAGE));
+    module.section(&imports);
+    let mut elements = ElementSection::new();
+    elements.active(
+        Some(0),
+        &ConstExpr::i32_const(0),
+        Elements::Functions(Cow::Borrowed(&[0, 1, 2])),
+    );
+    module.section(&elements);
+    module
+}
+
+/// A lowered host function `env.host_call` dispatches to.
+struct HostRoute {
+    interface: &'static str,
+    name: &'static str,
+    cap: u32,
+    func: u32,
+    params: &'static [(&'static str, HostType)],
+    result: HostType,
+}
+
+fn host_routes(imports: &[(&'static HostInterface, u32)]) -> Vec<HostRoute> {
+    imports
+        .iter()
+        .flat_map(|(interface, _)| {
+            interface.funcs.iter().filter_map(|f| match f.route {
+                Route::HostCall(module, function) => {
+                    let (cap, func) = host_call_ids(module, function);
+                    Some(HostRoute {
+                        interface: interface.name,
+                        name: f.name,
+                        cap,
+                        func,
+                        params: f.params,
+                        result: f.result,
+                    })
+                }
+                _ => None,
+            })
+        })
+        .collect()
+}
+
+/// Results returned through a pointer rather than as a flat value.
+fn indirect(result: HostType) -> bool {
+    !matches!(
+        result,
+        HostType::Unit | HostType::F64 | HostType::S64 | HostType::Bool
+    )
+}
+
+/// Imported function indices of the adapter.
+const A_ALLOC: u32 = 0;
+const A_FIRST_ROUTE: u32 = 1;
+
+/// `host_call(cap_id, fn_id, args)`: unbox the argument cells, call the
+/// lowered import and box its result.
+fn adapter_module(routes: &[HostRoute]) -> Module {
+    let mut module = Module::new();
+    let mut types = TypeSection::new();
+    // 0: alloc / (i32) -> i32, 1: host_call, 2: cell(tag, w1, w2), 3: number(f64)
+    types.ty().function([ValType::I32], [ValType::I32]);
+    types
+        .ty()
+        .function([ValType::I32, ValType::I32, ValType::I32], [ValType::I32]);
+    types.ty().function([ValType::F64], [ValType::I32]);
+    for route in routes {
+        let mut params: Vec<ValType> = route
+            .params
+            .iter()
+            .flat_map(|&(_, p)| match p {
+                HostType::F64 => vec![ValType::F64],
+                _ => vec![ValType::I32, ValType::I32],
+            })
+            .collect();
+        let results = match route.result {
+            HostType::Unit => vec![],
+            HostType::F64 => vec![ValType::F64],
+            HostType::S64 => vec![ValType::I64],
+            HostType::Bool => vec![ValType::I32],
+            _ => {
+                params.push(ValType::I32);
+                vec![]
+            }
+        };
+        types.ty().function(params, results);
+    }
+    module.section(&types);
+
+    let mut imports = ImportSection::new();
+    imports.import(
+        "space",
+        "memory",
+        EntityType::Memory(MemoryType {
+            minimum: 1,
+            maximum: None,
+            memory64: false,
+            shared: false,
+            page_size_log2: None,
+        }),
+    );
+    imports.import("space", "alloc", EntityType::Function(0));
+    for (i, route) in routes.iter().enumerate() {
+        imports.import(
+            route.interface,
+            route.name,
+            EntityType::Function(3 + i as u32),
+        );
+    }
+    module.section(&imports);
+
+    let host_call = A_FIRST_ROUTE + routes.len() as u32;
+    let cell = host_call + 1;
+    let number = host_call + 2;
+    let mut funcs = FunctionSection::new();
+    funcs.function(1).function(1).function(2);
+    module.section(&funcs);
+    let mut exports = ExportSection::new();
+    exports.export("host_call", ExportKind::Func, host_call);
+    module.section(&exports);
+
+    let mut code = CodeSection::new();
+    code.function(&adapter_host_call(routes, cell, number));
+    code.function(&adapter_cell());
+    code.function(&adapter_number());
+    module.section(&code);
+    module
+}
+
+fn adapter_host_call(routes: &[HostRoute], cell: u32, number: u32) -> Function {
+    // params: cap 0, fn 1, args 2; locals: arr 3, ret 4, c 5, out 6, i 7
+    let (arr, ret, c, out, i) = (3, 4, 5, 6, 7);
+    let mut f = Function::new([(5, ValType::I32)]);
+    let mut op = |ins: I| {
+        f.instruction(&ins);
+    };
+    op(I::LocalGet(2));
+    op(I::I32Load(memarg(4, 2)));
+    op(I::LocalSet(arr));
+    let string_cell = |op: &mut dyn FnMut(I), base: u32, at: u64| {
+        op(I::I32Const(TAG_STRING));
+        op(I::LocalGet(base));
+        op(I::I32Load(memarg(at, 2)));
+        op(I::LocalGet(base));
+        op(I::I32Load(memarg(at + 4, 2)));
+        op(I::Call(cell));
+    };
+    let nil = |op: &mut dyn FnMut(I)| {
+        op(I::I32Const(TAG_NIL));
+        op(I::I32Const(0));
+        op(I::I32Const(0));
+        op(I::Call(cell));
+    };
+    for (r, route) in routes.iter().enumerate() {
+        op(I::LocalGet(0));
+        op(I::I32Const(route.cap as i32));
+        op(I::I32Eq);
+        op(I::LocalGet(1));
+        op(I::I32Const(route.func as i32));
+        op(I::I32Eq);
+        op(I::I32And);
+        op(I::If(wasm_encoder::BlockType::Empty));
+        for (j, &(_, p)) in route.params.iter().enumerate() {
+            op(I::LocalGet(arr));
+            op(I::I32Load(memarg(4 * j as u64, 2)));
+            if p == HostType::F64 {
+                op(I::F64Load(memarg(4, 2)));
+            } else {
+                op(I::LocalTee(c));
+                op(I::I32Load(memarg(4, 2)));
+                op(I::LocalGet(c));
+                op(I::I32Load(memarg(8, 2)));
+            }
+        }
+        if indirect(route.result) {
+            op(I::I32Const(16 + 7));
+            op(I::Call(A_ALLOC));
+            op(I::I32Const(7));
+            op(I::I32Add);
+            op(I::I32Const(-8));
+            op(I::I32And);
+            op(I::LocalTee(ret));
+        }
+        op(I::Call(A_FIRST_ROUTE + r as u32));
+        match route.result {
+            HostType::Unit => nil(&mut op),
+            HostType::F64 => op(I::Call(number)),
+            HostType::S64 => {
+                op(I::F64ConvertI64S);
+                op(I::Call(number));
+            }
+            HostType::Bool => {
+                op(I::LocalSet(c));
+                op(I::I32Const(TAG_BOOL));
+                op(I::LocalGet(c));
+                op(I::I32Const(0));
+                op(I::Call(cell));
+            }
+            HostType::String => string_cell(&mut op, ret, 0),
+            HostType::OptionString => {
+                op(I::LocalGet(ret));
+                op(I::I32Load8U(memarg(0, 0)));
+                op(I::If(wasm_encoder::BlockType::Result(ValType::I32)));
+                string_cell(&mut op, ret, 4);
+                op(I::Else);
+                nil(&mut op);
+                op(I::End);
+            }
+            HostType::ResultString => {
+                // ok / err are the two ids after VARIANT_OK
+                op(I::I32Const(TAG_VARIANT));
+                op(I::I32Const(VARIANT_OK as i32));
+                op(I::LocalGet(ret));
+                op(I::I32Load8U(memarg(0, 0)));
+                op(I::I32Add);
+                string_cell(&mut op, ret, 4);
+                op(I::Call(cell));
+            }
+            HostType::ListString => {
+                op(I::LocalGet(ret));
+                op(I::I32Load(memarg(4, 2)));
+                op(I::I32Const(4));
+                op(I::I32Mul);
+                op(I::Call(A_ALLOC));
+                op(I::LocalSet(out));
+                op(I::I32Const(0));
+                op(I::LocalSet(i));
+                op(I::Block(wasm_encoder::BlockType::Empty));
+                op(I::Loop(wasm_encoder::BlockType::Empty));
+                op(I::LocalGet(i));
+                op(I::LocalGet(ret));
+                op(I::I32Load(memarg(4, 2)));
+                op(I::I32GeU);
+                op(I::BrIf(1));
+                // out[i] = string cell of element i
+                op(I::LocalGet(out));
+                op(I::LocalGet(i));
+                op(I::I32Const(4));
+                op(I::I32Mul);
+                op(I::I32Add);
+                op(I::LocalGet(ret));
+                op(I::I32Load(memarg(0, 2)));
+                op(I::LocalGet(i));
+                op(I::I32Const(8));
+                op(I::I32Mul);
+                op(I::I32Add);
+                op(I::LocalSet(c));
+                string_cell(&mut op, c, 0);
+                op(I::I32Store(memarg(0, 2)));
+                op(I::LocalGet(i));
+                op(I::I32Const(1));
+                op(I::I32Add);
+                op(I::LocalSet(i));
+                op(I::Br(0));
+                op(I::End);
+                op(I::End);
+                op(I::I32Const(TAG_LIST));
+                op(I::LocalGet(out));
+                op(I::LocalGet(ret));
+                op(I::I32Load(memarg(4, 2)));
+                op(I::Call(cell));
+            }
+            HostType::Position => {
+                // keys "latlon", then two entries [key_ptr, key_len, value]
+                op(I::I32Const(6 + 24));
+                op(I::Call(A_ALLOC));
+                op(I::LocalSet(out));
+                for (at, byte) in b"latlon".iter().enumerate() {
+                    op(I::LocalGet(out));
+                    op(I::I32Const(*byte as i32));
+                    op(I::I32Store8(memarg(24 + at as u64, 0)));
+                }
+                for field in 0..2u64 {
+                    op(I::LocalGet(out));
+                    op(I::LocalGet(out));
+                    op(I::I32Const(24 + 3 * field as i32));
+                    op(I::I32Add);
+                    op(I::I32Store(memarg(12 * field, 0)));
+                    op(I::LocalGet(out));
+                    op(I::I32Const(3));
+                    op(I::I32Store(memarg(12 * field + 4, 0)));
+                    op(I::LocalGet(out));
+                    op(I::LocalGet(ret));
+                    op(I::F64Load(memarg(8 * field, 3)));
+                    op(I::Call(number));
+                    op(I::I32Store(memarg(12 * field + 8, 0)));
+                }
+                op(I::I32Const(TAG_RECORD));
+                op(I::LocalGet(out));
+                op(I::I32Const(2));
+                op(I::Call(cell));
+            }
+        }
+        op(I::Return);
+        op(I::End);
+    }
+    // host_call ids no import covers: the world does not grant them
+    op(I::Unreachable);
+    op(I::End);
+    f
+}
+
+/// `cell(tag, w1, w2) -> ptr`.
+fn adapter_cell() -> Function {
+    let mut f = Function::new([(1, ValType::I32)]);
+    f.instruction(&I::I32Const(VALUE_SIZE as i32));
+    f.instruction(&I::Call(A_ALLOC));
+    f.instruction(&I::LocalSet(3));
+    for (param, at) in [(0, 0), (1, 4), (2, 8)] {
+        f.instruction(&I::LocalGet(3));
+        f.instruction(&I::LocalGet(param));
+        f.instruction(&I::I32Store(memarg(at, 2)));
+    }
+    f.instruction(&I::LocalGet(3));
+    f.instruction(&I::End);
+    f
+}
+
+/// `number(f64) -> ptr`.
+fn adapter_number() -> Function {
+    let mut f = Function::new([(1, ValType::I32)]);
+    f.instruction(&I::I32Const(VALUE_SIZE as i32));
+    f.instruction(&I::Call(A_ALLOC));
+    f.instruction(&I::LocalTee(1));
+    f.instruction(&I::I32Const(TAG_NUMBER));
+    f.instruction(&I::I32Store(memarg(0, 2)));
+    f.instruction(&I::LocalGet(1));
+    f.instruction(&I::LocalGet(0));
+    f.instruction(&I::F64Store(memarg(4, 2)));
+    f.instruction(&I::LocalGet(1));
+    f.instruction(&I::End);
+    f
+}
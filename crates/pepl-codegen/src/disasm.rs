@@ -0,0 +1,725 @@
+//! WAT disassembly of compiled PEPL modules.
+//!
+//! [`disassemble`] prints a generated module as WebAssembly text, using the
+//! [`SourceMap`] to make it readable:
+//!
+//! - space functions are named after their source map entry or export
+//!   (`$init`, `$dispatch_action`, `$get_state`) and headed by the PEPL
+//!   declarations they implement;
+//! - runtime helpers are labelled by their `RT_*` constant and stdlib
+//!   functions by their `module.function` name, also after tree-shaking
+//!   (see [`SourceMap::helpers`]);
+//! - every `global.set $trap_site` is preceded by a comment with the trap
+//!   site's line, column and enclosing frame — and, with
+//!   [`disassemble_with_source`], the PEPL line itself;
+//! - string constants passed as `(ptr, len)` pairs are decoded inline, and
+//!   the data segment is printed one string per line.
+
+use std::collections::{BTreeSet, HashSet};
+use std::fmt::Write as _;
+
+use wasmparser::{
+    BlockType, DataKind, ElementItems, ElementKind, ExternalKind, FuncType, Operator, Parser,
+    Payload, RefType, TypeRef, ValType,
+};
+
+use crate::error::{CodegenError, CodegenResult};
+use crate::runtime::{RT_FUNC_COUNT, RT_NAMES};
+use crate::source_map::{FuncKind, SourceMap};
+use crate::stdlib;
+use crate::types::*;
+
+/// Names of the module globals, indexed by the `GLOBAL_*` constants.
+const GLOBAL_NAMES: [&str; 5] = ["heap_ptr", "gas", "gas_limit", "state_ptr", "trap_site"];
+
+/// Longest run of data bytes printed on one line.
+const DATA_LINE_BYTES: usize = 48;
+
+/// Disassemble `wasm` to WAT annotated from `source_map`.
+pub fn disassemble(wasm: &[u8], source_map: &SourceMap) -> CodegenResult<String> {
+    Disassembler::new(wasm, source_map, None)?.print()
+}
+
+/// [`disassemble`], additionally quoting the PEPL source lines that trap
+/// sites and functions come from.
+pub fn disassemble_with_source(
+    wasm: &[u8],
+    source_map: &SourceMap,
+    source: &str,
+) -> CodegenResult<String> {
+    Disassembler::new(wasm, source_map, Some(source))?.print()
+}
+
+fn parse_err(e: wasmparser::BinaryReaderError) -> CodegenError {
+    CodegenError::Internal(format!("disassembly failed: {e}"))
+}
+
+struct Disassembler<'a> {
+    wasm: &'a [u8],
+    source_map: &'a SourceMap,
+    lines: Vec<&'a str>,
+    types: Vec<FuncType>,
+    /// Type index of every function, imports first.
+    func_types: Vec<u32>,
+    import_count: u32,
+    /// `$name` of every function, if it has one.
+    func_names: Vec<Option<String>>,
+    /// Active data segments as `(offset, bytes)`.
+    data: Vec<(u32, &'a [u8])>,
+    /// Start offsets of the strings referenced from code.
+    strings: BTreeSet<u32>,
+    out: String,
+}
+
+impl<'a> Disassembler<'a> {
+    /// First pass: collect types, function signatures, names and data so
+    /// that the printing pass can resolve references in any order.
+    fn new(
+        wasm: &'a [u8],
+        source_map: &'a SourceMap,
+        source: Option<&'a str>,
+    ) -> CodegenResult<Self> {
+        let mut types = Vec::new();
+        let mut func_types = Vec::new();
+        let mut imports = Vec::new();
+        let mut exports = Vec::new();
+        let mut data = Vec::new();
+        for payload in Parser::new(0).parse_all(wasm) {
+            match payload.map_err(parse_err)? {
+                Payload::TypeSection(reader) => {
+                    for ty in reader.into_iter_err_on_gc_types() {
+                        types.push(ty.map_err(parse_err)?);
+                    }
+                }
+                Payload::ImportSection(reader) => {
+                    for import in reader {
+                        let import = import.map_err(parse_err)?;
+                        if let TypeRef::Func(ty) = import.ty {
+                            func_types.push(ty);
+                            imports.push(import.name);
+                        }
+                    }
+                }
+                Payload::FunctionSection(reader) => {
+                    for ty in reader {
+                        func_types.push(ty.map_err(parse_err)?);
+                    }
+                }
+                Payload::ExportSection(reader) => {
+                    for export in reader {
+                        let export = export.map_err(parse_err)?;
+                        if export.kind == ExternalKind::Func {
+                            exports.push((export.index, export.name));
+                        }
+                    }
+                }
+                Payload::DataSection(reader) => {
+                    for segment in reader {
+                        let segment = segment.map_err(parse_err)?;
+                        if let DataKind::Active { offset_expr, .. } = segment.kind {
+                            if let Ok(Operator::I32Const { value }) =
+                                offset_expr.get_operators_reader().read()
+                            {
+                                data.push((value as u32, segment.data));
+                            }
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let import_count = imports.len() as u32;
+        let mut taken = HashSet::new();
+        let func_names = (0..func_types.len() as u32)
+            .map(|idx| {
+                let name = match imports.get(idx as usize) {
+                    Some(name) => Some(name.to_string()),
+                    None => func_name(source_map, idx, import_count).or_else(|| {
+                        let export = exports.iter().find(|(i, _)| *i == idx)?;
+                        Some(export.1.to_string())
+                    }),
+                }?;
+                let mut name = format!("${}", sanitize(&name));
+                if !taken.insert(name.clone()) {
+                    name = format!("{name}.{idx}");
+                }
+                Some(name)
+            })
+            .collect();
+
+        Ok(Self {
+            wasm,
+            source_map,
+            lines: source.map(|s| s.lines().collect()).unwrap_or_default(),
+            types,
+            func_types,
+            import_count,
+            func_names,
+            data,
+            strings: BTreeSet::new(),
+            out: String::new(),
+        })
+    }
+
+    /// Second pass: print every section in binary order.
+    fn print(mut self) -> CodegenResult<String> {
+        self.out.push_str("(module\n");
+        let mut func_idx = self.import_count;
+        for payload in Parser::new(0).parse_all(self.wasm) {
+            match payload.map_err(parse_err)? {
+                Payload::TypeSection(_) => {
+                    for (i, ty) in self.types.iter().enumerate() {
+                        let sig = signature(ty);
+                        let _ = writeln!(self.out, "  (type (;{i};) (func{sig}))");
+                    }
+                }
+                Payload::ImportSection(reader) => {
+                    let mut import_func = 0;
+                    for import in reader {
+                        let import = import.map_err(parse_err)?;
+                        let desc = match import.ty {
+                            TypeRef::Func(ty) => {
+                                import_func += 1;
+                                format!("(func {} (type {ty}))", self.func_label(import_func - 1))
+                            }
+                            other => format!("(; {other:?} ;)"),
+                        };
+                        let _ = writeln!(
+                            self.out,
+                            "  (import {:?} {:?} {desc})",
+                            import.module, import.name
+                        );
+                    }
+                }
+                Payload::TableSection(reader) => {
+                    for (i, table) in reader.into_iter().enumerate() {
+                        let ty = table.map_err(parse_err)?.ty;
+                        let max = ty.maximum.map(|m| format!(" {m}")).unwrap_or_default();
+                        let _ = writeln!(
+                            self.out,
+                            "  (table (;{i};) {}{max} {})",
+                            ty.initial,
+                            ref_type(ty.element_type)
+                        );
+                    }
+                }
+                Payload::MemorySection(reader) => {
+                    for (i, memory) in reader.into_iter().enumerate() {
+                        let ty = memory.map_err(parse_err)?;
+                        let max = ty.maximum.map(|m| format!(" {m}")).unwrap_or_default();
+                        let _ = writeln!(self.out, "  (memory (;{i};) {}{max})", ty.initial);
+                    }
+                }
+                Payload::GlobalSection(reader) => {
+                    for (i, global) in reader.into_iter().enumerate() {
+                        let global = global.map_err(parse_err)?;
+                        let ty = if global.ty.mutable {
+                            format!("(mut {})", global.ty.content_type)
+                        } else {
+                            global.ty.content_type.to_string()
+                        };
+                        let init = self.const_expr(&global.init_expr)?;
+                        let _ = writeln!(
+                            self.out,
+                            "  (global {} {ty} {init})",
+                            global_label(i as u32)
+                        );
+                    }
+                }
+                Payload::ExportSection(reader) => {
+                    for export in reader {
+                        let export = export.map_err(parse_err)?;
+                        let item = match export.kind {
+                            ExternalKind::Func => {
+                                format!("func {}", self.func_label(export.index))
+                            }
+                            ExternalKind::Global => {
+                                format!("global {}", global_label(export.index))
+                            }
+                            ExternalKind::Memory => format!("memory {}", export.index),
+                            ExternalKind::Table => format!("table {}", export.index),
+                            ExternalKind::Tag => format!("tag {}", export.index),
+                        };
+                        let _ = writeln!(self.out, "  (export {:?} ({item}))", export.name);
+                    }
+                }
+                Payload::StartSection { func, .. } => {
+                    let _ = writeln!(self.out, "  (start {})", self.func_label(func));
+                }
+                Payload::ElementSection(reader) => {
+                    for (i, element) in reader.into_iter().enumerate() {
+                        let element = element.map_err(parse_err)?;
+                        let offset = match &element.kind {
+                            ElementKind::Active { offset_expr, .. } => {
+                                format!(" {}", self.const_expr(offset_expr)?)
+                            }
+                            ElementKind::Passive => String::new(),
+                            ElementKind::Declared => " declare".to_string(),
+                        };
+                        let mut items = String::new();
+                        if let ElementItems::Functions(funcs) = element.items {
+                            items.push_str(" func");
+                            for func in funcs {
+                                let func = func.map_err(parse_err)?;
+                                let _ = write!(items, " {}", self.func_label(func));
+                            }
+                        }
+                        let _ = writeln!(self.out, "  (elem (;{i};){offset}{items})");
+                    }
+                }
+                Payload::CodeSectionEntry(body) => {
+                    self.function(func_idx, &body)?;
+                    func_idx += 1;
+                }
+                Payload::DataSection(reader) => {
+                    for (i, segment) in reader.into_iter().enumerate() {
+                        let segment = segment.map_err(parse_err)?;
+                        let (offset, init) = match &segment.kind {
+                            DataKind::Active { offset_expr, .. } => (
+                                offset_expr
+                                    .get_operators_reader()
+                                    .read()
+                                    .ok()
+                                    .and_then(|op| match op {
+                                        Operator::I32Const { value } => Some(value as u32),
+                                        _ => None,
+                                    }),
+                                format!(" {}", self.const_expr(offset_expr)?),
+                            ),
+                            DataKind::Passive => (None, String::new()),
+                        };
+                        self.data_segment(i, &init, offset.unwrap_or(0), segment.data);
+                    }
+                }
+                Payload::CustomSection(section) => {
+                    let _ = writeln!(
+                        self.out,
+                        "  ;; custom section {:?} ({} bytes)",
+                        section.name(),
+                        section.data().len()
+                    );
+                }
+                _ => {}
+            }
+        }
+        self.out.push_str(")\n");
+        Ok(self.out)
+    }
+
+    fn func_label(&self, idx: u32) -> String {
+        match self.func_names.get(idx as usize) {
+            Some(Some(name)) => name.clone(),
+            _ => idx.to_string(),
+        }
+    }
+
+    fn const_expr(&self, expr: &wasmparser::ConstExpr) -> CodegenResult<String> {
+        let mut reader = expr.get_operators_reader();
+        let mut parts = Vec::new();
+        while !reader.eof() {
+            let op = reader.read().map_err(parse_err)?;
+            if !matches!(op, Operator::End) {
+                parts.push(format!("({})", self.instruction(&op)));
+            }
+        }
+        Ok(parts.join(" "))
+    }
+
+    fn function(&mut self, idx: u32, body: &wasmparser::FunctionBody) -> CodegenResult<()> {
+        let sig = self
+            .func_types
+            .get(idx as usize)
+            .and_then(|&ty| self.types.get(ty as usize).map(|f| (ty, signature(f))));
+        let (ty, sig) = sig.unwrap_or_default();
+        let name = match self.func_names.get(idx as usize) {
+            Some(Some(name)) => format!(" {name}"),
+            _ => String::new(),
+        };
+        let _ = writeln!(self.out, "  (func{name} (;{idx};) (type {ty}){sig}");
+
+        // `dispatch_action` and `render` also carry one entry per action / view.
+        let entries = self.source_map.entries.iter();
+        for (i, entry) in entries.filter(|e| e.wasm_func_index == idx).enumerate() {
+            let _ = writeln!(
+                self.out,
+                "    ;; {} {} at {}:{}",
+                kind_label(&entry.kind),
+                entry.func_name,
+                entry.span.start_line,
+                entry.span.start_col
+            );
+            if let Some(text) = self.line(entry.span.start_line).filter(|_| i == 0) {
+                let _ = writeln!(self.out, "    ;; {:>4} | {text}", entry.span.start_line);
+            }
+        }
+
+        let mut locals = String::new();
+        for group in body.get_locals_reader().map_err(parse_err)? {
+            let (count, ty) = group.map_err(parse_err)?;
+            for _ in 0..count {
+                let _ = write!(locals, " {ty}");
+            }
+        }
+        if !locals.is_empty() {
+            let _ = writeln!(self.out, "    (local{locals})");
+        }
+
+        let mut ops = Vec::new();
+        let mut reader = body.get_operators_reader().map_err(parse_err)?;
+        while !reader.eof() {
+            ops.push(reader.read().map_err(parse_err)?);
+        }
+
+        let mut depth = 0usize;
+        for (i, op) in ops.iter().enumerate() {
+            let next = ops.get(i + 1);
+            let indent = |depth: usize| "  ".repeat(depth + 2);
+            match op {
+                Operator::End if depth == 0 => break,
+                Operator::End => depth -= 1,
+                _ => {}
+            }
+
+            if let (
+                Operator::I32Const { value },
+                Some(Operator::GlobalSet {
+                    global_index: GLOBAL_TRAP_SITE,
+                }),
+            ) = (op, next)
+            {
+                if let Some(comment) = self.site_comment(*value as u32) {
+                    let _ = writeln!(self.out, "{};; {comment}", indent(depth));
+                }
+            }
+
+            let mut line = self.instruction(op);
+            if let (Some(Operator::I32Const { value: ptr }), Operator::I32Const { value: len }) =
+                (i.checked_sub(1).and_then(|p| ops.get(p)), op)
+            {
+                if matches!(next, Some(Operator::Call { .. })) {
+                    if let Some(text) = self.string_at(*ptr as u32, *len as u32) {
+                        self.strings.insert(*ptr as u32);
+                        self.strings.insert((*ptr + *len) as u32);
+                        let _ = write!(line, "  ;; {}", quote(text.as_bytes(), 40));
+                    }
+                }
+            }
+
+            let at = if matches!(op, Operator::Else) {
+                depth.saturating_sub(1)
+            } else {
+                depth
+            };
+            let _ = writeln!(self.out, "{}{line}", indent(at));
+
+            if matches!(
+                op,
+                Operator::Block { .. } | Operator::Loop { .. } | Operator::If { .. }
+            ) {
+                depth += 1;
+            }
+        }
+        self.out.push_str("  )\n");
+        Ok(())
+    }
+
+    /// `;; 12:5 action add | count = count + 1` for trap site `id`.
+    fn site_comment(&self, id: u32) -> Option<String> {
+        let site = self.source_map.find_site(id)?;
+        let mut comment = format!("{}:{}", site.span.start_line, site.span.start_col);
+        if let Some(frame) = site.frames.last() {
+            let _ = write!(comment, " {} {}", frame.kind, frame.name);
+        }
+        if let Some(text) = self.line(site.span.start_line) {
+            let _ = write!(comment, " | {}", text.trim());
+        }
+        Some(comment)
+    }
+
+    fn line(&self, line: u32) -> Option<&'a str> {
+        let text = self.lines.get((line as usize).checked_sub(1)?)?;
+        Some(text.trim_end())
+    }
+
+    /// The printable UTF-8 string at `[ptr, ptr + len)`, if a data segment
+    /// holds one there.
+    fn string_at(&self, ptr: u32, len: u32) -> Option<&'a str> {
+        if len == 0 {
+            return None;
+        }
+        let (offset, bytes) = self
+            .data
+            .iter()
+            .find(|(offset, bytes)| ptr >= *offset && ptr - offset < bytes.len() as u32)?;
+        let start = (ptr - offset) as usize;
+        let bytes = bytes.get(start..start.checked_add(len as usize)?)?;
+        let text = std::str::from_utf8(bytes).ok()?;
+        (!text.chars().any(char::is_control)).then_some(text)
+    }
+
+    /// Print a data segment, breaking lines at the strings seen in code and
+    /// between text and binary data.
+    fn data_segment(&mut self, idx: usize, init: &str, offset: u32, bytes: &[u8]) {
+        let _ = writeln!(self.out, "  (data (;{idx};){init}");
+        let end = offset + bytes.len() as u32;
+        let mut breaks: BTreeSet<u32> = self.strings.range(offset + 1..end).copied().collect();
+        breaks.extend(
+            bytes
+                .windows(2)
+                .enumerate()
+                .filter(|(_, pair)| printable(pair[0]) != printable(pair[1]))
+                .map(|(i, _)| offset + i as u32 + 1),
+        );
+        breaks.insert(end);
+        let mut start = offset;
+        for brk in breaks {
+            while start < brk {
+                let stop = brk.min(start + DATA_LINE_BYTES as u32);
+                let chunk = &bytes[(start - offset) as usize..(stop - offset) as usize];
+                let _ = writeln!(self.out, "    {}  ;; {start}", quote(chunk, usize::MAX));
+                start = stop;
+            }
+        }
+        self.out.push_str("  )\n");
+    }
+
+    fn instruction(&self, op: &Operator) -> String {
+        let mut text = mnemonic(op);
+        match op {
+            Operator::I32Const { value } => {
+                let _ = write!(text, " {value}");
+            }
+            Operator::I64Const { value } => {
+                let _ = write!(text, " {value}");
+            }
+            Operator::F32Const { value } => {
+                let _ = write!(text, " {}", float(f32::from_bits(value.bits()) as f64));
+            }
+            Operator::F64Const { value } => {
+                let _ = write!(text, " {}", float(f64::from_bits(value.bits())));
+            }
+            Operator::LocalGet { local_index }
+            | Operator::LocalSet { local_index }
+            | Operator::LocalTee { local_index } => {
+                let _ = write!(text, " {local_index}");
+            }
+            Operator::GlobalGet { global_index } | Operator::GlobalSet { global_index } => {
+                let _ = write!(text, " {}", global_label(*global_index));
+            }
+            Operator::Call { function_index }
+            | Operator::ReturnCall { function_index }
+            | Operator::RefFunc { function_index } => {
+                let _ = write!(text, " {}", self.func_label(*function_index));
+            }
+            Operator::CallIndirect {
+                type_index,
+                table_index,
+            } => {
+                if *table_index != 0 {
+                    let _ = write!(text, " {table_index}");
+                }
+                let _ = write!(text, " (type {type_index})");
+            }
+            Operator::Br { relative_depth } | Operator::BrIf { relative_depth } => {
+                let _ = write!(text, " {relative_depth}");
+            }
+            Operator::BrTable { targets } => {
+                for target in targets.targets().flatten() {
+                    let _ = write!(text, " {target}");
+                }
+                let _ = write!(text, " {}", targets.default());
+            }
+            Operator::Block { blockty } | Operator::Loop { blockty } | Operator::If { blockty } => {
+                match blockty {
+                    BlockType::Empty => {}
+                    BlockType::Type(ty) => {
+                        let _ = write!(text, " (result {ty})");
+                    }
+                    BlockType::FuncType(ty) => {
+                        let _ = write!(text, " (type {ty})");
+                    }
+                }
+            }
+            Operator::I32Load { memarg }
+            | Operator::I64Load { memarg }
+            | Operator::F32Load { memarg }
+            | Operator::F64Load { memarg }
+            | Operator::I32Load8S { memarg }
+            | Operator::I32Load8U { memarg }
+            | Operator::I32Load16S { memarg }
+            | Operator::I32Load16U { memarg }
+            | Operator::I64Load8S { memarg }
+            | Operator::I64Load8U { memarg }
+            | Operator::I64Load16S { memarg }
+            | Operator::I64Load16U { memarg }
+            | Operator::I64Load32S { memarg }
+            | Operator::I64Load32U { memarg }
+            | Operator::I32Store { memarg }
+            | Operator::I64Store { memarg }
+            | Operator::F32Store { memarg }
+            | Operator::F64Store { memarg }
+            | Operator::I32Store8 { memarg }
+            | Operator::I32Store16 { memarg }
+            | Operator::I64Store8 { memarg }
+            | Operator::I64Store16 { memarg }
+            | Operator::I64Store32 { memarg } => {
+                if memarg.offset != 0 {
+                    let _ = write!(text, " offset={}", memarg.offset);
+                }
+                if memarg.align != memarg.max_align {
+                    let _ = write!(text, " align={}", 1u32 << memarg.align);
+                }
+            }
+            Operator::MemorySize { .. }
+            | Operator::MemoryGrow { .. }
+            | Operator::MemoryCopy { .. }
+            | Operator::MemoryFill { .. } => {}
+            other => {
+                // Anything the generator doesn't emit: keep the immediates
+                // readable rather than dropping them.
+                let debug = format!("{other:?}");
+                if let Some((_, fields)) = debug.split_once(' ') {
+                    let _ = write!(text, " (; {fields} ;)");
+                }
+            }
+        }
+        text
+    }
+}
+
+/// Name of a defined function from the source map or the helper layout.
+fn func_name(source_map: &SourceMap, idx: u32, import_count: u32) -> Option<String> {
+    if let Some(entry) = source_map.find_by_func_index(idx) {
+        return Some(entry.func_name.clone());
+    }
+    let pos = idx.checked_sub(import_count)?;
+    let ordinal = if source_map.helpers.is_empty() {
+        pos
+    } else {
+        *source_map.helpers.get(pos as usize)?
+    };
+    match RT_NAMES.get(ordinal as usize) {
+        Some(name) => Some(name.to_string()),
+        None => stdlib::func_name(ordinal - RT_FUNC_COUNT)
+            .map(|name| name.trim_start_matches('$').to_string()),
+    }
+}
+
+fn global_label(idx: u32) -> String {
+    match GLOBAL_NAMES.get(idx as usize) {
+        Some(name) => format!("${name}"),
+        None => idx.to_string(),
+    }
+}
+
+/// WAT mnemonic of an operator, derived from its variant name:
+/// `I32Load8U` → `i32.load8_u`, `BrIf` → `br_if`.
+fn mnemonic(op: &Operator) -> String {
+    const PREFIXES: &[&str] = &[
+        "i32", "i64", "f32", "f64", "v128", "local", "global", "memory", "table", "ref", "elem",
+        "data",
+    ];
+    let debug = format!("{op:?}");
+    let variant = debug.split([' ', '{', '(']).next().unwrap_or_default();
+    let mut words: Vec<String> = Vec::new();
+    for c in variant.chars() {
+        if c.is_ascii_uppercase() || words.is_empty() {
+            words.push(String::new());
+        }
+        if let Some(word) = words.last_mut() {
+            word.push(c.to_ascii_lowercase());
+        }
+    }
+    match words.split_first() {
+        Some((first, rest)) if !rest.is_empty() && PREFIXES.contains(&first.as_str()) => {
+            format!("{first}.{}", rest.join("_"))
+        }
+        _ => words.join("_"),
+    }
+}
+
+fn signature(ty: &FuncType) -> String {
+    let mut sig = String::new();
+    if !ty.params().is_empty() {
+        sig.push_str(" (param");
+        for param in ty.params() {
+            let _ = write!(sig, " {param}");
+        }
+        sig.push(')');
+    }
+    if !ty.results().is_empty() {
+        sig.push_str(" (result");
+        for result in ty.results() {
+            let _ = write!(sig, " {result}");
+        }
+        sig.push(')');
+    }
+    sig
+}
+
+fn ref_type(ty: RefType) -> String {
+    ValType::Ref(ty).to_string()
+}
+
+fn kind_label(kind: &FuncKind) -> &'static str {
+    match kind {
+        FuncKind::SpaceInfra => "space",
+        FuncKind::Action => "action",
+        FuncKind::View => "view",
+        FuncKind::Update => "update",
+        FuncKind::HandleEvent => "handleEvent",
+        FuncKind::Test => "test",
+        FuncKind::TestCount => "test count",
+        FuncKind::TestHelper => "test helper",
+        FuncKind::Lambda => "lambda",
+        FuncKind::InvokeLambda => "lambda trampoline",
+    }
+}
+
+fn float(value: f64) -> String {
+    if value.is_nan() {
+        "nan".to_string()
+    } else if value.is_infinite() {
+        if value < 0.0 { "-inf" } else { "inf" }.to_string()
+    } else {
+        format!("{value:?}")
+    }
+}
+
+/// A WAT string literal for `bytes`, cut to `max` bytes with `...`.
+fn quote(bytes: &[u8], max: usize) -> String {
+    let mut text = String::from("\"");
+    for &b in bytes.iter().take(max) {
+        match b {
+            b'"' => text.push_str("\\\""),
+            b'\\' => text.push_str("\\\\"),
+            _ if printable(b) => text.push(b as char),
+            _ => {
+                let _ = write!(text, "\\{b:02x}");
+            }
+        }
+    }
+    text.push('"');
+    if bytes.len() > max {
+        text.push_str("...");
+    }
+    text
+}
+
+fn printable(b: u8) -> bool {
+    (0x20..=0x7e).contains(&b)
+}
+
+/// Keep only WAT identifier characters.
+fn sanitize(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || "!#$%&'*+-./:<=>?@\\^_`|~".contains(c) {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
@@ -10,12 +10,23 @@ use wasm_encoder::{BlockType, Function, Instruction, ValType};
 use crate::compiler::FuncContext;
 use crate::error::CodegenResult;
 use crate::gas;
+use crate::layout;
 use crate::runtime::*;
+use crate::stdlib;
 use crate::stmt::emit_stmts;
 use crate::types::*;
+use crate::unbox::{boxed_local, emit_box, emit_scalar, native_scalar, Scalar};
 
 /// Emit instructions for an expression.  Leaves one i32 (value ptr) on stack.
 pub fn emit_expr(expr: &Expr, ctx: &mut FuncContext, f: &mut Function) -> CodegenResult<()> {
+    // Statically typed numbers/bools are computed natively and boxed once.
+    if let Some(scalar) = native_scalar(expr, ctx) {
+        emit_scalar(expr, scalar, ctx, f)?;
+        emit_box(scalar, ctx, f);
+        return Ok(());
+    }
+
+    gas::charge_expr(expr, ctx);
     match &expr.kind {
         // ── Literals ──────────────────────────────────────────────────────
         ExprKind::NumberLit(n) => emit_number_lit(*n, ctx, f),
@@ -136,7 +147,10 @@ fn emit_lambda(
 
         for (ci, cap_name) in captured.iter().enumerate() {
             let (cap_key_ptr, cap_key_len) = ctx.intern_string(cap_name);
-            let cap_val_local = ctx.get_local(cap_name).unwrap_or(0);
+            let cap_val_local = match ctx.get_local(cap_name) {
+                Some(local) => boxed_local(local, ctx, f),
+                None => 0,
+            };
             let base = (ci * 12) as u64;
             // key_offset
             f.instruction(&Instruction::LocalGet(cap_entries));
@@ -253,7 +267,18 @@ fn emit_record_lit(
         f.instruction(&Instruction::Call(rt_func_idx(RT_ALLOC)));
         f.instruction(&Instruction::LocalSet(entries_local));
 
-        let mut idx = 0usize;
+        // Values are evaluated in source order but stored in the canonical
+        // (name-ordered) layout the checker's field slots assume.
+        let mut by_name: Vec<&str> = entries
+            .iter()
+            .filter_map(|e| match e {
+                RecordEntry::Field { name, .. } => Some(name.name.as_str()),
+                RecordEntry::Spread(_) => None,
+            })
+            .collect();
+        by_name.sort();
+        let mut taken = vec![false; by_name.len()];
+
         for entry in entries {
             if let RecordEntry::Field { name, value } = entry {
                 let (key_ptr, key_len) = ctx.intern_string(&name.name);
@@ -261,7 +286,11 @@ fn emit_record_lit(
                 emit_expr(value, ctx, f)?;
                 f.instruction(&Instruction::LocalSet(val_local));
 
-                let base_offset = (idx * 12) as u64;
+                let idx = (0..by_name.len())
+                    .find(|&i| !taken[i] && by_name[i] == name.name)
+                    .expect("field is in the sorted names");
+                taken[idx] = true;
+                let base_offset = layout::entry_offset(idx as u32);
                 f.instruction(&Instruction::LocalGet(entries_local));
                 f.instruction(&Instruction::I32Const(key_ptr as i32));
                 f.instruction(&Instruction::I32Store(memarg(base_offset, 2)));
@@ -271,7 +300,6 @@ fn emit_record_lit(
                 f.instruction(&Instruction::LocalGet(entries_local));
                 f.instruction(&Instruction::LocalGet(val_local));
                 f.instruction(&Instruction::I32Store(memarg(base_offset + 8, 2)));
-                idx += 1;
             }
         }
 
@@ -509,13 +537,10 @@ fn emit_identifier(name: &str, ctx: &mut FuncContext, f: &mut Function) -> Codeg
         return Ok(());
     }
 
-    // State field access: record_get(state_ptr, key)
-    if ctx.is_state_field(name) {
-        let (key_ptr, key_len) = ctx.intern_string(name);
+    // State field access: a fixed slot of the state record
+    if let Some(slot) = ctx.state_slot(name) {
         f.instruction(&Instruction::GlobalGet(GLOBAL_STATE_PTR));
-        f.instruction(&Instruction::I32Const(key_ptr as i32));
-        f.instruction(&Instruction::I32Const(key_len as i32));
-        f.instruction(&Instruction::Call(rt_func_idx(RT_VAL_RECORD_GET)));
+        layout::emit_fixed_load(slot, f);
         return Ok(());
     }
 
@@ -537,9 +562,6 @@ fn emit_call(
     ctx: &mut FuncContext,
     f: &mut Function,
 ) -> CodegenResult<()> {
-    // Gas tick at every call site
-    gas::emit_gas_tick(f, ctx.data.gas_exhausted_ptr, ctx.data.gas_exhausted_len);
-
     // Check if this is a locally-defined function (action call, etc.)
     if let Some(func_idx) = ctx.get_function(name) {
         // Push args
@@ -566,14 +588,34 @@ fn emit_qualified_call(
     ctx: &mut FuncContext,
     f: &mut Function,
 ) -> CodegenResult<()> {
-    gas::emit_gas_tick(f, ctx.data.gas_exhausted_ptr, ctx.data.gas_exhausted_len);
+    // `list.of(a, b, …)` is variadic sugar for a list literal.
+    if module == "list" && function == "of" {
+        emit_list_lit(args, ctx, f)?;
+        gas::emit_stdlib_charge(module, function, &[], ctx, f);
+        return Ok(());
+    }
+
+    // Pure stdlib functions are compiled into the module: call directly.
+    if let Some((func_idx, arity)) = stdlib::pure_function(module, function) {
+        if args.len() == arity {
+            let mut values = Vec::with_capacity(arity + 1);
+            for arg in args {
+                let tmp = ctx.alloc_local(ValType::I32);
+                emit_expr(arg, ctx, f)?;
+                f.instruction(&Instruction::LocalSet(tmp));
+                values.push(tmp);
+            }
+            emit_stdlib_call(module, function, func_idx, &values, ctx, f);
+            return Ok(());
+        }
+    }
 
-    // Stdlib calls are dispatched via host_call for capability modules,
-    // or handled inline for pure modules (math, string, list, etc.).
-    // For now, we lower all qualified calls to host_call with serialized args.
+    // Capabilities, `time`, `timer` and `core.capability` go through
+    // host_call with serialized args.
 
     // Evaluate args into a list value
     let args_local = ctx.alloc_local(ValType::I32);
+    let mut values = Vec::with_capacity(args.len() + 1);
     if args.is_empty() {
         f.instruction(&Instruction::I32Const(0));
         f.instruction(&Instruction::I32Const(0));
@@ -592,6 +634,7 @@ fn emit_qualified_call(
             f.instruction(&Instruction::LocalGet(arr_local));
             f.instruction(&Instruction::LocalGet(tmp));
             f.instruction(&Instruction::I32Store(memarg(i as u64 * 4, 2)));
+            values.push(tmp);
         }
         f.instruction(&Instruction::LocalGet(arr_local));
         f.instruction(&Instruction::I32Const(count));
@@ -608,16 +651,70 @@ fn emit_qualified_call(
     f.instruction(&Instruction::LocalGet(args_local));
     f.instruction(&Instruction::Call(IMPORT_HOST_CALL));
 
+    let result_local = ctx.alloc_local(ValType::I32);
+    f.instruction(&Instruction::LocalSet(result_local));
+    values.push(result_local);
+    gas::emit_stdlib_charge(module, function, &values, ctx, f);
+    f.instruction(&Instruction::LocalGet(result_local));
     Ok(())
 }
 
+/// Call the compiled stdlib function `func_idx` on the value locals `args`
+/// and charge its gas.  Leaves the result on the stack.
+fn emit_stdlib_call(
+    module: &str,
+    function: &str,
+    func_idx: u32,
+    args: &[u32],
+    ctx: &mut FuncContext,
+    f: &mut Function,
+) {
+    for &arg in args {
+        f.instruction(&Instruction::LocalGet(arg));
+    }
+    f.instruction(&Instruction::Call(func_idx));
+    let result_local = ctx.alloc_local(ValType::I32);
+    f.instruction(&Instruction::LocalSet(result_local));
+    let mut values = args.to_vec();
+    values.push(result_local);
+    gas::emit_stdlib_charge(module, function, &values, ctx, f);
+    f.instruction(&Instruction::LocalGet(result_local));
+}
+
 fn emit_field_access(
     object: &Expr,
     field: &str,
     ctx: &mut FuncContext,
     f: &mut Function,
 ) -> CodegenResult<()> {
+    // `math.PI` / `math.E`, unless `math` is a local or state field
+    if let ExprKind::Identifier(module) = &object.kind {
+        if module == "math" && ctx.get_local(module).is_none() && !ctx.is_state_field(module) {
+            let constant = match field {
+                "PI" => Some(std::f64::consts::PI),
+                "E" => Some(std::f64::consts::E),
+                _ => None,
+            };
+            if let Some(value) = constant {
+                // The receiver is still charged, as the evaluator reads it
+                gas::charge_expr(object, ctx);
+                f.instruction(&Instruction::F64Const(value));
+                f.instruction(&Instruction::Call(
+                    stdlib::func_idx("$num").expect("stdlib $num"),
+                ));
+                return Ok(());
+            }
+        }
+    }
+
     emit_expr(object, ctx, f)?;
+    let slot = ctx.layouts.get(object.span).and_then(|l| l.slot(field));
+    if let Some(slot) = slot {
+        let record_local = ctx.alloc_local(ValType::I32);
+        f.instruction(&Instruction::LocalSet(record_local));
+        layout::emit_slot_load(record_local, slot, field, ctx, f);
+        return Ok(());
+    }
     let (key_ptr, key_len) = ctx.intern_string(field);
     f.instruction(&Instruction::I32Const(key_ptr as i32));
     f.instruction(&Instruction::I32Const(key_len as i32));
@@ -634,8 +731,48 @@ fn emit_method_call(
 ) -> CodegenResult<()> {
     // Method calls in PEPL are sugar for qualified calls on the receiver type.
     // E.g., `items.length()` → `list.length(items)`
-    // We emit as host_call with the receiver as the first arg.
-    gas::emit_gas_tick(f, ctx.data.gas_exhausted_ptr, ctx.data.gas_exhausted_len);
+
+    // Receivers are lists or strings; pick the compiled stdlib function by
+    // the receiver's tag at runtime when both modules define the method.
+    let arity = 1 + args.len();
+    let by_arity = |module| stdlib::pure_function(module, method).filter(|&(_, n)| n == arity);
+    let list_fn = by_arity("list");
+    let string_fn = by_arity("string");
+    if list_fn.is_some() || string_fn.is_some() {
+        let recv_local = ctx.alloc_local(ValType::I32);
+        emit_expr(object, ctx, f)?;
+        f.instruction(&Instruction::LocalSet(recv_local));
+        let mut arg_locals = Vec::with_capacity(args.len());
+        for arg in args {
+            let tmp = ctx.alloc_local(ValType::I32);
+            emit_expr(arg, ctx, f)?;
+            f.instruction(&Instruction::LocalSet(tmp));
+            arg_locals.push(tmp);
+        }
+        arg_locals.insert(0, recv_local);
+        match (list_fn, string_fn) {
+            (Some((list_idx, _)), Some((string_idx, _))) => {
+                gas::flush(ctx, f);
+                f.instruction(&Instruction::LocalGet(recv_local));
+                f.instruction(&Instruction::I32Load(memarg(0, 2)));
+                f.instruction(&Instruction::I32Const(TAG_LIST));
+                f.instruction(&Instruction::I32Eq);
+                f.instruction(&Instruction::If(BlockType::Result(ValType::I32)));
+                emit_stdlib_call("list", method, list_idx, &arg_locals, ctx, f);
+                gas::flush(ctx, f);
+                f.instruction(&Instruction::Else);
+                emit_stdlib_call("string", method, string_idx, &arg_locals, ctx, f);
+                gas::flush(ctx, f);
+                f.instruction(&Instruction::End);
+            }
+            (Some((idx, _)), None) => emit_stdlib_call("list", method, idx, &arg_locals, ctx, f),
+            (None, Some((idx, _))) => emit_stdlib_call("string", method, idx, &arg_locals, ctx, f),
+            (None, None) => unreachable!(),
+        }
+        return Ok(());
+    }
+
+    // Anything else is left to the host, with the receiver as the first arg.
 
     let total_args = 1 + args.len();
     let arr_local = ctx.alloc_local(ValType::I32);
@@ -652,6 +789,7 @@ fn emit_method_call(
     f.instruction(&Instruction::LocalGet(arr_local));
     f.instruction(&Instruction::LocalGet(recv_local));
     f.instruction(&Instruction::I32Store(memarg(0, 2)));
+    let mut values = vec![recv_local];
 
     // Store remaining args
     for (i, arg) in args.iter().enumerate() {
@@ -661,6 +799,7 @@ fn emit_method_call(
         f.instruction(&Instruction::LocalGet(arr_local));
         f.instruction(&Instruction::LocalGet(tmp));
         f.instruction(&Instruction::I32Store(memarg((i as u64 + 1) * 4, 2)));
+        values.push(tmp);
     }
 
     // Determine module from method name (heuristic: number methods → math, etc.)
@@ -674,9 +813,42 @@ fn emit_method_call(
     f.instruction(&Instruction::Call(rt_func_idx(RT_VAL_LIST)));
     f.instruction(&Instruction::Call(IMPORT_HOST_CALL));
 
+    let result_local = ctx.alloc_local(ValType::I32);
+    f.instruction(&Instruction::LocalSet(result_local));
+    values.push(result_local);
+    emit_method_charge(method, recv_local, &values, ctx, f);
+    f.instruction(&Instruction::LocalGet(result_local));
     Ok(())
 }
 
+/// Charge a method call the host ran, at the price of `list.method` or
+/// `string.method` according to the receiver's tag.
+fn emit_method_charge(
+    method: &str,
+    recv_local: u32,
+    values: &[u32],
+    ctx: &mut FuncContext,
+    f: &mut Function,
+) {
+    let list_cost = pepl_types::gas::stdlib_cost("list", method);
+    if list_cost == pepl_types::gas::stdlib_cost("string", method) {
+        gas::emit_stdlib_charge("list", method, values, ctx, f);
+        return;
+    }
+    gas::flush(ctx, f);
+    f.instruction(&Instruction::LocalGet(recv_local));
+    f.instruction(&Instruction::I32Load(memarg(0, 2)));
+    f.instruction(&Instruction::I32Const(TAG_LIST));
+    f.instruction(&Instruction::I32Eq);
+    f.instruction(&Instruction::If(BlockType::Empty));
+    gas::emit_stdlib_charge("list", method, values, ctx, f);
+    gas::flush(ctx, f);
+    f.instruction(&Instruction::Else);
+    gas::emit_stdlib_charge("string", method, values, ctx, f);
+    gas::flush(ctx, f);
+    f.instruction(&Instruction::End);
+}
+
 // ══════════════════════════════════════════════════════════════════════════════
 // Operators
 // ══════════════════════════════════════════════════════════════════════════════
@@ -696,8 +868,10 @@ fn emit_binary(
             f.instruction(&Instruction::LocalTee(left_local));
             // Check truthy (for bools: w1 != 0)
             f.instruction(&Instruction::I32Load(memarg(4, 2)));
+            gas::flush(ctx, f);
             f.instruction(&Instruction::If(BlockType::Result(ValType::I32)));
             emit_expr(right, ctx, f)?;
+            gas::flush(ctx, f);
             f.instruction(&Instruction::Else);
             f.instruction(&Instruction::LocalGet(left_local));
             f.instruction(&Instruction::End);
@@ -709,10 +883,12 @@ fn emit_binary(
             emit_expr(left, ctx, f)?;
             f.instruction(&Instruction::LocalTee(left_local));
             f.instruction(&Instruction::I32Load(memarg(4, 2)));
+            gas::flush(ctx, f);
             f.instruction(&Instruction::If(BlockType::Result(ValType::I32)));
             f.instruction(&Instruction::LocalGet(left_local));
             f.instruction(&Instruction::Else);
             emit_expr(right, ctx, f)?;
+            gas::flush(ctx, f);
             f.instruction(&Instruction::End);
             return Ok(());
         }
@@ -729,12 +905,21 @@ fn emit_binary(
 
     match op {
         BinOp::Add => {
-            // Add can be number + number or string + string
-            // Check if both are numbers: tag check
-            // For now, assume numeric addition (the type checker should have validated)
+            // number + number or string + string; the type checker rules
+            // out mixes, so the left tag decides
+            f.instruction(&Instruction::LocalGet(a));
+            f.instruction(&Instruction::I32Load(memarg(0, 2)));
+            f.instruction(&Instruction::I32Const(TAG_STRING));
+            f.instruction(&Instruction::I32Eq);
+            f.instruction(&Instruction::If(BlockType::Result(ValType::I32)));
+            f.instruction(&Instruction::LocalGet(a));
+            f.instruction(&Instruction::LocalGet(b));
+            f.instruction(&Instruction::Call(rt_func_idx(RT_VAL_STRING_CONCAT)));
+            f.instruction(&Instruction::Else);
             f.instruction(&Instruction::LocalGet(a));
             f.instruction(&Instruction::LocalGet(b));
             f.instruction(&Instruction::Call(rt_func_idx(RT_VAL_ADD)));
+            f.instruction(&Instruction::End);
         }
         BinOp::Sub => {
             f.instruction(&Instruction::LocalGet(a));
@@ -864,8 +1049,10 @@ fn emit_nil_coalesce(
     f.instruction(&Instruction::I32Load(memarg(0, 2)));
     f.instruction(&Instruction::I32Const(TAG_NIL));
     f.instruction(&Instruction::I32Eq);
+    gas::flush(ctx, f);
     f.instruction(&Instruction::If(BlockType::Result(ValType::I32)));
     emit_expr(right, ctx, f)?;
+    gas::flush(ctx, f);
     f.instruction(&Instruction::Else);
     f.instruction(&Instruction::LocalGet(left_local));
     f.instruction(&Instruction::End);
@@ -881,15 +1068,15 @@ fn emit_if_expr(
     ctx: &mut FuncContext,
     f: &mut Function,
 ) -> CodegenResult<()> {
-    // Evaluate condition
-    emit_expr(&if_expr.condition, ctx, f)?;
-    // Extract bool value: load w1 (i32)
-    f.instruction(&Instruction::I32Load(memarg(4, 2)));
+    // Evaluate condition as a native i32 bool
+    emit_scalar(&if_expr.condition, Scalar::Bool, ctx, f)?;
+    gas::flush(ctx, f);
 
     f.instruction(&Instruction::If(BlockType::Result(ValType::I32)));
 
     // Then branch — execute stmts, the last expr is the value
     emit_block_as_expr(&if_expr.then_block, ctx, f)?;
+    gas::flush(ctx, f);
 
     f.instruction(&Instruction::Else);
 
@@ -897,8 +1084,10 @@ fn emit_if_expr(
     match &if_expr.else_branch {
         Some(ElseBranch::Block(block)) => {
             emit_block_as_expr(block, ctx, f)?;
+            gas::flush(ctx, f);
         }
         Some(ElseBranch::ElseIf(elif)) => {
+            gas::charge(pepl_types::gas::ELSE_IF, ctx);
             emit_if_expr(elif, ctx, f)?;
         }
         None => {
@@ -941,11 +1130,7 @@ fn emit_for_expr(
 
     // The "item" local for each iteration
     let item_local = ctx.alloc_local(ValType::I32);
-    let index_local = if for_expr.index.is_some() {
-        Some(ctx.alloc_local(ValType::I32))
-    } else {
-        None
-    };
+    let index_local = for_expr.index.as_ref().map(|_| alloc_loop_index(ctx));
 
     // Register item binding
     ctx.push_local(&for_expr.item.name, item_local);
@@ -953,17 +1138,16 @@ fn emit_for_expr(
         ctx.push_local(&idx_ident.name, idx_local);
     }
 
+    gas::flush(ctx, f);
     f.instruction(&Instruction::Block(BlockType::Empty));
     f.instruction(&Instruction::Loop(BlockType::Empty));
 
-    // Gas tick at loop boundary
-    gas::emit_gas_tick(f, ctx.data.gas_exhausted_ptr, ctx.data.gas_exhausted_len);
-
     // break if i >= count
     f.instruction(&Instruction::LocalGet(i_local));
     f.instruction(&Instruction::LocalGet(count_local));
     f.instruction(&Instruction::I32GeU);
     f.instruction(&Instruction::BrIf(1));
+    gas::charge(pepl_types::gas::FOR_ITERATION, ctx);
 
     // item = arr[i]
     f.instruction(&Instruction::LocalGet(arr_local));
@@ -976,20 +1160,12 @@ fn emit_for_expr(
 
     // index = i (as number value)
     if let Some(idx_local) = index_local {
-        // Create a number value from i
-        f.instruction(&Instruction::I32Const(VALUE_SIZE as i32));
-        f.instruction(&Instruction::Call(rt_func_idx(RT_ALLOC)));
-        f.instruction(&Instruction::LocalTee(idx_local));
-        f.instruction(&Instruction::I32Const(TAG_NUMBER));
-        f.instruction(&Instruction::I32Store(memarg(0, 2)));
-        f.instruction(&Instruction::LocalGet(idx_local));
-        f.instruction(&Instruction::LocalGet(i_local));
-        f.instruction(&Instruction::F64ConvertI32U);
-        f.instruction(&Instruction::F64Store(memarg(4, 3)));
+        emit_loop_index(i_local, idx_local, ctx, f);
     }
 
     // Execute body
     emit_stmts(&for_expr.body.stmts, ctx, f)?;
+    gas::flush(ctx, f);
 
     // i += 1
     f.instruction(&Instruction::LocalGet(i_local));
@@ -1012,7 +1188,7 @@ fn emit_for_expr(
     Ok(())
 }
 
-fn emit_match_expr(
+pub(crate) fn emit_match_expr(
     match_expr: &MatchExpr,
     ctx: &mut FuncContext,
     f: &mut Function,
@@ -1021,6 +1197,7 @@ fn emit_match_expr(
     let subj_local = ctx.alloc_local(ValType::I32);
     emit_expr(&match_expr.subject, ctx, f)?;
     f.instruction(&Instruction::LocalSet(subj_local));
+    gas::flush(ctx, f);
 
     // For now, emit a simple if/else chain testing each arm's pattern.
     // Each arm: if pattern matches → execute body, else try next.
@@ -1046,6 +1223,7 @@ fn emit_match_expr(
                         emit_block_as_expr(block, ctx, f)?;
                     }
                 }
+                gas::flush(ctx, f);
                 f.instruction(&Instruction::LocalSet(result_local));
                 f.instruction(&Instruction::Br(0));
             }
@@ -1068,8 +1246,17 @@ fn emit_match_expr(
                 f.instruction(&Instruction::If(BlockType::Empty));
 
                 // Bind destructured fields
-                // The variant data is a record at w2
-                if !bindings.is_empty() {
+                // A result's payload is w2 itself; user variant data is a
+                // list at w2
+                if vid == VARIANT_OK || vid == VARIANT_ERR {
+                    if let Some(binding) = bindings.first() {
+                        let bind_local = ctx.alloc_local(ValType::I32);
+                        f.instruction(&Instruction::LocalGet(subj_local));
+                        f.instruction(&Instruction::I32Load(memarg(8, 2)));
+                        f.instruction(&Instruction::LocalSet(bind_local));
+                        ctx.push_local(&binding.name, bind_local);
+                    }
+                } else if !bindings.is_empty() {
                     let data_local = ctx.alloc_local(ValType::I32);
                     f.instruction(&Instruction::LocalGet(subj_local));
                     f.instruction(&Instruction::I32Load(memarg(8, 2)));
@@ -1098,10 +1285,16 @@ fn emit_match_expr(
                 f.instruction(&Instruction::LocalSet(result_local));
 
                 // Pop bindings
-                for binding in bindings.iter().rev() {
+                let bound = if vid == VARIANT_OK || vid == VARIANT_ERR {
+                    bindings.len().min(1)
+                } else {
+                    bindings.len()
+                };
+                for binding in bindings[..bound].iter().rev() {
                     ctx.pop_local(&binding.name);
                 }
 
+                gas::flush(ctx, f);
                 f.instruction(&Instruction::Br(2)); // break outer block
 
                 f.instruction(&Instruction::End); // end variant_id check
@@ -1139,6 +1332,7 @@ pub fn emit_block_as_expr(
     // The last statement: if it's an Expr statement, leave value on stack
     match last {
         Stmt::Expr(expr_stmt) => {
+            gas::charge_stmt(last, ctx);
             emit_expr(&expr_stmt.expr, ctx, f)?;
         }
         _ => {
@@ -1150,6 +1344,36 @@ pub fn emit_block_as_expr(
     Ok(())
 }
 
+/// Allocate the local for a `for` loop's index binding.  When checker types
+/// are available the index is a native `f64` and is only boxed on escape.
+pub(crate) fn alloc_loop_index(ctx: &mut FuncContext) -> u32 {
+    if ctx.scalars.is_empty() {
+        ctx.alloc_local(ValType::I32)
+    } else {
+        ctx.alloc_unboxed_local(Scalar::Number)
+    }
+}
+
+/// Store the loop counter `i_local` into the index binding `idx_local`.
+pub(crate) fn emit_loop_index(i_local: u32, idx_local: u32, ctx: &FuncContext, f: &mut Function) {
+    if ctx.unboxed_local(idx_local).is_some() {
+        f.instruction(&Instruction::LocalGet(i_local));
+        f.instruction(&Instruction::F64ConvertI32U);
+        f.instruction(&Instruction::LocalSet(idx_local));
+        return;
+    }
+    // Create a number value from i
+    f.instruction(&Instruction::I32Const(VALUE_SIZE as i32));
+    f.instruction(&Instruction::Call(rt_func_idx(RT_ALLOC)));
+    f.instruction(&Instruction::LocalTee(idx_local));
+    f.instruction(&Instruction::I32Const(TAG_NUMBER));
+    f.instruction(&Instruction::I32Store(memarg(0, 2)));
+    f.instruction(&Instruction::LocalGet(idx_local));
+    f.instruction(&Instruction::LocalGet(i_local));
+    f.instruction(&Instruction::F64ConvertI32U);
+    f.instruction(&Instruction::F64Store(memarg(4, 3)));
+}
+
 /// Create a `MemArg`.
 fn memarg(offset: u64, align: u32) -> wasm_encoder::MemArg {
     wasm_encoder::MemArg {
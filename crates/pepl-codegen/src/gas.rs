@@ -1,38 +1,130 @@
 //! Gas-metering instrumentation.
 //!
-//! Injects a `gas_tick` call at:
-//! - Every `for` loop iteration header
-//! - Every action / function call site
-//! - Every `update()` call boundary
+//! Costs come from the [`pepl_types::gas`] table the evaluator charges
+//! from, so a module spends exactly the gas the evaluator spends on the same
+//! program.  Static costs are collected in [`FuncContext::gas_pending`]
+//! while a straight run of code is emitted and added to the counter in one
+//! step by [`flush`].  Emitters flush before anything that branches
+//! (`if`, loop headers and back-edges, `br`, `return`, function end), so
+//! every path pays for exactly the nodes it runs.  Stdlib calls whose cost
+//! scales with their size are charged at runtime by [`emit_stdlib_charge`].
 //!
 //! When the counter exceeds the limit, the runtime traps.
 
-use wasm_encoder::{Function, Instruction};
+use pepl_types::ast::{Expr, Stmt};
+use pepl_types::gas::{self, Growth};
+use wasm_encoder::{BlockType, Function, Instruction, ValType};
 
-use crate::types::{GLOBAL_GAS, GLOBAL_GAS_LIMIT, IMPORT_TRAP};
+use crate::compiler::FuncContext;
+use crate::stmt::stmt_span;
+use crate::types::{GLOBAL_GAS, GLOBAL_GAS_LIMIT, IMPORT_TRAP, TAG_LIST, TAG_STRING};
 
-/// Emit instructions that increment the gas counter and trap if exhausted.
+/// Charge `cost` at the next [`flush`].
+pub fn charge(cost: u64, ctx: &mut FuncContext) {
+    ctx.gas_pending += cost;
+}
+
+/// Charge one evaluation of `expr` (its own node, not its children), plus
+/// whatever the optimiser folded into it.
+pub fn charge_expr(expr: &Expr, ctx: &mut FuncContext) {
+    let cost = gas::expr_cost(&expr.kind) + ctx.gas_carry.expr(expr.span);
+    charge(cost, ctx);
+}
+
+/// Charge one execution of `stmt`, plus whatever the optimiser folded into it.
+pub fn charge_stmt(stmt: &Stmt, ctx: &mut FuncContext) {
+    let cost = gas::stmt_cost(stmt) + ctx.gas_carry.stmt(stmt_span(stmt));
+    charge(cost, ctx);
+}
+
+/// Add the pending charges to the gas counter, trapping if it exceeds the
+/// limit.  Emits nothing when nothing is pending.
+pub fn flush(ctx: &mut FuncContext, f: &mut Function) {
+    let pending = std::mem::take(&mut ctx.gas_pending);
+    if pending == 0 {
+        return;
+    }
+    f.instruction(&Instruction::I32Const(pending as i32));
+    emit_add_gas(ctx, f);
+}
+
+/// Charge the stdlib cost of `module.function` for a call whose arguments
+/// and result are in the value locals `values`.  Size-independent costs
+/// join the pending charges; the others are computed and charged in place.
+pub fn emit_stdlib_charge(
+    module: &str,
+    function: &str,
+    values: &[u32],
+    ctx: &mut FuncContext,
+    f: &mut Function,
+) {
+    let cost = gas::stdlib_cost(module, function);
+    if cost.growth == Growth::Constant {
+        charge(cost.base, ctx);
+        return;
+    }
+
+    // size = Σ (list or string ? w2 : 0)
+    let size = ctx.alloc_local(ValType::I32);
+    f.instruction(&Instruction::I32Const(0));
+    for &value in values {
+        f.instruction(&Instruction::LocalGet(value));
+        f.instruction(&Instruction::I32Load(memarg(8)));
+        f.instruction(&Instruction::I32Const(0));
+        for tag in [TAG_LIST, TAG_STRING] {
+            f.instruction(&Instruction::LocalGet(value));
+            f.instruction(&Instruction::I32Load(memarg(0)));
+            f.instruction(&Instruction::I32Const(tag));
+            f.instruction(&Instruction::I32Eq);
+        }
+        f.instruction(&Instruction::I32Or);
+        f.instruction(&Instruction::Select);
+        f.instruction(&Instruction::I32Add);
+    }
+    f.instruction(&Instruction::LocalTee(size));
+
+    if cost.growth == Growth::NLogN {
+        // × bit length of the size
+        f.instruction(&Instruction::I32Const(32));
+        f.instruction(&Instruction::LocalGet(size));
+        f.instruction(&Instruction::I32Clz);
+        f.instruction(&Instruction::I32Sub);
+        f.instruction(&Instruction::I32Mul);
+    }
+    f.instruction(&Instruction::I32Const(cost.per_item as i32));
+    f.instruction(&Instruction::I32Mul);
+    f.instruction(&Instruction::I32Const(cost.base as i32));
+    f.instruction(&Instruction::I32Add);
+    emit_add_gas(ctx, f);
+}
+
+/// Add the i32 on the stack to the gas counter and trap if it is exhausted.
 ///
 /// Equivalent pseudo-code:
 /// ```text
-/// gas += 1
+/// gas += n
 /// if gas > gas_limit { trap("gas exhausted") }
 /// ```
-pub fn emit_gas_tick(f: &mut Function, trap_msg_ptr: u32, trap_msg_len: u32) {
-    // gas += 1
+fn emit_add_gas(ctx: &FuncContext, f: &mut Function) {
     f.instruction(&Instruction::GlobalGet(GLOBAL_GAS));
-    f.instruction(&Instruction::I32Const(1));
     f.instruction(&Instruction::I32Add);
     f.instruction(&Instruction::GlobalSet(GLOBAL_GAS));
 
-    // if gas > gas_limit → trap
     f.instruction(&Instruction::GlobalGet(GLOBAL_GAS));
     f.instruction(&Instruction::GlobalGet(GLOBAL_GAS_LIMIT));
     f.instruction(&Instruction::I32GtU);
-    f.instruction(&Instruction::If(wasm_encoder::BlockType::Empty));
-    f.instruction(&Instruction::I32Const(trap_msg_ptr as i32));
-    f.instruction(&Instruction::I32Const(trap_msg_len as i32));
+    f.instruction(&Instruction::If(BlockType::Empty));
+    f.instruction(&Instruction::I32Const(ctx.data.gas_exhausted_ptr as i32));
+    f.instruction(&Instruction::I32Const(ctx.data.gas_exhausted_len as i32));
     f.instruction(&Instruction::Call(IMPORT_TRAP));
     f.instruction(&Instruction::Unreachable);
     f.instruction(&Instruction::End);
 }
+
+fn memarg(offset: u64) -> wasm_encoder::MemArg {
+    wasm_encoder::MemArg {
+        offset,
+        align: 2,
+        memory_index: 0,
+    }
+}
@@ -0,0 +1,177 @@
+//! Compile-time record layouts.
+//!
+//! A record value is a RECORD cell whose `w1` points at `w2` entries of
+//! 12 bytes, `[key_ptr, key_len, value_ptr]`.  The runtime lookup
+//! ([`crate::runtime::emit_val_record_get`]) compares key bytes against every
+//! entry; when the shape is known at compile time a field lives at a fixed
+//! slot instead and is read with two loads.
+//!
+//! Layouts come from two places:
+//!
+//! - Records the code generator alone builds: the state record (one slot per
+//!   state and derived field, in declaration order) and lambda capture
+//!   environments.  These are read at their slot unconditionally.
+//! - Record types proven by the checker, reported as a [`RecordLayouts`]
+//!   table keyed by expression span.  Their fields are in name order, the
+//!   order record literals without a spread are stored in.
+//!
+//! Checked record types are structural — a value typed `{ a, b }` may carry
+//! more fields, or come from a spread, `json.parse` or the host in any
+//! order — so a checked slot is only used after confirming the entry's key
+//! pointer (keys are interned once per module); any other record takes the
+//! runtime lookup.
+
+use std::collections::HashMap;
+
+use pepl_types::Span;
+use wasm_encoder::{BlockType, Function, Instruction, ValType};
+
+use crate::compiler::FuncContext;
+use crate::runtime::{memarg, rt_func_idx, RT_VAL_RECORD_GET};
+
+// ══════════════════════════════════════════════════════════════════════════════
+// Layout table
+// ══════════════════════════════════════════════════════════════════════════════
+
+/// The field order of a record whose shape is known: sorted by name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecordLayout {
+    fields: Vec<String>,
+}
+
+impl RecordLayout {
+    /// The canonical layout of a record with the given field names.
+    pub fn new<S: Into<String>>(fields: impl IntoIterator<Item = S>) -> Self {
+        let mut fields: Vec<String> = fields.into_iter().map(Into::into).collect();
+        fields.sort();
+        fields.dedup();
+        Self { fields }
+    }
+
+    /// Slot index of `field`, if the record has it.
+    pub fn slot(&self, field: &str) -> Option<u32> {
+        self.fields
+            .binary_search_by(|f| f.as_str().cmp(field))
+            .ok()
+            .map(|i| i as u32)
+    }
+
+    pub fn fields(&self) -> &[String] {
+        &self.fields
+    }
+
+    pub fn len(&self) -> usize {
+        self.fields.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+}
+
+/// Expression span → layout of the record it evaluates to.
+///
+/// Built by the type checker; spans recorded with conflicting layouts, or
+/// never recorded, use the runtime lookup.  Nested `set` targets are keyed
+/// by the span of each path identifier.
+#[derive(Debug, Clone, Default)]
+pub struct RecordLayouts {
+    layouts: HashMap<Span, Option<RecordLayout>>,
+}
+
+impl RecordLayouts {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record the checked layout at `span` (`None` when the value is not a
+    /// record of known shape).
+    pub fn record(&mut self, span: Span, layout: Option<RecordLayout>) {
+        self.layouts
+            .entry(span)
+            .and_modify(|known| {
+                if *known != layout {
+                    *known = None;
+                }
+            })
+            .or_insert(layout);
+    }
+
+    /// The layout of the record at `span`, if known.
+    pub fn get(&self, span: Span) -> Option<&RecordLayout> {
+        self.layouts.get(&span).and_then(Option::as_ref)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.layouts.is_empty()
+    }
+}
+
+// ══════════════════════════════════════════════════════════════════════════════
+// Slot access
+// ══════════════════════════════════════════════════════════════════════════════
+
+/// Byte offset of entry `slot` within a record's entries array.
+pub fn entry_offset(slot: u32) -> u64 {
+    slot as u64 * 12
+}
+
+/// Replace the record pointer on the stack with the value at `slot`.
+///
+/// Only for records whose layout the code generator fixed itself.
+pub fn emit_fixed_load(slot: u32, f: &mut Function) {
+    f.instruction(&Instruction::I32Load(memarg(4, 2))); // entries (w1)
+    f.instruction(&Instruction::I32Load(memarg(entry_offset(slot) + 8, 2)));
+}
+
+/// Push whether entry `slot` of the record in `record_local` exists and is
+/// keyed by the interned `field`.
+pub fn emit_slot_matches(
+    record_local: u32,
+    slot: u32,
+    field: &str,
+    ctx: &mut FuncContext,
+    f: &mut Function,
+) {
+    let (key_ptr, _) = ctx.intern_string(field);
+    f.instruction(&Instruction::LocalGet(record_local));
+    f.instruction(&Instruction::I32Load(memarg(8, 2))); // count (w2)
+    f.instruction(&Instruction::I32Const(slot as i32));
+    f.instruction(&Instruction::I32GtU);
+    f.instruction(&Instruction::If(BlockType::Result(ValType::I32)));
+    f.instruction(&Instruction::LocalGet(record_local));
+    f.instruction(&Instruction::I32Load(memarg(4, 2)));
+    f.instruction(&Instruction::I32Load(memarg(entry_offset(slot), 2)));
+    f.instruction(&Instruction::I32Const(key_ptr as i32));
+    f.instruction(&Instruction::I32Eq);
+    f.instruction(&Instruction::Else);
+    f.instruction(&Instruction::I32Const(0));
+    f.instruction(&Instruction::End);
+}
+
+/// Push `record.field` for the record in `record_local`, reading `slot`
+/// directly when the key there matches and looking the key up otherwise.
+pub fn emit_slot_load(
+    record_local: u32,
+    slot: u32,
+    field: &str,
+    ctx: &mut FuncContext,
+    f: &mut Function,
+) {
+    emit_slot_matches(record_local, slot, field, ctx, f);
+    f.instruction(&Instruction::If(BlockType::Result(ValType::I32)));
+    f.instruction(&Instruction::LocalGet(record_local));
+    emit_fixed_load(slot, f);
+    f.instruction(&Instruction::Else);
+    emit_key_lookup(record_local, field, ctx, f);
+    f.instruction(&Instruction::End);
+}
+
+/// Push `record.field` via the runtime key lookup.
+pub fn emit_key_lookup(record_local: u32, field: &str, ctx: &mut FuncContext, f: &mut Function) {
+    let (key_ptr, key_len) = ctx.intern_string(field);
+    f.instruction(&Instruction::LocalGet(record_local));
+    f.instruction(&Instruction::I32Const(key_ptr as i32));
+    f.instruction(&Instruction::I32Const(key_len as i32));
+    f.instruction(&Instruction::Call(rt_func_idx(RT_VAL_RECORD_GET)));
+}
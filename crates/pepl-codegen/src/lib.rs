@@ -10,32 +10,82 @@
 //! - `env.host_call(cap_id, fn_id, args_ptr) → result_ptr`
 //! - `env.log(ptr, len)`
 //! - `env.trap(ptr, len)`
+//! - `env.get_timestamp() → i64`
 //!
 //! ## Exports
 //! - `init(gas_limit)` — initialise state to defaults
 //! - `dispatch_action(action_id, args_ptr) → result_ptr`
 //! - `render(view_id) → surface_ptr`
 //! - `get_state() → state_ptr`
+//! - `serialize_state() → (ptr, len)` — the state as canonical JSON
+//! - `restore_state(ptr, len) → ok` — load that JSON back (see [`snapshot`])
 //! - `alloc(size) → ptr`
 //! - `memory` — linear memory
+//! - `__trap_site` — id of the last trap site entered (see [`source_map`])
+//! - `__gas` — gas used by the current entry point (see [`gas`])
 //! - (conditional) `update(dt_ptr)`, `handle_event(event_ptr)`
+//! - (with [`CodegenOptions::component`]) `cabi_realloc` and one
+//!   `pepl:<space>/space#<function>` per function of the space's WIT
+//!   interface — see [`canon`]
 //!
 //! ## Value Representation
 //!
 //! Every PEPL value is a heap-allocated 12-byte cell:
 //! `[tag: i32, payload: 8 bytes]`.  See [`types`] for tag constants.
+//! Given the checker's types ([`CodegenOptions::scalar_types`]), numbers and
+//! bools stay in native locals until they escape — see [`unbox`].
+//! Records are arrays of `[key, value]` entries; the state record and the
+//! checker's record types ([`CodegenOptions::record_layouts`]) have fixed
+//! field slots — see [`layout`].
+//!
+//! ## Standard Library
+//!
+//! The pure stdlib (`math`, `string`, `list`, `record`, `convert`, `json`,
+//! `core`) is compiled into the module after the runtime helpers, so only
+//! capabilities, `time`, `timer` and `core.capability` reach
+//! `env.host_call` — see [`stdlib`].
+//!
+//! ## Optimisation
+//!
+//! [`OptLevel::O1`] folds constants and dead branches before emission and
+//! drops unused runtime helpers afterwards — see [`optimize`].
+//!
+//! ## Debugging
+//!
+//! [`disassemble`] prints a module as WAT annotated with function names,
+//! PEPL source lines, decoded strings and `RT_*` helper names — see
+//! [`disasm`].
+//!
+//! ## Component
+//!
+//! [`wit`] describes the contract above as a typed WIT world, and
+//! [`component::wrap`] turns a module compiled with
+//! [`CodegenOptions::component`] into a component implementing it.
 
+pub mod canon;
 pub mod compiler;
+pub mod component;
+pub mod disasm;
 pub mod error;
 pub mod expr;
 pub mod gas;
+pub mod layout;
+pub mod optimize;
 pub mod runtime;
+pub mod snapshot;
 pub mod source_map;
 pub mod space;
+pub mod stdlib;
 pub mod stmt;
 pub mod test_codegen;
 pub mod types;
+pub mod unbox;
+pub mod wit;
 
-pub use compiler::{compile, compile_with_source_map};
+pub use compiler::{compile, compile_with_options, compile_with_source_map, CodegenOptions};
+pub use disasm::disassemble;
 pub use error::{CodegenError, CodegenResult};
-pub use source_map::SourceMap;
+pub use layout::{RecordLayout, RecordLayouts};
+pub use optimize::OptLevel;
+pub use source_map::{SourceMap, TrapSite};
+pub use unbox::{Scalar, ScalarTypes};
@@ -0,0 +1,673 @@
+//! Optimisation stages around code generation.
+//!
+//! Two stages run at [`OptLevel::O1`]:
+//!
+//! 1. **AST folding** ([`fold_program`]) on the verified AST, before any
+//!    function is emitted: literal arithmetic, comparisons and logic are
+//!    folded to literals, literal string concatenation is joined, and `if`s
+//!    with a literal condition keep only the branch that is taken.
+//! 2. **Runtime tree-shaking** ([`shake_runtime`]) on the assembled module:
+//!    runtime helpers and stdlib functions that no space function reaches
+//!    through `call` are removed and every function index is renumbered.
+//!
+//! At [`OptLevel::O0`] the tree-shaking still drops the stdlib functions no
+//! space function reaches, so a module only grows with the stdlib it uses.
+//!
+//! Folds only happen where the result is the value the unoptimised module
+//! would compute; anything that would trap at runtime (division by zero, a
+//! NaN quotient) is left in place so it still traps.  Folding does not change
+//! the gas a program spends either: what the removed nodes would have cost
+//! is recorded in a [`GasCarry`] and charged by the code that replaces them.
+
+use std::collections::{HashMap, HashSet};
+use std::convert::Infallible;
+
+use pepl_types::ast::*;
+use pepl_types::gas;
+use pepl_types::Span;
+use wasm_encoder::reencode::{self, Reencode};
+use wasmparser::{Operator, Payload};
+
+use crate::error::{CodegenError, CodegenResult};
+use crate::runtime::RT_FUNC_COUNT;
+use crate::source_map::SourceMap;
+use crate::stdlib::STDLIB_FUNC_COUNT;
+use crate::stmt::stmt_span;
+use crate::types::IMPORT_COUNT;
+
+/// How much optimisation to apply during code generation.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OptLevel {
+    /// Lower the AST as written and emit every runtime helper, but only
+    /// the stdlib functions the space reaches.
+    #[default]
+    O0,
+    /// Fold constants, drop dead branches, intern strings once per module
+    /// and tree-shake the runtime.
+    O1,
+}
+
+// ══════════════════════════════════════════════════════════════════════════════
+// AST folding
+// ══════════════════════════════════════════════════════════════════════════════
+
+/// Return a copy of `program` with constants folded and dead branches removed.
+pub fn fold_program(program: &Program) -> Program {
+    fold_program_with_gas(program).0
+}
+
+/// Gas of the nodes folding removed, keyed by the span of the expression or
+/// statement that now pays for them.
+#[derive(Debug, Default)]
+pub(crate) struct GasCarry {
+    exprs: HashMap<Span, u64>,
+    stmts: HashMap<Span, u64>,
+}
+
+impl GasCarry {
+    /// Extra gas charged with the expression at `span`.
+    pub(crate) fn expr(&self, span: Span) -> u64 {
+        self.exprs.get(&span).copied().unwrap_or(0)
+    }
+
+    /// Extra gas charged with the statement at `span`.
+    pub(crate) fn stmt(&self, span: Span) -> u64 {
+        self.stmts.get(&span).copied().unwrap_or(0)
+    }
+
+    fn add_expr(&mut self, span: Span, cost: u64) {
+        if cost > 0 {
+            *self.exprs.entry(span).or_default() += cost;
+        }
+    }
+
+    fn add_stmt(&mut self, span: Span, cost: u64) {
+        if cost > 0 {
+            *self.stmts.entry(span).or_default() += cost;
+        }
+    }
+
+    /// Gas of evaluating `expr` itself, children excluded.
+    fn cost(&self, expr: &Expr) -> u64 {
+        gas::expr_cost(&expr.kind) + self.expr(expr.span)
+    }
+}
+
+/// [`fold_program`], also returning the gas the folded nodes would have cost.
+pub(crate) fn fold_program_with_gas(program: &Program) -> (Program, GasCarry) {
+    let mut program = program.clone();
+    let mut carry = GasCarry::default();
+    let body = &mut program.space.body;
+    for field in &mut body.state.fields {
+        fold_expr(&mut field.default, &mut carry);
+    }
+    if let Some(derived) = &mut body.derived {
+        for field in &mut derived.fields {
+            fold_expr(&mut field.value, &mut carry);
+        }
+    }
+    for invariant in &mut body.invariants {
+        fold_expr(&mut invariant.condition, &mut carry);
+    }
+    for action in &mut body.actions {
+        fold_block(&mut action.body, &mut carry);
+    }
+    for view in &mut body.views {
+        fold_ui_block(&mut view.body, &mut carry);
+    }
+    if let Some(update) = &mut body.update {
+        fold_block(&mut update.body, &mut carry);
+    }
+    if let Some(handle_event) = &mut body.handle_event {
+        fold_block(&mut handle_event.body, &mut carry);
+    }
+    for tests in &mut program.tests {
+        for case in &mut tests.cases {
+            fold_block(&mut case.body, &mut carry);
+        }
+    }
+    (program, carry)
+}
+
+fn fold_block(block: &mut Block, carry: &mut GasCarry) {
+    let stmts = std::mem::take(&mut block.stmts);
+    let count = stmts.len();
+    // Gas of spliced-away `if`s, charged with the next statement kept.  The
+    // last statement is never spliced, so there always is one.
+    let mut pending = 0;
+    for (i, stmt) in stmts.into_iter().enumerate() {
+        // A trailing statement decides the value of an expression block, so
+        // it is never replaced by the statements of a branch.
+        fold_stmt(stmt, i + 1 < count, &mut block.stmts, &mut pending, carry);
+    }
+    debug_assert_eq!(pending, 0, "spliced gas left without a statement");
+}
+
+/// Fold `stmt` and append what remains of it to `out`.  Literal `if`s are
+/// spliced only when `splice` is set; their own gas is added to `pending`.
+fn fold_stmt(
+    mut stmt: Stmt,
+    splice: bool,
+    out: &mut Vec<Stmt>,
+    pending: &mut u64,
+    carry: &mut GasCarry,
+) {
+    match &mut stmt {
+        Stmt::Set(set) => fold_expr(&mut set.value, carry),
+        Stmt::Let(binding) => fold_expr(&mut binding.value, carry),
+        Stmt::If(if_expr) => {
+            fold_expr(&mut if_expr.condition, carry);
+            if let (true, ExprKind::BoolLit(taken)) = (splice, &if_expr.condition.kind) {
+                let taken = *taken;
+                *pending += gas::stmt_cost(&stmt);
+                let Stmt::If(if_expr) = stmt else {
+                    unreachable!()
+                };
+                *pending += carry.stmt(if_expr.span) + carry.cost(&if_expr.condition);
+                // if-blocks share the enclosing scope, so the taken branch
+                // can be spliced in place.  A spliced `else if` is charged
+                // as an `if` statement, which costs the same.
+                match (taken, if_expr.else_branch) {
+                    (true, _) => splice_block(if_expr.then_block, out, pending, carry),
+                    (false, Some(ElseBranch::Block(block))) => {
+                        splice_block(block, out, pending, carry)
+                    }
+                    (false, Some(ElseBranch::ElseIf(elif))) => {
+                        fold_stmt(Stmt::If(*elif), true, out, pending, carry)
+                    }
+                    (false, None) => {}
+                }
+                return;
+            }
+            fold_if_branches(if_expr, carry);
+        }
+        Stmt::For(for_expr) => {
+            fold_expr(&mut for_expr.iterable, carry);
+            fold_block(&mut for_expr.body, carry);
+        }
+        Stmt::Match(match_expr) => fold_match(match_expr, carry),
+        Stmt::Assert(assert_stmt) => fold_expr(&mut assert_stmt.condition, carry),
+        Stmt::Expr(expr_stmt) => fold_expr(&mut expr_stmt.expr, carry),
+        Stmt::Return(_) => {}
+    }
+    carry.add_stmt(stmt_span(&stmt), std::mem::take(pending));
+    out.push(stmt);
+}
+
+fn splice_block(block: Block, out: &mut Vec<Stmt>, pending: &mut u64, carry: &mut GasCarry) {
+    for stmt in block.stmts {
+        fold_stmt(stmt, true, out, pending, carry);
+    }
+}
+
+fn fold_if_branches(if_expr: &mut IfExpr, carry: &mut GasCarry) {
+    fold_block(&mut if_expr.then_block, carry);
+    match &mut if_expr.else_branch {
+        Some(ElseBranch::Block(block)) => fold_block(block, carry),
+        Some(ElseBranch::ElseIf(elif)) => {
+            fold_expr(&mut elif.condition, carry);
+            fold_if_branches(elif, carry);
+        }
+        None => {}
+    }
+}
+
+fn fold_match(match_expr: &mut MatchExpr, carry: &mut GasCarry) {
+    fold_expr(&mut match_expr.subject, carry);
+    for arm in &mut match_expr.arms {
+        match &mut arm.body {
+            MatchArmBody::Expr(expr) => fold_expr(expr, carry),
+            MatchArmBody::Block(block) => fold_block(block, carry),
+        }
+    }
+}
+
+fn fold_expr(expr: &mut Expr, carry: &mut GasCarry) {
+    // Fold children first
+    match &mut expr.kind {
+        ExprKind::StringInterpolation(parts) => {
+            for part in parts.iter_mut() {
+                if let StringPart::Expr(e) = part {
+                    fold_expr(e, carry);
+                }
+            }
+        }
+        ExprKind::ListLit(items) => items.iter_mut().for_each(|e| fold_expr(e, carry)),
+        ExprKind::RecordLit(entries) => {
+            for entry in entries {
+                match entry {
+                    RecordEntry::Field { value, .. } => fold_expr(value, carry),
+                    RecordEntry::Spread(e) => fold_expr(e, carry),
+                }
+            }
+        }
+        ExprKind::Call { args, .. } | ExprKind::QualifiedCall { args, .. } => {
+            args.iter_mut().for_each(|e| fold_expr(e, carry))
+        }
+        ExprKind::FieldAccess { object, .. } => fold_expr(object, carry),
+        ExprKind::MethodCall { object, args, .. } => {
+            fold_expr(object, carry);
+            args.iter_mut().for_each(|e| fold_expr(e, carry));
+        }
+        ExprKind::Binary { left, right, .. } | ExprKind::NilCoalesce { left, right } => {
+            fold_expr(left, carry);
+            fold_expr(right, carry);
+        }
+        ExprKind::Unary { operand, .. } => fold_expr(operand, carry),
+        ExprKind::ResultUnwrap(inner) | ExprKind::Paren(inner) => fold_expr(inner, carry),
+        ExprKind::If(if_expr) => {
+            fold_expr(&mut if_expr.condition, carry);
+            fold_if_branches(if_expr, carry);
+        }
+        ExprKind::For(for_expr) => {
+            fold_expr(&mut for_expr.iterable, carry);
+            fold_block(&mut for_expr.body, carry);
+        }
+        ExprKind::Match(match_expr) => fold_match(match_expr, carry),
+        ExprKind::Lambda(lambda) => fold_block(&mut lambda.body, carry),
+        ExprKind::NumberLit(_)
+        | ExprKind::StringLit(_)
+        | ExprKind::BoolLit(_)
+        | ExprKind::NilLit
+        | ExprKind::Identifier(_) => {}
+    }
+
+    if let Some(kind) = fold_kind(&expr.kind) {
+        // The literal pays for the node and its (literal) operands
+        let folded = carry.cost(expr) + literal_operands_cost(&expr.kind, carry);
+        expr.kind = kind;
+        carry.add_expr(expr.span, folded - carry.cost(expr));
+    } else if let Some((replacement, cost)) = select_operand(expr, carry) {
+        *expr = replacement;
+        carry.add_expr(expr.span, cost);
+    }
+}
+
+/// Gas of the operands [`fold_kind`] folds away.
+fn literal_operands_cost(kind: &ExprKind, carry: &GasCarry) -> u64 {
+    match kind {
+        ExprKind::Paren(inner) | ExprKind::Unary { operand: inner, .. } => carry.cost(inner),
+        ExprKind::Binary { left, right, .. } => carry.cost(left) + carry.cost(right),
+        ExprKind::StringInterpolation(parts) => parts
+            .iter()
+            .map(|part| match part {
+                StringPart::Expr(e) => carry.cost(e),
+                StringPart::Literal(_) => 0,
+            })
+            .sum(),
+        _ => 0,
+    }
+}
+
+/// Fold an expression whose (already folded) operands are literals.
+fn fold_kind(kind: &ExprKind) -> Option<ExprKind> {
+    use ExprKind::{BoolLit, NilLit, NumberLit, StringLit};
+    Some(match kind {
+        ExprKind::Paren(inner) if is_literal(&inner.kind) => inner.kind.clone(),
+        ExprKind::Unary { op, operand } => match (op, &operand.kind) {
+            (UnaryOp::Neg, NumberLit(n)) => NumberLit(-n),
+            (UnaryOp::Not, BoolLit(b)) => BoolLit(!b),
+            _ => return None,
+        },
+        ExprKind::Binary { left, op, right } => match (&left.kind, &right.kind) {
+            (NumberLit(a), NumberLit(b)) => fold_numbers(*a, *op, *b)?,
+            (StringLit(a), StringLit(b)) => match op {
+                BinOp::Add => StringLit(format!("{a}{b}")),
+                BinOp::Eq => BoolLit(a == b),
+                BinOp::NotEq => BoolLit(a != b),
+                _ => return None,
+            },
+            (BoolLit(a), BoolLit(b)) => match op {
+                BinOp::Eq => BoolLit(a == b),
+                BinOp::NotEq => BoolLit(a != b),
+                _ => return None,
+            },
+            (NilLit, NilLit) => match op {
+                BinOp::Eq => BoolLit(true),
+                BinOp::NotEq => BoolLit(false),
+                _ => return None,
+            },
+            _ => return None,
+        },
+        ExprKind::StringInterpolation(parts) => {
+            let mut joined = String::new();
+            for part in parts {
+                match part {
+                    StringPart::Literal(s) => joined.push_str(s),
+                    StringPart::Expr(e) => match &e.kind {
+                        StringLit(s) => joined.push_str(s),
+                        BoolLit(b) => joined.push_str(if *b { "true" } else { "false" }),
+                        NilLit => joined.push_str("nil"),
+                        _ => return None,
+                    },
+                }
+            }
+            StringLit(joined)
+        }
+        _ => return None,
+    })
+}
+
+/// Fold a binary operator on two number literals, mirroring the runtime
+/// helpers.  Returns `None` where the runtime would trap.
+fn fold_numbers(a: f64, op: BinOp, b: f64) -> Option<ExprKind> {
+    let number = |n: f64| (!n.is_nan()).then_some(ExprKind::NumberLit(n));
+    match op {
+        BinOp::Add => number(a + b),
+        BinOp::Sub => number(a - b),
+        BinOp::Mul => number(a * b),
+        BinOp::Div if b != 0.0 => number(a / b),
+        BinOp::Mod if b != 0.0 => number(a - (a / b).trunc() * b),
+        // `val_eq` compares the payload bits
+        BinOp::Eq => Some(ExprKind::BoolLit(a.to_bits() == b.to_bits())),
+        BinOp::NotEq => Some(ExprKind::BoolLit(a.to_bits() != b.to_bits())),
+        BinOp::Less => Some(ExprKind::BoolLit(a < b)),
+        BinOp::LessEq => Some(ExprKind::BoolLit(a <= b)),
+        BinOp::Greater => Some(ExprKind::BoolLit(a > b)),
+        BinOp::GreaterEq => Some(ExprKind::BoolLit(a >= b)),
+        _ => None,
+    }
+}
+
+/// Short-circuit and conditional forms whose literal operand decides which
+/// side is the result.  Also returns the gas of what the replacement drops,
+/// which it is charged on top of its own.
+fn select_operand(expr: &Expr, carry: &GasCarry) -> Option<(Expr, u64)> {
+    let own = carry.cost(expr);
+    match &expr.kind {
+        ExprKind::Binary { left, op, right } => match (op, &left.kind) {
+            (BinOp::And, ExprKind::BoolLit(true)) | (BinOp::Or, ExprKind::BoolLit(false)) => {
+                Some(((**right).clone(), own + carry.cost(left)))
+            }
+            (BinOp::And, ExprKind::BoolLit(false)) | (BinOp::Or, ExprKind::BoolLit(true)) => {
+                Some(((**left).clone(), own))
+            }
+            _ => None,
+        },
+        ExprKind::NilCoalesce { left, right } => match &left.kind {
+            ExprKind::NilLit => Some(((**right).clone(), own + carry.cost(left))),
+            kind if is_literal(kind) => Some(((**left).clone(), own)),
+            _ => None,
+        },
+        ExprKind::If(if_expr) => {
+            let ExprKind::BoolLit(taken) = if_expr.condition.kind else {
+                return None;
+            };
+            let condition = carry.cost(&if_expr.condition);
+            // A replacement at the `if`'s span keeps its carry, and costs
+            // what the `if` node itself did
+            let nil = || Expr::new(ExprKind::NilLit, expr.span);
+            let block = match (taken, &if_expr.else_branch) {
+                (true, _) => &if_expr.then_block,
+                (false, Some(ElseBranch::Block(block))) => block,
+                (false, Some(ElseBranch::ElseIf(elif))) => {
+                    let replacement = Expr::new(ExprKind::If(elif.clone()), expr.span);
+                    return Some((replacement, condition + gas::ELSE_IF));
+                }
+                (false, None) => return Some((nil(), condition)),
+            };
+            // Only a single-expression block can stand in for the `if`.
+            match block.stmts.as_slice() {
+                [stmt @ Stmt::Expr(only)] => {
+                    let statement = gas::stmt_cost(stmt) + carry.stmt(only.span);
+                    Some((only.expr.clone(), own + condition + statement))
+                }
+                [] => Some((nil(), condition)),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+fn is_literal(kind: &ExprKind) -> bool {
+    matches!(
+        kind,
+        ExprKind::NumberLit(_) | ExprKind::StringLit(_) | ExprKind::BoolLit(_) | ExprKind::NilLit
+    )
+}
+
+fn fold_ui_block(block: &mut UIBlock, carry: &mut GasCarry) {
+    for element in &mut block.elements {
+        fold_ui_element(element, carry);
+    }
+}
+
+fn fold_ui_element(element: &mut UIElement, carry: &mut GasCarry) {
+    match element {
+        UIElement::Component(comp) => {
+            for prop in &mut comp.props {
+                fold_expr(&mut prop.value, carry);
+            }
+            if let Some(children) = &mut comp.children {
+                fold_ui_block(children, carry);
+            }
+        }
+        UIElement::Let(binding) => fold_expr(&mut binding.value, carry),
+        UIElement::If(ui_if) => {
+            fold_expr(&mut ui_if.condition, carry);
+            fold_ui_if_branches(ui_if, carry);
+            while let Some(taken) = take_ui_branch(ui_if, carry) {
+                *ui_if = taken;
+            }
+        }
+        UIElement::For(ui_for) => {
+            fold_expr(&mut ui_for.iterable, carry);
+            fold_ui_block(&mut ui_for.body, carry);
+        }
+    }
+}
+
+/// Resolve a view conditional with a literal condition to the branch it
+/// takes.  A conditional renders as one nested list, so the result is still a
+/// `UIIf`: either the taken `else if`, or `if true { … }` with no else (which
+/// `space::emit_ui_if` lowers to the bare block).
+///
+/// The `if true` keeps the literal condition's span, and so its gas; a taken
+/// `else if` is charged for the condition it skipped.
+fn take_ui_branch(ui_if: &UIIf, carry: &mut GasCarry) -> Option<UIIf> {
+    let ExprKind::BoolLit(taken) = ui_if.condition.kind else {
+        return None;
+    };
+    let block = match (taken, &ui_if.else_block) {
+        (true, _) => ui_if.then_block.clone(),
+        (false, Some(UIElse::Block(block))) => block.clone(),
+        (false, Some(UIElse::ElseIf(elif))) => {
+            let skipped = carry.cost(&ui_if.condition) + gas::ELSE_IF;
+            carry.add_expr(elif.condition.span, skipped);
+            return Some((**elif).clone());
+        }
+        (false, None) => UIBlock {
+            elements: Vec::new(),
+            span: ui_if.span,
+        },
+    };
+    if taken && ui_if.else_block.is_none() {
+        return None; // already in its simplest form
+    }
+    Some(UIIf {
+        condition: Expr::new(ExprKind::BoolLit(true), ui_if.condition.span),
+        then_block: block,
+        else_block: None,
+        span: ui_if.span,
+    })
+}
+
+fn fold_ui_if_branches(ui_if: &mut UIIf, carry: &mut GasCarry) {
+    fold_ui_block(&mut ui_if.then_block, carry);
+    match &mut ui_if.else_block {
+        Some(UIElse::Block(block)) => fold_ui_block(block, carry),
+        Some(UIElse::ElseIf(elif)) => {
+            fold_expr(&mut elif.condition, carry);
+            fold_ui_if_branches(elif, carry);
+        }
+        None => {}
+    }
+}
+
+// ══════════════════════════════════════════════════════════════════════════════
+// Runtime tree-shaking
+// ══════════════════════════════════════════════════════════════════════════════
+
+/// Remove runtime helpers unreachable from the space functions and renumber
+/// all function indices (calls, exports, table elements and `source_map`).
+///
+/// The compiled stdlib is a candidate for removal, and so is the runtime
+/// when `runtime` is set.  Every other function and every export is a
+/// root; helpers are kept if a root reaches them through `call` /
+/// `ref.func`, directly or through other helpers.
+pub fn shake_runtime(
+    wasm: &[u8],
+    source_map: &mut SourceMap,
+    runtime: bool,
+) -> CodegenResult<Vec<u8>> {
+    let parse_err = |e: wasmparser::BinaryReaderError| CodegenError::Internal(e.to_string());
+
+    // Call graph over defined functions
+    let mut callees: Vec<Vec<u32>> = Vec::new();
+    let mut exported: Vec<u32> = Vec::new();
+    for payload in wasmparser::Parser::new(0).parse_all(wasm) {
+        match payload.map_err(parse_err)? {
+            Payload::ExportSection(reader) => {
+                for export in reader {
+                    let export = export.map_err(parse_err)?;
+                    if export.kind == wasmparser::ExternalKind::Func {
+                        exported.push(export.index);
+                    }
+                }
+            }
+            Payload::CodeSectionEntry(body) => {
+                let mut calls = Vec::new();
+                let mut ops = body.get_operators_reader().map_err(parse_err)?;
+                while !ops.eof() {
+                    match ops.read().map_err(parse_err)? {
+                        Operator::Call { function_index }
+                        | Operator::RefFunc { function_index } => calls.push(function_index),
+                        _ => {}
+                    }
+                }
+                callees.push(calls);
+            }
+            _ => {}
+        }
+    }
+
+    let total = IMPORT_COUNT + callees.len() as u32;
+    let first = if runtime { 0 } else { RT_FUNC_COUNT };
+    let candidates = IMPORT_COUNT + first..IMPORT_COUNT + RT_FUNC_COUNT + STDLIB_FUNC_COUNT;
+    let mut live: HashSet<u32> = (0..total).filter(|i| !candidates.contains(i)).collect();
+    live.extend(exported);
+    let mut work: Vec<u32> = live.iter().copied().collect();
+    while let Some(func) = work.pop() {
+        let Some(calls) = func
+            .checked_sub(IMPORT_COUNT)
+            .and_then(|i| callees.get(i as usize))
+        else {
+            continue; // import
+        };
+        for &callee in calls {
+            if live.insert(callee) {
+                work.push(callee);
+            }
+        }
+    }
+
+    let mut remap = Vec::with_capacity(total as usize);
+    let mut next = 0;
+    for func in 0..total {
+        if live.contains(&func) {
+            remap.push(Some(next));
+            next += 1;
+        } else {
+            remap.push(None);
+        }
+    }
+
+    for entry in &mut source_map.entries {
+        if let Some(Some(idx)) = remap.get(entry.wasm_func_index as usize) {
+            entry.wasm_func_index = *idx;
+        }
+    }
+
+    source_map.helpers = (0..RT_FUNC_COUNT + STDLIB_FUNC_COUNT)
+        .filter(|i| live.contains(&(IMPORT_COUNT + i)))
+        .collect();
+
+    let mut shaker = RuntimeShaker {
+        remap,
+        source_map_json: source_map.to_json(),
+    };
+    let mut module = wasm_encoder::Module::new();
+    shaker
+        .parse_core_module(&mut module, wasmparser::Parser::new(0), wasm)
+        .map_err(|e| CodegenError::Internal(format!("runtime tree-shaking failed: {e}")))?;
+    Ok(module.finish())
+}
+
+/// Re-encodes a module without the dead runtime helpers.
+struct RuntimeShaker {
+    /// Old function index → new index (`None` = removed).
+    remap: Vec<Option<u32>>,
+    /// Replacement payload for the `pepl_source_map` custom section.
+    source_map_json: Vec<u8>,
+}
+
+impl RuntimeShaker {
+    fn keeps(&self, defined: usize) -> bool {
+        self.remap[IMPORT_COUNT as usize + defined].is_some()
+    }
+}
+
+impl Reencode for RuntimeShaker {
+    type Error = Infallible;
+
+    fn function_index(&mut self, func: u32) -> u32 {
+        self.remap[func as usize].expect("reachable functions only reference live functions")
+    }
+
+    fn parse_function_section(
+        &mut self,
+        functions: &mut wasm_encoder::FunctionSection,
+        section: wasmparser::FunctionSectionReader<'_>,
+    ) -> Result<(), reencode::Error> {
+        for (i, ty) in section.into_iter().enumerate() {
+            let ty = ty?;
+            if self.keeps(i) {
+                functions.function(self.type_index(ty));
+            }
+        }
+        Ok(())
+    }
+
+    fn parse_code_section(
+        &mut self,
+        code: &mut wasm_encoder::CodeSection,
+        section: wasmparser::CodeSectionReader<'_>,
+    ) -> Result<(), reencode::Error> {
+        for (i, body) in section.into_iter().enumerate() {
+            let body = body?;
+            if self.keeps(i) {
+                self.parse_function_body(code, body)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn parse_custom_section(
+        &mut self,
+        module: &mut wasm_encoder::Module,
+        section: wasmparser::CustomSectionReader<'_>,
+    ) -> Result<(), reencode::Error> {
+        if section.name() == SOURCE_MAP_SECTION {
+            module.section(&wasm_encoder::CustomSection {
+                name: SOURCE_MAP_SECTION.into(),
+                data: self.source_map_json.as_slice().into(),
+            });
+            return Ok(());
+        }
+        reencode::utils::parse_custom_section(self, module, section)
+    }
+}
+
+/// Name of the custom section holding the serialized [`SourceMap`].
+pub(crate) const SOURCE_MAP_SECTION: &str = "pepl_source_map";
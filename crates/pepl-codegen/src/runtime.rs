@@ -120,6 +120,42 @@ pub const RT_MEMCMP: u32 = 30;
 /// Total number of runtime helper functions.
 pub const RT_FUNC_COUNT: u32 = 31;
 
+/// Constant names of the runtime helpers, indexed by offset — used to label
+/// them in disassembly.
+pub const RT_NAMES: [&str; RT_FUNC_COUNT as usize] = [
+    "RT_ALLOC",
+    "RT_VAL_NIL",
+    "RT_VAL_NUMBER",
+    "RT_VAL_BOOL",
+    "RT_VAL_STRING",
+    "RT_VAL_LIST",
+    "RT_VAL_RECORD",
+    "RT_VAL_VARIANT",
+    "RT_VAL_ACTION_REF",
+    "RT_VAL_TAG",
+    "RT_VAL_GET_NUMBER",
+    "RT_VAL_GET_W1",
+    "RT_VAL_GET_W2",
+    "RT_VAL_EQ",
+    "RT_VAL_TO_STRING",
+    "RT_VAL_STRING_CONCAT",
+    "RT_VAL_ADD",
+    "RT_VAL_SUB",
+    "RT_VAL_MUL",
+    "RT_VAL_DIV",
+    "RT_VAL_MOD",
+    "RT_VAL_NEG",
+    "RT_VAL_NOT",
+    "RT_VAL_LT",
+    "RT_VAL_LE",
+    "RT_VAL_GT",
+    "RT_VAL_GE",
+    "RT_VAL_RECORD_GET",
+    "RT_VAL_LIST_GET",
+    "RT_CHECK_NAN",
+    "RT_MEMCMP",
+];
+
 // ── Absolute function indices ────────────────────────────────────────────────
 
 /// Compute the absolute WASM function index of a runtime helper.
@@ -556,8 +592,9 @@ pub fn emit_val_eq() -> Function {
 
 /// Emit `val_to_string(ptr: i32) -> i32` — converts any value to a STRING value.
 ///
-/// v1: strings pass through, bools → "true"/"false", nil → "nil",
-/// numbers (integer-valued) → decimal string, others → "[value]".
+/// Strings pass through, bools → "true"/"false", nil → "nil", safe
+/// integers → decimal digits; everything else is formatted by the stdlib's
+/// `$display`, matching the evaluator.
 pub fn emit_val_to_string(data: &DataSegmentTracker) -> Function {
     // Locals: 0=ptr, 1=tag, 2=f64_val, 3=is_neg, 4=abs_val(i64), 5=buf_ptr,
     //         6=write_pos, 7=digit_count, 8=start, 9=result
@@ -572,6 +609,7 @@ pub fn emit_val_to_string(data: &DataSegmentTracker) -> Function {
         (1, ValType::I32),  // local 8: start_pos
         (1, ValType::I32),  // local 9: result_ptr
     ]);
+    let display_idx = crate::stdlib::func_idx("$display").expect("stdlib $display");
     f.instruction(&Instruction::LocalGet(0));
     f.instruction(&Instruction::I32Load(memarg(0, 2)));
     f.instruction(&Instruction::LocalSet(1));
@@ -711,10 +749,9 @@ pub fn emit_val_to_string(data: &DataSegmentTracker) -> Function {
     f.instruction(&Instruction::Call(rt_func_idx(RT_VAL_STRING)));
 
     f.instruction(&Instruction::Else);
-    // Non-integer number → "[value]" placeholder
-    f.instruction(&Instruction::I32Const(data.value_ptr as i32));
-    f.instruction(&Instruction::I32Const(data.value_len as i32));
-    f.instruction(&Instruction::Call(rt_func_idx(RT_VAL_STRING)));
+    // Non-integer number → the stdlib's shortest round-trip formatting
+    f.instruction(&Instruction::LocalGet(0));
+    f.instruction(&Instruction::Call(display_idx));
     f.instruction(&Instruction::End); // end integer check
 
     f.instruction(&Instruction::Else);
@@ -749,10 +786,9 @@ pub fn emit_val_to_string(data: &DataSegmentTracker) -> Function {
     f.instruction(&Instruction::Call(rt_func_idx(RT_VAL_STRING)));
     f.instruction(&Instruction::Else);
 
-    // Default: return "[value]" placeholder
-    f.instruction(&Instruction::I32Const(data.value_ptr as i32));
-    f.instruction(&Instruction::I32Const(data.value_len as i32));
-    f.instruction(&Instruction::Call(rt_func_idx(RT_VAL_STRING)));
+    // Lists, records, results, … → the evaluator's display form
+    f.instruction(&Instruction::LocalGet(0));
+    f.instruction(&Instruction::Call(display_idx));
 
     f.instruction(&Instruction::End); // nil else
     f.instruction(&Instruction::End); // bool else
@@ -923,9 +959,10 @@ pub fn emit_val_div(trap_msg_ptr: u32, trap_msg_len: u32) -> Function {
     f
 }
 
-/// Emit `val_mod(a, b) -> i32` — f64 remainder.
+/// Emit `val_mod(a, b) -> i32` — f64 remainder, with the sign of `a` (as
+/// the evaluator's Rust `%`).
 pub fn emit_val_mod() -> Function {
-    // WASM doesn't have f64.rem, so we implement: a - floor(a/b) * b
+    // WASM doesn't have f64.rem, so we implement: a - trunc(a/b) * b
     let mut f = Function::new(vec![
         (1, ValType::I32), // local 2: result ptr
         (1, ValType::F64), // local 3: a_val
@@ -939,7 +976,7 @@ pub fn emit_val_mod() -> Function {
     f.instruction(&Instruction::F64Load(memarg(4, 3)));
     f.instruction(&Instruction::LocalSet(4));
 
-    // result = a - floor(a/b) * b
+    // result = a - trunc(a/b) * b
     f.instruction(&Instruction::I32Const(VALUE_SIZE as i32));
     f.instruction(&Instruction::Call(rt_func_idx(RT_ALLOC)));
     f.instruction(&Instruction::LocalSet(2));
@@ -952,7 +989,7 @@ pub fn emit_val_mod() -> Function {
     f.instruction(&Instruction::LocalGet(3)); // a
     f.instruction(&Instruction::LocalGet(4)); // b
     f.instruction(&Instruction::F64Div);
-    f.instruction(&Instruction::F64Floor);
+    f.instruction(&Instruction::F64Trunc);
     f.instruction(&Instruction::LocalGet(4)); // b
     f.instruction(&Instruction::F64Mul);
     f.instruction(&Instruction::F64Sub);
@@ -1301,6 +1338,8 @@ pub struct DataSegmentTracker {
     pub unwrap_failed_len: u32,
     pub oom_ptr: u32,
     pub oom_len: u32,
+    /// Start of the constant strings used by the compiled stdlib.
+    pub stdlib_ptr: u32,
     /// Next free offset in the data segment.
     pub next_offset: u32,
 }
@@ -1360,6 +1399,9 @@ impl DataSegmentTracker {
         let oom_len = 13u32; // "out of memory"
         offset += oom_len;
 
+        let stdlib_ptr = offset;
+        offset += crate::stdlib::string_data().len() as u32;
+
         Self {
             true_ptr,
             true_len,
@@ -1383,6 +1425,7 @@ impl DataSegmentTracker {
             unwrap_failed_len,
             oom_ptr,
             oom_len,
+            stdlib_ptr,
             next_offset: offset,
         }
     }
@@ -1401,6 +1444,7 @@ impl DataSegmentTracker {
         buf.extend_from_slice(b"invariant violated");
         buf.extend_from_slice(b"unwrap on Err!");
         buf.extend_from_slice(b"out of memory");
+        buf.extend_from_slice(&crate::stdlib::string_data());
         buf
     }
 
@@ -0,0 +1,414 @@
+//! State persistence: the `serialize_state` and `restore_state` exports.
+//!
+//! `serialize_state() -> (ptr, len)` writes the state fields (not derived
+//! ones) as a JSON object with sorted keys, byte-for-byte what the evaluator
+//! produces from `SpaceInstance::state_snapshot` through
+//! `value_to_json_public`.  `restore_state(ptr, len) -> i32` reads that JSON
+//! back: the text is parsed, checked against the declared state types,
+//! derived fields are recomputed and invariants checked.  It returns 1 when
+//! the new state was installed and 0 (leaving the old state in place) when
+//! the data is malformed, does not match the schema or breaks an invariant.
+//!
+//! The state types are compiled into a *schema*, a tree of little-endian
+//! words in the data segment that the stdlib's `$conform` interprets:
+//!
+//! | kind              | words                                          |
+//! |-------------------|------------------------------------------------|
+//! | `SCHEMA_ANY` … `SCHEMA_NIL`, `SCHEMA_REJECT` | `[kind]`            |
+//! | `SCHEMA_LIST`     | `[kind, item]`                                 |
+//! | `SCHEMA_RECORD`   | `[kind, n, (key_ptr, key_len, optional, field) × n]` |
+//! | `SCHEMA_RESULT`   | `[kind, ok, err]`                              |
+//! | `SCHEMA_VARIANTS` | `[kind, n, (id, name_ptr, name_len, fields) × n]`, `fields` = `[count, field × count]` |
+//!
+//! Nested schemas are referenced by address, so recursive sum types share
+//! one node.
+
+use std::collections::HashMap;
+
+use pepl_types::ast::*;
+use pepl_types::{FrameKind, RuntimeFrame};
+use wasm_encoder::{BlockType, Function, Instruction, ValType};
+
+use crate::compiler::FuncContext;
+use crate::error::CodegenResult;
+use crate::gas;
+use crate::layout::emit_fixed_load;
+use crate::runtime::*;
+use crate::space::emit_recompute_derived;
+use crate::stdlib;
+use crate::types::*;
+use crate::unbox::{emit_scalar, Scalar};
+
+/// Accepts any value unchanged.
+pub const SCHEMA_ANY: i32 = 0;
+pub const SCHEMA_NUMBER: i32 = 1;
+pub const SCHEMA_STRING: i32 = 2;
+pub const SCHEMA_BOOL: i32 = 3;
+pub const SCHEMA_NIL: i32 = 4;
+pub const SCHEMA_LIST: i32 = 5;
+pub const SCHEMA_RECORD: i32 = 6;
+pub const SCHEMA_RESULT: i32 = 7;
+pub const SCHEMA_VARIANTS: i32 = 8;
+/// Types that have no JSON form: functions, colors, surfaces, events.
+pub const SCHEMA_REJECT: i32 = 9;
+/// Bytes per record field / variant entry of a schema.
+pub const SCHEMA_ENTRY_SIZE: i32 = 16;
+
+// ══════════════════════════════════════════════════════════════════════════════
+// serialize_state
+// ══════════════════════════════════════════════════════════════════════════════
+
+/// Emit `serialize_state() -> (i32, i32)`: the `(ptr, len)` of the state
+/// as canonical JSON.
+pub fn emit_serialize_state(body: &SpaceBody, ctx: &mut FuncContext, f: &mut Function) {
+    let names = variant_names(&body.types, ctx);
+    let sb = ctx.alloc_local(ValType::I32);
+    let text = ctx.alloc_local(ValType::I32);
+    f.instruction(&Instruction::Call(std_idx("$sb_new")));
+    f.instruction(&Instruction::LocalSet(sb));
+    push_byte(sb, b'{', f);
+
+    let mut fields: Vec<&str> = body
+        .state
+        .fields
+        .iter()
+        .map(|s| s.name.name.as_str())
+        .collect();
+    fields.sort_unstable();
+    for (i, name) in fields.into_iter().enumerate() {
+        if i > 0 {
+            push_byte(sb, b',', f);
+        }
+        let (key_ptr, key_len) = ctx.intern_string(name);
+        f.instruction(&Instruction::LocalGet(sb));
+        f.instruction(&Instruction::I32Const(key_ptr as i32));
+        f.instruction(&Instruction::I32Const(key_len as i32));
+        f.instruction(&Instruction::Call(std_idx("$json_quote")));
+        push_byte(sb, b':', f);
+        let slot = ctx.state_slot(name).expect("state field has a slot");
+        f.instruction(&Instruction::LocalGet(sb));
+        f.instruction(&Instruction::GlobalGet(GLOBAL_STATE_PTR));
+        emit_fixed_load(slot, f);
+        f.instruction(&Instruction::I32Const(names as i32));
+        f.instruction(&Instruction::Call(std_idx("$state_into")));
+    }
+
+    push_byte(sb, b'}', f);
+    f.instruction(&Instruction::LocalGet(sb));
+    f.instruction(&Instruction::Call(std_idx("$sb_finish")));
+    f.instruction(&Instruction::LocalTee(text));
+    f.instruction(&Instruction::I32Load(memarg(4, 2)));
+    f.instruction(&Instruction::LocalGet(text));
+    f.instruction(&Instruction::I32Load(memarg(8, 2)));
+    f.instruction(&Instruction::End);
+}
+
+/// The `[count, (ptr, len) × count]` table of user variant names, indexed
+/// by variant id.
+fn variant_names(types: &[TypeDecl], ctx: &mut FuncContext) -> u32 {
+    let mut words = vec![0];
+    for decl in types {
+        if let TypeDeclBody::SumType(variants) = &decl.body {
+            for variant in variants {
+                let (ptr, len) = ctx.intern_string(&variant.name.name);
+                words.extend([ptr, len]);
+            }
+        }
+    }
+    words[0] = (words.len() as u32 - 1) / 2;
+    ctx.intern_words(&words)
+}
+
+fn push_byte(sb: u32, byte: u8, f: &mut Function) {
+    f.instruction(&Instruction::LocalGet(sb));
+    f.instruction(&Instruction::I32Const(byte as i32));
+    f.instruction(&Instruction::Call(std_idx("$sb_byte")));
+}
+
+fn std_idx(name: &str) -> u32 {
+    stdlib::func_idx(name).unwrap_or_else(|| panic!("stdlib {name}"))
+}
+
+// ══════════════════════════════════════════════════════════════════════════════
+// restore_state
+// ══════════════════════════════════════════════════════════════════════════════
+
+/// Emit `restore_state(ptr: i32, len: i32) -> i32`.
+pub fn emit_restore_state(
+    body: &SpaceBody,
+    recompute: &[usize],
+    ctx: &mut FuncContext,
+    f: &mut Function,
+) -> CodegenResult<()> {
+    let state: Vec<(&str, bool, &TypeAnnotation)> = body
+        .state
+        .fields
+        .iter()
+        .map(|s| (s.name.name.as_str(), false, &s.type_ann))
+        .collect();
+    let mut schema = Schema::new(&body.types);
+    let root = schema.record(&state, ctx);
+    let root = schema.place(ctx) + root * 4;
+
+    let snapshot = ctx.alloc_local(ValType::I32);
+    let cursor = ctx.alloc_local(ValType::I32);
+    let value = ctx.alloc_local(ValType::I32);
+    let entries = ctx.alloc_local(ValType::I32);
+    f.instruction(&Instruction::GlobalGet(GLOBAL_STATE_PTR));
+    f.instruction(&Instruction::LocalSet(snapshot));
+    f.instruction(&Instruction::I32Const(0));
+    f.instruction(&Instruction::GlobalSet(GLOBAL_GAS));
+
+    // Parse the whole text, then fit it to the state schema
+    f.instruction(&Instruction::I32Const(8));
+    f.instruction(&Instruction::Call(rt_func_idx(RT_ALLOC)));
+    f.instruction(&Instruction::LocalTee(cursor));
+    f.instruction(&Instruction::LocalGet(0));
+    f.instruction(&Instruction::I32Store(memarg(0, 2)));
+    f.instruction(&Instruction::LocalGet(cursor));
+    f.instruction(&Instruction::LocalGet(0));
+    f.instruction(&Instruction::LocalGet(1));
+    f.instruction(&Instruction::I32Add);
+    f.instruction(&Instruction::I32Store(memarg(4, 2)));
+    f.instruction(&Instruction::LocalGet(cursor));
+    f.instruction(&Instruction::Call(std_idx("$json_value")));
+    f.instruction(&Instruction::LocalTee(value));
+    reject_if_zero(f);
+    f.instruction(&Instruction::LocalGet(cursor));
+    f.instruction(&Instruction::Call(std_idx("$json_peek")));
+    f.instruction(&Instruction::I32Const(-1));
+    f.instruction(&Instruction::I32Ne);
+    reject_if(f);
+    f.instruction(&Instruction::LocalGet(value));
+    f.instruction(&Instruction::I32Const(root as i32));
+    f.instruction(&Instruction::Call(std_idx("$conform")));
+    f.instruction(&Instruction::LocalTee(value));
+    reject_if_zero(f);
+
+    // The conformed record has the state fields in slot order; derived
+    // slots start as nil
+    let field_count = ctx.state_field_names.len();
+    f.instruction(&Instruction::I32Const((field_count * 12) as i32));
+    f.instruction(&Instruction::Call(rt_func_idx(RT_ALLOC)));
+    f.instruction(&Instruction::LocalSet(entries));
+    for i in 0..field_count {
+        let name = ctx.state_field_names[i].clone();
+        let (key_ptr, key_len) = ctx.intern_string(&name);
+        let base = (i * 12) as u64;
+        f.instruction(&Instruction::LocalGet(entries));
+        f.instruction(&Instruction::I32Const(key_ptr as i32));
+        f.instruction(&Instruction::I32Store(memarg(base, 2)));
+        f.instruction(&Instruction::LocalGet(entries));
+        f.instruction(&Instruction::I32Const(key_len as i32));
+        f.instruction(&Instruction::I32Store(memarg(base + 4, 2)));
+        f.instruction(&Instruction::LocalGet(entries));
+        if i < state.len() {
+            f.instruction(&Instruction::LocalGet(value));
+            emit_fixed_load(i as u32, f);
+        } else {
+            f.instruction(&Instruction::Call(rt_func_idx(RT_VAL_NIL)));
+        }
+        f.instruction(&Instruction::I32Store(memarg(base + 8, 2)));
+    }
+    f.instruction(&Instruction::LocalGet(entries));
+    f.instruction(&Instruction::I32Const(field_count as i32));
+    f.instruction(&Instruction::Call(rt_func_idx(RT_VAL_RECORD)));
+    f.instruction(&Instruction::GlobalSet(GLOBAL_STATE_PTR));
+
+    if let Some(derived) = &body.derived {
+        emit_recompute_derived(derived, recompute, ctx, f)?;
+    }
+
+    // A violated invariant puts the previous state back
+    for inv in &body.invariants {
+        ctx.frames.push(RuntimeFrame::new(
+            FrameKind::Invariant,
+            &inv.name.name,
+            inv.span,
+        ));
+        ctx.mark_site(inv.condition.span, None, f);
+        ctx.frames.pop();
+        emit_scalar(&inv.condition, Scalar::Bool, ctx, f)?;
+        gas::flush(ctx, f);
+        f.instruction(&Instruction::I32Eqz);
+        f.instruction(&Instruction::If(BlockType::Empty));
+        f.instruction(&Instruction::LocalGet(snapshot));
+        f.instruction(&Instruction::GlobalSet(GLOBAL_STATE_PTR));
+        f.instruction(&Instruction::I32Const(0));
+        f.instruction(&Instruction::Return);
+        f.instruction(&Instruction::End);
+    }
+
+    f.instruction(&Instruction::I32Const(1));
+    f.instruction(&Instruction::End);
+    Ok(())
+}
+
+/// `return 0` when the i32 on the stack is zero.
+fn reject_if_zero(f: &mut Function) {
+    f.instruction(&Instruction::I32Eqz);
+    reject_if(f);
+}
+
+/// `return 0` when the i32 on the stack is non-zero.
+fn reject_if(f: &mut Function) {
+    f.instruction(&Instruction::If(BlockType::Empty));
+    f.instruction(&Instruction::I32Const(0));
+    f.instruction(&Instruction::Return);
+    f.instruction(&Instruction::End);
+}
+
+// ══════════════════════════════════════════════════════════════════════════════
+// Schema
+// ══════════════════════════════════════════════════════════════════════════════
+
+/// A schema under construction.  Nodes are addressed by word index until
+/// [`Schema::place`] fixes the base address; `relocs` lists the words that
+/// hold a node index.
+struct Schema<'a> {
+    types: HashMap<&'a str, &'a TypeDeclBody>,
+    variant_ids: HashMap<&'a str, u32>,
+    words: Vec<u32>,
+    relocs: Vec<usize>,
+    named: HashMap<String, u32>,
+}
+
+impl<'a> Schema<'a> {
+    fn new(decls: &'a [TypeDecl]) -> Self {
+        let mut variant_ids = HashMap::new();
+        for decl in decls {
+            if let TypeDeclBody::SumType(variants) = &decl.body {
+                for variant in variants {
+                    let id = variant_ids.len() as u32;
+                    variant_ids.insert(variant.name.name.as_str(), id);
+                }
+            }
+        }
+        Self {
+            types: decls
+                .iter()
+                .map(|d| (d.name.name.as_str(), &d.body))
+                .collect(),
+            variant_ids,
+            words: Vec::new(),
+            relocs: Vec::new(),
+            named: HashMap::new(),
+        }
+    }
+
+    /// Reserve `len` words for a node of `kind`; returns its index.
+    fn alloc(&mut self, kind: i32, len: usize) -> u32 {
+        let at = self.words.len();
+        self.words.push(kind as u32);
+        self.words.resize(at + len, 0);
+        at as u32
+    }
+
+    /// Point word `at` to node `node`.
+    fn link(&mut self, at: usize, node: u32) {
+        self.words[at] = node;
+        self.relocs.push(at);
+    }
+
+    fn node(&mut self, ty: &'a TypeAnnotation, ctx: &mut FuncContext) -> u32 {
+        match &ty.kind {
+            TypeKind::Number => self.alloc(SCHEMA_NUMBER, 1),
+            TypeKind::String => self.alloc(SCHEMA_STRING, 1),
+            TypeKind::Bool => self.alloc(SCHEMA_BOOL, 1),
+            TypeKind::Nil => self.alloc(SCHEMA_NIL, 1),
+            TypeKind::Any => self.alloc(SCHEMA_ANY, 1),
+            TypeKind::Color
+            | TypeKind::Surface
+            | TypeKind::InputEvent
+            | TypeKind::Function { .. } => self.alloc(SCHEMA_REJECT, 1),
+            TypeKind::List(item) => {
+                let at = self.alloc(SCHEMA_LIST, 2);
+                let item = self.node(item, ctx);
+                self.link(at as usize + 1, item);
+                at
+            }
+            TypeKind::Record(fields) => {
+                // Name order, the checker's record layout
+                let mut fields: Vec<(&str, bool, &TypeAnnotation)> = fields
+                    .iter()
+                    .map(|f| (f.name.name.as_str(), f.optional, &f.type_ann))
+                    .collect();
+                fields.sort_by_key(|&(name, ..)| name);
+                self.record(&fields, ctx)
+            }
+            TypeKind::Result(ok, err) => {
+                let at = self.alloc(SCHEMA_RESULT, 3);
+                let ok = self.node(ok, ctx);
+                self.link(at as usize + 1, ok);
+                let err = self.node(err, ctx);
+                self.link(at as usize + 2, err);
+                at
+            }
+            TypeKind::Named(name) => self.named(name, ctx),
+        }
+    }
+
+    fn named(&mut self, name: &str, ctx: &mut FuncContext) -> u32 {
+        if let Some(&at) = self.named.get(name) {
+            return at;
+        }
+        match self.types.get(name).copied() {
+            Some(TypeDeclBody::Alias(target)) => {
+                let at = self.node(target, ctx);
+                self.named.insert(name.to_string(), at);
+                at
+            }
+            Some(TypeDeclBody::SumType(variants)) => {
+                let at = self.alloc(SCHEMA_VARIANTS, 2 + 4 * variants.len());
+                self.named.insert(name.to_string(), at);
+                self.words[at as usize + 1] = variants.len() as u32;
+                for (i, variant) in variants.iter().enumerate() {
+                    let entry = at as usize + 2 + 4 * i;
+                    let (ptr, len) = ctx.intern_string(&variant.name.name);
+                    self.words[entry] = self.variant_ids[variant.name.name.as_str()];
+                    self.words[entry + 1] = ptr;
+                    self.words[entry + 2] = len;
+                    let fields = self.alloc(0, 1 + variant.params.len());
+                    self.words[fields as usize] = variant.params.len() as u32;
+                    self.link(entry + 3, fields);
+                    for (j, param) in variant.params.iter().enumerate() {
+                        let node = self.node(&param.type_ann, ctx);
+                        self.link(fields as usize + 1 + j, node);
+                    }
+                }
+                at
+            }
+            // Unknown names were rejected by the checker
+            None => self.alloc(SCHEMA_ANY, 1),
+        }
+    }
+
+    /// A record node for `(name, optional, type)` fields, in that order.
+    fn record(
+        &mut self,
+        fields: &[(&str, bool, &'a TypeAnnotation)],
+        ctx: &mut FuncContext,
+    ) -> u32 {
+        let at = self.alloc(SCHEMA_RECORD, 2 + 4 * fields.len());
+        self.words[at as usize + 1] = fields.len() as u32;
+        for (i, &(name, optional, ty)) in fields.iter().enumerate() {
+            let entry = at as usize + 2 + 4 * i;
+            let (ptr, len) = ctx.intern_string(name);
+            self.words[entry] = ptr;
+            self.words[entry + 1] = len;
+            self.words[entry + 2] = optional as u32;
+            let node = self.node(ty, ctx);
+            self.link(entry + 3, node);
+        }
+        at
+    }
+
+    /// Write the schema to the data segment; returns its base address.
+    fn place(mut self, ctx: &mut FuncContext) -> u32 {
+        let base = ctx.next_word_address();
+        for &at in &self.relocs {
+            self.words[at] = base + self.words[at] * 4;
+        }
+        ctx.intern_words(&self.words)
+    }
+}
@@ -4,15 +4,27 @@
 //! span (line, column).  This enables the host to resolve WASM traps back to
 //! human-readable source positions.
 //!
-//! Granularity is per-function in Phase 0.  Future phases may add
-//! instruction-level mappings.
+//! Function entries are per-function.  Finer locations come from trap
+//! sites: before each statement, derived field, invariant check and view
+//! component the generated code stores a site id in the exported
+//! `__trap_site` global.  After a trap the host reads that global and calls
+//! [`SourceMap::resolve_trap`] to get a [`RuntimeError`] with the span and
+//! PEPL call stack of the failing construct.
 
+use pepl_types::{ErrorCode, RuntimeError, RuntimeFrame};
 use serde::{Deserialize, Serialize};
 
 /// A complete source map for a compiled PEPL module.
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct SourceMap {
     pub entries: Vec<SourceMapEntry>,
+    /// Trap sites, indexed by the value of the `__trap_site` global.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sites: Vec<TrapSite>,
+    /// Runtime and stdlib helpers kept by tree-shaking, as offsets past the
+    /// imports in the unshaken layout.  Empty when nothing was shaken.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub helpers: Vec<u32>,
 }
 
 /// A single source map entry: one WASM function → one PEPL source region.
@@ -29,6 +41,20 @@ pub struct SourceMapEntry {
     pub span: pepl_types::Span,
 }
 
+/// A statement or declaration that may trap, recorded by id.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TrapSite {
+    /// Value stored in `__trap_site` while this construct executes (ids start at 1).
+    pub id: u32,
+    /// Source span of the statement / declaration.
+    pub span: pepl_types::Span,
+    /// Lexically enclosing PEPL frames, outermost first.
+    pub frames: Vec<RuntimeFrame>,
+    /// Error code for traps that can only come from this site (e.g. `assert`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code: Option<ErrorCode>,
+}
+
 /// Classification of a compiled function for the host.
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 #[serde(rename_all = "snake_case")]
@@ -47,6 +73,8 @@ pub enum FuncKind {
     Test,
     /// The __test_count helper.
     TestCount,
+    /// Surface query helpers used by compiled tests.
+    TestHelper,
     /// A compiled lambda body.
     Lambda,
     /// invoke_lambda trampoline.
@@ -57,6 +85,8 @@ impl SourceMap {
     pub fn new() -> Self {
         Self {
             entries: Vec::new(),
+            sites: Vec::new(),
+            helpers: Vec::new(),
         }
     }
 
@@ -81,6 +111,42 @@ impl SourceMap {
         self.entries.iter().find(|e| e.wasm_func_index == idx)
     }
 
+    /// Look up a trap site by the value read from `__trap_site`.
+    pub fn find_site(&self, id: u32) -> Option<&TrapSite> {
+        self.sites.iter().find(|s| s.id == id)
+    }
+
+    /// Turn a WASM trap into a structured [`RuntimeError`].
+    ///
+    /// `site` is the value of the `__trap_site` global after the trap and
+    /// `message` is the string passed to `env.trap`.  Site 0 (or an unknown
+    /// id) yields an error without span or frames.
+    pub fn resolve_trap(&self, site: u32, message: &str) -> RuntimeError {
+        let site = self.find_site(site);
+        let code = if message.starts_with("gas exhausted") {
+            ErrorCode::GAS_EXHAUSTED
+        } else if message.starts_with("division by zero") || message.starts_with("NaN result") {
+            ErrorCode::ARITHMETIC_TRAP
+        } else if message.starts_with("assertion failed") {
+            ErrorCode::ASSERTION_FAILED
+        } else if message.starts_with("invariant violated") {
+            ErrorCode::INVARIANT_VIOLATED
+        } else if message.starts_with("unwrap on Err") {
+            ErrorCode::UNWRAP_ERR
+        } else if message.starts_with("out of memory") {
+            ErrorCode::OUT_OF_MEMORY
+        } else {
+            site.and_then(|s| s.code)
+                .unwrap_or(ErrorCode::RUNTIME_FAILURE)
+        };
+        let mut err = RuntimeError::new(code, message);
+        if let Some(site) = site {
+            err.span = Some(site.span);
+            err.frames = site.frames.clone();
+        }
+        err
+    }
+
     /// Serialize to JSON bytes for embedding in a WASM custom section.
     pub fn to_json(&self) -> Vec<u8> {
         serde_json::to_vec(self).unwrap_or_default()
@@ -121,4 +187,30 @@ mod tests {
         assert_eq!(sm.find_by_func_index(35).unwrap().func_name, "init");
         assert!(sm.find_by_func_index(99).is_none());
     }
+
+    #[test]
+    fn resolve_trap_uses_site_span_and_frames() {
+        use pepl_types::FrameKind;
+
+        let mut sm = SourceMap::new();
+        sm.sites.push(TrapSite {
+            id: 1,
+            span: Span::new(8, 5, 8, 20),
+            frames: vec![RuntimeFrame::new(FrameKind::Action, "divide", Span::new(7, 3, 9, 4))],
+            code: None,
+        });
+
+        let err = sm.resolve_trap(1, "division by zero");
+        assert_eq!(err.code, ErrorCode::ARITHMETIC_TRAP);
+        assert_eq!(err.span, Some(Span::new(8, 5, 8, 20)));
+        assert_eq!(err.frames[0].name, "divide");
+
+        let err = sm.resolve_trap(0, "gas exhausted");
+        assert_eq!(err.code, ErrorCode::GAS_EXHAUSTED);
+        assert!(err.span.is_none());
+
+        let json = sm.to_json();
+        let sm2 = SourceMap::from_json(&json).expect("parse failed");
+        assert_eq!(sm2.sites, sm.sites);
+    }
 }
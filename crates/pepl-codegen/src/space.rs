@@ -5,36 +5,40 @@
 //! - `dispatch_action(action_id: i32, payload_ptr: i32, payload_len: i32)` — run an action
 //! - `render(view_id: i32) -> i32` — render a view to Surface tree
 //! - `get_state() -> i32` — return current state as a record value ptr
+//! - `serialize_state` / `restore_state` — see [`crate::snapshot`]
 //! - Conditionally: `update(dt_ptr: i32)`, `handle_event(event_ptr: i32)`
 
 use pepl_types::ast::*;
+use pepl_types::{ErrorCode, FrameKind, RuntimeFrame};
 use wasm_encoder::{BlockType, Function, Instruction, ValType};
 
 use crate::compiler::FuncContext;
 use crate::error::CodegenResult;
-use crate::expr::emit_expr;
+use crate::expr::{alloc_loop_index, emit_expr, emit_loop_index};
 use crate::gas;
 use crate::runtime::*;
 use crate::stmt::emit_stmts;
 use crate::types::*;
+use crate::unbox::{emit_scalar, Scalar};
 
 // ══════════════════════════════════════════════════════════════════════════════
 // init
 // ══════════════════════════════════════════════════════════════════════════════
 
 /// Default gas limit when init is called without parameters.
-const DEFAULT_GAS_LIMIT: i32 = 1_000_000;
+const DEFAULT_GAS_LIMIT: i32 = pepl_types::gas::DEFAULT_LIMIT as i32;
 
 /// Emit the `init()` function (parameterless).
 ///
 /// - Sets global gas_limit to DEFAULT_GAS_LIMIT
 /// - Resets gas counter to 0
 /// - Evaluates each state field's default expression
-/// - Builds the state record
+/// - Builds the state record, with nil in each derived field's slot
 /// - Evaluates derived fields
 pub fn emit_init(
     state: &StateBlock,
     derived: Option<&DerivedBlock>,
+    recompute: &[usize],
     ctx: &mut FuncContext,
     f: &mut Function,
 ) -> CodegenResult<()> {
@@ -45,8 +49,8 @@ pub fn emit_init(
     f.instruction(&Instruction::I32Const(0));
     f.instruction(&Instruction::GlobalSet(GLOBAL_GAS));
 
-    // Build state record from defaults
-    let field_count = state.fields.len();
+    // Build state record from defaults; it holds every slot from the start
+    let field_count = ctx.state_field_names.len();
     let entries_local = ctx.alloc_local(ValType::I32);
 
     f.instruction(&Instruction::I32Const((field_count * 12) as i32));
@@ -61,6 +65,7 @@ pub fn emit_init(
         // Evaluate default
         emit_expr(&field.default, ctx, f)?;
         f.instruction(&Instruction::LocalSet(val_local));
+        gas::flush(ctx, f);
 
         // key_offset
         f.instruction(&Instruction::LocalGet(entries_local));
@@ -76,6 +81,21 @@ pub fn emit_init(
         f.instruction(&Instruction::I32Store(memarg(base + 8, 2)));
     }
 
+    for i in state.fields.len()..field_count {
+        let name = ctx.state_field_names[i].clone();
+        let (key_ptr, key_len) = ctx.intern_string(&name);
+        let base = (i * 12) as u64;
+        f.instruction(&Instruction::LocalGet(entries_local));
+        f.instruction(&Instruction::I32Const(key_ptr as i32));
+        f.instruction(&Instruction::I32Store(memarg(base, 2)));
+        f.instruction(&Instruction::LocalGet(entries_local));
+        f.instruction(&Instruction::I32Const(key_len as i32));
+        f.instruction(&Instruction::I32Store(memarg(base + 4, 2)));
+        f.instruction(&Instruction::LocalGet(entries_local));
+        f.instruction(&Instruction::Call(rt_func_idx(RT_VAL_NIL)));
+        f.instruction(&Instruction::I32Store(memarg(base + 8, 2)));
+    }
+
     // state_ptr = record(entries, count)
     f.instruction(&Instruction::LocalGet(entries_local));
     f.instruction(&Instruction::I32Const(field_count as i32));
@@ -84,7 +104,7 @@ pub fn emit_init(
 
     // Recompute derived fields (if any)
     if let Some(derived_block) = derived {
-        emit_recompute_derived(derived_block, ctx, f)?;
+        emit_recompute_derived(derived_block, recompute, ctx, f)?;
     }
 
     f.instruction(&Instruction::End);
@@ -100,12 +120,15 @@ pub fn emit_init(
 /// Dispatches to the appropriate action handler based on action_id.
 /// `payload_ptr` (param 1) is a pointer to the arguments list value.
 /// `payload_len` (param 2) is reserved for future use (byte length of serialised payload).
-/// Checks invariants after execution and rolls back on failure.
+/// Recomputes the derived fields downstream of what the action writes
+/// (`recompute`, per action), then checks invariants and rolls back on
+/// failure.
 /// Returns void.
 pub fn emit_dispatch_action(
     actions: &[ActionDecl],
     invariants: &[InvariantDecl],
     derived: Option<&DerivedBlock>,
+    recompute: &[Vec<usize>],
     ctx: &mut FuncContext,
     f: &mut Function,
 ) -> CodegenResult<()> {
@@ -141,28 +164,37 @@ pub fn emit_dispatch_action(
         }
 
         // Execute action body
+        ctx.frames
+            .push(RuntimeFrame::new(FrameKind::Action, &action.name.name, action.span));
         emit_stmts(&action.body.stmts, ctx, f)?;
+        ctx.frames.pop();
 
         // Pop param bindings
         for param in action.params.iter().rev() {
             ctx.pop_local(&param.name.name);
         }
 
+        gas::flush(ctx, f);
+
+        // Recompute the derived fields this action can change
+        if let Some(derived_block) = derived {
+            emit_recompute_derived(derived_block, &recompute[i], ctx, f)?;
+        }
+
         f.instruction(&Instruction::Br(0)); // break to outer
         f.instruction(&Instruction::End); // end if
     }
 
     f.instruction(&Instruction::End); // end outer block
 
-    // Recompute derived fields
-    if let Some(derived_block) = derived {
-        emit_recompute_derived(derived_block, ctx, f)?;
-    }
-
     // Check invariants — if any fail, rollback
     for inv in invariants {
-        emit_expr(&inv.condition, ctx, f)?;
-        f.instruction(&Instruction::I32Load(memarg(4, 2)));
+        ctx.frames
+            .push(RuntimeFrame::new(FrameKind::Invariant, &inv.name.name, inv.span));
+        ctx.mark_site(inv.condition.span, Some(ErrorCode::INVARIANT_VIOLATED), f);
+        ctx.frames.pop();
+        emit_scalar(&inv.condition, Scalar::Bool, ctx, f)?;
+        gas::flush(ctx, f);
         f.instruction(&Instruction::I32Eqz);
         f.instruction(&Instruction::If(BlockType::Empty));
         // Rollback: restore snapshot
@@ -216,7 +248,10 @@ pub fn emit_render(
         }
 
         // Emit UI block → Surface node tree
+        ctx.frames
+            .push(RuntimeFrame::new(FrameKind::View, &view.name.name, view.span));
         emit_ui_block(&view.body, ctx, f)?;
+        ctx.frames.pop();
         f.instruction(&Instruction::LocalSet(result_local));
 
         // Pop param bindings
@@ -224,6 +259,7 @@ pub fn emit_render(
             ctx.pop_local(&param.name.name);
         }
 
+        gas::flush(ctx, f);
         f.instruction(&Instruction::Br(0));
         f.instruction(&Instruction::End);
     }
@@ -290,6 +326,8 @@ fn emit_component_expr(
     ctx: &mut FuncContext,
     f: &mut Function,
 ) -> CodegenResult<()> {
+    ctx.mark_site(comp.span, None, f);
+
     // We build a 3-field record: component, props, children
     let entries_local = ctx.alloc_local(ValType::I32);
     f.instruction(&Instruction::I32Const(3 * 12)); // 3 fields × 12 bytes
@@ -399,14 +437,26 @@ fn emit_props(props: &[PropAssign], ctx: &mut FuncContext, f: &mut Function) ->
 }
 
 fn emit_ui_if(ui_if: &UIIf, ctx: &mut FuncContext, f: &mut Function) -> CodegenResult<()> {
-    emit_expr(&ui_if.condition, ctx, f)?;
-    f.instruction(&Instruction::I32Load(memarg(4, 2)));
+    // `if true { … }` (what the optimiser leaves of a literal condition)
+    if let (ExprKind::BoolLit(true), None) = (&ui_if.condition.kind, &ui_if.else_block) {
+        gas::charge_expr(&ui_if.condition, ctx);
+        return emit_ui_block(&ui_if.then_block, ctx, f);
+    }
+    emit_scalar(&ui_if.condition, Scalar::Bool, ctx, f)?;
+    gas::flush(ctx, f);
     f.instruction(&Instruction::If(BlockType::Result(ValType::I32)));
     emit_ui_block(&ui_if.then_block, ctx, f)?;
+    gas::flush(ctx, f);
     f.instruction(&Instruction::Else);
     match &ui_if.else_block {
-        Some(UIElse::Block(block)) => emit_ui_block(block, ctx, f)?,
-        Some(UIElse::ElseIf(elif)) => emit_ui_if(elif, ctx, f)?,
+        Some(UIElse::Block(block)) => {
+            emit_ui_block(block, ctx, f)?;
+            gas::flush(ctx, f);
+        }
+        Some(UIElse::ElseIf(elif)) => {
+            gas::charge(pepl_types::gas::ELSE_IF, ctx);
+            emit_ui_if(elif, ctx, f)?;
+        }
         None => {
             f.instruction(&Instruction::I32Const(0));
             f.instruction(&Instruction::I32Const(0));
@@ -447,17 +497,14 @@ fn emit_ui_for(ui_for: &UIFor, ctx: &mut FuncContext, f: &mut Function) -> Codeg
     f.instruction(&Instruction::LocalSet(i_local));
 
     let item_local = ctx.alloc_local(ValType::I32);
-    let index_local = if ui_for.index.is_some() {
-        Some(ctx.alloc_local(ValType::I32))
-    } else {
-        None
-    };
+    let index_local = ui_for.index.as_ref().map(|_| alloc_loop_index(ctx));
 
     ctx.push_local(&ui_for.item.name, item_local);
     if let (Some(idx_ident), Some(idx_local)) = (&ui_for.index, index_local) {
         ctx.push_local(&idx_ident.name, idx_local);
     }
 
+    gas::flush(ctx, f);
     f.instruction(&Instruction::Block(BlockType::Empty));
     f.instruction(&Instruction::Loop(BlockType::Empty));
 
@@ -465,6 +512,7 @@ fn emit_ui_for(ui_for: &UIFor, ctx: &mut FuncContext, f: &mut Function) -> Codeg
     f.instruction(&Instruction::LocalGet(count_local));
     f.instruction(&Instruction::I32GeU);
     f.instruction(&Instruction::BrIf(1));
+    gas::charge(pepl_types::gas::FOR_ITERATION, ctx);
 
     // item = arr[i]
     f.instruction(&Instruction::LocalGet(arr_local));
@@ -476,15 +524,7 @@ fn emit_ui_for(ui_for: &UIFor, ctx: &mut FuncContext, f: &mut Function) -> Codeg
     f.instruction(&Instruction::LocalSet(item_local));
 
     if let Some(idx_local) = index_local {
-        f.instruction(&Instruction::I32Const(VALUE_SIZE as i32));
-        f.instruction(&Instruction::Call(rt_func_idx(RT_ALLOC)));
-        f.instruction(&Instruction::LocalTee(idx_local));
-        f.instruction(&Instruction::I32Const(TAG_NUMBER));
-        f.instruction(&Instruction::I32Store(memarg(0, 2)));
-        f.instruction(&Instruction::LocalGet(idx_local));
-        f.instruction(&Instruction::LocalGet(i_local));
-        f.instruction(&Instruction::F64ConvertI32U);
-        f.instruction(&Instruction::F64Store(memarg(4, 3)));
+        emit_loop_index(i_local, idx_local, ctx, f);
     }
 
     // Emit body → list of nodes
@@ -501,6 +541,8 @@ fn emit_ui_for(ui_for: &UIFor, ctx: &mut FuncContext, f: &mut Function) -> Codeg
     f.instruction(&Instruction::LocalGet(body_result));
     f.instruction(&Instruction::I32Store(memarg(0, 2)));
 
+    gas::flush(ctx, f);
+
     // i += 1
     f.instruction(&Instruction::LocalGet(i_local));
     f.instruction(&Instruction::I32Const(1));
@@ -540,25 +582,27 @@ pub fn emit_get_state(f: &mut Function) {
 pub fn emit_update(
     update_decl: &UpdateDecl,
     derived: Option<&DerivedBlock>,
+    recompute: &[usize],
     ctx: &mut FuncContext,
     f: &mut Function,
 ) -> CodegenResult<()> {
-    gas::emit_gas_tick(f, ctx.data.gas_exhausted_ptr, ctx.data.gas_exhausted_len);
-
     // Bind dt param
     let dt_local = ctx.alloc_local(ValType::I32);
     f.instruction(&Instruction::LocalGet(0));
     f.instruction(&Instruction::LocalSet(dt_local));
     ctx.push_local(&update_decl.param.name.name, dt_local);
 
+    ctx.frames
+        .push(RuntimeFrame::new(FrameKind::Update, "update", update_decl.span));
     emit_stmts(&update_decl.body.stmts, ctx, f)?;
 
     ctx.pop_local(&update_decl.param.name.name);
 
     // Recompute derived
     if let Some(derived_block) = derived {
-        emit_recompute_derived(derived_block, ctx, f)?;
+        emit_recompute_derived(derived_block, recompute, ctx, f)?;
     }
+    ctx.frames.pop();
 
     f.instruction(&Instruction::End);
     Ok(())
@@ -568,23 +612,28 @@ pub fn emit_update(
 pub fn emit_handle_event(
     handle_event_decl: &HandleEventDecl,
     derived: Option<&DerivedBlock>,
+    recompute: &[usize],
     ctx: &mut FuncContext,
     f: &mut Function,
 ) -> CodegenResult<()> {
-    gas::emit_gas_tick(f, ctx.data.gas_exhausted_ptr, ctx.data.gas_exhausted_len);
-
     let event_local = ctx.alloc_local(ValType::I32);
     f.instruction(&Instruction::LocalGet(0));
     f.instruction(&Instruction::LocalSet(event_local));
     ctx.push_local(&handle_event_decl.param.name.name, event_local);
 
+    ctx.frames.push(RuntimeFrame::new(
+        FrameKind::HandleEvent,
+        "handleEvent",
+        handle_event_decl.span,
+    ));
     emit_stmts(&handle_event_decl.body.stmts, ctx, f)?;
 
     ctx.pop_local(&handle_event_decl.param.name.name);
 
     if let Some(derived_block) = derived {
-        emit_recompute_derived(derived_block, ctx, f)?;
+        emit_recompute_derived(derived_block, recompute, ctx, f)?;
     }
+    ctx.frames.pop();
 
     f.instruction(&Instruction::End);
     Ok(())
@@ -594,16 +643,23 @@ pub fn emit_handle_event(
 // Derived field recomputation
 // ══════════════════════════════════════════════════════════════════════════════
 
-/// Recompute all derived fields and update the state record.
-fn emit_recompute_derived(
+/// Recompute the derived fields at indices `fields` of `derived`, in that
+/// order, and update the state record.
+pub(crate) fn emit_recompute_derived(
     derived: &DerivedBlock,
+    fields: &[usize],
     ctx: &mut FuncContext,
     f: &mut Function,
 ) -> CodegenResult<()> {
-    for field in &derived.fields {
+    for field in fields.iter().map(|&i| &derived.fields[i]) {
         let val_local = ctx.alloc_local(ValType::I32);
+        ctx.frames
+            .push(RuntimeFrame::new(FrameKind::Derived, &field.name.name, field.span));
+        ctx.mark_site(field.value.span, None, f);
         emit_expr(&field.value, ctx, f)?;
+        ctx.frames.pop();
         f.instruction(&Instruction::LocalSet(val_local));
+        gas::flush(ctx, f);
 
         // Update state record with the computed derived value
         let field_name = field.name.name.clone();
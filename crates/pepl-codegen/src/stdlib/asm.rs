@@ -0,0 +1,294 @@
+//! A tiny structured assembler for the stdlib emitters.
+//!
+//! The runtime helpers in `runtime.rs` are written instruction by
+//! instruction; the stdlib is several times larger, so its emitters use this
+//! builder instead.  Locals are allocated on demand, control flow is
+//! expressed with closures, and `brk` computes the branch depth to the
+//! innermost `while_` so loop exits never have to be counted by hand.
+
+use wasm_encoder::{BlockType, Function, Instruction, ValType};
+
+use crate::runtime::{memarg, rt_func_idx, RT_ALLOC, RT_VAL_BOOL, RT_VAL_NIL, RT_VAL_STRING};
+
+use super::{func_idx, string_offset};
+
+type I = Instruction<'static>;
+
+pub(crate) struct Asm {
+    locals: Vec<ValType>,
+    next_local: u32,
+    code: Vec<I>,
+    depth: u32,
+    loops: Vec<u32>,
+    strings_base: u32,
+}
+
+impl Asm {
+    pub(crate) fn new(params: u32, strings_base: u32) -> Self {
+        Self {
+            locals: Vec::new(),
+            next_local: params,
+            code: Vec::new(),
+            depth: 0,
+            loops: Vec::new(),
+            strings_base,
+        }
+    }
+
+    pub(crate) fn finish(mut self) -> Function {
+        self.code.push(Instruction::End);
+        let mut f = Function::new_with_locals_types(self.locals);
+        for ins in &self.code {
+            f.instruction(ins);
+        }
+        f
+    }
+
+    // ── Locals ───────────────────────────────────────────────────────────
+
+    pub(crate) fn local(&mut self, ty: ValType) -> u32 {
+        self.locals.push(ty);
+        self.next_local += 1;
+        self.next_local - 1
+    }
+
+    pub(crate) fn i32_local(&mut self) -> u32 {
+        self.local(ValType::I32)
+    }
+
+    pub(crate) fn f64_local(&mut self) -> u32 {
+        self.local(ValType::F64)
+    }
+
+    pub(crate) fn i64_local(&mut self) -> u32 {
+        self.local(ValType::I64)
+    }
+
+    // ── Plain instructions ───────────────────────────────────────────────
+
+    pub(crate) fn op(&mut self, ins: I) -> &mut Self {
+        self.code.push(ins);
+        self
+    }
+
+    pub(crate) fn get(&mut self, local: u32) -> &mut Self {
+        self.op(Instruction::LocalGet(local))
+    }
+
+    pub(crate) fn set(&mut self, local: u32) -> &mut Self {
+        self.op(Instruction::LocalSet(local))
+    }
+
+    pub(crate) fn tee(&mut self, local: u32) -> &mut Self {
+        self.op(Instruction::LocalTee(local))
+    }
+
+    pub(crate) fn i32(&mut self, v: i32) -> &mut Self {
+        self.op(Instruction::I32Const(v))
+    }
+
+    pub(crate) fn i64(&mut self, v: i64) -> &mut Self {
+        self.op(Instruction::I64Const(v))
+    }
+
+    pub(crate) fn f64(&mut self, v: f64) -> &mut Self {
+        self.op(Instruction::F64Const(v))
+    }
+
+    pub(crate) fn ret(&mut self) -> &mut Self {
+        self.op(Instruction::Return)
+    }
+
+    // ── Calls ────────────────────────────────────────────────────────────
+
+    /// Call another stdlib function (or `$helper`) by name.
+    pub(crate) fn call(&mut self, name: &str) -> &mut Self {
+        let idx = func_idx(name).unwrap_or_else(|| panic!("unknown stdlib function `{name}`"));
+        self.op(Instruction::Call(idx))
+    }
+
+    /// Call a runtime helper (`RT_*` offset).
+    pub(crate) fn rt(&mut self, offset: u32) -> &mut Self {
+        self.op(Instruction::Call(rt_func_idx(offset)))
+    }
+
+    /// Call an imported function (`IMPORT_*` index).
+    pub(crate) fn import(&mut self, idx: u32) -> &mut Self {
+        self.op(Instruction::Call(idx))
+    }
+
+    pub(crate) fn alloc(&mut self) -> &mut Self {
+        self.rt(RT_ALLOC)
+    }
+
+    pub(crate) fn nil(&mut self) -> &mut Self {
+        self.rt(RT_VAL_NIL)
+    }
+
+    /// Box the i32 on the stack as a BOOL value.
+    pub(crate) fn bool(&mut self) -> &mut Self {
+        self.rt(RT_VAL_BOOL)
+    }
+
+    /// Box the f64 on the stack as a NUMBER value.
+    pub(crate) fn number(&mut self) -> &mut Self {
+        self.call("$num")
+    }
+
+    // ── Memory ───────────────────────────────────────────────────────────
+
+    pub(crate) fn load(&mut self, offset: u64) -> &mut Self {
+        self.op(Instruction::I32Load(memarg(offset, 2)))
+    }
+
+    pub(crate) fn store(&mut self, offset: u64) -> &mut Self {
+        self.op(Instruction::I32Store(memarg(offset, 2)))
+    }
+
+    pub(crate) fn load8(&mut self, offset: u64) -> &mut Self {
+        self.op(Instruction::I32Load8U(memarg(offset, 0)))
+    }
+
+    pub(crate) fn store8(&mut self, offset: u64) -> &mut Self {
+        self.op(Instruction::I32Store8(memarg(offset, 0)))
+    }
+
+    pub(crate) fn f64_load(&mut self, offset: u64) -> &mut Self {
+        self.op(Instruction::F64Load(memarg(offset, 3)))
+    }
+
+    pub(crate) fn f64_store(&mut self, offset: u64) -> &mut Self {
+        self.op(Instruction::F64Store(memarg(offset, 3)))
+    }
+
+    /// `value.tag`
+    pub(crate) fn tag(&mut self, value: u32) -> &mut Self {
+        self.get(value).load(0)
+    }
+
+    /// `value.w1` — string data, list array, record entries, variant id.
+    pub(crate) fn w1(&mut self, value: u32) -> &mut Self {
+        self.get(value).load(4)
+    }
+
+    /// `value.w2` — string length, list count, record field count.
+    pub(crate) fn w2(&mut self, value: u32) -> &mut Self {
+        self.get(value).load(8)
+    }
+
+    /// The f64 payload of a NUMBER value.
+    pub(crate) fn num(&mut self, value: u32) -> &mut Self {
+        self.get(value).f64_load(4)
+    }
+
+    /// Push `base + index * 4` — the address of a list slot.
+    pub(crate) fn slot(&mut self, base: u32, index: u32) -> &mut Self {
+        self.get(base)
+            .get(index)
+            .i32(4)
+            .op(Instruction::I32Mul)
+            .op(Instruction::I32Add)
+    }
+
+    // ── Constant strings ─────────────────────────────────────────────────
+
+    /// Push `(ptr, len)` of a constant from [`super::STRINGS`].
+    pub(crate) fn lit(&mut self, s: &str) -> &mut Self {
+        let offset =
+            string_offset(s).unwrap_or_else(|| panic!("stdlib string `{s}` not in STRINGS"));
+        self.i32((self.strings_base + offset) as i32)
+            .i32(s.len() as i32)
+    }
+
+    /// Push a STRING value for a constant from [`super::STRINGS`].
+    pub(crate) fn str(&mut self, s: &str) -> &mut Self {
+        self.lit(s).rt(RT_VAL_STRING)
+    }
+
+    // ── Control flow ─────────────────────────────────────────────────────
+
+    pub(crate) fn if_(&mut self, then: impl FnOnce(&mut Self)) -> &mut Self {
+        self.op(Instruction::If(BlockType::Empty));
+        self.depth += 1;
+        then(self);
+        self.depth -= 1;
+        self.op(Instruction::End)
+    }
+
+    pub(crate) fn if_else(
+        &mut self,
+        ty: BlockType,
+        then: impl FnOnce(&mut Self),
+        els: impl FnOnce(&mut Self),
+    ) -> &mut Self {
+        self.op(Instruction::If(ty));
+        self.depth += 1;
+        then(self);
+        self.op(Instruction::Else);
+        els(self);
+        self.depth -= 1;
+        self.op(Instruction::End)
+    }
+
+    /// `if` producing an i32.
+    pub(crate) fn select_i32(
+        &mut self,
+        then: impl FnOnce(&mut Self),
+        els: impl FnOnce(&mut Self),
+    ) -> &mut Self {
+        self.if_else(BlockType::Result(ValType::I32), then, els)
+    }
+
+    /// Loop while `cond` leaves a non-zero i32; `brk` exits early.
+    pub(crate) fn while_(
+        &mut self,
+        cond: impl FnOnce(&mut Self),
+        body: impl FnOnce(&mut Self),
+    ) -> &mut Self {
+        self.op(Instruction::Block(BlockType::Empty));
+        self.depth += 1;
+        let exit = self.depth;
+        self.op(Instruction::Loop(BlockType::Empty));
+        self.depth += 1;
+        self.loops.push(exit);
+        cond(self);
+        self.op(Instruction::I32Eqz).op(Instruction::BrIf(1));
+        body(self);
+        self.op(Instruction::Br(0));
+        self.loops.pop();
+        self.depth -= 2;
+        self.op(Instruction::End).op(Instruction::End)
+    }
+
+    /// Loop until `brk` or `ret`.
+    pub(crate) fn forever(&mut self, body: impl FnOnce(&mut Self)) -> &mut Self {
+        self.while_(
+            |a| {
+                a.i32(1);
+            },
+            body,
+        )
+    }
+
+    /// `for i in 0..n` over i32 locals.
+    pub(crate) fn for_(&mut self, i: u32, n: u32, body: impl FnOnce(&mut Self)) -> &mut Self {
+        self.i32(-1).set(i);
+        self.while_(
+            |a| {
+                a.get(i)
+                    .i32(1)
+                    .op(Instruction::I32Add)
+                    .tee(i)
+                    .get(n)
+                    .op(Instruction::I32LtS);
+            },
+            body,
+        )
+    }
+
+    /// Leave the innermost `while_`/`for_`/`forever`.
+    pub(crate) fn brk(&mut self) -> &mut Self {
+        let exit = *self.loops.last().expect("brk outside a loop");
+        self.op(Instruction::Br(self.depth - exit))
+    }
+}
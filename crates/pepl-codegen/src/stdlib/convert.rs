@@ -0,0 +1,450 @@
+//! `convert.*` and the pure half of `core.*`.
+//!
+//! Number parsing accepts what Rust's `str::parse` accepts after trimming
+//! ASCII whitespace — `i64` syntax for `parse_int`, decimal float syntax for
+//! `parse_float`/`to_number` — and fails with `Err("cannot parse '…'")`.
+
+use wasm_encoder::{BlockType, Instruction as I};
+
+use crate::types::*;
+
+use super::asm::Asm;
+
+pub(super) fn to_string(a: &mut Asm) {
+    a.get(0).call("$display");
+}
+
+/// `convert.to_number(v)` — numbers pass through, bools become 0/1, anything
+/// else is parsed from its display form.
+pub(super) fn to_number(a: &mut Asm) {
+    a.tag(0).i32(TAG_NUMBER).op(I::I32Eq).if_(|a| {
+        a.get(0).call("$ok").ret();
+    });
+    a.tag(0).i32(TAG_BOOL).op(I::I32Eq).if_(|a| {
+        a.w1(0).op(I::F64ConvertI32U).number().call("$ok").ret();
+    });
+    a.get(0).call("$display").i32(1).call("$parse_number");
+}
+
+pub(super) fn parse_int(a: &mut Asm) {
+    a.get(0).i32(0).call("$parse_number");
+}
+
+pub(super) fn parse_float(a: &mut Asm) {
+    a.get(0).i32(1).call("$parse_number");
+}
+
+pub(super) fn to_bool(a: &mut Asm) {
+    a.get(0).call("$truthy").bool();
+}
+
+/// Push `1` when the byte at `p + i` (both locals) is an ASCII digit and
+/// `i < end`.
+fn is_digit_at(a: &mut Asm, p: u32, i: u32, end: u32) {
+    a.get(i).get(end).op(I::I32LtU).select_i32(
+        |a| {
+            a.get(p)
+                .get(i)
+                .op(I::I32Add)
+                .load8(0)
+                .i32(b'0' as i32)
+                .op(I::I32Sub)
+                .i32(9)
+                .op(I::I32LeU);
+        },
+        |a| {
+            a.i32(0);
+        },
+    );
+}
+
+/// Push `1` when the byte at `p + i` is `ch` and `i < end`.
+fn is_byte_at(a: &mut Asm, p: u32, i: u32, end: u32, ch: u8, fold_case: bool) {
+    a.get(i).get(end).op(I::I32LtU).select_i32(
+        |a| {
+            a.get(p).get(i).op(I::I32Add).load8(0);
+            if fold_case {
+                a.i32(0x20).op(I::I32Or);
+            }
+            a.i32(ch as i32).op(I::I32Eq);
+        },
+        |a| {
+            a.i32(0);
+        },
+    );
+}
+
+/// Advance `i` past a run of digits, adding the run length to `nd`.
+fn skip_digits(a: &mut Asm, p: u32, i: u32, end: u32, nd: u32) {
+    a.while_(
+        |a| is_digit_at(a, p, i, end),
+        |a| {
+            a.get(i).i32(1).op(I::I32Add).set(i);
+            a.get(nd).i32(1).op(I::I32Add).set(nd);
+        },
+    );
+}
+
+/// `$parse_number(s, allow_float) -> Result`
+pub(super) fn parse_number(a: &mut Asm) {
+    let (p, st, en, i, nd, ed, sb) = (
+        a.i32_local(),
+        a.i32_local(),
+        a.i32_local(),
+        a.i32_local(),
+        a.i32_local(),
+        a.i32_local(),
+        a.i32_local(),
+    );
+    a.w1(0).set(p);
+    a.i32(0).set(st);
+    a.w2(0).set(en);
+    a.while_(
+        |a| {
+            a.get(st).get(en).op(I::I32LtU).select_i32(
+                |a| {
+                    a.get(p).get(st).op(I::I32Add).load8(0).call("$is_space");
+                },
+                |a| {
+                    a.i32(0);
+                },
+            );
+        },
+        |a| {
+            a.get(st).i32(1).op(I::I32Add).set(st);
+        },
+    );
+    a.while_(
+        |a| {
+            a.get(en).get(st).op(I::I32GtU).select_i32(
+                |a| {
+                    a.get(p)
+                        .get(en)
+                        .op(I::I32Add)
+                        .i32(1)
+                        .op(I::I32Sub)
+                        .load8(0)
+                        .call("$is_space");
+                },
+                |a| {
+                    a.i32(0);
+                },
+            );
+        },
+        |a| {
+            a.get(en).i32(1).op(I::I32Sub).set(en);
+        },
+    );
+    a.get(st).set(i);
+    a.i32(0).set(nd);
+    is_byte_at(a, p, i, en, b'-', false);
+    is_byte_at(a, p, i, en, b'+', false);
+    a.op(I::I32Or).if_(|a| {
+        a.get(i).i32(1).op(I::I32Add).set(i);
+    });
+    skip_digits(a, p, i, en, nd);
+    a.get(1).if_(|a| {
+        is_byte_at(a, p, i, en, b'.', false);
+        a.if_(|a| {
+            a.get(i).i32(1).op(I::I32Add).set(i);
+            skip_digits(a, p, i, en, nd);
+        });
+        a.get(nd).if_(|a| {
+            is_byte_at(a, p, i, en, b'e', true);
+            a.if_(|a| {
+                a.get(i).i32(1).op(I::I32Add).set(i);
+                is_byte_at(a, p, i, en, b'-', false);
+                is_byte_at(a, p, i, en, b'+', false);
+                a.op(I::I32Or).if_(|a| {
+                    a.get(i).i32(1).op(I::I32Add).set(i);
+                });
+                a.i32(0).set(ed);
+                skip_digits(a, p, i, en, ed);
+                a.get(ed).op(I::I32Eqz).if_(|a| {
+                    a.i32(0).set(nd);
+                });
+            });
+        });
+    });
+    a.get(nd)
+        .i32(0)
+        .op(I::I32Ne)
+        .get(i)
+        .get(en)
+        .op(I::I32Eq)
+        .op(I::I32And)
+        .if_(|a| {
+            a.get(p)
+                .get(st)
+                .op(I::I32Add)
+                .get(en)
+                .get(st)
+                .op(I::I32Sub)
+                .call("$decimal");
+            a.number().call("$ok").ret();
+        });
+    a.call("$sb_new").set(sb);
+    a.get(sb).lit("cannot parse '").call("$sb_push");
+    a.get(sb).get(0).call("$sb_str");
+    a.get(sb).i32(b'\'' as i32).call("$sb_byte");
+    a.get(sb).call("$sb_finish").call("$err");
+}
+
+/// `$decimal(ptr, len) -> f64` for already-validated
+/// `[+-]digits[.digits][(e|E)[+-]digits]` text.
+///
+/// Up to 19 significant digits are kept in an i64; when that mantissa and
+/// the decimal exponent are both small the result is a single correctly
+/// rounded multiply or divide by an exact power of ten.
+pub(super) fn decimal(a: &mut Asm) {
+    let (p, end) = (0, 1);
+    let (i, neg, nd, exp, e2, esign, b) = (
+        a.i32_local(),
+        a.i32_local(),
+        a.i32_local(),
+        a.i32_local(),
+        a.i32_local(),
+        a.i32_local(),
+        a.i32_local(),
+    );
+    let (m, f, pw) = (a.i64_local(), a.f64_local(), a.f64_local());
+    // `end` becomes an absolute pointer; `i` walks from `p`.
+    a.get(p).get(end).op(I::I32Add).set(end);
+    a.get(p).set(i);
+    a.i32(0).set(exp);
+    a.i32(0).set(nd);
+    a.i64(0).set(m);
+    let at = |a: &mut Asm| {
+        a.get(i).get(end).op(I::I32LtU).select_i32(
+            |a| {
+                a.get(i).load8(0);
+            },
+            |a| {
+                a.i32(-1);
+            },
+        );
+    };
+    at(a);
+    a.tee(b).i32(b'-' as i32).op(I::I32Eq).set(neg);
+    a.get(b)
+        .i32(b'-' as i32)
+        .op(I::I32Eq)
+        .get(b)
+        .i32(b'+' as i32)
+        .op(I::I32Eq)
+        .op(I::I32Or)
+        .if_(|a| {
+            a.get(i).i32(1).op(I::I32Add).set(i);
+        });
+    // Integer digits, then fraction digits (which also lower the exponent).
+    for fraction in [false, true] {
+        if fraction {
+            at(a);
+            a.i32(b'.' as i32).op(I::I32Ne).if_(|a| {
+                a.i32(0).set(b);
+            });
+            at(a);
+            a.i32(b'.' as i32).op(I::I32Eq).if_(|a| {
+                a.get(i).i32(1).op(I::I32Add).set(i);
+            });
+        }
+        a.forever(|a| {
+            at(a);
+            a.i32(b'0' as i32)
+                .op(I::I32Sub)
+                .tee(b)
+                .i32(9)
+                .op(I::I32GtU)
+                .if_(|a| {
+                    a.brk();
+                });
+            a.get(nd).i32(19).op(I::I32LtS).if_else(
+                BlockType::Empty,
+                |a| {
+                    a.get(m)
+                        .i64(10)
+                        .op(I::I64Mul)
+                        .get(b)
+                        .op(I::I64ExtendI32U)
+                        .op(I::I64Add)
+                        .set(m);
+                    a.get(m).i64(0).op(I::I64Ne).if_(|a| {
+                        a.get(nd).i32(1).op(I::I32Add).set(nd);
+                    });
+                    if fraction {
+                        a.get(exp).i32(1).op(I::I32Sub).set(exp);
+                    }
+                },
+                |a| {
+                    if !fraction {
+                        a.get(exp).i32(1).op(I::I32Add).set(exp);
+                    }
+                },
+            );
+            a.get(i).i32(1).op(I::I32Add).set(i);
+        });
+    }
+    at(a);
+    a.i32(0x20)
+        .op(I::I32Or)
+        .i32(b'e' as i32)
+        .op(I::I32Eq)
+        .if_(|a| {
+            a.get(i).i32(1).op(I::I32Add).set(i);
+            a.i32(1).set(esign);
+            at(a);
+            a.tee(b).i32(b'-' as i32).op(I::I32Eq).if_(|a| {
+                a.i32(-1).set(esign);
+            });
+            a.get(b)
+                .i32(b'-' as i32)
+                .op(I::I32Eq)
+                .get(b)
+                .i32(b'+' as i32)
+                .op(I::I32Eq)
+                .op(I::I32Or)
+                .if_(|a| {
+                    a.get(i).i32(1).op(I::I32Add).set(i);
+                });
+            a.i32(0).set(e2);
+            a.forever(|a| {
+                at(a);
+                a.i32(b'0' as i32)
+                    .op(I::I32Sub)
+                    .tee(b)
+                    .i32(9)
+                    .op(I::I32GtU)
+                    .if_(|a| {
+                        a.brk();
+                    });
+                a.get(e2).i32(100_000).op(I::I32LtS).if_(|a| {
+                    a.get(e2).i32(10).op(I::I32Mul).get(b).op(I::I32Add).set(e2);
+                });
+                a.get(i).i32(1).op(I::I32Add).set(i);
+            });
+            a.get(exp)
+                .get(e2)
+                .get(esign)
+                .op(I::I32Mul)
+                .op(I::I32Add)
+                .set(exp);
+        });
+    a.get(m).op(I::F64ConvertI64U).set(f);
+    // Scale by 10^exp: exact powers of ten up to 1e22, in chunks beyond.
+    a.forever(|a| {
+        a.get(f)
+            .f64(0.0)
+            .op(I::F64Eq)
+            .get(exp)
+            .op(I::I32Eqz)
+            .op(I::I32Or)
+            .if_(|a| {
+                a.brk();
+            });
+        a.get(exp).i32(0).op(I::I32GtS).if_else(
+            BlockType::Empty,
+            |a| {
+                pow10(a, exp, pw);
+                a.get(f).get(pw).op(I::F64Mul).set(f);
+            },
+            |a| {
+                a.i32(0).get(exp).op(I::I32Sub).set(exp);
+                pow10(a, exp, pw);
+                a.i32(0).get(exp).op(I::I32Sub).set(exp);
+                a.get(f).get(pw).op(I::F64Div).set(f);
+            },
+        );
+        a.get(f).f64(f64::INFINITY).op(I::F64Eq).if_(|a| {
+            a.brk();
+        });
+    });
+    a.get(neg).if_(|a| {
+        a.get(f).op(I::F64Neg).set(f);
+    });
+    a.get(f);
+}
+
+/// `pw = 10^min(|k|, 22)` and move `k` toward zero by that many places.
+/// `k` must be positive.
+fn pow10(a: &mut Asm, k: u32, pw: u32) {
+    let j = a.i32_local();
+    a.f64(1.0).set(pw);
+    a.i32(0).set(j);
+    a.while_(
+        |a| {
+            a.get(j)
+                .get(k)
+                .op(I::I32LtS)
+                .get(j)
+                .i32(22)
+                .op(I::I32LtS)
+                .op(I::I32And);
+        },
+        |a| {
+            a.get(pw).f64(10.0).op(I::F64Mul).set(pw);
+            a.get(j).i32(1).op(I::I32Add).set(j);
+        },
+    );
+    a.get(k).get(j).op(I::I32Sub).set(k);
+}
+
+// ── core ─────────────────────────────────────────────────────────────────────
+
+/// `core.log(value)` — the display form goes to `env.log`.
+pub(super) fn log(a: &mut Asm) {
+    let s = a.i32_local();
+    a.get(0).call("$display").set(s);
+    a.w1(s).w2(s).import(IMPORT_LOG);
+    a.nil();
+}
+
+/// `core.assert(condition, message)` — traps with `message` when the
+/// condition is falsy.
+pub(super) fn assert(a: &mut Asm) {
+    a.get(0).call("$truthy").op(I::I32Eqz).if_(|a| {
+        a.tag(1).i32(TAG_STRING).op(I::I32Eq).if_else(
+            BlockType::Empty,
+            |a| {
+                a.w1(1).w2(1).import(IMPORT_TRAP);
+            },
+            |a| {
+                a.lit("assertion failed").import(IMPORT_TRAP);
+            },
+        );
+        a.op(I::Unreachable);
+    });
+    a.nil();
+}
+
+/// `core.type_of(value)`
+pub(super) fn type_of(a: &mut Asm) {
+    let t = a.i32_local();
+    a.tag(0).set(t);
+    a.get(t).i32(TAG_VARIANT).op(I::I32Eq).if_(|a| {
+        a.w1(0)
+            .i32(VARIANT_OK as i32)
+            .op(I::I32Eq)
+            .w1(0)
+            .i32(VARIANT_ERR as i32)
+            .op(I::I32Eq);
+        a.op(I::I32Or).if_(|a| {
+            a.str("result").ret();
+        });
+        a.str("variant").ret();
+    });
+    for (tag, name) in [
+        (TAG_NIL, "nil"),
+        (TAG_NUMBER, "number"),
+        (TAG_BOOL, "bool"),
+        (TAG_STRING, "string"),
+        (TAG_LIST, "list"),
+        (TAG_RECORD, "record"),
+        (TAG_LAMBDA, "function"),
+        (TAG_COLOR, "color"),
+    ] {
+        a.get(t).i32(tag).op(I::I32Eq).if_(|a| {
+            a.str(name).ret();
+        });
+    }
+    a.str("action");
+}
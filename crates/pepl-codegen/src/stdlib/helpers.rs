@@ -0,0 +1,735 @@
+//! Internal helpers shared by the stdlib modules (`$`-prefixed entries).
+//!
+//! These cover what every module needs: boxing numbers, truthiness and deep
+//! equality with the evaluator's semantics, invoking lambdas through the
+//! indirect table, growable byte/list builders, record lookup and the
+//! `display` formatting used by `string.from`, `convert.to_string`,
+//! `string.join` and interpolation of non-integer numbers.
+
+use wasm_encoder::{BlockType, Instruction as I};
+
+use crate::runtime::{RT_MEMCMP, RT_VAL_LIST, RT_VAL_RECORD, RT_VAL_STRING, RT_VAL_VARIANT};
+use crate::types::*;
+
+use super::asm::Asm;
+
+/// Bytes per record entry: `key_ptr`, `key_len`, `value_ptr`.
+pub(super) const ENTRY_SIZE: i32 = 12;
+
+// ── Values ───────────────────────────────────────────────────────────────────
+
+/// `$num(x: f64) -> value`
+pub(super) fn num(a: &mut Asm) {
+    let p = a.i32_local();
+    a.i32(VALUE_SIZE as i32).alloc().set(p);
+    a.get(p).i32(TAG_NUMBER).store(0);
+    a.get(p).get(0).f64_store(4);
+    a.get(p);
+}
+
+/// `$truthy(v) -> i32` — the evaluator's `is_truthy`.
+pub(super) fn truthy(a: &mut Asm) {
+    let t = a.i32_local();
+    a.tag(0).set(t);
+    a.get(t).i32(TAG_BOOL).op(I::I32Eq).if_(|a| {
+        a.w1(0).ret();
+    });
+    a.get(t).i32(TAG_NIL).op(I::I32Eq).if_(|a| {
+        a.i32(0).ret();
+    });
+    a.get(t).i32(TAG_NUMBER).op(I::I32Eq).if_(|a| {
+        a.num(0).f64(0.0).op(I::F64Ne).ret();
+    });
+    a.get(t).i32(TAG_STRING).op(I::I32Eq).if_(|a| {
+        a.w2(0).i32(0).op(I::I32Ne).ret();
+    });
+    a.i32(1);
+}
+
+/// `$ok(v) -> Ok(v)`
+pub(super) fn ok(a: &mut Asm) {
+    a.i32(VARIANT_OK as i32).get(0).rt(RT_VAL_VARIANT);
+}
+
+/// `$err(v) -> Err(v)`
+pub(super) fn err(a: &mut Asm) {
+    a.i32(VARIANT_ERR as i32).get(0).rt(RT_VAL_VARIANT);
+}
+
+/// `$index(v) -> i32` — an exact list index, or -1 when `v` is negative,
+/// fractional, NaN or not a number.
+pub(super) fn index(a: &mut Asm) {
+    let n = a.f64_local();
+    a.tag(0).i32(TAG_NUMBER).op(I::I32Ne).if_(|a| {
+        a.i32(-1).ret();
+    });
+    a.num(0).set(n);
+    a.get(n).f64(0.0).op(I::F64Ge).op(I::I32Eqz).if_(|a| {
+        a.i32(-1).ret();
+    });
+    a.get(n).f64(2147483648.0).op(I::F64Ge).if_(|a| {
+        a.i32(-1).ret();
+    });
+    a.get(n).op(I::F64Floor).get(n).op(I::F64Ne).if_(|a| {
+        a.i32(-1).ret();
+    });
+    a.get(n).op(I::I32TruncF64S);
+}
+
+/// `$count(v) -> i32` — a non-negative count: truncated, negatives and NaN
+/// become 0, huge values saturate.
+pub(super) fn count(a: &mut Asm) {
+    a.tag(0).i32(TAG_NUMBER).op(I::I32Ne).if_(|a| {
+        a.i32(0).ret();
+    });
+    a.num(0).f64(0.0).op(I::F64Gt).op(I::I32Eqz).if_(|a| {
+        a.i32(0).ret();
+    });
+    a.num(0).op(I::I32TruncSatF64S);
+}
+
+// ── Lambdas ──────────────────────────────────────────────────────────────────
+
+/// `$call1(f, x) -> value` — invoke a LAMBDA through the indirect table.
+pub(super) fn call1(a: &mut Asm) {
+    a.tag(0).i32(TAG_LAMBDA).op(I::I32Ne).if_(|a| {
+        a.nil().ret();
+    });
+    a.w2(0).get(1).w1(0).op(I::CallIndirect {
+        type_index: TYPE_I32X2_I32,
+        table_index: 0,
+    });
+}
+
+/// `$call2(f, x, y) -> value` — two-parameter lambdas take their arguments
+/// as a LIST.
+pub(super) fn call2(a: &mut Asm) {
+    let arr = a.i32_local();
+    a.i32(8).alloc().set(arr);
+    a.get(arr).get(1).store(0);
+    a.get(arr).get(2).store(4);
+    a.get(0).get(arr).i32(2).rt(RT_VAL_LIST).call("$call1");
+}
+
+// ── Builders ─────────────────────────────────────────────────────────────────
+//
+// A builder is a 12-byte cell `[buf, len, cap]`; `buf` is reallocated with
+// doubling when it fills.  Byte builders finish as STRINGs, pointer builders
+// as LISTs.
+
+/// `$sb_new() -> builder`
+pub(super) fn sb_new(a: &mut Asm) {
+    let sb = a.i32_local();
+    a.i32(12).alloc().set(sb);
+    a.get(sb).i32(32).alloc().store(0);
+    a.get(sb).i32(0).store(4);
+    a.get(sb).i32(32).store(8);
+    a.get(sb);
+}
+
+/// `$sb_reserve(sb, n) -> ptr` — grow by `n` bytes, return where to write.
+pub(super) fn sb_reserve(a: &mut Asm) {
+    let (len, need, cap, buf) = (a.i32_local(), a.i32_local(), a.i32_local(), a.i32_local());
+    a.get(0).load(4).tee(len).get(1).op(I::I32Add).set(need);
+    a.get(need).get(0).load(8).op(I::I32GtU).if_(|a| {
+        // cap = max(cap * 2, need)
+        a.get(0).load(8).i32(1).op(I::I32Shl).set(cap);
+        a.get(need)
+            .get(cap)
+            .get(need)
+            .get(cap)
+            .op(I::I32GtU)
+            .op(I::Select)
+            .set(cap);
+        a.get(cap).alloc().set(buf);
+        a.get(buf).get(0).load(0).get(len).op(I::MemoryCopy {
+            src_mem: 0,
+            dst_mem: 0,
+        });
+        a.get(0).get(buf).store(0);
+        a.get(0).get(cap).store(8);
+    });
+    a.get(0).get(need).store(4);
+    a.get(0).load(0).get(len).op(I::I32Add);
+}
+
+/// `$sb_push(sb, ptr, len)`
+pub(super) fn sb_push(a: &mut Asm) {
+    a.get(0).get(2).call("$sb_reserve");
+    a.get(1).get(2).op(I::MemoryCopy {
+        src_mem: 0,
+        dst_mem: 0,
+    });
+}
+
+/// `$sb_str(sb, s)` — append a STRING value's bytes.
+pub(super) fn sb_str(a: &mut Asm) {
+    a.get(0).w1(1).w2(1).call("$sb_push");
+}
+
+/// `$sb_byte(sb, b)`
+pub(super) fn sb_byte(a: &mut Asm) {
+    a.get(0).i32(1).call("$sb_reserve").get(1).store8(0);
+}
+
+/// `$sb_finish(sb) -> STRING`
+pub(super) fn sb_finish(a: &mut Asm) {
+    a.get(0).load(0).get(0).load(4).rt(RT_VAL_STRING);
+}
+
+/// `$lb_push(lb, v)`
+pub(super) fn lb_push(a: &mut Asm) {
+    a.get(0).i32(4).call("$sb_reserve").get(1).store(0);
+}
+
+/// `$lb_finish(lb) -> LIST`
+pub(super) fn lb_finish(a: &mut Asm) {
+    a.get(0)
+        .load(0)
+        .get(0)
+        .load(4)
+        .i32(2)
+        .op(I::I32ShrU)
+        .rt(RT_VAL_LIST);
+}
+
+/// `$list_alloc(n) -> LIST` with `n` uninitialised slots.
+pub(super) fn list_alloc(a: &mut Asm) {
+    a.get(0).i32(4).op(I::I32Mul).alloc().get(0).rt(RT_VAL_LIST);
+}
+
+// ── Equality ─────────────────────────────────────────────────────────────────
+
+/// `$deep_eq(a, b) -> i32` — the evaluator's `structural_eq`.
+pub(super) fn deep_eq(a: &mut Asm) {
+    let (t, n, i, x, y, e, found) = (
+        a.i32_local(),
+        a.i32_local(),
+        a.i32_local(),
+        a.i32_local(),
+        a.i32_local(),
+        a.i32_local(),
+        a.i32_local(),
+    );
+    a.tag(0).tee(t).tag(1).op(I::I32Ne).if_(|a| {
+        a.i32(0).ret();
+    });
+    a.get(t).i32(TAG_NUMBER).op(I::I32Eq).if_(|a| {
+        a.num(0).num(1).op(I::F64Eq).ret();
+    });
+    a.get(t).i32(TAG_NIL).op(I::I32Eq).if_(|a| {
+        a.i32(1).ret();
+    });
+    a.get(t).i32(TAG_STRING).op(I::I32Eq).if_(|a| {
+        a.w2(0).w2(1).op(I::I32Ne).if_(|a| {
+            a.i32(0).ret();
+        });
+        a.w1(0).w1(1).w2(0).rt(RT_MEMCMP).ret();
+    });
+    a.get(t).i32(TAG_LIST).op(I::I32Eq).if_(|a| {
+        a.w2(0).tee(n).w2(1).op(I::I32Ne).if_(|a| {
+            a.i32(0).ret();
+        });
+        a.w1(0).set(x);
+        a.w1(1).set(y);
+        a.for_(i, n, |a| {
+            a.slot(x, i)
+                .load(0)
+                .slot(y, i)
+                .load(0)
+                .call("$deep_eq")
+                .op(I::I32Eqz)
+                .if_(|a| {
+                    a.i32(0).ret();
+                });
+        });
+        a.i32(1).ret();
+    });
+    a.get(t).i32(TAG_RECORD).op(I::I32Eq).if_(|a| {
+        a.w2(0).tee(n).w2(1).op(I::I32Ne).if_(|a| {
+            a.i32(0).ret();
+        });
+        a.for_(i, n, |a| {
+            a.w1(0)
+                .get(i)
+                .i32(ENTRY_SIZE)
+                .op(I::I32Mul)
+                .op(I::I32Add)
+                .set(e);
+            a.get(1)
+                .get(e)
+                .load(0)
+                .get(e)
+                .load(4)
+                .call("$record_find")
+                .tee(found);
+            a.op(I::I32Eqz).if_(|a| {
+                a.i32(0).ret();
+            });
+            a.get(e)
+                .load(8)
+                .get(found)
+                .load(8)
+                .call("$deep_eq")
+                .op(I::I32Eqz)
+                .if_(|a| {
+                    a.i32(0).ret();
+                });
+        });
+        a.i32(1).ret();
+    });
+    a.get(t).i32(TAG_VARIANT).op(I::I32Eq).if_(|a| {
+        a.w1(0).w1(1).op(I::I32Ne).if_(|a| {
+            a.i32(0).ret();
+        });
+        a.w2(0).set(x);
+        a.w2(1).set(y);
+        a.get(x).get(y).op(I::I32Eq).if_(|a| {
+            a.i32(1).ret();
+        });
+        a.get(x)
+            .op(I::I32Eqz)
+            .get(y)
+            .op(I::I32Eqz)
+            .op(I::I32Or)
+            .if_(|a| {
+                a.i32(0).ret();
+            });
+        a.get(x).get(y).call("$deep_eq").ret();
+    });
+    // Functions never compare equal; the rest compare their first word.
+    a.get(t).i32(TAG_LAMBDA).op(I::I32Eq).if_(|a| {
+        a.i32(0).ret();
+    });
+    a.w1(0).w1(1).op(I::I32Eq);
+}
+
+// ── Records ──────────────────────────────────────────────────────────────────
+
+/// `$record_find(rec, key_ptr, key_len) -> entry ptr` (0 when absent or
+/// when `rec` is not a record).
+pub(super) fn record_find(a: &mut Asm) {
+    let (n, i, e) = (a.i32_local(), a.i32_local(), a.i32_local());
+    a.tag(0).i32(TAG_RECORD).op(I::I32Ne).if_(|a| {
+        a.i32(0).ret();
+    });
+    a.w2(0).set(n);
+    a.for_(i, n, |a| {
+        a.w1(0)
+            .get(i)
+            .i32(ENTRY_SIZE)
+            .op(I::I32Mul)
+            .op(I::I32Add)
+            .set(e);
+        a.get(e).load(4).get(2).op(I::I32Eq).if_(|a| {
+            a.get(e).load(0).get(1).get(2).rt(RT_MEMCMP).if_(|a| {
+                a.get(e).ret();
+            });
+        });
+    });
+    a.i32(0);
+}
+
+/// `$key_lt(entry_a, entry_b) -> i32` — bytewise key order, as `BTreeMap`.
+pub(super) fn key_lt(a: &mut Asm) {
+    let (n, i, x, y) = (a.i32_local(), a.i32_local(), a.i32_local(), a.i32_local());
+    a.get(0)
+        .load(4)
+        .get(1)
+        .load(4)
+        .get(0)
+        .load(4)
+        .get(1)
+        .load(4)
+        .op(I::I32LtU);
+    a.op(I::Select).set(n);
+    a.for_(i, n, |a| {
+        a.get(0).load(0).get(i).op(I::I32Add).load8(0).set(x);
+        a.get(1).load(0).get(i).op(I::I32Add).load8(0).set(y);
+        a.get(x).get(y).op(I::I32Ne).if_(|a| {
+            a.get(x).get(y).op(I::I32LtU).ret();
+        });
+    });
+    a.get(0).load(4).get(1).load(4).op(I::I32LtU);
+}
+
+/// `$sorted_entries(rec) -> ptr` — an array of `rec.w2` entry pointers in
+/// key order (stable insertion sort).
+pub(super) fn sorted_entries(a: &mut Asm) {
+    let (n, arr, i, j, x) = (
+        a.i32_local(),
+        a.i32_local(),
+        a.i32_local(),
+        a.i32_local(),
+        a.i32_local(),
+    );
+    a.w2(0).set(n);
+    a.get(n).i32(4).op(I::I32Mul).alloc().set(arr);
+    a.for_(i, n, |a| {
+        a.slot(arr, i)
+            .w1(0)
+            .get(i)
+            .i32(ENTRY_SIZE)
+            .op(I::I32Mul)
+            .op(I::I32Add)
+            .store(0);
+    });
+    a.for_(i, n, |a| {
+        a.slot(arr, i).load(0).set(x);
+        a.get(i).i32(1).op(I::I32Sub).set(j);
+        a.while_(
+            |a| {
+                a.get(j).i32(0).op(I::I32GeS).select_i32(
+                    |a| {
+                        a.get(x).slot(arr, j).load(0).call("$key_lt");
+                    },
+                    |a| {
+                        a.i32(0);
+                    },
+                );
+            },
+            |a| {
+                a.slot(arr, j)
+                    .i32(4)
+                    .op(I::I32Add)
+                    .slot(arr, j)
+                    .load(0)
+                    .store(0);
+                a.get(j).i32(1).op(I::I32Sub).set(j);
+            },
+        );
+        a.slot(arr, j).i32(4).op(I::I32Add).get(x).store(0);
+    });
+    a.get(arr);
+}
+
+// ── Display ──────────────────────────────────────────────────────────────────
+
+/// `$sb_u64(sb, n: i64, min_digits)` — append `n` (unsigned) in decimal,
+/// zero-padded to at least `min_digits`.
+pub(super) fn sb_u64(a: &mut Asm) {
+    let (buf, k) = (a.i32_local(), a.i32_local());
+    a.i32(24).alloc().set(buf);
+    a.i32(0).set(k);
+    a.forever(|a| {
+        a.get(buf).i32(23).op(I::I32Add).get(k).op(I::I32Sub);
+        a.get(1)
+            .i64(10)
+            .op(I::I64RemU)
+            .op(I::I32WrapI64)
+            .i32(b'0' as i32)
+            .op(I::I32Add)
+            .store8(0);
+        a.get(1).i64(10).op(I::I64DivU).set(1);
+        a.get(k).i32(1).op(I::I32Add).set(k);
+        a.get(k).i32(24).op(I::I32GeS).if_(|a| {
+            a.brk();
+        });
+        a.get(1)
+            .op(I::I64Eqz)
+            .get(k)
+            .get(2)
+            .op(I::I32GeS)
+            .op(I::I32And)
+            .if_(|a| {
+                a.brk();
+            });
+    });
+    a.get(0)
+        .get(buf)
+        .i32(24)
+        .op(I::I32Add)
+        .get(k)
+        .op(I::I32Sub)
+        .get(k)
+        .call("$sb_push");
+}
+
+/// `$number_into(sb, x: f64)` — Rust's `Display` for the numbers the
+/// evaluator prints: integers without a fraction (saturating to i64),
+/// others with the shortest fraction that reads back to the same f64.
+pub(super) fn number_into(a: &mut Asm) {
+    let x = 1;
+    let (ip, frac, p, m, d) = (
+        a.f64_local(),
+        a.f64_local(),
+        a.f64_local(),
+        a.f64_local(),
+        a.i32_local(),
+    );
+    let (n, pw) = (a.i64_local(), a.i64_local());
+    a.get(x).get(x).op(I::F64Ne).if_(|a| {
+        a.get(0).lit("NaN").call("$sb_push").ret();
+    });
+    a.get(x).f64(f64::INFINITY).op(I::F64Eq).if_(|a| {
+        a.get(0).lit("inf").call("$sb_push").ret();
+    });
+    a.get(x).f64(f64::NEG_INFINITY).op(I::F64Eq).if_(|a| {
+        a.get(0).lit("-inf").call("$sb_push").ret();
+    });
+    a.get(x).op(I::F64Floor).get(x).op(I::F64Eq).if_(|a| {
+        a.get(x).op(I::I64TruncSatF64S).set(n);
+        a.get(n).i64(0).op(I::I64LtS).if_(|a| {
+            a.get(0).i32(b'-' as i32).call("$sb_byte");
+            a.i64(0).get(n).op(I::I64Sub).set(n);
+        });
+        a.get(0).get(n).i32(1).call("$sb_u64").ret();
+    });
+    a.get(x).f64(0.0).op(I::F64Lt).if_(|a| {
+        a.get(0).i32(b'-' as i32).call("$sb_byte");
+        a.get(x).op(I::F64Neg).set(x);
+    });
+    a.get(x).op(I::F64Trunc).set(ip);
+    a.get(x).get(ip).op(I::F64Sub).set(frac);
+    // Find the fewest fraction digits that reproduce x.
+    a.f64(1.0).set(p);
+    a.i32(0).set(d);
+    a.forever(|a| {
+        a.get(d).i32(1).op(I::I32Add).set(d);
+        a.get(p).f64(10.0).op(I::F64Mul).set(p);
+        a.get(frac).get(p).op(I::F64Mul).op(I::F64Nearest).set(m);
+        a.get(m).f64(9007199254740992.0).op(I::F64Ge).if_(|a| {
+            a.brk();
+        });
+        a.get(ip)
+            .get(m)
+            .get(p)
+            .op(I::F64Div)
+            .op(I::F64Add)
+            .get(x)
+            .op(I::F64Eq)
+            .if_(|a| {
+                a.brk();
+            });
+        a.get(d).i32(22).op(I::I32GeS).if_(|a| {
+            a.brk();
+        });
+    });
+    // A fraction that rounded up to a whole unit carries into the integer.
+    a.get(m).get(p).op(I::F64Ge).if_(|a| {
+        a.get(m).get(p).op(I::F64Sub).set(m);
+        a.get(ip).f64(1.0).op(I::F64Add).set(ip);
+    });
+    a.get(0)
+        .get(ip)
+        .op(I::I64TruncSatF64U)
+        .i32(1)
+        .call("$sb_u64");
+    a.get(0).i32(b'.' as i32).call("$sb_byte");
+    a.get(m).op(I::I64TruncSatF64U).set(pw);
+    a.get(0).get(pw).get(d).call("$sb_u64");
+}
+
+/// `$display_into(sb, v)` — append `value_to_display_string(v)`.
+pub(super) fn display_into(a: &mut Asm) {
+    let v = 1;
+    let (t, n, i, arr) = (a.i32_local(), a.i32_local(), a.i32_local(), a.i32_local());
+    a.tag(v).set(t);
+    a.get(t).i32(TAG_STRING).op(I::I32Eq).if_(|a| {
+        a.get(0).get(v).call("$sb_str").ret();
+    });
+    a.get(t).i32(TAG_NUMBER).op(I::I32Eq).if_(|a| {
+        a.get(0).num(v).call("$number_into").ret();
+    });
+    a.get(t).i32(TAG_BOOL).op(I::I32Eq).if_(|a| {
+        a.w1(v).if_else(
+            BlockType::Empty,
+            |a| {
+                a.get(0).lit("true").call("$sb_push");
+            },
+            |a| {
+                a.get(0).lit("false").call("$sb_push");
+            },
+        );
+        a.ret();
+    });
+    a.get(t).i32(TAG_NIL).op(I::I32Eq).if_(|a| {
+        a.get(0).lit("nil").call("$sb_push").ret();
+    });
+    a.get(t).i32(TAG_LIST).op(I::I32Eq).if_(|a| {
+        a.w1(v).set(arr);
+        a.w2(v).set(n);
+        a.get(0).i32(b'[' as i32).call("$sb_byte");
+        a.for_(i, n, |a| {
+            a.get(i).if_(|a| {
+                a.get(0).lit(", ").call("$sb_push");
+            });
+            a.get(0).slot(arr, i).load(0).call("$display_into");
+        });
+        a.get(0).i32(b']' as i32).call("$sb_byte").ret();
+    });
+    a.get(t).i32(TAG_RECORD).op(I::I32Eq).if_(|a| {
+        a.get(v).call("$sorted_entries").set(arr);
+        a.w2(v).set(n);
+        a.get(0).lit("{ ").call("$sb_push");
+        a.for_(i, n, |a| {
+            a.get(i).if_(|a| {
+                a.get(0).lit(", ").call("$sb_push");
+            });
+            a.get(0)
+                .slot(arr, i)
+                .load(0)
+                .load(0)
+                .slot(arr, i)
+                .load(0)
+                .load(4)
+                .call("$sb_push");
+            a.get(0).lit(": ").call("$sb_push");
+            a.get(0).slot(arr, i).load(0).load(8).call("$display_into");
+        });
+        a.get(0).lit(" }").call("$sb_push").ret();
+    });
+    a.get(t).i32(TAG_VARIANT).op(I::I32Eq).if_(|a| {
+        for (id, open) in [(VARIANT_OK, "Ok("), (VARIANT_ERR, "Err(")] {
+            a.w1(v).i32(id as i32).op(I::I32Eq).if_(|a| {
+                a.get(0).lit(open).call("$sb_push");
+                a.get(0).w2(v).call("$display_into");
+                a.get(0).i32(b')' as i32).call("$sb_byte").ret();
+            });
+        }
+    });
+    a.get(t).i32(TAG_LAMBDA).op(I::I32Eq).if_(|a| {
+        a.get(0).lit("<function>").call("$sb_push").ret();
+    });
+    a.get(0).lit("[value]").call("$sb_push");
+}
+
+/// `$display(v) -> STRING`
+pub(super) fn display(a: &mut Asm) {
+    let sb = a.i32_local();
+    a.tag(0).i32(TAG_STRING).op(I::I32Eq).if_(|a| {
+        a.get(0).ret();
+    });
+    a.call("$sb_new").set(sb);
+    a.get(sb).get(0).call("$display_into");
+    a.get(sb).call("$sb_finish");
+}
+
+// ── Text ─────────────────────────────────────────────────────────────────────
+
+/// `$utf8_len(lead_byte) -> i32`
+pub(super) fn utf8_len(a: &mut Asm) {
+    a.get(0).i32(0x80).op(I::I32LtU).if_(|a| {
+        a.i32(1).ret();
+    });
+    a.get(0).i32(0xE0).op(I::I32LtU).if_(|a| {
+        a.i32(2).ret();
+    });
+    a.get(0).i32(0xF0).op(I::I32LtU).if_(|a| {
+        a.i32(3).ret();
+    });
+    a.i32(4);
+}
+
+/// `$char_count(ptr, len) -> i32` — count of UTF-8 scalar values.
+pub(super) fn char_count(a: &mut Asm) {
+    let (i, c) = (a.i32_local(), a.i32_local());
+    a.i32(0).set(c);
+    a.for_(i, 1, |a| {
+        a.get(0)
+            .get(i)
+            .op(I::I32Add)
+            .load8(0)
+            .i32(0xC0)
+            .op(I::I32And)
+            .i32(0x80)
+            .op(I::I32Ne);
+        a.get(c).op(I::I32Add).set(c);
+    });
+    a.get(c);
+}
+
+/// `$char_offset(ptr, len, n) -> i32` — byte offset of char `n`, clamped
+/// to `len`.
+pub(super) fn char_offset(a: &mut Asm) {
+    let (i, k) = (a.i32_local(), a.i32_local());
+    a.i32(0).set(k);
+    a.for_(i, 1, |a| {
+        a.get(0)
+            .get(i)
+            .op(I::I32Add)
+            .load8(0)
+            .i32(0xC0)
+            .op(I::I32And)
+            .i32(0x80)
+            .op(I::I32Ne);
+        a.if_(|a| {
+            a.get(k).get(2).op(I::I32GeS).if_(|a| {
+                a.get(i).ret();
+            });
+            a.get(k).i32(1).op(I::I32Add).set(k);
+        });
+    });
+    a.get(1);
+}
+
+/// `$substr(s, from, to) -> STRING` — a view of bytes `from..to`.
+pub(super) fn substr(a: &mut Asm) {
+    a.w1(0)
+        .get(1)
+        .op(I::I32Add)
+        .get(2)
+        .get(1)
+        .op(I::I32Sub)
+        .rt(RT_VAL_STRING);
+}
+
+/// `$str_find(s, needle, from) -> i32` — byte index of the first match at or
+/// after `from`, or -1.
+pub(super) fn str_find(a: &mut Asm) {
+    let i = a.i32_local();
+    a.get(2).set(i);
+    a.while_(
+        |a| {
+            a.get(i).w2(1).op(I::I32Add).w2(0).op(I::I32LeU);
+        },
+        |a| {
+            a.w1(0)
+                .get(i)
+                .op(I::I32Add)
+                .w1(1)
+                .w2(1)
+                .rt(RT_MEMCMP)
+                .if_(|a| {
+                    a.get(i).ret();
+                });
+            a.get(i).i32(1).op(I::I32Add).set(i);
+        },
+    );
+    a.i32(-1);
+}
+
+/// `$is_space(b) -> i32` — ASCII whitespace, as `u8::is_ascii_whitespace`
+/// plus vertical tab.
+pub(super) fn is_space(a: &mut Asm) {
+    a.get(0).i32(b' ' as i32).op(I::I32Eq);
+    a.get(0).i32(9).op(I::I32Sub).i32(4).op(I::I32LeU);
+    a.op(I::I32Or);
+}
+
+/// `$record_entries(n) -> ptr` — allocate `n` record entries.
+pub(super) fn record_entries(a: &mut Asm) {
+    a.get(0).i32(ENTRY_SIZE).op(I::I32Mul).alloc();
+}
+
+/// `$record_copy(rec, extra) -> RECORD` — a copy of `rec` with `extra`
+/// zeroed entry slots appended to its count.
+pub(super) fn record_copy(a: &mut Asm) {
+    let (n, out) = (a.i32_local(), a.i32_local());
+    a.w2(0).set(n);
+    a.get(n)
+        .get(1)
+        .op(I::I32Add)
+        .call("$record_entries")
+        .set(out);
+    a.get(out)
+        .w1(0)
+        .get(n)
+        .i32(ENTRY_SIZE)
+        .op(I::I32Mul)
+        .op(I::MemoryCopy {
+            src_mem: 0,
+            dst_mem: 0,
+        });
+    a.get(out).get(n).get(1).op(I::I32Add).rt(RT_VAL_RECORD);
+}
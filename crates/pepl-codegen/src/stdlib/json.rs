@@ -0,0 +1,678 @@
+//! `json.*` — compact serialisation and a strict RFC 8259 parser.
+//!
+//! `stringify` writes records with their keys sorted (the evaluator's
+//! record order), non-finite numbers as `null`, and `Ok`/`Err` as their
+//! payload.  `parse` maps objects to records (last duplicate key wins),
+//! arrays to lists and `null` to nil; any syntax error is
+//! `Err("invalid JSON")`.
+//!
+//! The parser walks a cursor cell `[pos, end]` of absolute byte pointers;
+//! every `$json_*` reader returns `0` on a syntax error.
+
+use wasm_encoder::{BlockType, Instruction as I};
+
+use crate::runtime::{RT_MEMCMP, RT_VAL_RECORD};
+use crate::types::*;
+
+use super::asm::Asm;
+use super::helpers::ENTRY_SIZE;
+
+pub(super) fn stringify(a: &mut Asm) {
+    let sb = a.i32_local();
+    a.call("$sb_new").set(sb);
+    a.get(sb).get(0).call("$json_into");
+    a.get(sb).call("$sb_finish");
+}
+
+/// `$json_into(sb, v)`
+pub(super) fn json_into(a: &mut Asm) {
+    let v = 1;
+    let (t, n, i, arr) = (a.i32_local(), a.i32_local(), a.i32_local(), a.i32_local());
+    a.tag(v).set(t);
+    a.get(t).i32(TAG_NUMBER).op(I::I32Eq).if_(|a| {
+        // Finite iff x - x == 0.
+        a.num(v)
+            .num(v)
+            .op(I::F64Sub)
+            .f64(0.0)
+            .op(I::F64Eq)
+            .if_(|a| {
+                a.get(0).num(v).call("$number_into").ret();
+            });
+    });
+    a.get(t).i32(TAG_STRING).op(I::I32Eq).if_(|a| {
+        a.get(0).w1(v).w2(v).call("$json_quote").ret();
+    });
+    a.get(t).i32(TAG_BOOL).op(I::I32Eq).if_(|a| {
+        a.w1(v).if_else(
+            BlockType::Empty,
+            |a| {
+                a.get(0).lit("true").call("$sb_push");
+            },
+            |a| {
+                a.get(0).lit("false").call("$sb_push");
+            },
+        );
+        a.ret();
+    });
+    a.get(t).i32(TAG_LIST).op(I::I32Eq).if_(|a| {
+        a.w1(v).set(arr);
+        a.w2(v).set(n);
+        a.get(0).i32(b'[' as i32).call("$sb_byte");
+        a.for_(i, n, |a| {
+            a.get(i).if_(|a| {
+                a.get(0).i32(b',' as i32).call("$sb_byte");
+            });
+            a.get(0).slot(arr, i).load(0).call("$json_into");
+        });
+        a.get(0).i32(b']' as i32).call("$sb_byte").ret();
+    });
+    a.get(t).i32(TAG_RECORD).op(I::I32Eq).if_(|a| {
+        a.get(v).call("$sorted_entries").set(arr);
+        a.w2(v).set(n);
+        a.get(0).i32(b'{' as i32).call("$sb_byte");
+        a.for_(i, n, |a| {
+            a.get(i).if_(|a| {
+                a.get(0).i32(b',' as i32).call("$sb_byte");
+            });
+            a.get(0)
+                .slot(arr, i)
+                .load(0)
+                .load(0)
+                .slot(arr, i)
+                .load(0)
+                .load(4)
+                .call("$json_quote");
+            a.get(0).i32(b':' as i32).call("$sb_byte");
+            a.get(0).slot(arr, i).load(0).load(8).call("$json_into");
+        });
+        a.get(0).i32(b'}' as i32).call("$sb_byte").ret();
+    });
+    a.get(t).i32(TAG_VARIANT).op(I::I32Eq).if_(|a| {
+        a.w1(v)
+            .i32(VARIANT_OK as i32)
+            .op(I::I32Eq)
+            .w1(v)
+            .i32(VARIANT_ERR as i32)
+            .op(I::I32Eq);
+        a.op(I::I32Or).if_(|a| {
+            a.get(0).w2(v).call("$json_into").ret();
+        });
+    });
+    a.get(0).lit("null").call("$sb_push");
+}
+
+/// `$json_quote(sb, ptr, len)` — append a quoted, escaped JSON string.
+pub(super) fn json_quote(a: &mut Asm) {
+    let (sb, p, n) = (0, 1, 2);
+    let (i, b, esc) = (a.i32_local(), a.i32_local(), a.i32_local());
+    a.get(sb).i32(b'"' as i32).call("$sb_byte");
+    a.for_(i, n, |a| {
+        a.get(p).get(i).op(I::I32Add).load8(0).set(b);
+        a.i32(0).set(esc);
+        for (ch, out) in [
+            (b'"', b'"'),
+            (b'\\', b'\\'),
+            (b'\n', b'n'),
+            (b'\r', b'r'),
+            (b'\t', b't'),
+            (0x08, b'b'),
+            (0x0c, b'f'),
+        ] {
+            a.get(b).i32(ch as i32).op(I::I32Eq).if_(|a| {
+                a.i32(out as i32).set(esc);
+            });
+        }
+        a.get(esc).if_else(
+            BlockType::Empty,
+            |a| {
+                a.get(sb).i32(b'\\' as i32).call("$sb_byte");
+                a.get(sb).get(esc).call("$sb_byte");
+            },
+            |a| {
+                a.get(b).i32(0x20).op(I::I32LtU).if_else(
+                    BlockType::Empty,
+                    |a| {
+                        a.get(sb).lit("\\u00").call("$sb_push");
+                        a.get(sb)
+                            .lit(HEX)
+                            .op(I::Drop)
+                            .get(b)
+                            .i32(4)
+                            .op(I::I32ShrU)
+                            .op(I::I32Add);
+                        a.load8(0).call("$sb_byte");
+                        a.get(sb)
+                            .lit(HEX)
+                            .op(I::Drop)
+                            .get(b)
+                            .i32(15)
+                            .op(I::I32And)
+                            .op(I::I32Add);
+                        a.load8(0).call("$sb_byte");
+                    },
+                    |a| {
+                        a.get(sb).get(b).call("$sb_byte");
+                    },
+                );
+            },
+        );
+    });
+    a.get(sb).i32(b'"' as i32).call("$sb_byte");
+}
+
+const HEX: &str = "0123456789abcdef";
+
+pub(super) fn parse(a: &mut Asm) {
+    let (cur, v) = (a.i32_local(), a.i32_local());
+    a.i32(8).alloc().set(cur);
+    a.get(cur).w1(0).store(0);
+    a.get(cur).w1(0).w2(0).op(I::I32Add).store(4);
+    a.get(cur).call("$json_value").tee(v).if_(|a| {
+        a.get(cur).call("$json_peek").i32(-1).op(I::I32Eq).if_(|a| {
+            a.get(v).call("$ok").ret();
+        });
+    });
+    a.str("invalid JSON").call("$err");
+}
+
+/// `$json_peek(cur) -> byte` — skip whitespace; `-1` at the end.
+pub(super) fn json_peek(a: &mut Asm) {
+    let b = a.i32_local();
+    a.forever(|a| {
+        a.get(0).load(0).get(0).load(4).op(I::I32GeU).if_(|a| {
+            a.i32(-1).ret();
+        });
+        a.get(0).load(0).load8(0).set(b);
+        a.get(b).i32(b' ' as i32).op(I::I32Eq);
+        a.get(b).i32(b'\t' as i32).op(I::I32Eq).op(I::I32Or);
+        a.get(b).i32(b'\n' as i32).op(I::I32Eq).op(I::I32Or);
+        a.get(b).i32(b'\r' as i32).op(I::I32Eq).op(I::I32Or);
+        a.op(I::I32Eqz).if_(|a| {
+            a.get(b).ret();
+        });
+        advance(a, 0);
+    });
+    a.i32(-1);
+}
+
+/// `cur.pos += 1`
+fn advance(a: &mut Asm, cur: u32) {
+    a.get(cur).get(cur).load(0).i32(1).op(I::I32Add).store(0);
+}
+
+/// Push the byte at the cursor, or `-1` at the end (no whitespace skip).
+fn current(a: &mut Asm, cur: u32) {
+    a.get(cur)
+        .load(0)
+        .get(cur)
+        .load(4)
+        .op(I::I32LtU)
+        .select_i32(
+            |a| {
+                a.get(cur).load(0).load8(0);
+            },
+            |a| {
+                a.i32(-1);
+            },
+        );
+}
+
+/// `$json_value(cur) -> value | 0`
+pub(super) fn json_value(a: &mut Asm) {
+    let cur = 0;
+    let (c, b, k, v, n, i, e) = (
+        a.i32_local(),
+        a.i32_local(),
+        a.i32_local(),
+        a.i32_local(),
+        a.i32_local(),
+        a.i32_local(),
+        a.i32_local(),
+    );
+    a.get(cur).call("$json_peek").set(c);
+    a.get(c).i32(b'"' as i32).op(I::I32Eq).if_(|a| {
+        a.get(cur).call("$json_string").ret();
+    });
+    for (lit, first) in [("true", b't'), ("false", b'f'), ("null", b'n')] {
+        a.get(c).i32(first as i32).op(I::I32Eq).if_(|a| {
+            a.get(cur)
+                .load(4)
+                .get(cur)
+                .load(0)
+                .op(I::I32Sub)
+                .i32(lit.len() as i32)
+                .op(I::I32LtU);
+            a.if_(|a| {
+                a.i32(0).ret();
+            });
+            a.get(cur)
+                .load(0)
+                .lit(lit)
+                .rt(RT_MEMCMP)
+                .op(I::I32Eqz)
+                .if_(|a| {
+                    a.i32(0).ret();
+                });
+            a.get(cur)
+                .get(cur)
+                .load(0)
+                .i32(lit.len() as i32)
+                .op(I::I32Add)
+                .store(0);
+            match lit {
+                "true" => a.i32(1).bool(),
+                "false" => a.i32(0).bool(),
+                _ => a.nil(),
+            };
+            a.ret();
+        });
+    }
+    a.get(c).i32(b'[' as i32).op(I::I32Eq).if_(|a| {
+        advance(a, cur);
+        a.call("$sb_new").set(b);
+        a.get(cur)
+            .call("$json_peek")
+            .i32(b']' as i32)
+            .op(I::I32Eq)
+            .if_(|a| {
+                advance(a, cur);
+                a.get(b).call("$lb_finish").ret();
+            });
+        a.forever(|a| {
+            a.get(cur)
+                .call("$json_value")
+                .tee(v)
+                .op(I::I32Eqz)
+                .if_(|a| {
+                    a.i32(0).ret();
+                });
+            a.get(b).get(v).call("$lb_push");
+            a.get(cur).call("$json_peek").set(c);
+            advance(a, cur);
+            a.get(c).i32(b']' as i32).op(I::I32Eq).if_(|a| {
+                a.get(b).call("$lb_finish").ret();
+            });
+            a.get(c).i32(b',' as i32).op(I::I32Ne).if_(|a| {
+                a.i32(0).ret();
+            });
+        });
+    });
+    a.get(c).i32(b'{' as i32).op(I::I32Eq).if_(|a| {
+        advance(a, cur);
+        // Entries accumulate in a byte builder, ENTRY_SIZE bytes each.
+        a.call("$sb_new").set(b);
+        a.get(cur)
+            .call("$json_peek")
+            .i32(b'}' as i32)
+            .op(I::I32Eq)
+            .if_(|a| {
+                advance(a, cur);
+                a.get(b).load(0).i32(0).rt(RT_VAL_RECORD).ret();
+            });
+        a.forever(|a| {
+            a.get(cur)
+                .call("$json_peek")
+                .i32(b'"' as i32)
+                .op(I::I32Ne)
+                .if_(|a| {
+                    a.i32(0).ret();
+                });
+            a.get(cur)
+                .call("$json_string")
+                .tee(k)
+                .op(I::I32Eqz)
+                .if_(|a| {
+                    a.i32(0).ret();
+                });
+            a.get(cur)
+                .call("$json_peek")
+                .i32(b':' as i32)
+                .op(I::I32Ne)
+                .if_(|a| {
+                    a.i32(0).ret();
+                });
+            advance(a, cur);
+            a.get(cur)
+                .call("$json_value")
+                .tee(v)
+                .op(I::I32Eqz)
+                .if_(|a| {
+                    a.i32(0).ret();
+                });
+            // Overwrite an earlier entry with the same key, else append.
+            a.get(b).load(4).i32(ENTRY_SIZE).op(I::I32DivU).set(n);
+            a.i32(0).set(e);
+            a.for_(i, n, |a| {
+                a.get(b)
+                    .load(0)
+                    .get(i)
+                    .i32(ENTRY_SIZE)
+                    .op(I::I32Mul)
+                    .op(I::I32Add)
+                    .set(e);
+                a.get(e).load(4).w2(k).op(I::I32Eq).select_i32(
+                    |a| {
+                        a.get(e).load(0).w1(k).w2(k).rt(RT_MEMCMP);
+                    },
+                    |a| {
+                        a.i32(0);
+                    },
+                );
+                a.if_(|a| {
+                    a.brk();
+                });
+                a.i32(0).set(e);
+            });
+            a.get(e).op(I::I32Eqz).if_(|a| {
+                a.get(b)
+                    .i32(ENTRY_SIZE)
+                    .call("$sb_reserve")
+                    .tee(e)
+                    .w1(k)
+                    .store(0);
+                a.get(e).w2(k).store(4);
+            });
+            a.get(e).get(v).store(8);
+            a.get(cur).call("$json_peek").set(c);
+            advance(a, cur);
+            a.get(c).i32(b'}' as i32).op(I::I32Eq).if_(|a| {
+                a.get(b)
+                    .load(0)
+                    .get(b)
+                    .load(4)
+                    .i32(ENTRY_SIZE)
+                    .op(I::I32DivU);
+                a.rt(RT_VAL_RECORD).ret();
+            });
+            a.get(c).i32(b',' as i32).op(I::I32Ne).if_(|a| {
+                a.i32(0).ret();
+            });
+        });
+    });
+    a.get(c)
+        .i32(b'-' as i32)
+        .op(I::I32Eq)
+        .get(c)
+        .i32(b'0' as i32)
+        .op(I::I32Sub)
+        .i32(9)
+        .op(I::I32LeU);
+    a.op(I::I32Or).if_(|a| {
+        a.get(cur).call("$json_number").ret();
+    });
+    a.i32(0);
+}
+
+/// Skip digits at the cursor; push how many there were.
+fn digits(a: &mut Asm, cur: u32, n: u32) {
+    a.i32(0).set(n);
+    a.while_(
+        |a| {
+            current(a, cur);
+            a.i32(b'0' as i32).op(I::I32Sub).i32(9).op(I::I32LeU);
+        },
+        |a| {
+            advance(a, cur);
+            a.get(n).i32(1).op(I::I32Add).set(n);
+        },
+    );
+    a.get(n);
+}
+
+/// `$json_number(cur) -> NUMBER | 0` — `-?(0|[1-9]\d*)(\.\d+)?([eE][+-]?\d+)?`
+pub(super) fn json_number(a: &mut Asm) {
+    let cur = 0;
+    let (start, n, c) = (a.i32_local(), a.i32_local(), a.i32_local());
+    a.get(cur).load(0).set(start);
+    current(a, cur);
+    a.i32(b'-' as i32).op(I::I32Eq).if_(|a| {
+        advance(a, cur);
+    });
+    current(a, cur);
+    a.i32(b'0' as i32).op(I::I32Eq).if_else(
+        BlockType::Empty,
+        |a| {
+            advance(a, cur);
+        },
+        |a| {
+            digits(a, cur, n);
+            a.op(I::I32Eqz).if_(|a| {
+                a.i32(0).ret();
+            });
+        },
+    );
+    current(a, cur);
+    a.i32(b'.' as i32).op(I::I32Eq).if_(|a| {
+        advance(a, cur);
+        digits(a, cur, n);
+        a.op(I::I32Eqz).if_(|a| {
+            a.i32(0).ret();
+        });
+    });
+    current(a, cur);
+    a.i32(0x20)
+        .op(I::I32Or)
+        .i32(b'e' as i32)
+        .op(I::I32Eq)
+        .if_(|a| {
+            advance(a, cur);
+            current(a, cur);
+            a.tee(c)
+                .i32(b'+' as i32)
+                .op(I::I32Eq)
+                .get(c)
+                .i32(b'-' as i32)
+                .op(I::I32Eq)
+                .op(I::I32Or);
+            a.if_(|a| {
+                advance(a, cur);
+            });
+            digits(a, cur, n);
+            a.op(I::I32Eqz).if_(|a| {
+                a.i32(0).ret();
+            });
+        });
+    a.get(start)
+        .get(cur)
+        .load(0)
+        .get(start)
+        .op(I::I32Sub)
+        .call("$decimal")
+        .number();
+}
+
+/// `$json_string(cur) -> STRING | 0`, the cursor on the opening quote.
+pub(super) fn json_string(a: &mut Asm) {
+    let cur = 0;
+    let (sb, b, o, cp, lo) = (
+        a.i32_local(),
+        a.i32_local(),
+        a.i32_local(),
+        a.i32_local(),
+        a.i32_local(),
+    );
+    advance(a, cur);
+    a.call("$sb_new").set(sb);
+    a.forever(|a| {
+        current(a, cur);
+        a.tee(b)
+            .i32(0)
+            .op(I::I32LtS)
+            .get(b)
+            .i32(0x20)
+            .op(I::I32LtU)
+            .op(I::I32Or)
+            .if_(|a| {
+                a.i32(0).ret();
+            });
+        advance(a, cur);
+        a.get(b).i32(b'"' as i32).op(I::I32Eq).if_(|a| {
+            a.get(sb).call("$sb_finish").ret();
+        });
+        a.get(b).i32(b'\\' as i32).op(I::I32Ne).if_(|a| {
+            a.get(sb).get(b).call("$sb_byte");
+        });
+        a.get(b).i32(b'\\' as i32).op(I::I32Eq).if_(|a| {
+            current(a, cur);
+            a.set(b);
+            advance(a, cur);
+            a.i32(-1).set(o);
+            for (ch, out) in [
+                (b'"', b'"'),
+                (b'\\', b'\\'),
+                (b'/', b'/'),
+                (b'b', 0x08),
+                (b'f', 0x0c),
+                (b'n', b'\n'),
+                (b'r', b'\r'),
+                (b't', b'\t'),
+            ] {
+                a.get(b).i32(ch as i32).op(I::I32Eq).if_(|a| {
+                    a.i32(out as i32).set(o);
+                });
+            }
+            a.get(o).i32(0).op(I::I32GeS).if_else(
+                BlockType::Empty,
+                |a| {
+                    a.get(sb).get(o).call("$sb_byte");
+                },
+                |a| {
+                    a.get(b).i32(b'u' as i32).op(I::I32Ne).if_(|a| {
+                        a.i32(0).ret();
+                    });
+                    a.get(cur)
+                        .call("$hex4")
+                        .tee(cp)
+                        .i32(0)
+                        .op(I::I32LtS)
+                        .if_(|a| {
+                            a.i32(0).ret();
+                        });
+                    // Lone low surrogate.
+                    a.get(cp)
+                        .i32(0xFC00)
+                        .op(I::I32And)
+                        .i32(0xDC00)
+                        .op(I::I32Eq)
+                        .if_(|a| {
+                            a.i32(0).ret();
+                        });
+                    // High surrogate: a `\uDC00`–`\uDFFF` must follow.
+                    a.get(cp)
+                        .i32(0xFC00)
+                        .op(I::I32And)
+                        .i32(0xD800)
+                        .op(I::I32Eq)
+                        .if_(|a| {
+                            current(a, cur);
+                            a.i32(b'\\' as i32).op(I::I32Ne).if_(|a| {
+                                a.i32(0).ret();
+                            });
+                            advance(a, cur);
+                            current(a, cur);
+                            a.i32(b'u' as i32).op(I::I32Ne).if_(|a| {
+                                a.i32(0).ret();
+                            });
+                            advance(a, cur);
+                            a.get(cur)
+                                .call("$hex4")
+                                .tee(lo)
+                                .i32(0xFC00)
+                                .op(I::I32And)
+                                .i32(0xDC00)
+                                .op(I::I32Ne);
+                            a.if_(|a| {
+                                a.i32(0).ret();
+                            });
+                            a.get(cp).i32(0x3FF).op(I::I32And).i32(10).op(I::I32Shl);
+                            a.get(lo)
+                                .i32(0x3FF)
+                                .op(I::I32And)
+                                .op(I::I32Or)
+                                .i32(0x10000)
+                                .op(I::I32Add)
+                                .set(cp);
+                        });
+                    a.get(sb).get(cp).call("$utf8_put");
+                },
+            );
+        });
+    });
+    a.i32(0);
+}
+
+/// `$hex4(cur) -> code unit | -1`, consuming four hex digits.
+pub(super) fn hex4(a: &mut Asm) {
+    let cur = 0;
+    let (v, k, c, d) = (a.i32_local(), a.i32_local(), a.i32_local(), a.i32_local());
+    a.i32(0).set(v);
+    a.i32(0).set(k);
+    a.while_(
+        |a| {
+            a.get(k).i32(4).op(I::I32LtS);
+        },
+        |a| {
+            current(a, cur);
+            a.set(c);
+            a.get(c)
+                .i32(b'0' as i32)
+                .op(I::I32Sub)
+                .tee(d)
+                .i32(9)
+                .op(I::I32GtU)
+                .if_(|a| {
+                    a.get(c)
+                        .i32(0x20)
+                        .op(I::I32Or)
+                        .i32(b'a' as i32)
+                        .op(I::I32Sub)
+                        .tee(d)
+                        .i32(5)
+                        .op(I::I32GtU);
+                    a.if_(|a| {
+                        a.i32(-1).ret();
+                    });
+                    a.get(d).i32(10).op(I::I32Add).set(d);
+                });
+            a.get(v).i32(4).op(I::I32Shl).get(d).op(I::I32Or).set(v);
+            advance(a, cur);
+            a.get(k).i32(1).op(I::I32Add).set(k);
+        },
+    );
+    a.get(v);
+}
+
+/// `$utf8_put(sb, cp)` — append `cp` encoded as UTF-8.
+pub(super) fn utf8_put(a: &mut Asm) {
+    let (sb, cp) = (0, 1);
+    // (limit, lead-byte marker, continuation count)
+    for (limit, lead, cont) in [
+        (0x80, 0x00, 0),
+        (0x800, 0xC0, 1),
+        (0x10000, 0xE0, 2),
+        (0x110000, 0xF0, 3),
+    ] {
+        a.get(cp).i32(limit).op(I::I32LtU).if_(|a| {
+            a.get(sb)
+                .get(cp)
+                .i32(6 * cont)
+                .op(I::I32ShrU)
+                .i32(lead)
+                .op(I::I32Or)
+                .call("$sb_byte");
+            for k in (0..cont).rev() {
+                a.get(sb)
+                    .get(cp)
+                    .i32(6 * k)
+                    .op(I::I32ShrU)
+                    .i32(0x3F)
+                    .op(I::I32And)
+                    .i32(0x80)
+                    .op(I::I32Or);
+                a.call("$sb_byte");
+            }
+            a.ret();
+        });
+    }
+}
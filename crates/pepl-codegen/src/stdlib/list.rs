@@ -0,0 +1,621 @@
+//! `list.*` — immutable lists; every "mutation" returns a fresh list.
+//!
+//! Out-of-range `get`/`remove`/`update` leave the list alone (or yield nil),
+//! `insert`/`slice`/`take`/`drop` clamp their bounds.  Higher-order functions
+//! call lambdas through `$call1`/`$call2`; `sort` is a stable insertion sort
+//! ordered by `compare(a, b) > 0`.
+
+use wasm_encoder::Instruction as I;
+
+use crate::runtime::{RT_VAL_LIST, RT_VAL_NIL};
+use crate::types::*;
+
+use super::asm::Asm;
+
+const COPY: I<'static> = I::MemoryCopy {
+    src_mem: 0,
+    dst_mem: 0,
+};
+
+/// Push `a.len()`.
+fn len(a: &mut Asm, list: u32) {
+    a.w2(list);
+}
+
+/// Push `min(x, y)` for two i32 locals.
+fn min_i32(a: &mut Asm, x: u32, y: u32) {
+    a.get(x).get(y).get(x).get(y).op(I::I32LtS).op(I::Select);
+}
+
+/// Copy `count` slots from `src[from..]` to `dst[to..]` (all i32 locals or
+/// the constant 0 for the offsets when `None`).
+fn copy_slots(a: &mut Asm, dst: u32, to: Option<u32>, src: u32, from: Option<u32>, count: u32) {
+    a.get(dst);
+    if let Some(to) = to {
+        a.get(to).i32(4).op(I::I32Mul).op(I::I32Add);
+    }
+    a.get(src);
+    if let Some(from) = from {
+        a.get(from).i32(4).op(I::I32Mul).op(I::I32Add);
+    }
+    a.get(count).i32(4).op(I::I32Mul).op(COPY);
+}
+
+/// `out = $list_alloc(n)` and `dst = out.w1`.
+fn alloc_into(a: &mut Asm, n: u32, out: u32, dst: u32) {
+    a.get(n).call("$list_alloc").tee(out).load(4).set(dst);
+}
+
+pub(super) fn empty(a: &mut Asm) {
+    a.i32(0).i32(0).rt(RT_VAL_LIST);
+}
+
+pub(super) fn repeat(a: &mut Asm) {
+    let (n, out, dst, i) = (a.i32_local(), a.i32_local(), a.i32_local(), a.i32_local());
+    a.get(1).call("$count").set(n);
+    alloc_into(a, n, out, dst);
+    a.for_(i, n, |a| {
+        a.slot(dst, i).get(0).store(0);
+    });
+    a.get(out);
+}
+
+/// `list.range(start, end)` — `start, start + 1, …` while `< end`.
+pub(super) fn range(a: &mut Asm) {
+    let (n, out, dst, i, d) = (
+        a.i32_local(),
+        a.i32_local(),
+        a.i32_local(),
+        a.i32_local(),
+        a.f64_local(),
+    );
+    a.num(1).num(0).op(I::F64Sub).op(I::F64Ceil).set(d);
+    a.get(d).f64(0.0).op(I::F64Gt).select_i32(
+        |a| {
+            a.get(d).op(I::I32TruncSatF64S);
+        },
+        |a| {
+            a.i32(0);
+        },
+    );
+    a.set(n);
+    alloc_into(a, n, out, dst);
+    a.for_(i, n, |a| {
+        a.slot(dst, i)
+            .num(0)
+            .get(i)
+            .op(I::F64ConvertI32S)
+            .op(I::F64Add)
+            .number()
+            .store(0);
+    });
+    a.get(out);
+}
+
+pub(super) fn length(a: &mut Asm) {
+    len(a, 0);
+    a.op(I::F64ConvertI32U).number();
+}
+
+pub(super) fn get(a: &mut Asm) {
+    let idx = a.i32_local();
+    a.get(1).call("$index").tee(idx);
+    len(a, 0);
+    a.op(I::I32GeU).if_(|a| {
+        a.nil().ret();
+    });
+    a.w1(0).get(idx).i32(4).op(I::I32Mul).op(I::I32Add).load(0);
+}
+
+pub(super) fn first(a: &mut Asm) {
+    len(a, 0);
+    a.op(I::I32Eqz).if_(|a| {
+        a.nil().ret();
+    });
+    a.w1(0).load(0);
+}
+
+pub(super) fn last(a: &mut Asm) {
+    len(a, 0);
+    a.op(I::I32Eqz).if_(|a| {
+        a.nil().ret();
+    });
+    a.w1(0)
+        .w2(0)
+        .i32(4)
+        .op(I::I32Mul)
+        .op(I::I32Add)
+        .i32(4)
+        .op(I::I32Sub)
+        .load(0);
+}
+
+/// Loop over `list`'s items with `i`/`x` bound; `n` and `src` are scratch.
+fn each(a: &mut Asm, list: u32, body: impl FnOnce(&mut Asm, u32, u32)) {
+    let (n, src, i, x) = (a.i32_local(), a.i32_local(), a.i32_local(), a.i32_local());
+    len(a, list);
+    a.set(n);
+    a.w1(list).set(src);
+    a.for_(i, n, |a| {
+        a.slot(src, i).load(0).set(x);
+        body(a, i, x);
+    });
+}
+
+pub(super) fn index_of(a: &mut Asm) {
+    each(a, 0, |a, i, x| {
+        a.get(x).get(1).call("$deep_eq").if_(|a| {
+            a.get(i).op(I::F64ConvertI32S).number().ret();
+        });
+    });
+    a.f64(-1.0).number();
+}
+
+pub(super) fn contains(a: &mut Asm) {
+    each(a, 0, |a, _, x| {
+        a.get(x).get(1).call("$deep_eq").if_(|a| {
+            a.i32(1).bool().ret();
+        });
+    });
+    a.i32(0).bool();
+}
+
+pub(super) fn append(a: &mut Asm) {
+    let (n, out, dst, src, m) = (
+        a.i32_local(),
+        a.i32_local(),
+        a.i32_local(),
+        a.i32_local(),
+        a.i32_local(),
+    );
+    len(a, 0);
+    a.tee(n).i32(1).op(I::I32Add).set(m);
+    alloc_into(a, m, out, dst);
+    a.w1(0).set(src);
+    copy_slots(a, dst, None, src, None, n);
+    a.slot(dst, n).get(1).store(0);
+    a.get(out);
+}
+
+pub(super) fn prepend(a: &mut Asm) {
+    let (n, out, dst, src, m, one) = (
+        a.i32_local(),
+        a.i32_local(),
+        a.i32_local(),
+        a.i32_local(),
+        a.i32_local(),
+        a.i32_local(),
+    );
+    len(a, 0);
+    a.tee(n).i32(1).op(I::I32Add).set(m);
+    alloc_into(a, m, out, dst);
+    a.w1(0).set(src);
+    a.i32(1).set(one);
+    copy_slots(a, dst, Some(one), src, None, n);
+    a.get(dst).get(1).store(0);
+    a.get(out);
+}
+
+/// `list.insert(items, index, value)` — the index is clamped to `0..=len`.
+pub(super) fn insert(a: &mut Asm) {
+    let (n, out, dst, src, m, k, k1, rest) = (
+        a.i32_local(),
+        a.i32_local(),
+        a.i32_local(),
+        a.i32_local(),
+        a.i32_local(),
+        a.i32_local(),
+        a.i32_local(),
+        a.i32_local(),
+    );
+    len(a, 0);
+    a.tee(n).i32(1).op(I::I32Add).set(m);
+    a.get(1).call("$count").set(k);
+    min_i32(a, k, n);
+    a.set(k);
+    alloc_into(a, m, out, dst);
+    a.w1(0).set(src);
+    copy_slots(a, dst, None, src, None, k);
+    a.slot(dst, k).get(2).store(0);
+    a.get(k).i32(1).op(I::I32Add).set(k1);
+    a.get(n).get(k).op(I::I32Sub).set(rest);
+    copy_slots(a, dst, Some(k1), src, Some(k), rest);
+    a.get(out);
+}
+
+pub(super) fn remove(a: &mut Asm) {
+    let (n, out, dst, src, m, k, k1) = (
+        a.i32_local(),
+        a.i32_local(),
+        a.i32_local(),
+        a.i32_local(),
+        a.i32_local(),
+        a.i32_local(),
+        a.i32_local(),
+    );
+    len(a, 0);
+    a.set(n);
+    a.get(1)
+        .call("$index")
+        .tee(k)
+        .get(n)
+        .op(I::I32GeU)
+        .if_(|a| {
+            a.get(0).ret();
+        });
+    a.get(n).i32(1).op(I::I32Sub).set(m);
+    alloc_into(a, m, out, dst);
+    a.w1(0).set(src);
+    copy_slots(a, dst, None, src, None, k);
+    a.get(k).i32(1).op(I::I32Add).set(k1);
+    a.get(m).get(k).op(I::I32Sub).set(m);
+    copy_slots(a, dst, Some(k), src, Some(k1), m);
+    a.get(out);
+}
+
+/// `list.update` / `list.set` — replace one slot; out of range is a no-op.
+pub(super) fn update(a: &mut Asm) {
+    let (n, out, dst, src, k) = (
+        a.i32_local(),
+        a.i32_local(),
+        a.i32_local(),
+        a.i32_local(),
+        a.i32_local(),
+    );
+    len(a, 0);
+    a.set(n);
+    a.get(1)
+        .call("$index")
+        .tee(k)
+        .get(n)
+        .op(I::I32GeU)
+        .if_(|a| {
+            a.get(0).ret();
+        });
+    alloc_into(a, n, out, dst);
+    a.w1(0).set(src);
+    copy_slots(a, dst, None, src, None, n);
+    a.slot(dst, k).get(2).store(0);
+    a.get(out);
+}
+
+pub(super) fn slice(a: &mut Asm) {
+    let (n, out, dst, src, st, en, tmp) = (
+        a.i32_local(),
+        a.i32_local(),
+        a.i32_local(),
+        a.i32_local(),
+        a.i32_local(),
+        a.i32_local(),
+        a.i32_local(),
+    );
+    len(a, 0);
+    a.set(n);
+    a.get(1).call("$count").set(tmp);
+    min_i32(a, tmp, n);
+    a.set(st);
+    a.get(2).call("$count").set(tmp);
+    min_i32(a, tmp, n);
+    a.set(en);
+    a.get(st).get(en).op(I::I32GeS).if_(|a| {
+        a.i32(0).i32(0).rt(RT_VAL_LIST).ret();
+    });
+    a.get(en).get(st).op(I::I32Sub).set(tmp);
+    alloc_into(a, tmp, out, dst);
+    a.w1(0).set(src);
+    copy_slots(a, dst, None, src, Some(st), tmp);
+    a.get(out);
+}
+
+/// Clamp `n` (a value) to the list length into `k`.
+fn clamp_count(a: &mut Asm, list: u32, n: u32, k: u32, len_local: u32) {
+    len(a, list);
+    a.set(len_local);
+    a.get(n).call("$count").set(k);
+    min_i32(a, k, len_local);
+    a.set(k);
+}
+
+pub(super) fn take(a: &mut Asm) {
+    let (n, out, dst, src, k) = (
+        a.i32_local(),
+        a.i32_local(),
+        a.i32_local(),
+        a.i32_local(),
+        a.i32_local(),
+    );
+    clamp_count(a, 0, 1, k, n);
+    alloc_into(a, k, out, dst);
+    a.w1(0).set(src);
+    copy_slots(a, dst, None, src, None, k);
+    a.get(out);
+}
+
+pub(super) fn drop(a: &mut Asm) {
+    let (n, out, dst, src, k, m) = (
+        a.i32_local(),
+        a.i32_local(),
+        a.i32_local(),
+        a.i32_local(),
+        a.i32_local(),
+        a.i32_local(),
+    );
+    clamp_count(a, 0, 1, k, n);
+    a.get(n).get(k).op(I::I32Sub).set(m);
+    alloc_into(a, m, out, dst);
+    a.w1(0).set(src);
+    copy_slots(a, dst, None, src, Some(k), m);
+    a.get(out);
+}
+
+pub(super) fn concat(a: &mut Asm) {
+    let (na, nb, out, dst, src, m) = (
+        a.i32_local(),
+        a.i32_local(),
+        a.i32_local(),
+        a.i32_local(),
+        a.i32_local(),
+        a.i32_local(),
+    );
+    len(a, 0);
+    a.set(na);
+    len(a, 1);
+    a.tee(nb).get(na).op(I::I32Add).set(m);
+    alloc_into(a, m, out, dst);
+    a.w1(0).set(src);
+    copy_slots(a, dst, None, src, None, na);
+    a.w1(1).set(src);
+    copy_slots(a, dst, Some(na), src, None, nb);
+    a.get(out);
+}
+
+pub(super) fn reverse(a: &mut Asm) {
+    let (n, out, dst) = (a.i32_local(), a.i32_local(), a.i32_local());
+    len(a, 0);
+    a.set(n);
+    alloc_into(a, n, out, dst);
+    each(a, 0, |a, i, x| {
+        a.get(dst)
+            .get(n)
+            .get(i)
+            .op(I::I32Sub)
+            .i32(4)
+            .op(I::I32Mul)
+            .op(I::I32Add);
+        a.i32(4).op(I::I32Sub).get(x).store(0);
+    });
+    a.get(out);
+}
+
+/// `list.flatten(items)` — one level: nested lists are spliced, other items
+/// kept as they are.
+pub(super) fn flatten(a: &mut Asm) {
+    let (lb, j, m, inner) = (a.i32_local(), a.i32_local(), a.i32_local(), a.i32_local());
+    a.call("$sb_new").set(lb);
+    each(a, 0, |a, _, x| {
+        a.tag(x).i32(TAG_LIST).op(I::I32Eq).if_else(
+            wasm_encoder::BlockType::Empty,
+            |a| {
+                a.w2(x).set(m);
+                a.w1(x).set(inner);
+                a.for_(j, m, |a| {
+                    a.get(lb).slot(inner, j).load(0).call("$lb_push");
+                });
+            },
+            |a| {
+                a.get(lb).get(x).call("$lb_push");
+            },
+        );
+    });
+    a.get(lb).call("$lb_finish");
+}
+
+/// `list.unique(items)` — first occurrences, by structural equality.
+pub(super) fn unique(a: &mut Asm) {
+    let (lb, j, m, seen, dup) = (
+        a.i32_local(),
+        a.i32_local(),
+        a.i32_local(),
+        a.i32_local(),
+        a.i32_local(),
+    );
+    a.call("$sb_new").set(lb);
+    each(a, 0, |a, _, x| {
+        a.i32(0).set(dup);
+        a.get(lb).load(0).set(seen);
+        a.get(lb).load(4).i32(2).op(I::I32ShrU).set(m);
+        a.for_(j, m, |a| {
+            a.slot(seen, j).load(0).get(x).call("$deep_eq").if_(|a| {
+                a.i32(1).set(dup);
+                a.brk();
+            });
+        });
+        a.get(dup).op(I::I32Eqz).if_(|a| {
+            a.get(lb).get(x).call("$lb_push");
+        });
+    });
+    a.get(lb).call("$lb_finish");
+}
+
+pub(super) fn map(a: &mut Asm) {
+    let (n, out, dst) = (a.i32_local(), a.i32_local(), a.i32_local());
+    len(a, 0);
+    a.set(n);
+    alloc_into(a, n, out, dst);
+    each(a, 0, |a, i, x| {
+        a.slot(dst, i).get(1).get(x).call("$call1").store(0);
+    });
+    a.get(out);
+}
+
+/// Push `truthy(f(x))`.
+fn test(a: &mut Asm, f: u32, x: u32) {
+    a.get(f).get(x).call("$call1").call("$truthy");
+}
+
+pub(super) fn filter(a: &mut Asm) {
+    let lb = a.i32_local();
+    a.call("$sb_new").set(lb);
+    each(a, 0, |a, _, x| {
+        test(a, 1, x);
+        a.if_(|a| {
+            a.get(lb).get(x).call("$lb_push");
+        });
+    });
+    a.get(lb).call("$lb_finish");
+}
+
+pub(super) fn reduce(a: &mut Asm) {
+    let acc = a.i32_local();
+    a.get(1).set(acc);
+    each(a, 0, |a, _, x| {
+        a.get(2).get(acc).get(x).call("$call2").set(acc);
+    });
+    a.get(acc);
+}
+
+pub(super) fn find(a: &mut Asm) {
+    each(a, 0, |a, _, x| {
+        test(a, 1, x);
+        a.if_(|a| {
+            a.get(x).ret();
+        });
+    });
+    a.rt(RT_VAL_NIL);
+}
+
+pub(super) fn find_index(a: &mut Asm) {
+    each(a, 0, |a, i, x| {
+        test(a, 1, x);
+        a.if_(|a| {
+            a.get(i).op(I::F64ConvertI32S).number().ret();
+        });
+    });
+    a.f64(-1.0).number();
+}
+
+pub(super) fn every(a: &mut Asm) {
+    each(a, 0, |a, _, x| {
+        test(a, 1, x);
+        a.op(I::I32Eqz).if_(|a| {
+            a.i32(0).bool().ret();
+        });
+    });
+    a.i32(1).bool();
+}
+
+pub(super) fn any(a: &mut Asm) {
+    each(a, 0, |a, _, x| {
+        test(a, 1, x);
+        a.if_(|a| {
+            a.i32(1).bool().ret();
+        });
+    });
+    a.i32(0).bool();
+}
+
+pub(super) fn count(a: &mut Asm) {
+    let c = a.i32_local();
+    a.i32(0).set(c);
+    each(a, 0, |a, _, x| {
+        test(a, 1, x);
+        a.get(c).op(I::I32Add).set(c);
+    });
+    a.get(c).op(I::F64ConvertI32U).number();
+}
+
+/// `list.sort(items, compare)` — stable; `x` moves before `y` only when
+/// `compare(y, x) > 0`.
+pub(super) fn sort(a: &mut Asm) {
+    let (n, out, dst, src, i, j, x, c) = (
+        a.i32_local(),
+        a.i32_local(),
+        a.i32_local(),
+        a.i32_local(),
+        a.i32_local(),
+        a.i32_local(),
+        a.i32_local(),
+        a.i32_local(),
+    );
+    len(a, 0);
+    a.set(n);
+    alloc_into(a, n, out, dst);
+    a.w1(0).set(src);
+    copy_slots(a, dst, None, src, None, n);
+    a.for_(i, n, |a| {
+        a.slot(dst, i).load(0).set(x);
+        a.get(i).i32(1).op(I::I32Sub).set(j);
+        a.while_(
+            |a| {
+                a.get(j).i32(0).op(I::I32GeS).select_i32(
+                    |a| {
+                        a.get(1).slot(dst, j).load(0).get(x).call("$call2").tee(c);
+                        a.load(0).i32(TAG_NUMBER).op(I::I32Eq).select_i32(
+                            |a| {
+                                a.num(c).f64(0.0).op(I::F64Gt);
+                            },
+                            |a| {
+                                a.i32(0);
+                            },
+                        );
+                    },
+                    |a| {
+                        a.i32(0);
+                    },
+                );
+            },
+            |a| {
+                a.slot(dst, j)
+                    .i32(4)
+                    .op(I::I32Add)
+                    .slot(dst, j)
+                    .load(0)
+                    .store(0);
+                a.get(j).i32(1).op(I::I32Sub).set(j);
+            },
+        );
+        a.slot(dst, j).i32(4).op(I::I32Add).get(x).store(0);
+    });
+    a.get(out);
+}
+
+/// `list.zip(a, b)` — `[a_i, b_i]` pairs up to the shorter length.
+pub(super) fn zip(a: &mut Asm) {
+    let (na, nb, n, out, dst, i, pair) = (
+        a.i32_local(),
+        a.i32_local(),
+        a.i32_local(),
+        a.i32_local(),
+        a.i32_local(),
+        a.i32_local(),
+        a.i32_local(),
+    );
+    len(a, 0);
+    a.set(na);
+    len(a, 1);
+    a.set(nb);
+    min_i32(a, na, nb);
+    a.set(n);
+    alloc_into(a, n, out, dst);
+    a.for_(i, n, |a| {
+        a.i32(8).alloc().set(pair);
+        a.get(pair)
+            .w1(0)
+            .get(i)
+            .i32(4)
+            .op(I::I32Mul)
+            .op(I::I32Add)
+            .load(0)
+            .store(0);
+        a.get(pair)
+            .w1(1)
+            .get(i)
+            .i32(4)
+            .op(I::I32Mul)
+            .op(I::I32Add)
+            .load(0)
+            .store(4);
+        a.slot(dst, i).get(pair).i32(2).rt(RT_VAL_LIST).store(0);
+    });
+    a.get(out);
+}
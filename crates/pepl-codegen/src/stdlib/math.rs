@@ -0,0 +1,310 @@
+//! `math.*` — f64 arithmetic with Rust's `f64` semantics.
+//!
+//! WASM has native `abs`, `floor`, `ceil`, `sqrt`, `min` and `max`; `round`
+//! (half away from zero) and `pow` are built here, with `ln`/`exp` for
+//! fractional exponents evaluated by argument reduction and short series.
+
+use wasm_encoder::Instruction as I;
+
+use super::asm::Asm;
+
+const LN2_HI: f64 = 6.931_471_803_691_238e-1;
+const LN2_LO: f64 = 1.908_214_929_270_587_7e-10;
+
+fn unary(a: &mut Asm, op: I<'static>) {
+    a.num(0).op(op).number();
+}
+
+fn binary(a: &mut Asm, op: I<'static>) {
+    a.num(0).num(1).op(op).number();
+}
+
+pub(super) fn abs(a: &mut Asm) {
+    unary(a, I::F64Abs);
+}
+
+pub(super) fn floor(a: &mut Asm) {
+    unary(a, I::F64Floor);
+}
+
+pub(super) fn ceil(a: &mut Asm) {
+    unary(a, I::F64Ceil);
+}
+
+pub(super) fn sqrt(a: &mut Asm) {
+    unary(a, I::F64Sqrt);
+}
+
+pub(super) fn min(a: &mut Asm) {
+    binary(a, I::F64Min);
+}
+
+pub(super) fn max(a: &mut Asm) {
+    binary(a, I::F64Max);
+}
+
+pub(super) fn round(a: &mut Asm) {
+    a.num(0).call("$round").number();
+}
+
+pub(super) fn pow(a: &mut Asm) {
+    a.num(0).num(1).call("$pow").number();
+}
+
+/// `math.round_to(x, decimals)` — `round(x * 10^d) / 10^d`.
+pub(super) fn round_to(a: &mut Asm) {
+    let p = a.f64_local();
+    a.f64(10.0).num(1).call("$pow").set(p);
+    a.num(0)
+        .get(p)
+        .op(I::F64Mul)
+        .call("$round")
+        .get(p)
+        .op(I::F64Div)
+        .number();
+}
+
+/// `math.clamp(x, lo, hi)` — `x.max(lo).min(hi)`.
+pub(super) fn clamp(a: &mut Asm) {
+    a.num(0).num(1).op(I::F64Max).num(2).op(I::F64Min).number();
+}
+
+/// `$round(x: f64) -> f64` — half away from zero.
+pub(super) fn round_f64(a: &mut Asm) {
+    let t = a.f64_local();
+    a.get(0).op(I::F64Trunc).set(t);
+    a.get(0)
+        .get(t)
+        .op(I::F64Sub)
+        .op(I::F64Abs)
+        .f64(0.5)
+        .op(I::F64Ge)
+        .if_(|a| {
+            a.get(t)
+                .f64(1.0)
+                .get(0)
+                .op(I::F64Copysign)
+                .op(I::F64Add)
+                .ret();
+        });
+    a.get(t);
+}
+
+/// `$pow(base: f64, exp: f64) -> f64`
+///
+/// Integral exponents go to `$powi`; fractional ones through
+/// `exp(e * ln b)`.
+pub(super) fn pow_f64(a: &mut Asm) {
+    let (b, e) = (0, 1);
+    a.get(e)
+        .f64(0.0)
+        .op(I::F64Eq)
+        .get(b)
+        .f64(1.0)
+        .op(I::F64Eq)
+        .op(I::I32Or)
+        .if_(|a| {
+            a.f64(1.0).ret();
+        });
+    a.get(e)
+        .get(e)
+        .op(I::F64Ne)
+        .get(b)
+        .get(b)
+        .op(I::F64Ne)
+        .op(I::I32Or)
+        .if_(|a| {
+            a.f64(f64::NAN).ret();
+        });
+    a.get(e)
+        .op(I::F64Abs)
+        .f64(f64::INFINITY)
+        .op(I::F64Eq)
+        .if_(|a| {
+            a.get(b).op(I::F64Abs).f64(1.0).op(I::F64Eq).if_(|a| {
+                a.f64(1.0).ret();
+            });
+            a.get(b)
+                .op(I::F64Abs)
+                .f64(1.0)
+                .op(I::F64Gt)
+                .get(e)
+                .f64(0.0)
+                .op(I::F64Gt)
+                .op(I::I32Eq);
+            a.if_(|a| {
+                a.f64(f64::INFINITY).ret();
+            });
+            a.f64(0.0).ret();
+        });
+    a.get(e).op(I::F64Trunc).get(e).op(I::F64Eq).if_(|a| {
+        a.get(b).get(e).call("$powi").ret();
+    });
+    a.get(b).f64(0.0).op(I::F64Lt).if_(|a| {
+        a.f64(f64::NAN).ret();
+    });
+    a.get(b).f64(0.0).op(I::F64Eq).if_(|a| {
+        a.get(e).f64(0.0).op(I::F64Gt).if_(|a| {
+            a.f64(0.0).ret();
+        });
+        a.f64(f64::INFINITY).ret();
+    });
+    a.get(b).f64(f64::INFINITY).op(I::F64Eq).if_(|a| {
+        a.get(e).f64(0.0).op(I::F64Gt).if_(|a| {
+            a.f64(f64::INFINITY).ret();
+        });
+        a.f64(0.0).ret();
+    });
+    a.get(e).f64(0.5).op(I::F64Eq).if_(|a| {
+        a.get(b).op(I::F64Sqrt).ret();
+    });
+    a.get(e).get(b).call("$ln").op(I::F64Mul).call("$exp");
+}
+
+/// `$powi(base: f64, exp: f64) -> f64` for an integral `exp`, by
+/// square-and-multiply.
+pub(super) fn powi(a: &mut Asm) {
+    let (b, e) = (0, 1);
+    let (r, base, n) = (a.f64_local(), a.f64_local(), a.i64_local());
+    a.f64(1.0).set(r);
+    a.get(b).set(base);
+    a.get(e).op(I::F64Abs).op(I::I64TruncSatF64U).set(n);
+    a.while_(
+        |a| {
+            a.get(n).i64(0).op(I::I64Ne);
+        },
+        |a| {
+            a.get(n).i64(1).op(I::I64And).op(I::I32WrapI64).if_(|a| {
+                a.get(r).get(base).op(I::F64Mul).set(r);
+            });
+            a.get(base).get(base).op(I::F64Mul).set(base);
+            a.get(n).i64(1).op(I::I64ShrU).set(n);
+        },
+    );
+    a.get(e).f64(0.0).op(I::F64Lt).if_(|a| {
+        a.f64(1.0).get(r).op(I::F64Div).ret();
+    });
+    a.get(r);
+}
+
+/// `$ln(x: f64) -> f64` for finite `x > 0`.
+///
+/// `x = 2^k · m` with `m ∈ [√2/2, √2)`; `ln m = 2·atanh(s)`,
+/// `s = (m-1)/(m+1)`, summed to `s^21`.
+pub(super) fn ln(a: &mut Asm) {
+    let x = 0;
+    let (k, m, s, z, poly, bits) = (
+        a.f64_local(),
+        a.f64_local(),
+        a.f64_local(),
+        a.f64_local(),
+        a.f64_local(),
+        a.i64_local(),
+    );
+    a.f64(0.0).set(k);
+    // Scale subnormals into the normal range.
+    a.get(x).f64(f64::MIN_POSITIVE).op(I::F64Lt).if_(|a| {
+        a.get(x).f64(18014398509481984.0).op(I::F64Mul).set(x);
+        a.f64(-54.0).set(k);
+    });
+    a.get(x).op(I::I64ReinterpretF64).set(bits);
+    a.get(k);
+    a.get(bits)
+        .i64(52)
+        .op(I::I64ShrU)
+        .i64(0x7ff)
+        .op(I::I64And)
+        .i64(1023)
+        .op(I::I64Sub);
+    a.op(I::F64ConvertI64S).op(I::F64Add).set(k);
+    a.get(bits)
+        .i64(0x000F_FFFF_FFFF_FFFF)
+        .op(I::I64And)
+        .i64(0x3FF0_0000_0000_0000)
+        .op(I::I64Or);
+    a.op(I::F64ReinterpretI64).set(m);
+    a.get(m)
+        .f64(std::f64::consts::SQRT_2)
+        .op(I::F64Gt)
+        .if_(|a| {
+            a.get(m).f64(0.5).op(I::F64Mul).set(m);
+            a.get(k).f64(1.0).op(I::F64Add).set(k);
+        });
+    a.get(m)
+        .f64(1.0)
+        .op(I::F64Sub)
+        .get(m)
+        .f64(1.0)
+        .op(I::F64Add)
+        .op(I::F64Div)
+        .set(s);
+    a.get(s).get(s).op(I::F64Mul).set(z);
+    // poly = 1 + z/3 + z²/5 + … + z^10/21, by Horner.
+    a.f64(1.0 / 21.0).set(poly);
+    for odd in (1..=19).rev().step_by(2) {
+        a.get(poly)
+            .get(z)
+            .op(I::F64Mul)
+            .f64(1.0 / odd as f64)
+            .op(I::F64Add)
+            .set(poly);
+    }
+    a.get(k).f64(LN2_HI).op(I::F64Mul);
+    a.get(k).f64(LN2_LO).op(I::F64Mul);
+    a.f64(2.0).get(s).op(I::F64Mul).get(poly).op(I::F64Mul);
+    a.op(I::F64Add).op(I::F64Add);
+}
+
+/// `$exp(x: f64) -> f64`
+///
+/// `x = k·ln2 + r` with `|r| ≤ ln2/2`; `e^r` by a degree-13 Taylor series,
+/// then scaled by `2^k` in two steps so subnormal results survive.
+pub(super) fn exp(a: &mut Asm) {
+    let x = 0;
+    let (k, r, p, ki) = (a.f64_local(), a.f64_local(), a.f64_local(), a.i64_local());
+    a.get(x).get(x).op(I::F64Ne).if_(|a| {
+        a.get(x).ret();
+    });
+    a.get(x).f64(709.782712893384).op(I::F64Gt).if_(|a| {
+        a.f64(f64::INFINITY).ret();
+    });
+    a.get(x).f64(-745.1332191019412).op(I::F64Lt).if_(|a| {
+        a.f64(0.0).ret();
+    });
+    a.get(x)
+        .f64(std::f64::consts::LOG2_E)
+        .op(I::F64Mul)
+        .op(I::F64Nearest)
+        .set(k);
+    a.get(x).get(k).f64(LN2_HI).op(I::F64Mul).op(I::F64Sub);
+    a.get(k).f64(LN2_LO).op(I::F64Mul).op(I::F64Sub).set(r);
+    // p = 1 + r(1 + r/2(1 + r/3(… (1 + r/13))))
+    a.f64(1.0).set(p);
+    for n in (1..=13).rev() {
+        a.get(r)
+            .get(p)
+            .op(I::F64Mul)
+            .f64(n as f64)
+            .op(I::F64Div)
+            .f64(1.0)
+            .op(I::F64Add)
+            .set(p);
+    }
+    a.get(k).op(I::I64TruncSatF64S).set(ki);
+    a.get(p);
+    a.get(ki).i64(1).op(I::I64ShrS);
+    pow2(a);
+    a.op(I::F64Mul);
+    a.get(ki).get(ki).i64(1).op(I::I64ShrS).op(I::I64Sub);
+    pow2(a);
+    a.op(I::F64Mul);
+}
+
+/// `2^j` for the i64 `j` on the stack, `-1022 ≤ j ≤ 1023`.
+fn pow2(a: &mut Asm) {
+    a.i64(1023)
+        .op(I::I64Add)
+        .i64(52)
+        .op(I::I64Shl)
+        .op(I::F64ReinterpretI64);
+}
@@ -0,0 +1,283 @@
+//! The pure standard library, compiled into every generated module.
+//!
+//! `math`, `string`, `list`, `record`, `convert`, `json` and the pure half
+//! of `core` are emitted as ordinary WASM functions that follow the
+//! evaluator's semantics, so a host only has to provide real capabilities
+//! (`http`, `storage`, `location`, `notifications`), `time`, `timer` and
+//! `core.capability` through `env.host_call`.
+//!
+//! Functions are laid out in [`FUNCS`] order directly after the runtime
+//! helpers.  Public entries are named `module.function` and take and return
+//! value pointers; `$`-prefixed entries are internal helpers with raw
+//! signatures.  Constant strings they need live in [`STRINGS`], which the
+//! data segment places at the start of user data.
+
+pub(crate) mod asm;
+mod convert;
+mod helpers;
+mod json;
+mod list;
+mod math;
+mod record;
+mod state;
+mod text;
+
+use wasm_encoder::Function;
+
+use crate::runtime::RT_FUNC_COUNT;
+use crate::types::*;
+
+use asm::Asm;
+
+struct StdFn {
+    name: &'static str,
+    ty: u32,
+    emit: fn(&mut Asm),
+}
+
+const fn f(name: &'static str, ty: u32, emit: fn(&mut Asm)) -> StdFn {
+    StdFn { name, ty, emit }
+}
+
+const FUNCS: &[StdFn] = &[
+    // ── Internal helpers ──
+    f("$num", TYPE_F64_I32, helpers::num),
+    f("$truthy", TYPE_I32_I32, helpers::truthy),
+    f("$ok", TYPE_I32_I32, helpers::ok),
+    f("$err", TYPE_I32_I32, helpers::err),
+    f("$index", TYPE_I32_I32, helpers::index),
+    f("$count", TYPE_I32_I32, helpers::count),
+    f("$call1", TYPE_I32X2_I32, helpers::call1),
+    f("$call2", TYPE_I32X3_I32, helpers::call2),
+    f("$sb_new", TYPE_VOID_I32, helpers::sb_new),
+    f("$sb_reserve", TYPE_I32X2_I32, helpers::sb_reserve),
+    f("$sb_push", TYPE_I32X3_VOID, helpers::sb_push),
+    f("$sb_str", TYPE_I32X2_VOID, helpers::sb_str),
+    f("$sb_byte", TYPE_I32X2_VOID, helpers::sb_byte),
+    f("$sb_finish", TYPE_I32_I32, helpers::sb_finish),
+    f("$lb_push", TYPE_I32X2_VOID, helpers::lb_push),
+    f("$lb_finish", TYPE_I32_I32, helpers::lb_finish),
+    f("$list_alloc", TYPE_I32_I32, helpers::list_alloc),
+    f("$deep_eq", TYPE_I32X2_I32, helpers::deep_eq),
+    f("$record_find", TYPE_I32X3_I32, helpers::record_find),
+    f("$key_lt", TYPE_I32X2_I32, helpers::key_lt),
+    f("$sorted_entries", TYPE_I32_I32, helpers::sorted_entries),
+    f("$sb_u64", TYPE_I32_I64_I32_VOID, helpers::sb_u64),
+    f("$number_into", TYPE_I32_F64_VOID, helpers::number_into),
+    f("$display_into", TYPE_I32X2_VOID, helpers::display_into),
+    f("$display", TYPE_I32_I32, helpers::display),
+    f("$utf8_len", TYPE_I32_I32, helpers::utf8_len),
+    f("$char_count", TYPE_I32X2_I32, helpers::char_count),
+    f("$char_offset", TYPE_I32X3_I32, helpers::char_offset),
+    f("$substr", TYPE_I32X3_I32, helpers::substr),
+    f("$str_find", TYPE_I32X3_I32, helpers::str_find),
+    f("$is_space", TYPE_I32_I32, helpers::is_space),
+    f("$record_entries", TYPE_I32_I32, helpers::record_entries),
+    f("$record_copy", TYPE_I32X2_I32, helpers::record_copy),
+    f("$round", TYPE_F64_F64, math::round_f64),
+    f("$pow", TYPE_F64X2_F64, math::pow_f64),
+    f("$powi", TYPE_F64X2_F64, math::powi),
+    f("$ln", TYPE_F64_F64, math::ln),
+    f("$exp", TYPE_F64_F64, math::exp),
+    f("$pad_fill", TYPE_I32X3_VOID, text::pad_fill),
+    f("$parse_number", TYPE_I32X2_I32, convert::parse_number),
+    f("$decimal", TYPE_I32X2_F64, convert::decimal),
+    f("$json_into", TYPE_I32X2_VOID, json::json_into),
+    f("$json_quote", TYPE_I32X3_VOID, json::json_quote),
+    f("$json_peek", TYPE_I32_I32, json::json_peek),
+    f("$json_value", TYPE_I32_I32, json::json_value),
+    f("$json_number", TYPE_I32_I32, json::json_number),
+    f("$json_string", TYPE_I32_I32, json::json_string),
+    f("$hex4", TYPE_I32_I32, json::hex4),
+    f("$utf8_put", TYPE_I32X2_VOID, json::utf8_put),
+    f("$state_into", TYPE_I32X3_VOID, state::state_into),
+    f("$sci_into", TYPE_I32_F64_VOID, state::sci_into),
+    f("$conform", TYPE_I32X2_I32, state::conform),
+    // ── core ──
+    f("core.log", TYPE_I32_I32, convert::log),
+    f("core.assert", TYPE_I32X2_I32, convert::assert),
+    f("core.type_of", TYPE_I32_I32, convert::type_of),
+    // ── math ──
+    f("math.abs", TYPE_I32_I32, math::abs),
+    f("math.min", TYPE_I32X2_I32, math::min),
+    f("math.max", TYPE_I32X2_I32, math::max),
+    f("math.floor", TYPE_I32_I32, math::floor),
+    f("math.ceil", TYPE_I32_I32, math::ceil),
+    f("math.round", TYPE_I32_I32, math::round),
+    f("math.round_to", TYPE_I32X2_I32, math::round_to),
+    f("math.pow", TYPE_I32X2_I32, math::pow),
+    f("math.clamp", TYPE_I32X3_I32, math::clamp),
+    f("math.sqrt", TYPE_I32_I32, math::sqrt),
+    // ── string ──
+    f("string.length", TYPE_I32_I32, text::length),
+    f("string.concat", TYPE_I32X2_I32, text::concat),
+    f("string.contains", TYPE_I32X2_I32, text::contains),
+    f("string.slice", TYPE_I32X3_I32, text::slice),
+    f("string.trim", TYPE_I32_I32, text::trim),
+    f("string.split", TYPE_I32X2_I32, text::split),
+    f("string.to_upper", TYPE_I32_I32, text::to_upper),
+    f("string.to_lower", TYPE_I32_I32, text::to_lower),
+    f("string.starts_with", TYPE_I32X2_I32, text::starts_with),
+    f("string.ends_with", TYPE_I32X2_I32, text::ends_with),
+    f("string.replace", TYPE_I32X3_I32, text::replace),
+    f("string.replace_all", TYPE_I32X3_I32, text::replace_all),
+    f("string.pad_start", TYPE_I32X3_I32, text::pad_start),
+    f("string.pad_end", TYPE_I32X3_I32, text::pad_end),
+    f("string.repeat", TYPE_I32X2_I32, text::repeat),
+    f("string.join", TYPE_I32X2_I32, text::join),
+    f("string.format", TYPE_I32X2_I32, text::format),
+    f("string.from", TYPE_I32_I32, text::from),
+    f("string.is_empty", TYPE_I32_I32, text::is_empty),
+    f("string.index_of", TYPE_I32X2_I32, text::index_of),
+    // ── list ──
+    f("list.empty", TYPE_VOID_I32, list::empty),
+    f("list.repeat", TYPE_I32X2_I32, list::repeat),
+    f("list.range", TYPE_I32X2_I32, list::range),
+    f("list.length", TYPE_I32_I32, list::length),
+    f("list.get", TYPE_I32X2_I32, list::get),
+    f("list.first", TYPE_I32_I32, list::first),
+    f("list.last", TYPE_I32_I32, list::last),
+    f("list.index_of", TYPE_I32X2_I32, list::index_of),
+    f("list.append", TYPE_I32X2_I32, list::append),
+    f("list.prepend", TYPE_I32X2_I32, list::prepend),
+    f("list.insert", TYPE_I32X3_I32, list::insert),
+    f("list.remove", TYPE_I32X2_I32, list::remove),
+    f("list.update", TYPE_I32X3_I32, list::update),
+    f("list.set", TYPE_I32X3_I32, list::update),
+    f("list.slice", TYPE_I32X3_I32, list::slice),
+    f("list.concat", TYPE_I32X2_I32, list::concat),
+    f("list.reverse", TYPE_I32_I32, list::reverse),
+    f("list.flatten", TYPE_I32_I32, list::flatten),
+    f("list.unique", TYPE_I32_I32, list::unique),
+    f("list.map", TYPE_I32X2_I32, list::map),
+    f("list.filter", TYPE_I32X2_I32, list::filter),
+    f("list.reduce", TYPE_I32X3_I32, list::reduce),
+    f("list.find", TYPE_I32X2_I32, list::find),
+    f("list.find_index", TYPE_I32X2_I32, list::find_index),
+    f("list.every", TYPE_I32X2_I32, list::every),
+    f("list.any", TYPE_I32X2_I32, list::any),
+    f("list.some", TYPE_I32X2_I32, list::any),
+    f("list.sort", TYPE_I32X2_I32, list::sort),
+    f("list.contains", TYPE_I32X2_I32, list::contains),
+    f("list.count", TYPE_I32X2_I32, list::count),
+    f("list.zip", TYPE_I32X2_I32, list::zip),
+    f("list.take", TYPE_I32X2_I32, list::take),
+    f("list.drop", TYPE_I32X2_I32, list::drop),
+    // ── record ──
+    f("record.get", TYPE_I32X2_I32, record::get),
+    f("record.set", TYPE_I32X3_I32, record::set),
+    f("record.has", TYPE_I32X2_I32, record::has),
+    f("record.keys", TYPE_I32_I32, record::keys),
+    f("record.values", TYPE_I32_I32, record::values),
+    // ── convert ──
+    f("convert.to_string", TYPE_I32_I32, convert::to_string),
+    f("convert.to_number", TYPE_I32_I32, convert::to_number),
+    f("convert.parse_int", TYPE_I32_I32, convert::parse_int),
+    f("convert.parse_float", TYPE_I32_I32, convert::parse_float),
+    f("convert.to_bool", TYPE_I32_I32, convert::to_bool),
+    // ── json ──
+    f("json.parse", TYPE_I32_I32, json::parse),
+    f("json.stringify", TYPE_I32_I32, json::stringify),
+];
+
+/// Number of stdlib functions emitted after the runtime helpers.
+pub const STDLIB_FUNC_COUNT: u32 = FUNCS.len() as u32;
+
+/// Constant strings referenced by the stdlib, laid out back to back.
+const STRINGS: &[&str] = &[
+    "NaN",
+    "inf",
+    "-inf",
+    "true",
+    "false",
+    "nil",
+    "null",
+    ", ",
+    "{ ",
+    ": ",
+    " }",
+    "Ok(",
+    "Err(",
+    "<function>",
+    "[value]",
+    "cannot parse '",
+    "invalid JSON",
+    "assertion failed",
+    "\\u00",
+    "0123456789abcdef",
+    "number",
+    "string",
+    "bool",
+    "list",
+    "record",
+    "color",
+    "function",
+    "result",
+    "variant",
+    "action",
+    "Ok",
+    "Err",
+];
+
+/// Absolute function index of a stdlib entry (`"list.map"`, `"$display"`).
+pub fn func_idx(name: &str) -> Option<u32> {
+    FUNCS
+        .iter()
+        .position(|f| f.name == name)
+        .map(|i| IMPORT_COUNT + RT_FUNC_COUNT + i as u32)
+}
+
+/// Name of the stdlib function at `offset` past the runtime helpers.
+pub fn func_name(offset: u32) -> Option<&'static str> {
+    FUNCS.get(offset as usize).map(|f| f.name)
+}
+
+/// The compiled implementation of `module.function`, if it is pure, as
+/// `(function index, parameter count)`.
+pub fn pure_function(module: &str, function: &str) -> Option<(u32, usize)> {
+    let pos = FUNCS
+        .iter()
+        .position(|f| f.name.split_once('.') == Some((module, function)))?;
+    Some((
+        IMPORT_COUNT + RT_FUNC_COUNT + pos as u32,
+        param_count(FUNCS[pos].ty) as usize,
+    ))
+}
+
+/// The bytes of [`STRINGS`], to be placed at the start of the data segment.
+pub fn string_data() -> Vec<u8> {
+    STRINGS.iter().flat_map(|s| s.bytes()).collect()
+}
+
+fn string_offset(s: &str) -> Option<u32> {
+    let mut offset = 0;
+    for candidate in STRINGS {
+        if *candidate == s {
+            return Some(offset);
+        }
+        offset += candidate.len() as u32;
+    }
+    None
+}
+
+/// `(type index, body)` for every stdlib function, in index order.
+/// `strings_base` is where [`string_data`] was placed in memory.
+pub(crate) fn emit_all(strings_base: u32) -> Vec<(u32, Function)> {
+    FUNCS
+        .iter()
+        .map(|f| {
+            let mut asm = Asm::new(param_count(f.ty), strings_base);
+            (f.emit)(&mut asm);
+            (f.ty, asm.finish())
+        })
+        .collect()
+}
+
+fn param_count(ty: u32) -> u32 {
+    match ty {
+        TYPE_VOID_VOID | TYPE_VOID_I32 | TYPE_VOID_I64 => 0,
+        TYPE_I32_VOID | TYPE_I32_I32 | TYPE_F64_I32 | TYPE_F64_F64 => 1,
+        TYPE_I32X3_I32 | TYPE_I32X3_VOID | TYPE_I32_I64_I32_VOID => 3,
+        _ => 2,
+    }
+}
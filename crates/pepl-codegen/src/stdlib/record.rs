@@ -0,0 +1,94 @@
+//! `record.*` — lookups by key, copy-on-set, and key-ordered listings.
+//!
+//! Records keep their fields in declaration order in memory; `keys` and
+//! `values` sort by key bytes so they match the evaluator's ordered map.
+
+use wasm_encoder::Instruction as I;
+
+use crate::runtime::RT_VAL_STRING;
+
+use super::asm::Asm;
+use super::helpers::ENTRY_SIZE;
+
+/// Push `$record_find(rec, key.ptr, key.len)`.
+fn find(a: &mut Asm) {
+    a.get(0).w1(1).w2(1).call("$record_find");
+}
+
+pub(super) fn get(a: &mut Asm) {
+    let e = a.i32_local();
+    find(a);
+    a.tee(e).op(I::I32Eqz).if_(|a| {
+        a.nil().ret();
+    });
+    a.get(e).load(8);
+}
+
+/// `record.set(rec, key, value)` — replace the field in a copy, or append
+/// it when the key is new.
+pub(super) fn set(a: &mut Asm) {
+    let (e, out) = (a.i32_local(), a.i32_local());
+    find(a);
+    a.tee(e).if_(|a| {
+        a.get(0).i32(0).call("$record_copy").set(out);
+        // Same slot in the copy: out.entries + (e - rec.entries)
+        a.w1(out)
+            .get(e)
+            .op(I::I32Add)
+            .w1(0)
+            .op(I::I32Sub)
+            .get(2)
+            .store(8);
+        a.get(out).ret();
+    });
+    a.get(0).i32(1).call("$record_copy").set(out);
+    a.w1(out)
+        .w2(0)
+        .i32(ENTRY_SIZE)
+        .op(I::I32Mul)
+        .op(I::I32Add)
+        .set(e);
+    a.get(e).w1(1).store(0);
+    a.get(e).w2(1).store(4);
+    a.get(e).get(2).store(8);
+    a.get(out);
+}
+
+pub(super) fn has(a: &mut Asm) {
+    find(a);
+    a.i32(0).op(I::I32Ne).bool();
+}
+
+/// Build a list from the key-ordered entries, `item` mapping an entry
+/// pointer (on the stack) to a value.
+fn sorted_list(a: &mut Asm, item: impl Fn(&mut Asm)) {
+    let (n, sorted, out, dst, i) = (
+        a.i32_local(),
+        a.i32_local(),
+        a.i32_local(),
+        a.i32_local(),
+        a.i32_local(),
+    );
+    a.w2(0).set(n);
+    a.get(0).call("$sorted_entries").set(sorted);
+    a.get(n).call("$list_alloc").tee(out).load(4).set(dst);
+    a.for_(i, n, |a| {
+        a.slot(dst, i).slot(sorted, i).load(0);
+        item(a);
+        a.store(0);
+    });
+    a.get(out);
+}
+
+pub(super) fn keys(a: &mut Asm) {
+    let e = a.i32_local();
+    sorted_list(a, |a| {
+        a.tee(e).load(0).get(e).load(4).rt(RT_VAL_STRING);
+    });
+}
+
+pub(super) fn values(a: &mut Asm) {
+    sorted_list(a, |a| {
+        a.load(8);
+    });
+}
@@ -0,0 +1,580 @@
+//! Helpers behind the `serialize_state` / `restore_state` exports.
+//!
+//! `$state_into` writes a value the way the evaluator's
+//! `value_to_json_public` does (integers without a fraction, `serde_json`'s
+//! scientific form for tiny and huge numbers, `{"Ok": …}` results and named
+//! variants), so a module and the evaluator persist the same bytes.  (The
+//! shortest digits are found by reading candidates back with `$decimal`, so
+//! numbers near the ends of the f64 range may differ in the last digit.)
+//! `$conform` walks a parsed JSON value against a schema laid out by
+//! [`crate::snapshot`] and rebuilds it as the value the program expects.
+
+use wasm_encoder::{BlockType, Instruction as I, ValType};
+
+use crate::runtime::{RT_MEMCMP, RT_VAL_LIST, RT_VAL_RECORD, RT_VAL_VARIANT};
+use crate::snapshot::*;
+use crate::types::*;
+
+use super::asm::Asm;
+use super::helpers::ENTRY_SIZE;
+
+/// `$state_into(sb, v, names)` — `names` is the variant name table
+/// `[count, (ptr, len) × count]`, indexed by variant id.
+pub(super) fn state_into(a: &mut Asm) {
+    let (sb, v, names) = (0, 1, 2);
+    let (t, n, i, arr, id, fields) = (
+        a.i32_local(),
+        a.i32_local(),
+        a.i32_local(),
+        a.i32_local(),
+        a.i32_local(),
+        a.i32_local(),
+    );
+    let x = a.f64_local();
+    a.tag(v).set(t);
+    a.get(t).i32(TAG_NUMBER).op(I::I32Eq).if_(|a| {
+        a.num(v).set(x);
+        a.get(x)
+            .get(x)
+            .op(I::F64Sub)
+            .f64(0.0)
+            .op(I::F64Ne)
+            .if_(|a| {
+                a.get(sb).lit("null").call("$sb_push").ret();
+            });
+        // Integers in i64 range print as integers; other numbers print
+        // like `Display` unless they are below 1e-5 or beyond i64.
+        a.get(x).op(I::F64Floor).get(x).op(I::F64Eq).select_i32(
+            |a| {
+                a.get(x)
+                    .op(I::F64Abs)
+                    .f64(9223372036854775808.0)
+                    .op(I::F64Le);
+            },
+            |a| {
+                a.get(x).op(I::F64Abs).f64(1e-5).op(I::F64Ge);
+            },
+        );
+        a.if_(|a| {
+            a.get(sb).get(x).call("$number_into").ret();
+        });
+        a.get(x).f64(0.0).op(I::F64Lt).if_(|a| {
+            a.get(sb).i32(b'-' as i32).call("$sb_byte");
+        });
+        a.get(sb).get(x).op(I::F64Abs).call("$sci_into").ret();
+    });
+    a.get(t).i32(TAG_STRING).op(I::I32Eq).if_(|a| {
+        a.get(sb).w1(v).w2(v).call("$json_quote").ret();
+    });
+    a.get(t).i32(TAG_BOOL).op(I::I32Eq).if_(|a| {
+        a.w1(v).if_else(
+            BlockType::Empty,
+            |a| {
+                a.get(sb).lit("true").call("$sb_push");
+            },
+            |a| {
+                a.get(sb).lit("false").call("$sb_push");
+            },
+        );
+        a.ret();
+    });
+    a.get(t).i32(TAG_LIST).op(I::I32Eq).if_(|a| {
+        a.w1(v).set(arr);
+        a.w2(v).set(n);
+        items_into(a, sb, arr, n, i, names);
+        a.ret();
+    });
+    a.get(t).i32(TAG_RECORD).op(I::I32Eq).if_(|a| {
+        a.get(v).call("$sorted_entries").set(arr);
+        a.w2(v).set(n);
+        a.get(sb).i32(b'{' as i32).call("$sb_byte");
+        a.for_(i, n, |a| {
+            a.get(i).if_(|a| {
+                a.get(sb).i32(b',' as i32).call("$sb_byte");
+            });
+            a.get(sb)
+                .slot(arr, i)
+                .load(0)
+                .load(0)
+                .slot(arr, i)
+                .load(0)
+                .load(4)
+                .call("$json_quote");
+            a.get(sb).i32(b':' as i32).call("$sb_byte");
+            a.get(sb)
+                .slot(arr, i)
+                .load(0)
+                .load(8)
+                .get(names)
+                .call("$state_into");
+        });
+        a.get(sb).i32(b'}' as i32).call("$sb_byte").ret();
+    });
+    a.get(t).i32(TAG_VARIANT).op(I::I32Eq).if_(|a| {
+        a.w1(v).set(id);
+        for (vid, name) in [(VARIANT_OK, "Ok"), (VARIANT_ERR, "Err")] {
+            a.get(id).i32(vid as i32).op(I::I32Eq).if_(|a| {
+                a.get(sb).i32(b'{' as i32).call("$sb_byte");
+                a.get(sb).lit(name).call("$json_quote");
+                a.get(sb).i32(b':' as i32).call("$sb_byte");
+                a.get(sb).w2(v).get(names).call("$state_into");
+                a.get(sb).i32(b'}' as i32).call("$sb_byte").ret();
+            });
+        }
+        a.get(id).get(names).load(0).op(I::I32LtU).if_(|a| {
+            // Unit variants are their quoted name, others `{"Name": [fields]}`.
+            a.get(names)
+                .get(id)
+                .i32(8)
+                .op(I::I32Mul)
+                .op(I::I32Add)
+                .set(arr);
+            a.w2(v).set(fields);
+            a.i32(0).set(n);
+            a.get(fields).select_i32(
+                |a| {
+                    a.get(fields).load(0).i32(TAG_LIST).op(I::I32Eq);
+                },
+                |a| {
+                    a.i32(0);
+                },
+            );
+            a.if_(|a| {
+                a.get(fields).load(8).set(n);
+            });
+            a.get(n).op(I::I32Eqz).if_(|a| {
+                a.get(sb)
+                    .get(arr)
+                    .load(4)
+                    .get(arr)
+                    .load(8)
+                    .call("$json_quote")
+                    .ret();
+            });
+            a.get(sb).i32(b'{' as i32).call("$sb_byte");
+            a.get(sb)
+                .get(arr)
+                .load(4)
+                .get(arr)
+                .load(8)
+                .call("$json_quote");
+            a.get(sb).i32(b':' as i32).call("$sb_byte");
+            a.get(fields).load(4).set(arr);
+            items_into(a, sb, arr, n, i, names);
+            a.get(sb).i32(b'}' as i32).call("$sb_byte").ret();
+        });
+    });
+    a.get(t).i32(TAG_LAMBDA).op(I::I32Eq).if_(|a| {
+        a.get(sb).lit("<function>").call("$json_quote").ret();
+    });
+    a.get(sb).lit("null").call("$sb_push");
+}
+
+/// Append `[item, …]` for the `n` value pointers at `arr`.
+fn items_into(a: &mut Asm, sb: u32, arr: u32, n: u32, i: u32, names: u32) {
+    a.get(sb).i32(b'[' as i32).call("$sb_byte");
+    a.for_(i, n, |a| {
+        a.get(i).if_(|a| {
+            a.get(sb).i32(b',' as i32).call("$sb_byte");
+        });
+        a.get(sb)
+            .slot(arr, i)
+            .load(0)
+            .get(names)
+            .call("$state_into");
+    });
+    a.get(sb).i32(b']' as i32).call("$sb_byte");
+}
+
+/// `$sci_into(sb, x: f64)` — `serde_json`'s `1.5e-7` / `1e+20` form for a positive,
+/// finite `x`: the fewest significant digits that `$decimal` reads back
+/// as `x`.
+pub(super) fn sci_into(a: &mut Asm) {
+    let (sb, x) = (0, 1);
+    let (e, d, s, tmp, len, k) = (
+        a.i32_local(),
+        a.i32_local(),
+        a.i32_local(),
+        a.i32_local(),
+        a.i32_local(),
+        a.i32_local(),
+    );
+    let (m, xs) = (a.f64_local(), a.f64_local());
+    a.get(x)
+        .call("$ln")
+        .f64(std::f64::consts::LN_10)
+        .op(I::F64Div)
+        .op(I::F64Floor)
+        .op(I::I32TruncSatF64S)
+        .set(e);
+    a.call("$sb_new").set(tmp);
+    a.i32(0).set(d);
+    a.forever(|a| {
+        a.get(d).i32(1).op(I::I32Add).set(d);
+        // m = round(x / 10^s), a d-digit mantissa
+        a.get(e).get(d).op(I::I32Sub).i32(1).op(I::I32Add).set(s);
+        a.get(s).i32(0).op(I::I32GeS).if_else(
+            BlockType::Result(ValType::F64),
+            |a| {
+                a.get(x)
+                    .f64(10.0)
+                    .get(s)
+                    .op(I::F64ConvertI32S)
+                    .call("$powi")
+                    .op(I::F64Div);
+            },
+            |a| {
+                // 10^-s overflows below 1e-308; scale subnormals in two steps
+                a.get(x).set(xs);
+                a.get(s).set(k);
+                a.get(k).i32(-300).op(I::I32LtS).if_(|a| {
+                    a.get(xs).f64(1e300).op(I::F64Mul).set(xs);
+                    a.get(k).i32(300).op(I::I32Add).set(k);
+                });
+                a.get(xs)
+                    .f64(10.0)
+                    .i32(0)
+                    .get(k)
+                    .op(I::I32Sub)
+                    .op(I::F64ConvertI32S)
+                    .call("$powi")
+                    .op(I::F64Mul);
+            },
+        );
+        a.op(I::F64Nearest).set(m);
+        a.get(tmp).i32(0).store(4);
+        a.get(tmp)
+            .get(m)
+            .op(I::I64TruncSatF64U)
+            .i32(1)
+            .call("$sb_u64");
+        a.get(s).set(k);
+        exponent_into(a, tmp, k);
+        a.get(tmp)
+            .load(0)
+            .get(tmp)
+            .load(4)
+            .call("$decimal")
+            .get(x)
+            .op(I::F64Eq)
+            .get(d)
+            .i32(17)
+            .op(I::I32GeS)
+            .op(I::I32Or)
+            .if_(|a| {
+                a.brk();
+            });
+    });
+    // d.ddd with trailing zeros dropped, then the exponent of the first digit.
+    a.get(tmp).i32(0).store(4);
+    a.get(tmp)
+        .get(m)
+        .op(I::I64TruncSatF64U)
+        .i32(1)
+        .call("$sb_u64");
+    a.get(tmp).load(4).set(len);
+    a.get(s).get(len).op(I::I32Add).i32(1).op(I::I32Sub).set(k);
+    a.while_(
+        |a| {
+            a.get(len).i32(1).op(I::I32GtS).select_i32(
+                |a| {
+                    a.get(tmp)
+                        .load(0)
+                        .get(len)
+                        .op(I::I32Add)
+                        .i32(1)
+                        .op(I::I32Sub)
+                        .load8(0)
+                        .i32(b'0' as i32)
+                        .op(I::I32Eq);
+                },
+                |a| {
+                    a.i32(0);
+                },
+            );
+        },
+        |a| {
+            a.get(len).i32(1).op(I::I32Sub).set(len);
+        },
+    );
+    a.get(sb).get(tmp).load(0).load8(0).call("$sb_byte");
+    a.get(len).i32(1).op(I::I32GtS).if_(|a| {
+        a.get(sb).i32(b'.' as i32).call("$sb_byte");
+        a.get(sb)
+            .get(tmp)
+            .load(0)
+            .i32(1)
+            .op(I::I32Add)
+            .get(len)
+            .i32(1)
+            .op(I::I32Sub)
+            .call("$sb_push");
+    });
+    exponent_into(a, sb, k);
+}
+
+/// Append `e+<k>` / `e-<|k|>`; clobbers `k`.
+fn exponent_into(a: &mut Asm, sb: u32, k: u32) {
+    a.get(sb).i32(b'e' as i32).call("$sb_byte");
+    a.get(k).i32(0).op(I::I32LtS).if_else(
+        BlockType::Empty,
+        |a| {
+            a.get(sb).i32(b'-' as i32).call("$sb_byte");
+            a.i32(0).get(k).op(I::I32Sub).set(k);
+        },
+        |a| {
+            a.get(sb).i32(b'+' as i32).call("$sb_byte");
+        },
+    );
+    a.get(sb).get(k).op(I::I64ExtendI32U).i32(1).call("$sb_u64");
+}
+
+/// `$conform(v, schema) -> value | 0` — `v` rebuilt as `schema` describes,
+/// or 0 when it does not fit.
+pub(super) fn conform(a: &mut Asm) {
+    let (v, s) = (0, 1);
+    let (kind, t, n, i, j, arr, out, e, f, c, k, fs, fc) = (
+        a.i32_local(),
+        a.i32_local(),
+        a.i32_local(),
+        a.i32_local(),
+        a.i32_local(),
+        a.i32_local(),
+        a.i32_local(),
+        a.i32_local(),
+        a.i32_local(),
+        a.i32_local(),
+        a.i32_local(),
+        a.i32_local(),
+        a.i32_local(),
+    );
+    a.get(s).load(0).set(kind);
+    a.tag(v).set(t);
+    a.get(kind).i32(SCHEMA_ANY).op(I::I32Eq).if_(|a| {
+        a.get(v).ret();
+    });
+    for (schema, tag) in [
+        (SCHEMA_NUMBER, TAG_NUMBER),
+        (SCHEMA_STRING, TAG_STRING),
+        (SCHEMA_BOOL, TAG_BOOL),
+        (SCHEMA_NIL, TAG_NIL),
+    ] {
+        a.get(kind).i32(schema).op(I::I32Eq).if_(|a| {
+            a.get(t).i32(tag).op(I::I32Eq).select_i32(
+                |a| {
+                    a.get(v);
+                },
+                |a| {
+                    a.i32(0);
+                },
+            );
+            a.ret();
+        });
+    }
+    a.get(kind).i32(SCHEMA_LIST).op(I::I32Eq).if_(|a| {
+        a.get(t).i32(TAG_LIST).op(I::I32Ne).if_(|a| {
+            a.i32(0).ret();
+        });
+        a.w1(v).set(arr);
+        a.w2(v).set(n);
+        a.get(n).i32(4).op(I::I32Mul).alloc().set(out);
+        a.for_(i, n, |a| {
+            a.slot(arr, i)
+                .load(0)
+                .get(s)
+                .load(4)
+                .call("$conform")
+                .tee(c)
+                .op(I::I32Eqz)
+                .if_(|a| {
+                    a.i32(0).ret();
+                });
+            a.slot(out, i).get(c).store(0);
+        });
+        a.get(out).get(n).rt(RT_VAL_LIST).ret();
+    });
+    a.get(kind).i32(SCHEMA_RECORD).op(I::I32Eq).if_(|a| {
+        a.get(t).i32(TAG_RECORD).op(I::I32Ne).if_(|a| {
+            a.i32(0).ret();
+        });
+        a.get(s).load(4).set(n);
+        a.get(n).i32(ENTRY_SIZE).op(I::I32Mul).alloc().set(out);
+        a.i32(0).set(k);
+        a.for_(i, n, |a| {
+            field_at(a, s, i, f);
+            a.get(v)
+                .get(f)
+                .load(0)
+                .get(f)
+                .load(4)
+                .call("$record_find")
+                .set(e);
+            // A missing field is only allowed when it is optional.
+            a.get(e).op(I::I32Eqz).if_(|a| {
+                a.get(f).load(8).op(I::I32Eqz).if_(|a| {
+                    a.i32(0).ret();
+                });
+            });
+            a.get(e).if_(|a| {
+                a.get(e)
+                    .load(8)
+                    .get(f)
+                    .load(12)
+                    .call("$conform")
+                    .tee(c)
+                    .op(I::I32Eqz)
+                    .if_(|a| {
+                        a.i32(0).ret();
+                    });
+                a.get(out)
+                    .get(k)
+                    .i32(ENTRY_SIZE)
+                    .op(I::I32Mul)
+                    .op(I::I32Add)
+                    .set(e);
+                a.get(e).get(f).load(0).store(0);
+                a.get(e).get(f).load(4).store(4);
+                a.get(e).get(c).store(8);
+                a.get(k).i32(1).op(I::I32Add).set(k);
+            });
+        });
+        // Every key has to be a declared field.
+        a.w2(v).get(k).op(I::I32Ne).if_(|a| {
+            a.i32(0).ret();
+        });
+        a.get(out).get(k).rt(RT_VAL_RECORD).ret();
+    });
+    a.get(kind).i32(SCHEMA_RESULT).op(I::I32Eq).if_(|a| {
+        a.w2(v).i32(1).op(I::I32Ne).if_(|a| {
+            a.i32(0).ret();
+        });
+        for (vid, name, offset) in [(VARIANT_OK, "Ok", 4), (VARIANT_ERR, "Err", 8)] {
+            a.get(v).lit(name).call("$record_find").tee(e).if_(|a| {
+                a.get(e)
+                    .load(8)
+                    .get(s)
+                    .load(offset)
+                    .call("$conform")
+                    .tee(c)
+                    .op(I::I32Eqz)
+                    .if_(|a| {
+                        a.i32(0).ret();
+                    });
+                a.i32(vid as i32).get(c).rt(RT_VAL_VARIANT).ret();
+            });
+        }
+        a.i32(0).ret();
+    });
+    a.get(kind).i32(SCHEMA_VARIANTS).op(I::I32Eq).if_(|a| {
+        // `"Name"` for unit variants, `{"Name": [fields]}` otherwise; `k`
+        // and `c` hold the name, `arr` the field list (0 for a unit).
+        a.i32(0).set(arr);
+        a.get(t).i32(TAG_STRING).op(I::I32Eq).if_else(
+            BlockType::Empty,
+            |a| {
+                a.w1(v).set(k);
+                a.w2(v).set(c);
+            },
+            |a| {
+                a.get(t)
+                    .i32(TAG_RECORD)
+                    .op(I::I32Ne)
+                    .w2(v)
+                    .i32(1)
+                    .op(I::I32Ne)
+                    .op(I::I32Or)
+                    .if_(|a| {
+                        a.i32(0).ret();
+                    });
+                a.w1(v).set(e);
+                a.get(e).load(0).set(k);
+                a.get(e).load(4).set(c);
+                a.get(e)
+                    .load(8)
+                    .tee(arr)
+                    .load(0)
+                    .i32(TAG_LIST)
+                    .op(I::I32Ne)
+                    .if_(|a| {
+                        a.i32(0).ret();
+                    });
+            },
+        );
+        a.get(s).load(4).set(n);
+        a.for_(i, n, |a| {
+            field_at(a, s, i, f);
+            a.get(f).load(8).get(c).op(I::I32Eq).select_i32(
+                |a| {
+                    a.get(f).load(4).get(k).get(c).rt(RT_MEMCMP);
+                },
+                |a| {
+                    a.i32(0);
+                },
+            );
+            a.if_(|a| {
+                a.get(f).load(12).tee(fs).load(0).set(fc);
+                a.get(arr).op(I::I32Eqz).if_(|a| {
+                    a.get(fc).if_(|a| {
+                        a.i32(0).ret();
+                    });
+                    a.get(f)
+                        .load(0)
+                        .i32(0)
+                        .i32(0)
+                        .rt(RT_VAL_LIST)
+                        .rt(RT_VAL_VARIANT)
+                        .ret();
+                });
+                a.get(fc)
+                    .op(I::I32Eqz)
+                    .get(arr)
+                    .load(8)
+                    .get(fc)
+                    .op(I::I32Ne)
+                    .op(I::I32Or)
+                    .if_(|a| {
+                        a.i32(0).ret();
+                    });
+                a.get(fc).i32(4).op(I::I32Mul).alloc().set(out);
+                a.get(arr).load(4).set(e);
+                a.for_(j, fc, |a| {
+                    a.slot(e, j).load(0);
+                    a.get(fs)
+                        .i32(4)
+                        .op(I::I32Add)
+                        .get(j)
+                        .i32(4)
+                        .op(I::I32Mul)
+                        .op(I::I32Add)
+                        .load(0);
+                    a.call("$conform").tee(c).op(I::I32Eqz).if_(|a| {
+                        a.i32(0).ret();
+                    });
+                    a.slot(out, j).get(c).store(0);
+                });
+                a.get(f)
+                    .load(0)
+                    .get(out)
+                    .get(fc)
+                    .rt(RT_VAL_LIST)
+                    .rt(RT_VAL_VARIANT)
+                    .ret();
+            });
+        });
+        a.i32(0).ret();
+    });
+    a.i32(0);
+}
+
+/// `f = schema + 8 + i * 16` — the i-th field or variant of a record or
+/// variants schema.
+fn field_at(a: &mut Asm, s: u32, i: u32, f: u32) {
+    a.get(s)
+        .i32(8)
+        .op(I::I32Add)
+        .get(i)
+        .i32(SCHEMA_ENTRY_SIZE)
+        .op(I::I32Mul)
+        .op(I::I32Add)
+        .set(f);
+}
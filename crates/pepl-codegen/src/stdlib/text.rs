@@ -0,0 +1,519 @@
+//! `string.*` — UTF-8 strings addressed by char, as the evaluator does.
+//!
+//! Results that are a contiguous run of the input (`slice`, `trim`, `split`
+//! pieces) are views onto the same bytes: strings are immutable, so sharing
+//! is safe and saves a copy.  Case mapping and trimming are ASCII-only.
+
+use wasm_encoder::{BlockType, Instruction as I};
+
+use crate::runtime::{RT_MEMCMP, RT_VAL_STRING, RT_VAL_STRING_CONCAT};
+
+use super::asm::Asm;
+
+/// Push `min(x, y)` for two i32 locals.
+fn min_i32(a: &mut Asm, x: u32, y: u32) {
+    a.get(x).get(y).get(x).get(y).op(I::I32LtS).op(I::Select);
+}
+
+/// Push `char_count(s)`.
+fn chars(a: &mut Asm, s: u32) {
+    a.w1(s).w2(s).call("$char_count");
+}
+
+pub(super) fn length(a: &mut Asm) {
+    chars(a, 0);
+    a.op(I::F64ConvertI32U).number();
+}
+
+pub(super) fn concat(a: &mut Asm) {
+    a.get(0).get(1).rt(RT_VAL_STRING_CONCAT);
+}
+
+pub(super) fn contains(a: &mut Asm) {
+    a.get(0)
+        .get(1)
+        .i32(0)
+        .call("$str_find")
+        .i32(-1)
+        .op(I::I32Ne)
+        .bool();
+}
+
+/// `string.slice(s, start, end)` — chars `start..end`, clamped.
+pub(super) fn slice(a: &mut Asm) {
+    let (n, st, en, tmp) = (a.i32_local(), a.i32_local(), a.i32_local(), a.i32_local());
+    chars(a, 0);
+    a.set(n);
+    a.get(1).call("$count").set(tmp);
+    min_i32(a, tmp, n);
+    a.set(st);
+    a.get(2).call("$count").set(tmp);
+    min_i32(a, tmp, n);
+    a.set(en);
+    a.get(st).get(en).op(I::I32GeS).if_(|a| {
+        a.i32(0).i32(0).rt(RT_VAL_STRING).ret();
+    });
+    a.get(0);
+    a.w1(0).w2(0).get(st).call("$char_offset");
+    a.w1(0).w2(0).get(en).call("$char_offset");
+    a.call("$substr");
+}
+
+pub(super) fn trim(a: &mut Asm) {
+    let (st, en) = (a.i32_local(), a.i32_local());
+    a.i32(0).set(st);
+    a.w2(0).set(en);
+    a.while_(
+        |a| {
+            a.get(st).get(en).op(I::I32LtU).select_i32(
+                |a| {
+                    a.w1(0).get(st).op(I::I32Add).load8(0).call("$is_space");
+                },
+                |a| {
+                    a.i32(0);
+                },
+            );
+        },
+        |a| {
+            a.get(st).i32(1).op(I::I32Add).set(st);
+        },
+    );
+    a.while_(
+        |a| {
+            a.get(en).get(st).op(I::I32GtU).select_i32(
+                |a| {
+                    a.w1(0)
+                        .get(en)
+                        .op(I::I32Add)
+                        .i32(1)
+                        .op(I::I32Sub)
+                        .load8(0)
+                        .call("$is_space");
+                },
+                |a| {
+                    a.i32(0);
+                },
+            );
+        },
+        |a| {
+            a.get(en).i32(1).op(I::I32Sub).set(en);
+        },
+    );
+    a.get(0).get(st).get(en).call("$substr");
+}
+
+/// `string.split(s, delimiter)` — an empty delimiter splits into chars.
+pub(super) fn split(a: &mut Asm) {
+    let (lb, pos, cl, at) = (a.i32_local(), a.i32_local(), a.i32_local(), a.i32_local());
+    a.call("$sb_new").set(lb);
+    a.i32(0).set(pos);
+    a.w2(1).op(I::I32Eqz).if_(|a| {
+        a.while_(
+            |a| {
+                a.get(pos).w2(0).op(I::I32LtU);
+            },
+            |a| {
+                a.w1(0)
+                    .get(pos)
+                    .op(I::I32Add)
+                    .load8(0)
+                    .call("$utf8_len")
+                    .set(cl);
+                a.get(lb)
+                    .get(0)
+                    .get(pos)
+                    .get(pos)
+                    .get(cl)
+                    .op(I::I32Add)
+                    .call("$substr");
+                a.call("$lb_push");
+                a.get(pos).get(cl).op(I::I32Add).set(pos);
+            },
+        );
+        a.get(lb).call("$lb_finish").ret();
+    });
+    a.forever(|a| {
+        a.get(0).get(1).get(pos).call("$str_find").tee(at);
+        a.i32(0).op(I::I32LtS).if_(|a| {
+            a.brk();
+        });
+        a.get(lb)
+            .get(0)
+            .get(pos)
+            .get(at)
+            .call("$substr")
+            .call("$lb_push");
+        a.get(at).w2(1).op(I::I32Add).set(pos);
+    });
+    a.get(lb)
+        .get(0)
+        .get(pos)
+        .w2(0)
+        .call("$substr")
+        .call("$lb_push");
+    a.get(lb).call("$lb_finish");
+}
+
+/// Copy `s`, shifting bytes in `lo..=hi` by `delta`.
+fn map_ascii(a: &mut Asm, lo: u8, hi: u8, delta: i32) {
+    let (out, i, n, b) = (a.i32_local(), a.i32_local(), a.i32_local(), a.i32_local());
+    a.w2(0).tee(n).alloc().set(out);
+    a.for_(i, n, |a| {
+        a.w1(0).get(i).op(I::I32Add).load8(0).set(b);
+        a.get(b)
+            .i32(lo as i32)
+            .op(I::I32Sub)
+            .i32((hi - lo) as i32)
+            .op(I::I32LeU)
+            .if_(|a| {
+                a.get(b).i32(delta).op(I::I32Add).set(b);
+            });
+        a.get(out).get(i).op(I::I32Add).get(b).store8(0);
+    });
+    a.get(out).get(n).rt(RT_VAL_STRING);
+}
+
+pub(super) fn to_upper(a: &mut Asm) {
+    map_ascii(a, b'a', b'z', -32);
+}
+
+pub(super) fn to_lower(a: &mut Asm) {
+    map_ascii(a, b'A', b'Z', 32);
+}
+
+pub(super) fn starts_with(a: &mut Asm) {
+    a.w2(1).w2(0).op(I::I32LeU).select_i32(
+        |a| {
+            a.w1(0).w1(1).w2(1).rt(RT_MEMCMP);
+        },
+        |a| {
+            a.i32(0);
+        },
+    );
+    a.bool();
+}
+
+pub(super) fn ends_with(a: &mut Asm) {
+    a.w2(1).w2(0).op(I::I32LeU).select_i32(
+        |a| {
+            a.w1(0)
+                .w2(0)
+                .op(I::I32Add)
+                .w2(1)
+                .op(I::I32Sub)
+                .w1(1)
+                .w2(1)
+                .rt(RT_MEMCMP);
+        },
+        |a| {
+            a.i32(0);
+        },
+    );
+    a.bool();
+}
+
+/// `string.replace(s, from, to)` — first occurrence only.
+pub(super) fn replace(a: &mut Asm) {
+    let (at, sb, rest) = (a.i32_local(), a.i32_local(), a.i32_local());
+    a.get(0).get(1).i32(0).call("$str_find").tee(at);
+    a.i32(0).op(I::I32LtS).if_(|a| {
+        a.get(0).ret();
+    });
+    a.call("$sb_new").set(sb);
+    a.get(sb).w1(0).get(at).call("$sb_push");
+    a.get(sb).get(2).call("$sb_str");
+    a.get(at).w2(1).op(I::I32Add).set(rest);
+    a.get(sb)
+        .w1(0)
+        .get(rest)
+        .op(I::I32Add)
+        .w2(0)
+        .get(rest)
+        .op(I::I32Sub)
+        .call("$sb_push");
+    a.get(sb).call("$sb_finish");
+}
+
+/// `string.replace_all(s, from, to)` — an empty `from` inserts `to` around
+/// every char, like `str::replace`.
+pub(super) fn replace_all(a: &mut Asm) {
+    let (sb, pos, cl, at) = (a.i32_local(), a.i32_local(), a.i32_local(), a.i32_local());
+    a.call("$sb_new").set(sb);
+    a.i32(0).set(pos);
+    a.w2(1).op(I::I32Eqz).if_(|a| {
+        a.while_(
+            |a| {
+                a.get(pos).w2(0).op(I::I32LtU);
+            },
+            |a| {
+                a.get(sb).get(2).call("$sb_str");
+                a.w1(0)
+                    .get(pos)
+                    .op(I::I32Add)
+                    .load8(0)
+                    .call("$utf8_len")
+                    .set(cl);
+                a.get(sb)
+                    .w1(0)
+                    .get(pos)
+                    .op(I::I32Add)
+                    .get(cl)
+                    .call("$sb_push");
+                a.get(pos).get(cl).op(I::I32Add).set(pos);
+            },
+        );
+        a.get(sb).get(2).call("$sb_str");
+        a.get(sb).call("$sb_finish").ret();
+    });
+    a.forever(|a| {
+        a.get(0).get(1).get(pos).call("$str_find").tee(at);
+        a.i32(0).op(I::I32LtS).if_(|a| {
+            a.brk();
+        });
+        a.get(sb)
+            .w1(0)
+            .get(pos)
+            .op(I::I32Add)
+            .get(at)
+            .get(pos)
+            .op(I::I32Sub)
+            .call("$sb_push");
+        a.get(sb).get(2).call("$sb_str");
+        a.get(at).w2(1).op(I::I32Add).set(pos);
+    });
+    a.get(sb)
+        .w1(0)
+        .get(pos)
+        .op(I::I32Add)
+        .w2(0)
+        .get(pos)
+        .op(I::I32Sub)
+        .call("$sb_push");
+    a.get(sb).call("$sb_finish");
+}
+
+/// `string.pad_start` / `string.pad_end` — cycle `pad` until `s` is
+/// `length` chars long.
+fn pad(a: &mut Asm, at_start: bool) {
+    let (target, cur, sb) = (a.i32_local(), a.i32_local(), a.i32_local());
+    a.get(1).call("$count").set(target);
+    chars(a, 0);
+    a.tee(cur)
+        .get(target)
+        .op(I::I32GeS)
+        .w2(2)
+        .op(I::I32Eqz)
+        .op(I::I32Or)
+        .if_(|a| {
+            a.get(0).ret();
+        });
+    a.call("$sb_new").set(sb);
+    if !at_start {
+        a.get(sb).get(0).call("$sb_str");
+    }
+    a.get(sb)
+        .get(2)
+        .get(target)
+        .get(cur)
+        .op(I::I32Sub)
+        .call("$pad_fill");
+    if at_start {
+        a.get(sb).get(0).call("$sb_str");
+    }
+    a.get(sb).call("$sb_finish");
+}
+
+pub(super) fn pad_start(a: &mut Asm) {
+    pad(a, true);
+}
+
+pub(super) fn pad_end(a: &mut Asm) {
+    pad(a, false);
+}
+
+/// `$pad_fill(sb, pad, n)` — append `n` chars cycling through `pad`.
+pub(super) fn pad_fill(a: &mut Asm) {
+    let (pos, k, cl) = (a.i32_local(), a.i32_local(), a.i32_local());
+    a.i32(0).set(pos);
+    a.for_(k, 2, |a| {
+        a.w1(1)
+            .get(pos)
+            .op(I::I32Add)
+            .load8(0)
+            .call("$utf8_len")
+            .set(cl);
+        a.get(0)
+            .w1(1)
+            .get(pos)
+            .op(I::I32Add)
+            .get(cl)
+            .call("$sb_push");
+        a.get(pos)
+            .get(cl)
+            .op(I::I32Add)
+            .tee(pos)
+            .w2(1)
+            .op(I::I32GeU)
+            .if_(|a| {
+                a.i32(0).set(pos);
+            });
+    });
+}
+
+pub(super) fn repeat(a: &mut Asm) {
+    let (n, i, sb) = (a.i32_local(), a.i32_local(), a.i32_local());
+    a.get(1).call("$count").set(n);
+    a.call("$sb_new").set(sb);
+    a.for_(i, n, |a| {
+        a.get(sb).get(0).call("$sb_str");
+    });
+    a.get(sb).call("$sb_finish");
+}
+
+pub(super) fn join(a: &mut Asm) {
+    let (n, i, sb) = (a.i32_local(), a.i32_local(), a.i32_local());
+    a.call("$sb_new").set(sb);
+    a.w2(0).set(n);
+    a.for_(i, n, |a| {
+        a.get(i).if_(|a| {
+            a.get(sb).get(1).call("$sb_str");
+        });
+        a.get(sb)
+            .w1(0)
+            .get(i)
+            .i32(4)
+            .op(I::I32Mul)
+            .op(I::I32Add)
+            .load(0)
+            .call("$display_into");
+    });
+    a.get(sb).call("$sb_finish");
+}
+
+/// `string.format(template, values)` — `{key}` placeholders are replaced by
+/// the display form of `values.key`; unknown keys and an unclosed `{` are
+/// kept verbatim.
+pub(super) fn format(a: &mut Asm) {
+    let (p, n, i, j, sb, e) = (
+        a.i32_local(),
+        a.i32_local(),
+        a.i32_local(),
+        a.i32_local(),
+        a.i32_local(),
+        a.i32_local(),
+    );
+    a.w1(0).set(p);
+    a.w2(0).set(n);
+    a.i32(0).set(i);
+    a.call("$sb_new").set(sb);
+    a.while_(
+        |a| {
+            a.get(i).get(n).op(I::I32LtU);
+        },
+        |a| {
+            a.get(p)
+                .get(i)
+                .op(I::I32Add)
+                .load8(0)
+                .i32(b'{' as i32)
+                .op(I::I32Eq)
+                .if_else(
+                    BlockType::Empty,
+                    |a| {
+                        a.get(i).i32(1).op(I::I32Add).set(j);
+                        a.while_(
+                            |a| {
+                                a.get(j).get(n).op(I::I32LtU).select_i32(
+                                    |a| {
+                                        a.get(p)
+                                            .get(j)
+                                            .op(I::I32Add)
+                                            .load8(0)
+                                            .i32(b'}' as i32)
+                                            .op(I::I32Ne);
+                                    },
+                                    |a| {
+                                        a.i32(0);
+                                    },
+                                );
+                            },
+                            |a| {
+                                a.get(j).i32(1).op(I::I32Add).set(j);
+                            },
+                        );
+                        a.get(j).get(n).op(I::I32GeU).if_(|a| {
+                            a.get(sb)
+                                .get(p)
+                                .get(i)
+                                .op(I::I32Add)
+                                .get(n)
+                                .get(i)
+                                .op(I::I32Sub)
+                                .call("$sb_push");
+                            a.get(n).set(i);
+                            a.brk();
+                        });
+                        a.get(1).get(p).get(i).op(I::I32Add).i32(1).op(I::I32Add);
+                        a.get(j)
+                            .get(i)
+                            .op(I::I32Sub)
+                            .i32(1)
+                            .op(I::I32Sub)
+                            .call("$record_find")
+                            .tee(e);
+                        a.if_else(
+                            BlockType::Empty,
+                            |a| {
+                                a.get(sb).get(e).load(8).call("$display_into");
+                            },
+                            |a| {
+                                a.get(sb)
+                                    .get(p)
+                                    .get(i)
+                                    .op(I::I32Add)
+                                    .get(j)
+                                    .get(i)
+                                    .op(I::I32Sub)
+                                    .i32(1)
+                                    .op(I::I32Add);
+                                a.call("$sb_push");
+                            },
+                        );
+                        a.get(j).i32(1).op(I::I32Add).set(i);
+                    },
+                    |a| {
+                        a.get(sb)
+                            .get(p)
+                            .get(i)
+                            .op(I::I32Add)
+                            .load8(0)
+                            .call("$sb_byte");
+                        a.get(i).i32(1).op(I::I32Add).set(i);
+                    },
+                );
+        },
+    );
+    a.get(sb).call("$sb_finish");
+}
+
+pub(super) fn from(a: &mut Asm) {
+    a.get(0).call("$display");
+}
+
+pub(super) fn is_empty(a: &mut Asm) {
+    a.w2(0).op(I::I32Eqz).bool();
+}
+
+/// `string.index_of(s, sub)` — char index of the first match, or -1.
+pub(super) fn index_of(a: &mut Asm) {
+    let at = a.i32_local();
+    a.get(0).get(1).i32(0).call("$str_find").tee(at);
+    a.i32(0).op(I::I32LtS).if_(|a| {
+        a.f64(-1.0).number().ret();
+    });
+    a.w1(0)
+        .get(at)
+        .call("$char_count")
+        .op(I::F64ConvertI32U)
+        .number();
+}
@@ -4,14 +4,17 @@
 //! or have side effects like `set`).
 
 use pepl_types::ast::*;
+use pepl_types::{ErrorCode, Span};
 use wasm_encoder::{BlockType, Function, Instruction, ValType};
 
 use crate::compiler::FuncContext;
 use crate::error::CodegenResult;
-use crate::expr::emit_expr;
+use crate::expr::{alloc_loop_index, emit_expr, emit_loop_index};
 use crate::gas;
+use crate::layout;
 use crate::runtime::*;
 use crate::types::*;
+use crate::unbox::{emit_scalar, native_scalar, Scalar};
 
 /// Emit a slice of statements.
 pub fn emit_stmts(stmts: &[Stmt], ctx: &mut FuncContext, f: &mut Function) -> CodegenResult<()> {
@@ -23,15 +26,35 @@ pub fn emit_stmts(stmts: &[Stmt], ctx: &mut FuncContext, f: &mut Function) -> Co
 
 /// Emit a single statement.
 pub fn emit_stmt(stmt: &Stmt, ctx: &mut FuncContext, f: &mut Function) -> CodegenResult<()> {
+    // Record the statement as the current trap site
+    let code = matches!(stmt, Stmt::Assert(_)).then_some(ErrorCode::ASSERTION_FAILED);
+    ctx.mark_site(stmt_span(stmt), code, f);
+    gas::charge_stmt(stmt, ctx);
     match stmt {
-        Stmt::Set(set) => emit_set(set, ctx, f),
-        Stmt::Let(let_bind) => emit_let(let_bind, ctx, f),
-        Stmt::If(if_expr) => emit_if_stmt(if_expr, ctx, f),
-        Stmt::For(for_expr) => emit_for_stmt(for_expr, ctx, f),
-        Stmt::Match(match_expr) => emit_match_stmt(match_expr, ctx, f),
-        Stmt::Return(_) => emit_return(f),
-        Stmt::Assert(assert_stmt) => emit_assert(assert_stmt, ctx, f),
-        Stmt::Expr(expr_stmt) => emit_expr_stmt(&expr_stmt.expr, ctx, f),
+        Stmt::Set(set) => emit_set(set, ctx, f)?,
+        Stmt::Let(let_bind) => emit_let(let_bind, ctx, f)?,
+        Stmt::If(if_expr) => emit_if_stmt(if_expr, ctx, f)?,
+        Stmt::For(for_expr) => emit_for_stmt(for_expr, ctx, f)?,
+        Stmt::Match(match_expr) => emit_match_stmt(match_expr, ctx, f)?,
+        Stmt::Return(_) => emit_return(ctx, f)?,
+        Stmt::Assert(assert_stmt) => emit_assert(assert_stmt, ctx, f)?,
+        Stmt::Expr(expr_stmt) => emit_expr_stmt(&expr_stmt.expr, ctx, f)?,
+    }
+    gas::flush(ctx, f);
+    Ok(())
+}
+
+/// Source span of a statement.
+pub fn stmt_span(stmt: &Stmt) -> Span {
+    match stmt {
+        Stmt::Set(set) => set.span,
+        Stmt::Let(let_bind) => let_bind.span,
+        Stmt::If(if_expr) => if_expr.span,
+        Stmt::For(for_expr) => for_expr.span,
+        Stmt::Match(match_expr) => match_expr.span,
+        Stmt::Return(ret) => ret.span,
+        Stmt::Assert(assert_stmt) => assert_stmt.span,
+        Stmt::Expr(expr_stmt) => expr_stmt.span,
     }
 }
 
@@ -71,56 +94,37 @@ pub(crate) fn emit_state_field_set(
     ctx: &mut FuncContext,
     f: &mut Function,
 ) -> CodegenResult<()> {
-    // We rebuild the state record with the updated field.
-    // This is expensive but correct for the immutable-update model.
-    // Strategy: iterate state fields, for each field:
-    //   if name matches → use val_local
-    //   else → copy from old state
-
-    let state_fields = ctx.state_field_names.clone();
-    let field_count = state_fields.len();
+    // We rebuild the state record with the updated field (immutable-update
+    // model): copy every entry of the old record, then overwrite the field's
+    // slot.  The state record always holds all slots in declaration order.
+    let field_count = ctx.state_field_names.len() as i32;
+    let slot = ctx
+        .state_slot(field_name)
+        .expect("set target is a state or derived field");
     let entries_local = ctx.alloc_local(ValType::I32);
 
     // Allocate entries array
-    f.instruction(&Instruction::I32Const((field_count * 12) as i32));
+    f.instruction(&Instruction::I32Const(field_count * 12));
     f.instruction(&Instruction::Call(rt_func_idx(RT_ALLOC)));
-    f.instruction(&Instruction::LocalSet(entries_local));
-
-    for (i, sf) in state_fields.iter().enumerate() {
-        let (key_ptr, key_len) = ctx.intern_string(sf);
-        let base = (i * 12) as u64;
-
-        // key_offset
-        f.instruction(&Instruction::LocalGet(entries_local));
-        f.instruction(&Instruction::I32Const(key_ptr as i32));
-        f.instruction(&Instruction::I32Store(memarg(base, 2)));
-        // key_len
-        f.instruction(&Instruction::LocalGet(entries_local));
-        f.instruction(&Instruction::I32Const(key_len as i32));
-        f.instruction(&Instruction::I32Store(memarg(base + 4, 2)));
-
-        // value: either the new value or the old one
-        if sf == field_name {
-            f.instruction(&Instruction::LocalGet(entries_local));
-            f.instruction(&Instruction::LocalGet(val_local));
-            f.instruction(&Instruction::I32Store(memarg(base + 8, 2)));
-        } else {
-            // Read from old state
-            let old_val = ctx.alloc_local(ValType::I32);
-            f.instruction(&Instruction::GlobalGet(GLOBAL_STATE_PTR));
-            f.instruction(&Instruction::I32Const(key_ptr as i32));
-            f.instruction(&Instruction::I32Const(key_len as i32));
-            f.instruction(&Instruction::Call(rt_func_idx(RT_VAL_RECORD_GET)));
-            f.instruction(&Instruction::LocalSet(old_val));
-            f.instruction(&Instruction::LocalGet(entries_local));
-            f.instruction(&Instruction::LocalGet(old_val));
-            f.instruction(&Instruction::I32Store(memarg(base + 8, 2)));
-        }
-    }
+    f.instruction(&Instruction::LocalTee(entries_local));
+
+    // Copy keys and current values from the old state
+    f.instruction(&Instruction::GlobalGet(GLOBAL_STATE_PTR));
+    f.instruction(&Instruction::I32Load(memarg(4, 2))); // entries (w1)
+    f.instruction(&Instruction::I32Const(field_count * 12));
+    f.instruction(&Instruction::MemoryCopy {
+        src_mem: 0,
+        dst_mem: 0,
+    });
+
+    // New value into the field's slot
+    f.instruction(&Instruction::LocalGet(entries_local));
+    f.instruction(&Instruction::LocalGet(val_local));
+    f.instruction(&Instruction::I32Store(memarg(layout::entry_offset(slot) + 8, 2)));
 
     // Build new state record
     f.instruction(&Instruction::LocalGet(entries_local));
-    f.instruction(&Instruction::I32Const(field_count as i32));
+    f.instruction(&Instruction::I32Const(field_count));
     f.instruction(&Instruction::Call(rt_func_idx(RT_VAL_RECORD)));
     f.instruction(&Instruction::GlobalSet(GLOBAL_STATE_PTR));
 
@@ -147,19 +151,28 @@ fn emit_nested_set(
     let mut intermediates: Vec<u32> = Vec::with_capacity(depth - 1);
 
     for i in 0..depth - 1 {
-        let (key_ptr, key_len) = ctx.intern_string(&target[i].name);
-        let record_local = ctx.alloc_local(ValType::I32);
-
+        let field = &target[i].name;
         if i == 0 {
             // First level reads from global state
+            let record_local = ctx.alloc_local(ValType::I32);
+            let slot = ctx
+                .state_slot(field)
+                .expect("set target is a state field");
             f.instruction(&Instruction::GlobalGet(GLOBAL_STATE_PTR));
-        } else {
-            // Subsequent levels read from the previous intermediate
-            f.instruction(&Instruction::LocalGet(intermediates[i - 1]));
+            layout::emit_fixed_load(slot, f);
+            f.instruction(&Instruction::LocalSet(record_local));
+            intermediates.push(record_local);
+            continue;
+        }
+
+        // Subsequent levels read from the previous intermediate, at a fixed
+        // slot when the checker knows that record's layout
+        let parent = intermediates[i - 1];
+        match record_slot(&target[i - 1], field, ctx) {
+            Some(slot) => layout::emit_slot_load(parent, slot, field, ctx, f),
+            None => layout::emit_key_lookup(parent, field, ctx, f),
         }
-        f.instruction(&Instruction::I32Const(key_ptr as i32));
-        f.instruction(&Instruction::I32Const(key_len as i32));
-        f.instruction(&Instruction::Call(rt_func_idx(RT_VAL_RECORD_GET)));
+        let record_local = ctx.alloc_local(ValType::I32);
         f.instruction(&Instruction::LocalSet(record_local));
 
         intermediates.push(record_local);
@@ -173,9 +186,10 @@ fn emit_nested_set(
     for i in (0..depth - 1).rev() {
         let old_record = intermediates[i];
         let field_to_replace = &target[i + 1].name;
+        let slot = record_slot(&target[i], field_to_replace, ctx);
 
         current_val =
-            emit_record_field_replace(old_record, field_to_replace, current_val, ctx, f)?;
+            emit_record_field_replace(old_record, field_to_replace, slot, current_val, ctx, f)?;
     }
 
     // Phase 3: Set the root state field to the fully-rebuilt record.
@@ -184,13 +198,21 @@ fn emit_nested_set(
     Ok(())
 }
 
+/// Checked slot of `field` in the record at set-path identifier `record`.
+fn record_slot(record: &Ident, field: &str, ctx: &FuncContext) -> Option<u32> {
+    ctx.layouts.get(record.span).and_then(|l| l.slot(field))
+}
+
 /// Clone a record with one field replaced, returning the local holding the new record.
 ///
 /// Copies all entries from `old_record_local`, replacing the entry whose key matches
 /// `field_name` with `new_val_local`. Returns the local index of the new record pointer.
+/// With a checked `slot`, a record whose entry there has the key is copied
+/// whole and the slot overwritten; otherwise every key is compared.
 fn emit_record_field_replace(
     old_record_local: u32,
     field_name: &str,
+    slot: Option<u32>,
     new_val_local: u32,
     ctx: &mut FuncContext,
     f: &mut Function,
@@ -213,6 +235,24 @@ fn emit_record_field_replace(
     f.instruction(&Instruction::Call(rt_func_idx(RT_ALLOC)));
     f.instruction(&Instruction::LocalSet(new_entries));
 
+    if let Some(slot) = slot {
+        layout::emit_slot_matches(old_record_local, slot, field_name, ctx, f);
+        f.instruction(&Instruction::If(BlockType::Empty));
+        f.instruction(&Instruction::LocalGet(new_entries));
+        f.instruction(&Instruction::LocalGet(old_entries_ptr));
+        f.instruction(&Instruction::LocalGet(old_count));
+        f.instruction(&Instruction::I32Const(12));
+        f.instruction(&Instruction::I32Mul);
+        f.instruction(&Instruction::MemoryCopy {
+            src_mem: 0,
+            dst_mem: 0,
+        });
+        f.instruction(&Instruction::LocalGet(new_entries));
+        f.instruction(&Instruction::LocalGet(new_val_local));
+        f.instruction(&Instruction::I32Store(memarg(layout::entry_offset(slot) + 8, 2)));
+        f.instruction(&Instruction::Else);
+    }
+
     // Intern the target field key
     let (field_key_ptr, field_key_len) = ctx.intern_string(field_name);
 
@@ -300,6 +340,9 @@ fn emit_record_field_replace(
 
     f.instruction(&Instruction::End); // end loop
     f.instruction(&Instruction::End); // end block
+    if slot.is_some() {
+        f.instruction(&Instruction::End); // end slot check
+    }
 
     // Build new record from entries
     let new_record = ctx.alloc_local(ValType::I32);
@@ -316,6 +359,15 @@ fn emit_record_field_replace(
 // ══════════════════════════════════════════════════════════════════════════════
 
 fn emit_let(let_bind: &LetBinding, ctx: &mut FuncContext, f: &mut Function) -> CodegenResult<()> {
+    // A natively computed number/bool stays unboxed in its local
+    if let (Some(ident), Some(scalar)) = (&let_bind.name, native_scalar(&let_bind.value, ctx)) {
+        emit_scalar(&let_bind.value, scalar, ctx, f)?;
+        let local = ctx.alloc_unboxed_local(scalar);
+        f.instruction(&Instruction::LocalSet(local));
+        ctx.push_local(&ident.name, local);
+        return Ok(());
+    }
+
     emit_expr(&let_bind.value, ctx, f)?;
 
     match &let_bind.name {
@@ -338,8 +390,8 @@ fn emit_let(let_bind: &LetBinding, ctx: &mut FuncContext, f: &mut Function) -> C
 
 fn emit_if_stmt(if_expr: &IfExpr, ctx: &mut FuncContext, f: &mut Function) -> CodegenResult<()> {
     // Evaluate condition
-    emit_expr(&if_expr.condition, ctx, f)?;
-    f.instruction(&Instruction::I32Load(memarg(4, 2)));
+    emit_scalar(&if_expr.condition, Scalar::Bool, ctx, f)?;
+    gas::flush(ctx, f);
 
     f.instruction(&Instruction::If(BlockType::Empty));
     emit_stmts(&if_expr.then_block.stmts, ctx, f)?;
@@ -351,6 +403,7 @@ fn emit_if_stmt(if_expr: &IfExpr, ctx: &mut FuncContext, f: &mut Function) -> Co
         }
         Some(ElseBranch::ElseIf(elif)) => {
             f.instruction(&Instruction::Else);
+            gas::charge(pepl_types::gas::ELSE_IF, ctx);
             emit_if_stmt(elif, ctx, f)?;
         }
         None => {}
@@ -382,26 +435,22 @@ fn emit_for_stmt(for_expr: &ForExpr, ctx: &mut FuncContext, f: &mut Function) ->
     f.instruction(&Instruction::LocalSet(i_local));
 
     let item_local = ctx.alloc_local(ValType::I32);
-    let index_local = if for_expr.index.is_some() {
-        Some(ctx.alloc_local(ValType::I32))
-    } else {
-        None
-    };
+    let index_local = for_expr.index.as_ref().map(|_| alloc_loop_index(ctx));
 
     ctx.push_local(&for_expr.item.name, item_local);
     if let (Some(idx_ident), Some(idx_local)) = (&for_expr.index, index_local) {
         ctx.push_local(&idx_ident.name, idx_local);
     }
 
+    gas::flush(ctx, f);
     f.instruction(&Instruction::Block(BlockType::Empty));
     f.instruction(&Instruction::Loop(BlockType::Empty));
 
-    gas::emit_gas_tick(f, ctx.data.gas_exhausted_ptr, ctx.data.gas_exhausted_len);
-
     f.instruction(&Instruction::LocalGet(i_local));
     f.instruction(&Instruction::LocalGet(count_local));
     f.instruction(&Instruction::I32GeU);
     f.instruction(&Instruction::BrIf(1));
+    gas::charge(pepl_types::gas::FOR_ITERATION, ctx);
 
     f.instruction(&Instruction::LocalGet(arr_local));
     f.instruction(&Instruction::LocalGet(i_local));
@@ -412,18 +461,11 @@ fn emit_for_stmt(for_expr: &ForExpr, ctx: &mut FuncContext, f: &mut Function) ->
     f.instruction(&Instruction::LocalSet(item_local));
 
     if let Some(idx_local) = index_local {
-        f.instruction(&Instruction::I32Const(VALUE_SIZE as i32));
-        f.instruction(&Instruction::Call(rt_func_idx(RT_ALLOC)));
-        f.instruction(&Instruction::LocalTee(idx_local));
-        f.instruction(&Instruction::I32Const(TAG_NUMBER));
-        f.instruction(&Instruction::I32Store(memarg(0, 2)));
-        f.instruction(&Instruction::LocalGet(idx_local));
-        f.instruction(&Instruction::LocalGet(i_local));
-        f.instruction(&Instruction::F64ConvertI32U);
-        f.instruction(&Instruction::F64Store(memarg(4, 3)));
+        emit_loop_index(i_local, idx_local, ctx, f);
     }
 
     emit_stmts(&for_expr.body.stmts, ctx, f)?;
+    gas::flush(ctx, f);
 
     f.instruction(&Instruction::LocalGet(i_local));
     f.instruction(&Instruction::I32Const(1));
@@ -448,19 +490,13 @@ fn emit_match_stmt(
     f: &mut Function,
 ) -> CodegenResult<()> {
     // Emit as expr then drop the result
-    crate::expr::emit_expr(
-        &Expr::new(
-            ExprKind::Match(Box::new(match_expr.clone())),
-            match_expr.span,
-        ),
-        ctx,
-        f,
-    )?;
+    crate::expr::emit_match_expr(match_expr, ctx, f)?;
     f.instruction(&Instruction::Drop);
     Ok(())
 }
 
-fn emit_return(f: &mut Function) -> CodegenResult<()> {
+fn emit_return(ctx: &mut FuncContext, f: &mut Function) -> CodegenResult<()> {
+    gas::flush(ctx, f);
     // In dispatch_action (returns i32), we need a value on the stack.
     // Push a nil value as the return value for early return.
     f.instruction(&Instruction::Call(rt_func_idx(RT_VAL_NIL)));
@@ -473,10 +509,8 @@ fn emit_assert(
     ctx: &mut FuncContext,
     f: &mut Function,
 ) -> CodegenResult<()> {
-    // Evaluate condition
-    emit_expr(&assert_stmt.condition, ctx, f)?;
-    // Extract bool
-    f.instruction(&Instruction::I32Load(memarg(4, 2)));
+    // Evaluate condition as a native bool
+    emit_scalar(&assert_stmt.condition, Scalar::Bool, ctx, f)?;
     f.instruction(&Instruction::I32Eqz);
     f.instruction(&Instruction::If(BlockType::Empty));
 
@@ -8,17 +8,51 @@
 //!
 //! The host calls `__test_count()` to discover how many tests exist,
 //! then `__test_N()` (N = 0, 1, ...) to run each.
+//!
+//! ## Surface assertions
+//!
+//! - `let s = render(view)` calls the exported `render(view_id)`
+//! - `assert_component(s, name, props?)` and `count_components(s, name)`
+//!   walk the tree with the internal `__surface_count` helper
+//! - `assert_snapshot(s, name)` calls
+//!   `host_call(TEST_HOOK_MODULE_ID, TEST_HOOK_SNAPSHOT, [s, name])`;
+//!   the host compares against its golden file and traps on mismatch
+//!
+//! ## Game loop
+//!
+//! - `tick(dt)` calls the space's `update(dt)`; `tick_n(n, dt)` calls it `n` times
+//! - `send_event(event)` calls `handle_event(event)` with a record value
 
 use std::collections::HashMap;
 use wasm_encoder::{Function, Instruction};
 
 use crate::compiler::FuncContext;
 use crate::error::CodegenResult;
-use crate::runtime::memarg;
+use crate::runtime::{memarg, rt_func_idx, RT_ALLOC, RT_VAL_EQ, RT_VAL_LIST, RT_VAL_RECORD_GET};
 use crate::types::*;
 
 use pepl_types::ast::*;
 
+/// `host_call` module id reserved for test-runner hooks.
+pub const TEST_HOOK_MODULE_ID: i32 = 200;
+
+/// `assert_snapshot(surface, name)` hook — args list `[surface, name]`.
+pub const TEST_HOOK_SNAPSHOT: i32 = 1;
+
+/// Function indices and view ids used by test-only built-ins.
+pub struct TestFuncs<'a> {
+    /// View name → `render(view_id)` id.
+    pub views: &'a HashMap<String, u32>,
+    /// Index of the exported `render` function.
+    pub render_func_idx: u32,
+    /// Index of `__surface_count(nodes, name, props) -> i32`.
+    pub surface_count_idx: u32,
+    /// Index of `update(dt_ptr)`, if the space declares one.
+    pub update_func_idx: Option<u32>,
+    /// Index of `handle_event(event_ptr)`, if the space declares one.
+    pub handle_event_func_idx: Option<u32>,
+}
+
 /// Compile a single test body into WASM instructions.
 ///
 /// Emits:
@@ -29,6 +63,7 @@ pub fn emit_test_body(
     actions: &HashMap<String, u32>,
     dispatch_func_idx: u32,
     init_func_idx: u32,
+    funcs: &TestFuncs,
     ctx: &mut FuncContext,
     f: &mut Function,
 ) -> CodegenResult<()> {
@@ -37,7 +72,9 @@ pub fn emit_test_body(
 
     // Compile each test statement
     for stmt in &body.stmts {
-        emit_test_stmt(stmt, actions, dispatch_func_idx, ctx, f)?;
+        ctx.mark_site(crate::stmt::stmt_span(stmt), None, f);
+        emit_test_stmt(stmt, actions, dispatch_func_idx, funcs, ctx, f)?;
+        crate::gas::flush(ctx, f);
     }
     Ok(())
 }
@@ -47,6 +84,7 @@ fn emit_test_stmt(
     stmt: &Stmt,
     actions: &HashMap<String, u32>,
     dispatch_func_idx: u32,
+    funcs: &TestFuncs,
     ctx: &mut FuncContext,
     f: &mut Function,
 ) -> CodegenResult<()> {
@@ -54,6 +92,25 @@ fn emit_test_stmt(
         Stmt::Expr(expr_stmt) => {
             if is_action_call(&expr_stmt.expr, actions) {
                 emit_action_dispatch(&expr_stmt.expr, actions, dispatch_func_idx, f)
+            } else if let ExprKind::Call { name, args } = &expr_stmt.expr.kind {
+                match name.name.as_str() {
+                    "assert_component" => emit_assert_component(args, funcs, ctx, f),
+                    "assert_snapshot" => emit_assert_snapshot(args, ctx, f),
+                    "tick" => emit_tick(args, None, funcs.update_func_idx, ctx, f),
+                    "tick_n" => emit_tick(
+                        args.get(1..).unwrap_or(&[]),
+                        args.first(),
+                        funcs.update_func_idx,
+                        ctx,
+                        f,
+                    ),
+                    "send_event" => emit_send_event(args, funcs.handle_event_func_idx, ctx, f),
+                    _ => {
+                        crate::expr::emit_expr(&expr_stmt.expr, ctx, f)?;
+                        f.instruction(&Instruction::Drop);
+                        Ok(())
+                    }
+                }
             } else {
                 crate::expr::emit_expr(&expr_stmt.expr, ctx, f)?;
                 f.instruction(&Instruction::Drop);
@@ -62,7 +119,18 @@ fn emit_test_stmt(
         }
         Stmt::Assert(assert_stmt) => emit_test_assert(assert_stmt, ctx, f),
         Stmt::Let(binding) => {
-            crate::expr::emit_expr(&binding.value, ctx, f)?;
+            match &binding.value.kind {
+                ExprKind::Call { name, args } if name.name == "render" => {
+                    let view = match args.first().map(|a| &a.kind) {
+                        Some(ExprKind::Identifier(view)) => view.as_str(),
+                        _ => "main",
+                    };
+                    let view_id = funcs.views.get(view).copied().unwrap_or(0);
+                    f.instruction(&Instruction::I32Const(view_id as i32));
+                    f.instruction(&Instruction::Call(funcs.render_func_idx));
+                }
+                _ => crate::expr::emit_expr(&binding.value, ctx, f)?,
+            }
             if let Some(name) = &binding.name {
                 let local = ctx.alloc_local(wasm_encoder::ValType::I32);
                 f.instruction(&Instruction::LocalSet(local));
@@ -126,6 +194,353 @@ fn emit_test_assert(
     Ok(())
 }
 
+/// Compile `tick(dt)` / `tick_n(n, dt)` — call `update(dt)` once, or `n` times.
+///
+/// `args` holds `dt`; `frames` is the `n` expression for `tick_n`.
+fn emit_tick(
+    args: &[Expr],
+    frames: Option<&Expr>,
+    update_func_idx: Option<u32>,
+    ctx: &mut FuncContext,
+    f: &mut Function,
+) -> CodegenResult<()> {
+    let Some(update_idx) = update_func_idx else {
+        // Rejected by the type checker; nothing to drive.
+        return Ok(());
+    };
+    let Some(dt) = args.first() else {
+        return Ok(());
+    };
+
+    let Some(frames) = frames else {
+        crate::expr::emit_expr(dt, ctx, f)?;
+        f.instruction(&Instruction::Call(update_idx));
+        return Ok(());
+    };
+
+    // frames and dt are evaluated once, in argument order
+    let frames_local = ctx.alloc_local(wasm_encoder::ValType::F64);
+    crate::expr::emit_expr(frames, ctx, f)?;
+    f.instruction(&Instruction::F64Load(memarg(4, 3)));
+    f.instruction(&Instruction::LocalSet(frames_local));
+
+    let dt_local = ctx.alloc_local(wasm_encoder::ValType::I32);
+    crate::expr::emit_expr(dt, ctx, f)?;
+    f.instruction(&Instruction::LocalSet(dt_local));
+
+    // Like the evaluator, reject a negative or non-integer frame count
+    // before truncating it: !(frames >= 0 && frames - trunc(frames) == 0)
+    // (NaN and infinities fail the second comparison)
+    f.instruction(&Instruction::LocalGet(frames_local));
+    f.instruction(&Instruction::F64Const(0.0));
+    f.instruction(&Instruction::F64Ge);
+    f.instruction(&Instruction::LocalGet(frames_local));
+    f.instruction(&Instruction::LocalGet(frames_local));
+    f.instruction(&Instruction::F64Trunc);
+    f.instruction(&Instruction::F64Sub);
+    f.instruction(&Instruction::F64Const(0.0));
+    f.instruction(&Instruction::F64Eq);
+    f.instruction(&Instruction::I32And);
+    f.instruction(&Instruction::I32Eqz);
+    f.instruction(&Instruction::If(wasm_encoder::BlockType::Empty));
+    let (msg_ptr, msg_len) = ctx.intern_string("invalid arguments to tick_n()");
+    f.instruction(&Instruction::I32Const(msg_ptr as i32));
+    f.instruction(&Instruction::I32Const(msg_len as i32));
+    f.instruction(&Instruction::Call(IMPORT_TRAP));
+    f.instruction(&Instruction::End);
+
+    // n = i32(frames)
+    let n_local = ctx.alloc_local(wasm_encoder::ValType::I32);
+    f.instruction(&Instruction::LocalGet(frames_local));
+    f.instruction(&Instruction::I32TruncF64S);
+    f.instruction(&Instruction::LocalSet(n_local));
+
+    let i_local = ctx.alloc_local(wasm_encoder::ValType::I32);
+    f.instruction(&Instruction::I32Const(0));
+    f.instruction(&Instruction::LocalSet(i_local));
+    f.instruction(&Instruction::Block(wasm_encoder::BlockType::Empty));
+    f.instruction(&Instruction::Loop(wasm_encoder::BlockType::Empty));
+    f.instruction(&Instruction::LocalGet(i_local));
+    f.instruction(&Instruction::LocalGet(n_local));
+    f.instruction(&Instruction::I32GeS);
+    f.instruction(&Instruction::BrIf(1));
+    f.instruction(&Instruction::LocalGet(dt_local));
+    f.instruction(&Instruction::Call(update_idx));
+    f.instruction(&Instruction::LocalGet(i_local));
+    f.instruction(&Instruction::I32Const(1));
+    f.instruction(&Instruction::I32Add);
+    f.instruction(&Instruction::LocalSet(i_local));
+    f.instruction(&Instruction::Br(0));
+    f.instruction(&Instruction::End);
+    f.instruction(&Instruction::End);
+    Ok(())
+}
+
+/// Compile `send_event(event)` — call `handle_event(event)`.
+fn emit_send_event(
+    args: &[Expr],
+    handle_event_func_idx: Option<u32>,
+    ctx: &mut FuncContext,
+    f: &mut Function,
+) -> CodegenResult<()> {
+    let (Some(handler_idx), Some(event)) = (handle_event_func_idx, args.first()) else {
+        return Ok(());
+    };
+    crate::expr::emit_expr(event, ctx, f)?;
+    f.instruction(&Instruction::Call(handler_idx));
+    Ok(())
+}
+
+/// Compile `assert_component(surface, name, props?)` — traps unless at least
+/// one node matches.
+fn emit_assert_component(
+    args: &[Expr],
+    funcs: &TestFuncs,
+    ctx: &mut FuncContext,
+    f: &mut Function,
+) -> CodegenResult<()> {
+    for arg in args.iter().take(3) {
+        crate::expr::emit_expr(arg, ctx, f)?;
+    }
+    for _ in args.len()..3 {
+        f.instruction(&Instruction::I32Const(0)); // no props filter
+    }
+    f.instruction(&Instruction::Call(funcs.surface_count_idx));
+    f.instruction(&Instruction::I32Eqz);
+
+    f.instruction(&Instruction::If(wasm_encoder::BlockType::Empty));
+    let msg = match args.get(1).map(|a| &a.kind) {
+        Some(ExprKind::StringLit(component)) => {
+            format!("no component matching {component} in rendered surface")
+        }
+        _ => "no matching component in rendered surface".to_string(),
+    };
+    let (msg_ptr, msg_len) = ctx.intern_string(&msg);
+    f.instruction(&Instruction::I32Const(msg_ptr as i32));
+    f.instruction(&Instruction::I32Const(msg_len as i32));
+    f.instruction(&Instruction::Call(IMPORT_TRAP));
+    f.instruction(&Instruction::End);
+    Ok(())
+}
+
+/// Compile `assert_snapshot(surface, name)` as a host test hook.
+fn emit_assert_snapshot(
+    args: &[Expr],
+    ctx: &mut FuncContext,
+    f: &mut Function,
+) -> CodegenResult<()> {
+    let arr_local = ctx.alloc_local(wasm_encoder::ValType::I32);
+    let count = args.len() as i32;
+    f.instruction(&Instruction::I32Const(count * 4));
+    f.instruction(&Instruction::Call(rt_func_idx(RT_ALLOC)));
+    f.instruction(&Instruction::LocalSet(arr_local));
+    for (i, arg) in args.iter().enumerate() {
+        let tmp = ctx.alloc_local(wasm_encoder::ValType::I32);
+        crate::expr::emit_expr(arg, ctx, f)?;
+        f.instruction(&Instruction::LocalSet(tmp));
+        f.instruction(&Instruction::LocalGet(arr_local));
+        f.instruction(&Instruction::LocalGet(tmp));
+        f.instruction(&Instruction::I32Store(memarg(i as u64 * 4, 2)));
+    }
+
+    f.instruction(&Instruction::I32Const(TEST_HOOK_MODULE_ID));
+    f.instruction(&Instruction::I32Const(TEST_HOOK_SNAPSHOT));
+    f.instruction(&Instruction::LocalGet(arr_local));
+    f.instruction(&Instruction::I32Const(count));
+    f.instruction(&Instruction::Call(rt_func_idx(RT_VAL_LIST)));
+    f.instruction(&Instruction::Call(IMPORT_HOST_CALL));
+    f.instruction(&Instruction::Drop);
+    Ok(())
+}
+
+/// Emit `__surface_count(nodes, name, props) -> i32`.
+///
+/// Counts records in the (possibly nested) node list whose `component`
+/// equals `name` and whose `props` contain every entry of `props`
+/// (`props == 0` matches any props). Recurses into `children` and into
+/// nested lists produced by UI `if`/`for`.
+pub fn emit_surface_count(self_idx: u32, ctx: &mut FuncContext) -> Function {
+    // params: 0 = nodes, 1 = name, 2 = props
+    const I: u32 = 3;
+    const N: u32 = 4;
+    const COUNT: u32 = 5;
+    const NODE: u32 = 6;
+    const MATCHED: u32 = 7;
+    const J: u32 = 8;
+    const ENTRY: u32 = 9;
+    const NODE_PROPS: u32 = 10;
+    let mut f = Function::new(vec![(8, wasm_encoder::ValType::I32)]);
+    let (comp_ptr, comp_len) = ctx.intern_string("component");
+    let (props_ptr, props_len) = ctx.intern_string("props");
+    let (children_ptr, children_len) = ctx.intern_string("children");
+
+    // Not a list → nothing to count
+    f.instruction(&Instruction::LocalGet(0));
+    f.instruction(&Instruction::I32Load(memarg(0, 2)));
+    f.instruction(&Instruction::I32Const(TAG_LIST));
+    f.instruction(&Instruction::I32Ne);
+    f.instruction(&Instruction::If(wasm_encoder::BlockType::Empty));
+    f.instruction(&Instruction::I32Const(0));
+    f.instruction(&Instruction::Return);
+    f.instruction(&Instruction::End);
+
+    f.instruction(&Instruction::LocalGet(0));
+    f.instruction(&Instruction::I32Load(memarg(8, 2)));
+    f.instruction(&Instruction::LocalSet(N));
+
+    f.instruction(&Instruction::Block(wasm_encoder::BlockType::Empty));
+    f.instruction(&Instruction::Loop(wasm_encoder::BlockType::Empty));
+    f.instruction(&Instruction::LocalGet(I));
+    f.instruction(&Instruction::LocalGet(N));
+    f.instruction(&Instruction::I32GeU);
+    f.instruction(&Instruction::BrIf(1));
+
+    // node = arr[i]
+    f.instruction(&Instruction::LocalGet(0));
+    f.instruction(&Instruction::I32Load(memarg(4, 2)));
+    f.instruction(&Instruction::LocalGet(I));
+    f.instruction(&Instruction::I32Const(4));
+    f.instruction(&Instruction::I32Mul);
+    f.instruction(&Instruction::I32Add);
+    f.instruction(&Instruction::I32Load(memarg(0, 2)));
+    f.instruction(&Instruction::LocalSet(NODE));
+
+    // Nested list (UI if/for) → recurse
+    f.instruction(&Instruction::LocalGet(NODE));
+    f.instruction(&Instruction::I32Load(memarg(0, 2)));
+    f.instruction(&Instruction::I32Const(TAG_LIST));
+    f.instruction(&Instruction::I32Eq);
+    f.instruction(&Instruction::If(wasm_encoder::BlockType::Empty));
+    f.instruction(&Instruction::LocalGet(COUNT));
+    f.instruction(&Instruction::LocalGet(NODE));
+    f.instruction(&Instruction::LocalGet(1));
+    f.instruction(&Instruction::LocalGet(2));
+    f.instruction(&Instruction::Call(self_idx));
+    f.instruction(&Instruction::I32Add);
+    f.instruction(&Instruction::LocalSet(COUNT));
+    f.instruction(&Instruction::End);
+
+    // Component record
+    f.instruction(&Instruction::LocalGet(NODE));
+    f.instruction(&Instruction::I32Load(memarg(0, 2)));
+    f.instruction(&Instruction::I32Const(TAG_RECORD));
+    f.instruction(&Instruction::I32Eq);
+    f.instruction(&Instruction::If(wasm_encoder::BlockType::Empty));
+
+    // matched = node.component == name
+    f.instruction(&Instruction::LocalGet(NODE));
+    f.instruction(&Instruction::I32Const(comp_ptr as i32));
+    f.instruction(&Instruction::I32Const(comp_len as i32));
+    f.instruction(&Instruction::Call(rt_func_idx(RT_VAL_RECORD_GET)));
+    f.instruction(&Instruction::LocalGet(1));
+    f.instruction(&Instruction::Call(rt_func_idx(RT_VAL_EQ)));
+    f.instruction(&Instruction::I32Load(memarg(4, 2)));
+    f.instruction(&Instruction::LocalSet(MATCHED));
+
+    // Props filter: every entry of `props` must equal node.props[key]
+    f.instruction(&Instruction::LocalGet(MATCHED));
+    f.instruction(&Instruction::LocalGet(2));
+    f.instruction(&Instruction::I32Const(0));
+    f.instruction(&Instruction::I32Ne);
+    f.instruction(&Instruction::I32And);
+    f.instruction(&Instruction::If(wasm_encoder::BlockType::Empty));
+    f.instruction(&Instruction::LocalGet(NODE));
+    f.instruction(&Instruction::I32Const(props_ptr as i32));
+    f.instruction(&Instruction::I32Const(props_len as i32));
+    f.instruction(&Instruction::Call(rt_func_idx(RT_VAL_RECORD_GET)));
+    f.instruction(&Instruction::LocalSet(NODE_PROPS));
+    f.instruction(&Instruction::I32Const(0));
+    f.instruction(&Instruction::LocalSet(J));
+    f.instruction(&Instruction::Block(wasm_encoder::BlockType::Empty));
+    f.instruction(&Instruction::Loop(wasm_encoder::BlockType::Empty));
+    f.instruction(&Instruction::LocalGet(J));
+    f.instruction(&Instruction::LocalGet(2));
+    f.instruction(&Instruction::I32Load(memarg(8, 2)));
+    f.instruction(&Instruction::I32GeU);
+    f.instruction(&Instruction::BrIf(1));
+    // entry = props.entries + j * 12
+    f.instruction(&Instruction::LocalGet(2));
+    f.instruction(&Instruction::I32Load(memarg(4, 2)));
+    f.instruction(&Instruction::LocalGet(J));
+    f.instruction(&Instruction::I32Const(12));
+    f.instruction(&Instruction::I32Mul);
+    f.instruction(&Instruction::I32Add);
+    f.instruction(&Instruction::LocalSet(ENTRY));
+    // node_props[entry.key] == entry.value
+    f.instruction(&Instruction::LocalGet(NODE_PROPS));
+    f.instruction(&Instruction::LocalGet(ENTRY));
+    f.instruction(&Instruction::I32Load(memarg(0, 2)));
+    f.instruction(&Instruction::LocalGet(ENTRY));
+    f.instruction(&Instruction::I32Load(memarg(4, 2)));
+    f.instruction(&Instruction::Call(rt_func_idx(RT_VAL_RECORD_GET)));
+    f.instruction(&Instruction::LocalGet(ENTRY));
+    f.instruction(&Instruction::I32Load(memarg(8, 2)));
+    f.instruction(&Instruction::Call(rt_func_idx(RT_VAL_EQ)));
+    f.instruction(&Instruction::I32Load(memarg(4, 2)));
+    f.instruction(&Instruction::I32Eqz);
+    f.instruction(&Instruction::If(wasm_encoder::BlockType::Empty));
+    f.instruction(&Instruction::I32Const(0));
+    f.instruction(&Instruction::LocalSet(MATCHED));
+    f.instruction(&Instruction::Br(3)); // out of if + loop + block
+    f.instruction(&Instruction::End);
+    f.instruction(&Instruction::LocalGet(J));
+    f.instruction(&Instruction::I32Const(1));
+    f.instruction(&Instruction::I32Add);
+    f.instruction(&Instruction::LocalSet(J));
+    f.instruction(&Instruction::Br(0));
+    f.instruction(&Instruction::End); // loop
+    f.instruction(&Instruction::End); // block
+    f.instruction(&Instruction::End); // props filter
+
+    // count += matched + __surface_count(node.children, name, props)
+    f.instruction(&Instruction::LocalGet(COUNT));
+    f.instruction(&Instruction::LocalGet(MATCHED));
+    f.instruction(&Instruction::I32Add);
+    f.instruction(&Instruction::LocalGet(NODE));
+    f.instruction(&Instruction::I32Const(children_ptr as i32));
+    f.instruction(&Instruction::I32Const(children_len as i32));
+    f.instruction(&Instruction::Call(rt_func_idx(RT_VAL_RECORD_GET)));
+    f.instruction(&Instruction::LocalGet(1));
+    f.instruction(&Instruction::LocalGet(2));
+    f.instruction(&Instruction::Call(self_idx));
+    f.instruction(&Instruction::I32Add);
+    f.instruction(&Instruction::LocalSet(COUNT));
+    f.instruction(&Instruction::End); // record
+
+    // i += 1
+    f.instruction(&Instruction::LocalGet(I));
+    f.instruction(&Instruction::I32Const(1));
+    f.instruction(&Instruction::I32Add);
+    f.instruction(&Instruction::LocalSet(I));
+    f.instruction(&Instruction::Br(0));
+    f.instruction(&Instruction::End); // loop
+    f.instruction(&Instruction::End); // block
+
+    f.instruction(&Instruction::LocalGet(COUNT));
+    f.instruction(&Instruction::End);
+    f
+}
+
+/// Emit `count_components(surface, name) -> number value`.
+pub fn emit_count_components(surface_count_idx: u32) -> Function {
+    let mut f = Function::new(vec![(1, wasm_encoder::ValType::I32)]); // local 2: result
+    f.instruction(&Instruction::I32Const(VALUE_SIZE as i32));
+    f.instruction(&Instruction::Call(rt_func_idx(RT_ALLOC)));
+    f.instruction(&Instruction::LocalTee(2));
+    f.instruction(&Instruction::I32Const(TAG_NUMBER));
+    f.instruction(&Instruction::I32Store(memarg(0, 2)));
+    f.instruction(&Instruction::LocalGet(2));
+    f.instruction(&Instruction::LocalGet(0));
+    f.instruction(&Instruction::LocalGet(1));
+    f.instruction(&Instruction::I32Const(0));
+    f.instruction(&Instruction::Call(surface_count_idx));
+    f.instruction(&Instruction::F64ConvertI32U);
+    f.instruction(&Instruction::F64Store(memarg(4, 3)));
+    f.instruction(&Instruction::LocalGet(2));
+    f.instruction(&Instruction::End);
+    f
+}
+
 /// Emit `__test_count() -> i32`.
 pub fn emit_test_count(count: usize) -> Function {
     let mut f = Function::new(vec![]);
@@ -38,6 +38,15 @@ pub const TAG_LAMBDA: i32 = 7;
 pub const TAG_COLOR: i32 = 8;
 pub const TAG_ACTION_REF: i32 = 9;
 
+// ── Reserved variant ids ─────────────────────────────────────────────────────
+// User sum-type variants are numbered from 0 in declaration order; the
+// built-in Result variants sit at the top of the id space so they never clash.
+
+/// Variant id of `Ok(value)` — the payload is stored directly in word-2.
+pub const VARIANT_OK: u32 = 0xFFFF_FF00;
+/// Variant id of `Err(error)` — the payload is stored directly in word-2.
+pub const VARIANT_ERR: u32 = 0xFFFF_FF01;
+
 // ── Global variable indices ──────────────────────────────────────────────────
 // (order must match the global section emission in compiler.rs)
 
@@ -49,6 +58,8 @@ pub const GLOBAL_GAS: u32 = 1;
 pub const GLOBAL_GAS_LIMIT: u32 = 2;
 /// Pointer to the state record value.
 pub const GLOBAL_STATE_PTR: u32 = 3;
+/// Id of the trap site currently executing (exported as `__trap_site`).
+pub const GLOBAL_TRAP_SITE: u32 = 4;
 
 // ── Imported function indices ────────────────────────────────────────────────
 // (order must match the import section emission in compiler.rs)
@@ -90,9 +101,19 @@ pub const TYPE_I32_F64_VOID: u32 = 8;
 pub const TYPE_VOID_I64: u32 = 9;
 /// `(i32, i32, i32) -> ()`
 pub const TYPE_I32X3_VOID: u32 = 10;
+/// `(f64) -> f64`
+pub const TYPE_F64_F64: u32 = 11;
+/// `(f64, f64) -> f64`
+pub const TYPE_F64X2_F64: u32 = 12;
+/// `(i32, i32) -> f64`
+pub const TYPE_I32X2_F64: u32 = 13;
+/// `(i32, i64, i32) -> ()`
+pub const TYPE_I32_I64_I32_VOID: u32 = 14;
+/// `() -> (i32, i32)`
+pub const TYPE_VOID_I32X2: u32 = 15;
 
 /// Total number of fixed type signatures.
-pub const TYPE_COUNT: u32 = 11;
+pub const TYPE_COUNT: u32 = 16;
 
 // ── Memory ───────────────────────────────────────────────────────────────────
 
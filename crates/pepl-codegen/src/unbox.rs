@@ -0,0 +1,347 @@
+//! Type-directed unboxing of `number` and `bool` values.
+//!
+//! The default lowering in [`crate::expr`] leaves a boxed value pointer on
+//! the stack for every expression, so `a + b * c` allocates a 12-byte cell
+//! per operator.  When the type checker has proven an expression to be a
+//! `number` or `bool`, the operators below it run on native `f64` / `i32`
+//! operands instead and only the final result is boxed — or never, when it
+//! lands in a `let` local or a condition.
+//!
+//! Boxing happens at the escape points, all of which go through
+//! [`crate::expr::emit_expr`]: list and record construction, `set`, host
+//! calls, closure captures and block results.
+//!
+//! The checker reports its findings as a [`ScalarTypes`] table keyed by
+//! expression span.  An empty table reproduces the fully boxed output.
+
+use std::collections::HashMap;
+
+use pepl_types::ast::*;
+use pepl_types::Span;
+use wasm_encoder::{BlockType, Function, Instruction, ValType};
+
+use crate::compiler::FuncContext;
+use crate::error::CodegenResult;
+use crate::expr::emit_expr;
+use crate::gas;
+use crate::runtime::*;
+use crate::types::*;
+
+// ══════════════════════════════════════════════════════════════════════════════
+// Scalar type table
+// ══════════════════════════════════════════════════════════════════════════════
+
+/// A value type with a native WASM representation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scalar {
+    /// `number`, held as `f64`.
+    Number,
+    /// `bool`, held as `i32` (0 or 1).
+    Bool,
+}
+
+impl Scalar {
+    /// The WASM type of the unboxed representation.
+    pub fn val_type(self) -> ValType {
+        match self {
+            Scalar::Number => ValType::F64,
+            Scalar::Bool => ValType::I32,
+        }
+    }
+}
+
+/// Expression span → statically known scalar type.
+///
+/// Built by the type checker; spans it never recorded, or recorded with
+/// conflicting types, are treated as unknown and stay boxed.
+#[derive(Debug, Clone, Default)]
+pub struct ScalarTypes {
+    types: HashMap<Span, Option<Scalar>>,
+}
+
+impl ScalarTypes {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record the checked type of the expression at `span` (`None` for
+    /// anything that is not a scalar).
+    pub fn record(&mut self, span: Span, scalar: Option<Scalar>) {
+        self.types
+            .entry(span)
+            .and_modify(|known| {
+                if *known != scalar {
+                    *known = None;
+                }
+            })
+            .or_insert(scalar);
+    }
+
+    /// The scalar type of the expression at `span`, if known.
+    pub fn get(&self, span: Span) -> Option<Scalar> {
+        self.types.get(&span).copied().flatten()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.types.is_empty()
+    }
+}
+
+// ══════════════════════════════════════════════════════════════════════════════
+// Native emission
+// ══════════════════════════════════════════════════════════════════════════════
+
+/// The scalar `expr` produces natively, or `None` if it is lowered boxed.
+///
+/// Only operators, literals and unboxed locals qualify; calls, field reads
+/// and control flow always produce a boxed value.
+pub fn native_scalar(expr: &Expr, ctx: &FuncContext) -> Option<Scalar> {
+    let checked = || ctx.scalars.get(expr.span);
+    match &expr.kind {
+        ExprKind::Identifier(name) => ctx.get_local(name).and_then(|l| ctx.unboxed_local(l)),
+        ExprKind::Paren(inner) => native_scalar(inner, ctx),
+        ExprKind::NumberLit(_) => checked().filter(|s| *s == Scalar::Number),
+        ExprKind::BoolLit(_) => checked().filter(|s| *s == Scalar::Bool),
+        ExprKind::Unary { op, .. } => {
+            let produced = match op {
+                UnaryOp::Neg => Scalar::Number,
+                UnaryOp::Not => Scalar::Bool,
+            };
+            checked().filter(|s| *s == produced)
+        }
+        ExprKind::Binary { left, op, right } => match op {
+            BinOp::Add | BinOp::Sub | BinOp::Mul | BinOp::Div | BinOp::Mod => {
+                checked().filter(|s| *s == Scalar::Number)
+            }
+            // `val_eq` compares tags first; only same-typed scalars may skip it.
+            BinOp::Eq | BinOp::NotEq => {
+                let operands = ctx.scalars.get(left.span)?;
+                (ctx.scalars.get(right.span) == Some(operands))
+                    .then(checked)
+                    .flatten()
+                    .filter(|s| *s == Scalar::Bool)
+            }
+            _ => checked().filter(|s| *s == Scalar::Bool),
+        },
+        _ => None,
+    }
+}
+
+/// Emit `expr` leaving a native `f64` (number) or `i32` (bool) on the stack.
+///
+/// Expressions that are not natively scalar are emitted boxed and their
+/// payload is loaded, exactly as the boxed runtime helpers would.
+pub fn emit_scalar(
+    expr: &Expr,
+    want: Scalar,
+    ctx: &mut FuncContext,
+    f: &mut Function,
+) -> CodegenResult<()> {
+    if native_scalar(expr, ctx) != Some(want) {
+        emit_expr(expr, ctx, f)?;
+        emit_unbox(want, f);
+        return Ok(());
+    }
+
+    gas::charge_expr(expr, ctx);
+    match &expr.kind {
+        ExprKind::NumberLit(n) => {
+            f.instruction(&Instruction::F64Const(*n));
+        }
+        ExprKind::BoolLit(b) => {
+            f.instruction(&Instruction::I32Const(*b as i32));
+        }
+        ExprKind::Identifier(name) => {
+            let local = ctx.get_local(name).expect("native identifier is bound");
+            f.instruction(&Instruction::LocalGet(local));
+        }
+        ExprKind::Paren(inner) => emit_scalar(inner, want, ctx, f)?,
+        ExprKind::Unary { op, operand } => match op {
+            UnaryOp::Neg => {
+                emit_scalar(operand, Scalar::Number, ctx, f)?;
+                f.instruction(&Instruction::F64Neg);
+            }
+            UnaryOp::Not => {
+                emit_scalar(operand, Scalar::Bool, ctx, f)?;
+                f.instruction(&Instruction::I32Eqz);
+            }
+        },
+        ExprKind::Binary { left, op, right } => emit_scalar_binary(left, *op, right, ctx, f)?,
+        _ => unreachable!("native_scalar only accepts operators, literals and locals"),
+    }
+    Ok(())
+}
+
+fn emit_scalar_binary(
+    left: &Expr,
+    op: BinOp,
+    right: &Expr,
+    ctx: &mut FuncContext,
+    f: &mut Function,
+) -> CodegenResult<()> {
+    match op {
+        BinOp::And => {
+            emit_scalar(left, Scalar::Bool, ctx, f)?;
+            gas::flush(ctx, f);
+            f.instruction(&Instruction::If(BlockType::Result(ValType::I32)));
+            emit_scalar(right, Scalar::Bool, ctx, f)?;
+            gas::flush(ctx, f);
+            f.instruction(&Instruction::Else);
+            f.instruction(&Instruction::I32Const(0));
+            f.instruction(&Instruction::End);
+        }
+        BinOp::Or => {
+            let left_local = ctx.alloc_local(ValType::I32);
+            emit_scalar(left, Scalar::Bool, ctx, f)?;
+            f.instruction(&Instruction::LocalTee(left_local));
+            gas::flush(ctx, f);
+            f.instruction(&Instruction::If(BlockType::Result(ValType::I32)));
+            f.instruction(&Instruction::LocalGet(left_local));
+            f.instruction(&Instruction::Else);
+            emit_scalar(right, Scalar::Bool, ctx, f)?;
+            gas::flush(ctx, f);
+            f.instruction(&Instruction::End);
+        }
+        BinOp::Eq | BinOp::NotEq => {
+            let operands = ctx.scalars.get(left.span).unwrap_or(Scalar::Number);
+            for operand in [left, right] {
+                emit_scalar(operand, operands, ctx, f)?;
+                if operands == Scalar::Number {
+                    // Bitwise, like `val_eq` comparing both payload words.
+                    f.instruction(&Instruction::I64ReinterpretF64);
+                }
+            }
+            let instr = match (operands, op) {
+                (Scalar::Number, BinOp::Eq) => Instruction::I64Eq,
+                (Scalar::Number, _) => Instruction::I64Ne,
+                (Scalar::Bool, BinOp::Eq) => Instruction::I32Eq,
+                (Scalar::Bool, _) => Instruction::I32Ne,
+            };
+            f.instruction(&instr);
+        }
+        BinOp::Div => emit_scalar_div(left, right, ctx, f)?,
+        BinOp::Mod => {
+            // a - trunc(a / b) * b, as in `val_mod`
+            let a = ctx.alloc_local(ValType::F64);
+            let b = ctx.alloc_local(ValType::F64);
+            emit_scalar(left, Scalar::Number, ctx, f)?;
+            f.instruction(&Instruction::LocalSet(a));
+            emit_scalar(right, Scalar::Number, ctx, f)?;
+            f.instruction(&Instruction::LocalSet(b));
+            f.instruction(&Instruction::LocalGet(a));
+            f.instruction(&Instruction::LocalGet(a));
+            f.instruction(&Instruction::LocalGet(b));
+            f.instruction(&Instruction::F64Div);
+            f.instruction(&Instruction::F64Trunc);
+            f.instruction(&Instruction::LocalGet(b));
+            f.instruction(&Instruction::F64Mul);
+            f.instruction(&Instruction::F64Sub);
+        }
+        _ => {
+            let (operands, instr) = match op {
+                BinOp::Add => (Scalar::Number, Instruction::F64Add),
+                BinOp::Sub => (Scalar::Number, Instruction::F64Sub),
+                BinOp::Mul => (Scalar::Number, Instruction::F64Mul),
+                BinOp::Less => (Scalar::Number, Instruction::F64Lt),
+                BinOp::LessEq => (Scalar::Number, Instruction::F64Le),
+                BinOp::Greater => (Scalar::Number, Instruction::F64Gt),
+                BinOp::GreaterEq => (Scalar::Number, Instruction::F64Ge),
+                _ => unreachable!("handled above"),
+            };
+            emit_scalar(left, operands, ctx, f)?;
+            emit_scalar(right, operands, ctx, f)?;
+            f.instruction(&instr);
+        }
+    }
+    Ok(())
+}
+
+/// Native division with the same zero-divisor and NaN traps as `val_div`.
+fn emit_scalar_div(
+    left: &Expr,
+    right: &Expr,
+    ctx: &mut FuncContext,
+    f: &mut Function,
+) -> CodegenResult<()> {
+    let dividend = ctx.alloc_local(ValType::F64);
+    let divisor = ctx.alloc_local(ValType::F64);
+    let quotient = ctx.alloc_local(ValType::F64);
+    emit_scalar(left, Scalar::Number, ctx, f)?;
+    f.instruction(&Instruction::LocalSet(dividend));
+    emit_scalar(right, Scalar::Number, ctx, f)?;
+    f.instruction(&Instruction::LocalSet(divisor));
+
+    f.instruction(&Instruction::LocalGet(divisor));
+    f.instruction(&Instruction::F64Const(0.0));
+    f.instruction(&Instruction::F64Eq);
+    emit_trap_if(ctx, f);
+
+    f.instruction(&Instruction::LocalGet(dividend));
+    f.instruction(&Instruction::LocalGet(divisor));
+    f.instruction(&Instruction::F64Div);
+    f.instruction(&Instruction::LocalTee(quotient));
+    f.instruction(&Instruction::LocalGet(quotient));
+    f.instruction(&Instruction::F64Ne);
+    emit_trap_if(ctx, f);
+
+    f.instruction(&Instruction::LocalGet(quotient));
+    Ok(())
+}
+
+/// Trap with the division message if the i32 on the stack is non-zero.
+fn emit_trap_if(ctx: &FuncContext, f: &mut Function) {
+    f.instruction(&Instruction::If(BlockType::Empty));
+    f.instruction(&Instruction::I32Const(ctx.data.div_by_zero_ptr as i32));
+    f.instruction(&Instruction::I32Const(ctx.data.div_by_zero_len as i32));
+    f.instruction(&Instruction::Call(IMPORT_TRAP));
+    f.instruction(&Instruction::Unreachable);
+    f.instruction(&Instruction::End);
+}
+
+// ══════════════════════════════════════════════════════════════════════════════
+// Boxing
+// ══════════════════════════════════════════════════════════════════════════════
+
+/// Box the native scalar on the stack into a value cell.  Leaves an i32 ptr.
+pub fn emit_box(scalar: Scalar, ctx: &mut FuncContext, f: &mut Function) {
+    match scalar {
+        Scalar::Number => {
+            let n = ctx.alloc_local(ValType::F64);
+            let ptr = ctx.alloc_local(ValType::I32);
+            f.instruction(&Instruction::LocalSet(n));
+            f.instruction(&Instruction::I32Const(VALUE_SIZE as i32));
+            f.instruction(&Instruction::Call(rt_func_idx(RT_ALLOC)));
+            f.instruction(&Instruction::LocalTee(ptr));
+            f.instruction(&Instruction::I32Const(TAG_NUMBER));
+            f.instruction(&Instruction::I32Store(memarg(0, 2)));
+            f.instruction(&Instruction::LocalGet(ptr));
+            f.instruction(&Instruction::LocalGet(n));
+            f.instruction(&Instruction::F64Store(memarg(4, 3)));
+            f.instruction(&Instruction::LocalGet(ptr));
+        }
+        Scalar::Bool => {
+            f.instruction(&Instruction::Call(rt_func_idx(RT_VAL_BOOL)));
+        }
+    }
+}
+
+/// Load the payload of the boxed value on the stack as a native scalar.
+pub fn emit_unbox(scalar: Scalar, f: &mut Function) {
+    match scalar {
+        Scalar::Number => f.instruction(&Instruction::F64Load(memarg(4, 3))),
+        Scalar::Bool => f.instruction(&Instruction::I32Load(memarg(4, 2))),
+    };
+}
+
+/// An i32 local holding `local` as a boxed value, boxing it first if it is
+/// an unboxed scalar.
+pub fn boxed_local(local: u32, ctx: &mut FuncContext, f: &mut Function) -> u32 {
+    let Some(scalar) = ctx.unboxed_local(local) else {
+        return local;
+    };
+    let boxed = ctx.alloc_local(ValType::I32);
+    f.instruction(&Instruction::LocalGet(local));
+    emit_box(scalar, ctx, f);
+    f.instruction(&Instruction::LocalSet(boxed));
+    boxed
+}
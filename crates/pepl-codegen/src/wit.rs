@@ -0,0 +1,1173 @@
+//! The WIT world of a space.
+//!
+//! The core module's host contract is untyped: capabilities are reached
+//! through `env.host_call` with numeric ids, and every value crosses the
+//! boundary as a pointer to a 12-byte cell.  This module describes the same
+//! contract in [WIT]:
+//!
+//! - [`host_wit`] — the `pepl:host` package, one interface per capability
+//!   plus `runtime` (log, trap, timestamp, `core.capability`) and `time`
+//! - [`space_wit`] — per space, a `pepl:<space>` package whose world imports
+//!   `runtime`, `time`, `timer` and the declared capabilities, and exports a
+//!   `space` interface with the space's own types
+//!
+//! [`crate::component`] implements that world by wrapping the core module.
+//!
+//! # Type mapping
+//!
+//! | PEPL                     | WIT                                   |
+//! |--------------------------|---------------------------------------|
+//! | `number` / `bool` / `string` | `f64` / `bool` / `string`        |
+//! | `list<T>`                | `list<T>`                             |
+//! | `Result<T, E>`           | `result<T, E>` (`nil` sides omitted)  |
+//! | `{ a: T, b?: U }`        | `record` (`b: option<U>`)             |
+//! | sum type                 | `enum` if every variant is a unit, else `variant` |
+//! | `Surface`                | the `surface` record (flattened node list) |
+//!
+//! `any`, `color`, function types, recursive types and `nil` outside a
+//! `Result` have no WIT counterpart and are rejected.  Names become
+//! kebab-case; user types whose name collides with a built-in item get a
+//! `-type` suffix, and actions a `-action` suffix.
+//!
+//! [WIT]: https://component-model.bytecodealliance.org/design/wit.html
+
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write as _;
+
+use pepl_types::ast::*;
+use wasm_encoder::ValType;
+
+use crate::error::{CodegenError, CodegenResult};
+
+/// Version of the `pepl:host` package.
+pub const HOST_VERSION: &str = "0.1.0";
+
+// ══════════════════════════════════════════════════════════════════════════════
+// Host interfaces
+// ══════════════════════════════════════════════════════════════════════════════
+
+/// A `pepl:host` interface.
+#[derive(Debug)]
+pub struct HostInterface {
+    pub name: &'static str,
+    pub funcs: &'static [HostFunc],
+}
+
+/// A function of a [`HostInterface`].
+#[derive(Debug)]
+pub struct HostFunc {
+    pub name: &'static str,
+    pub route: Route,
+    pub params: &'static [(&'static str, HostType)],
+    pub result: HostType,
+}
+
+/// How the core module reaches a host function.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Route {
+    /// `env.log`.
+    Log,
+    /// `env.trap`.
+    Trap,
+    /// `env.get_timestamp`.
+    Timestamp,
+    /// `env.host_call` for the PEPL function `module.function`.
+    HostCall(&'static str, &'static str),
+}
+
+/// The few shapes host functions take and return.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HostType {
+    Unit,
+    F64,
+    S64,
+    Bool,
+    String,
+    OptionString,
+    ListString,
+    ResultString,
+    /// `location.position`.
+    Position,
+}
+
+impl HostType {
+    fn wit(self) -> &'static str {
+        match self {
+            HostType::Unit => "",
+            HostType::F64 => "f64",
+            HostType::S64 => "s64",
+            HostType::Bool => "bool",
+            HostType::String => "string",
+            HostType::OptionString => "option<string>",
+            HostType::ListString => "list<string>",
+            HostType::ResultString => "result<string, string>",
+            HostType::Position => "position",
+        }
+    }
+}
+
+const fn call(
+    name: &'static str,
+    module: &'static str,
+    function: &'static str,
+    params: &'static [(&'static str, HostType)],
+    result: HostType,
+) -> HostFunc {
+    HostFunc {
+        name,
+        route: Route::HostCall(module, function),
+        params,
+        result,
+    }
+}
+
+use HostType as H;
+
+/// Every `pepl:host` interface, capabilities last.
+pub const HOST_INTERFACES: &[HostInterface] = &[
+    HostInterface {
+        name: "runtime",
+        funcs: &[
+            HostFunc {
+                name: "log",
+                route: Route::Log,
+                params: &[("message", H::String)],
+                result: H::Unit,
+            },
+            HostFunc {
+                name: "trap",
+                route: Route::Trap,
+                params: &[("message", H::String)],
+                result: H::Unit,
+            },
+            HostFunc {
+                name: "timestamp",
+                route: Route::Timestamp,
+                params: &[],
+                result: H::S64,
+            },
+            call(
+                "has-capability",
+                "core",
+                "capability",
+                &[("name", H::String)],
+                H::Bool,
+            ),
+        ],
+    },
+    HostInterface {
+        name: "time",
+        funcs: &[
+            call("now", "time", "now", &[], H::F64),
+            call(
+                "format",
+                "time",
+                "format",
+                &[("timestamp", H::F64), ("pattern", H::String)],
+                H::String,
+            ),
+            call(
+                "diff",
+                "time",
+                "diff",
+                &[("a", H::F64), ("b", H::F64)],
+                H::F64,
+            ),
+            call(
+                "day-of-week",
+                "time",
+                "day_of_week",
+                &[("timestamp", H::F64)],
+                H::F64,
+            ),
+            call(
+                "start-of-day",
+                "time",
+                "start_of_day",
+                &[("timestamp", H::F64)],
+                H::F64,
+            ),
+        ],
+    },
+    HostInterface {
+        name: "timer",
+        funcs: &[
+            call(
+                "start",
+                "timer",
+                "start",
+                &[("id", H::String), ("interval-ms", H::F64)],
+                H::String,
+            ),
+            call(
+                "start-once",
+                "timer",
+                "start_once",
+                &[("id", H::String), ("delay-ms", H::F64)],
+                H::String,
+            ),
+            call("stop", "timer", "stop", &[("id", H::String)], H::Unit),
+            call("stop-all", "timer", "stop_all", &[], H::Unit),
+        ],
+    },
+    HostInterface {
+        name: "http",
+        funcs: &[
+            call("get", "http", "get", &[("url", H::String)], H::ResultString),
+            call(
+                "post",
+                "http",
+                "post",
+                &[("url", H::String), ("body", H::String)],
+                H::ResultString,
+            ),
+            call(
+                "put",
+                "http",
+                "put",
+                &[("url", H::String), ("body", H::String)],
+                H::ResultString,
+            ),
+            call(
+                "patch",
+                "http",
+                "patch",
+                &[("url", H::String), ("body", H::String)],
+                H::ResultString,
+            ),
+            call(
+                "delete",
+                "http",
+                "delete",
+                &[("url", H::String)],
+                H::ResultString,
+            ),
+        ],
+    },
+    HostInterface {
+        name: "storage",
+        funcs: &[
+            call(
+                "get",
+                "storage",
+                "get",
+                &[("key", H::String)],
+                H::OptionString,
+            ),
+            call(
+                "set",
+                "storage",
+                "set",
+                &[("key", H::String), ("value", H::String)],
+                H::Unit,
+            ),
+            call(
+                "delete",
+                "storage",
+                "delete",
+                &[("key", H::String)],
+                H::Unit,
+            ),
+            call("keys", "storage", "keys", &[], H::ListString),
+        ],
+    },
+    HostInterface {
+        name: "location",
+        funcs: &[call("current", "location", "current", &[], H::Position)],
+    },
+    HostInterface {
+        name: "notifications",
+        funcs: &[call(
+            "send",
+            "notifications",
+            "send",
+            &[("title", H::String), ("body", H::String)],
+            H::Unit,
+        )],
+    },
+];
+
+/// Interfaces every space imports, whatever its capabilities.
+const ALWAYS_IMPORTED: &[&str] = &["runtime", "time", "timer"];
+
+/// The `pepl:host` package as WIT text.
+pub fn host_wit() -> String {
+    let mut out = format!("package pepl:host@{HOST_VERSION};\n");
+    for interface in HOST_INTERFACES {
+        let _ = write!(out, "\ninterface {} {{\n", interface.name);
+        if interface.name == "location" {
+            out.push_str("    record position {\n        lat: f64,\n        lon: f64,\n    }\n\n");
+        }
+        for func in interface.funcs {
+            let params: Vec<String> = func
+                .params
+                .iter()
+                .map(|(name, ty)| format!("{}: {}", escape(name), ty.wit()))
+                .collect();
+            let _ = write!(
+                out,
+                "    {}: func({})",
+                escape(func.name),
+                params.join(", ")
+            );
+            if func.result != HostType::Unit {
+                let _ = write!(out, " -> {}", func.result.wit());
+            }
+            out.push_str(";\n");
+        }
+        out.push_str("}\n");
+    }
+    out
+}
+
+/// The WIT package of `program`'s space.
+///
+/// Fails with [`CodegenError::Unsupported`] if the space uses a type WIT
+/// cannot express.
+pub fn space_wit(program: &Program) -> CodegenResult<String> {
+    Ok(SpaceWorld::new(program)?.to_wit())
+}
+
+// ══════════════════════════════════════════════════════════════════════════════
+// Space model
+// ══════════════════════════════════════════════════════════════════════════════
+
+/// A WIT value type.
+#[derive(Debug, Clone, PartialEq)]
+pub enum WitType {
+    U32,
+    F64,
+    Bool,
+    String,
+    List(Box<WitType>),
+    Option(Box<WitType>),
+    Result(Option<Box<WitType>>, Option<Box<WitType>>),
+    Tuple(Vec<WitType>),
+    /// A named type, by index into [`SpaceWorld::types`].
+    Def(usize),
+}
+
+/// A named type of the `space` interface.
+#[derive(Debug, Clone)]
+pub struct TypeDef {
+    pub name: String,
+    pub kind: DefKind,
+}
+
+#[derive(Debug, Clone)]
+pub enum DefKind {
+    Record(Vec<WitField>),
+    Variant(Vec<WitCase>),
+    Enum(Vec<WitCase>),
+}
+
+/// A record field; `key` is the PEPL field name.
+#[derive(Debug, Clone)]
+pub struct WitField {
+    pub key: String,
+    pub name: String,
+    pub ty: WitType,
+    /// Declared `name?:` — `ty` is then an `option`.
+    pub optional: bool,
+}
+
+/// A variant or enum case.
+#[derive(Debug, Clone)]
+pub struct WitCase {
+    pub name: String,
+    /// Runtime variant id (see [`crate::types::VARIANT_OK`]).
+    pub id: u32,
+    /// Payload fields, in declaration order.
+    pub fields: Vec<WitType>,
+}
+
+impl WitCase {
+    /// The case payload: nothing, the single field, or a tuple.
+    pub fn payload(&self) -> Option<WitType> {
+        match self.fields.len() {
+            0 => None,
+            1 => Some(self.fields[0].clone()),
+            _ => Some(WitType::Tuple(self.fields.clone())),
+        }
+    }
+}
+
+/// What an exported function wraps.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Entry {
+    Init,
+    Action(u32),
+    GetState,
+    SerializeState,
+    RestoreState,
+    Render(u32),
+    Update,
+    HandleEvent,
+}
+
+/// A function of the exported `space` interface.
+#[derive(Debug, Clone)]
+pub struct WitFunc {
+    pub name: String,
+    pub params: Vec<(String, WitType)>,
+    pub result: Option<WitType>,
+    pub entry: Entry,
+}
+
+/// Indices of the built-in Surface types in [`SpaceWorld::types`].
+#[derive(Debug, Clone, Copy)]
+pub struct SurfaceTypes {
+    pub prop_value: usize,
+    pub prop: usize,
+    pub node: usize,
+    pub surface: usize,
+}
+
+/// The world of one space: what it imports and the interface it exports.
+#[derive(Debug, Clone)]
+pub struct SpaceWorld {
+    /// Kebab-case space name, the package is `pepl:<name>`.
+    pub name: String,
+    pub imports: Vec<&'static HostInterface>,
+    /// Named types, each after the types it refers to.
+    pub types: Vec<TypeDef>,
+    pub funcs: Vec<WitFunc>,
+    pub surface: Option<SurfaceTypes>,
+}
+
+/// Items the `space` interface declares itself.
+const BUILTIN_NAMES: &[&str] = &[
+    "state",
+    "prop-value",
+    "prop",
+    "node",
+    "surface",
+    "init",
+    "get-state",
+    "serialize-state",
+    "restore-state",
+    "update",
+    "handle-event",
+];
+
+impl SpaceWorld {
+    /// Describe `program`'s space.
+    pub fn new(program: &Program) -> CodegenResult<Self> {
+        let body = &program.space.body;
+        let declared: Vec<&str> = body
+            .capabilities
+            .iter()
+            .flat_map(|c| c.required.iter().chain(&c.optional))
+            .map(|c| c.name.as_str())
+            .collect();
+        let imports = HOST_INTERFACES
+            .iter()
+            .filter(|i| ALWAYS_IMPORTED.contains(&i.name) || declared.contains(&i.name))
+            .collect();
+
+        let mut builder = Builder::new(body);
+        let surface = if body.views.is_empty() {
+            None
+        } else {
+            Some(builder.surface_types())
+        };
+
+        // The state record, in slot order
+        let mut fields = Vec::new();
+        let state = body
+            .state
+            .fields
+            .iter()
+            .map(|f| (&f.name.name, &f.type_ann));
+        let derived = body
+            .derived
+            .iter()
+            .flat_map(|d| &d.fields)
+            .map(|f| (&f.name.name, &f.type_ann));
+        for (key, ty) in state.chain(derived) {
+            fields.push(WitField {
+                key: key.clone(),
+                name: kebab(key),
+                ty: builder.map(ty, key, "state")?,
+                optional: false,
+            });
+        }
+        check_unique(fields.iter().map(|f| &f.name), "state fields")?;
+        let state = builder.push("state".to_string(), DefKind::Record(fields));
+
+        let mut funcs = vec![WitFunc {
+            name: "init".into(),
+            params: Vec::new(),
+            result: None,
+            entry: Entry::Init,
+        }];
+        for (i, action) in body.actions.iter().enumerate() {
+            let mut params = Vec::new();
+            for param in &action.params {
+                let context = format!("action `{}`", action.name.name);
+                let ty = builder.map(&param.type_ann, &param.name.name, &context)?;
+                params.push((kebab(&param.name.name), ty));
+            }
+            check_unique(
+                params.iter().map(|(name, _)| name),
+                &format!("parameters of action `{}`", action.name.name),
+            )?;
+            let name = builder.claim(kebab(&action.name.name), "-action");
+            funcs.push(WitFunc {
+                name,
+                params,
+                result: None,
+                entry: Entry::Action(i as u32),
+            });
+        }
+        funcs.push(WitFunc {
+            name: "get-state".into(),
+            params: Vec::new(),
+            result: Some(WitType::Def(state)),
+            entry: Entry::GetState,
+        });
+        funcs.push(WitFunc {
+            name: "serialize-state".into(),
+            params: Vec::new(),
+            result: Some(WitType::String),
+            entry: Entry::SerializeState,
+        });
+        funcs.push(WitFunc {
+            name: "restore-state".into(),
+            params: vec![("snapshot".into(), WitType::String)],
+            result: Some(WitType::Bool),
+            entry: Entry::RestoreState,
+        });
+        if let Some(surface) = surface {
+            for (i, view) in body.views.iter().enumerate() {
+                let name = builder.claim(format!("render-{}", kebab(&view.name.name)), "-view");
+                funcs.push(WitFunc {
+                    name,
+                    params: Vec::new(),
+                    result: Some(WitType::Def(surface.surface)),
+                    entry: Entry::Render(i as u32),
+                });
+            }
+        }
+        if body.update.is_some() {
+            funcs.push(WitFunc {
+                name: "update".into(),
+                params: vec![("dt".into(), WitType::F64)],
+                result: None,
+                entry: Entry::Update,
+            });
+        }
+        if body.handle_event.is_some() {
+            funcs.push(WitFunc {
+                name: "handle-event".into(),
+                params: vec![("event".into(), WitType::String)],
+                result: Some(WitType::Bool),
+                entry: Entry::HandleEvent,
+            });
+        }
+
+        Ok(Self {
+            name: kebab(&program.space.name.name),
+            imports,
+            types: builder.types,
+            funcs,
+            surface,
+        })
+    }
+
+    /// `pepl:<space>/space`, the name of the exported interface.
+    pub fn interface_name(&self) -> String {
+        format!("pepl:{}/space", self.name)
+    }
+
+    /// The world as WIT text.
+    pub fn to_wit(&self) -> String {
+        let mut out = format!("package pepl:{};\n\ninterface space {{\n", self.name);
+        for def in &self.types {
+            let name = escape(&def.name);
+            match &def.kind {
+                DefKind::Record(fields) => {
+                    let _ = writeln!(out, "    record {name} {{");
+                    for field in fields {
+                        let _ = writeln!(
+                            out,
+                            "        {}: {},",
+                            escape(&field.name),
+                            self.type_wit(&field.ty)
+                        );
+                    }
+                }
+                DefKind::Variant(cases) => {
+                    let _ = writeln!(out, "    variant {name} {{");
+                    for case in cases {
+                        match case.payload() {
+                            Some(ty) => {
+                                let _ = writeln!(
+                                    out,
+                                    "        {}({}),",
+                                    escape(&case.name),
+                                    self.type_wit(&ty)
+                                );
+                            }
+                            None => {
+                                let _ = writeln!(out, "        {},", escape(&case.name));
+                            }
+                        }
+                    }
+                }
+                DefKind::Enum(cases) => {
+                    let _ = writeln!(out, "    enum {name} {{");
+                    for case in cases {
+                        let _ = writeln!(out, "        {},", escape(&case.name));
+                    }
+                }
+            }
+            out.push_str("    }\n\n");
+        }
+        for func in &self.funcs {
+            let params: Vec<String> = func
+                .params
+                .iter()
+                .map(|(name, ty)| format!("{}: {}", escape(name), self.type_wit(ty)))
+                .collect();
+            let _ = write!(
+                out,
+                "    {}: func({})",
+                escape(&func.name),
+                params.join(", ")
+            );
+            if let Some(result) = &func.result {
+                let _ = write!(out, " -> {}", self.type_wit(result));
+            }
+            out.push_str(";\n");
+        }
+        let _ = write!(out, "}}\n\nworld {} {{\n", escape(&self.name));
+        for interface in &self.imports {
+            let _ = writeln!(
+                out,
+                "    import pepl:host/{}@{HOST_VERSION};",
+                interface.name
+            );
+        }
+        out.push_str("\n    export space;\n}\n");
+        out
+    }
+
+    fn type_wit(&self, ty: &WitType) -> String {
+        match ty {
+            WitType::U32 => "u32".into(),
+            WitType::F64 => "f64".into(),
+            WitType::Bool => "bool".into(),
+            WitType::String => "string".into(),
+            WitType::List(item) => format!("list<{}>", self.type_wit(item)),
+            WitType::Option(item) => format!("option<{}>", self.type_wit(item)),
+            WitType::Result(ok, err) => {
+                let side = |t: &Option<Box<WitType>>| t.as_ref().map(|t| self.type_wit(t));
+                match (side(ok), side(err)) {
+                    (None, None) => "result".into(),
+                    (Some(ok), None) => format!("result<{ok}>"),
+                    (None, Some(err)) => format!("result<_, {err}>"),
+                    (Some(ok), Some(err)) => format!("result<{ok}, {err}>"),
+                }
+            }
+            WitType::Tuple(items) => {
+                let items: Vec<String> = items.iter().map(|t| self.type_wit(t)).collect();
+                format!("tuple<{}>", items.join(", "))
+            }
+            WitType::Def(idx) => escape(&self.types[*idx].name),
+        }
+    }
+
+    // ── Canonical ABI layout ─────────────────────────────────────────────
+
+    /// Byte size of `ty` in linear memory.
+    pub fn size(&self, ty: &WitType) -> u32 {
+        match ty {
+            WitType::Bool => 1,
+            WitType::U32 => 4,
+            WitType::F64 => 8,
+            WitType::String | WitType::List(_) => 8,
+            WitType::Tuple(items) => self.struct_layout(items).1,
+            WitType::Def(idx) => match &self.types[*idx].kind {
+                DefKind::Record(fields) => {
+                    let tys: Vec<WitType> = fields.iter().map(|f| f.ty.clone()).collect();
+                    self.struct_layout(&tys).1
+                }
+                _ => self.variant_layout(ty).2,
+            },
+            WitType::Option(_) | WitType::Result(..) => self.variant_layout(ty).2,
+        }
+    }
+
+    /// Alignment of `ty` in linear memory.
+    pub fn align(&self, ty: &WitType) -> u32 {
+        match ty {
+            WitType::Bool => 1,
+            WitType::U32 | WitType::String | WitType::List(_) => 4,
+            WitType::F64 => 8,
+            WitType::Tuple(items) => items.iter().map(|t| self.align(t)).max().unwrap_or(1),
+            WitType::Def(idx) => match &self.types[*idx].kind {
+                DefKind::Record(fields) => {
+                    fields.iter().map(|f| self.align(&f.ty)).max().unwrap_or(1)
+                }
+                _ => self.variant_layout(ty).1,
+            },
+            WitType::Option(_) | WitType::Result(..) => self.variant_layout(ty).1,
+        }
+    }
+
+    /// Offsets of a record or tuple's fields, and its size.
+    pub fn struct_layout(&self, items: &[WitType]) -> (Vec<u32>, u32) {
+        let mut offset = 0;
+        let mut align = 1;
+        let offsets = items
+            .iter()
+            .map(|item| {
+                let a = self.align(item);
+                align = align.max(a);
+                offset = align_to(offset, a);
+                let at = offset;
+                offset += self.size(item);
+                at
+            })
+            .collect();
+        (offsets, align_to(offset, align))
+    }
+
+    /// The cases of a variant-like type (`option`, `result`, variant, enum),
+    /// as their payloads.
+    pub fn cases(&self, ty: &WitType) -> Vec<Option<WitType>> {
+        match ty {
+            WitType::Option(item) => vec![None, Some((**item).clone())],
+            WitType::Result(ok, err) => vec![ok.as_deref().cloned(), err.as_deref().cloned()],
+            WitType::Def(idx) => match &self.types[*idx].kind {
+                DefKind::Variant(cases) | DefKind::Enum(cases) => {
+                    cases.iter().map(WitCase::payload).collect()
+                }
+                DefKind::Record(_) => Vec::new(),
+            },
+            _ => Vec::new(),
+        }
+    }
+
+    /// `(payload offset, align, size)` of a variant-like type.
+    pub fn variant_layout(&self, ty: &WitType) -> (u32, u32, u32) {
+        let cases = self.cases(ty);
+        let disc = discriminant_size(cases.len());
+        let payload_align = cases
+            .iter()
+            .flatten()
+            .map(|t| self.align(t))
+            .max()
+            .unwrap_or(1);
+        let payload_size = cases
+            .iter()
+            .flatten()
+            .map(|t| self.size(t))
+            .max()
+            .unwrap_or(0);
+        let offset = align_to(disc, payload_align);
+        let align = disc.max(payload_align);
+        (offset, align, align_to(offset + payload_size, align))
+    }
+
+    /// Core value types `ty` flattens to when passed as parameters or results.
+    pub fn flat(&self, ty: &WitType) -> Vec<ValType> {
+        match ty {
+            WitType::Bool | WitType::U32 => vec![ValType::I32],
+            WitType::F64 => vec![ValType::F64],
+            WitType::String | WitType::List(_) => vec![ValType::I32, ValType::I32],
+            WitType::Tuple(items) => items.iter().flat_map(|t| self.flat(t)).collect(),
+            WitType::Def(idx) => match &self.types[*idx].kind {
+                DefKind::Record(fields) => fields.iter().flat_map(|f| self.flat(&f.ty)).collect(),
+                _ => self.flat_variant(ty),
+            },
+            WitType::Option(_) | WitType::Result(..) => self.flat_variant(ty),
+        }
+    }
+
+    /// The discriminant, then the cases' payloads joined slot by slot.
+    fn flat_variant(&self, ty: &WitType) -> Vec<ValType> {
+        let mut joined: Vec<ValType> = Vec::new();
+        for payload in self.cases(ty).iter().flatten() {
+            for (i, vt) in self.flat(payload).into_iter().enumerate() {
+                match joined.get(i) {
+                    None => joined.push(vt),
+                    Some(&prev) if prev == vt => {}
+                    Some(_) => joined[i] = ValType::I64,
+                }
+            }
+        }
+        std::iter::once(ValType::I32).chain(joined).collect()
+    }
+}
+
+/// Bytes of the discriminant of a variant with `cases` cases.
+pub fn discriminant_size(cases: usize) -> u32 {
+    match cases {
+        0..=256 => 1,
+        257..=65536 => 2,
+        _ => 4,
+    }
+}
+
+pub fn align_to(offset: u32, align: u32) -> u32 {
+    offset.div_ceil(align) * align
+}
+
+// ══════════════════════════════════════════════════════════════════════════════
+// Type mapping
+// ══════════════════════════════════════════════════════════════════════════════
+
+struct Builder<'a> {
+    decls: HashMap<&'a str, &'a TypeDeclBody>,
+    /// First variant id of each sum type.
+    variant_base: HashMap<&'a str, u32>,
+    types: Vec<TypeDef>,
+    /// Item names taken in the `space` interface.
+    taken: HashSet<String>,
+    /// User type name → index, once mapped.
+    named: HashMap<&'a str, usize>,
+    /// User types being mapped (recursion guard).
+    open: Vec<&'a str>,
+    /// Anonymous record shape → index.
+    records: HashMap<String, usize>,
+}
+
+impl<'a> Builder<'a> {
+    fn new(body: &'a SpaceBody) -> Self {
+        let mut decls = HashMap::new();
+        let mut variant_base = HashMap::new();
+        let mut next_id = 0;
+        for decl in &body.types {
+            decls.insert(decl.name.name.as_str(), &decl.body);
+            if let TypeDeclBody::SumType(variants) = &decl.body {
+                variant_base.insert(decl.name.name.as_str(), next_id);
+                next_id += variants.len() as u32;
+            }
+        }
+        Self {
+            decls,
+            variant_base,
+            types: Vec::new(),
+            taken: BUILTIN_NAMES.iter().map(|n| n.to_string()).collect(),
+            named: HashMap::new(),
+            open: Vec::new(),
+            records: HashMap::new(),
+        }
+    }
+
+    /// Take `name`, appending `suffix` until it is free.
+    fn claim(&mut self, mut name: String, suffix: &str) -> String {
+        while self.taken.contains(&name) {
+            name.push_str(suffix);
+        }
+        self.taken.insert(name.clone());
+        name
+    }
+
+    fn push(&mut self, name: String, kind: DefKind) -> usize {
+        self.types.push(TypeDef { name, kind });
+        self.types.len() - 1
+    }
+
+    fn surface_types(&mut self) -> SurfaceTypes {
+        let prop_value = self.push(
+            "prop-value".into(),
+            DefKind::Variant(vec![
+                WitCase {
+                    name: "action".into(),
+                    id: 0,
+                    fields: vec![WitType::U32],
+                },
+                WitCase {
+                    name: "json".into(),
+                    id: 1,
+                    fields: vec![WitType::String],
+                },
+            ]),
+        );
+        let field = |name: &str, ty: WitType| WitField {
+            key: name.into(),
+            name: name.into(),
+            ty,
+            optional: false,
+        };
+        let prop = self.push(
+            "prop".into(),
+            DefKind::Record(vec![
+                field("name", WitType::String),
+                field("value", WitType::Def(prop_value)),
+            ]),
+        );
+        let node = self.push(
+            "node".into(),
+            DefKind::Record(vec![
+                field("component", WitType::String),
+                field("props", WitType::List(Box::new(WitType::Def(prop)))),
+                field("children", WitType::List(Box::new(WitType::U32))),
+            ]),
+        );
+        let surface = self.push(
+            "surface".into(),
+            DefKind::Record(vec![
+                field("roots", WitType::List(Box::new(WitType::U32))),
+                field("nodes", WitType::List(Box::new(WitType::Def(node)))),
+            ]),
+        );
+        SurfaceTypes {
+            prop_value,
+            prop,
+            node,
+            surface,
+        }
+    }
+
+    /// Map a type annotation; `hint` names anonymous records, `context`
+    /// says where the type was found for errors.
+    fn map(&mut self, ty: &'a TypeAnnotation, hint: &str, context: &str) -> CodegenResult<WitType> {
+        let unsupported =
+            |what: &str| CodegenError::Unsupported(format!("{what} in {context} has no WIT type"));
+        Ok(match &ty.kind {
+            TypeKind::Number => WitType::F64,
+            TypeKind::Bool => WitType::Bool,
+            TypeKind::String => WitType::String,
+            TypeKind::List(item) => WitType::List(Box::new(self.map(
+                item,
+                &format!("{hint}_item"),
+                context,
+            )?)),
+            TypeKind::Result(ok, err) => {
+                let mut side = |t: &'a TypeAnnotation, suffix: &str| {
+                    if t.kind == TypeKind::Nil {
+                        Ok(None)
+                    } else {
+                        self.map(t, &format!("{hint}_{suffix}"), context)
+                            .map(|t| Some(Box::new(t)))
+                    }
+                };
+                let ok = side(ok, "ok")?;
+                WitType::Result(ok, side(err, "err")?)
+            }
+            TypeKind::Record(fields) => {
+                let shape = ty.kind.to_string();
+                if let Some(&idx) = self.records.get(&shape) {
+                    return Ok(WitType::Def(idx));
+                }
+                let kind = self.record(fields, context)?;
+                let name = self.claim(kebab(hint), "-type");
+                let idx = self.push(name, kind);
+                self.records.insert(shape, idx);
+                WitType::Def(idx)
+            }
+            TypeKind::Named(name) => return self.named(name, context),
+            TypeKind::Surface => return Err(unsupported("`Surface`")),
+            TypeKind::Nil => return Err(unsupported("`nil`")),
+            other => return Err(unsupported(&format!("`{other}`"))),
+        })
+    }
+
+    fn record(&mut self, fields: &'a [RecordTypeField], context: &str) -> CodegenResult<DefKind> {
+        if fields.is_empty() {
+            return Err(CodegenError::Unsupported(format!(
+                "empty record in {context} has no WIT type"
+            )));
+        }
+        let mut sorted: Vec<&RecordTypeField> = fields.iter().collect();
+        sorted.sort_by(|a, b| a.name.name.cmp(&b.name.name));
+        let mut out = Vec::new();
+        for field in sorted {
+            let mut ty = self.map(&field.type_ann, &field.name.name, context)?;
+            if field.optional {
+                ty = WitType::Option(Box::new(ty));
+            }
+            out.push(WitField {
+                key: field.name.name.clone(),
+                name: kebab(&field.name.name),
+                ty,
+                optional: field.optional,
+            });
+        }
+        check_unique(
+            out.iter().map(|f| &f.name),
+            &format!("a record in {context}"),
+        )?;
+        Ok(DefKind::Record(out))
+    }
+
+    fn named(&mut self, name: &'a str, context: &str) -> CodegenResult<WitType> {
+        if let Some(&idx) = self.named.get(name) {
+            return Ok(WitType::Def(idx));
+        }
+        if self.open.contains(&name) {
+            return Err(CodegenError::Unsupported(format!(
+                "recursive type `{name}` has no WIT type"
+            )));
+        }
+        let Some(&decl) = self.decls.get(name) else {
+            return Err(CodegenError::UnresolvedSymbol(name.to_string()));
+        };
+        self.open.push(name);
+        let result = match decl {
+            TypeDeclBody::Alias(ann) => match &ann.kind {
+                // An aliased record takes the alias's name
+                TypeKind::Record(fields) => {
+                    let shape = ann.kind.to_string();
+                    match self.records.get(&shape) {
+                        Some(&idx) => Ok(WitType::Def(idx)),
+                        None => self.record(fields, context).map(|kind| {
+                            let wit_name = self.claim(kebab(name), "-type");
+                            let idx = self.push(wit_name, kind);
+                            self.records.insert(shape, idx);
+                            WitType::Def(idx)
+                        }),
+                    }
+                }
+                _ => self.map(ann, name, context),
+            },
+            TypeDeclBody::SumType(variants) => self.sum_type(name, variants, context),
+        };
+        self.open.pop();
+        let ty = result?;
+        if let WitType::Def(idx) = ty {
+            self.named.insert(name, idx);
+        }
+        Ok(ty)
+    }
+
+    fn sum_type(
+        &mut self,
+        name: &'a str,
+        variants: &'a [VariantDef],
+        context: &str,
+    ) -> CodegenResult<WitType> {
+        let base = self.variant_base[name];
+        let mut cases = Vec::new();
+        for (i, variant) in variants.iter().enumerate() {
+            let mut fields = Vec::new();
+            for param in &variant.params {
+                fields.push(self.map(&param.type_ann, &param.name.name, context)?);
+            }
+            cases.push(WitCase {
+                name: kebab(&variant.name.name),
+                id: base + i as u32,
+                fields,
+            });
+        }
+        check_unique(cases.iter().map(|c| &c.name), &format!("type `{name}`"))?;
+        let kind = if cases.iter().all(|c| c.fields.is_empty()) {
+            DefKind::Enum(cases)
+        } else {
+            DefKind::Variant(cases)
+        };
+        let wit_name = self.claim(kebab(name), "-type");
+        Ok(WitType::Def(self.push(wit_name, kind)))
+    }
+}
+
+fn check_unique<'n>(names: impl Iterator<Item = &'n String>, what: &str) -> CodegenResult<()> {
+    let mut seen = HashSet::new();
+    for name in names {
+        if !seen.insert(name) {
+            return Err(CodegenError::Unsupported(format!(
+                "{what} share the WIT name `{name}`"
+            )));
+        }
+    }
+    Ok(())
+}
+
+// ══════════════════════════════════════════════════════════════════════════════
+// Names
+// ══════════════════════════════════════════════════════════════════════════════
+
+/// Kebab-case a PEPL identifier: `addItem` → `add-item`, `max_retries` →
+/// `max-retries`, `HTTPStatus` → `http-status`.  Digits stay with the
+/// preceding word, since WIT words cannot start with one.
+pub fn kebab(name: &str) -> String {
+    let chars: Vec<char> = name.chars().collect();
+    let mut words: Vec<String> = Vec::new();
+    let mut word = String::new();
+    for (i, &c) in chars.iter().enumerate() {
+        if !c.is_ascii_alphanumeric() {
+            if !word.is_empty() {
+                words.push(std::mem::take(&mut word));
+            }
+            continue;
+        }
+        if c.is_ascii_uppercase() && !word.is_empty() {
+            let prev = chars[i - 1];
+            let next_lower = chars.get(i + 1).is_some_and(|n| n.is_ascii_lowercase());
+            if !prev.is_ascii_uppercase() || next_lower {
+                words.push(std::mem::take(&mut word));
+            }
+        }
+        if c.is_ascii_digit() && word.is_empty() {
+            if let Some(last) = words.last_mut() {
+                last.push(c);
+                continue;
+            }
+            word.push('n');
+        }
+        word.push(c.to_ascii_lowercase());
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+    if words.is_empty() {
+        return "x".into();
+    }
+    words.join("-")
+}
+
+const KEYWORDS: &[&str] = &[
+    "as",
+    "bool",
+    "borrow",
+    "char",
+    "constructor",
+    "enum",
+    "export",
+    "f32",
+    "f64",
+    "flags",
+    "from",
+    "func",
+    "future",
+    "import",
+    "include",
+    "interface",
+    "list",
+    "option",
+    "own",
+    "package",
+    "record",
+    "resource",
+    "result",
+    "s16",
+    "s32",
+    "s64",
+    "s8",
+    "static",
+    "stream",
+    "string",
+    "tuple",
+    "type",
+    "u16",
+    "u32",
+    "u64",
+    "u8",
+    "use",
+    "variant",
+    "with",
+    "world",
+];
+
+/// `%`-escape WIT keywords used as names.
+fn escape(name: &str) -> String {
+    if KEYWORDS.contains(&name) {
+        format!("%{name}")
+    } else {
+        name.to_string()
+    }
+}
@@ -10,7 +10,10 @@
 //! - Deterministic output (same input → same bytes)
 //! - Canonical examples compile successfully
 
-use pepl_codegen::{compile, CodegenError};
+use pepl_codegen::{
+    compile, compile_with_options, CodegenError, CodegenOptions, OptLevel, RecordLayout,
+    RecordLayouts, Scalar, ScalarTypes,
+};
 use pepl_lexer::Lexer;
 use pepl_parser::Parser;
 use pepl_types::SourceFile;
@@ -253,6 +256,81 @@ fn deterministic_output_100_iterations() {
     }
 }
 
+#[test]
+fn empty_scalar_types_reproduce_boxed_output() {
+    let prog = parse(COUNTER_SPACE);
+    let (typed, _) = compile_with_options(&prog, CodegenOptions::default()).unwrap();
+    assert_eq!(typed, compile(&prog).unwrap());
+}
+
+#[test]
+fn scalar_types_change_arithmetic_lowering() {
+    let prog = parse(COUNTER_SPACE);
+    let mut scalars = ScalarTypes::new();
+    let increment = &prog.space.body.actions[0].body.stmts[0];
+    let pepl_types::ast::Stmt::Set(set) = increment else {
+        panic!("expected set statement");
+    };
+    scalars.record(set.value.span, Some(Scalar::Number));
+    let options = CodegenOptions {
+        scalar_types: scalars,
+        ..CodegenOptions::default()
+    };
+    let (typed, _) = compile_with_options(&prog, options).unwrap();
+    assert!(is_valid_wasm(&typed));
+    assert_ne!(typed, compile(&prog).unwrap());
+}
+
+#[test]
+fn record_layout_orders_fields_by_name() {
+    let layout = RecordLayout::new(["y", "x", "label", "x"]);
+    assert_eq!(layout.fields(), ["label", "x", "y"]);
+    assert_eq!(layout.slot("x"), Some(1));
+    assert_eq!(layout.slot("z"), None);
+
+    let mut layouts = RecordLayouts::new();
+    let span = pepl_types::Span::new(1, 1, 1, 5);
+    layouts.record(span, Some(layout.clone()));
+    assert_eq!(layouts.get(span), Some(&layout));
+    layouts.record(span, Some(RecordLayout::new(["x"])));
+    assert_eq!(layouts.get(span), None, "conflicting layouts are unknown");
+}
+
+#[test]
+fn record_layouts_change_field_access_lowering() {
+    let source = r#"
+space Layout {
+  state {
+    point: { x: number, y: number } = { y: 2, x: 1 }
+    sum: number = 0
+  }
+  action add() {
+    set sum = point.x + point.y
+  }
+  view main() -> Surface { Column { } { } }
+}
+"#;
+    let prog = parse(source);
+    let pepl_types::ast::Stmt::Set(set) = &prog.space.body.actions[0].body.stmts[0] else {
+        panic!("expected set statement");
+    };
+    let pepl_types::ast::ExprKind::Binary { left, .. } = &set.value.kind else {
+        panic!("expected binary expression");
+    };
+    let pepl_types::ast::ExprKind::FieldAccess { object, .. } = &left.kind else {
+        panic!("expected field access");
+    };
+    let mut layouts = RecordLayouts::new();
+    layouts.record(object.span, Some(RecordLayout::new(["x", "y"])));
+    let options = CodegenOptions {
+        record_layouts: layouts,
+        ..CodegenOptions::default()
+    };
+    let (typed, _) = compile_with_options(&prog, options).unwrap();
+    assert!(is_valid_wasm(&typed));
+    assert_ne!(typed, compile(&prog).unwrap());
+}
+
 // ══════════════════════════════════════════════════════════════════════════════
 // Counter Example
 // ══════════════════════════════════════════════════════════════════════════════
@@ -920,12 +998,22 @@ space T {
 #[test]
 fn minimal_module_reasonable_size() {
     let wasm = compile_source(MINIMAL_SPACE);
-    // A minimal module should be under 10KB
+    // A minimal module should be under 12.5KB.  The budget was 10KB until
+    // every module gained serialize_state / restore_state: their JSON codec
+    // is live in every space, and O0 keeps all the runtime helpers besides
     assert!(
-        wasm.len() < 10_000,
+        wasm.len() < 12_500,
         "minimal module too large: {} bytes",
         wasm.len()
     );
+    // O1 drops the runtime helpers nothing calls, which brings the same
+    // module, codec included, back under 10KB
+    let (shaken, _) = compile_at(MINIMAL_SPACE, OptLevel::O1);
+    assert!(
+        shaken.len() < 10_000,
+        "minimal O1 module too large: {} bytes",
+        shaken.len()
+    );
 }
 
 #[test]
@@ -1472,3 +1560,262 @@ space DeepNest {
         );
     }
 }
+
+// ══════════════════════════════════════════════════════════════════════════════
+// Optimisation (OptLevel::O1)
+// ══════════════════════════════════════════════════════════════════════════════
+
+/// Compile PEPL source at the given optimisation level.
+fn compile_at(source: &str, opt_level: OptLevel) -> (Vec<u8>, pepl_codegen::SourceMap) {
+    let options = CodegenOptions {
+        opt_level,
+        ..CodegenOptions::default()
+    };
+    compile_with_options(&parse(source), options)
+        .unwrap_or_else(|e| panic!("codegen failed: {e}"))
+}
+
+/// Number of function bodies in the code section.
+fn count_functions(wasm: &[u8]) -> usize {
+    WasmParser::new(0)
+        .parse_all(wasm)
+        .filter(|p| matches!(p, Ok(Payload::CodeSectionEntry(_))))
+        .count()
+}
+
+/// The value expression of the first `set` in action `index`, after folding.
+fn folded_set_value(source: &str, index: usize) -> pepl_types::ast::ExprKind {
+    let folded = pepl_codegen::optimize::fold_program(&parse(source));
+    match &folded.space.body.actions[index].body.stmts[0] {
+        pepl_types::ast::Stmt::Set(set) => set.value.kind.clone(),
+        other => panic!("expected set statement, got {other:?}"),
+    }
+}
+
+#[test]
+fn o0_options_reproduce_compile() {
+    let (wasm, _) = compile_at(COUNTER_SPACE, OptLevel::O0);
+    assert_eq!(wasm, compile_source(COUNTER_SPACE));
+}
+
+#[test]
+fn o1_shakes_unused_runtime_helpers() {
+    let (o0, _) = compile_at(COUNTER_SPACE, OptLevel::O0);
+    let (o1, _) = compile_at(COUNTER_SPACE, OptLevel::O1);
+    assert!(is_valid_wasm(&o1));
+    assert!(count_functions(&o1) < count_functions(&o0));
+    assert!(o1.len() < o0.len());
+    assert_eq!(get_exports(&o1), get_exports(&o0));
+}
+
+#[test]
+fn o1_source_map_section_matches_remapped_functions() {
+    let (o1, source_map) = compile_at(COUNTER_SPACE, OptLevel::O1);
+    let embedded = get_custom_section_data(&o1, "pepl_source_map").unwrap();
+    assert_eq!(embedded, source_map.to_json());
+    let total = get_import_count(&o1) + count_functions(&o1);
+    for entry in &source_map.entries {
+        assert!((entry.wasm_func_index as usize) < total, "{}", entry.func_name);
+    }
+}
+
+#[test]
+fn o1_output_is_deterministic() {
+    let (reference, _) = compile_at(COUNTER_SPACE, OptLevel::O1);
+    for _ in 0..10 {
+        assert_eq!(compile_at(COUNTER_SPACE, OptLevel::O1).0, reference);
+    }
+}
+
+#[test]
+fn fold_literal_arithmetic_and_comparisons() {
+    let source = r#"
+space Fold {
+  state {
+    n: number = 0
+    ok: bool = false
+    s: string = ""
+  }
+  action a() { set n = (2 + 3) * 4 - 10 / 4 }
+  action b() { set ok = 3 > 2 and not false }
+  action c() { set s = "ab" + "cd" }
+  action d() { set n = 7 % 3 }
+  action e() { set n = -7 % 3 }
+  view main() -> Surface { Column { } { } }
+}
+"#;
+    use pepl_types::ast::ExprKind;
+    assert_eq!(folded_set_value(source, 0), ExprKind::NumberLit(17.5));
+    assert_eq!(folded_set_value(source, 1), ExprKind::BoolLit(true));
+    assert_eq!(folded_set_value(source, 2), ExprKind::StringLit("abcd".into()));
+    assert_eq!(folded_set_value(source, 3), ExprKind::NumberLit(1.0));
+    // The remainder takes the sign of the dividend, as in the evaluator
+    assert_eq!(folded_set_value(source, 4), ExprKind::NumberLit(-1.0));
+}
+
+#[test]
+fn fold_keeps_trapping_division() {
+    let source = r#"
+space Trap {
+  state { n: number = 0 }
+  action a() { set n = 1 / 0 }
+  action b() { set n = 0 / 0 }
+  view main() -> Surface { Column { } { } }
+}
+"#;
+    use pepl_types::ast::ExprKind;
+    assert!(matches!(folded_set_value(source, 0), ExprKind::Binary { .. }));
+    assert!(matches!(folded_set_value(source, 1), ExprKind::Binary { .. }));
+}
+
+#[test]
+fn fold_removes_literal_dead_branches() {
+    let source = r#"
+space Dead {
+  state { n: number = 0 }
+  action a() {
+    if 1 > 2 { set n = 1 } else { set n = 2 }
+    set n = n + 1
+  }
+  view main() -> Surface {
+    Column { } {
+      if false { Text { value: "never" } }
+      Text { value: "always" }
+    }
+  }
+}
+"#;
+    use pepl_types::ast::{ExprKind, Stmt, UIElement};
+    let folded = pepl_codegen::optimize::fold_program(&parse(source));
+    let body = &folded.space.body;
+    let stmts = &body.actions[0].body.stmts;
+    assert_eq!(stmts.len(), 2);
+    let Stmt::Set(set) = &stmts[0] else {
+        panic!("dead branch not spliced: {:?}", stmts[0]);
+    };
+    assert_eq!(set.value.kind, ExprKind::NumberLit(2.0));
+    let UIElement::Component(column) = &body.views[0].body.elements[0] else {
+        panic!("expected Column");
+    };
+    // A view conditional still renders as a (now empty) nested list.
+    let children = &column.children.as_ref().unwrap().elements;
+    let UIElement::If(dead) = &children[0] else {
+        panic!("expected the folded conditional, got {:?}", children[0]);
+    };
+    assert_eq!(dead.condition.kind, ExprKind::BoolLit(true));
+    assert!(dead.then_block.elements.is_empty());
+    assert!(dead.else_block.is_none());
+}
+
+// ══════════════════════════════════════════════════════════════════════════════
+// Compiled stdlib
+// ══════════════════════════════════════════════════════════════════════════════
+
+/// Number of `call host_call` instructions across all function bodies.
+fn host_call_sites(wasm: &[u8]) -> usize {
+    let mut sites = 0;
+    for payload in WasmParser::new(0).parse_all(wasm) {
+        if let Ok(Payload::CodeSectionEntry(body)) = payload {
+            let mut ops = body.get_operators_reader().unwrap();
+            while !ops.eof() {
+                if let wasmparser::Operator::Call { function_index: 0 } = ops.read().unwrap() {
+                    sites += 1;
+                }
+            }
+        }
+    }
+    sites
+}
+
+#[test]
+fn pure_stdlib_calls_stay_inside_module() {
+    let source = r#"
+space Pure {
+  state {
+    items: list<number> = [3, 1, 2]
+    out: string = ""
+  }
+  action run() {
+    let sorted = list.sort(items, fn(a: number, b: number) { a - b })
+    let shown = list.map(sorted, fn(x: number) { convert.to_string(math.round_to(x / 3, 2)) })
+    set out = string.trim(string.join(shown, ", ")) + json.stringify({ n: list.length(items) })
+  }
+  view main() -> Surface { Column { } { } }
+}
+"#;
+    for level in [OptLevel::O0, OptLevel::O1] {
+        let (wasm, _) = compile_at(source, level);
+        assert!(is_valid_wasm(&wasm));
+        assert_eq!(host_call_sites(&wasm), 0, "{level:?}");
+    }
+}
+
+#[test]
+fn capability_calls_still_use_host_call() {
+    let source = r#"
+space Fetch {
+  state {
+    body: string = ""
+  }
+  capabilities {
+    required: [http]
+  }
+  action load() {
+    let res = http.get("https://example.com")
+    set body = string.trim(convert.to_string(res))
+  }
+  view main() -> Surface { Column { } { } }
+}
+"#;
+    let (wasm, _) = compile_at(source, OptLevel::O1);
+    assert_eq!(host_call_sites(&wasm), 1);
+}
+
+// ══════════════════════════════════════════════════════════════════════════════
+// Disassembly
+// ══════════════════════════════════════════════════════════════════════════════
+
+#[test]
+fn disassembly_names_space_functions_and_runtime_helpers() {
+    let (wasm, source_map) = compile_at(COUNTER_SPACE, OptLevel::O0);
+    let wat = pepl_codegen::disassemble(&wasm, &source_map).unwrap();
+    assert!(wat.starts_with("(module\n"), "{wat}");
+    assert!(wat.contains("(import \"env\" \"host_call\" (func $host_call"));
+    assert!(wat.contains("(func $init (;"));
+    assert!(wat.contains("(func $dispatch_action (;"));
+    assert!(wat.contains("(func $get_state (;"));
+    assert!(wat.contains("(func $RT_ALLOC (;4;)"));
+    assert!(wat.contains("(func $display_into (;"));
+    assert!(!wat.contains("(func $list.map (;"));
+    assert!(wat.contains("call $RT_VAL_ADD"));
+    assert!(wat.contains("(export \"__trap_site\" (global $trap_site))"));
+}
+
+#[test]
+fn disassembly_keeps_helper_names_after_shaking() {
+    let (wasm, source_map) = compile_at(COUNTER_SPACE, OptLevel::O1);
+    assert!(!source_map.helpers.is_empty());
+    let wat = pepl_codegen::disassemble(&wasm, &source_map).unwrap();
+    assert!(wat.contains("(func $RT_ALLOC (;4;)"));
+    assert!(wat.contains("call $RT_VAL_ADD"));
+    assert!(!wat.contains("(func $list.map (;"));
+}
+
+#[test]
+fn disassembly_annotates_trap_sites_and_strings() {
+    let (wasm, source_map) = compile_at(COUNTER_SPACE, OptLevel::O1);
+    let wat =
+        pepl_codegen::disasm::disassemble_with_source(&wasm, &source_map, COUNTER_SPACE).unwrap();
+    assert!(wat.contains(";; 9:5 action increment | set count = count + 1"));
+    assert!(wat.contains(";; space init at 3:3"));
+    assert!(wat.contains(";; action increment at 8:3"));
+    assert!(wat.contains(";; \"Counter\""));
+    assert!(wat.contains("    \"Counter\"  ;; "));
+    assert!(wat.contains(";; custom section \"pepl_source_map\""));
+}
+
+#[test]
+fn disassembly_rejects_malformed_modules() {
+    let err = pepl_codegen::disassemble(b"\0asm\x01\0\0\0\x01", &Default::default());
+    assert!(matches!(err, Err(CodegenError::Internal(_))));
+}
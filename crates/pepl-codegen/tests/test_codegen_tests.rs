@@ -369,6 +369,447 @@ fn test_deterministic_compilation_with_tests() {
     assert_eq!(wasm1, wasm2, "same input must produce identical bytes");
 }
 
+// ══════════════════════════════════════════════════════════════════════════════
+// Tests — Render assertions
+// ══════════════════════════════════════════════════════════════════════════════
+
+fn run_test_fn(wasm: &[u8], name: &str) -> bool {
+    let (mut store, instance) = instantiate(wasm);
+    let test_fn = instance
+        .get_typed_func::<(), ()>(&store, name)
+        .unwrap_or_else(|_| panic!("{name} export missing"));
+    std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| test_fn.call(&mut store, ())))
+        .map(|r| r.is_ok())
+        .unwrap_or(false)
+}
+
+#[test]
+fn test_render_assertions_pass() {
+    let source = r#"
+space Counter {
+  state { count: number = 0 }
+  action increment() { set count = count + 1 }
+  view main() -> Surface {
+    Column { } {
+      Text { value: "Count" }
+      Row { } {
+        Button { label: "Add", on_tap: increment }
+        if count > 0 {
+          Text { value: "positive" }
+        }
+      }
+    }
+  }
+}
+
+tests {
+  test "render" {
+    let s = render(main)
+    assert_component(s, "Button", { label: "Add" })
+    assert count_components(s, "Text") == 1
+    increment()
+    let after = render(main)
+    assert count_components(after, "Text") == 2
+    assert_component(after, "Text", { value: "positive" })
+  }
+}
+"#;
+    let wasm = compile_source(source);
+    assert!(run_test_fn(&wasm, "__test_0"), "render assertions should pass");
+}
+
+#[test]
+fn test_render_assert_component_traps() {
+    let source = r#"
+space Counter {
+  state { count: number = 0 }
+  action increment() { set count = count + 1 }
+  view main() -> Surface {
+    Column { } {
+      Text { value: "Count" }
+      Row { } {
+        Button { label: "Add", on_tap: increment }
+        if count > 0 {
+          Text { value: "positive" }
+        }
+      }
+    }
+  }
+}
+
+tests {
+  test "missing" {
+    let s = render(main)
+    assert_component(s, "Button", { label: "Remove" })
+  }
+  test "hidden" {
+    let s = render(main)
+    assert_component(s, "Text", { value: "positive" })
+  }
+}
+"#;
+    let wasm = compile_source(source);
+    assert!(!run_test_fn(&wasm, "__test_0"), "wrong props should trap");
+    assert!(!run_test_fn(&wasm, "__test_1"), "unrendered branch should trap");
+}
+
+#[test]
+fn test_assert_snapshot_calls_test_hook() {
+    let source = r#"
+space Counter {
+  state { count: number = 0 }
+  action increment() { set count = count + 1 }
+  view main() -> Surface {
+    Column { } {
+      Text { value: "Count" }
+      Row { } {
+        Button { label: "Add", on_tap: increment }
+        if count > 0 {
+          Text { value: "positive" }
+        }
+      }
+    }
+  }
+}
+
+tests {
+  test "snapshot" {
+    let s = render(main)
+    assert_snapshot(s, "counter")
+  }
+}
+"#;
+    let wasm = compile_source(source);
+
+    let engine = Engine::default();
+    let module = Module::new(&engine, &wasm).unwrap();
+    let mut store = Store::new(&engine, Vec::<(i32, i32, String)>::new());
+    let mut linker = Linker::<Vec<(i32, i32, String)>>::new(&engine);
+    linker
+        .func_wrap(
+            "env",
+            "host_call",
+            |mut caller: wasmi::Caller<'_, Vec<(i32, i32, String)>>,
+             module: i32,
+             func: i32,
+             args: i32|
+             -> i32 {
+                // args: LIST [surface, name] — decode the name string
+                let mem = caller.get_export("memory").unwrap().into_memory().unwrap();
+                let word = |c: &wasmi::Caller<'_, _>, at: i32| {
+                    let mut buf = [0u8; 4];
+                    mem.read(c, at as usize, &mut buf).unwrap();
+                    i32::from_le_bytes(buf)
+                };
+                let arr = word(&caller, args + 4);
+                let name_val = word(&caller, arr + 4);
+                let ptr = word(&caller, name_val + 4);
+                let len = word(&caller, name_val + 8);
+                let mut name = vec![0u8; len as usize];
+                mem.read(&caller, ptr as usize, &mut name).unwrap();
+                let name = String::from_utf8(name).unwrap();
+                caller.data_mut().push((module, func, name));
+                0
+            },
+        )
+        .unwrap();
+    linker
+        .func_wrap("env", "log", |_: wasmi::Caller<'_, _>, _: i32, _: i32| {})
+        .unwrap();
+    linker
+        .func_wrap("env", "trap", |_: wasmi::Caller<'_, _>, _: i32, _: i32| -> () {
+            panic!("WASM trap triggered");
+        })
+        .unwrap();
+    linker
+        .func_wrap("env", "get_timestamp", |_: wasmi::Caller<'_, _>| -> i64 { 0 })
+        .unwrap();
+    let instance = linker
+        .instantiate(&mut store, &module)
+        .unwrap()
+        .start(&mut store)
+        .unwrap();
+
+    instance
+        .get_typed_func::<(), ()>(&store, "__test_0")
+        .unwrap()
+        .call(&mut store, ())
+        .unwrap();
+    assert_eq!(
+        store.data(),
+        &vec![(
+            pepl_codegen::test_codegen::TEST_HOOK_MODULE_ID,
+            pepl_codegen::test_codegen::TEST_HOOK_SNAPSHOT,
+            "counter".to_string()
+        )]
+    );
+}
+
+#[test]
+fn test_surface_helpers_not_exported() {
+    let source = r#"
+space Counter {
+  state { count: number = 0 }
+  action increment() { set count = count + 1 }
+  view main() -> Surface {
+    Column { } {
+      Text { value: "Count" }
+      Row { } {
+        Button { label: "Add", on_tap: increment }
+        if count > 0 {
+          Text { value: "positive" }
+        }
+      }
+    }
+  }
+}
+
+tests {
+  test "t" {
+    assert count == 0
+  }
+}
+"#;
+    let wasm = compile_source(source);
+    let names: Vec<String> = get_exports(&wasm).into_iter().map(|(n, _)| n).collect();
+    assert!(names.contains(&"__test_0".to_string()));
+    assert!(!names.iter().any(|n| n == "__surface_count" || n == "count_components"));
+}
+
+// ══════════════════════════════════════════════════════════════════════════════
+// Tests — Game loop built-ins
+// ══════════════════════════════════════════════════════════════════════════════
+
+const GAME_WITH_TESTS: &str = r#"
+space Game {
+  state {
+    y: number = 0
+    taps: number = 0
+  }
+
+  view main() -> Surface { Column { } { } }
+
+  update(dt: number) {
+    set y = y + dt
+  }
+
+  handleEvent(event: InputEvent) {
+    set taps = taps + 1
+  }
+}
+
+tests {
+  test "tick" {
+    tick(2)
+    assert y == 2
+  }
+
+  test "tick_n" {
+    tick_n(5, 2)
+    assert y == 10
+  }
+
+  test "send_event" {
+    send_event({ type: "tap" })
+    send_event({ type: "tap" })
+    assert taps == 2
+  }
+
+  test "wrong frame count" {
+    tick_n(3, 1)
+    assert y == 4
+  }
+}
+"#;
+
+#[test]
+fn test_tick_and_send_event_execute() {
+    let wasm = compile_source(GAME_WITH_TESTS);
+    for i in 0..3 {
+        assert!(run_test_fn(&wasm, &format!("__test_{i}")), "__test_{i} should pass");
+    }
+    assert!(!run_test_fn(&wasm, "__test_3"), "__test_3 should trap");
+}
+
+// ══════════════════════════════════════════════════════════════════════════════
+// Tests — Operators
+// ══════════════════════════════════════════════════════════════════════════════
+
+const OPERATORS_WITH_TESTS: &str = r#"
+space Calc {
+  state {
+    title: string = "level"
+  }
+
+  action rename() {
+    set title = title + " up"
+  }
+
+  view main() -> Surface { Column { } { } }
+}
+
+tests {
+  test "remainder takes the sign of the dividend" {
+    assert 7 % 2 == 1
+    assert -7 % 2 == -1
+    assert 7 % -2 == 1
+    assert -7.5 % 2 == -1.5
+  }
+
+  test "plus concatenates strings" {
+    rename()
+    assert title == "level up"
+  }
+}
+"#;
+
+#[test]
+fn test_operators_match_evaluator() {
+    let wasm = compile_source(OPERATORS_WITH_TESTS);
+    assert!(run_test_fn(&wasm, "__test_0"), "modulo");
+    assert!(run_test_fn(&wasm, "__test_1"), "string concatenation");
+}
+
+// ══════════════════════════════════════════════════════════════════════════════
+// Tests — Trap sites
+// ══════════════════════════════════════════════════════════════════════════════
+
+const DIVIDE_WITH_TESTS: &str = r#"
+space Calc {
+  state {
+    value: number = 0
+    zero: number = 0
+  }
+
+  action divide() {
+    set value = 1
+    set value = value / zero
+  }
+
+  view main() -> Surface { Column { } { } }
+}
+
+tests {
+  test "divide by zero" {
+    divide()
+  }
+
+  test "bad assert" {
+    assert value == 1
+  }
+}
+"#;
+
+/// Run a test export, returning the trap message and `__trap_site` value on failure.
+fn run_test_trap(wasm: &[u8], name: &str) -> Option<(String, u32)> {
+    let engine = Engine::default();
+    let module = Module::new(&engine, wasm).unwrap();
+    let mut store = Store::new(&engine, String::new());
+    let mut linker = Linker::<String>::new(&engine);
+    linker
+        .func_wrap("env", "host_call", |_: wasmi::Caller<'_, _>, _: i32, _: i32, _: i32| -> i32 { 0 })
+        .unwrap();
+    linker
+        .func_wrap("env", "log", |_: wasmi::Caller<'_, _>, _: i32, _: i32| {})
+        .unwrap();
+    linker
+        .func_wrap(
+            "env",
+            "trap",
+            |mut caller: wasmi::Caller<'_, String>, ptr: i32, len: i32| -> Result<(), wasmi::Error> {
+                let mem = caller.get_export("memory").unwrap().into_memory().unwrap();
+                let mut buf = vec![0u8; len as usize];
+                mem.read(&caller, ptr as usize, &mut buf).unwrap();
+                *caller.data_mut() = String::from_utf8(buf).unwrap();
+                Err(wasmi::Error::new("trap"))
+            },
+        )
+        .unwrap();
+    linker
+        .func_wrap("env", "get_timestamp", |_: wasmi::Caller<'_, _>| -> i64 { 0 })
+        .unwrap();
+    let instance = linker
+        .instantiate(&mut store, &module)
+        .unwrap()
+        .start(&mut store)
+        .unwrap();
+
+    let result = instance
+        .get_typed_func::<(), ()>(&store, name)
+        .unwrap()
+        .call(&mut store, ());
+    if result.is_ok() {
+        return None;
+    }
+    let site = instance
+        .get_global(&store, "__trap_site")
+        .expect("__trap_site export missing")
+        .get(&store)
+        .i32()
+        .unwrap();
+    Some((store.data().clone(), site as u32))
+}
+
+#[test]
+fn trap_site_resolves_to_action_statement() {
+    let (wasm, map) = compile_with_map(DIVIDE_WITH_TESTS);
+    let (message, site) = run_test_trap(&wasm, "__test_0").expect("division should trap");
+    assert!(site > 0);
+
+    let err = map.resolve_trap(site, &message);
+    assert_eq!(err.code, pepl_types::ErrorCode::ARITHMETIC_TRAP);
+    assert_eq!(err.span.unwrap().start_line, 10);
+    let frames: Vec<_> = err.frames.iter().map(|f| (f.kind, f.name.as_str())).collect();
+    assert_eq!(frames, vec![(pepl_types::FrameKind::Action, "divide")]);
+}
+
+#[test]
+fn trap_site_resolves_to_test_assertion() {
+    let (wasm, map) = compile_with_map(DIVIDE_WITH_TESTS);
+    let (message, site) = run_test_trap(&wasm, "__test_1").expect("assert should trap");
+
+    let err = map.resolve_trap(site, &message);
+    assert_eq!(err.code, pepl_types::ErrorCode::ASSERTION_FAILED);
+    assert_eq!(err.span.unwrap().start_line, 22);
+    assert_eq!(err.frames.len(), 1);
+    assert_eq!(err.frames[0].kind, pepl_types::FrameKind::Test);
+    assert_eq!(err.frames[0].name, "bad assert");
+}
+
+const BAD_FRAME_COUNTS: &str = r#"
+space Game {
+  state {
+    y: number = 0
+  }
+
+  view main() -> Surface { Column { } { } }
+
+  update(dt: number) {
+    set y = y + dt
+  }
+}
+
+tests {
+  test "fractional frames" {
+    tick_n(1.5, 1)
+  }
+
+  test "negative frames" {
+    tick_n(-1, 1)
+  }
+}
+"#;
+
+#[test]
+fn tick_n_traps_on_bad_frame_count() {
+    let wasm = compile_source(BAD_FRAME_COUNTS);
+    for i in 0..2 {
+        let (message, _) =
+            run_test_trap(&wasm, &format!("__test_{i}")).expect("bad frame count should trap");
+        assert_eq!(message, "invalid arguments to tick_n()");
+    }
+}
+
 // ══════════════════════════════════════════════════════════════════════════════
 // Tests — Source map
 // ══════════════════════════════════════════════════════════════════════════════
@@ -457,3 +898,155 @@ fn source_map_find_by_func_index() {
     assert!(found.is_some());
     assert_eq!(found.unwrap().func_name, "init");
 }
+
+// ══════════════════════════════════════════════════════════════════════════════
+// Tests — Compiled stdlib
+// ══════════════════════════════════════════════════════════════════════════════
+
+/// Each test exercises one stdlib module; lambdas live in actions because
+/// test bodies only call them.
+const STDLIB_SPACE: &str = r#"
+space Std {
+  state {
+    items: list<number> = [3, 1, 2]
+    out: string = ""
+  }
+
+  action transform() {
+    let doubled = list.map(items, fn(x: number) { x * 2 })
+    let big = list.filter(doubled, fn(x: number) { x > 2 })
+    let sorted = list.sort(big, fn(a: number, b: number) { a - b })
+    let total = list.reduce(sorted, 0, fn(acc: number, x: number) { acc + x })
+    let shown = list.map(sorted, fn(x: number) { convert.to_string(x) })
+    set out = "${string.join(shown, ",")}=${total}"
+  }
+
+  action nested() {
+    let below = list.map(items, fn(x: number) { list.filter(items, fn(y: number) { y < x }) })
+    set out = convert.to_string(below)
+  }
+
+  action parse_bad() {
+    match json.parse("oops") {
+      Ok(v) -> { set out = "ok ${core.type_of(v)}" }
+      Err(e) -> { set out = e }
+    }
+  }
+
+  action parse_good() {
+    match convert.parse_int("12") {
+      Ok(n) -> { set out = "ok ${n + 1}" }
+      Err(e) -> { set out = e }
+    }
+  }
+
+  view main() -> Surface { Column { } { } }
+}
+
+tests {
+  test "math" {
+    assert math.round(2.5) == 3
+    assert math.round(-2.5) == -3
+    assert math.floor(-1.5) == -2
+    assert math.pow(2, 10) == 1024
+    assert math.pow(2, -1) == 0.5
+    assert math.sqrt(16) == 4
+    assert math.clamp(15, 0, 10) == 10
+    assert math.round_to(3.14159, 2) == 3.14
+    assert math.max(1, 7) == 7
+    assert math.abs(-4) == 4
+    assert math.pow(4, 0.5) == 2
+  }
+
+  test "string" {
+    assert string.to_upper(string.trim("  hi ")) == "HI"
+    let s = json.parse("\"h\\u00e9llo\"")?
+    assert string.length(s) == 5
+    assert string.length(string.slice(s, 1, 3)) == 2
+    assert string.slice(s, 2, 5) == "llo"
+    assert string.join(string.split("a,b,c", ","), "-") == "a-b-c"
+    assert string.replace("aaa", "a", "b") == "baa"
+    assert string.replace_all("aaa", "a", "b") == "bbb"
+    assert string.pad_start("7", 3, "0") == "007"
+    assert string.pad_end("ab", 5, "xy") == "abxyx"
+    assert string.format("{a} and {b}", { a: 1, b: "x" }) == "1 and x"
+    assert string.index_of("hello", "l") == 2
+    assert string.repeat("ab", 3) == "ababab"
+    assert string.starts_with("hello", "he")
+    assert string.ends_with("hello", "lo")
+    assert "hello".contains("ell")
+    assert "hello".length() == 5
+  }
+
+  test "list" {
+    let xs = list.range(0, 5)
+    assert xs.length() == 5
+    assert convert.to_string(list.reverse(xs)) == "[4, 3, 2, 1, 0]"
+    assert convert.to_string(list.slice(xs, 1, 3)) == "[1, 2]"
+    assert convert.to_string(list.insert(xs, 1, 9)) == "[0, 9, 1, 2, 3, 4]"
+    assert convert.to_string(list.unique([1, 1, 2, 1])) == "[1, 2]"
+    assert convert.to_string(list.flatten([[1], [2, 3], 4])) == "[1, 2, 3, 4]"
+    assert convert.to_string(list.zip([1, 2], ["a"])) == "[[1, a]]"
+    assert list.get(xs, 9) == nil
+    assert list.contains(xs, 3)
+    assert list.index_of(xs, 4) == 4
+    assert convert.to_string(list.of(1, "a", true)) == "[1, a, true]"
+  }
+
+  test "record" {
+    let r = record.set({ b: 1, a: 2 }, "c", 3)
+    assert record.get(r, "c") == 3
+    assert record.has(r, "a")
+    assert convert.to_string(record.keys(r)) == "[a, b, c]"
+    assert convert.to_string(record.values(r)) == "[2, 1, 3]"
+  }
+
+  test "convert" {
+    assert convert.to_string(0.1 + 0.2) == "0.30000000000000004"
+    assert convert.to_string(1.5) == "1.5"
+    assert convert.to_string(-0.001) == "-0.001"
+    assert convert.to_string({ b: [1, nil], a: "x" }) == "{ a: x, b: [1, nil] }"
+    assert convert.parse_int(" 42 ")? == 42
+    assert convert.parse_float("2.5e3")? == 2500
+    assert convert.to_number(true)? == 1
+    assert convert.to_bool("")  == false
+    assert core.type_of([1]) == "list"
+    assert core.type_of(convert.parse_int("x")) == "result"
+    assert "${0.25}" == "0.25"
+  }
+
+  test "json" {
+    assert json.stringify({ b: [1, 2.5, true], a: nil }) == "{\"a\":null,\"b\":[1,2.5,true]}"
+    assert json.stringify("a\"b\n") == "\"a\\\"b\\n\""
+    let v = json.parse(" {\"k\": [1, -2.5e1, \"\\u00e9\\ud83d\\ude00\"], \"k\": {\"t\": false}} ")?
+    assert convert.to_string(v) == "{ k: { t: false } }"
+    let w = json.parse("[1, \"\\u00e9\\ud83d\\ude00\", null]")?
+    assert string.length(list.get(w, 1)) == 2
+    assert string.length(convert.to_string(w)) == 12
+    assert core.type_of(json.parse("[1,]")) == "result"
+  }
+
+  test "lambdas" {
+    transform()
+    assert out == "4,6=10"
+    nested()
+    assert out == "[[1, 2], [], [1]]"
+  }
+
+  test "results" {
+    parse_bad()
+    assert out == "invalid JSON"
+    parse_good()
+    assert out == "ok 13"
+  }
+}
+"#;
+
+#[test]
+fn stdlib_functions_run_in_module() {
+    let wasm = compile_source(STDLIB_SPACE);
+    let failed: Vec<usize> = (0..8)
+        .filter(|i| !run_test_fn(&wasm, &format!("__test_{i}")))
+        .collect();
+    assert!(failed.is_empty(), "failing stdlib tests: {failed:?}");
+}
@@ -0,0 +1,1511 @@
+//! Typed host bindings generated from a space's declarations.
+//!
+//! [`CompileResult`](crate::CompileResult) lists state fields, actions and
+//! views as name and type strings; [`generate`] turns the same declarations
+//! into code a host compiles against, so a space that changes shape breaks
+//! the host's build instead of its runtime:
+//!
+//! - **TypeScript** — a `.d.ts` with the state interface, sum types, typed
+//!   `dispatch.addItem(text: string)` methods, views and Surface node types
+//! - **JavaScript** — the ES module that `.d.ts` describes: it instantiates
+//!   the `.wasm`, writes action arguments into linear memory and reads state
+//!   and surfaces back
+//! - **Rust** — typed state structs, an action enum and a wrapper over a
+//!   `Runtime` trait the host implements on its WASM engine
+//!
+//! Values use the shape of `serialize_state`'s canonical JSON: records are
+//! objects, results are `{ Ok: … }` / `{ Err: … }`, unit variants are their
+//! name and other variants `{ Name: [fields…] }`.  Action names become
+//! camelCase methods in TypeScript and PascalCase variants in Rust.
+
+use std::collections::{HashMap, HashSet};
+
+use pepl_types::ast::*;
+use serde::{Deserialize, Serialize};
+
+use crate::checker::VALID_COMPONENTS;
+
+/// The three binding files for one space.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Bindings {
+    /// TypeScript declarations (`<space>.d.ts`).
+    pub typescript: String,
+    /// ES module implementing the declarations (`<space>.js`).
+    pub javascript: String,
+    /// Rust module (`<space>.rs`).
+    pub rust: String,
+}
+
+/// Generate host bindings for a parsed, type-checked program.
+pub fn generate(program: &Program) -> Bindings {
+    let space = Space::new(program);
+    Bindings {
+        typescript: space.typescript(),
+        javascript: space.javascript(),
+        rust: space.rust(),
+    }
+}
+
+/// Names the bindings declare themselves; user types that clash get a
+/// `Type` suffix.
+const RESERVED_TYPE_NAMES: &[&str] = &[
+    "Box",
+    "Component",
+    "Err",
+    "Error",
+    "InputEvent",
+    "None",
+    "Ok",
+    "Option",
+    "PeplValue",
+    "Result",
+    "Runtime",
+    "Some",
+    "String",
+    "SurfaceNode",
+    "Value",
+    "Vec",
+];
+
+// ══════════════════════════════════════════════════════════════════════════════
+// Space model
+// ══════════════════════════════════════════════════════════════════════════════
+
+/// A field of the state interface: a state or derived field.
+struct Field<'a> {
+    name: &'a str,
+    ty: &'a TypeAnnotation,
+    derived: bool,
+}
+
+struct Space<'a> {
+    name: &'a str,
+    body: &'a SpaceBody,
+    fields: Vec<Field<'a>>,
+    /// User type name → its declaration.
+    types: HashMap<&'a str, &'a TypeDeclBody>,
+    /// User type name → name in the bindings.
+    type_names: HashMap<&'a str, String>,
+    /// Anonymous record types, named for Rust, in first-use order.
+    records: Vec<(String, &'a [RecordTypeField])>,
+    /// Record shape → index into `records`.
+    record_index: HashMap<String, usize>,
+}
+
+impl<'a> Space<'a> {
+    fn new(program: &'a Program) -> Self {
+        let body = &program.space.body;
+        let name = program.space.name.name.as_str();
+
+        let mut fields: Vec<Field<'a>> = body
+            .state
+            .fields
+            .iter()
+            .map(|f| Field {
+                name: &f.name.name,
+                ty: &f.type_ann,
+                derived: false,
+            })
+            .collect();
+        if let Some(derived) = &body.derived {
+            fields.extend(derived.fields.iter().map(|f| Field {
+                name: &f.name.name,
+                ty: &f.type_ann,
+                derived: true,
+            }));
+        }
+
+        let mut taken: HashSet<String> = RESERVED_TYPE_NAMES
+            .iter()
+            .map(|n| n.to_string())
+            .chain(
+                ["", "State", "Action", "Actions", "Views", "Space"]
+                    .iter()
+                    .map(|suffix| format!("{name}{suffix}")),
+            )
+            .collect();
+        let mut types = HashMap::new();
+        let mut type_names = HashMap::new();
+        for decl in &body.types {
+            let mut bound = decl.name.name.clone();
+            while taken.contains(&bound) {
+                bound.push_str("Type");
+            }
+            taken.insert(bound.clone());
+            types.insert(decl.name.name.as_str(), &decl.body);
+            type_names.insert(decl.name.name.as_str(), bound);
+        }
+
+        let mut space = Self {
+            name,
+            body,
+            fields,
+            types,
+            type_names,
+            records: Vec::new(),
+            record_index: HashMap::new(),
+        };
+        space.collect_records(&mut taken);
+        space
+    }
+
+    /// Name every anonymous record type after where it first appears.
+    fn collect_records(&mut self, taken: &mut HashSet<String>) {
+        let mut uses: Vec<(String, &'a TypeAnnotation)> = Vec::new();
+        for decl in &self.body.types {
+            if let TypeDeclBody::SumType(variants) = &decl.body {
+                for variant in variants {
+                    for param in &variant.params {
+                        uses.push((pascal(&param.name.name), &param.type_ann));
+                    }
+                }
+            } else if let TypeDeclBody::Alias(ann) = &decl.body {
+                // An aliased record is the struct itself
+                let bound = self.type_names[decl.name.name.as_str()].clone();
+                if let TypeKind::Record(fields) = &ann.kind {
+                    let shape = ann.kind.to_string();
+                    if !self.record_index.contains_key(&shape) {
+                        self.record_index.insert(shape, self.records.len());
+                        self.records.push((bound.clone(), fields));
+                    }
+                }
+                uses.push((bound, ann));
+            }
+        }
+        for field in &self.fields {
+            uses.push((pascal(field.name), field.ty));
+        }
+        for action in &self.body.actions {
+            for param in &action.params {
+                uses.push((pascal(&param.name.name), &param.type_ann));
+            }
+        }
+        for (hint, ty) in uses {
+            self.collect_record(&hint, ty, taken);
+        }
+    }
+
+    fn collect_record(&mut self, hint: &str, ty: &'a TypeAnnotation, taken: &mut HashSet<String>) {
+        match &ty.kind {
+            TypeKind::List(item) => self.collect_record(&format!("{hint}Item"), item, taken),
+            TypeKind::Result(ok, err) => {
+                self.collect_record(&format!("{hint}Ok"), ok, taken);
+                self.collect_record(&format!("{hint}Err"), err, taken);
+            }
+            TypeKind::Record(fields) => {
+                let shape = ty.kind.to_string();
+                if !self.record_index.contains_key(&shape) {
+                    let mut name = hint.to_string();
+                    let mut n = 1;
+                    while taken.contains(&name) {
+                        n += 1;
+                        name = format!("{hint}{n}");
+                    }
+                    taken.insert(name.clone());
+                    self.record_index.insert(shape, self.records.len());
+                    self.records.push((name.clone(), fields));
+                }
+                for field in fields {
+                    let hint = format!("{hint}{}", pascal(&field.name.name));
+                    self.collect_record(&hint, &field.type_ann, taken);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn sum_type(&self, name: &str) -> Option<&'a [VariantDef]> {
+        match self.types.get(name) {
+            Some(TypeDeclBody::SumType(variants)) => Some(variants),
+            _ => None,
+        }
+    }
+
+    /// Variant ids in `dispatch_action`'s numbering: declaration order
+    /// across all sum types.
+    fn variants(&self) -> impl Iterator<Item = (u32, &'a str, &'a VariantDef)> + '_ {
+        self.body
+            .types
+            .iter()
+            .filter_map(|decl| match &decl.body {
+                TypeDeclBody::SumType(variants) => Some((decl.name.name.as_str(), variants)),
+                TypeDeclBody::Alias(_) => None,
+            })
+            .flat_map(|(ty, variants)| variants.iter().map(move |v| (ty, v)))
+            .enumerate()
+            .map(|(id, (ty, v))| (id as u32, ty, v))
+    }
+
+    fn uses_optional_fields(&self) -> bool {
+        self.records
+            .iter()
+            .any(|(_, fields)| fields.iter().any(|f| f.optional))
+    }
+
+    // ══════════════════════════════════════════════════════════════════════
+    // TypeScript
+    // ══════════════════════════════════════════════════════════════════════
+
+    fn typescript(&self) -> String {
+        let name = self.name;
+        let mut out =
+            format!("// Generated by pepl-compiler from space `{name}`. Do not edit.\n\n");
+
+        for decl in &self.body.types {
+            let bound = &self.type_names[decl.name.name.as_str()];
+            let ty = match &decl.body {
+                TypeDeclBody::Alias(ann) => self.ts_type(ann),
+                TypeDeclBody::SumType(variants) => variants
+                    .iter()
+                    .map(|v| {
+                        if v.params.is_empty() {
+                            format!("\"{}\"", v.name.name)
+                        } else {
+                            let params: Vec<String> = v
+                                .params
+                                .iter()
+                                .map(|p| format!("{}: {}", p.name.name, self.ts_type(&p.type_ann)))
+                                .collect();
+                            format!("{{ {}: [{}] }}", v.name.name, params.join(", "))
+                        }
+                    })
+                    .collect::<Vec<_>>()
+                    .join(" | "),
+            };
+            out.push_str(&format!("export type {bound} = {ty};\n\n"));
+        }
+
+        out.push_str(&format!(
+            "/** `state` and `derived` fields of `{name}`. */\nexport interface {name}State {{\n"
+        ));
+        for field in &self.fields {
+            if field.derived {
+                out.push_str("  /** Derived: recomputed after every action. */\n  readonly ");
+            } else {
+                out.push_str("  ");
+            }
+            out.push_str(&format!("{}: {};\n", field.name, self.ts_type(field.ty)));
+        }
+        out.push_str("}\n\n");
+
+        out.push_str(&format!(
+            "/** Actions of `{name}`; each call runs `dispatch_action`. */\n\
+             export interface {name}Actions {{\n"
+        ));
+        for action in &self.body.actions {
+            let params: Vec<String> = action
+                .params
+                .iter()
+                .map(|p| format!("{}: {}", ts_param(&p.name.name), self.ts_type(&p.type_ann)))
+                .collect();
+            out.push_str(&format!(
+                "  {}({}): void;\n",
+                camel(&action.name.name),
+                params.join(", ")
+            ));
+        }
+        out.push_str("}\n\n");
+
+        out.push_str(&format!(
+            "/** Views of `{name}`, rendered to surface trees. */\nexport interface {name}Views {{\n"
+        ));
+        for view in &self.body.views {
+            out.push_str(&format!("  {}(): SurfaceNode[];\n", camel(&view.name.name)));
+        }
+        out.push_str("}\n\n");
+
+        let components: Vec<String> = VALID_COMPONENTS
+            .iter()
+            .map(|c| format!("\"{c}\""))
+            .collect();
+        out.push_str(&format!(
+            "export type Component =\n  | {};\n\n",
+            components.join("\n  | ")
+        ));
+        out.push_str(TS_SURFACE);
+
+        out.push_str(&format!(
+            "/** An instantiated `{name}` module. */\nexport interface {name}Space {{\n"
+        ));
+        out.push_str(&format!(
+            "  readonly instance: WebAssembly.Instance;\n  \
+             readonly dispatch: {name}Actions;\n  \
+             readonly render: {name}Views;\n"
+        ));
+        out.push_str(&format!(
+            "  /** Current state and derived fields (`get_state`). */\n  state(): {name}State;\n"
+        ));
+        out.push_str(TS_PERSISTENCE);
+        if self.body.update.is_some() {
+            out.push_str("  update(dt: number): void;\n");
+        }
+        if self.body.handle_event.is_some() {
+            out.push_str("  handleEvent(event: InputEvent): void;\n");
+        }
+        out.push_str("}\n\n");
+
+        out.push_str(&format!(
+            "/**\n \
+             * Instantiate a compiled `{name}` module and run `init`.  `env` replaces\n \
+             * the default imports; without `host_call`, capabilities return nil.\n \
+             */\n\
+             export function instantiate(\n  \
+             source: BufferSource | WebAssembly.Module,\n  \
+             env?: WebAssembly.ModuleImports,\n\
+             ): Promise<{name}Space>;\n"
+        ));
+        out
+    }
+
+    fn ts_type(&self, ty: &TypeAnnotation) -> String {
+        match &ty.kind {
+            TypeKind::Number => "number".into(),
+            TypeKind::String => "string".into(),
+            TypeKind::Bool => "boolean".into(),
+            TypeKind::Nil => "null".into(),
+            TypeKind::Surface => "SurfaceNode[]".into(),
+            TypeKind::InputEvent => "InputEvent".into(),
+            TypeKind::Any | TypeKind::Color | TypeKind::Function { .. } => "unknown".into(),
+            TypeKind::List(item) => {
+                let item = self.ts_type(item);
+                if item.chars().all(|c| c.is_alphanumeric() || c == '_') {
+                    format!("{item}[]")
+                } else {
+                    format!("Array<{item}>")
+                }
+            }
+            TypeKind::Record(fields) => {
+                if fields.is_empty() {
+                    return "{}".into();
+                }
+                let fields: Vec<String> = fields
+                    .iter()
+                    .map(|f| {
+                        let optional = if f.optional { "?" } else { "" };
+                        format!("{}{optional}: {}", f.name.name, self.ts_type(&f.type_ann))
+                    })
+                    .collect();
+                format!("{{ {} }}", fields.join("; "))
+            }
+            TypeKind::Result(ok, err) => format!(
+                "{{ Ok: {} }} | {{ Err: {} }}",
+                self.ts_type(ok),
+                self.ts_type(err)
+            ),
+            TypeKind::Named(name) => self
+                .type_names
+                .get(name.as_str())
+                .cloned()
+                .unwrap_or_else(|| "unknown".into()),
+        }
+    }
+
+    // ══════════════════════════════════════════════════════════════════════
+    // JavaScript
+    // ══════════════════════════════════════════════════════════════════════
+
+    fn javascript(&self) -> String {
+        let mut out = format!(
+            "// Generated by pepl-compiler from space `{}`. Do not edit.\n\n",
+            self.name
+        );
+
+        let actions: Vec<String> = self
+            .body
+            .actions
+            .iter()
+            .map(|a| format!("\"{}\"", a.name.name))
+            .collect();
+        out.push_str(&format!("const ACTIONS = [{}];\n", actions.join(", ")));
+        let variants: Vec<String> = self
+            .variants()
+            .map(|(_, _, v)| format!("\"{}\"", v.name.name))
+            .collect();
+        out.push_str(&format!("const VARIANTS = [{}];\n", variants.join(", ")));
+
+        // Sum type name → variant name → [id, field schemas]
+        out.push_str("const TYPES = {");
+        let mut any_type = false;
+        for decl in &self.body.types {
+            if self.sum_type(&decl.name.name).is_none() {
+                continue;
+            }
+            any_type = true;
+            out.push_str(&format!("\n  {}: {{\n", decl.name.name));
+            for (id, _, variant) in self.variants().filter(|(_, ty, _)| *ty == decl.name.name) {
+                let fields: Vec<String> = variant
+                    .params
+                    .iter()
+                    .map(|p| self.js_schema(&p.type_ann, 0))
+                    .collect();
+                out.push_str(&format!(
+                    "    {}: [{id}, [{}]],\n",
+                    variant.name.name,
+                    fields.join(", ")
+                ));
+            }
+            out.push_str("  },");
+        }
+        out.push_str(if any_type { "\n};\n" } else { "};\n" });
+
+        out.push_str(JS_RUNTIME);
+
+        out.push_str(
+            "function bind(instance) {\n  \
+             const exports = instance.exports;\n  \
+             const dispatch = (id, schemas, args) => {\n    \
+             const ptrs = args.map((arg, i) => encode(exports, schemas[i], arg));\n    \
+             exports.dispatch_action(id, list(exports, ptrs), 0);\n  \
+             };\n  \
+             return {\n    \
+             instance,\n    \
+             dispatch: {\n",
+        );
+        for (id, action) in self.body.actions.iter().enumerate() {
+            let params: Vec<String> = action
+                .params
+                .iter()
+                .map(|p| ts_param(&p.name.name))
+                .collect();
+            let schemas: Vec<String> = action
+                .params
+                .iter()
+                .map(|p| self.js_schema(&p.type_ann, 0))
+                .collect();
+            out.push_str(&format!(
+                "      {}: ({params}) => dispatch({id}, [{}], [{params}]),\n",
+                camel(&action.name.name),
+                schemas.join(", "),
+                params = params.join(", "),
+            ));
+        }
+        out.push_str("    },\n    render: {\n");
+        for (id, view) in self.body.views.iter().enumerate() {
+            out.push_str(&format!(
+                "      {}: () => surface(decode(exports, exports.render({id}))),\n",
+                camel(&view.name.name)
+            ));
+        }
+        out.push_str("    },\n");
+        out.push_str(JS_STATE);
+        if self.body.update.is_some() {
+            out.push_str("    update: (dt) => exports.update(encode(exports, \"number\", dt)),\n");
+        }
+        if self.body.handle_event.is_some() {
+            out.push_str(
+                "    handleEvent: (event) => exports.handle_event(encode(exports, \"any\", event)),\n",
+            );
+        }
+        out.push_str("  };\n}\n");
+        out
+    }
+
+    /// The encoder schema for values of `ty` (see `encode` in [`JS_RUNTIME`]).
+    fn js_schema(&self, ty: &TypeAnnotation, depth: usize) -> String {
+        match &ty.kind {
+            TypeKind::Number => "\"number\"".into(),
+            TypeKind::String => "\"string\"".into(),
+            TypeKind::Bool => "\"bool\"".into(),
+            TypeKind::Nil => "\"nil\"".into(),
+            TypeKind::List(item) => format!("[\"list\", {}]", self.js_schema(item, depth)),
+            TypeKind::Record(fields) => {
+                let fields: Vec<String> = fields
+                    .iter()
+                    .map(|f| format!("{}: {}", f.name.name, self.js_schema(&f.type_ann, depth)))
+                    .collect();
+                if fields.is_empty() {
+                    "[\"record\", {}]".into()
+                } else {
+                    format!("[\"record\", {{ {} }}]", fields.join(", "))
+                }
+            }
+            TypeKind::Result(ok, err) => format!(
+                "[\"result\", {}, {}]",
+                self.js_schema(ok, depth),
+                self.js_schema(err, depth)
+            ),
+            // Aliases are inlined; the depth guard only matters for
+            // programs the checker would have rejected
+            TypeKind::Named(name) => match self.types.get(name.as_str()) {
+                Some(TypeDeclBody::SumType(_)) => format!("[\"variants\", \"{name}\"]"),
+                Some(TypeDeclBody::Alias(target)) if depth < 32 => {
+                    self.js_schema(target, depth + 1)
+                }
+                _ => "\"any\"".into(),
+            },
+            _ => "\"any\"".into(),
+        }
+    }
+
+    // ══════════════════════════════════════════════════════════════════════
+    // Rust
+    // ══════════════════════════════════════════════════════════════════════
+
+    fn rust(&self) -> String {
+        let name = self.name;
+        let mut out = format!(
+            "//! Host bindings for the PEPL space `{name}`.\n//!\n\
+             //! Generated by pepl-compiler. Do not edit.\n\n"
+        );
+        out.push_str(RUST_VALUES);
+        if self.uses_optional_fields() {
+            out.push_str(RUST_OPTIONAL);
+        }
+        out.push_str(RUST_SURFACE);
+
+        // Runtime trait
+        out.push_str(
+            "/// The module's exports, implemented by the host on its WASM engine.\n\
+             pub trait Runtime {\n    \
+             type Error;\n\n    \
+             /// `dispatch_action(action_id, args)`, with `args` written as a list.\n    \
+             fn dispatch_action(&mut self, action_id: u32, args: Vec<Value>) -> Result<(), Self::Error>;\n\n    \
+             /// `get_state()`, read back.\n    \
+             fn get_state(&mut self) -> Result<Value, Self::Error>;\n\n    \
+             /// `render(view_id)`, read back.\n    \
+             fn render(&mut self, view_id: u32) -> Result<Value, Self::Error>;\n",
+        );
+        if self.body.update.is_some() {
+            out.push_str(
+                "\n    /// `update(dt)`, with `dt` written as a number.\n    \
+                 fn update(&mut self, dt: Value) -> Result<(), Self::Error>;\n",
+            );
+        }
+        if self.body.handle_event.is_some() {
+            out.push_str(
+                "\n    /// `handle_event(event)`, with `event` written as a record.\n    \
+                 fn handle_event(&mut self, event: Value) -> Result<(), Self::Error>;\n",
+            );
+        }
+        out.push_str("}\n");
+
+        for decl in &self.body.types {
+            let bound = &self.type_names[decl.name.name.as_str()];
+            match &decl.body {
+                TypeDeclBody::Alias(ann) => {
+                    let ty = self.rust_type(ann);
+                    if ty != *bound {
+                        out.push_str(&format!("\npub type {bound} = {ty};\n"));
+                    }
+                }
+                TypeDeclBody::SumType(_) => self.rust_enum(&decl.name.name, &mut out),
+            }
+        }
+        for (record, fields) in &self.records {
+            let fields: Vec<(&str, &TypeAnnotation, bool, Option<&str>)> = fields
+                .iter()
+                .map(|f| (f.name.name.as_str(), &f.type_ann, f.optional, None))
+                .collect();
+            self.rust_struct(record, None, &fields, &mut out);
+        }
+
+        let state: Vec<(&str, &TypeAnnotation, bool, Option<&str>)> = self
+            .fields
+            .iter()
+            .map(|f| {
+                let doc = f
+                    .derived
+                    .then_some("Derived: recomputed after every action.");
+                (f.name, f.ty, false, doc)
+            })
+            .collect();
+        let doc = format!("`state` and `derived` fields of `{name}`.");
+        self.rust_struct(&format!("{name}State"), Some(&doc), &state, &mut out);
+
+        self.rust_actions(&mut out);
+        self.rust_wrapper(&mut out);
+        out
+    }
+
+    fn rust_type(&self, ty: &TypeAnnotation) -> String {
+        match &ty.kind {
+            TypeKind::Number => "f64".into(),
+            TypeKind::String => "String".into(),
+            TypeKind::Bool => "bool".into(),
+            TypeKind::Nil => "()".into(),
+            TypeKind::Surface => "Vec<SurfaceNode>".into(),
+            TypeKind::Any | TypeKind::Color | TypeKind::Function { .. } | TypeKind::InputEvent => {
+                "Value".into()
+            }
+            TypeKind::List(item) => format!("Vec<{}>", self.rust_type(item)),
+            TypeKind::Record(_) => {
+                let index = self.record_index[&ty.kind.to_string()];
+                self.records[index].0.clone()
+            }
+            TypeKind::Result(ok, err) => {
+                format!("Result<{}, {}>", self.rust_type(ok), self.rust_type(err))
+            }
+            TypeKind::Named(name) => self
+                .type_names
+                .get(name.as_str())
+                .cloned()
+                .unwrap_or_else(|| "Value".into()),
+        }
+    }
+
+    /// Whether a variant field of type `ty` needs a `Box` to keep a
+    /// recursive sum type finite: it holds a sum type outside a list.
+    fn needs_box(&self, ty: &TypeAnnotation) -> bool {
+        match &ty.kind {
+            TypeKind::Named(name) => self.types.contains_key(name.as_str()),
+            TypeKind::Record(fields) => fields.iter().any(|f| self.needs_box(&f.type_ann)),
+            TypeKind::Result(ok, err) => self.needs_box(ok) || self.needs_box(err),
+            _ => false,
+        }
+    }
+
+    fn rust_enum(&self, ty: &str, out: &mut String) {
+        let bound = &self.type_names[ty];
+        let variants: Vec<(u32, &VariantDef)> = self
+            .variants()
+            .filter(|(_, owner, _)| *owner == ty)
+            .map(|(id, _, v)| (id, v))
+            .collect();
+
+        out.push_str(&format!(
+            "\n#[derive(Debug, Clone, PartialEq)]\npub enum {bound} {{\n"
+        ));
+        for (_, variant) in &variants {
+            if variant.params.is_empty() {
+                out.push_str(&format!("    {},\n", variant.name.name));
+                continue;
+            }
+            let fields: Vec<String> = variant
+                .params
+                .iter()
+                .map(|p| {
+                    let ty = self.rust_type(&p.type_ann);
+                    let ty = if self.needs_box(&p.type_ann) {
+                        format!("Box<{ty}>")
+                    } else {
+                        ty
+                    };
+                    format!("{}: {ty}", rust_field(&p.name.name))
+                })
+                .collect();
+            out.push_str(&format!(
+                "    {} {{ {} }},\n",
+                variant.name.name,
+                fields.join(", ")
+            ));
+        }
+        out.push_str("}\n\n");
+
+        out.push_str(&format!(
+            "impl PeplValue for {bound} {{\n    \
+             fn into_value(self) -> Value {{\n        \
+             match self {{\n"
+        ));
+        for (id, variant) in &variants {
+            let names: Vec<String> = variant
+                .params
+                .iter()
+                .map(|p| rust_field(&p.name.name))
+                .collect();
+            if names.is_empty() {
+                out.push_str(&format!(
+                    "            {bound}::{} => Value::Variant({id}, Vec::new()),\n",
+                    variant.name.name
+                ));
+            } else {
+                let values: Vec<String> =
+                    names.iter().map(|n| format!("{n}.into_value()")).collect();
+                out.push_str(&format!(
+                    "            {bound}::{} {{ {} }} => {{\n                \
+                     Value::Variant({id}, vec![{}])\n            \
+                     }}\n",
+                    variant.name.name,
+                    names.join(", "),
+                    values.join(", ")
+                ));
+            }
+        }
+        out.push_str(
+            "        }\n    }\n\n    \
+             fn from_value(value: Value) -> Option<Self> {\n        \
+             let Value::Variant(id, fields) = value else {\n            \
+             return None;\n        \
+             };\n        \
+             let mut fields = fields.into_iter();\n        \
+             let variant = match id {\n",
+        );
+        for (id, variant) in &variants {
+            if variant.params.is_empty() {
+                out.push_str(&format!(
+                    "            {id} => {bound}::{},\n",
+                    variant.name.name
+                ));
+                continue;
+            }
+            out.push_str(&format!(
+                "            {id} => {bound}::{} {{\n",
+                variant.name.name
+            ));
+            for param in &variant.params {
+                out.push_str(&format!(
+                    "                {}: PeplValue::from_value(fields.next()?)?,\n",
+                    rust_field(&param.name.name)
+                ));
+            }
+            out.push_str("            },\n");
+        }
+        out.push_str(
+            "            _ => return None,\n        \
+             };\n        \
+             fields.next().is_none().then_some(variant)\n    \
+             }\n}\n",
+        );
+    }
+
+    /// A struct over record fields `(key, type, optional, doc)`.
+    fn rust_struct(
+        &self,
+        name: &str,
+        doc: Option<&str>,
+        fields: &[(&str, &TypeAnnotation, bool, Option<&str>)],
+        out: &mut String,
+    ) {
+        out.push('\n');
+        if let Some(doc) = doc {
+            out.push_str(&format!("/// {doc}\n"));
+        }
+        out.push_str(&format!(
+            "#[derive(Debug, Clone, PartialEq)]\npub struct {name} {{\n"
+        ));
+        for (key, ty, optional, doc) in fields {
+            if let Some(doc) = doc {
+                out.push_str(&format!("    /// {doc}\n"));
+            }
+            let ty = self.rust_type(ty);
+            let ty = if *optional {
+                format!("Option<{ty}>")
+            } else {
+                ty
+            };
+            out.push_str(&format!("    pub {}: {ty},\n", rust_field(key)));
+        }
+        out.push_str("}\n\n");
+
+        out.push_str(&format!(
+            "impl PeplValue for {name} {{\n    \
+             fn into_value(self) -> Value {{\n"
+        ));
+        let required: Vec<String> = fields
+            .iter()
+            .filter(|f| !f.2)
+            .map(|(key, ..)| {
+                format!(
+                    "            (\"{key}\".to_string(), self.{}.into_value()),\n",
+                    rust_field(key)
+                )
+            })
+            .collect();
+        let has_optional = fields.iter().any(|f| f.2);
+        if has_optional {
+            out.push_str(&format!(
+                "        let mut fields = vec![\n{}        ];\n",
+                required.concat()
+            ));
+            for (key, ..) in fields.iter().filter(|f| f.2) {
+                let field = rust_field(key);
+                out.push_str(&format!(
+                    "        if let Some(value) = self.{field} {{\n            \
+                     fields.push((\"{key}\".to_string(), value.into_value()));\n        \
+                     }}\n"
+                ));
+            }
+            out.push_str("        Value::Record(fields)\n");
+        } else if required.is_empty() {
+            out.push_str("        Value::Record(Vec::new())\n");
+        } else {
+            out.push_str(&format!(
+                "        Value::Record(vec![\n{}        ])\n",
+                required.concat()
+            ));
+        }
+        out.push_str("    }\n\n    fn from_value(value: Value) -> Option<Self> {\n");
+        if fields.is_empty() {
+            out.push_str(&format!(
+                "        matches!(value, Value::Record(_)).then_some({name} {{}})\n    }}\n}}\n"
+            ));
+            return;
+        }
+        out.push_str(&format!(
+            "        let Value::Record(mut fields) = value else {{\n            \
+             return None;\n        \
+             }};\n        \
+             Some({name} {{\n"
+        ));
+        for (key, _, optional, _) in fields {
+            let field = rust_field(key);
+            if *optional {
+                out.push_str(&format!(
+                    "            {field}: optional(take(&mut fields, \"{key}\"))?,\n"
+                ));
+            } else {
+                out.push_str(&format!(
+                    "            {field}: PeplValue::from_value(take(&mut fields, \"{key}\")?)?,\n"
+                ));
+            }
+        }
+        out.push_str("        })\n    }\n}\n");
+    }
+
+    fn rust_actions(&self, out: &mut String) {
+        let name = self.name;
+        let actions = &self.body.actions;
+        out.push_str(&format!(
+            "\n/// Actions of `{name}`, in `dispatch_action` order.\n\
+             #[derive(Debug, Clone, PartialEq)]\npub enum {name}Action {{\n"
+        ));
+        for action in actions {
+            let variant = pascal(&action.name.name);
+            if action.params.is_empty() {
+                out.push_str(&format!("    {variant},\n"));
+            } else {
+                let params: Vec<String> = action
+                    .params
+                    .iter()
+                    .map(|p| {
+                        format!(
+                            "{}: {}",
+                            rust_field(&p.name.name),
+                            self.rust_type(&p.type_ann)
+                        )
+                    })
+                    .collect();
+                out.push_str(&format!("    {variant} {{ {} }},\n", params.join(", ")));
+            }
+        }
+        out.push_str("}\n\n");
+
+        out.push_str(&format!(
+            "impl {name}Action {{\n    \
+             /// The id `dispatch_action` expects.\n    \
+             pub fn id(&self) -> u32 {{\n        \
+             match *self {{\n"
+        ));
+        for (id, action) in actions.iter().enumerate() {
+            let rest = if action.params.is_empty() {
+                ""
+            } else {
+                " { .. }"
+            };
+            out.push_str(&format!(
+                "            {name}Action::{}{rest} => {id},\n",
+                pascal(&action.name.name)
+            ));
+        }
+        out.push_str(
+            "        }\n    }\n\n    \
+             /// The arguments, in declaration order.\n    \
+             pub fn into_args(self) -> Vec<Value> {\n        \
+             match self {\n",
+        );
+        for action in actions {
+            let variant = pascal(&action.name.name);
+            if action.params.is_empty() {
+                out.push_str(&format!(
+                    "            {name}Action::{variant} => Vec::new(),\n"
+                ));
+                continue;
+            }
+            let names: Vec<String> = action
+                .params
+                .iter()
+                .map(|p| rust_field(&p.name.name))
+                .collect();
+            let values: Vec<String> = names.iter().map(|n| format!("{n}.into_value()")).collect();
+            out.push_str(&format!(
+                "            {name}Action::{variant} {{ {} }} => {{\n                \
+                 vec![{}]\n            \
+                 }}\n",
+                names.join(", "),
+                values.join(", ")
+            ));
+        }
+        out.push_str("        }\n    }\n}\n");
+    }
+
+    fn rust_wrapper(&self, out: &mut String) {
+        let name = self.name;
+        out.push_str(&format!(
+            "\n/// A `{name}` module driven through a host [`Runtime`].\n\
+             pub struct {name}<R> {{\n    \
+             runtime: R,\n\
+             }}\n\n\
+             impl<R: Runtime> {name}<R> {{\n    \
+             pub fn new(runtime: R) -> Self {{\n        \
+             {name} {{ runtime }}\n    \
+             }}\n\n    \
+             pub fn runtime(&mut self) -> &mut R {{\n        \
+             &mut self.runtime\n    \
+             }}\n\n    \
+             pub fn into_runtime(self) -> R {{\n        \
+             self.runtime\n    \
+             }}\n\n    \
+             pub fn dispatch(&mut self, action: {name}Action) -> Result<(), R::Error> {{\n        \
+             let id = action.id();\n        \
+             self.runtime.dispatch_action(id, action.into_args())\n    \
+             }}\n\n    \
+             pub fn state(&mut self) -> Result<{name}State, Error<R::Error>> {{\n        \
+             let value = self.runtime.get_state().map_err(Error::Runtime)?;\n        \
+             {name}State::from_value(value).ok_or(Error::Shape(\"{name}State\"))\n    \
+             }}\n"
+        ));
+        for (id, view) in self.body.views.iter().enumerate() {
+            out.push_str(&format!(
+                "\n    pub fn render_{}(&mut self) -> Result<Vec<SurfaceNode>, Error<R::Error>> {{\n        \
+                 let value = self.runtime.render({id}).map_err(Error::Runtime)?;\n        \
+                 surface(value).ok_or(Error::Shape(\"Surface\"))\n    \
+                 }}\n",
+                snake(&view.name.name)
+            ));
+        }
+        if self.body.update.is_some() {
+            out.push_str(
+                "\n    pub fn update(&mut self, dt: f64) -> Result<(), R::Error> {\n        \
+                 self.runtime.update(dt.into_value())\n    \
+                 }\n",
+            );
+        }
+        if self.body.handle_event.is_some() {
+            out.push_str(
+                "\n    pub fn handle_event(&mut self, event: Value) -> Result<(), R::Error> {\n        \
+                 self.runtime.handle_event(event)\n    \
+                 }\n",
+            );
+        }
+        out.push_str("}\n");
+    }
+}
+
+// ══════════════════════════════════════════════════════════════════════════════
+// Names
+// ══════════════════════════════════════════════════════════════════════════════
+
+/// `add_item` / `addItem` → `AddItem`.
+fn pascal(name: &str) -> String {
+    name.split('_')
+        .filter(|part| !part.is_empty())
+        .map(|part| {
+            let mut chars = part.chars();
+            match chars.next() {
+                Some(first) => first.to_uppercase().chain(chars).collect(),
+                None => String::new(),
+            }
+        })
+        .collect()
+}
+
+/// `add_item` → `addItem`.
+fn camel(name: &str) -> String {
+    let pascal = pascal(name);
+    let mut chars = pascal.chars();
+    match chars.next() {
+        Some(first) => first.to_lowercase().chain(chars).collect(),
+        None => pascal,
+    }
+}
+
+/// `newHabit` → `new_habit`.
+fn snake(name: &str) -> String {
+    let mut out = String::with_capacity(name.len() + 4);
+    for (i, c) in name.chars().enumerate() {
+        if c.is_uppercase() {
+            if i > 0 && !out.ends_with('_') {
+                out.push('_');
+            }
+            out.extend(c.to_lowercase());
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+/// A Rust field or binding name for a PEPL identifier.
+fn rust_field(name: &str) -> String {
+    const KEYWORDS: &[&str] = &[
+        "abstract", "as", "async", "await", "become", "box", "break", "const", "continue", "do",
+        "dyn", "else", "enum", "extern", "false", "final", "fn", "for", "gen", "if", "impl", "in",
+        "let", "loop", "macro", "match", "mod", "move", "mut", "override", "priv", "pub", "ref",
+        "return", "static", "struct", "trait", "true", "try", "type", "typeof", "unsafe",
+        "unsized", "use", "virtual", "where", "while", "yield",
+    ];
+    let name = snake(name);
+    if matches!(name.as_str(), "self" | "super" | "crate") {
+        format!("{name}_")
+    } else if KEYWORDS.contains(&name.as_str()) {
+        format!("r#{name}")
+    } else {
+        name
+    }
+}
+
+/// A JavaScript parameter name for a PEPL identifier.
+fn ts_param(name: &str) -> String {
+    const RESERVED: &[&str] = &[
+        "arguments",
+        "await",
+        "break",
+        "case",
+        "catch",
+        "class",
+        "const",
+        "continue",
+        "debugger",
+        "default",
+        "delete",
+        "do",
+        "else",
+        "enum",
+        "eval",
+        "export",
+        "extends",
+        "false",
+        "finally",
+        "for",
+        "function",
+        "if",
+        "implements",
+        "import",
+        "in",
+        "instanceof",
+        "interface",
+        "let",
+        "new",
+        "null",
+        "package",
+        "private",
+        "protected",
+        "public",
+        "return",
+        "static",
+        "super",
+        "switch",
+        "this",
+        "throw",
+        "true",
+        "try",
+        "typeof",
+        "var",
+        "void",
+        "while",
+        "with",
+        "yield",
+    ];
+    if RESERVED.contains(&name) {
+        format!("{name}_")
+    } else {
+        name.to_string()
+    }
+}
+
+// ══════════════════════════════════════════════════════════════════════════════
+// Static parts
+// ══════════════════════════════════════════════════════════════════════════════
+
+const TS_SURFACE: &str = r#"/**
+ * A rendered component.  Action props read `{ __action: name }`, lambda
+ * props `{ __lambda: id }`.
+ */
+export interface SurfaceNode {
+  component: Component;
+  props: { [prop: string]: unknown };
+  children: SurfaceNode[];
+}
+
+export type InputEvent = { [field: string]: unknown };
+
+"#;
+
+const TS_PERSISTENCE: &str = r#"  /** Canonical JSON of the state fields (`serialize_state`). */
+  serializeState(): string;
+  /** Load JSON from `serializeState`; `false` if the module rejected it. */
+  restoreState(json: string): boolean;
+"#;
+
+/// Value cell codec shared by every generated ES module; see
+/// `pepl_codegen::types` for the layout.
+const JS_RUNTIME: &str = r#"
+const VARIANT_OK = 0xffffff00;
+const VARIANT_ERR = 0xffffff01;
+const utf8 = new TextEncoder();
+const utf8Decoder = new TextDecoder();
+
+function text(exports, ptr, len) {
+  return utf8Decoder.decode(new Uint8Array(exports.memory.buffer, ptr, len));
+}
+
+function bytes(exports, data) {
+  const ptr = exports.alloc(data.length);
+  new Uint8Array(exports.memory.buffer, ptr, data.length).set(data);
+  return ptr;
+}
+
+function cell(exports, tag, w1, w2) {
+  const ptr = exports.alloc(12);
+  const mem = new DataView(exports.memory.buffer);
+  mem.setInt32(ptr, tag, true);
+  mem.setUint32(ptr + 4, w1 >>> 0, true);
+  mem.setUint32(ptr + 8, w2 >>> 0, true);
+  return ptr;
+}
+
+function list(exports, ptrs) {
+  const items = exports.alloc(ptrs.length * 4);
+  const mem = new DataView(exports.memory.buffer);
+  ptrs.forEach((ptr, i) => mem.setUint32(items + i * 4, ptr, true));
+  return cell(exports, 4, items, ptrs.length);
+}
+
+function record(exports, fields) {
+  const keys = fields.map(([key]) => utf8.encode(key));
+  const keyPtrs = keys.map((key) => bytes(exports, key));
+  const entries = exports.alloc(fields.length * 12);
+  const mem = new DataView(exports.memory.buffer);
+  fields.forEach(([, ptr], i) => {
+    mem.setUint32(entries + i * 12, keyPtrs[i], true);
+    mem.setUint32(entries + i * 12 + 4, keys[i].length, true);
+    mem.setUint32(entries + i * 12 + 8, ptr, true);
+  });
+  return cell(exports, 5, entries, fields.length);
+}
+
+function anySchema(value) {
+  switch (typeof value) {
+    case "number":
+      return "number";
+    case "string":
+      return "string";
+    case "boolean":
+      return "bool";
+  }
+  if (value === null || value === undefined) return "nil";
+  if (Array.isArray(value)) return ["list", "any"];
+  return ["record", Object.fromEntries(Object.keys(value).map((key) => [key, "any"]))];
+}
+
+function encode(exports, schema, value) {
+  if (schema === "any") schema = anySchema(value);
+  switch (schema) {
+    case "number": {
+      const ptr = cell(exports, 1, 0, 0);
+      new DataView(exports.memory.buffer).setFloat64(ptr + 4, value, true);
+      return ptr;
+    }
+    case "string": {
+      const data = utf8.encode(value);
+      return cell(exports, 3, bytes(exports, data), data.length);
+    }
+    case "bool":
+      return cell(exports, 2, value ? 1 : 0, 0);
+    case "nil":
+      return cell(exports, 0, 0, 0);
+  }
+  const [kind, inner, err] = schema;
+  switch (kind) {
+    case "list":
+      return list(exports, value.map((item) => encode(exports, inner, item)));
+    case "record":
+      return record(
+        exports,
+        Object.entries(inner)
+          .filter(([key]) => value[key] !== undefined)
+          .map(([key, field]) => [key, encode(exports, field, value[key])]),
+      );
+    case "result":
+      return "Ok" in value
+        ? cell(exports, 6, VARIANT_OK, encode(exports, inner, value.Ok))
+        : cell(exports, 6, VARIANT_ERR, encode(exports, err, value.Err));
+    case "variants": {
+      const name = typeof value === "string" ? value : Object.keys(value)[0];
+      const variant = TYPES[inner][name];
+      if (variant === undefined) throw new TypeError(`${name} is not a ${inner}`);
+      const [id, fields] = variant;
+      const values = typeof value === "string" ? [] : value[name];
+      const ptrs = fields.map((field, i) => encode(exports, field, values[i]));
+      return cell(exports, 6, id, list(exports, ptrs));
+    }
+  }
+  throw new TypeError(`unknown schema ${kind}`);
+}
+
+function decode(exports, ptr) {
+  const mem = new DataView(exports.memory.buffer);
+  const w1 = mem.getUint32(ptr + 4, true);
+  const w2 = mem.getUint32(ptr + 8, true);
+  const word = (at) => mem.getUint32(at, true);
+  switch (mem.getInt32(ptr, true)) {
+    case 1:
+      return mem.getFloat64(ptr + 4, true);
+    case 2:
+      return w1 !== 0;
+    case 3:
+      return text(exports, w1, w2);
+    case 4:
+      return Array.from({ length: w2 }, (_, i) => decode(exports, word(w1 + i * 4)));
+    case 5:
+      return Object.fromEntries(
+        Array.from({ length: w2 }, (_, i) => {
+          const at = w1 + i * 12;
+          return [text(exports, word(at), word(at + 4)), decode(exports, word(at + 8))];
+        }),
+      );
+    case 6: {
+      if (w1 === VARIANT_OK) return { Ok: decode(exports, w2) };
+      if (w1 === VARIANT_ERR) return { Err: decode(exports, w2) };
+      const fields = w2 === 0 ? [] : decode(exports, w2);
+      return fields.length === 0 ? VARIANTS[w1] : { [VARIANTS[w1]]: fields };
+    }
+    case 7:
+      return { __lambda: w1 };
+    case 9:
+      return { __action: ACTIONS[w1] };
+    default:
+      return null;
+  }
+}
+
+function surface(value) {
+  if (Array.isArray(value)) return value.flatMap(surface);
+  if (value === null) return [];
+  const { component, props, children } = value;
+  return [{ component, props, children: surface(children ?? []) }];
+}
+
+export async function instantiate(source, env = {}) {
+  let exports;
+  const imports = {
+    env: {
+      host_call: () => 0,
+      log: (ptr, len) => console.log(text(exports, ptr, len)),
+      trap: (ptr, len) => {
+        throw new Error(text(exports, ptr, len));
+      },
+      get_timestamp: () => BigInt(Date.now()),
+      ...env,
+    },
+  };
+  const result = await WebAssembly.instantiate(source, imports);
+  const instance = result instanceof WebAssembly.Instance ? result : result.instance;
+  exports = instance.exports;
+  exports.init();
+  return bind(instance);
+}
+
+"#;
+
+const JS_STATE: &str = r#"    state: () => decode(exports, exports.get_state()),
+    serializeState: () => {
+      const [ptr, len] = exports.serialize_state();
+      return text(exports, ptr, len);
+    },
+    restoreState: (json) => {
+      const data = utf8.encode(json);
+      return exports.restore_state(bytes(exports, data), data.length) === 1;
+    },
+"#;
+
+const RUST_VALUES: &str = r#"/// A value in the module's memory layout.  Sum type variants carry their
+/// fields in order; results are the variants [`VARIANT_OK`] and
+/// [`VARIANT_ERR`] with one field, written without the list around it.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Nil,
+    Number(f64),
+    Bool(bool),
+    String(String),
+    List(Vec<Value>),
+    Record(Vec<(String, Value)>),
+    Variant(u32, Vec<Value>),
+    Lambda(u32),
+    ActionRef(u32),
+}
+
+pub const VARIANT_OK: u32 = 0xFFFF_FF00;
+pub const VARIANT_ERR: u32 = 0xFFFF_FF01;
+
+/// Conversion between a bound Rust type and [`Value`].
+pub trait PeplValue: Sized {
+    fn into_value(self) -> Value;
+    fn from_value(value: Value) -> Option<Self>;
+}
+
+impl PeplValue for Value {
+    fn into_value(self) -> Value {
+        self
+    }
+
+    fn from_value(value: Value) -> Option<Self> {
+        Some(value)
+    }
+}
+
+impl PeplValue for f64 {
+    fn into_value(self) -> Value {
+        Value::Number(self)
+    }
+
+    fn from_value(value: Value) -> Option<Self> {
+        match value {
+            Value::Number(n) => Some(n),
+            _ => None,
+        }
+    }
+}
+
+impl PeplValue for bool {
+    fn into_value(self) -> Value {
+        Value::Bool(self)
+    }
+
+    fn from_value(value: Value) -> Option<Self> {
+        match value {
+            Value::Bool(b) => Some(b),
+            _ => None,
+        }
+    }
+}
+
+impl PeplValue for String {
+    fn into_value(self) -> Value {
+        Value::String(self)
+    }
+
+    fn from_value(value: Value) -> Option<Self> {
+        match value {
+            Value::String(s) => Some(s),
+            _ => None,
+        }
+    }
+}
+
+impl PeplValue for () {
+    fn into_value(self) -> Value {
+        Value::Nil
+    }
+
+    fn from_value(value: Value) -> Option<Self> {
+        matches!(value, Value::Nil).then_some(())
+    }
+}
+
+impl<T: PeplValue> PeplValue for Vec<T> {
+    fn into_value(self) -> Value {
+        Value::List(self.into_iter().map(T::into_value).collect())
+    }
+
+    fn from_value(value: Value) -> Option<Self> {
+        match value {
+            Value::List(items) => items.into_iter().map(T::from_value).collect(),
+            _ => None,
+        }
+    }
+}
+
+impl<T: PeplValue> PeplValue for Box<T> {
+    fn into_value(self) -> Value {
+        (*self).into_value()
+    }
+
+    fn from_value(value: Value) -> Option<Self> {
+        T::from_value(value).map(Box::new)
+    }
+}
+
+impl<T: PeplValue, E: PeplValue> PeplValue for Result<T, E> {
+    fn into_value(self) -> Value {
+        match self {
+            Ok(value) => Value::Variant(VARIANT_OK, vec![value.into_value()]),
+            Err(error) => Value::Variant(VARIANT_ERR, vec![error.into_value()]),
+        }
+    }
+
+    fn from_value(value: Value) -> Option<Self> {
+        match value {
+            Value::Variant(VARIANT_OK, mut fields) if fields.len() == 1 => {
+                T::from_value(fields.pop()?).map(Ok)
+            }
+            Value::Variant(VARIANT_ERR, mut fields) if fields.len() == 1 => {
+                E::from_value(fields.pop()?).map(Err)
+            }
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum Error<E> {
+    /// The runtime failed: a trap, a violated invariant or an engine error.
+    Runtime(E),
+    /// A value read back does not have the bound type.
+    Shape(&'static str),
+}
+
+/// Remove the entry `name` from a record.
+fn take(fields: &mut Vec<(String, Value)>, name: &str) -> Option<Value> {
+    let index = fields.iter().position(|(key, _)| key == name)?;
+    Some(fields.swap_remove(index).1)
+}
+"#;
+
+const RUST_OPTIONAL: &str = r#"
+/// Read an optional record field: absent and nil are `None`.
+fn optional<T: PeplValue>(value: Option<Value>) -> Option<Option<T>> {
+    match value {
+        None | Some(Value::Nil) => Some(None),
+        Some(value) => T::from_value(value).map(Some),
+    }
+}
+"#;
+
+const RUST_SURFACE: &str = r#"
+/// A rendered component; action props are [`Value::ActionRef`]s.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SurfaceNode {
+    pub component: String,
+    pub props: Vec<(String, Value)>,
+    pub children: Vec<SurfaceNode>,
+}
+
+/// Read a rendered value, flattening the lists `if` and `for` blocks nest.
+pub fn surface(value: Value) -> Option<Vec<SurfaceNode>> {
+    let mut nodes = Vec::new();
+    collect_nodes(value, &mut nodes)?;
+    Some(nodes)
+}
+
+fn collect_nodes(value: Value, out: &mut Vec<SurfaceNode>) -> Option<()> {
+    match value {
+        Value::List(items) => {
+            for item in items {
+                collect_nodes(item, out)?;
+            }
+        }
+        Value::Nil => {}
+        Value::Record(mut fields) => {
+            let Value::String(component) = take(&mut fields, "component")? else {
+                return None;
+            };
+            let props = match take(&mut fields, "props") {
+                Some(Value::Record(props)) => props,
+                _ => Vec::new(),
+            };
+            let children = match take(&mut fields, "children") {
+                Some(children) => surface(children)?,
+                None => Vec::new(),
+            };
+            out.push(SurfaceNode {
+                component,
+                props,
+                children,
+            });
+        }
+        _ => return None,
+    }
+    Some(())
+}
+
+"#;
@@ -18,6 +18,9 @@
 //! - E601: derived field modification
 //! - E604: undeclared credential in state initializer
 //! - E605: credential modification
+//! - E609: credential reaches an unapproved sink (see [`crate::taint`])
+//! - E610: derived fields that depend on each other
+//! - E608: gas budget exceeded (warning — constant gas bound above the budget)
 //!
 //! Error codes emitted by lexer/parser (not this checker):
 //! - E100: unexpected token (parser)
@@ -31,11 +34,17 @@
 
 use std::collections::{HashMap, HashSet};
 
+use pepl_codegen::{CodegenOptions, OptLevel, RecordLayout, RecordLayouts, Scalar, ScalarTypes};
 use pepl_types::ast::*;
-use pepl_types::{CompileErrors, ErrorCode, SourceFile, Span};
+use pepl_types::derived::DerivedGraph;
+use pepl_types::{
+    Applicability, CompileErrors, ErrorCode, Fix, PeplError, SourceFile, Span, TextEdit,
+};
 
 use crate::env::{ScopeKind, TypeEnv};
+use crate::gas_bound::{self, EntryGas, SizedExprs};
 use crate::stdlib::{self, StdlibRegistry};
+use crate::taint;
 use crate::ty::{FnSig, RecordField, SumVariant, Type};
 
 // ══════════════════════════════════════════════════════════════════════════════
@@ -54,18 +63,36 @@ pub struct TypeChecker<'a> {
     state_fields: HashMap<String, Type>,
     /// Derived field names → types (read-only).
     derived_fields: HashMap<String, Type>,
+    /// What each derived field reads.
+    derived_graph: DerivedGraph,
     /// Declared action names.
     action_names: HashSet<String>,
+    /// Declared view names (for `render(view)` in tests).
+    view_names: HashSet<String>,
+    /// Whether the space declares `update(dt)` (for `tick` in tests).
+    has_update: bool,
+    /// Whether the space declares `handleEvent(event)` (for `send_event` in tests).
+    has_handle_event: bool,
     /// Declared required capabilities.
     required_capabilities: HashSet<String>,
     /// Declared optional capabilities.
     optional_capabilities: HashSet<String>,
+    /// The `capabilities` block, and the span of the `state` block it
+    /// follows — where a fix declaring a capability inserts it.
+    capabilities_block: Option<CapabilitiesBlock>,
+    state_span: Span,
     /// Declared credential names → types.
     credentials: HashMap<String, Type>,
     /// Capability module mapping (e.g. "http" → "http").
     capability_modules: HashMap<&'static str, &'static str>,
     /// Name of the action currently being checked (for recursion detection).
     current_action_name: Option<String>,
+    /// Expressions proven to be `number` / `bool`, for unboxed codegen.
+    scalar_types: ScalarTypes,
+    /// Expressions proven to be records of a fixed shape, for slot access.
+    record_layouts: RecordLayouts,
+    /// Expressions holding a list or string, for gas bounds.
+    sized_exprs: SizedExprs,
 }
 
 impl<'a> TypeChecker<'a> {
@@ -79,12 +106,61 @@ impl<'a> TypeChecker<'a> {
             sum_types: HashMap::new(),
             state_fields: HashMap::new(),
             derived_fields: HashMap::new(),
+            derived_graph: DerivedGraph::default(),
             action_names: HashSet::new(),
+            view_names: HashSet::new(),
+            has_update: false,
+            has_handle_event: false,
             required_capabilities: HashSet::new(),
             optional_capabilities: HashSet::new(),
+            capabilities_block: None,
+            state_span: Span::point(1, 1),
             credentials: HashMap::new(),
             capability_modules: stdlib::capability_modules(),
             current_action_name: None,
+            scalar_types: ScalarTypes::new(),
+            record_layouts: RecordLayouts::new(),
+            sized_exprs: SizedExprs::new(),
+        }
+    }
+
+    /// Compute the worst-case gas of every entry point of a checked
+    /// `program`, warning about those whose bound is a constant above
+    /// `budget`.  Empty when checking found errors.
+    pub fn gas_bounds(&mut self, program: &Program, budget: u64) -> Vec<EntryGas> {
+        if self.errors.has_errors() {
+            return Vec::new();
+        }
+        let entries = gas_bound::analyze(program, &self.sized_exprs);
+        for entry in &entries {
+            if let Some(gas) = entry.bound.constant().filter(|&gas| gas > budget) {
+                self.warning_with_suggestion(
+                    ErrorCode::GAS_BUDGET_EXCEEDED,
+                    format!(
+                        "{} '{}' may use up to {gas} gas, over the budget of {budget}",
+                        entry.kind, entry.name
+                    ),
+                    entry.span,
+                    "Split the work across several actions or iterate over fewer items",
+                );
+            }
+        }
+        entries
+    }
+
+    /// The dependency graph of the derived fields (built by [`Self::check`]).
+    pub fn derived_graph(&self) -> &DerivedGraph {
+        &self.derived_graph
+    }
+
+    /// Consume the checker, returning codegen options carrying the types
+    /// it recorded.
+    pub fn into_codegen_options(self, opt_level: OptLevel) -> CodegenOptions {
+        CodegenOptions {
+            opt_level,
+            scalar_types: self.scalar_types,
+            record_layouts: self.record_layouts,
+            ..Default::default()
         }
     }
 
@@ -94,6 +170,25 @@ impl<'a> TypeChecker<'a> {
         for test_block in &program.tests {
             self.check_tests_block(test_block);
         }
+        self.check_credential_flows(program);
+    }
+
+    /// E609: a value derived from a credential is logged, stored, rendered
+    /// or sent anywhere but an http request.
+    fn check_credential_flows(&mut self, program: &Program) {
+        for leak in taint::analyze(program, &self.stdlib) {
+            self.error_with_suggestion(
+                ErrorCode::CREDENTIAL_LEAK,
+                format!(
+                    "credential '{}' reaches {}: {}",
+                    leak.credential,
+                    leak.sink,
+                    leak.path_string()
+                ),
+                leak.span,
+                "Credentials may only be sent in an http request URL or body — never log, store or display them",
+            );
+        }
     }
 
     // ══════════════════════════════════════════════════════════════════════
@@ -117,6 +212,8 @@ impl<'a> TypeChecker<'a> {
         }
 
         // 3. Register capabilities
+        self.capabilities_block = body.capabilities.clone();
+        self.state_span = body.state.span;
         if let Some(caps) = &body.capabilities {
             for cap in &caps.required {
                 self.required_capabilities.insert(cap.name.clone());
@@ -140,10 +237,17 @@ impl<'a> TypeChecker<'a> {
             self.check_state_initializer(&field.default, &field.name.name, field.span);
         }
 
-        // 6. Check derived fields (in order — each can reference prior derived)
+        // 6. Check derived fields — each can reference any other, as long as
+        // none depends on itself
         if let Some(derived) = &body.derived {
             for field in &derived.fields {
                 let declared_ty = self.resolve_type_annotation(&field.type_ann);
+                self.derived_fields
+                    .insert(field.name.name.clone(), declared_ty.clone());
+                self.env.define(&field.name.name, declared_ty);
+            }
+            for field in &derived.fields {
+                let declared_ty = self.derived_fields[&field.name.name].clone();
                 self.env.push_scope(ScopeKind::Derived);
                 let inferred_ty = self.check_expr(&field.value);
                 self.env.pop_scope();
@@ -158,10 +262,26 @@ impl<'a> TypeChecker<'a> {
                         field.value.span,
                     );
                 }
+            }
 
-                self.derived_fields
-                    .insert(field.name.name.clone(), declared_ty.clone());
-                self.env.define(&field.name.name, declared_ty);
+            self.derived_graph = DerivedGraph::new(body);
+            for cycle in self.derived_graph.cycles() {
+                let span = derived
+                    .fields
+                    .iter()
+                    .find(|f| f.name.name == cycle[0])
+                    .map_or(derived.span, |f| f.span);
+                let message = if cycle.len() == 2 {
+                    format!("derived field '{}' depends on itself", cycle[0])
+                } else {
+                    format!("derived fields depend on each other: {}", cycle.join(" → "))
+                };
+                self.error_with_suggestion(
+                    ErrorCode::DERIVED_CYCLE,
+                    message,
+                    span,
+                    "Compute one of these fields from state fields instead",
+                );
             }
         }
 
@@ -193,16 +313,19 @@ impl<'a> TypeChecker<'a> {
 
         // 9. Check views
         for view in &body.views {
+            self.view_names.insert(view.name.name.clone());
             self.check_view(view);
         }
 
         // 10. Check update
         if let Some(update) = &body.update {
+            self.has_update = true;
             self.check_update(update);
         }
 
         // 11. Check handleEvent
         if let Some(handle_event) = &body.handle_event {
+            self.has_handle_event = true;
             self.check_handle_event(handle_event);
         }
     }
@@ -410,11 +533,17 @@ impl<'a> TypeChecker<'a> {
             UIElement::Component(comp) => {
                 // Validate component name against the 10 Phase 0 components
                 if !is_valid_component(&comp.name.name) {
-                    self.error(
+                    let mut error = self.diagnostic(
                         ErrorCode::UNKNOWN_COMPONENT,
                         format!("unknown component '{}'", comp.name.name),
                         comp.name.span,
                     );
+                    if let Some(name) =
+                        closest_name(&comp.name.name, VALID_COMPONENTS.iter().copied())
+                    {
+                        error = error.with_fix(rename_fix(comp.name.span, name, name));
+                    }
+                    self.errors.push_error(error);
                 }
 
                 // Check prop expressions
@@ -456,12 +585,18 @@ impl<'a> TypeChecker<'a> {
                 ui_if.condition.span,
             );
         }
+        self.env.push_scope(ScopeKind::Block);
+        self.narrow_nil(&ui_if.condition, true);
         self.check_ui_block(&ui_if.then_block);
+        self.env.pop_scope();
         if let Some(else_block) = &ui_if.else_block {
+            self.env.push_scope(ScopeKind::Block);
+            self.narrow_nil(&ui_if.condition, false);
             match else_block {
                 UIElse::ElseIf(elif) => self.check_ui_if(elif),
                 UIElse::Block(block) => self.check_ui_block(block),
             }
+            self.env.pop_scope();
         }
     }
 
@@ -540,10 +675,32 @@ impl<'a> TypeChecker<'a> {
 
     fn check_block(&mut self, block: &Block) {
         self.env.push_scope(ScopeKind::Block);
-        for stmt in &block.stmts {
+        self.check_stmts(&block.stmts);
+        self.env.pop_scope();
+    }
+
+    /// Check statements in the current scope, carrying nil narrowing past
+    /// early exits (`if x == nil { return }`) and asserts.
+    fn check_stmts(&mut self, stmts: &[Stmt]) {
+        for stmt in stmts {
             self.check_stmt(stmt);
+            match stmt {
+                Stmt::If(if_expr) => {
+                    let then_exits = block_exits(&if_expr.then_block);
+                    let else_exits = match &if_expr.else_branch {
+                        Some(ElseBranch::Block(block)) => block_exits(block),
+                        _ => false,
+                    };
+                    if then_exits && !else_exits {
+                        self.narrow_nil(&if_expr.condition, false);
+                    } else if else_exits && !then_exits {
+                        self.narrow_nil(&if_expr.condition, true);
+                    }
+                }
+                Stmt::Assert(assert_stmt) => self.narrow_nil(&assert_stmt.condition, true),
+                _ => {}
+            }
         }
-        self.env.pop_scope();
     }
 
     fn check_stmt(&mut self, stmt: &Stmt) {
@@ -648,7 +805,9 @@ impl<'a> TypeChecker<'a> {
             .get(target_name)
             .cloned()
             .unwrap_or(Type::Unknown);
-        for field_ident in set.target.iter().skip(1) {
+        for (parent, field_ident) in set.target.iter().zip(set.target.iter().skip(1)) {
+            self.record_layouts
+                .record(parent.span, record_layout(&target_ty));
             match &target_ty {
                 Type::Record(fields) => {
                     if let Some(rf) = fields.iter().find(|f| f.name == field_ident.name) {
@@ -687,10 +846,25 @@ impl<'a> TypeChecker<'a> {
                 set.value.span,
             );
         }
+
+        // The old value's narrowing no longer holds; a non-nil value
+        // narrows the target afresh.
+        let path = set_path(set);
+        self.env.widen(&path);
+        if !value_ty.is_nullable() {
+            if let Some(Type::Nullable(inner)) = self.path_type(&path) {
+                self.env.narrow(&path, *inner);
+            }
+        }
     }
 
     fn check_let_binding(&mut self, binding: &LetBinding) {
-        let value_ty = self.check_expr(&binding.value);
+        let value_ty = match &binding.value.kind {
+            ExprKind::Call { name, args } if name.name == "render" && self.env.in_test() => {
+                self.check_render_call(args, binding.value.span)
+            }
+            _ => self.check_expr(&binding.value),
+        };
 
         if let Some(type_ann) = &binding.type_ann {
             let declared_ty = self.resolve_type_annotation(type_ann);
@@ -741,6 +915,19 @@ impl<'a> TypeChecker<'a> {
     // ══════════════════════════════════════════════════════════════════════
 
     fn check_expr(&mut self, expr: &Expr) -> Type {
+        let ty = self.check_expr_kind(expr);
+        let scalar = match ty {
+            Type::Number => Some(Scalar::Number),
+            Type::Bool => Some(Scalar::Bool),
+            _ => None,
+        };
+        self.scalar_types.record(expr.span, scalar);
+        self.record_layouts.record(expr.span, record_layout(&ty));
+        self.sized_exprs.record(expr.span, is_sized(&ty));
+        ty
+    }
+
+    fn check_expr_kind(&mut self, expr: &Expr) -> Type {
         match &expr.kind {
             // ── Literals ──
             ExprKind::NumberLit(_) => Type::Number,
@@ -863,7 +1050,11 @@ impl<'a> TypeChecker<'a> {
             // ── Field Access ──
             ExprKind::FieldAccess { object, field } => {
                 let obj_ty = self.check_expr(object);
-                self.resolve_field_access(&obj_ty, &field.name, field.span)
+                let ty = self.resolve_field_access(&obj_ty, &field.name, field.span);
+                match narrow_path(expr).and_then(|path| self.env.lookup(&path)) {
+                    Some(narrowed) => narrowed.clone(),
+                    None => ty,
+                }
             }
 
             ExprKind::MethodCall {
@@ -901,6 +1092,20 @@ impl<'a> TypeChecker<'a> {
                 let right_ty = self.check_expr(right);
                 // Left should be nullable; result is the non-nil type
                 match &left_ty {
+                    // A nullable fallback (`a ?? b ?? 0`) keeps the result nullable
+                    Type::Nullable(inner) if matches!(right_ty, Type::Nil | Type::Nullable(_)) => {
+                        if !right_ty.is_assignable_to(&left_ty) {
+                            self.error(
+                                ErrorCode::TYPE_MISMATCH,
+                                format!(
+                                    "nil-coalescing fallback type {} doesn't match {}",
+                                    right_ty, inner
+                                ),
+                                right.span,
+                            );
+                        }
+                        left_ty.clone()
+                    }
                     Type::Nullable(inner) => {
                         if !right_ty.is_assignable_to(inner) {
                             self.error(
@@ -929,7 +1134,7 @@ impl<'a> TypeChecker<'a> {
             ExprKind::Match(match_expr) => self.check_match_expr(match_expr),
 
             // ── Lambda ──
-            ExprKind::Lambda(lambda) => self.check_lambda(lambda),
+            ExprKind::Lambda(lambda) => self.check_lambda(lambda, None),
 
             // ── Grouping ──
             ExprKind::Paren(inner) => self.check_expr(inner),
@@ -968,6 +1173,13 @@ impl<'a> TypeChecker<'a> {
             return Type::Void;
         }
 
+        // Test-only built-ins (render assertions, snapshots)
+        if self.env.in_test() {
+            if let Some(ty) = self.check_test_builtin(name, args, span) {
+                return ty;
+            }
+        }
+
         // Check if it resolves to a function in scope
         if let Some(ty) = self.env.lookup(&name.name).cloned() {
             match &ty {
@@ -1010,6 +1222,174 @@ impl<'a> TypeChecker<'a> {
         Type::Unknown
     }
 
+    /// `let s = render(view)` inside a test — `view` names a declared view;
+    /// `render()` renders `main`.
+    fn check_render_call(&mut self, args: &[Expr], span: Span) -> Type {
+        match args {
+            [] => {
+                if !self.view_names.contains("main") {
+                    self.error(
+                        ErrorCode::TYPE_MISMATCH,
+                        "render() requires a view named 'main'".to_string(),
+                        span,
+                    );
+                }
+            }
+            [arg] => match &arg.kind {
+                ExprKind::Identifier(view) if self.view_names.contains(view) => {}
+                ExprKind::Identifier(view) => {
+                    self.error(
+                        ErrorCode::TYPE_MISMATCH,
+                        format!("render: unknown view '{}'", view),
+                        arg.span,
+                    );
+                }
+                _ => {
+                    self.error(
+                        ErrorCode::TYPE_MISMATCH,
+                        "render expects a view name".to_string(),
+                        arg.span,
+                    );
+                }
+            },
+            _ => {
+                self.error(
+                    ErrorCode::WRONG_ARG_COUNT,
+                    format!("render expects 0 or 1 arguments, got {}", args.len()),
+                    span,
+                );
+            }
+        }
+        Type::Surface
+    }
+
+    /// Built-ins available only inside `tests { }` bodies. Returns `None`
+    /// when `name` is not a test built-in.
+    fn check_test_builtin(&mut self, name: &Ident, args: &[Expr], span: Span) -> Option<Type> {
+        let (params, ret): (&[Type], Type) = match name.name.as_str() {
+            "render" => {
+                self.error(
+                    ErrorCode::TYPE_MISMATCH,
+                    "render() must be bound with let, e.g. 'let s = render(main)'".to_string(),
+                    span,
+                );
+                return Some(Type::Unknown);
+            }
+            "count_components" => (&[Type::Surface, Type::String], Type::Number),
+            "assert_snapshot" => (&[Type::Surface, Type::String], Type::Void),
+            "assert_component" => {
+                if !(2..=3).contains(&args.len()) {
+                    self.error(
+                        ErrorCode::WRONG_ARG_COUNT,
+                        format!(
+                            "assert_component expects 2 or 3 arguments, got {}",
+                            args.len()
+                        ),
+                        span,
+                    );
+                }
+                (&[Type::Surface, Type::String, Type::Any], Type::Void)
+            }
+            "tick" | "tick_n" => {
+                if !self.has_update {
+                    self.error(
+                        ErrorCode::TYPE_MISMATCH,
+                        format!("{}() requires the space to declare update(dt)", name.name),
+                        span,
+                    );
+                }
+                if name.name == "tick" {
+                    (&[Type::Number], Type::Void)
+                } else {
+                    (&[Type::Number, Type::Number], Type::Void)
+                }
+            }
+            "send_event" => {
+                if !self.has_handle_event {
+                    self.error(
+                        ErrorCode::TYPE_MISMATCH,
+                        "send_event() requires the space to declare handleEvent(event)".to_string(),
+                        span,
+                    );
+                }
+                (&[Type::InputEvent], Type::Void)
+            }
+            _ => return None,
+        };
+
+        if name.name != "assert_component" && args.len() != params.len() {
+            self.error(
+                ErrorCode::WRONG_ARG_COUNT,
+                format!(
+                    "{} expects {} arguments, got {}",
+                    name.name,
+                    params.len(),
+                    args.len()
+                ),
+                span,
+            );
+        }
+
+        for (i, arg) in args.iter().enumerate() {
+            let arg_ty = self.check_expr(arg);
+            let Some(expected) = params.get(i) else {
+                continue;
+            };
+            // Props filters and events are written as record literals
+            let record_arg = matches!((name.name.as_str(), i), ("assert_component", 2))
+                || matches!(expected, Type::InputEvent);
+            if record_arg {
+                if !matches!(
+                    arg_ty,
+                    Type::Record(_) | Type::InputEvent | Type::Any | Type::Unknown
+                ) {
+                    self.error(
+                        ErrorCode::TYPE_MISMATCH,
+                        format!(
+                            "argument {} of '{}' must be a record, got {}",
+                            i + 1,
+                            name.name,
+                            arg_ty
+                        ),
+                        arg.span,
+                    );
+                }
+            } else if !arg_ty.is_assignable_to(expected) {
+                self.error(
+                    ErrorCode::TYPE_MISMATCH,
+                    format!(
+                        "argument {} of '{}': expected {}, got {}",
+                        i + 1,
+                        name.name,
+                        expected,
+                        arg_ty
+                    ),
+                    arg.span,
+                );
+            }
+            // Component names given as literals must be known components
+            if i == 1 && matches!(name.name.as_str(), "assert_component" | "count_components") {
+                if let ExprKind::StringLit(component) = &arg.kind {
+                    if !is_valid_component(component) {
+                        let mut error = self.diagnostic(
+                            ErrorCode::UNKNOWN_COMPONENT,
+                            format!("unknown component '{}'", component),
+                            arg.span,
+                        );
+                        if let Some(name) =
+                            closest_name(component, VALID_COMPONENTS.iter().copied())
+                        {
+                            let literal = format!("\"{name}\"");
+                            error = error.with_fix(rename_fix(arg.span, name, &literal));
+                        }
+                        self.errors.push_error(error);
+                    }
+                }
+            }
+        }
+        Some(ret)
+    }
+
     fn check_qualified_call(
         &mut self,
         module: &Ident,
@@ -1023,15 +1403,20 @@ impl<'a> TypeChecker<'a> {
             if !self.required_capabilities.contains(&cap)
                 && !self.optional_capabilities.contains(&cap)
             {
-                self.error_with_suggestion(
-                    ErrorCode::UNDECLARED_CAPABILITY,
-                    format!(
-                        "module '{}' requires capability '{}' but it is not declared",
-                        module.name, cap
-                    ),
-                    module.span,
-                    "Add the capability to `capabilities { required: [...] }` or `optional: [...]`",
-                );
+                let error = self
+                    .diagnostic(
+                        ErrorCode::UNDECLARED_CAPABILITY,
+                        format!(
+                            "module '{}' requires capability '{}' but it is not declared",
+                            module.name, cap
+                        ),
+                        module.span,
+                    )
+                    .with_suggestion(
+                        "Add the capability to `capabilities { required: [...] }` or `optional: [...]`",
+                    )
+                    .with_fix(self.declare_capability_fix(&cap));
+                self.errors.push_error(error);
             } else if self.optional_capabilities.contains(&cap)
                 && !self.required_capabilities.contains(&cap)
             {
@@ -1085,37 +1470,42 @@ impl<'a> TypeChecker<'a> {
             sig.clone()
         } else {
             // Could be a constant (already checked above), otherwise unknown
-            self.error(
+            let mut error = self.diagnostic(
                 ErrorCode::TYPE_MISMATCH,
                 format!("unknown function '{}.{}'", module.name, function.name),
                 function.span,
             );
+            if let Some(fix) = self.function_name_fix(&module.name, function) {
+                error = error.with_fix(fix);
+            }
+            self.errors.push_error(error);
             for arg in args {
                 self.check_expr(arg);
             }
             return Type::Unknown;
         };
 
-        self.check_call_against_sig(
-            &format!("{}.{}", module.name, function.name),
-            &sig,
-            args,
-            span,
-        )
+        self.check_call_against_sig(&module.name, &function.name, &sig, args, span)
     }
 
     fn check_call_against_sig(
         &mut self,
-        name: &str,
+        module: &str,
+        function: &str,
         sig: &FnSig,
         args: &[Expr],
         span: Span,
     ) -> Type {
-        self.validate_arg_count(name, args.len(), sig.params.len(), sig.variadic, span);
+        let name = format!("{}.{}", module, function);
+        self.validate_arg_count(&name, args.len(), sig.params.len(), sig.variadic, span);
 
-        for (i, arg) in args.iter().enumerate() {
-            let arg_ty = self.check_expr(arg);
+        let arg_types = self.check_args(module, function, &[], args);
+        for (i, (arg, arg_ty)) in args.iter().zip(&arg_types).enumerate() {
             if i < sig.params.len() {
+                if lambda_arg(arg).is_some() && matches!(sig.params[i].1, Type::Function(..)) {
+                    // Already checked against the instantiated callback type
+                    continue;
+                }
                 let expected = &sig.params[i].1;
                 if !arg_ty.is_assignable_to(expected) {
                     self.error(
@@ -1133,7 +1523,42 @@ impl<'a> TypeChecker<'a> {
             }
         }
 
-        sig.ret.clone()
+        self.stdlib
+            .instantiated_return(module, function, &arg_types)
+            .unwrap_or_else(|| sig.ret.clone())
+    }
+
+    /// Check call arguments, leaving lambdas until last so each can be
+    /// checked against the callback type the stdlib signature expects,
+    /// instantiated with the types of the other arguments.  `leading`
+    /// holds already-checked arguments that precede `args` (the receiver
+    /// of a method call); the result covers `args` only.
+    fn check_args(
+        &mut self,
+        module: &str,
+        function: &str,
+        leading: &[Type],
+        args: &[Expr],
+    ) -> Vec<Type> {
+        let mut types = leading.to_vec();
+        for arg in args {
+            let ty = if lambda_arg(arg).is_some() {
+                Type::Unknown
+            } else {
+                self.check_expr(arg)
+            };
+            types.push(ty);
+        }
+        for (i, arg) in args.iter().enumerate() {
+            if let Some(lambda) = lambda_arg(arg) {
+                let index = leading.len() + i;
+                let expected = self
+                    .stdlib
+                    .expected_callback(module, function, index, &types);
+                types[index] = self.check_lambda(lambda, expected.as_ref());
+            }
+        }
+        types.split_off(leading.len())
     }
 
     fn validate_arg_count(
@@ -1205,13 +1630,15 @@ impl<'a> TypeChecker<'a> {
         // Method calls on lists: items.filter(fn(x) { ... })
         // These are syntactic sugar for stdlib calls: list.filter(items, fn(x) { ... })
         if let Type::List(_) = obj_ty {
-            let mut full_args = Vec::with_capacity(args.len());
-            // Type-check arguments
-            for arg in args {
-                full_args.push(self.check_expr(arg));
-            }
             // Delegate to list module lookup
             if let Some(sig) = self.stdlib.get("list", &method.name).cloned() {
+                let mut arg_types = vec![obj_ty.clone()];
+                arg_types.extend(self.check_args(
+                    "list",
+                    &method.name,
+                    std::slice::from_ref(obj_ty),
+                    args,
+                ));
                 // Validate arg count (method call adds the object as first arg)
                 self.validate_arg_count(
                     &format!("list.{}", method.name),
@@ -1220,7 +1647,10 @@ impl<'a> TypeChecker<'a> {
                     sig.variadic,
                     span,
                 );
-                return sig.ret.clone();
+                return self
+                    .stdlib
+                    .instantiated_return("list", &method.name, &arg_types)
+                    .unwrap_or_else(|| sig.ret.clone());
             }
         }
 
@@ -1249,11 +1679,20 @@ impl<'a> TypeChecker<'a> {
         match obj_ty {
             Type::Any | Type::Unknown => Type::Any,
             _ => {
-                self.error(
+                let mut error = self.diagnostic(
                     ErrorCode::TYPE_MISMATCH,
                     format!("type {} has no method '{}'", obj_ty, method.name),
                     span,
                 );
+                let module = match obj_ty {
+                    Type::List(_) => Some("list"),
+                    Type::String => Some("string"),
+                    _ => None,
+                };
+                if let Some(fix) = module.and_then(|m| self.function_name_fix(m, method)) {
+                    error = error.with_fix(fix);
+                }
+                self.errors.push_error(error);
                 Type::Unknown
             }
         }
@@ -1263,7 +1702,17 @@ impl<'a> TypeChecker<'a> {
 
     fn check_binary(&mut self, left: &Expr, op: BinOp, right: &Expr, _span: Span) -> Type {
         let left_ty = self.check_expr(left);
-        let right_ty = self.check_expr(right);
+        let right_ty = if matches!(op, BinOp::And | BinOp::Or) {
+            // The right operand only runs when the left is true (`and`) or
+            // false (`or`).
+            self.env.push_scope(ScopeKind::Block);
+            self.narrow_nil(left, op == BinOp::And);
+            let ty = self.check_expr(right);
+            self.env.pop_scope();
+            ty
+        } else {
+            self.check_expr(right)
+        };
 
         match op {
             // Arithmetic: number × number → number
@@ -1395,11 +1844,13 @@ impl<'a> TypeChecker<'a> {
             );
         }
 
-        // Nil narrowing: if x != nil, narrow x in then-block
-        self.apply_nil_narrowing(&if_expr.condition, &if_expr.then_block);
-
+        self.env.push_scope(ScopeKind::Block);
+        self.narrow_nil(&if_expr.condition, true);
         self.check_block(&if_expr.then_block);
+        self.env.pop_scope();
         if let Some(else_branch) = &if_expr.else_branch {
+            self.env.push_scope(ScopeKind::Block);
+            self.narrow_nil(&if_expr.condition, false);
             match else_branch {
                 ElseBranch::ElseIf(elif) => {
                     self.check_if_expr(elif);
@@ -1408,32 +1859,54 @@ impl<'a> TypeChecker<'a> {
                     self.check_block(block);
                 }
             }
+            self.env.pop_scope();
         }
         // If expressions used as statements return Void
         Type::Void
     }
 
-    fn apply_nil_narrowing(&mut self, condition: &Expr, _then_block: &Block) {
-        // Pattern: x != nil  →  narrow x from Nullable(T) to T in then block
-        if let ExprKind::Binary {
-            left,
-            op: BinOp::NotEq,
-            right,
-        } = &condition.kind
-        {
-            if matches!(right.kind, ExprKind::NilLit) {
-                if let ExprKind::Identifier(name) = &left.kind {
-                    if let Some(Type::Nullable(inner)) = self.env.lookup(name).cloned() {
-                        self.env.narrow(name, *inner);
-                    }
-                }
+    // ── Nil narrowing ─────────────────────────────────────────────────────
+
+    /// Narrow, in the current scope, every path `condition` proves non-nil
+    /// when it evaluates to `outcome`.
+    fn narrow_nil(&mut self, condition: &Expr, outcome: bool) {
+        for path in non_nil_paths(condition, outcome) {
+            if let Some(Type::Nullable(inner)) = self.path_type(&path) {
+                self.env.narrow(&path, *inner);
+            }
+        }
+    }
+
+    /// The current type of an identifier or field path, without reporting
+    /// errors.
+    fn path_type(&self, path: &str) -> Option<Type> {
+        if let Some(ty) = self.env.lookup(path) {
+            return Some(ty.clone());
+        }
+        let (parent, field) = path.rsplit_once('.')?;
+        match self.path_type(parent)? {
+            Type::Record(fields) => {
+                let rf = fields.into_iter().find(|f| f.name == field)?;
+                Some(if rf.optional {
+                    Type::Nullable(Box::new(rf.ty))
+                } else {
+                    rf.ty
+                })
             }
+            _ => None,
         }
     }
 
     fn check_for_expr(&mut self, for_expr: &ForExpr) -> Type {
         let iter_ty = self.check_expr(&for_expr.iterable);
 
+        // A later iteration may see values assigned by an earlier one.
+        let mut assigned = Vec::new();
+        assigned_paths(&for_expr.body.stmts, &mut assigned);
+        for path in &assigned {
+            self.env.widen(path);
+        }
+
         self.env.push_scope(ScopeKind::Block);
 
         match &iter_ty {
@@ -1467,7 +1940,12 @@ impl<'a> TypeChecker<'a> {
     }
 
     fn check_match_expr(&mut self, match_expr: &MatchExpr) -> Type {
-        let subject_ty = self.check_expr(&match_expr.subject);
+        // Variant arms of a nullable subject see it non-nil; `nil` is left to `_`.
+        let (subject_ty, nullable) = match self.check_expr(&match_expr.subject) {
+            Type::Nullable(inner) => (*inner, true),
+            ty => (ty, false),
+        };
+        let subject_path = narrow_path(&match_expr.subject);
 
         // Collect matched variant names for exhaustiveness check
         let mut matched_variants: HashSet<String> = HashSet::new();
@@ -1479,6 +1957,9 @@ impl<'a> TypeChecker<'a> {
             match &arm.pattern {
                 Pattern::Variant { name, bindings } => {
                     matched_variants.insert(name.name.clone());
+                    if let Some(path) = subject_path.as_deref().filter(|_| nullable) {
+                        self.env.narrow(path, subject_ty.clone());
+                    }
 
                     // Resolve variant parameter types
                     if let Type::SumType { variants, .. } = &subject_ty {
@@ -1573,9 +2054,7 @@ impl<'a> TypeChecker<'a> {
                 }
                 MatchArmBody::Block(block) => {
                     // Don't push another scope — we already have one
-                    for stmt in &block.stmts {
-                        self.check_stmt(stmt);
-                    }
+                    self.check_stmts(&block.stmts);
                 }
             }
 
@@ -1585,40 +2064,46 @@ impl<'a> TypeChecker<'a> {
         // Exhaustiveness check
         if !has_wildcard {
             let all_variants = match &subject_ty {
-                Type::SumType { variants, .. } => Some(
-                    variants
-                        .iter()
-                        .map(|v| v.name.clone())
-                        .collect::<HashSet<_>>(),
-                ),
-                Type::Named(name) => self.sum_types.get(name).map(|variants| {
-                    variants
-                        .iter()
-                        .map(|v| v.name.clone())
-                        .collect::<HashSet<_>>()
-                }),
-                Type::Result(_, _) => {
-                    let mut s = HashSet::new();
-                    s.insert("Ok".to_string());
-                    s.insert("Err".to_string());
-                    Some(s)
-                }
+                Type::SumType { variants, .. } => Some(variants.clone()),
+                Type::Named(name) => self.sum_types.get(name).cloned(),
+                Type::Result(ok, err) => Some(vec![
+                    SumVariant {
+                        name: "Ok".to_string(),
+                        params: vec![("value".to_string(), (**ok).clone())],
+                    },
+                    SumVariant {
+                        name: "Err".to_string(),
+                        params: vec![("error".to_string(), (**err).clone())],
+                    },
+                ]),
                 _ => None,
             };
 
             if let Some(all) = all_variants {
-                let missing: Vec<_> = all.difference(&matched_variants).cloned().collect();
-                if !missing.is_empty() {
-                    self.error_with_suggestion(
-                        ErrorCode::NON_EXHAUSTIVE_MATCH,
-                        format!(
-                            "non-exhaustive match: missing variant{} {}",
-                            if missing.len() == 1 { "" } else { "s" },
-                            missing.join(", ")
-                        ),
-                        match_expr.span,
-                        "Add the missing variant arms or use a wildcard `_ => { ... }` arm",
-                    );
+                let missing: Vec<_> = all
+                    .iter()
+                    .filter(|v| !matched_variants.contains(&v.name))
+                    .collect();
+                let mut names: Vec<_> = missing.iter().map(|v| v.name.as_str()).collect();
+                if nullable {
+                    names.push("nil");
+                }
+                if !names.is_empty() {
+                    let error = self
+                        .diagnostic(
+                            ErrorCode::NON_EXHAUSTIVE_MATCH,
+                            format!(
+                                "non-exhaustive match: missing variant{} {}",
+                                if names.len() == 1 { "" } else { "s" },
+                                names.join(", ")
+                            ),
+                            match_expr.span,
+                        )
+                        .with_suggestion(
+                            "Add the missing variant arms or use a wildcard `_ => { ... }` arm",
+                        )
+                        .with_fix(self.missing_arms_fix(match_expr, &missing, nullable));
+                    self.errors.push_error(error);
                 }
             }
         }
@@ -1628,12 +2113,64 @@ impl<'a> TypeChecker<'a> {
 
     // ── Lambda ────────────────────────────────────────────────────────────
 
-    fn check_lambda(&mut self, lambda: &LambdaExpr) -> Type {
+    /// Check a lambda, optionally against the function type its context
+    /// expects.  Unannotated parameters take their types from `expected`,
+    /// and the body's result is checked against the expected return type.
+    fn check_lambda(&mut self, lambda: &LambdaExpr, expected: Option<&Type>) -> Type {
+        let (expected_params, expected_ret) = match expected {
+            Some(Type::Function(params, ret)) => (Some(params.as_slice()), Some(&**ret)),
+            _ => (None, None),
+        };
+        if let Some(params) = expected_params {
+            if params.len() != lambda.params.len() {
+                self.error(
+                    ErrorCode::WRONG_ARG_COUNT,
+                    format!(
+                        "lambda should take {} parameter{}, got {}",
+                        params.len(),
+                        if params.len() == 1 { "" } else { "s" },
+                        lambda.params.len()
+                    ),
+                    lambda.span,
+                );
+            }
+        }
+
         self.env.push_scope(ScopeKind::Lambda);
 
         let mut param_types = Vec::new();
-        for param in &lambda.params {
-            let ty = self.resolve_type_annotation(&param.type_ann);
+        for (i, param) in lambda.params.iter().enumerate() {
+            let inferred = expected_params.and_then(|params| params.get(i));
+            let ty = match (&param.type_ann, inferred) {
+                (Some(ann), inferred) => {
+                    let ty = self.resolve_type_annotation(ann);
+                    if let Some(inferred) = inferred {
+                        if !inferred.is_assignable_to(&ty) {
+                            self.error(
+                                ErrorCode::TYPE_MISMATCH,
+                                format!(
+                                    "parameter '{}' is annotated {}, but receives {}",
+                                    param.name.name, ty, inferred
+                                ),
+                                ann.span,
+                            );
+                        }
+                    }
+                    ty
+                }
+                (None, Some(inferred)) => inferred.clone(),
+                (None, None) => {
+                    if expected_params.is_none() {
+                        self.error_with_suggestion(
+                            ErrorCode::UNKNOWN_TYPE,
+                            format!("cannot infer the type of parameter '{}'", param.name.name),
+                            param.span,
+                            &format!("Annotate it, e.g. `{}: number`", param.name.name),
+                        );
+                    }
+                    Type::Unknown
+                }
+            };
             param_types.push(ty.clone());
             if !self.env.define(&param.name.name, ty) {
                 self.error(
@@ -1647,20 +2184,33 @@ impl<'a> TypeChecker<'a> {
         // Check body and capture the type of the last expression (return type).
         // Must be done BEFORE popping the scope so lambda params remain visible.
         let mut last_type = Type::Void;
+        let mut last_span = lambda.span;
         for stmt in &lambda.body.stmts {
             match stmt {
                 Stmt::Expr(expr_stmt) => {
                     last_type = self.check_expr(&expr_stmt.expr);
+                    last_span = expr_stmt.expr.span;
                 }
                 other => {
                     self.check_stmt(other);
                     last_type = Type::Void;
+                    last_span = lambda.span;
                 }
             }
         }
 
         self.env.pop_scope();
 
+        if let Some(ret) = expected_ret {
+            if !last_type.is_assignable_to(ret) {
+                self.error(
+                    ErrorCode::TYPE_MISMATCH,
+                    format!("lambda should return {}, got {}", ret, last_type),
+                    last_span,
+                );
+            }
+        }
+
         Type::Function(param_types, Box::new(last_type))
     }
 
@@ -1705,15 +2255,14 @@ impl<'a> TypeChecker<'a> {
     // Error Reporting
     // ══════════════════════════════════════════════════════════════════════
 
-    fn error(&mut self, code: ErrorCode, message: String, span: Span) {
+    fn diagnostic(&self, code: ErrorCode, message: String, span: Span) -> PeplError {
         let source_line = self.source.line(span.start_line).unwrap_or("").to_string();
-        self.errors.push_error(pepl_types::PeplError::new(
-            &self.source.name,
-            code,
-            message,
-            span,
-            source_line,
-        ));
+        PeplError::new(&self.source.name, code, message, span, source_line)
+    }
+
+    fn error(&mut self, code: ErrorCode, message: String, span: Span) {
+        let error = self.diagnostic(code, message, span);
+        self.errors.push_error(error);
     }
 
     fn error_with_suggestion(
@@ -1744,12 +2293,330 @@ impl<'a> TypeChecker<'a> {
         warning.severity = pepl_types::Severity::Warning;
         self.errors.push_warning(warning);
     }
+
+    // ══════════════════════════════════════════════════════════════════════
+    // Fixes
+    // ══════════════════════════════════════════════════════════════════════
+
+    /// Indentation of a source line.
+    fn indent_of(&self, line: u32) -> &str {
+        let text = self.source.line(line).unwrap_or("");
+        &text[..text.len() - text.trim_start().len()]
+    }
+
+    /// First occurrence of `needle` within `span`, as a 1-based position.
+    fn find_in_span(&self, span: Span, needle: &str) -> Option<(u32, u32)> {
+        (span.start_line..=span.end_line).find_map(|line| {
+            let text = self.source.line(line)?;
+            let from = if line == span.start_line {
+                (span.start_col as usize).saturating_sub(1).min(text.len())
+            } else {
+                0
+            };
+            let at = from + text[from..].find(needle)?;
+            Some((line, at as u32 + 1))
+        })
+    }
+
+    /// Fix for an unknown stdlib function: the function an alias borrowed
+    /// from another language stands for, or else the closest name.
+    fn function_name_fix(&self, module: &str, function: &Ident) -> Option<Fix> {
+        if let Some(name) = stdlib::alias(module, &function.name) {
+            return Some(Fix::new(
+                format!("replace with '{name}'"),
+                Applicability::MachineApplicable,
+                vec![TextEdit::replace(function.span, name)],
+            ));
+        }
+        let functions = self.stdlib.modules().get(module)?.keys();
+        closest_name(&function.name, functions.map(String::as_str))
+            .map(|name| rename_fix(function.span, name, name))
+    }
+
+    /// Add `cap` to `capabilities { required: [...] }`, creating the list
+    /// or the whole block if needed.
+    fn declare_capability_fix(&self, cap: &str) -> Fix {
+        let edit = match &self.capabilities_block {
+            Some(block) => {
+                if let Some(last) = block.required.last() {
+                    TextEdit::insert(
+                        last.span.end_line,
+                        last.span.end_col + 1,
+                        format!(", {cap}"),
+                    )
+                } else if let Some((line, col)) = self
+                    .find_in_span(block.span, "required")
+                    .and_then(|(line, col)| {
+                        let at = Span::new(line, col, line, col);
+                        self.find_in_span(at, "[")
+                    })
+                {
+                    TextEdit::insert(line, col + 1, cap)
+                } else {
+                    let (line, col) = self
+                        .find_in_span(block.span, "{")
+                        .unwrap_or((block.span.start_line, block.span.start_col));
+                    let indent = self.indent_of(block.span.start_line);
+                    TextEdit::insert(line, col + 1, format!("\n{indent}  required: [{cap}]"))
+                }
+            }
+            None => {
+                let state = self.state_span;
+                let indent = self.indent_of(state.start_line);
+                TextEdit::insert(
+                    state.end_line,
+                    state.end_col + 1,
+                    format!("\n\n{indent}capabilities {{\n{indent}  required: [{cap}]\n{indent}}}"),
+                )
+            }
+        };
+        Fix::new(
+            format!("declare capability '{cap}'"),
+            Applicability::MachineApplicable,
+            vec![edit],
+        )
+    }
+
+    /// Insert an arm with an empty body for every missing variant (and a
+    /// wildcard for `nil`) before the closing brace of `match_expr`.
+    fn missing_arms_fix(&self, match_expr: &MatchExpr, missing: &[&SumVariant], nil: bool) -> Fix {
+        let mut patterns: Vec<String> = missing
+            .iter()
+            .map(|v| {
+                if v.params.is_empty() {
+                    v.name.clone()
+                } else {
+                    let params: Vec<_> = v.params.iter().map(|(name, _)| name.as_str()).collect();
+                    format!("{}({})", v.name, params.join(", "))
+                }
+            })
+            .collect();
+        if nil {
+            patterns.push("_".to_string());
+        }
+
+        let end = match_expr.span;
+        let closing_line = self.source.line(end.end_line).unwrap_or("");
+        let before_brace = closing_line.get(..end.end_col as usize - 1).unwrap_or("");
+        let edit = if before_brace.trim().is_empty() && end.end_line > end.start_line {
+            // `}` on its own line: one arm per line, indented like the others
+            let indent = match match_expr.arms.first() {
+                Some(arm) if arm.span.start_line > end.start_line => {
+                    self.indent_of(arm.span.start_line).to_string()
+                }
+                _ => format!("{}  ", self.indent_of(end.end_line)),
+            };
+            // Follow the existing arms' trailing commas
+            let comma = match match_expr.arms.last() {
+                Some(arm) => {
+                    let line = self.source.line(arm.span.end_line).unwrap_or("");
+                    let after = line.get(arm.span.end_col as usize..).unwrap_or("");
+                    if after.trim_start().starts_with(',') {
+                        ","
+                    } else {
+                        ""
+                    }
+                }
+                None => ",",
+            };
+            let arms: String = patterns
+                .iter()
+                .map(|p| format!("{indent}{p} -> {{}}{comma}\n"))
+                .collect();
+            TextEdit::insert(end.end_line, 1, arms)
+        } else {
+            let arms: Vec<_> = patterns.iter().map(|p| format!("{p} -> {{}}")).collect();
+            match match_expr.arms.last() {
+                Some(arm) => TextEdit::insert(
+                    arm.span.end_line,
+                    arm.span.end_col + 1,
+                    format!(", {}", arms.join(", ")),
+                ),
+                None => {
+                    TextEdit::insert(end.end_line, end.end_col, format!("{} ", arms.join(", ")))
+                }
+            }
+        };
+        Fix::new(
+            format!(
+                "add the missing arm{}",
+                if patterns.len() == 1 { "" } else { "s" }
+            ),
+            Applicability::HasPlaceholders,
+            vec![edit],
+        )
+    }
 }
 
 // ══════════════════════════════════════════════════════════════════════════════
 // Helpers
 // ══════════════════════════════════════════════════════════════════════════════
 
+/// Edit distance between two names, in bytes: insertions, deletions,
+/// substitutions and swaps of adjacent bytes each count as one edit.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let (a, b) = (a.as_bytes(), b.as_bytes());
+    // d[i][j] = distance between a[..i] and b[..j]
+    let mut d = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in d.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in d[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            d[i][j] = (d[i - 1][j] + 1)
+                .min(d[i][j - 1] + 1)
+                .min(d[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                d[i][j] = d[i][j].min(d[i - 2][j - 2] + 1);
+            }
+        }
+    }
+    d[a.len()][b.len()]
+}
+
+/// The candidate a misspelled `name` most likely meant: the closest one
+/// within a third of its length (at least one edit), ties going to the
+/// alphabetically first.
+fn closest_name<'c>(name: &str, candidates: impl Iterator<Item = &'c str>) -> Option<&'c str> {
+    let limit = (name.len() / 3).max(1);
+    candidates
+        .map(|candidate| (edit_distance(name, candidate), candidate))
+        .filter(|(distance, _)| *distance <= limit)
+        .min()
+        .map(|(_, candidate)| candidate)
+}
+
+/// Replace a misspelled name at `span` with `replacement`.
+fn rename_fix(span: Span, name: &str, replacement: &str) -> Fix {
+    Fix::new(
+        format!("replace with '{name}'"),
+        Applicability::MaybeIncorrect,
+        vec![TextEdit::replace(span, replacement)],
+    )
+}
+
+/// The lambda an argument expression is, looking through parentheses.
+fn lambda_arg(expr: &Expr) -> Option<&LambdaExpr> {
+    match &expr.kind {
+        ExprKind::Lambda(lambda) => Some(lambda),
+        ExprKind::Paren(inner) => lambda_arg(inner),
+        _ => None,
+    }
+}
+
+/// Identifier or field path (`user.address.city`) named by `expr`, if it
+/// can be narrowed.
+fn narrow_path(expr: &Expr) -> Option<String> {
+    match &expr.kind {
+        ExprKind::Identifier(name) => Some(name.clone()),
+        ExprKind::FieldAccess { object, field } => {
+            Some(format!("{}.{}", narrow_path(object)?, field.name))
+        }
+        ExprKind::Paren(inner) => narrow_path(inner),
+        _ => None,
+    }
+}
+
+/// Paths that cannot be nil when `condition` evaluates to `outcome`.
+fn non_nil_paths(condition: &Expr, outcome: bool) -> Vec<String> {
+    match &condition.kind {
+        ExprKind::Paren(inner) => non_nil_paths(inner, outcome),
+        ExprKind::Unary {
+            op: UnaryOp::Not,
+            operand,
+        } => non_nil_paths(operand, !outcome),
+        // `a and b` is true only if both are; `a or b` is false only if both are
+        ExprKind::Binary {
+            left,
+            op: op @ (BinOp::And | BinOp::Or),
+            right,
+        } => {
+            let left = non_nil_paths(left, outcome);
+            let right = non_nil_paths(right, outcome);
+            if (*op == BinOp::And) == outcome {
+                left.into_iter().chain(right).collect()
+            } else {
+                left.into_iter().filter(|p| right.contains(p)).collect()
+            }
+        }
+        ExprKind::Binary {
+            left,
+            op: op @ (BinOp::Eq | BinOp::NotEq),
+            right,
+        } if (*op == BinOp::NotEq) == outcome => match (&left.kind, &right.kind) {
+            (_, ExprKind::NilLit) => narrow_path(left).into_iter().collect(),
+            (ExprKind::NilLit, _) => narrow_path(right).into_iter().collect(),
+            _ => Vec::new(),
+        },
+        // `flag ?? false` can only be true, and `flag ?? true` only false,
+        // through a non-nil `flag`
+        ExprKind::NilCoalesce { left, right } if matches!(right.kind, ExprKind::BoolLit(b) if b != outcome) => {
+            narrow_path(left).into_iter().collect()
+        }
+        _ => Vec::new(),
+    }
+}
+
+/// Whether control never falls off the end of `block` (it always returns).
+fn block_exits(block: &Block) -> bool {
+    block.stmts.iter().any(|stmt| match stmt {
+        Stmt::Return(_) => true,
+        Stmt::If(if_expr) => if_exits(if_expr),
+        _ => false,
+    })
+}
+
+/// Whether every branch of `if_expr` returns.
+pub(crate) fn if_exits(if_expr: &IfExpr) -> bool {
+    block_exits(&if_expr.then_block)
+        && match &if_expr.else_branch {
+            Some(ElseBranch::ElseIf(elif)) => if_exits(elif),
+            Some(ElseBranch::Block(block)) => block_exits(block),
+            None => false,
+        }
+}
+
+/// The state path a `set` assigns (`user.nickname`).
+fn set_path(set: &SetStmt) -> String {
+    set.target
+        .iter()
+        .map(|ident| ident.name.as_str())
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+/// Every state path assigned anywhere in `stmts`.
+fn assigned_paths(stmts: &[Stmt], out: &mut Vec<String>) {
+    for stmt in stmts {
+        match stmt {
+            Stmt::Set(set) => out.push(set_path(set)),
+            Stmt::If(if_expr) => assigned_in_if(if_expr, out),
+            Stmt::For(for_expr) => assigned_paths(&for_expr.body.stmts, out),
+            Stmt::Match(match_expr) => {
+                for arm in &match_expr.arms {
+                    if let MatchArmBody::Block(block) = &arm.body {
+                        assigned_paths(&block.stmts, out);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn assigned_in_if(if_expr: &IfExpr, out: &mut Vec<String>) {
+    assigned_paths(&if_expr.then_block.stmts, out);
+    match &if_expr.else_branch {
+        Some(ElseBranch::ElseIf(elif)) => assigned_in_if(elif, out),
+        Some(ElseBranch::Block(block)) => assigned_paths(&block.stmts, out),
+        None => {}
+    }
+}
+
 fn op_symbol(op: BinOp) -> &'static str {
     match op {
         BinOp::Add => "+",
@@ -1768,8 +2635,31 @@ fn op_symbol(op: BinOp) -> &'static str {
     }
 }
 
+/// The slot layout of a record type whose fields are all required.
+///
+/// Optional fields may be absent at runtime, so their records keep the
+/// dynamic key lookup.
+fn record_layout(ty: &Type) -> Option<RecordLayout> {
+    match ty {
+        Type::Record(fields) if fields.iter().all(|f| !f.optional) => {
+            Some(RecordLayout::new(fields.iter().map(|f| f.name.as_str())))
+        }
+        _ => None,
+    }
+}
+
+/// Whether values of `ty` may be lists or strings, whose size scales
+/// stdlib gas costs.
+fn is_sized(ty: &Type) -> bool {
+    match ty {
+        Type::String | Type::List(_) | Type::Any | Type::Unknown | Type::Named(_) => true,
+        Type::Nullable(inner) => is_sized(inner),
+        _ => false,
+    }
+}
+
 /// The 10 Phase 0 component names.
-const VALID_COMPONENTS: &[&str] = &[
+pub(crate) const VALID_COMPONENTS: &[&str] = &[
     "Button",
     "Column",
     "Modal",
@@ -0,0 +1,407 @@
+//! Capability effects: which capability functions each entry point can call.
+//!
+//! `capabilities { required: [...] }` says what a space may use; [`analyze`]
+//! infers what each action, `update` and `handleEvent` actually uses, so a
+//! host can ask for permission per action.  A capability call counts
+//! towards the entry point whose body contains it, including inside a
+//! lambda, which may run whenever the body does.  A lambda stored in a
+//! state or derived field carries its calls to every entry point that
+//! names the field — `set on_save = fn() { storage.set(...) }` in one
+//! action makes `on_save()`, `let f = on_save` or `list.map(xs, on_save)`
+//! in another an effect of the second.  Storing a value that names such a
+//! field (`{ save: on_save }`, `if c { on_save } else { on_load }`) carries
+//! the calls on to the new field.
+//!
+//! Only capabilities backed by a stdlib module (`http`, `storage`, ...) can
+//! be seen in code; host capabilities such as `display` are listed as
+//! declared but never appear in an entry point.  Views cannot use
+//! capabilities (E501), and test blocks are not analyzed.
+//! The result is a [`CapabilityManifest`], returned in
+//! [`crate::CompileResult::capability_manifest`] and embedded in the
+//! `.wasm` as the [`MANIFEST_SECTION`] custom section.
+
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+use pepl_types::ast::*;
+use serde::{Deserialize, Serialize};
+
+use crate::gas_bound::EntryKind;
+use crate::stdlib;
+
+/// Name of the custom section holding the JSON [`CapabilityManifest`].
+pub const MANIFEST_SECTION: &str = "pepl_capabilities";
+
+/// Declared and inferred capabilities of a space.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CapabilityManifest {
+    pub required: Vec<String>,
+    pub optional: Vec<String>,
+    /// Every action, then `update` and `handleEvent`, in declaration order.
+    pub entries: Vec<EntryEffects>,
+}
+
+/// The capabilities one entry point can use.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EntryEffects {
+    pub kind: EntryKind,
+    pub name: String,
+    /// Capabilities used, sorted (`["http", "storage"]`).
+    pub capabilities: Vec<String>,
+    /// Capability functions it can call, sorted (`["http.get"]`).
+    pub functions: Vec<String>,
+}
+
+impl CapabilityManifest {
+    /// Capabilities at least one entry point uses.
+    pub fn used(&self) -> BTreeSet<&str> {
+        self.entries
+            .iter()
+            .flat_map(|entry| entry.capabilities.iter().map(String::as_str))
+            .collect()
+    }
+
+    /// The entry point named `name`, if any.
+    pub fn entry(&self, name: &str) -> Option<&EntryEffects> {
+        self.entries.iter().find(|entry| entry.name == name)
+    }
+
+    /// The contents of the [`MANIFEST_SECTION`] custom section.
+    pub fn to_json(&self) -> Vec<u8> {
+        serde_json::to_vec(self).unwrap_or_default()
+    }
+}
+
+/// Infer the capability functions every entry point of `program` can call.
+pub fn analyze(program: &Program) -> CapabilityManifest {
+    let body = &program.space.body;
+    let names = |idents: &[Ident]| idents.iter().map(|i| i.name.clone()).collect();
+    let (required, optional) = match &body.capabilities {
+        Some(caps) => (names(&caps.required), names(&caps.optional)),
+        None => (Vec::new(), Vec::new()),
+    };
+
+    let mut entry_bodies: Vec<(EntryKind, &str, &Block)> = body
+        .actions
+        .iter()
+        .map(|a| (EntryKind::Action, a.name.name.as_str(), &a.body))
+        .collect();
+    if let Some(update) = &body.update {
+        entry_bodies.push((EntryKind::Update, "update", &update.body));
+    }
+    if let Some(handle_event) = &body.handle_event {
+        entry_bodies.push((EntryKind::HandleEvent, "handleEvent", &handle_event.body));
+    }
+
+    // What calling each field can do, grown until no lambda stored in a
+    // field calls a field whose effects are still growing
+    let fields = field_names(body);
+    let mut field_effects: HashMap<String, BTreeSet<String>> = HashMap::new();
+    loop {
+        let mut collector = Collector::new(&fields, &field_effects);
+        for field in &body.state.fields {
+            collector.store(&field.name.name, &field.default);
+        }
+        if let Some(derived) = &body.derived {
+            for field in &derived.fields {
+                collector.store(&field.name.name, &field.value);
+            }
+        }
+        for (_, _, block) in &entry_bodies {
+            collector.block(block);
+        }
+        if collector.stored == field_effects {
+            break;
+        }
+        field_effects = collector.stored;
+    }
+
+    let entries = entry_bodies
+        .into_iter()
+        .map(|(kind, name, block)| {
+            let mut collector = Collector::new(&fields, &field_effects);
+            collector.block(block);
+            let capabilities: BTreeSet<String> = collector
+                .calls
+                .iter()
+                .filter_map(|call| call.split_once('.'))
+                .map(|(module, _)| module.to_string())
+                .collect();
+            EntryEffects {
+                kind,
+                name: name.to_string(),
+                capabilities: capabilities.into_iter().collect(),
+                functions: collector.calls.into_iter().collect(),
+            }
+        })
+        .collect();
+
+    CapabilityManifest {
+        required,
+        optional,
+        entries,
+    }
+}
+
+fn field_names(body: &SpaceBody) -> HashSet<String> {
+    let mut fields: HashSet<String> = body
+        .state
+        .fields
+        .iter()
+        .map(|f| f.name.name.clone())
+        .collect();
+    if let Some(derived) = &body.derived {
+        fields.extend(derived.fields.iter().map(|f| f.name.name.clone()));
+    }
+    fields
+}
+
+// ══════════════════════════════════════════════════════════════════════════════
+// Collector
+// ══════════════════════════════════════════════════════════════════════════════
+
+/// Walks code, collecting the capability functions it can call.
+struct Collector<'a> {
+    capability_modules: HashMap<&'static str, &'static str>,
+    fields: &'a HashSet<String>,
+    /// What calling each field can do, as known so far.
+    field_effects: &'a HashMap<String, BTreeSet<String>>,
+    /// `module.function` of every capability call found.
+    calls: BTreeSet<String>,
+    /// Calls inside the lambdas stored in each field.
+    stored: HashMap<String, BTreeSet<String>>,
+}
+
+impl<'a> Collector<'a> {
+    fn new(
+        fields: &'a HashSet<String>,
+        field_effects: &'a HashMap<String, BTreeSet<String>>,
+    ) -> Self {
+        Collector {
+            capability_modules: stdlib::capability_modules(),
+            fields,
+            field_effects,
+            calls: BTreeSet::new(),
+            stored: HashMap::new(),
+        }
+    }
+
+    /// `value` is stored in `field`: the calls of its lambdas go with it.
+    fn store(&mut self, field: &str, value: &Expr) {
+        let (mut lambdas, mut names) = (Vec::new(), Vec::new());
+        carried(value, &mut lambdas, &mut names);
+        let outer = std::mem::take(&mut self.calls);
+        for lambda in lambdas {
+            self.block(&lambda.body);
+        }
+        // `set on_save = on_load` copies whatever lambda `on_load` holds
+        for name in names {
+            self.call_field(name);
+        }
+        let calls = std::mem::replace(&mut self.calls, outer);
+        self.stored
+            .entry(field.to_string())
+            .or_default()
+            .extend(calls);
+        self.expr(value);
+    }
+
+    fn call_field(&mut self, name: &str) {
+        if self.fields.contains(name) {
+            if let Some(effects) = self.field_effects.get(name) {
+                self.calls.extend(effects.iter().cloned());
+            }
+        }
+    }
+
+    fn block(&mut self, block: &Block) {
+        for stmt in &block.stmts {
+            self.stmt(stmt);
+        }
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Set(set) => self.store(&set.target[0].name, &set.value),
+            Stmt::Let(binding) => self.expr(&binding.value),
+            Stmt::If(if_expr) => self.if_expr(if_expr),
+            Stmt::For(for_expr) => self.for_expr(for_expr),
+            Stmt::Match(match_expr) => self.match_expr(match_expr),
+            Stmt::Return(_) => {}
+            Stmt::Assert(assert) => self.expr(&assert.condition),
+            Stmt::Expr(expr_stmt) => self.expr(&expr_stmt.expr),
+        }
+    }
+
+    fn if_expr(&mut self, if_expr: &IfExpr) {
+        self.expr(&if_expr.condition);
+        self.block(&if_expr.then_block);
+        match &if_expr.else_branch {
+            Some(ElseBranch::Block(block)) => self.block(block),
+            Some(ElseBranch::ElseIf(inner)) => self.if_expr(inner),
+            None => {}
+        }
+    }
+
+    fn for_expr(&mut self, for_expr: &ForExpr) {
+        self.expr(&for_expr.iterable);
+        self.block(&for_expr.body);
+    }
+
+    fn match_expr(&mut self, match_expr: &MatchExpr) {
+        self.expr(&match_expr.subject);
+        for arm in &match_expr.arms {
+            match &arm.body {
+                MatchArmBody::Expr(expr) => self.expr(expr),
+                MatchArmBody::Block(block) => self.block(block),
+            }
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        match &expr.kind {
+            ExprKind::NumberLit(_)
+            | ExprKind::StringLit(_)
+            | ExprKind::BoolLit(_)
+            | ExprKind::NilLit => {}
+            // A field holding a lambda can be called through anything that
+            // gets hold of it: `let f = on_save`, `list.map(xs, on_save)`,
+            // `settings.on_save()`
+            ExprKind::Identifier(name) => self.call_field(name),
+            ExprKind::StringInterpolation(parts) => {
+                for part in parts {
+                    if let StringPart::Expr(inner) = part {
+                        self.expr(inner);
+                    }
+                }
+            }
+            ExprKind::ListLit(items) => {
+                for item in items {
+                    self.expr(item);
+                }
+            }
+            ExprKind::RecordLit(entries) => {
+                for entry in entries {
+                    match entry {
+                        RecordEntry::Field { value, .. } => self.expr(value),
+                        RecordEntry::Spread(inner) => self.expr(inner),
+                    }
+                }
+            }
+            ExprKind::Call { name, args } => {
+                self.call_field(&name.name);
+                for arg in args {
+                    self.expr(arg);
+                }
+            }
+            ExprKind::QualifiedCall {
+                module,
+                function,
+                args,
+            } => {
+                if let Some(capability) = self.capability_modules.get(module.name.as_str()) {
+                    self.calls
+                        .insert(format!("{}.{}", capability, function.name));
+                }
+                for arg in args {
+                    self.expr(arg);
+                }
+            }
+            ExprKind::MethodCall { object, args, .. } => {
+                self.expr(object);
+                for arg in args {
+                    self.expr(arg);
+                }
+            }
+            ExprKind::FieldAccess { object, .. } => self.expr(object),
+            ExprKind::Binary { left, right, .. } | ExprKind::NilCoalesce { left, right } => {
+                self.expr(left);
+                self.expr(right);
+            }
+            ExprKind::Unary { operand, .. } => self.expr(operand),
+            ExprKind::ResultUnwrap(inner) | ExprKind::Paren(inner) => self.expr(inner),
+            ExprKind::If(if_expr) => self.if_expr(if_expr),
+            ExprKind::For(for_expr) => self.for_expr(for_expr),
+            ExprKind::Match(match_expr) => self.match_expr(match_expr),
+            ExprKind::Lambda(lambda) => self.block(&lambda.body),
+        }
+    }
+}
+
+/// What storing `expr` can store: its outermost lambdas (lambdas nested in
+/// them are part of their bodies) and the names outside them, any of which
+/// may hold a lambda.
+fn carried<'e>(expr: &'e Expr, lambdas: &mut Vec<&'e LambdaExpr>, names: &mut Vec<&'e str>) {
+    match &expr.kind {
+        ExprKind::Lambda(lambda) => lambdas.push(lambda),
+        ExprKind::Identifier(name) => names.push(name),
+        ExprKind::ListLit(items) => {
+            for item in items {
+                carried(item, lambdas, names);
+            }
+        }
+        ExprKind::RecordLit(entries) => {
+            for entry in entries {
+                match entry {
+                    RecordEntry::Field { value, .. } => carried(value, lambdas, names),
+                    RecordEntry::Spread(inner) => carried(inner, lambdas, names),
+                }
+            }
+        }
+        ExprKind::Call { args, .. } | ExprKind::QualifiedCall { args, .. } => {
+            for arg in args {
+                carried(arg, lambdas, names);
+            }
+        }
+        ExprKind::MethodCall { object, args, .. } => {
+            carried(object, lambdas, names);
+            for arg in args {
+                carried(arg, lambdas, names);
+            }
+        }
+        ExprKind::FieldAccess { object, .. } => carried(object, lambdas, names),
+        ExprKind::Binary { left, right, .. } | ExprKind::NilCoalesce { left, right } => {
+            carried(left, lambdas, names);
+            carried(right, lambdas, names);
+        }
+        ExprKind::ResultUnwrap(inner) | ExprKind::Paren(inner) => carried(inner, lambdas, names),
+        ExprKind::If(if_expr) => carried_if(if_expr, lambdas, names),
+        ExprKind::Match(match_expr) => {
+            for arm in &match_expr.arms {
+                match &arm.body {
+                    MatchArmBody::Expr(expr) => carried(expr, lambdas, names),
+                    MatchArmBody::Block(block) => carried_block(block, lambdas, names),
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+fn carried_if<'e>(
+    if_expr: &'e IfExpr,
+    lambdas: &mut Vec<&'e LambdaExpr>,
+    names: &mut Vec<&'e str>,
+) {
+    carried_block(&if_expr.then_block, lambdas, names);
+    match &if_expr.else_branch {
+        Some(ElseBranch::Block(block)) => carried_block(block, lambdas, names),
+        Some(ElseBranch::ElseIf(inner)) => carried_if(inner, lambdas, names),
+        None => {}
+    }
+}
+
+/// A branch yields its last expression; anything bound on the way may
+/// end up there.
+fn carried_block<'e>(
+    block: &'e Block,
+    lambdas: &mut Vec<&'e LambdaExpr>,
+    names: &mut Vec<&'e str>,
+) {
+    for stmt in &block.stmts {
+        match stmt {
+            Stmt::Let(binding) => carried(&binding.value, lambdas, names),
+            Stmt::Expr(expr_stmt) => carried(&expr_stmt.expr, lambdas, names),
+            _ => {}
+        }
+    }
+}
@@ -45,6 +45,9 @@ pub enum ScopeKind {
 struct Scope {
     kind: ScopeKind,
     bindings: HashMap<String, Type>,
+    /// Types refined by control flow, keyed by identifier or field path
+    /// (`user.nickname`).  Consulted before `bindings`.
+    narrowed: HashMap<String, Type>,
 }
 
 // ══════════════════════════════════════════════════════════════════════════════
@@ -64,6 +67,7 @@ impl TypeEnv {
             scopes: vec![Scope {
                 kind: ScopeKind::Space,
                 bindings: HashMap::new(),
+                narrowed: HashMap::new(),
             }],
         }
     }
@@ -73,6 +77,7 @@ impl TypeEnv {
         self.scopes.push(Scope {
             kind,
             bindings: HashMap::new(),
+            narrowed: HashMap::new(),
         });
     }
 
@@ -90,16 +95,27 @@ impl TypeEnv {
         if scope.bindings.contains_key(name) {
             return false;
         }
+        scope.narrowed.retain(|path, _| !is_under(path, name));
         scope.bindings.insert(name.to_string(), ty);
         true
     }
 
     /// Look up a binding by name, searching from innermost to outermost scope.
+    /// A field path (`user.nickname`) resolves only if it has been narrowed.
     pub fn lookup(&self, name: &str) -> Option<&Type> {
+        let root = name.split('.').next().unwrap_or(name);
         for scope in self.scopes.iter().rev() {
-            if let Some(ty) = scope.bindings.get(name) {
+            if let Some(ty) = scope
+                .narrowed
+                .get(name)
+                .or_else(|| scope.bindings.get(name))
+            {
                 return Some(ty);
             }
+            if scope.bindings.contains_key(root) {
+                // The path's root is shadowed here; outer narrowings are stale.
+                return None;
+            }
         }
         None
     }
@@ -146,12 +162,28 @@ impl TypeEnv {
         self.scopes.last().expect("no scope").kind
     }
 
-    /// Narrow a binding's type in the current scope (for nil narrowing).
-    /// Creates a new binding in the current scope that shadows the outer one.
-    pub fn narrow(&mut self, name: &str, ty: Type) {
+    /// Narrow an identifier or field path to `ty` until the current scope
+    /// is popped (for nil narrowing).
+    pub fn narrow(&mut self, path: &str, ty: Type) {
         let scope = self.scopes.last_mut().expect("no scope");
-        scope.bindings.insert(name.to_string(), ty);
+        scope.narrowed.insert(path.to_string(), ty);
     }
+
+    /// Forget every narrowing of `path` and the paths below it, in all
+    /// scopes — the value was reassigned.
+    pub fn widen(&mut self, path: &str) {
+        for scope in &mut self.scopes {
+            scope
+                .narrowed
+                .retain(|narrowed, _| !is_under(narrowed, path));
+        }
+    }
+}
+
+/// Whether `path` is `root` or a field path below it.
+fn is_under(path: &str, root: &str) -> bool {
+    path.strip_prefix(root)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with('.'))
 }
 
 impl Default for TypeEnv {
@@ -0,0 +1,891 @@
+//! Static worst-case gas bounds.
+//!
+//! For every action, view, `update` and `handleEvent`, [`analyze`] computes
+//! an upper bound on the gas one call can spend, charged from the shared
+//! [`pepl_types::gas`] table.  A bound is a polynomial in the *sizes* of the
+//! entry point's inputs: the length of every list and string held in state,
+//! in a derived field or passed as an action parameter.  Loops contribute
+//! their trip count (the size of the list they walk), and stdlib calls the
+//! size-scaled cost of their arguments and result.
+//!
+//! When the analysis cannot bound a size it needs (a loop over a list built
+//! from unknown elements, a call to a lambda it cannot see), the entry point
+//! is [`GasBound::Unbounded`], with the reason and where it arose.
+//!
+//! The bound covers both backends: besides its body, a state-changing entry
+//! point pays for recomputing derived fields and checking invariants.
+//! Explaining a failed invariant and rolling back are free.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+
+use pepl_types::ast::*;
+use pepl_types::gas::{self, Growth};
+use pepl_types::Span;
+use serde::{Deserialize, Serialize};
+
+/// Upper bound used for the `log n` factor of [`Growth::NLogN`] costs when
+/// `n` is symbolic: the bit width of a list length.
+const LOG_SIZE_BOUND: u64 = 32;
+
+/// Rough upper bound on the length of a number or bool converted to a string.
+const SCALAR_STRING_BOUND: u64 = 24;
+
+// ══════════════════════════════════════════════════════════════════════════════
+// Bounds
+// ══════════════════════════════════════════════════════════════════════════════
+
+/// One term of a [`GasBound`]: `coefficient × len(sizes[0]) × len(sizes[1]) × …`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Term {
+    pub coefficient: u64,
+    /// Inputs whose sizes multiply the coefficient (sorted; may repeat).
+    pub sizes: Vec<String>,
+}
+
+/// Worst-case gas of one entry point.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum GasBound {
+    /// At most the sum of `terms`; a constant bound has one term without
+    /// sizes (or none, for zero).
+    Bounded { terms: Vec<Term> },
+    /// No bound could be derived.
+    Unbounded { reason: String, span: Span },
+}
+
+impl GasBound {
+    /// The bound, if it does not depend on any input size.
+    pub fn constant(&self) -> Option<u64> {
+        match self {
+            GasBound::Bounded { terms } if terms.iter().all(|t| t.sizes.is_empty()) => {
+                Some(terms.iter().map(|t| t.coefficient).sum())
+            }
+            _ => None,
+        }
+    }
+
+    /// The bound for concrete input sizes (missing inputs count as 0).
+    /// `None` when the entry point is unbounded.
+    pub fn evaluate(&self, sizes: &HashMap<String, u64>) -> Option<u64> {
+        let GasBound::Bounded { terms } = self else {
+            return None;
+        };
+        let total = terms
+            .iter()
+            .map(|t| {
+                t.sizes.iter().fold(t.coefficient, |acc, name| {
+                    acc.saturating_mul(sizes.get(name).copied().unwrap_or(0))
+                })
+            })
+            .fold(0u64, u64::saturating_add);
+        Some(total)
+    }
+}
+
+impl fmt::Display for GasBound {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GasBound::Bounded { terms } if terms.is_empty() => write!(f, "0"),
+            GasBound::Bounded { terms } => {
+                for (i, term) in terms.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " + ")?;
+                    }
+                    if term.sizes.is_empty() || term.coefficient != 1 {
+                        write!(f, "{}", term.coefficient)?;
+                        if !term.sizes.is_empty() {
+                            write!(f, "·")?;
+                        }
+                    }
+                    let sizes: Vec<String> =
+                        term.sizes.iter().map(|s| format!("len({s})")).collect();
+                    write!(f, "{}", sizes.join("·"))?;
+                }
+                Ok(())
+            }
+            GasBound::Unbounded { reason, .. } => write!(f, "unbounded ({reason})"),
+        }
+    }
+}
+
+/// Kind of entry point a [`EntryGas`] describes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EntryKind {
+    Action,
+    View,
+    Update,
+    HandleEvent,
+}
+
+impl fmt::Display for EntryKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            EntryKind::Action => "action",
+            EntryKind::View => "view",
+            EntryKind::Update => "update",
+            EntryKind::HandleEvent => "handleEvent",
+        })
+    }
+}
+
+/// The worst-case gas of one entry point.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EntryGas {
+    pub kind: EntryKind,
+    pub name: String,
+    pub bound: GasBound,
+    /// Span of the entry point's declaration.
+    pub span: Span,
+}
+
+// ══════════════════════════════════════════════════════════════════════════════
+// Checked types
+// ══════════════════════════════════════════════════════════════════════════════
+
+/// Which expressions the type checker found to hold a list or a string —
+/// the values whose size scales stdlib costs.  Expressions it never saw, or
+/// saw with both kinds of type, are treated as sized.
+#[derive(Debug, Clone, Default)]
+pub struct SizedExprs {
+    sized: HashMap<Span, bool>,
+}
+
+impl SizedExprs {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record whether the expression at `span` is a list or string.
+    pub fn record(&mut self, span: Span, sized: bool) {
+        *self.sized.entry(span).or_insert(sized) |= sized;
+    }
+
+    fn is_sized(&self, span: Span) -> bool {
+        self.sized.get(&span).copied().unwrap_or(true)
+    }
+}
+
+// ══════════════════════════════════════════════════════════════════════════════
+// Polynomials
+// ══════════════════════════════════════════════════════════════════════════════
+
+/// A polynomial with non-negative coefficients over input sizes; the key
+/// of each term is its sorted list of size names.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct Poly(BTreeMap<Vec<String>, u64>);
+
+impl Poly {
+    fn constant(c: u64) -> Self {
+        let mut p = Poly::default();
+        p.add_term(Vec::new(), c);
+        p
+    }
+
+    fn size_of(name: &str) -> Self {
+        let mut p = Poly::default();
+        p.add_term(vec![name.to_string()], 1);
+        p
+    }
+
+    fn add_term(&mut self, key: Vec<String>, c: u64) {
+        if c > 0 {
+            let entry = self.0.entry(key).or_default();
+            *entry = entry.saturating_add(c);
+        }
+    }
+
+    fn as_constant(&self) -> Option<u64> {
+        match self.0.len() {
+            0 => Some(0),
+            1 => self.0.get(&Vec::new()).copied(),
+            _ => None,
+        }
+    }
+
+    fn plus(mut self, other: &Poly) -> Poly {
+        for (key, &c) in &other.0 {
+            self.add_term(key.clone(), c);
+        }
+        self
+    }
+
+    fn times(&self, other: &Poly) -> Poly {
+        let mut out = Poly::default();
+        for (a, &ca) in &self.0 {
+            for (b, &cb) in &other.0 {
+                let mut key: Vec<String> = a.iter().chain(b).cloned().collect();
+                key.sort();
+                out.add_term(key, ca.saturating_mul(cb));
+            }
+        }
+        out
+    }
+
+    fn scale(&self, c: u64) -> Poly {
+        self.times(&Poly::constant(c))
+    }
+
+    /// A polynomial bounding both `self` and `other` (sizes are never
+    /// negative, so the larger coefficient of every term will do).
+    fn max(mut self, other: &Poly) -> Poly {
+        for (key, &c) in &other.0 {
+            let entry = self.0.entry(key.clone()).or_default();
+            *entry = (*entry).max(c);
+        }
+        self
+    }
+
+    fn into_bound(self) -> GasBound {
+        // Constant term first, then by degree and name
+        let mut terms: Vec<Term> = self
+            .0
+            .into_iter()
+            .map(|(sizes, coefficient)| Term { coefficient, sizes })
+            .collect();
+        terms.sort_by(|a, b| (a.sizes.len(), &a.sizes).cmp(&(b.sizes.len(), &b.sizes)));
+        GasBound::Bounded { terms }
+    }
+}
+
+// ══════════════════════════════════════════════════════════════════════════════
+// Analysis
+// ══════════════════════════════════════════════════════════════════════════════
+
+/// Why a bound could not be derived.
+struct Unbounded {
+    reason: String,
+    span: Span,
+}
+
+type Bound = Result<Poly, Unbounded>;
+
+fn unbounded(reason: impl Into<String>, span: Span) -> Unbounded {
+    Unbounded {
+        reason: reason.into(),
+        span,
+    }
+}
+
+/// What a name in scope stands for.
+#[derive(Clone)]
+enum Binding<'a> {
+    /// A value of the given size (`None` when unknown).
+    Value(Option<Poly>),
+    /// A lambda, whose body is charged where it is called.
+    Lambda(&'a LambdaExpr),
+}
+
+/// Compute the worst-case gas of every entry point of `program`.
+pub fn analyze(program: &Program, sized: &SizedExprs) -> Vec<EntryGas> {
+    let body = &program.space.body;
+    let mut entries = Vec::new();
+    let mut entry = |kind, name: &str, span, bound: Bound| {
+        let bound = match bound {
+            Ok(poly) => poly.into_bound(),
+            Err(Unbounded { reason, span }) => GasBound::Unbounded { reason, span },
+        };
+        entries.push(EntryGas {
+            kind,
+            name: name.to_string(),
+            bound,
+            span,
+        });
+    };
+
+    for action in &body.actions {
+        let mut a = Analyzer::new(program, sized);
+        a.bind_inputs(&action.params);
+        let bound = a.block(&action.body).and_then(|b| Ok(b.plus(&a.commit()?)));
+        entry(EntryKind::Action, &action.name.name, action.span, bound);
+    }
+    for view in &body.views {
+        let mut a = Analyzer::new(program, sized);
+        a.bind_unknown(view.params.iter().map(|p| p.name.name.as_str()));
+        let bound = a.ui_block(&view.body);
+        entry(EntryKind::View, &view.name.name, view.span, bound);
+    }
+    if let Some(update) = &body.update {
+        let mut a = Analyzer::new(program, sized);
+        a.bind_unknown([update.param.name.name.as_str()]);
+        let bound = a.block(&update.body).and_then(|b| Ok(b.plus(&a.commit()?)));
+        entry(EntryKind::Update, "update", update.span, bound);
+    }
+    if let Some(handle_event) = &body.handle_event {
+        let mut a = Analyzer::new(program, sized);
+        a.bind_unknown([handle_event.param.name.name.as_str()]);
+        let bound = a
+            .block(&handle_event.body)
+            .and_then(|b| Ok(b.plus(&a.commit()?)));
+        entry(
+            EntryKind::HandleEvent,
+            "handleEvent",
+            handle_event.span,
+            bound,
+        );
+    }
+    entries
+}
+
+struct Analyzer<'a> {
+    program: &'a Program,
+    sized: &'a SizedExprs,
+    /// Innermost scope last; the first holds state and derived fields.
+    scopes: Vec<HashMap<String, Binding<'a>>>,
+}
+
+impl<'a> Analyzer<'a> {
+    fn new(program: &'a Program, sized: &'a SizedExprs) -> Self {
+        let body = &program.space.body;
+        let fields = body.state.fields.iter().map(|f| &f.name.name);
+        let derived = body
+            .derived
+            .iter()
+            .flat_map(|d| &d.fields)
+            .map(|f| &f.name.name);
+        let globals = fields
+            .chain(derived)
+            .map(|name| (name.clone(), Binding::Value(Some(Poly::size_of(name)))))
+            .collect();
+        Self {
+            program,
+            sized,
+            scopes: vec![globals],
+        }
+    }
+
+    /// Action parameters are inputs: each has a size of its own.
+    fn bind_inputs(&mut self, params: &[Param]) {
+        let scope = params
+            .iter()
+            .map(|p| {
+                let name = p.name.name.clone();
+                (name.clone(), Binding::Value(Some(Poly::size_of(&name))))
+            })
+            .collect();
+        self.scopes.push(scope);
+    }
+
+    fn bind_unknown<'n>(&mut self, names: impl IntoIterator<Item = &'n str>) {
+        let scope = names
+            .into_iter()
+            .map(|name| (name.to_string(), Binding::Value(None)))
+            .collect();
+        self.scopes.push(scope);
+    }
+
+    fn lookup(&self, name: &str) -> Option<&Binding<'a>> {
+        self.scopes.iter().rev().find_map(|scope| scope.get(name))
+    }
+
+    fn define(&mut self, name: &str, binding: Binding<'a>) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.to_string(), binding);
+        }
+    }
+
+    /// Rebind `name` where it is defined (a `set` on a state field).
+    fn assign(&mut self, name: &str, size: Option<Poly>) {
+        if let Some(scope) = self.scopes.iter_mut().rev().find(|s| s.contains_key(name)) {
+            scope.insert(name.to_string(), Binding::Value(size));
+        }
+    }
+
+    /// Merge the sizes known after two alternative branches into `self`.
+    fn join(&mut self, other: Vec<HashMap<String, Binding<'a>>>) {
+        for (mine, theirs) in self.scopes.iter_mut().zip(other) {
+            for (name, binding) in mine.iter_mut() {
+                if let (Binding::Value(a), Some(Binding::Value(b))) = (&*binding, theirs.get(name))
+                {
+                    let merged = match (a, b) {
+                        (Some(a), Some(b)) => Some(a.clone().max(b)),
+                        _ => None,
+                    };
+                    *binding = Binding::Value(merged);
+                }
+            }
+        }
+    }
+
+    /// Recompute derived fields and check invariants after a state change.
+    fn commit(&mut self) -> Bound {
+        let body = &self.program.space.body;
+        let mut derived = Poly::default();
+        for field in body.derived.iter().flat_map(|d| &d.fields) {
+            derived = derived.plus(&self.expr(&field.value)?);
+            let size = self.size(&field.value);
+            self.assign(&field.name.name, size);
+        }
+        let mut checks = Poly::default();
+        for invariant in &body.invariants {
+            checks = checks.plus(&self.expr(&invariant.condition)?);
+        }
+        Ok(derived.plus(&checks))
+    }
+
+    // ── Statements ──────────────────────────────────────────────────────────
+
+    fn block(&mut self, block: &'a Block) -> Bound {
+        self.scopes.push(HashMap::new());
+        let result = block
+            .stmts
+            .iter()
+            .try_fold(Poly::default(), |acc, stmt| Ok(acc.plus(&self.stmt(stmt)?)));
+        self.scopes.pop();
+        result
+    }
+
+    fn stmt(&mut self, stmt: &'a Stmt) -> Bound {
+        let own = Poly::constant(gas::stmt_cost(stmt));
+        let inner = match stmt {
+            Stmt::Set(set) => {
+                let value = self.expr(&set.value)?;
+                if let [field] = set.target.as_slice() {
+                    let size = self.size(&set.value);
+                    self.assign(&field.name, size);
+                }
+                value
+            }
+            Stmt::Let(binding) => {
+                let value = self.expr(&binding.value)?;
+                if let Some(name) = &binding.name {
+                    let bound = match &binding.value.kind {
+                        ExprKind::Lambda(lambda) => Binding::Lambda(lambda),
+                        _ => Binding::Value(self.size(&binding.value)),
+                    };
+                    self.define(&name.name, bound);
+                }
+                value
+            }
+            Stmt::If(if_expr) => self.if_expr(if_expr)?,
+            Stmt::For(for_expr) => self.for_expr(for_expr)?,
+            Stmt::Match(match_expr) => self.match_expr(match_expr)?,
+            Stmt::Return(_) => Poly::default(),
+            Stmt::Assert(assert_stmt) => self.expr(&assert_stmt.condition)?,
+            Stmt::Expr(expr_stmt) => self.expr(&expr_stmt.expr)?,
+        };
+        Ok(own.plus(&inner))
+    }
+
+    fn if_expr(&mut self, if_expr: &'a IfExpr) -> Bound {
+        let condition = self.expr(&if_expr.condition)?;
+        let before = self.scopes.clone();
+        let then = self.block(&if_expr.then_block)?;
+        let after_then = std::mem::replace(&mut self.scopes, before);
+        let other = match &if_expr.else_branch {
+            Some(ElseBranch::Block(block)) => self.block(block)?,
+            Some(ElseBranch::ElseIf(elif)) => {
+                Poly::constant(gas::ELSE_IF).plus(&self.if_expr(elif)?)
+            }
+            None => Poly::default(),
+        };
+        self.join(after_then);
+        Ok(condition.plus(&then.max(&other)))
+    }
+
+    fn for_expr(&mut self, for_expr: &'a ForExpr) -> Bound {
+        let iterable = self.expr(&for_expr.iterable)?;
+        let trips = self.size(&for_expr.iterable).ok_or_else(|| {
+            unbounded(
+                "`for` over a list of unknown length",
+                for_expr.iterable.span,
+            )
+        })?;
+        // Fields set in the body may grow on every iteration
+        let mut targets = HashSet::new();
+        set_targets(&for_expr.body, &mut targets);
+        for name in &targets {
+            self.assign(name, None);
+        }
+        let mut names = vec![for_expr.item.name.as_str()];
+        names.extend(for_expr.index.iter().map(|i| i.name.as_str()));
+        self.bind_unknown(names);
+        let body = self.block(&for_expr.body);
+        self.scopes.pop();
+        let per_trip = Poly::constant(gas::FOR_ITERATION).plus(&body?);
+        Ok(iterable.plus(&trips.times(&per_trip)))
+    }
+
+    fn match_expr(&mut self, match_expr: &'a MatchExpr) -> Bound {
+        let subject = self.expr(&match_expr.subject)?;
+        let before = self.scopes.clone();
+        let mut worst = Poly::default();
+        let mut after = Vec::new();
+        for arm in &match_expr.arms {
+            self.scopes = before.clone();
+            let names: Vec<&str> = match &arm.pattern {
+                Pattern::Variant { bindings, .. } => {
+                    bindings.iter().map(|b| b.name.as_str()).collect()
+                }
+                Pattern::Wildcard(_) => Vec::new(),
+            };
+            self.bind_unknown(names);
+            let cost = match &arm.body {
+                MatchArmBody::Expr(expr) => self.expr(expr),
+                MatchArmBody::Block(block) => self.block(block),
+            };
+            self.scopes.pop();
+            worst = worst.max(&cost?);
+            after.push(std::mem::take(&mut self.scopes));
+        }
+        self.scopes = before;
+        for scopes in after {
+            self.join(scopes);
+        }
+        Ok(subject.plus(&worst))
+    }
+
+    // ── Expressions ─────────────────────────────────────────────────────────
+
+    fn expr(&mut self, expr: &'a Expr) -> Bound {
+        let own = Poly::constant(gas::expr_cost(&expr.kind));
+        let inner = match &expr.kind {
+            ExprKind::NumberLit(_)
+            | ExprKind::StringLit(_)
+            | ExprKind::BoolLit(_)
+            | ExprKind::NilLit
+            | ExprKind::Identifier(_)
+            // Charged when called
+            | ExprKind::Lambda(_) => Poly::default(),
+            ExprKind::StringInterpolation(parts) => {
+                let exprs = parts.iter().filter_map(|part| match part {
+                    StringPart::Expr(e) => Some(e),
+                    StringPart::Literal(_) => None,
+                });
+                self.exprs(exprs)?
+            }
+            ExprKind::ListLit(items) => self.exprs(items)?,
+            ExprKind::RecordLit(entries) => {
+                let exprs = entries.iter().map(|entry| match entry {
+                    RecordEntry::Field { value, .. } => value,
+                    RecordEntry::Spread(e) => e,
+                });
+                self.exprs(exprs)?
+            }
+            ExprKind::Call { name, args } => {
+                let args_cost = self.exprs(args)?;
+                let Some(Binding::Lambda(lambda)) = self.lookup(&name.name).cloned() else {
+                    return Err(unbounded(
+                        format!("call to '{}', whose body is not known", name.name),
+                        expr.span,
+                    ));
+                };
+                args_cost.plus(&self.lambda(lambda)?)
+            }
+            ExprKind::QualifiedCall {
+                module,
+                function,
+                args,
+            } => {
+                let args_cost = self.exprs(args)?;
+                let arg_refs: Vec<&Expr> = args.iter().collect();
+                args_cost.plus(&self.stdlib_call(&module.name, &function.name, &arg_refs, expr)?)
+            }
+            ExprKind::FieldAccess { object, .. } => self.expr(object)?,
+            ExprKind::MethodCall {
+                object,
+                method,
+                args,
+            } => {
+                let args_cost = self.expr(object)?.plus(&self.exprs(args)?);
+                let mut all: Vec<&Expr> = vec![object];
+                all.extend(args.iter());
+                // The receiver picks the module at runtime
+                let list = self.stdlib_call("list", &method.name, &all, expr)?;
+                let string = self.stdlib_call("string", &method.name, &all, expr)?;
+                args_cost.plus(&list.max(&string))
+            }
+            ExprKind::Binary { left, right, .. } | ExprKind::NilCoalesce { left, right } => {
+                self.expr(left)?.plus(&self.expr(right)?)
+            }
+            ExprKind::Unary { operand, .. } => self.expr(operand)?,
+            ExprKind::ResultUnwrap(inner) | ExprKind::Paren(inner) => self.expr(inner)?,
+            ExprKind::If(if_expr) => self.if_expr(if_expr)?,
+            ExprKind::For(for_expr) => self.for_expr(for_expr)?,
+            ExprKind::Match(match_expr) => self.match_expr(match_expr)?,
+        };
+        Ok(own.plus(&inner))
+    }
+
+    fn exprs(&mut self, exprs: impl IntoIterator<Item = &'a Expr>) -> Bound {
+        exprs
+            .into_iter()
+            .try_fold(Poly::default(), |acc, e| Ok(acc.plus(&self.expr(e)?)))
+    }
+
+    /// One call of `lambda`: its body, with parameters of unknown size.
+    fn lambda(&mut self, lambda: &'a LambdaExpr) -> Bound {
+        self.bind_unknown(lambda.params.iter().map(|p| p.name.name.as_str()));
+        let body = self.block(&lambda.body);
+        self.scopes.pop();
+        body
+    }
+
+    /// The lambda an argument evaluates to, if it is one.
+    fn lambda_arg(&self, arg: &'a Expr) -> Option<&'a LambdaExpr> {
+        match &arg.kind {
+            ExprKind::Lambda(lambda) => Some(lambda),
+            ExprKind::Identifier(name) => match self.lookup(name) {
+                Some(Binding::Lambda(lambda)) => Some(lambda),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Gas of `module.function` itself and of the callbacks it runs.
+    fn stdlib_call(
+        &mut self,
+        module: &str,
+        function: &str,
+        args: &[&'a Expr],
+        call: &'a Expr,
+    ) -> Bound {
+        let cost = gas::stdlib_cost(module, function);
+        let mut total = match cost.growth {
+            Growth::Constant => Poly::constant(cost.base),
+            growth => {
+                let unknown = || {
+                    unbounded(
+                        format!("size of the values passed to {module}.{function} is not known"),
+                        call.span,
+                    )
+                };
+                let mut size = self
+                    .stdlib_result_size(module, function, args, call)
+                    .ok_or_else(unknown)?;
+                for arg in args {
+                    size = size.plus(&self.size(arg).ok_or_else(unknown)?);
+                }
+                match (growth, size.as_constant()) {
+                    (_, Some(n)) => Poly::constant(cost.charge(n)),
+                    (Growth::NLogN, None) => Poly::constant(cost.base)
+                        .plus(&size.scale(cost.per_item.saturating_mul(LOG_SIZE_BOUND))),
+                    (_, None) => Poly::constant(cost.base).plus(&size.scale(cost.per_item)),
+                }
+            }
+        };
+
+        // Callbacks run once per item (a comparison sort: once per pair)
+        for arg in args {
+            let Some(lambda) = self.lambda_arg(arg) else {
+                continue;
+            };
+            let items = match (module, args.first()) {
+                ("list", Some(list)) => self.size(list),
+                _ => None,
+            }
+            .ok_or_else(|| {
+                unbounded(
+                    format!("callback of {module}.{function} runs an unknown number of times"),
+                    arg.span,
+                )
+            })?;
+            let calls = if function == "sort" {
+                items.times(&items)
+            } else {
+                items
+            };
+            total = total.plus(&calls.times(&self.lambda(lambda)?));
+        }
+        Ok(total)
+    }
+
+    // ── Sizes ───────────────────────────────────────────────────────────────
+
+    /// Upper bound on the size of the value `expr` produces: the length of a
+    /// list or string, 0 for anything else.  `None` when it is not known.
+    fn size(&self, expr: &Expr) -> Option<Poly> {
+        if !self.sized.is_sized(expr.span) {
+            return Some(Poly::default());
+        }
+        match &expr.kind {
+            ExprKind::NumberLit(_) | ExprKind::BoolLit(_) | ExprKind::NilLit => {
+                Some(Poly::default())
+            }
+            ExprKind::StringLit(s) => Some(Poly::constant(s.len() as u64)),
+            ExprKind::StringInterpolation(parts) => {
+                parts.iter().try_fold(Poly::default(), |acc, part| {
+                    let part = match part {
+                        StringPart::Literal(s) => Poly::constant(s.len() as u64),
+                        StringPart::Expr(e) if self.sized.is_sized(e.span) => self.size(e)?,
+                        StringPart::Expr(_) => Poly::constant(SCALAR_STRING_BOUND),
+                    };
+                    Some(acc.plus(&part))
+                })
+            }
+            ExprKind::ListLit(items) => Some(Poly::constant(items.len() as u64)),
+            ExprKind::Identifier(name) => match self.lookup(name)? {
+                Binding::Value(size) => size.clone(),
+                Binding::Lambda(_) => Some(Poly::default()),
+            },
+            ExprKind::Paren(inner) => self.size(inner),
+            ExprKind::Binary {
+                left,
+                op: BinOp::Add,
+                right,
+            } => Some(self.size(left)?.plus(&self.size(right)?)),
+            ExprKind::NilCoalesce { left, right } => Some(self.size(left)?.max(&self.size(right)?)),
+            ExprKind::QualifiedCall {
+                module,
+                function,
+                args,
+            } => {
+                let args: Vec<&Expr> = args.iter().collect();
+                self.stdlib_result_size(&module.name, &function.name, &args, expr)
+            }
+            ExprKind::MethodCall {
+                object,
+                method,
+                args,
+            } => {
+                let mut all: Vec<&Expr> = vec![object];
+                all.extend(args.iter());
+                let list = self.stdlib_result_size("list", &method.name, &all, expr)?;
+                let string = self.stdlib_result_size("string", &method.name, &all, expr)?;
+                Some(list.max(&string))
+            }
+            _ => None,
+        }
+    }
+
+    /// Upper bound on the size of what `module.function(args)` returns.
+    fn stdlib_result_size(
+        &self,
+        module: &str,
+        function: &str,
+        args: &[&Expr],
+        call: &Expr,
+    ) -> Option<Poly> {
+        if !self.sized.is_sized(call.span) {
+            return Some(Poly::default());
+        }
+        let arg = |i: usize| args.get(i).and_then(|a| self.size(a));
+        let literal = |i: usize| match args.get(i).map(|a| &a.kind) {
+            Some(ExprKind::NumberLit(n)) if *n >= 0.0 => Some(n.ceil() as u64),
+            _ => None,
+        };
+        match (module, function) {
+            ("list", "empty") => Some(Poly::default()),
+            ("list", "of") => Some(Poly::constant(args.len() as u64)),
+            ("list", "range") => {
+                let (start, end) = match (args.first()?.kind.clone(), args.get(1)?.kind.clone()) {
+                    (ExprKind::NumberLit(s), ExprKind::NumberLit(e)) => (s, e),
+                    _ => return None,
+                };
+                Some(Poly::constant((end - start).max(0.0).ceil() as u64))
+            }
+            ("list", "repeat") => literal(1).map(Poly::constant),
+            (
+                "list",
+                "map" | "filter" | "sort" | "reverse" | "unique" | "take" | "drop" | "slice"
+                | "remove" | "update" | "set" | "zip",
+            ) => arg(0),
+            ("list", "append" | "prepend" | "insert") => Some(arg(0)?.plus(&Poly::constant(1))),
+            ("list", "concat") => Some(arg(0)?.plus(&arg(1)?)),
+            (
+                "string",
+                "trim" | "upper" | "lower" | "slice" | "substring" | "trim_start" | "trim_end",
+            ) => arg(0),
+            ("string", "concat") => Some(arg(0)?.plus(&arg(1)?)),
+            ("string", "split") => Some(arg(0)?.plus(&Poly::constant(1))),
+            ("string", "pad_start" | "pad_end") => Some(arg(0)?.plus(&Poly::constant(literal(1)?))),
+            ("convert", "to_string") if !self.sized.is_sized(args.first()?.span) => {
+                Some(Poly::constant(SCALAR_STRING_BOUND))
+            }
+            _ => None,
+        }
+    }
+
+    // ── Views ───────────────────────────────────────────────────────────────
+
+    fn ui_block(&mut self, block: &'a UIBlock) -> Bound {
+        self.scopes.push(HashMap::new());
+        let result = block
+            .elements
+            .iter()
+            .try_fold(Poly::default(), |acc, element| {
+                Ok(acc.plus(&self.ui_element(element)?))
+            });
+        self.scopes.pop();
+        result
+    }
+
+    fn ui_element(&mut self, element: &'a UIElement) -> Bound {
+        match element {
+            UIElement::Component(comp) => {
+                let props = self.exprs(comp.props.iter().map(|p| &p.value))?;
+                match &comp.children {
+                    Some(children) => Ok(props.plus(&self.ui_block(children)?)),
+                    None => Ok(props),
+                }
+            }
+            UIElement::Let(binding) => {
+                let value = self.expr(&binding.value)?;
+                if let Some(name) = &binding.name {
+                    let size = self.size(&binding.value);
+                    self.define(&name.name, Binding::Value(size));
+                }
+                Ok(Poly::constant(gas::LET).plus(&value))
+            }
+            UIElement::If(ui_if) => self.ui_if(ui_if),
+            UIElement::For(ui_for) => {
+                let iterable = self.expr(&ui_for.iterable)?;
+                let trips = self.size(&ui_for.iterable).ok_or_else(|| {
+                    unbounded("`for` over a list of unknown length", ui_for.iterable.span)
+                })?;
+                let mut names = vec![ui_for.item.name.as_str()];
+                names.extend(ui_for.index.iter().map(|i| i.name.as_str()));
+                self.bind_unknown(names);
+                let body = self.ui_block(&ui_for.body);
+                self.scopes.pop();
+                let per_trip = Poly::constant(gas::FOR_ITERATION).plus(&body?);
+                Ok(iterable.plus(&trips.times(&per_trip)))
+            }
+        }
+    }
+
+    fn ui_if(&mut self, ui_if: &'a UIIf) -> Bound {
+        let condition = self.expr(&ui_if.condition)?;
+        let then = self.ui_block(&ui_if.then_block)?;
+        let other = match &ui_if.else_block {
+            Some(UIElse::Block(block)) => self.ui_block(block)?,
+            Some(UIElse::ElseIf(elif)) => Poly::constant(gas::ELSE_IF).plus(&self.ui_if(elif)?),
+            None => Poly::default(),
+        };
+        Ok(condition.plus(&then.max(&other)))
+    }
+}
+
+/// Names of the state fields `set` anywhere in `block`.
+pub(crate) fn set_targets(block: &Block, out: &mut HashSet<String>) {
+    for stmt in &block.stmts {
+        match stmt {
+            Stmt::Set(set) => {
+                if let Some(field) = set.target.first() {
+                    out.insert(field.name.clone());
+                }
+            }
+            Stmt::If(if_expr) => if_targets(if_expr, out),
+            Stmt::For(for_expr) => set_targets(&for_expr.body, out),
+            Stmt::Match(match_expr) => {
+                for arm in &match_expr.arms {
+                    if let MatchArmBody::Block(block) = &arm.body {
+                        set_targets(block, out);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn if_targets(if_expr: &IfExpr, out: &mut HashSet<String>) {
+    set_targets(&if_expr.then_block, out);
+    match &if_expr.else_branch {
+        Some(ElseBranch::Block(block)) => set_targets(block, out),
+        Some(ElseBranch::ElseIf(elif)) => if_targets(elif, out),
+        None => {}
+    }
+}
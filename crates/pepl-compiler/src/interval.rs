@@ -0,0 +1,1436 @@
+//! Interval analysis: numeric ranges and list lengths.
+//!
+//! An abstract interpretation of the space that tracks, for every number,
+//! an [`Interval`] it is known to lie in and, for every list, an interval
+//! for its length.  [`analyze`] uses them to find operations that may fail
+//! or misbehave at runtime:
+//!
+//! - a divisor (`/`, `%`) that may be zero — a runtime trap,
+//! - a `math.sqrt` argument that may be negative — a NaN trap,
+//! - a `list.get`/`remove`/`update`/`set` index that may be out of bounds
+//!   — nil, or a silent no-op.
+//!
+//! Facts come from:
+//!
+//! - state: the values each field can hold, found as a fixpoint over every
+//!   action, `update` and `handleEvent` starting from the initializers
+//!   (widened to infinity where a field keeps growing),
+//! - invariants, assumed at every entry point and after every state change,
+//! - guards: `if`/`else`, `and`/`or` and `assert` conditions comparing a
+//!   variable or `list.length(xs)` against a number,
+//! - `for item, i in xs`, whose index is below the length of `xs`, as is a
+//!   variable compared with `i < list.length(xs)`.
+//!
+//! Only comparisons with a single variable refine anything, and records,
+//! nullable values and lambda parameters are unknown, so the analysis
+//! errs towards reporting.  Test blocks are not analyzed.
+
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt;
+
+use pepl_types::ast::*;
+use pepl_types::Span;
+
+use crate::stdlib::StdlibRegistry;
+use crate::ty::Type;
+
+/// Fixpoint rounds after which growing bounds are widened to infinity.
+const WIDEN_AFTER: usize = 2;
+
+/// Fixpoint rounds before giving up and assuming nothing about state.
+const MAX_ROUNDS: usize = 16;
+
+// ══════════════════════════════════════════════════════════════════════════════
+// Intervals
+// ══════════════════════════════════════════════════════════════════════════════
+
+/// A set of numbers: `[lo, hi]`, optionally only integers, optionally
+/// excluding zero (`x != 0` with `x` anywhere in `[-5, 5]`).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Interval {
+    pub lo: f64,
+    pub hi: f64,
+    /// Every value is an integer.
+    pub int: bool,
+    /// Zero is excluded.
+    pub nonzero: bool,
+}
+
+impl Interval {
+    /// Every number.
+    pub const TOP: Interval = Interval {
+        lo: f64::NEG_INFINITY,
+        hi: f64::INFINITY,
+        int: false,
+        nonzero: false,
+    };
+
+    /// The possible lengths of a list or string.
+    pub const LENGTH: Interval = Interval {
+        lo: 0.0,
+        hi: f64::INFINITY,
+        int: true,
+        nonzero: false,
+    };
+
+    pub fn new(lo: f64, hi: f64, int: bool) -> Interval {
+        Interval {
+            lo,
+            hi,
+            int,
+            nonzero: false,
+        }
+        .normalize()
+        .unwrap_or(Interval::TOP)
+    }
+
+    pub fn point(value: f64) -> Interval {
+        Interval::new(value, value, value.fract() == 0.0)
+    }
+
+    /// Tighten bounds to what `int` and `nonzero` imply; `None` if empty.
+    fn normalize(mut self) -> Option<Interval> {
+        if self.lo.is_nan() {
+            self.lo = f64::NEG_INFINITY;
+        }
+        if self.hi.is_nan() {
+            self.hi = f64::INFINITY;
+        }
+        if self.int {
+            self.lo = self.lo.ceil();
+            self.hi = self.hi.floor();
+            if self.nonzero && self.lo == 0.0 {
+                self.lo = 1.0;
+            }
+            if self.nonzero && self.hi == 0.0 {
+                self.hi = -1.0;
+            }
+        }
+        if self.lo > self.hi || (self.lo == 0.0 && self.hi == 0.0 && self.nonzero) {
+            return None;
+        }
+        self.nonzero |= self.lo > 0.0 || self.hi < 0.0;
+        Some(self)
+    }
+
+    pub fn is_top(&self) -> bool {
+        *self == Interval::TOP
+    }
+
+    pub fn may_be_zero(&self) -> bool {
+        !self.nonzero && self.lo <= 0.0 && self.hi >= 0.0
+    }
+
+    pub fn join(self, other: Interval) -> Interval {
+        Interval {
+            lo: self.lo.min(other.lo),
+            hi: self.hi.max(other.hi),
+            int: self.int && other.int,
+            nonzero: self.nonzero && other.nonzero,
+        }
+    }
+
+    /// Both at once; `None` if no number is in both.
+    pub fn meet(self, other: Interval) -> Option<Interval> {
+        Interval {
+            lo: self.lo.max(other.lo),
+            hi: self.hi.min(other.hi),
+            int: self.int || other.int,
+            nonzero: self.nonzero || other.nonzero,
+        }
+        .normalize()
+    }
+
+    /// `newer` joined into `self`, with any bound that moved pushed to
+    /// infinity so fixpoints terminate.
+    fn widen(self, newer: Interval) -> Interval {
+        let joined = self.join(newer);
+        Interval {
+            lo: if joined.lo < self.lo {
+                f64::NEG_INFINITY
+            } else {
+                self.lo
+            },
+            hi: if joined.hi > self.hi {
+                f64::INFINITY
+            } else {
+                self.hi
+            },
+            ..joined
+        }
+    }
+
+    fn neg(self) -> Interval {
+        Interval {
+            lo: -self.hi,
+            hi: -self.lo,
+            ..self
+        }
+    }
+
+    fn add(self, other: Interval) -> Interval {
+        Interval::new(
+            self.lo + other.lo,
+            self.hi + other.hi,
+            self.int && other.int,
+        )
+    }
+
+    fn sub(self, other: Interval) -> Interval {
+        self.add(other.neg())
+    }
+
+    fn mul(self, other: Interval) -> Interval {
+        // 0 × ∞ is 0 here: the bound is approached, not reached
+        let mul = |a: f64, b: f64| if a == 0.0 || b == 0.0 { 0.0 } else { a * b };
+        let corners = [
+            mul(self.lo, other.lo),
+            mul(self.lo, other.hi),
+            mul(self.hi, other.lo),
+            mul(self.hi, other.hi),
+        ];
+        Interval {
+            nonzero: self.nonzero && other.nonzero,
+            ..Interval::new(
+                corners.iter().copied().fold(f64::INFINITY, f64::min),
+                corners.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+                self.int && other.int,
+            )
+        }
+    }
+
+    fn div(self, other: Interval) -> Interval {
+        if other.lo <= 0.0 && other.hi >= 0.0 {
+            return Interval::TOP;
+        }
+        let corners = [
+            self.lo / other.lo,
+            self.lo / other.hi,
+            self.hi / other.lo,
+            self.hi / other.hi,
+        ];
+        if corners.iter().any(|c| c.is_nan()) {
+            return Interval::TOP;
+        }
+        Interval {
+            nonzero: self.nonzero,
+            ..Interval::new(
+                corners.iter().copied().fold(f64::INFINITY, f64::min),
+                corners.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+                false,
+            )
+        }
+    }
+
+    /// `%` takes the sign of the dividend and is smaller than the divisor.
+    fn rem(self, other: Interval) -> Interval {
+        let bound = other.lo.abs().max(other.hi.abs());
+        Interval::new(
+            self.lo.max(-bound).min(0.0),
+            self.hi.min(bound).max(0.0),
+            self.int && other.int,
+        )
+    }
+
+    fn abs(self) -> Interval {
+        if self.lo >= 0.0 {
+            self
+        } else if self.hi <= 0.0 {
+            self.neg()
+        } else {
+            Interval {
+                nonzero: self.nonzero,
+                ..Interval::new(0.0, (-self.lo).max(self.hi), self.int)
+            }
+        }
+    }
+
+    fn map_monotone(self, f: impl Fn(f64) -> f64, int: bool) -> Interval {
+        Interval::new(f(self.lo), f(self.hi), int)
+    }
+}
+
+impl fmt::Display for Interval {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.lo.is_finite(), self.hi.is_finite()) {
+            (true, true) => write!(f, "[{}, {}]", self.lo, self.hi),
+            (true, false) => write!(f, "[{}, ∞)", self.lo),
+            (false, true) => write!(f, "(-∞, {}]", self.hi),
+            (false, false) => write!(f, "(-∞, ∞)"),
+        }
+    }
+}
+
+// ══════════════════════════════════════════════════════════════════════════════
+// Values
+// ══════════════════════════════════════════════════════════════════════════════
+
+/// What is known about a value.
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    Number(Interval),
+    /// A list, with the interval of its length and of its elements (`TOP`
+    /// unless they are known numbers).
+    List {
+        length: Interval,
+        items: Interval,
+    },
+    Other,
+}
+
+impl Value {
+    const LIST: Value = Value::List {
+        length: Interval::LENGTH,
+        items: Interval::TOP,
+    };
+
+    /// The most that can be assumed about a value of type `ann`.
+    fn of_type(ann: &TypeAnnotation) -> Value {
+        match ann.kind {
+            TypeKind::Number => Value::Number(Interval::TOP),
+            TypeKind::List(_) => Value::LIST,
+            _ => Value::Other,
+        }
+    }
+
+    fn of_stdlib_type(ty: &Type) -> Value {
+        match ty {
+            Type::Number => Value::Number(Interval::TOP),
+            Type::List(_) => Value::LIST,
+            _ => Value::Other,
+        }
+    }
+
+    fn number(&self) -> Interval {
+        match self {
+            Value::Number(interval) => *interval,
+            _ => Interval::TOP,
+        }
+    }
+
+    fn length(&self) -> Interval {
+        match self {
+            Value::List { length, .. } => *length,
+            _ => Interval::LENGTH,
+        }
+    }
+
+    fn items(&self) -> Interval {
+        match self {
+            Value::List { items, .. } => *items,
+            _ => Interval::TOP,
+        }
+    }
+
+    /// One element of this list.
+    fn item(&self) -> Value {
+        match self {
+            Value::List { items, .. } if !items.is_top() => Value::Number(*items),
+            _ => Value::Other,
+        }
+    }
+
+    fn list(length: Interval, items: Interval) -> Value {
+        Value::List {
+            length: length.meet(Interval::LENGTH).unwrap_or(Interval::LENGTH),
+            items,
+        }
+    }
+
+    fn join(&self, other: &Value) -> Value {
+        match (self, other) {
+            (Value::Number(a), Value::Number(b)) => Value::Number(a.join(*b)),
+            (
+                Value::List { length, items },
+                Value::List {
+                    length: l2,
+                    items: i2,
+                },
+            ) => Value::List {
+                length: length.join(*l2),
+                items: items.join(*i2),
+            },
+            _ => Value::Other,
+        }
+    }
+
+    fn widen(&self, newer: &Value) -> Value {
+        match (self, newer) {
+            (Value::Number(a), Value::Number(b)) => Value::Number(a.widen(*b)),
+            (
+                Value::List { length, items },
+                Value::List {
+                    length: l2,
+                    items: i2,
+                },
+            ) => Value::List {
+                length: length.widen(*l2),
+                items: items.widen(*i2),
+            },
+            _ => Value::Other,
+        }
+    }
+}
+
+/// The value of each state (and derived) field.
+type Fields = HashMap<String, Value>;
+
+fn join_fields(a: &Fields, b: &Fields) -> Fields {
+    a.iter()
+        .filter_map(|(name, value)| Some((name.clone(), value.join(b.get(name)?))))
+        .collect()
+}
+
+// ══════════════════════════════════════════════════════════════════════════════
+// Environments
+// ══════════════════════════════════════════════════════════════════════════════
+
+#[derive(Debug, Clone, PartialEq)]
+struct Env {
+    /// Scope 0 holds state and derived fields, later scopes locals.
+    scopes: Vec<Fields>,
+    /// `(index, list)`: the number `index` is below `list.length(list)`.
+    below_length: BTreeSet<(String, String)>,
+    /// No execution gets here (after `return`, or under an impossible guard).
+    unreachable: bool,
+}
+
+impl Env {
+    fn new(fields: Fields) -> Env {
+        Env {
+            scopes: vec![fields],
+            below_length: BTreeSet::new(),
+            unreachable: false,
+        }
+    }
+
+    fn lookup(&self, name: &str) -> Option<&Value> {
+        self.scopes.iter().rev().find_map(|scope| scope.get(name))
+    }
+
+    fn lookup_mut(&mut self, name: &str) -> Option<&mut Value> {
+        self.scopes
+            .iter_mut()
+            .rev()
+            .find_map(|scope| scope.get_mut(name))
+    }
+
+    fn define(&mut self, name: &str, value: Value) {
+        self.forget(name);
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.to_string(), value);
+        }
+    }
+
+    /// Drop the facts about `name`, which now holds a different value.
+    fn forget(&mut self, name: &str) {
+        self.below_length
+            .retain(|(index, list)| index != name && list != name);
+    }
+
+    fn join(&self, other: &Env) -> Env {
+        if self.unreachable {
+            return other.clone();
+        }
+        if other.unreachable {
+            return self.clone();
+        }
+        Env {
+            scopes: self
+                .scopes
+                .iter()
+                .zip(&other.scopes)
+                .map(|(a, b)| join_fields(a, b))
+                .collect(),
+            below_length: self
+                .below_length
+                .intersection(&other.below_length)
+                .cloned()
+                .collect(),
+            unreachable: false,
+        }
+    }
+
+    fn widen(&self, newer: &Env) -> Env {
+        if self.unreachable || newer.unreachable {
+            return self.join(newer);
+        }
+        Env {
+            scopes: self
+                .scopes
+                .iter()
+                .zip(&newer.scopes)
+                .map(|(a, b)| {
+                    a.iter()
+                        .filter_map(|(name, value)| Some((name.clone(), value.widen(b.get(name)?))))
+                        .collect()
+                })
+                .collect(),
+            below_length: self
+                .below_length
+                .intersection(&newer.below_length)
+                .cloned()
+                .collect(),
+            unreachable: false,
+        }
+    }
+}
+
+/// What a comparison can refine.
+enum Place {
+    /// A number held in a local, state or derived field.
+    Var(String),
+    /// The length of a list held in one.
+    Length(String),
+}
+
+// ══════════════════════════════════════════════════════════════════════════════
+// Hazards
+// ══════════════════════════════════════════════════════════════════════════════
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HazardKind {
+    /// `/` or `%` by a number that may be zero.
+    DivisionByZero,
+    /// A math function outside its domain (`math.sqrt` of a negative).
+    MathDomain,
+    /// A list index that may be negative or past the end.
+    IndexOutOfBounds,
+}
+
+/// An operation the analysis cannot prove safe.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hazard {
+    pub kind: HazardKind,
+    pub message: String,
+    /// The divisor, argument or index.
+    pub span: Span,
+    pub suggestion: String,
+}
+
+/// Find the divisions, math calls and list indexes of `program` that may
+/// fail.  Each span is reported once, in the order found.
+pub fn analyze(program: &Program) -> Vec<Hazard> {
+    let mut analyzer = Analyzer {
+        body: &program.space.body,
+        stdlib: StdlibRegistry::new(),
+        env: Env::new(Fields::new()),
+        returns: None,
+        report: false,
+        reported: HashSet::new(),
+        hazards: Vec::new(),
+    };
+    analyzer.run();
+    analyzer.hazards
+}
+
+// ══════════════════════════════════════════════════════════════════════════════
+// Analyzer
+// ══════════════════════════════════════════════════════════════════════════════
+
+struct Analyzer<'a> {
+    body: &'a SpaceBody,
+    stdlib: StdlibRegistry,
+    env: Env,
+    /// State fields at each `return` of the current entry point.
+    returns: Option<Fields>,
+    /// Whether hazards are reported — off while iterating to a fixpoint.
+    report: bool,
+    reported: HashSet<Span>,
+    hazards: Vec<Hazard>,
+}
+
+impl<'a> Analyzer<'a> {
+    fn run(&mut self) {
+        let state = self.state_fixpoint();
+        self.report = true;
+        let body = self.body;
+        for action in &body.actions {
+            self.run_entry(&state, &action.params, &action.body);
+        }
+        if let Some(update) = &body.update {
+            self.run_entry(&state, std::slice::from_ref(&update.param), &update.body);
+        }
+        if let Some(handle_event) = &body.handle_event {
+            self.run_entry(
+                &state,
+                std::slice::from_ref(&handle_event.param),
+                &handle_event.body,
+            );
+        }
+        for view in &body.views {
+            self.entry_env(&state);
+            self.env.scopes.push(Fields::new());
+            for param in &view.params {
+                self.env
+                    .define(&param.name.name, Value::of_type(&param.type_ann));
+            }
+            self.ui_block(&view.body);
+        }
+        // Derived fields, in case no entry point analyzed them
+        self.entry_env(&state);
+    }
+
+    fn hazard(&mut self, kind: HazardKind, message: String, span: Span, suggestion: &str) {
+        if self.report && !self.env.unreachable && self.reported.insert(span) {
+            self.hazards.push(Hazard {
+                kind,
+                message,
+                span,
+                suggestion: suggestion.to_string(),
+            });
+        }
+    }
+
+    // ── State ─────────────────────────────────────────────────────────────
+
+    /// The values state fields can hold between entry points.
+    fn state_fixpoint(&mut self) -> Fields {
+        let body = self.body;
+        self.env = Env::new(Fields::new());
+        let mut initial = Fields::new();
+        for field in &body.state.fields {
+            let value = self.expr(&field.default);
+            initial.insert(field.name.name.clone(), value);
+        }
+        let mut state = self.assume_invariants(initial);
+
+        for round in 0..MAX_ROUNDS {
+            let mut next = state.clone();
+            for action in &body.actions {
+                let exit = self.run_entry(&state, &action.params, &action.body);
+                next = join_fields(&next, &exit);
+            }
+            if let Some(update) = &body.update {
+                let params = std::slice::from_ref(&update.param);
+                let exit = self.run_entry(&state, params, &update.body);
+                next = join_fields(&next, &exit);
+            }
+            if let Some(handle_event) = &body.handle_event {
+                let params = std::slice::from_ref(&handle_event.param);
+                let exit = self.run_entry(&state, params, &handle_event.body);
+                next = join_fields(&next, &exit);
+            }
+            if round >= WIDEN_AFTER {
+                next = state
+                    .iter()
+                    .filter_map(|(name, value)| Some((name.clone(), value.widen(next.get(name)?))))
+                    .collect();
+            }
+            if next == state {
+                return state;
+            }
+            state = next;
+        }
+
+        body.state
+            .fields
+            .iter()
+            .map(|f| (f.name.name.clone(), Value::of_type(&f.type_ann)))
+            .collect()
+    }
+
+    /// `state` narrowed by every invariant.  A state violating one is
+    /// rolled back, so it is never observed.
+    fn assume_invariants(&mut self, state: Fields) -> Fields {
+        self.env = Env::new(state.clone());
+        let body = self.body;
+        for invariant in &body.invariants {
+            self.assume(&invariant.condition, true);
+        }
+        if self.env.unreachable {
+            return state;
+        }
+        self.env.scopes.swap_remove(0)
+    }
+
+    /// Set up the environment at the start of an entry point: state
+    /// narrowed by invariants, then derived fields.
+    fn entry_env(&mut self, state: &Fields) {
+        let fields = self.assume_invariants(state.clone());
+        self.env = Env::new(fields);
+        let body = self.body;
+        if let Some(derived) = &body.derived {
+            for field in &derived.fields {
+                let value = self.expr(&field.value);
+                self.env.scopes[0].insert(field.name.name.clone(), value);
+            }
+        }
+    }
+
+    /// Analyze one action, `update` or `handleEvent`, returning the state
+    /// fields it can leave behind.
+    fn run_entry(&mut self, state: &Fields, params: &'a [Param], body: &'a Block) -> Fields {
+        self.entry_env(state);
+        self.env.scopes.push(Fields::new());
+        for param in params {
+            self.env
+                .define(&param.name.name, Value::of_type(&param.type_ann));
+        }
+        self.returns = None;
+        self.block(body);
+
+        let mut exit = self.env.clone();
+        exit.scopes.truncate(1);
+        let mut fields = if exit.unreachable {
+            None
+        } else {
+            Some(exit.scopes.swap_remove(0))
+        };
+        if let Some(returned) = self.returns.take() {
+            fields = Some(match fields {
+                Some(fields) => join_fields(&fields, &returned),
+                None => returned,
+            });
+        }
+        let Some(fields) = fields else {
+            return state.clone();
+        };
+        let exit = self.assume_invariants(fields);
+        state
+            .keys()
+            .filter_map(|name| Some((name.clone(), exit.get(name)?.clone())))
+            .collect()
+    }
+
+    // ── Guards ────────────────────────────────────────────────────────────
+
+    /// Narrow the environment to where `condition` evaluates to `truth`.
+    fn assume(&mut self, condition: &'a Expr, truth: bool) {
+        if self.env.unreachable {
+            return;
+        }
+        let report = std::mem::replace(&mut self.report, false);
+        self.assume_inner(condition, truth);
+        self.report = report;
+    }
+
+    fn assume_inner(&mut self, condition: &'a Expr, truth: bool) {
+        match &condition.kind {
+            ExprKind::Paren(inner) => self.assume_inner(inner, truth),
+            ExprKind::BoolLit(value) if *value != truth => self.env.unreachable = true,
+            ExprKind::Unary {
+                op: UnaryOp::Not,
+                operand,
+            } => self.assume_inner(operand, !truth),
+            ExprKind::Binary { left, op, right } => match (op, truth) {
+                (BinOp::And, true) | (BinOp::Or, false) => {
+                    self.assume_inner(left, truth);
+                    self.assume_inner(right, truth);
+                }
+                (BinOp::And, false) | (BinOp::Or, true) => {
+                    // Either the left side decides, or it doesn't and the right does
+                    let before = self.env.clone();
+                    self.assume_inner(left, truth);
+                    let decided = std::mem::replace(&mut self.env, before);
+                    self.assume_inner(left, !truth);
+                    self.assume_inner(right, truth);
+                    self.env = decided.join(&self.env);
+                }
+                (_, _) => {
+                    let op = if truth { *op } else { negate(*op) };
+                    self.assume_comparison(left, op, right);
+                }
+            },
+            _ => {}
+        }
+    }
+
+    /// Narrow both sides of `left op right`, assumed true.
+    fn assume_comparison(&mut self, left: &'a Expr, op: BinOp, right: &'a Expr) {
+        let left_value = self.expr(left).number();
+        let right_value = self.expr(right).number();
+        let left_place = self.place(left);
+        let right_place = self.place(right);
+
+        if let (Some(Place::Var(index)), Some(Place::Length(list))) = (&left_place, &right_place) {
+            if op == BinOp::Less {
+                self.env.below_length.insert((index.clone(), list.clone()));
+            }
+        }
+        if let (Some(Place::Length(list)), Some(Place::Var(index))) = (&left_place, &right_place) {
+            if op == BinOp::Greater {
+                self.env.below_length.insert((index.clone(), list.clone()));
+            }
+        }
+
+        if let Some(place) = left_place {
+            let int = self.place_is_int(&place);
+            if let Some(bound) = constraint(op, right_value, int) {
+                self.refine(&place, bound);
+            }
+        }
+        if let Some(place) = right_place {
+            let int = self.place_is_int(&place);
+            if let Some(bound) = constraint(flip(op), left_value, int) {
+                self.refine(&place, bound);
+            }
+        }
+    }
+
+    fn place(&self, expr: &Expr) -> Option<Place> {
+        let list_name = |expr: &Expr| match &expr.kind {
+            ExprKind::Identifier(name) => Some(name.clone()),
+            _ => None,
+        };
+        match &expr.kind {
+            ExprKind::Paren(inner) => self.place(inner),
+            ExprKind::Identifier(name) => match self.env.lookup(name) {
+                Some(Value::Number(_)) => Some(Place::Var(name.clone())),
+                _ => None,
+            },
+            ExprKind::QualifiedCall {
+                module,
+                function,
+                args,
+            } if module.name == "list" && function.name == "length" && args.len() == 1 => {
+                list_name(&args[0]).map(Place::Length)
+            }
+            ExprKind::MethodCall {
+                object,
+                method,
+                args,
+            } if method.name == "length" && args.is_empty() => {
+                let name = list_name(object)?;
+                matches!(self.env.lookup(&name), Some(Value::List { .. }))
+                    .then_some(Place::Length(name))
+            }
+            _ => None,
+        }
+    }
+
+    fn place_is_int(&self, place: &Place) -> bool {
+        match place {
+            Place::Var(name) => self.env.lookup(name).is_some_and(|v| v.number().int),
+            Place::Length(_) => true,
+        }
+    }
+
+    fn refine(&mut self, place: &Place, bound: Interval) {
+        let (name, is_length) = match place {
+            Place::Var(name) => (name, false),
+            Place::Length(name) => (name, true),
+        };
+        let Some(value) = self.env.lookup_mut(name) else {
+            return;
+        };
+        let narrowed = match value {
+            Value::Number(interval) if !is_length => interval.meet(bound).map(|i| *interval = i),
+            Value::List { length, .. } if is_length => length.meet(bound).map(|l| *length = l),
+            _ => Some(()),
+        };
+        if narrowed.is_none() {
+            self.env.unreachable = true;
+        }
+    }
+
+    // ── Views ─────────────────────────────────────────────────────────────
+
+    fn ui_block(&mut self, block: &'a UIBlock) {
+        self.env.scopes.push(Fields::new());
+        for element in &block.elements {
+            match element {
+                UIElement::Component(comp) => {
+                    for prop in &comp.props {
+                        self.expr(&prop.value);
+                    }
+                    if let Some(children) = &comp.children {
+                        self.ui_block(children);
+                    }
+                }
+                UIElement::Let(binding) => self.let_binding(binding),
+                UIElement::If(ui_if) => self.ui_if(ui_if),
+                UIElement::For(ui_for) => {
+                    let before = self.env.clone();
+                    let list = self.expr(&ui_for.iterable);
+                    self.env.scopes.push(Fields::new());
+                    self.bind_loop(&ui_for.item, ui_for.index.as_ref(), &ui_for.iterable, &list);
+                    self.ui_block(&ui_for.body);
+                    self.env = before;
+                }
+            }
+        }
+        self.env.scopes.pop();
+    }
+
+    fn ui_if(&mut self, ui_if: &'a UIIf) {
+        self.expr(&ui_if.condition);
+        let before = self.env.clone();
+        self.assume(&ui_if.condition, true);
+        self.ui_block(&ui_if.then_block);
+        self.env = before.clone();
+        self.assume(&ui_if.condition, false);
+        match &ui_if.else_block {
+            Some(UIElse::ElseIf(inner)) => self.ui_if(inner),
+            Some(UIElse::Block(block)) => self.ui_block(block),
+            None => {}
+        }
+        self.env = before;
+    }
+
+    // ── Statements ────────────────────────────────────────────────────────
+
+    /// Analyze a block, returning the value of its last expression.
+    fn block(&mut self, block: &'a Block) -> Value {
+        self.env.scopes.push(Fields::new());
+        let mut value = Value::Other;
+        for stmt in &block.stmts {
+            value = self.stmt(stmt);
+        }
+        self.env.scopes.pop();
+        value
+    }
+
+    fn stmt(&mut self, stmt: &'a Stmt) -> Value {
+        match stmt {
+            Stmt::Set(set) => {
+                let value = self.expr(&set.value);
+                let root = &set.target[0].name;
+                let value = if set.target.len() == 1 {
+                    value
+                } else {
+                    Value::Other
+                };
+                self.env.forget(root);
+                if let Some(field) = self.env.scopes[0].get_mut(root) {
+                    *field = value;
+                }
+                Value::Other
+            }
+            Stmt::Let(binding) => {
+                self.let_binding(binding);
+                Value::Other
+            }
+            Stmt::If(if_expr) => self.if_expr(if_expr),
+            Stmt::For(for_expr) => self.for_expr(for_expr),
+            Stmt::Match(match_expr) => self.match_expr(match_expr),
+            Stmt::Return(_) => {
+                if !self.env.unreachable {
+                    let state = &self.env.scopes[0];
+                    self.returns = Some(match &self.returns {
+                        Some(returned) => join_fields(returned, state),
+                        None => state.clone(),
+                    });
+                }
+                self.env.unreachable = true;
+                Value::Other
+            }
+            Stmt::Assert(assert) => {
+                self.expr(&assert.condition);
+                self.assume(&assert.condition, true);
+                Value::Other
+            }
+            Stmt::Expr(expr_stmt) => self.expr(&expr_stmt.expr),
+        }
+    }
+
+    fn let_binding(&mut self, binding: &'a LetBinding) {
+        let value = self.expr(&binding.value);
+        if let Some(name) = &binding.name {
+            self.env.define(&name.name, value);
+        }
+    }
+
+    fn if_expr(&mut self, if_expr: &'a IfExpr) -> Value {
+        self.expr(&if_expr.condition);
+        let before = self.env.clone();
+        self.assume(&if_expr.condition, true);
+        let then_value = self.block(&if_expr.then_block);
+        let then_env = std::mem::replace(&mut self.env, before);
+        self.assume(&if_expr.condition, false);
+        let else_value = match &if_expr.else_branch {
+            Some(ElseBranch::ElseIf(inner)) => self.if_expr(inner),
+            Some(ElseBranch::Block(block)) => self.block(block),
+            None => Value::Other,
+        };
+        let value = match (then_env.unreachable, self.env.unreachable) {
+            (true, false) => else_value,
+            (false, true) => then_value,
+            _ => then_value.join(&else_value),
+        };
+        self.env = then_env.join(&self.env);
+        value
+    }
+
+    fn bind_loop(
+        &mut self,
+        item: &'a Ident,
+        index: Option<&'a Ident>,
+        iterable: &'a Expr,
+        list: &Value,
+    ) {
+        self.env.define(&item.name, list.item());
+        if let Some(index) = index {
+            let length = list.length();
+            let bound = Interval::new(0.0, (length.hi - 1.0).max(0.0), true);
+            self.env.define(&index.name, Value::Number(bound));
+            if let ExprKind::Identifier(list) = &iterable.kind {
+                self.env
+                    .below_length
+                    .insert((index.name.clone(), list.clone()));
+            }
+        }
+    }
+
+    /// A loop runs its body any number of times: iterate to a fixpoint of
+    /// the state it sets, then analyze the body once more, reporting.
+    fn for_expr(&mut self, for_expr: &'a ForExpr) -> Value {
+        let list = self.expr(&for_expr.iterable);
+        let report = std::mem::replace(&mut self.report, false);
+        let mut head = self.env.clone();
+        for round in 0..MAX_ROUNDS {
+            self.env = head.clone();
+            self.loop_body(for_expr, &list);
+            let joined = head.join(&self.env);
+            let next = if round >= WIDEN_AFTER {
+                head.widen(&joined)
+            } else {
+                joined
+            };
+            if next == head {
+                break;
+            }
+            head = next;
+        }
+        self.report = report;
+        self.env = head.clone();
+        self.loop_body(for_expr, &list);
+        self.env = head.join(&self.env);
+        Value::Other
+    }
+
+    fn loop_body(&mut self, for_expr: &'a ForExpr, list: &Value) {
+        self.env.scopes.push(Fields::new());
+        self.bind_loop(
+            &for_expr.item,
+            for_expr.index.as_ref(),
+            &for_expr.iterable,
+            list,
+        );
+        self.block(&for_expr.body);
+        self.env.scopes.pop();
+    }
+
+    fn match_expr(&mut self, match_expr: &'a MatchExpr) -> Value {
+        self.expr(&match_expr.subject);
+        let before = self.env.clone();
+        let mut exit: Option<Env> = None;
+        let mut value: Option<Value> = None;
+        for arm in &match_expr.arms {
+            self.env = before.clone();
+            self.env.scopes.push(Fields::new());
+            if let Pattern::Variant { bindings, .. } = &arm.pattern {
+                for binding in bindings {
+                    self.env.define(&binding.name, Value::Other);
+                }
+            }
+            let arm_value = match &arm.body {
+                MatchArmBody::Expr(expr) => self.expr(expr),
+                MatchArmBody::Block(block) => self.block(block),
+            };
+            self.env.scopes.pop();
+            if !self.env.unreachable {
+                value = Some(match value {
+                    Some(value) => value.join(&arm_value),
+                    None => arm_value,
+                });
+            }
+            exit = Some(match exit {
+                Some(exit) => exit.join(&self.env),
+                None => self.env.clone(),
+            });
+        }
+        self.env = exit.unwrap_or(before);
+        value.unwrap_or(Value::Other)
+    }
+
+    // ── Expressions ───────────────────────────────────────────────────────
+
+    fn expr(&mut self, expr: &'a Expr) -> Value {
+        match &expr.kind {
+            ExprKind::NumberLit(n) => Value::Number(Interval::point(*n)),
+            ExprKind::StringLit(_) | ExprKind::BoolLit(_) | ExprKind::NilLit => Value::Other,
+            ExprKind::StringInterpolation(parts) => {
+                for part in parts {
+                    if let StringPart::Expr(inner) = part {
+                        self.expr(inner);
+                    }
+                }
+                Value::Other
+            }
+            ExprKind::ListLit(items) => {
+                let values: Vec<_> = items.iter().map(|item| self.expr(item)).collect();
+                let n = values.len() as f64;
+                Value::list(Interval::point(n), numbers_hull(&values))
+            }
+            ExprKind::RecordLit(entries) => {
+                for entry in entries {
+                    match entry {
+                        RecordEntry::Field { value, .. } => self.expr(value),
+                        RecordEntry::Spread(inner) => self.expr(inner),
+                    };
+                }
+                Value::Other
+            }
+            ExprKind::Identifier(name) => self.env.lookup(name).cloned().unwrap_or(Value::Other),
+            ExprKind::Call { args, .. } => {
+                for arg in args {
+                    self.expr(arg);
+                }
+                Value::Other
+            }
+            ExprKind::QualifiedCall {
+                module,
+                function,
+                args,
+            } => {
+                let mut values: Vec<Value> = Vec::new();
+                for arg in args {
+                    let list = match module.name.as_str() {
+                        "list" => values.first().cloned(),
+                        _ => None,
+                    };
+                    values.push(self.arg(arg, list.as_ref()));
+                }
+                let exprs: Vec<_> = args.iter().collect();
+                self.call(&module.name, &function.name, &exprs, &values)
+            }
+            ExprKind::FieldAccess { object, .. } => {
+                self.expr(object);
+                Value::Other
+            }
+            ExprKind::MethodCall {
+                object,
+                method,
+                args,
+            } => {
+                let receiver = self.expr(object);
+                let module = match receiver {
+                    Value::List { .. } => "list",
+                    Value::Number(_) => "math",
+                    Value::Other => "string",
+                };
+                let mut values = vec![receiver.clone()];
+                values.extend(args.iter().map(|arg| self.arg(arg, Some(&receiver))));
+                let mut exprs = vec![&**object];
+                exprs.extend(args.iter());
+                self.call(module, &method.name, &exprs, &values)
+            }
+            ExprKind::Binary { left, op, right } => self.binary(left, *op, right),
+            ExprKind::Unary { op, operand } => {
+                let value = self.expr(operand);
+                match op {
+                    UnaryOp::Neg => Value::Number(value.number().neg()),
+                    UnaryOp::Not => Value::Other,
+                }
+            }
+            ExprKind::ResultUnwrap(inner) => {
+                self.expr(inner);
+                Value::Other
+            }
+            ExprKind::NilCoalesce { left, right } => {
+                self.expr(left);
+                self.expr(right)
+            }
+            ExprKind::If(if_expr) => self.if_expr(if_expr),
+            ExprKind::For(for_expr) => self.for_expr(for_expr),
+            ExprKind::Match(match_expr) => self.match_expr(match_expr),
+            ExprKind::Lambda(lambda) => {
+                self.lambda(lambda, None);
+                Value::Other
+            }
+            ExprKind::Paren(inner) => self.expr(inner),
+        }
+    }
+
+    /// A call argument; a lambda is analyzed with its first parameter an
+    /// element of `list` (the first argument of a list function).
+    fn arg(&mut self, arg: &'a Expr, list: Option<&Value>) -> Value {
+        match &arg.kind {
+            ExprKind::Lambda(lambda) => {
+                self.lambda(lambda, list.map(Value::item));
+                Value::Other
+            }
+            _ => self.expr(arg),
+        }
+    }
+
+    fn lambda(&mut self, lambda: &'a LambdaExpr, first: Option<Value>) {
+        let before = self.env.clone();
+        self.env.scopes.push(Fields::new());
+        for (i, param) in lambda.params.iter().enumerate() {
+            let value = match (&first, &param.type_ann) {
+                (Some(first), _) if i == 0 => first.clone(),
+                (_, Some(ann)) => Value::of_type(ann),
+                _ => Value::Other,
+            };
+            self.env.define(&param.name.name, value);
+        }
+        self.block(&lambda.body);
+        self.env = before;
+    }
+
+    fn binary(&mut self, left: &'a Expr, op: BinOp, right: &'a Expr) -> Value {
+        let left_value = self.expr(left);
+        // `and`/`or` only evaluate their right side when the left allows
+        if matches!(op, BinOp::And | BinOp::Or) {
+            let before = self.env.clone();
+            self.assume(left, op == BinOp::And);
+            self.expr(right);
+            self.env = before;
+            return Value::Other;
+        }
+        let right_value = self.expr(right);
+        let (a, b) = (left_value.number(), right_value.number());
+        match op {
+            BinOp::Add => match (&left_value, &right_value) {
+                (Value::Number(_), Value::Number(_)) => Value::Number(a.add(b)),
+                _ => Value::Other,
+            },
+            BinOp::Sub => Value::Number(a.sub(b)),
+            // `x * x` is a square, never negative
+            BinOp::Mul if same_variable(left, right) => Value::Number(a.abs().mul(a.abs())),
+            BinOp::Mul => Value::Number(a.mul(b)),
+            BinOp::Div | BinOp::Mod => {
+                if b.may_be_zero() {
+                    let operation = if op == BinOp::Div {
+                        "division"
+                    } else {
+                        "modulo"
+                    };
+                    self.hazard(
+                        HazardKind::DivisionByZero,
+                        format!("possible {operation} by zero: the divisor {}", describe(b)),
+                        right.span,
+                        "Check the divisor first (`if d != 0`), or rule out zero with an invariant",
+                    );
+                }
+                Value::Number(if op == BinOp::Div { a.div(b) } else { a.rem(b) })
+            }
+            _ => Value::Other,
+        }
+    }
+
+    /// A stdlib call: check indexes and domains, and compute the result.
+    fn call(
+        &mut self,
+        module: &str,
+        function: &str,
+        exprs: &[&'a Expr],
+        values: &[Value],
+    ) -> Value {
+        let arg = |i: usize| values.get(i).cloned().unwrap_or(Value::Other);
+        let num = |i: usize| arg(i).number();
+        match (module, function) {
+            ("math", "sqrt") => {
+                let x = num(0);
+                if x.lo < 0.0 {
+                    self.hazard(
+                        HazardKind::MathDomain,
+                        format!("math.sqrt argument may be negative: it {}", describe(x)),
+                        exprs[0].span,
+                        "Check the argument first (`if x >= 0`), or take `math.abs` of it",
+                    );
+                }
+                let x = x
+                    .meet(Interval::new(0.0, f64::INFINITY, false))
+                    .unwrap_or(Interval::TOP);
+                Value::Number(x.map_monotone(f64::sqrt, false))
+            }
+            ("math", "abs") => Value::Number(num(0).abs()),
+            ("math", "min") => {
+                let (a, b) = (num(0), num(1));
+                Value::Number(Interval::new(
+                    a.lo.min(b.lo),
+                    a.hi.min(b.hi),
+                    a.int && b.int,
+                ))
+            }
+            ("math", "max") => {
+                let (a, b) = (num(0), num(1));
+                Value::Number(Interval::new(
+                    a.lo.max(b.lo),
+                    a.hi.max(b.hi),
+                    a.int && b.int,
+                ))
+            }
+            ("math", "floor") => Value::Number(num(0).map_monotone(f64::floor, true)),
+            ("math", "ceil") => Value::Number(num(0).map_monotone(f64::ceil, true)),
+            ("math", "round") => Value::Number(num(0).map_monotone(f64::round, true)),
+            ("math", "clamp") => {
+                let (x, min, max) = (num(0), num(1), num(2));
+                Value::Number(Interval::new(
+                    x.lo.max(min.lo).min(max.hi),
+                    x.hi.min(max.hi).max(min.lo),
+                    x.int && min.int && max.int,
+                ))
+            }
+            ("string", "length") => Value::Number(Interval::LENGTH),
+            ("string", "index_of") => Value::Number(Interval::new(-1.0, f64::INFINITY, true)),
+            ("list", _) => self.list_call(function, exprs, values),
+            _ => match self.stdlib.get(module, function) {
+                Some(sig) => Value::of_stdlib_type(&sig.ret),
+                None => Value::Other,
+            },
+        }
+    }
+
+    fn list_call(&mut self, function: &str, exprs: &[&'a Expr], values: &[Value]) -> Value {
+        let arg = |i: usize| values.get(i).cloned().unwrap_or(Value::Other);
+        let list = arg(0);
+        let (length, items) = (list.length(), list.items());
+        let one = Interval::point(1.0);
+        let up_to = |hi: f64| Interval::new(0.0, hi, true);
+        if matches!(function, "get" | "remove" | "update" | "set") {
+            self.check_index(exprs, values);
+        }
+        match function {
+            "empty" => Value::list(Interval::point(0.0), Interval::TOP),
+            "of" => Value::list(Interval::point(values.len() as f64), numbers_hull(values)),
+            "repeat" => Value::list(arg(1).number(), arg(0).number()),
+            "range" => {
+                let (start, end) = (arg(0).number(), arg(1).number());
+                let count = |n: f64| n.max(0.0).ceil();
+                Value::list(
+                    Interval::new(count(end.lo - start.hi), count(end.hi - start.lo), true),
+                    Interval::new(start.lo, end.hi, start.int),
+                )
+            }
+            "length" => Value::Number(length),
+            "index_of" | "find_index" => Value::Number(Interval::new(-1.0, length.hi - 1.0, true)),
+            "count" => Value::Number(up_to(length.hi)),
+            "append" | "prepend" | "insert" => {
+                let value = if function == "insert" { arg(2) } else { arg(1) };
+                Value::list(length.add(one), join_item(items, &value))
+            }
+            "remove" => Value::list(Interval::new(length.lo - 1.0, length.hi, true), items),
+            "update" | "set" => Value::list(length, join_item(items, &arg(2))),
+            "slice" | "filter" | "unique" | "take" | "drop" => Value::list(up_to(length.hi), items),
+            "reverse" | "sort" => Value::list(length, items),
+            "map" => Value::list(length, Interval::TOP),
+            "concat" => Value::list(length.add(arg(1).length()), items.join(arg(1).items())),
+            "zip" => Value::list(up_to(length.hi.min(arg(1).length().hi)), Interval::TOP),
+            _ => match self.stdlib.get("list", function) {
+                Some(sig) => Value::of_stdlib_type(&sig.ret),
+                None => Value::Other,
+            },
+        }
+    }
+
+    /// `list.get(items, i)` and friends: `i` must be in `0..length`.
+    fn check_index(&mut self, exprs: &[&'a Expr], values: &[Value]) {
+        let (Some(list_expr), Some(index_expr)) = (exprs.first(), exprs.get(1)) else {
+            return;
+        };
+        let length = values[0].length();
+        let index = values.get(1).map(Value::number).unwrap_or(Interval::TOP);
+        let known_below = match (&index_expr.kind, &list_expr.kind) {
+            (ExprKind::Identifier(index), ExprKind::Identifier(list)) => self
+                .env
+                .below_length
+                .contains(&(index.clone(), list.clone())),
+            _ => false,
+        };
+        if index.lo >= 0.0 && (known_below || index.hi <= length.lo - 1.0) {
+            return;
+        }
+        self.hazard(
+            HazardKind::IndexOutOfBounds,
+            format!(
+                "list index may be out of bounds: the index {}, the length {}",
+                describe(index),
+                describe(length)
+            ),
+            index_expr.span,
+            "Check the index against `list.length` first, or loop with `for item, i in items`",
+        );
+    }
+}
+
+// ══════════════════════════════════════════════════════════════════════════════
+// Helpers
+// ══════════════════════════════════════════════════════════════════════════════
+
+/// `is in [0, 10]`, or `is unconstrained`.
+fn describe(interval: Interval) -> String {
+    if interval.is_top() {
+        "is unconstrained".to_string()
+    } else {
+        format!("is in {interval}")
+    }
+}
+
+/// The hull of `values` if all are numbers, else `TOP`.
+fn numbers_hull(values: &[Value]) -> Interval {
+    let mut hull: Option<Interval> = None;
+    for value in values {
+        let Value::Number(n) = value else {
+            return Interval::TOP;
+        };
+        hull = Some(hull.map_or(*n, |h| h.join(*n)));
+    }
+    hull.unwrap_or(Interval::TOP)
+}
+
+fn same_variable(left: &Expr, right: &Expr) -> bool {
+    match (&left.kind, &right.kind) {
+        (ExprKind::Identifier(a), ExprKind::Identifier(b)) => a == b,
+        _ => false,
+    }
+}
+
+fn join_item(items: Interval, value: &Value) -> Interval {
+    match value {
+        Value::Number(n) => items.join(*n),
+        _ => Interval::TOP,
+    }
+}
+
+/// `a op b` is false exactly when `a negate(op) b` is true.
+fn negate(op: BinOp) -> BinOp {
+    match op {
+        BinOp::Eq => BinOp::NotEq,
+        BinOp::NotEq => BinOp::Eq,
+        BinOp::Less => BinOp::GreaterEq,
+        BinOp::GreaterEq => BinOp::Less,
+        BinOp::Greater => BinOp::LessEq,
+        BinOp::LessEq => BinOp::Greater,
+        other => other,
+    }
+}
+
+/// `a op b` is `b flip(op) a`.
+fn flip(op: BinOp) -> BinOp {
+    match op {
+        BinOp::Less => BinOp::Greater,
+        BinOp::Greater => BinOp::Less,
+        BinOp::LessEq => BinOp::GreaterEq,
+        BinOp::GreaterEq => BinOp::LessEq,
+        other => other,
+    }
+}
+
+/// The numbers `x` can be when `x op other` holds, `other` in `bound`.
+fn constraint(op: BinOp, bound: Interval, int: bool) -> Option<Interval> {
+    let (lo, hi) = (f64::NEG_INFINITY, f64::INFINITY);
+    let interval = |lo: f64, hi: f64, nonzero: bool| Interval {
+        lo,
+        hi,
+        int: false,
+        nonzero,
+    };
+    match op {
+        BinOp::Less if int => Some(interval(lo, bound.hi.ceil() - 1.0, false)),
+        BinOp::Less => Some(interval(lo, bound.hi, bound.hi <= 0.0)),
+        BinOp::LessEq => Some(interval(lo, bound.hi, bound.hi < 0.0)),
+        BinOp::Greater if int => Some(interval(bound.lo.floor() + 1.0, hi, false)),
+        BinOp::Greater => Some(interval(bound.lo, hi, bound.lo >= 0.0)),
+        BinOp::GreaterEq => Some(interval(bound.lo, hi, bound.lo > 0.0)),
+        BinOp::Eq => Some(bound),
+        BinOp::NotEq if bound.lo == 0.0 && bound.hi == 0.0 => Some(Interval {
+            nonzero: true,
+            ..Interval::TOP
+        }),
+        _ => None,
+    }
+}
@@ -0,0 +1,1367 @@
+//! Static invariant preservation proofs.
+//!
+//! At runtime an action that breaks an invariant is rolled back.  For every
+//! (action, invariant) pair, [`analyze`] tries to show that cannot happen:
+//! if every invariant holds before the action runs, the invariant still
+//! holds after it.
+//!
+//! The action is executed symbolically, one path per `if` branch, `match`
+//! arm and `return`.  State fields and parameters are variables: numbers
+//! and bools directly, lists and strings through their length.  Each path
+//! yields a formula over those variables, and a small solver decides
+//!
+//! ```text
+//! invariants(before) ∧ path condition ∧ ¬invariant(after)
+//! ```
+//!
+//! The solver handles linear arithmetic, booleans and list lengths:
+//! formulas are put in disjunctive normal form, equalities are solved by
+//! substitution and inequalities by Fourier–Motzkin elimination, which also
+//! yields a model.  If no case is satisfiable the pair is
+//! [`Verdict::Proven`].  A model is a [`Verdict::Refuted`] counterexample:
+//! arguments and a state satisfying every invariant that the action leaves
+//! violating this one.
+//!
+//! Whatever the analysis does not model — stdlib calls beyond list and
+//! string lengths and `math.min`/`max`/`abs`/`clamp`, non-linear
+//! arithmetic, records, loops, which `match` arm runs — becomes an
+//! unconstrained variable.  That keeps proofs sound but makes a model that
+//! depends on one only a possibility, so the pair is [`Verdict::Unknown`].
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+
+use pepl_types::ast::*;
+use pepl_types::Span;
+use serde::{Deserialize, Serialize};
+
+use crate::gas_bound::set_targets;
+
+/// Paths explored per action before giving up.
+const MAX_PATHS: usize = 256;
+
+/// Cases of one proof obligation before giving up.
+const MAX_CONJUNCTS: usize = 1024;
+
+/// Inequalities kept while eliminating one variable before giving up.
+const MAX_ROWS: usize = 2048;
+
+/// Tolerance of the solver's floating-point comparisons.
+const EPSILON: f64 = 1e-9;
+
+// ══════════════════════════════════════════════════════════════════════════════
+// Results
+// ══════════════════════════════════════════════════════════════════════════════
+
+/// Whether one action preserves one invariant.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InvariantProof {
+    pub action: String,
+    pub invariant: String,
+    pub verdict: Verdict,
+    /// Span of the action's declaration.
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum Verdict {
+    /// The action always preserves the invariant.
+    Proven,
+    /// The action can break the invariant, for example on these inputs.
+    Refuted { counterexample: Counterexample },
+    /// Neither could be shown.
+    Unknown { reason: String },
+}
+
+/// Inputs on which an action breaks an invariant, as PEPL literals.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Counterexample {
+    /// The action's arguments.
+    pub params: BTreeMap<String, String>,
+    /// State fields before the action; every invariant holds.
+    pub before: BTreeMap<String, String>,
+    /// The fields the action set, after it.
+    pub after: BTreeMap<String, String>,
+}
+
+impl fmt::Display for Counterexample {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let list = |values: &BTreeMap<String, String>| {
+            values
+                .iter()
+                .map(|(name, value)| format!("{name} = {value}"))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        let mut sections = Vec::new();
+        if !self.params.is_empty() {
+            sections.push(format!("params: {}", list(&self.params)));
+        }
+        if !self.before.is_empty() {
+            sections.push(format!("before: {}", list(&self.before)));
+        }
+        if !self.after.is_empty() {
+            sections.push(format!("after: {}", list(&self.after)));
+        }
+        f.write_str(&sections.join("; "))
+    }
+}
+
+/// Try to prove every invariant of `program` preserved by every action.
+/// Pairs are returned action by action, invariants in declaration order.
+pub fn analyze(program: &Program) -> Vec<InvariantProof> {
+    let body = &program.space.body;
+    let mut proofs = Vec::new();
+    if body.invariants.is_empty() {
+        return proofs;
+    }
+    for action in &body.actions {
+        let mut targets = HashSet::new();
+        set_targets(&action.body, &mut targets);
+
+        let mut executor = Executor::new(body);
+        let mut entry = executor.entry(&action.params);
+        let before: Vec<Formula> = body
+            .invariants
+            .iter()
+            .map(|invariant| executor.condition(&mut entry, &invariant.condition))
+            .collect();
+        let exits = executor.block(vec![entry], &action.body);
+
+        for invariant in &body.invariants {
+            let verdict = match fields_read(&invariant.condition) {
+                Some(read) if read.is_disjoint(&targets) => Verdict::Proven,
+                _ => executor.verdict(action, &before, &exits, &invariant.condition),
+            };
+            proofs.push(InvariantProof {
+                action: action.name.name.clone(),
+                invariant: invariant.name.name.clone(),
+                verdict,
+                span: action.span,
+            });
+        }
+    }
+    proofs
+}
+
+/// Identifiers `expr` reads; `None` if it contains a lambda or a block,
+/// which are not searched.
+fn fields_read(expr: &Expr) -> Option<HashSet<String>> {
+    fn walk(expr: &Expr, out: &mut HashSet<String>) -> bool {
+        match &expr.kind {
+            ExprKind::Identifier(name) => {
+                out.insert(name.clone());
+                true
+            }
+            ExprKind::Lambda(_) | ExprKind::If(_) | ExprKind::For(_) | ExprKind::Match(_) => false,
+            ExprKind::NumberLit(_)
+            | ExprKind::StringLit(_)
+            | ExprKind::BoolLit(_)
+            | ExprKind::NilLit => true,
+            ExprKind::StringInterpolation(parts) => parts.iter().all(|part| match part {
+                StringPart::Expr(inner) => walk(inner, out),
+                _ => true,
+            }),
+            ExprKind::ListLit(items) => items.iter().all(|item| walk(item, out)),
+            ExprKind::RecordLit(entries) => entries.iter().all(|entry| match entry {
+                RecordEntry::Field { value, .. } => walk(value, out),
+                RecordEntry::Spread(inner) => walk(inner, out),
+            }),
+            ExprKind::Call { args, .. } | ExprKind::QualifiedCall { args, .. } => {
+                args.iter().all(|arg| walk(arg, out))
+            }
+            ExprKind::MethodCall { object, args, .. } => {
+                walk(object, out) && args.iter().all(|arg| walk(arg, out))
+            }
+            ExprKind::FieldAccess { object, .. } => walk(object, out),
+            ExprKind::Binary { left, right, .. } | ExprKind::NilCoalesce { left, right } => {
+                walk(left, out) && walk(right, out)
+            }
+            ExprKind::Unary { operand, .. } => walk(operand, out),
+            ExprKind::ResultUnwrap(inner) | ExprKind::Paren(inner) => walk(inner, out),
+        }
+    }
+    let mut out = HashSet::new();
+    walk(expr, &mut out).then_some(out)
+}
+
+// ══════════════════════════════════════════════════════════════════════════════
+// Formulas
+// ══════════════════════════════════════════════════════════════════════════════
+
+/// `Σ coefficient × variable + constant`.
+#[derive(Debug, Clone, PartialEq, Default)]
+struct Linear {
+    terms: BTreeMap<String, f64>,
+    constant: f64,
+}
+
+impl Linear {
+    fn constant(value: f64) -> Linear {
+        Linear {
+            terms: BTreeMap::new(),
+            constant: value,
+        }
+    }
+
+    fn var(name: &str) -> Linear {
+        Linear {
+            terms: BTreeMap::from([(name.to_string(), 1.0)]),
+            constant: 0.0,
+        }
+    }
+
+    fn as_constant(&self) -> Option<f64> {
+        self.terms.is_empty().then_some(self.constant)
+    }
+
+    fn coefficient(&self, var: &str) -> f64 {
+        self.terms.get(var).copied().unwrap_or(0.0)
+    }
+
+    fn plus(&self, other: &Linear) -> Linear {
+        let mut sum = self.clone();
+        for (var, c) in &other.terms {
+            let entry = sum.terms.entry(var.clone()).or_insert(0.0);
+            *entry += c;
+            if entry.abs() < EPSILON {
+                sum.terms.remove(var);
+            }
+        }
+        sum.constant += other.constant;
+        sum
+    }
+
+    fn scale(&self, k: f64) -> Linear {
+        if k == 0.0 {
+            return Linear::constant(0.0);
+        }
+        Linear {
+            terms: self.terms.iter().map(|(v, c)| (v.clone(), c * k)).collect(),
+            constant: self.constant * k,
+        }
+    }
+
+    fn minus(&self, other: &Linear) -> Linear {
+        self.plus(&other.scale(-1.0))
+    }
+
+    /// `self` with `var` replaced by `by`.
+    fn substitute(&self, var: &str, by: &Linear) -> Linear {
+        let c = self.coefficient(var);
+        if c == 0.0 {
+            return self.clone();
+        }
+        let mut rest = self.clone();
+        rest.terms.remove(var);
+        rest.plus(&by.scale(c))
+    }
+
+    /// The value under `model`, unassigned variables being 0.
+    fn eval(&self, model: &Model) -> f64 {
+        self.terms
+            .iter()
+            .map(|(var, c)| c * model.get(var).copied().unwrap_or(0.0))
+            .sum::<f64>()
+            + self.constant
+    }
+}
+
+/// How a [`Linear`] compares with zero.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Rel {
+    Le,
+    Lt,
+    Eq,
+}
+
+impl Rel {
+    fn holds(self, value: f64) -> bool {
+        match self {
+            Rel::Le => value <= EPSILON,
+            Rel::Lt => value < -EPSILON,
+            Rel::Eq => value.abs() <= EPSILON,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Formula {
+    Const(bool),
+    /// `linear rel 0`.
+    Atom(Linear, Rel),
+    Var(String),
+    Not(Box<Formula>),
+    And(Vec<Formula>),
+    Or(Vec<Formula>),
+}
+
+fn atom(linear: Linear, rel: Rel) -> Formula {
+    match linear.as_constant() {
+        Some(value) => Formula::Const(rel.holds(value)),
+        None => Formula::Atom(linear, rel),
+    }
+}
+
+/// `left op right`, for a comparison `op`.
+fn compare(left: &Linear, op: BinOp, right: &Linear) -> Option<Formula> {
+    Some(match op {
+        BinOp::Less => atom(left.minus(right), Rel::Lt),
+        BinOp::LessEq => atom(left.minus(right), Rel::Le),
+        BinOp::Greater => atom(right.minus(left), Rel::Lt),
+        BinOp::GreaterEq => atom(right.minus(left), Rel::Le),
+        BinOp::Eq => atom(left.minus(right), Rel::Eq),
+        BinOp::NotEq => not(atom(left.minus(right), Rel::Eq)),
+        _ => return None,
+    })
+}
+
+fn not(formula: Formula) -> Formula {
+    match formula {
+        Formula::Const(value) => Formula::Const(!value),
+        Formula::Not(inner) => *inner,
+        other => Formula::Not(Box::new(other)),
+    }
+}
+
+fn and(parts: Vec<Formula>) -> Formula {
+    let mut kept = Vec::new();
+    for part in parts {
+        match part {
+            Formula::Const(true) => {}
+            Formula::Const(false) => return Formula::Const(false),
+            other => kept.push(other),
+        }
+    }
+    match kept.len() {
+        0 => Formula::Const(true),
+        1 => kept.swap_remove(0),
+        _ => Formula::And(kept),
+    }
+}
+
+fn or(parts: Vec<Formula>) -> Formula {
+    let mut kept = Vec::new();
+    for part in parts {
+        match part {
+            Formula::Const(false) => {}
+            Formula::Const(true) => return Formula::Const(true),
+            other => kept.push(other),
+        }
+    }
+    match kept.len() {
+        0 => Formula::Const(false),
+        1 => kept.swap_remove(0),
+        _ => Formula::Or(kept),
+    }
+}
+
+fn iff(a: Formula, b: Formula) -> Formula {
+    or(vec![
+        and(vec![a.clone(), b.clone()]),
+        and(vec![not(a), not(b)]),
+    ])
+}
+
+/// The value of `formula` under a model.
+fn holds(formula: &Formula, model: &Model, bools: &BTreeMap<String, bool>) -> bool {
+    match formula {
+        Formula::Const(value) => *value,
+        Formula::Atom(linear, rel) => rel.holds(linear.eval(model)),
+        Formula::Var(name) => bools.get(name).copied().unwrap_or(false),
+        Formula::Not(inner) => !holds(inner, model, bools),
+        Formula::And(parts) => parts.iter().all(|p| holds(p, model, bools)),
+        Formula::Or(parts) => parts.iter().any(|p| holds(p, model, bools)),
+    }
+}
+
+// ══════════════════════════════════════════════════════════════════════════════
+// Solver
+// ══════════════════════════════════════════════════════════════════════════════
+
+/// One case of a formula in disjunctive normal form.
+#[derive(Debug, Clone, Default)]
+struct Conjunct {
+    atoms: Vec<(Linear, Rel)>,
+    bools: BTreeMap<String, bool>,
+}
+
+impl Conjunct {
+    /// Both cases at once; `None` if they assign a bool differently.
+    fn merge(&self, other: &Conjunct) -> Option<Conjunct> {
+        let mut merged = self.clone();
+        for (name, value) in &other.bools {
+            if *merged.bools.entry(name.clone()).or_insert(*value) != *value {
+                return None;
+            }
+        }
+        merged.atoms.extend(other.atoms.iter().cloned());
+        Some(merged)
+    }
+
+    fn variables(&self) -> impl Iterator<Item = &String> {
+        self.atoms
+            .iter()
+            .flat_map(|(linear, _)| linear.terms.keys())
+            .chain(self.bools.keys())
+    }
+}
+
+/// The cases in which `formula` evaluates to `truth`; `None` if there are
+/// too many.
+fn dnf(formula: &Formula, truth: bool) -> Option<Vec<Conjunct>> {
+    let single = |atoms: Vec<(Linear, Rel)>| Conjunct {
+        atoms,
+        bools: BTreeMap::new(),
+    };
+    Some(match formula {
+        Formula::Const(value) if *value == truth => vec![Conjunct::default()],
+        Formula::Const(_) => Vec::new(),
+        Formula::Var(name) => vec![Conjunct {
+            atoms: Vec::new(),
+            bools: BTreeMap::from([(name.clone(), truth)]),
+        }],
+        Formula::Not(inner) => dnf(inner, !truth)?,
+        Formula::Atom(linear, rel) if truth => vec![single(vec![(linear.clone(), *rel)])],
+        Formula::Atom(linear, rel) => match rel {
+            Rel::Le => vec![single(vec![(linear.scale(-1.0), Rel::Lt)])],
+            Rel::Lt => vec![single(vec![(linear.scale(-1.0), Rel::Le)])],
+            Rel::Eq => vec![
+                single(vec![(linear.clone(), Rel::Lt)]),
+                single(vec![(linear.scale(-1.0), Rel::Lt)]),
+            ],
+        },
+        Formula::And(parts) | Formula::Or(parts) if matches!(formula, Formula::And(_)) == truth => {
+            let mut cases = vec![Conjunct::default()];
+            for part in parts {
+                let part_cases = dnf(part, truth)?;
+                cases = cases
+                    .iter()
+                    .flat_map(|case| part_cases.iter().filter_map(|p| case.merge(p)))
+                    .collect();
+                if cases.len() > MAX_CONJUNCTS {
+                    return None;
+                }
+            }
+            cases
+        }
+        Formula::And(parts) | Formula::Or(parts) => {
+            let mut cases = Vec::new();
+            for part in parts {
+                cases.extend(dnf(part, truth)?);
+                if cases.len() > MAX_CONJUNCTS {
+                    return None;
+                }
+            }
+            cases
+        }
+    })
+}
+
+/// A value for every variable of a satisfiable conjunct.
+type Model = BTreeMap<String, f64>;
+
+enum Solution {
+    Unsat,
+    Sat(Model),
+    /// Too large, or satisfiable over the reals with no integer model
+    /// found.
+    Unknown,
+}
+
+/// Decide a conjunction of atoms, `ints` taking only integer values.
+fn solve(atoms: &[(Linear, Rel)], ints: &HashSet<String>) -> Solution {
+    // Equalities: solve for one variable and substitute it everywhere
+    let mut pending = atoms.to_vec();
+    let mut substitutions: Vec<(String, Linear)> = Vec::new();
+    while let Some(i) = pending.iter().position(|(_, rel)| *rel == Rel::Eq) {
+        let (equality, _) = pending.swap_remove(i);
+        let Some((var, c)) = equality.terms.iter().next().map(|(v, c)| (v.clone(), *c)) else {
+            if !Rel::Eq.holds(equality.constant) {
+                return Solution::Unsat;
+            }
+            continue;
+        };
+        let mut rest = equality.clone();
+        rest.terms.remove(&var);
+        let by = rest.scale(-1.0 / c);
+        for (linear, _) in &mut pending {
+            *linear = linear.substitute(&var, &by);
+        }
+        for (_, earlier) in &mut substitutions {
+            *earlier = earlier.substitute(&var, &by);
+        }
+        substitutions.push((var, by));
+    }
+
+    // Inequalities: Fourier–Motzkin, keeping each variable's bounds for
+    // building the model
+    let mut rows: Vec<(Linear, bool)> = pending
+        .into_iter()
+        .map(|(linear, rel)| tighten(linear, rel == Rel::Lt, ints))
+        .collect();
+    let mut eliminated: Vec<(String, Vec<(Linear, bool)>)> = Vec::new();
+    loop {
+        let mut kept = Vec::new();
+        for (linear, strict) in rows {
+            match linear.as_constant() {
+                Some(value) => {
+                    let rel = if strict { Rel::Lt } else { Rel::Le };
+                    if !rel.holds(value) {
+                        return Solution::Unsat;
+                    }
+                }
+                None if !kept.contains(&(linear.clone(), strict)) => kept.push((linear, strict)),
+                None => {}
+            }
+        }
+        rows = kept;
+        let Some(var) = rows
+            .iter()
+            .flat_map(|(linear, _)| linear.terms.keys())
+            .next()
+            .cloned()
+        else {
+            break;
+        };
+        let (bounds, mut next): (Vec<_>, Vec<_>) = rows
+            .into_iter()
+            .partition(|(linear, _)| linear.coefficient(&var) != 0.0);
+        for (upper, upper_strict) in bounds.iter().filter(|(l, _)| l.coefficient(&var) > 0.0) {
+            for (lower, lower_strict) in bounds.iter().filter(|(l, _)| l.coefficient(&var) < 0.0) {
+                let a = upper.coefficient(&var);
+                let b = -lower.coefficient(&var);
+                let mut combined = upper.scale(b).plus(&lower.scale(a));
+                combined.terms.remove(&var);
+                next.push((combined, *upper_strict || *lower_strict));
+            }
+        }
+        if next.len() > MAX_ROWS {
+            return Solution::Unknown;
+        }
+        eliminated.push((var, bounds));
+        rows = next;
+    }
+
+    let mut model = Model::new();
+    for (var, bounds) in eliminated.iter().rev() {
+        let (mut lo, mut lo_strict) = (f64::NEG_INFINITY, false);
+        let (mut hi, mut hi_strict) = (f64::INFINITY, false);
+        for (linear, strict) in bounds {
+            let c = linear.coefficient(var);
+            let mut rest = linear.clone();
+            rest.terms.remove(var);
+            let bound = -rest.eval(&model) / c;
+            if c > 0.0 && (bound < hi || (bound == hi && *strict)) {
+                (hi, hi_strict) = (bound, *strict);
+            }
+            if c < 0.0 && (bound > lo || (bound == lo && *strict)) {
+                (lo, lo_strict) = (bound, *strict);
+            }
+        }
+        let Some(value) = pick(lo, lo_strict, hi, hi_strict, ints.contains(var)) else {
+            return Solution::Unknown;
+        };
+        model.insert(var.clone(), value);
+    }
+    for (var, by) in substitutions.iter().rev() {
+        let value = by.eval(&model);
+        if ints.contains(var) && (value - value.round()).abs() > EPSILON {
+            return Solution::Unknown;
+        }
+        model.insert(var.clone(), value);
+    }
+    Solution::Sat(model)
+}
+
+/// `linear < 0` or `linear <= 0` over integers only, with the constant
+/// rounded so the relaxation to the reals excludes fractional solutions:
+/// `2 < n < 3` becomes `3 <= n <= 2`.
+fn tighten(linear: Linear, strict: bool, ints: &HashSet<String>) -> (Linear, bool) {
+    let integral = linear
+        .terms
+        .iter()
+        .all(|(var, c)| ints.contains(var) && c.fract() == 0.0);
+    if !integral || linear.terms.is_empty() {
+        return (linear, strict);
+    }
+    let limit = -linear.constant;
+    let bound = if strict {
+        limit.ceil() - 1.0
+    } else {
+        limit.floor()
+    };
+    (
+        Linear {
+            constant: -bound,
+            ..linear
+        },
+        false,
+    )
+}
+
+/// A value between the bounds, preferring the integer closest to zero.
+fn pick(lo: f64, lo_strict: bool, hi: f64, hi_strict: bool, int: bool) -> Option<f64> {
+    let fits = |x: f64| (x > lo || (!lo_strict && x >= lo)) && (x < hi || (!hi_strict && x <= hi));
+    let nearest = if lo > 0.0 || (lo == 0.0 && lo_strict) {
+        if lo_strict {
+            lo.floor() + 1.0
+        } else {
+            lo.ceil()
+        }
+    } else if hi < 0.0 || (hi == 0.0 && hi_strict) {
+        if hi_strict {
+            hi.ceil() - 1.0
+        } else {
+            hi.floor()
+        }
+    } else {
+        0.0
+    };
+    if fits(nearest) {
+        return Some(nearest);
+    }
+    if int {
+        return None;
+    }
+    let value = match (lo.is_finite(), hi.is_finite()) {
+        (true, true) => (lo + hi) / 2.0,
+        (true, false) => lo + 1.0,
+        (false, true) => hi - 1.0,
+        (false, false) => 0.0,
+    };
+    fits(value).then_some(value)
+}
+
+// ══════════════════════════════════════════════════════════════════════════════
+// Symbolic execution
+// ══════════════════════════════════════════════════════════════════════════════
+
+/// What the analysis tracks about a value of some type.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Kind {
+    Number,
+    Bool,
+    /// A list or string, through its length.
+    Sized,
+    Other,
+}
+
+impl Kind {
+    fn of(ann: &TypeAnnotation) -> Kind {
+        match ann.kind {
+            TypeKind::Number => Kind::Number,
+            TypeKind::Bool => Kind::Bool,
+            TypeKind::String | TypeKind::List(_) => Kind::Sized,
+            _ => Kind::Other,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Sym {
+    Num(Linear),
+    Bool(Formula),
+    /// A list or string of this length.
+    Sized(Linear),
+    Other,
+}
+
+/// One way through an action.
+#[derive(Debug, Clone)]
+struct Path {
+    state: HashMap<String, Sym>,
+    scopes: Vec<HashMap<String, Sym>>,
+    condition: Vec<Formula>,
+    returned: bool,
+}
+
+impl Path {
+    fn lookup(&self, name: &str) -> Option<&Sym> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name))
+            .or_else(|| self.state.get(name))
+    }
+}
+
+/// Variable names: `state.x` and `param.x` for inputs, `len(…)` for their
+/// lengths, `=n` for helpers defined exactly by the path condition, and
+/// `?n` for values the analysis does not model.
+struct Executor<'a> {
+    body: &'a SpaceBody,
+    fresh: usize,
+    /// Why each `?n` variable is not modelled.
+    approximations: HashMap<String, String>,
+    /// Variables holding lengths.
+    ints: HashSet<String>,
+    too_many_paths: bool,
+}
+
+impl<'a> Executor<'a> {
+    fn new(body: &'a SpaceBody) -> Executor<'a> {
+        Executor {
+            body,
+            fresh: 0,
+            approximations: HashMap::new(),
+            ints: HashSet::new(),
+            too_many_paths: false,
+        }
+    }
+
+    /// The path an action starts on: every state field and parameter an
+    /// input variable.
+    fn entry(&mut self, params: &[Param]) -> Path {
+        let mut path = Path {
+            state: HashMap::new(),
+            scopes: vec![HashMap::new()],
+            condition: Vec::new(),
+            returned: false,
+        };
+        for field in &self.body.state.fields {
+            let name = &field.name.name;
+            let sym = self.input(
+                &mut path,
+                &format!("state.{name}"),
+                Kind::of(&field.type_ann),
+            );
+            path.state.insert(name.clone(), sym);
+        }
+        for param in params {
+            let name = &param.name.name;
+            let sym = self.input(
+                &mut path,
+                &format!("param.{name}"),
+                Kind::of(&param.type_ann),
+            );
+            path.scopes[0].insert(name.clone(), sym);
+        }
+        path
+    }
+
+    fn input(&mut self, path: &mut Path, var: &str, kind: Kind) -> Sym {
+        match kind {
+            Kind::Number => Sym::Num(Linear::var(var)),
+            Kind::Bool => Sym::Bool(Formula::Var(var.to_string())),
+            Kind::Sized => Sym::Sized(self.length(path, format!("len({var})"))),
+            Kind::Other => Sym::Other,
+        }
+    }
+
+    /// A non-negative integer variable.
+    fn length(&mut self, path: &mut Path, var: String) -> Linear {
+        let length = Linear::var(&var);
+        path.condition.push(atom(length.scale(-1.0), Rel::Le));
+        self.ints.insert(var);
+        length
+    }
+
+    /// A value of `kind` the analysis does not model, for `reason`.
+    fn unknown(&mut self, path: &mut Path, kind: Kind, reason: String) -> Sym {
+        self.fresh += 1;
+        let var = format!("?{}", self.fresh);
+        self.approximations.insert(var.clone(), reason);
+        match kind {
+            Kind::Number => Sym::Num(Linear::var(&var)),
+            Kind::Bool => Sym::Bool(Formula::Var(var)),
+            Kind::Sized => Sym::Sized(self.length(path, var)),
+            Kind::Other => Sym::Other,
+        }
+    }
+
+    /// A helper variable equal to `a` where `a_wins` holds, else to `b`.
+    fn select(&mut self, path: &mut Path, a: Linear, b: Linear, a_wins: Formula) -> Linear {
+        self.fresh += 1;
+        let value = Linear::var(&format!("={}", self.fresh));
+        path.condition.push(or(vec![
+            and(vec![a_wins.clone(), atom(value.minus(&a), Rel::Eq)]),
+            and(vec![not(a_wins), atom(value.minus(&b), Rel::Eq)]),
+        ]));
+        value
+    }
+
+    // ── Conversions ───────────────────────────────────────────────────────
+
+    /// An unknown of `kind` for the value of `expr`.  A local or field
+    /// keeps it, so every read of the name sees the same unknown.
+    fn unknown_value(&mut self, path: &mut Path, kind: Kind, expr: &Expr) -> Sym {
+        let value = self.unknown(path, kind, not_modelled(expr));
+        if let ExprKind::Identifier(name) = &expr.kind {
+            let scope = path
+                .scopes
+                .iter_mut()
+                .rev()
+                .find_map(|scope| scope.get_mut(name));
+            if let Some(binding) = scope.or_else(|| path.state.get_mut(name)) {
+                *binding = value.clone();
+            }
+        }
+        value
+    }
+
+    fn as_num(&mut self, path: &mut Path, sym: Sym, expr: &Expr) -> Linear {
+        match sym {
+            Sym::Num(linear) => linear,
+            _ => match self.unknown_value(path, Kind::Number, expr) {
+                Sym::Num(linear) => linear,
+                _ => unreachable!(),
+            },
+        }
+    }
+
+    fn as_bool(&mut self, path: &mut Path, sym: Sym, expr: &Expr) -> Formula {
+        match sym {
+            Sym::Bool(formula) => formula,
+            _ => match self.unknown_value(path, Kind::Bool, expr) {
+                Sym::Bool(formula) => formula,
+                _ => unreachable!(),
+            },
+        }
+    }
+
+    fn as_sized(&mut self, path: &mut Path, sym: Sym, expr: &Expr) -> Linear {
+        match sym {
+            Sym::Sized(length) => length,
+            _ => match self.unknown_value(path, Kind::Sized, expr) {
+                Sym::Sized(length) => length,
+                _ => unreachable!(),
+            },
+        }
+    }
+
+    fn convert(&mut self, path: &mut Path, sym: Sym, kind: Kind, expr: &Expr) -> Sym {
+        match kind {
+            Kind::Number => Sym::Num(self.as_num(path, sym, expr)),
+            Kind::Bool => Sym::Bool(self.as_bool(path, sym, expr)),
+            Kind::Sized => Sym::Sized(self.as_sized(path, sym, expr)),
+            Kind::Other => Sym::Other,
+        }
+    }
+
+    /// `expr` as a formula over the path's variables.
+    fn condition(&mut self, path: &mut Path, expr: &Expr) -> Formula {
+        let sym = self.expr(path, expr);
+        self.as_bool(path, sym, expr)
+    }
+
+    fn field_kind(&self, name: &str) -> Kind {
+        self.body
+            .state
+            .fields
+            .iter()
+            .find(|f| f.name.name == name)
+            .map_or(Kind::Other, |f| Kind::of(&f.type_ann))
+    }
+
+    // ── Statements ────────────────────────────────────────────────────────
+
+    fn block(&mut self, paths: Vec<Path>, block: &Block) -> Vec<Path> {
+        let mut paths: Vec<Path> = paths
+            .into_iter()
+            .map(|mut path| {
+                path.scopes.push(HashMap::new());
+                path
+            })
+            .collect();
+        for stmt in &block.stmts {
+            let mut next = Vec::new();
+            for path in paths {
+                next.extend(self.stmt(path, stmt));
+            }
+            if next.len() > MAX_PATHS {
+                self.too_many_paths = true;
+                next.truncate(MAX_PATHS);
+            }
+            paths = next;
+        }
+        for path in &mut paths {
+            path.scopes.pop();
+        }
+        paths
+    }
+
+    fn stmt(&mut self, mut path: Path, stmt: &Stmt) -> Vec<Path> {
+        if path.returned {
+            return vec![path];
+        }
+        match stmt {
+            Stmt::Set(set) => {
+                let root = &set.target[0].name;
+                let kind = self.field_kind(root);
+                let value = self.expr(&mut path, &set.value);
+                let value = if set.target.len() == 1 {
+                    self.convert(&mut path, value, kind, &set.value)
+                } else {
+                    Sym::Other
+                };
+                path.state.insert(root.clone(), value);
+                vec![path]
+            }
+            Stmt::Let(binding) => {
+                let value = self.expr(&mut path, &binding.value);
+                if let (Some(name), Some(scope)) = (&binding.name, path.scopes.last_mut()) {
+                    scope.insert(name.name.clone(), value);
+                }
+                vec![path]
+            }
+            Stmt::If(if_expr) => self.if_stmt(path, if_expr),
+            Stmt::For(for_expr) => {
+                // Any number of iterations: whatever the loop sets could be
+                // anything afterwards
+                let mut targets = HashSet::new();
+                set_targets(&for_expr.body, &mut targets);
+                let mut targets: Vec<_> = targets.into_iter().collect();
+                targets.sort();
+                for name in targets {
+                    let kind = self.field_kind(&name);
+                    let value = self.unknown(
+                        &mut path,
+                        kind,
+                        format!("the action sets '{name}' in a for loop"),
+                    );
+                    path.state.insert(name, value);
+                }
+                if contains_return(&for_expr.body) {
+                    let mut returned = path.clone();
+                    returned.returned = true;
+                    return vec![path, returned];
+                }
+                vec![path]
+            }
+            Stmt::Match(match_expr) => {
+                let mut exits = Vec::new();
+                for arm in &match_expr.arms {
+                    let mut arm_path = path.clone();
+                    let taken = self.unknown(
+                        &mut arm_path,
+                        Kind::Bool,
+                        "which match arm runs is not modelled".to_string(),
+                    );
+                    if let Sym::Bool(taken) = taken {
+                        arm_path.condition.push(taken);
+                    }
+                    let mut bound = HashMap::new();
+                    if let Pattern::Variant { bindings, .. } = &arm.pattern {
+                        for binding in bindings {
+                            bound.insert(binding.name.clone(), Sym::Other);
+                        }
+                    }
+                    arm_path.scopes.push(bound);
+                    let mut arm_exits = match &arm.body {
+                        MatchArmBody::Block(block) => self.block(vec![arm_path], block),
+                        MatchArmBody::Expr(_) => vec![arm_path],
+                    };
+                    for exit in &mut arm_exits {
+                        exit.scopes.pop();
+                    }
+                    exits.extend(arm_exits);
+                }
+                exits
+            }
+            Stmt::Return(_) => {
+                path.returned = true;
+                vec![path]
+            }
+            Stmt::Assert(assert) => {
+                // A failing assert rolls the action back
+                let condition = self.condition(&mut path, &assert.condition);
+                path.condition.push(condition);
+                vec![path]
+            }
+            Stmt::Expr(_) => vec![path],
+        }
+    }
+
+    fn if_stmt(&mut self, mut path: Path, if_expr: &IfExpr) -> Vec<Path> {
+        let condition = self.condition(&mut path, &if_expr.condition);
+        let mut exits = Vec::new();
+        if condition != Formula::Const(false) {
+            let mut then_path = path.clone();
+            then_path.condition.push(condition.clone());
+            exits.extend(self.block(vec![then_path], &if_expr.then_block));
+        }
+        if condition != Formula::Const(true) {
+            path.condition.push(not(condition));
+            exits.extend(match &if_expr.else_branch {
+                Some(ElseBranch::ElseIf(inner)) => self.if_stmt(path, inner),
+                Some(ElseBranch::Block(block)) => self.block(vec![path], block),
+                None => vec![path],
+            });
+        }
+        exits
+    }
+
+    // ── Expressions ───────────────────────────────────────────────────────
+
+    fn expr(&mut self, path: &mut Path, expr: &Expr) -> Sym {
+        match &expr.kind {
+            ExprKind::NumberLit(n) => Sym::Num(Linear::constant(*n)),
+            ExprKind::BoolLit(b) => Sym::Bool(Formula::Const(*b)),
+            ExprKind::StringLit(s) => Sym::Sized(Linear::constant(s.chars().count() as f64)),
+            ExprKind::ListLit(items) => Sym::Sized(Linear::constant(items.len() as f64)),
+            ExprKind::Identifier(name) => path.lookup(name).cloned().unwrap_or(Sym::Other),
+            ExprKind::Paren(inner) => self.expr(path, inner),
+            ExprKind::Unary { op, operand } => {
+                let value = self.expr(path, operand);
+                match op {
+                    UnaryOp::Neg => Sym::Num(self.as_num(path, value, operand).scale(-1.0)),
+                    UnaryOp::Not => Sym::Bool(not(self.as_bool(path, value, operand))),
+                }
+            }
+            ExprKind::Binary { left, op, right } => self.binary(path, left, *op, right),
+            ExprKind::QualifiedCall {
+                module,
+                function,
+                args,
+            } => {
+                let values: Vec<Sym> = args.iter().map(|arg| self.expr(path, arg)).collect();
+                let exprs: Vec<&Expr> = args.iter().collect();
+                self.call(path, &module.name, &function.name, &exprs, values)
+            }
+            ExprKind::MethodCall {
+                object,
+                method,
+                args,
+            } => {
+                let receiver = self.expr(path, object);
+                let module = match receiver {
+                    Sym::Num(_) => "math",
+                    _ => "list",
+                };
+                let mut values = vec![receiver];
+                values.extend(args.iter().map(|arg| self.expr(path, arg)));
+                let mut exprs = vec![&**object];
+                exprs.extend(args.iter());
+                self.call(path, module, &method.name, &exprs, values)
+            }
+            _ => Sym::Other,
+        }
+    }
+
+    fn binary(&mut self, path: &mut Path, left: &Expr, op: BinOp, right: &Expr) -> Sym {
+        let left_value = self.expr(path, left);
+        let right_value = self.expr(path, right);
+        match op {
+            BinOp::And | BinOp::Or => {
+                let a = self.as_bool(path, left_value, left);
+                let b = self.as_bool(path, right_value, right);
+                Sym::Bool(if op == BinOp::And {
+                    and(vec![a, b])
+                } else {
+                    or(vec![a, b])
+                })
+            }
+            BinOp::Add => match (left_value, right_value) {
+                (Sym::Sized(a), Sym::Sized(b)) => Sym::Sized(a.plus(&b)),
+                (a, b) => {
+                    let a = self.as_num(path, a, left);
+                    let b = self.as_num(path, b, right);
+                    Sym::Num(a.plus(&b))
+                }
+            },
+            BinOp::Sub => {
+                let a = self.as_num(path, left_value, left);
+                let b = self.as_num(path, right_value, right);
+                Sym::Num(a.minus(&b))
+            }
+            BinOp::Mul => match (left_value, right_value) {
+                (Sym::Num(a), Sym::Num(b))
+                    if a.as_constant().is_some() || b.as_constant().is_some() =>
+                {
+                    Sym::Num(match a.as_constant() {
+                        Some(k) => b.scale(k),
+                        None => a.scale(b.constant),
+                    })
+                }
+                _ => Sym::Other,
+            },
+            BinOp::Div => match (left_value, right_value) {
+                (Sym::Num(a), Sym::Num(b)) => match b.as_constant() {
+                    Some(k) if k != 0.0 => Sym::Num(a.scale(1.0 / k)),
+                    _ => Sym::Other,
+                },
+                _ => Sym::Other,
+            },
+            BinOp::Mod => Sym::Other,
+            _ => match (left_value, right_value) {
+                (Sym::Bool(a), Sym::Bool(b)) if matches!(op, BinOp::Eq | BinOp::NotEq) => {
+                    let same = iff(a, b);
+                    Sym::Bool(if op == BinOp::Eq { same } else { not(same) })
+                }
+                (a @ Sym::Num(_), b) | (a, b @ Sym::Num(_)) => {
+                    let a = self.as_num(path, a, left);
+                    let b = self.as_num(path, b, right);
+                    compare(&a, op, &b).map_or(Sym::Other, Sym::Bool)
+                }
+                _ => Sym::Other,
+            },
+        }
+    }
+
+    /// A stdlib call on lengths or numbers.
+    fn call(
+        &mut self,
+        path: &mut Path,
+        module: &str,
+        function: &str,
+        exprs: &[&Expr],
+        values: Vec<Sym>,
+    ) -> Sym {
+        let arity = match (module, function) {
+            ("list", "empty" | "of") => 0,
+            ("list", "concat") | ("math", "max" | "min") => 2,
+            ("math", "clamp") => 3,
+            _ => 1,
+        };
+        if values.len() < arity {
+            return Sym::Other;
+        }
+        let num = |this: &mut Self, path: &mut Path, i: usize| {
+            this.as_num(path, values[i].clone(), exprs[i])
+        };
+        let sized = |this: &mut Self, path: &mut Path, i: usize| {
+            this.as_sized(path, values[i].clone(), exprs[i])
+        };
+        match (module, function) {
+            ("list" | "string", "length") => Sym::Num(sized(self, path, 0)),
+            ("list" | "string", "is_empty") => Sym::Bool(atom(sized(self, path, 0), Rel::Eq)),
+            ("list", "empty") => Sym::Sized(Linear::constant(0.0)),
+            ("list", "of") => Sym::Sized(Linear::constant(values.len() as f64)),
+            ("list", "append" | "prepend" | "insert") => {
+                Sym::Sized(sized(self, path, 0).plus(&Linear::constant(1.0)))
+            }
+            ("list", "set" | "update" | "reverse" | "sort" | "map") => {
+                Sym::Sized(sized(self, path, 0))
+            }
+            ("list", "concat") => {
+                let a = sized(self, path, 0);
+                Sym::Sized(a.plus(&sized(self, path, 1)))
+            }
+            ("math", "max" | "min") => {
+                let (a, b) = (num(self, path, 0), num(self, path, 1));
+                let op = if function == "max" {
+                    BinOp::GreaterEq
+                } else {
+                    BinOp::LessEq
+                };
+                let a_wins = compare(&a, op, &b).unwrap_or(Formula::Const(true));
+                Sym::Num(self.select(path, a, b, a_wins))
+            }
+            ("math", "abs") => {
+                let x = num(self, path, 0);
+                let positive = atom(x.scale(-1.0), Rel::Le);
+                Sym::Num(self.select(path, x.clone(), x.scale(-1.0), positive))
+            }
+            ("math", "clamp") => {
+                let (x, lo, hi) = (num(self, path, 0), num(self, path, 1), num(self, path, 2));
+                let above = compare(&x, BinOp::GreaterEq, &lo).unwrap_or(Formula::Const(true));
+                let raised = self.select(path, x, lo, above);
+                let below = compare(&raised, BinOp::LessEq, &hi).unwrap_or(Formula::Const(true));
+                Sym::Num(self.select(path, raised, hi, below))
+            }
+            _ => Sym::Other,
+        }
+    }
+
+    // ── Proofs ────────────────────────────────────────────────────────────
+
+    /// Whether `invariant` holds at every exit, given `before`.
+    fn verdict(
+        &mut self,
+        action: &ActionDecl,
+        before: &[Formula],
+        exits: &[Path],
+        invariant: &Expr,
+    ) -> Verdict {
+        if self.too_many_paths {
+            return Verdict::Unknown {
+                reason: format!("the action has more than {MAX_PATHS} paths"),
+            };
+        }
+        let mut reason: Option<String> = None;
+        for exit in exits {
+            let mut path = exit.clone();
+            path.scopes.clear();
+            let after = self.condition(&mut path, invariant);
+            let mut parts = path.condition.clone();
+            parts.extend(before.iter().cloned());
+            parts.push(not(after));
+            let Some(cases) = dnf(&and(parts), true) else {
+                reason.get_or_insert_with(|| "the proof obligation is too large".to_string());
+                continue;
+            };
+            for case in cases {
+                match solve(&case.atoms, &self.ints) {
+                    Solution::Unsat => {}
+                    Solution::Sat(model) => match self.approximation(&case) {
+                        None => {
+                            return Verdict::Refuted {
+                                counterexample: self.counterexample(action, &path, &model, &case),
+                            }
+                        }
+                        Some(why) => {
+                            reason.get_or_insert(why);
+                        }
+                    },
+                    Solution::Unknown => {
+                        reason.get_or_insert_with(|| {
+                            "no counterexample with whole-number lengths was found".to_string()
+                        });
+                    }
+                }
+            }
+        }
+        match reason {
+            Some(reason) => Verdict::Unknown { reason },
+            None => Verdict::Proven,
+        }
+    }
+
+    /// Why a satisfiable case may not be a real execution, if it may not.
+    fn approximation(&self, case: &Conjunct) -> Option<String> {
+        let mut vars: Vec<&String> = case.variables().filter(|v| v.starts_with('?')).collect();
+        vars.sort_by_key(|v| v[1..].parse::<usize>().unwrap_or(0));
+        vars.first()
+            .and_then(|v| self.approximations.get(*v))
+            .cloned()
+    }
+
+    fn counterexample(
+        &self,
+        action: &ActionDecl,
+        exit: &Path,
+        model: &Model,
+        case: &Conjunct,
+    ) -> Counterexample {
+        let input = |prefix: &str, name: &str, ann: &TypeAnnotation| -> Option<String> {
+            let var = format!("{prefix}.{name}");
+            let value = match Kind::of(ann) {
+                Kind::Number => model.get(&var).copied().unwrap_or(0.0),
+                Kind::Bool => f64::from(u8::from(case.bools.get(&var).copied().unwrap_or(false))),
+                Kind::Sized => model.get(&format!("len({var})")).copied().unwrap_or(0.0),
+                Kind::Other => return None,
+            };
+            Some(literal(&ann.kind, value))
+        };
+        let params = action
+            .params
+            .iter()
+            .filter_map(|p| {
+                Some((
+                    p.name.name.clone(),
+                    input("param", &p.name.name, &p.type_ann)?,
+                ))
+            })
+            .collect();
+        let before = self
+            .body
+            .state
+            .fields
+            .iter()
+            .filter_map(|f| {
+                Some((
+                    f.name.name.clone(),
+                    input("state", &f.name.name, &f.type_ann)?,
+                ))
+            })
+            .collect();
+
+        let mut targets = HashSet::new();
+        set_targets(&action.body, &mut targets);
+        let after = self
+            .body
+            .state
+            .fields
+            .iter()
+            .filter(|f| targets.contains(&f.name.name))
+            .filter_map(|f| {
+                let value = match exit.state.get(&f.name.name)? {
+                    Sym::Num(linear) | Sym::Sized(linear) if defined(linear, model) => {
+                        linear.eval(model)
+                    }
+                    Sym::Bool(formula) => f64::from(u8::from(holds(formula, model, &case.bools))),
+                    _ => return None,
+                };
+                Some((f.name.name.clone(), literal(&f.type_ann.kind, value)))
+            })
+            .collect();
+        Counterexample {
+            params,
+            before,
+            after,
+        }
+    }
+}
+
+/// Whether `model` fixes every variable of `linear`.
+fn defined(linear: &Linear, model: &Model) -> bool {
+    linear
+        .terms
+        .keys()
+        .all(|var| !var.starts_with('?') || model.contains_key(var))
+}
+
+/// Why the value of `expr` is not modelled.
+fn not_modelled(expr: &Expr) -> String {
+    match &expr.kind {
+        ExprKind::QualifiedCall {
+            module, function, ..
+        } => format!("{}.{} is not modelled", module.name, function.name),
+        ExprKind::MethodCall { method, .. } => format!(".{}() is not modelled", method.name),
+        ExprKind::Binary {
+            op: BinOp::Mul | BinOp::Div,
+            ..
+        } => "non-linear arithmetic is not modelled".to_string(),
+        ExprKind::Binary { op: BinOp::Mod, .. } => "'%' is not modelled".to_string(),
+        ExprKind::Identifier(name) => format!("the value of '{name}' is not modelled"),
+        ExprKind::FieldAccess { .. } => "record fields are not modelled".to_string(),
+        ExprKind::Paren(inner) => not_modelled(inner),
+        _ => "this expression is not modelled".to_string(),
+    }
+}
+
+/// A PEPL literal of type `kind`: the number, the bool (`value` 0 or 1),
+/// or a list or string of length `value`.
+fn literal(kind: &TypeKind, value: f64) -> String {
+    let count = value.max(0.0).round() as usize;
+    match kind {
+        TypeKind::Bool => (value != 0.0).to_string(),
+        TypeKind::String => format!("\"{}\"", "a".repeat(count)),
+        TypeKind::List(inner) => {
+            let item = match inner.kind {
+                TypeKind::Number => "0",
+                TypeKind::String => "\"\"",
+                TypeKind::Bool => "false",
+                _ if count == 1 => return "a list of 1 item".to_string(),
+                _ => return format!("a list of {count} items"),
+            };
+            format!("[{}]", vec![item; count].join(", "))
+        }
+        _ if value.fract() == 0.0 && value.abs() < 1e15 => format!("{}", value as i64),
+        _ => value.to_string(),
+    }
+}
+
+fn contains_return(block: &Block) -> bool {
+    block.stmts.iter().any(|stmt| match stmt {
+        Stmt::Return(_) => true,
+        Stmt::If(if_expr) => if_contains_return(if_expr),
+        Stmt::For(for_expr) => contains_return(&for_expr.body),
+        Stmt::Match(match_expr) => match_expr.arms.iter().any(|arm| match &arm.body {
+            MatchArmBody::Block(block) => contains_return(block),
+            MatchArmBody::Expr(_) => false,
+        }),
+        _ => false,
+    })
+}
+
+fn if_contains_return(if_expr: &IfExpr) -> bool {
+    contains_return(&if_expr.then_block)
+        || match &if_expr.else_branch {
+            Some(ElseBranch::Block(block)) => contains_return(block),
+            Some(ElseBranch::ElseIf(inner)) => if_contains_return(inner),
+            None => false,
+        }
+}
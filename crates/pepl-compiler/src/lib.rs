@@ -9,11 +9,29 @@
 //! - [`type_check`] — Parse + type-check only, returning structured errors.
 //! - [`compile`] — Full pipeline: parse → type-check → codegen → `.wasm` bytes.
 //! - [`compile_to_result`] — Full pipeline returning a [`CompileResult`] (JSON-serializable).
+//! - [`disassemble`] — Full pipeline, printing the module as annotated WAT.
+//! - [`apply_fixes`] — Apply the structured fixes attached to diagnostics.
+//! - [`repair::repair`] — Apply safe fixes in rounds until near-miss
+//!   (e.g. LLM-generated) source compiles.
+//!
+//! [`CompileResult::bindings`] generates typed TypeScript, JavaScript and
+//! Rust host bindings for a compiled space.
+//!
+//! Every entry point runs the [`lint`]s after a successful type check;
+//! [`CompileOptions::lints`] configures their levels.
 
+pub mod bindings;
 pub mod checker;
+pub mod effects;
 pub mod env;
+pub mod gas_bound;
+pub mod interval;
+pub mod invariant_proof;
+pub mod lint;
 pub mod reference;
+pub mod repair;
 pub mod stdlib;
+pub mod taint;
 pub mod ty;
 
 use pepl_codegen::CodegenError;
@@ -100,6 +118,70 @@ pub struct CompileResult {
     /// Source map: WASM function index → PEPL source location.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub source_map: Option<pepl_codegen::SourceMap>,
+
+    /// Worst-case gas per action, view, `update` and `handleEvent`
+    /// (empty unless type-checking succeeded).
+    pub gas_bounds: Vec<gas_bound::EntryGas>,
+
+    /// Whether each action preserves each invariant (see
+    /// [`invariant_proof`]; empty unless type-checking succeeded).
+    pub invariant_proofs: Vec<invariant_proof::InvariantProof>,
+
+    /// Capability functions each action, `update` and `handleEvent` can
+    /// call (see [`effects`]; also embedded in `wasm` as the
+    /// [`effects::MANIFEST_SECTION`] custom section).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub capability_manifest: Option<effects::CapabilityManifest>,
+
+    /// What each derived field reads, and the order they are recomputed
+    /// in (see [`pepl_types::derived`]; present whenever type-checking
+    /// ran, so tooling can show a rejected cycle).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub derived_graph: Option<pepl_types::derived::DerivedGraph>,
+
+    /// The space as a WebAssembly component, with
+    /// [`CompileOptions::component`] set.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub component: Option<Vec<u8>>,
+
+    /// WIT of the world `component` implements (see [`pepl_codegen::wit`]).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub wit: Option<String>,
+}
+
+impl CompileResult {
+    /// Typed host bindings for the compiled space (see [`bindings`]);
+    /// `None` unless compilation succeeded.
+    pub fn bindings(&self) -> Option<bindings::Bindings> {
+        match &self.ast {
+            Some(program) if self.success => Some(bindings::generate(program)),
+            _ => None,
+        }
+    }
+}
+
+// ── CompileOptions ────────────────────────────────────────────────────────────
+
+/// Options for [`compile_to_result_with_options`].
+#[derive(Debug, Clone)]
+pub struct CompileOptions {
+    /// Gas limit of the host; entry points whose constant worst-case gas
+    /// exceeds it get an E608 warning.
+    pub gas_budget: u64,
+    /// Also wrap the module as a component ([`CompileResult::component`]).
+    pub component: bool,
+    /// Lint levels (see [`lint`]).
+    pub lints: lint::LintConfig,
+}
+
+impl Default for CompileOptions {
+    fn default() -> Self {
+        Self {
+            gas_budget: pepl_types::gas::DEFAULT_LIMIT,
+            component: false,
+            lints: lint::LintConfig::default(),
+        }
+    }
 }
 
 // ── type_check ────────────────────────────────────────────────────────────────
@@ -132,6 +214,16 @@ pub fn type_check(source: &str, name: &str) -> CompileErrors {
     let mut tc = checker::TypeChecker::new(&mut errors, &source_file);
     tc.check(&program);
 
+    // 4. Lint
+    if !errors.has_errors() {
+        lint::check(
+            &program,
+            &source_file,
+            &lint::LintConfig::default(),
+            &mut errors,
+        );
+    }
+
     errors
 }
 
@@ -143,6 +235,26 @@ pub fn type_check(source: &str, name: &str) -> CompileErrors {
 /// syntax, type, or invariant errors. Codegen errors are converted to a
 /// single internal error in `CompileErrors`.
 pub fn compile(source: &str, name: &str) -> Result<Vec<u8>, CompileErrors> {
+    codegen(source, name).map(|(wasm, _source_map)| wasm)
+}
+
+/// Compile a PEPL source file and disassemble the module to WAT, annotated
+/// with PEPL function names, source lines, decoded strings and runtime
+/// helper names (see [`pepl_codegen::disasm`]).
+pub fn disassemble(source: &str, name: &str) -> Result<String, CompileErrors> {
+    let (wasm, source_map) = codegen(source, name)?;
+    pepl_codegen::disasm::disassemble_with_source(&wasm, &source_map, source).map_err(|e| {
+        let mut errors = CompileErrors::empty();
+        errors.push_error(codegen_error_to_pepl_error(&e, name));
+        errors
+    })
+}
+
+/// The pipeline shared by [`compile`] and [`disassemble`].
+fn codegen(
+    source: &str,
+    name: &str,
+) -> Result<(Vec<u8>, pepl_codegen::SourceMap), CompileErrors> {
     let source_file = SourceFile::new(name.to_string(), source.to_string());
 
     // 1. Lex
@@ -164,23 +276,33 @@ pub fn compile(source: &str, name: &str) -> Result<Vec<u8>, CompileErrors> {
 
     // 3. Type-check (includes invariant checking)
     let mut errors = CompileErrors::empty();
-    {
+    let mut options = {
         let mut tc = checker::TypeChecker::new(&mut errors, &source_file);
         tc.check(&program);
+        tc.into_codegen_options(pepl_codegen::OptLevel::O1)
+    };
+    if !errors.has_errors() {
+        lint::check(
+            &program,
+            &source_file,
+            &lint::LintConfig::default(),
+            &mut errors,
+        );
     }
     if errors.has_errors() {
         return Err(errors);
     }
 
     // 4. Codegen → .wasm
-    match pepl_codegen::compile(&program) {
-        Ok(wasm) => Ok(wasm),
-        Err(e) => {
-            let mut errors = CompileErrors::empty();
-            errors.push_error(codegen_error_to_pepl_error(&e, name));
-            Err(errors)
-        }
-    }
+    options.custom_sections.push((
+        effects::MANIFEST_SECTION.to_string(),
+        effects::analyze(&program).to_json(),
+    ));
+    pepl_codegen::compile_with_options(&program, options).map_err(|e| {
+        let mut errors = CompileErrors::empty();
+        errors.push_error(codegen_error_to_pepl_error(&e, name));
+        errors
+    })
 }
 
 /// Full compilation pipeline, returning a [`CompileResult`] (JSON-serializable).
@@ -188,6 +310,15 @@ pub fn compile(source: &str, name: &str) -> Result<Vec<u8>, CompileErrors> {
 /// This is the main entry point for the playground / WASM host.
 /// Includes enriched metadata: AST, hashes, state/action/view lists, versions.
 pub fn compile_to_result(source: &str, name: &str) -> CompileResult {
+    compile_to_result_with_options(source, name, &CompileOptions::default())
+}
+
+/// [`compile_to_result`] with explicit [`CompileOptions`].
+pub fn compile_to_result_with_options(
+    source: &str,
+    name: &str,
+    options: &CompileOptions,
+) -> CompileResult {
     let source_hash = sha256_hex(source.as_bytes());
     let source_file = SourceFile::new(name.to_string(), source.to_string());
 
@@ -210,6 +341,12 @@ pub fn compile_to_result(source: &str, name: &str) -> CompileResult {
             compiler_version: PEPL_COMPILER_VERSION.to_string(),
             warnings: Vec::new(),
             source_map: None,
+            gas_bounds: Vec::new(),
+            invariant_proofs: Vec::new(),
+            capability_manifest: None,
+            derived_graph: None,
+            component: None,
+            wit: None,
         };
     }
 
@@ -232,6 +369,12 @@ pub fn compile_to_result(source: &str, name: &str) -> CompileResult {
             compiler_version: PEPL_COMPILER_VERSION.to_string(),
             warnings: Vec::new(),
             source_map: None,
+            gas_bounds: Vec::new(),
+            invariant_proofs: Vec::new(),
+            capability_manifest: None,
+            derived_graph: None,
+            component: None,
+            wit: None,
         };
     }
 
@@ -254,6 +397,12 @@ pub fn compile_to_result(source: &str, name: &str) -> CompileResult {
                 compiler_version: PEPL_COMPILER_VERSION.to_string(),
                 warnings: Vec::new(),
                 source_map: None,
+                gas_bounds: Vec::new(),
+                invariant_proofs: Vec::new(),
+                capability_manifest: None,
+                derived_graph: None,
+                component: None,
+                wit: None,
             };
         }
     };
@@ -263,9 +412,17 @@ pub fn compile_to_result(source: &str, name: &str) -> CompileResult {
 
     // 3. Type-check
     let mut errors = CompileErrors::empty();
-    {
+    let (mut codegen_options, gas_bounds, derived_graph) = {
         let mut tc = checker::TypeChecker::new(&mut errors, &source_file);
         tc.check(&program);
+        let gas_bounds = tc.gas_bounds(&program, options.gas_budget);
+        let derived_graph = tc.derived_graph().clone();
+        let mut codegen_options = tc.into_codegen_options(pepl_codegen::OptLevel::O1);
+        codegen_options.component = options.component;
+        (codegen_options, gas_bounds, derived_graph)
+    };
+    if !errors.has_errors() {
+        lint::check(&program, &source_file, &options.lints, &mut errors);
     }
 
     let warnings = errors.warnings.clone();
@@ -287,13 +444,41 @@ pub fn compile_to_result(source: &str, name: &str) -> CompileResult {
             compiler_version: PEPL_COMPILER_VERSION.to_string(),
             warnings,
             source_map: None,
+            gas_bounds: Vec::new(),
+            invariant_proofs: Vec::new(),
+            capability_manifest: None,
+            derived_graph: Some(derived_graph),
+            component: None,
+            wit: None,
         };
     }
 
+    let invariant_proofs = invariant_proof::analyze(&program);
+    let capability_manifest = effects::analyze(&program);
+    codegen_options.custom_sections.push((
+        effects::MANIFEST_SECTION.to_string(),
+        capability_manifest.to_json(),
+    ));
+
     // 4. Codegen → .wasm
-    match pepl_codegen::compile_with_source_map(&program) {
-        Ok((wasm, source_map)) => {
+    let compiled = pepl_codegen::compile_with_options(&program, codegen_options).and_then(
+        |(wasm, source_map)| {
+            // 5. Optionally wrap as a component
+            let component = if options.component {
+                Some((
+                    pepl_codegen::component::wrap(&program, &wasm)?,
+                    pepl_codegen::wit::space_wit(&program)?,
+                ))
+            } else {
+                None
+            };
+            Ok((wasm, source_map, component))
+        },
+    );
+    match compiled {
+        Ok((wasm, source_map, component)) => {
             let wasm_hash = sha256_hex(&wasm);
+            let (component, wit) = component.unzip();
             CompileResult {
                 success: true,
                 wasm: Some(wasm),
@@ -310,6 +495,12 @@ pub fn compile_to_result(source: &str, name: &str) -> CompileResult {
                 compiler_version: PEPL_COMPILER_VERSION.to_string(),
                 warnings,
                 source_map: Some(source_map),
+                gas_bounds,
+                invariant_proofs,
+                capability_manifest: Some(capability_manifest),
+                derived_graph: Some(derived_graph),
+                component,
+                wit,
             }
         }
         Err(e) => {
@@ -331,11 +522,86 @@ pub fn compile_to_result(source: &str, name: &str) -> CompileResult {
                 compiler_version: PEPL_COMPILER_VERSION.to_string(),
                 warnings,
                 source_map: None,
+                gas_bounds,
+                invariant_proofs,
+                capability_manifest: Some(capability_manifest),
+                derived_graph: Some(derived_graph),
+                component: None,
+                wit: None,
             }
         }
     }
 }
 
+// ── apply_fixes ───────────────────────────────────────────────────────────────
+
+/// Apply the structured [`Fix`](pepl_types::Fix)es attached to `errors`
+/// (errors and warnings) to `source`, returning the edited source.
+///
+/// Only each diagnostic's first fix is used, whatever its applicability —
+/// filter `errors` first to be more conservative. A fix overlapping one
+/// already applied (including the same insertion reported by several
+/// diagnostics) is skipped; re-check and apply again to pick those up.
+pub fn apply_fixes(source: &str, errors: &CompileErrors) -> String {
+    let fixes = errors
+        .errors
+        .iter()
+        .chain(&errors.warnings)
+        .filter_map(|e| e.fixes.first());
+    apply_fix_list(source, fixes).0
+}
+
+/// Apply `fixes` in order, skipping any that overlap one already applied;
+/// returns the edited source and the indices of the fixes applied.
+pub(crate) fn apply_fix_list<'f>(
+    source: &str,
+    fixes: impl IntoIterator<Item = &'f pepl_types::Fix>,
+) -> (String, Vec<usize>) {
+    let line_starts: Vec<usize> = std::iter::once(0)
+        .chain(source.match_indices('\n').map(|(i, _)| i + 1))
+        .collect();
+    let offset = |line: u32, col: u32| -> Option<usize> {
+        let start = *line_starts.get(line.checked_sub(1)? as usize)?;
+        Some((start + col.saturating_sub(1) as usize).min(source.len()))
+            .filter(|&offset| source.is_char_boundary(offset))
+    };
+
+    let mut edits: Vec<(usize, usize, &str)> = Vec::new();
+    let mut applied = Vec::new();
+    for (index, fix) in fixes.into_iter().enumerate() {
+        let Some(resolved) = fix
+            .edits
+            .iter()
+            .map(|edit| {
+                let span = edit.span;
+                let start = offset(span.start_line, span.start_col)?;
+                let end = offset(span.end_line, span.end_col)?;
+                (start <= end).then_some((start, end, edit.replacement.as_str()))
+            })
+            .collect::<Option<Vec<_>>>()
+        else {
+            continue;
+        };
+        let clashes = resolved.iter().any(|&(start, end, _)| {
+            edits
+                .iter()
+                .any(|&(s, e, _)| start == s || (start < e && s < end))
+        });
+        if !clashes {
+            edits.extend(resolved);
+            applied.push(index);
+        }
+    }
+
+    // Apply back to front so earlier offsets stay valid
+    edits.sort_by(|a, b| b.0.cmp(&a.0).then(b.1.cmp(&a.1)));
+    let mut fixed = source.to_string();
+    for (start, end, replacement) in edits {
+        fixed.replace_range(start..end, replacement);
+    }
+    (fixed, applied)
+}
+
 // ── Metadata extraction ───────────────────────────────────────────────────────
 
 struct SpaceMetadata {
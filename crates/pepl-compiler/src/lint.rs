@@ -0,0 +1,1007 @@
+//! Lints: named warnings about code that type-checks but is probably wrong.
+//!
+//! Every lint has a stable name (`unused_let`) and an E8xx code, and is
+//! reported at a [`Level`]: `allow` (silent), `warn` (a warning) or `deny`
+//! (an error that fails compilation).  Levels come from, in increasing
+//! precedence:
+//!
+//! 1. each lint's default (all built-in lints `warn`),
+//! 2. [`LintConfig`] — per compilation, via [`crate::CompileOptions::lints`],
+//! 3. file directives — `// pepl-allow(lint, ...)`, `// pepl-warn(...)` or
+//!    `// pepl-deny(...)` comments before the `space` declaration,
+//! 4. line directives — the same comments anywhere else.  A trailing
+//!    comment covers its own line; a comment on a line of its own covers
+//!    the next line of code, and the whole declaration (action, view,
+//!    state field, ...) if one starts there.
+//!
+//! Lints run after a successful type check, on the AST alone.  The
+//! `division_by_zero`, `math_domain` and `index_out_of_bounds` lints report
+//! what [`crate::interval`] analysis cannot prove safe, and
+//! `unused_capability` what [`crate::effects`] finds no entry point using.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use pepl_types::ast::*;
+use pepl_types::{CompileErrors, ErrorCode, PeplError, Severity, SourceFile, Span};
+use serde::{Deserialize, Serialize};
+
+use crate::checker::if_exits;
+use crate::effects;
+use crate::interval::{self, HazardKind};
+use crate::stdlib;
+
+// ══════════════════════════════════════════════════════════════════════════════
+// Lints
+// ══════════════════════════════════════════════════════════════════════════════
+
+/// How a lint is reported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Level {
+    Allow,
+    Warn,
+    Deny,
+}
+
+/// A built-in lint.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Lint {
+    /// Machine-readable ID, used in configuration and directives.
+    pub name: &'static str,
+    pub code: ErrorCode,
+    pub default_level: Level,
+    pub description: &'static str,
+}
+
+pub const UNUSED_LET: Lint = Lint {
+    name: "unused_let",
+    code: ErrorCode::UNUSED_LET,
+    default_level: Level::Warn,
+    description: "a `let` binding that is never read",
+};
+
+pub const UNUSED_STATE: Lint = Lint {
+    name: "unused_state",
+    code: ErrorCode::UNUSED_STATE,
+    default_level: Level::Warn,
+    description: "a state field that nothing in the space reads",
+};
+
+pub const UNUSED_ACTION: Lint = Lint {
+    name: "unused_action",
+    code: ErrorCode::UNUSED_ACTION,
+    default_level: Level::Warn,
+    description: "an action that no view references and no timer starts",
+};
+
+pub const UNUSED_PARAM: Lint = Lint {
+    name: "unused_param",
+    code: ErrorCode::UNUSED_PARAM,
+    default_level: Level::Warn,
+    description: "an action, view or lambda parameter that is never read",
+};
+
+pub const SHADOWING: Lint = Lint {
+    name: "shadowing",
+    code: ErrorCode::SHADOWED_BINDING,
+    default_level: Level::Warn,
+    description: "a binding that hides a state field, derived field or outer binding",
+};
+
+pub const UNREACHABLE_CODE: Lint = Lint {
+    name: "unreachable_code",
+    code: ErrorCode::UNREACHABLE_CODE,
+    default_level: Level::Warn,
+    description: "statements after a `return`",
+};
+
+pub const FLOAT_EQ: Lint = Lint {
+    name: "float_eq",
+    code: ErrorCode::FLOAT_EQUALITY,
+    default_level: Level::Warn,
+    description: "`==` or `!=` on a number that is likely fractional",
+};
+
+pub const CONSTANT_DERIVED: Lint = Lint {
+    name: "constant_derived",
+    code: ErrorCode::CONSTANT_DERIVED,
+    default_level: Level::Warn,
+    description: "a derived field that depends on no state any action sets",
+};
+
+pub const DIVISION_BY_ZERO: Lint = Lint {
+    name: "division_by_zero",
+    code: ErrorCode::DIVISION_BY_ZERO,
+    default_level: Level::Warn,
+    description: "`/` or `%` by a number that may be zero",
+};
+
+pub const MATH_DOMAIN: Lint = Lint {
+    name: "math_domain",
+    code: ErrorCode::MATH_DOMAIN,
+    default_level: Level::Warn,
+    description: "`math.sqrt` of a number that may be negative",
+};
+
+pub const INDEX_OUT_OF_BOUNDS: Lint = Lint {
+    name: "index_out_of_bounds",
+    code: ErrorCode::INDEX_OUT_OF_BOUNDS,
+    default_level: Level::Warn,
+    description: "a list index that may be negative or past the end",
+};
+
+pub const UNUSED_CAPABILITY: Lint = Lint {
+    name: "unused_capability",
+    code: ErrorCode::UNUSED_CAPABILITY,
+    default_level: Level::Warn,
+    description: "a declared capability no action, update or handleEvent uses",
+};
+
+pub const UNKNOWN_LINT: Lint = Lint {
+    name: "unknown_lint",
+    code: ErrorCode::UNKNOWN_LINT,
+    default_level: Level::Warn,
+    description: "a lint configuration or directive naming no known lint",
+};
+
+/// Every built-in lint.
+pub const LINTS: &[Lint] = &[
+    UNUSED_LET,
+    UNUSED_STATE,
+    UNUSED_ACTION,
+    UNUSED_PARAM,
+    SHADOWING,
+    UNREACHABLE_CODE,
+    FLOAT_EQ,
+    CONSTANT_DERIVED,
+    DIVISION_BY_ZERO,
+    MATH_DOMAIN,
+    INDEX_OUT_OF_BOUNDS,
+    UNUSED_CAPABILITY,
+    UNKNOWN_LINT,
+];
+
+/// Look up a built-in lint by name.
+pub fn find(name: &str) -> Option<&'static Lint> {
+    LINTS.iter().find(|lint| lint.name == name)
+}
+
+// ══════════════════════════════════════════════════════════════════════════════
+// Configuration
+// ══════════════════════════════════════════════════════════════════════════════
+
+/// Lint levels overriding the defaults for one compilation.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct LintConfig {
+    /// Lint name → level.  Unknown names are reported as `unknown_lint`.
+    pub levels: BTreeMap<String, Level>,
+}
+
+impl LintConfig {
+    /// Set the level of `lint`.
+    pub fn with(mut self, lint: &str, level: Level) -> Self {
+        self.levels.insert(lint.to_string(), level);
+        self
+    }
+
+    /// The configured level of `lint`.
+    pub fn level(&self, lint: &Lint) -> Level {
+        self.levels
+            .get(lint.name)
+            .copied()
+            .unwrap_or(lint.default_level)
+    }
+}
+
+// ══════════════════════════════════════════════════════════════════════════════
+// Entry point
+// ══════════════════════════════════════════════════════════════════════════════
+
+/// Run every lint over `program`, pushing warnings (and errors, for denied
+/// lints) into `errors`.
+pub fn check(
+    program: &Program,
+    source: &SourceFile,
+    config: &LintConfig,
+    errors: &mut CompileErrors,
+) {
+    let mut linter = Linter::new(program);
+    linter.program(program);
+    for hazard in interval::analyze(program) {
+        let lint = match hazard.kind {
+            HazardKind::DivisionByZero => &DIVISION_BY_ZERO,
+            HazardKind::MathDomain => &MATH_DOMAIN,
+            HazardKind::IndexOutOfBounds => &INDEX_OUT_OF_BOUNDS,
+        };
+        linter.emit(lint, hazard.message, hazard.span, Some(hazard.suggestion));
+    }
+    if let Some(capabilities) = &program.space.body.capabilities {
+        // Only capabilities backed by a stdlib module show up in code;
+        // host capabilities such as `display` are never "used"
+        let modules = stdlib::capability_modules();
+        let manifest = effects::analyze(program);
+        let used = manifest.used();
+        for capability in capabilities.required.iter().chain(&capabilities.optional) {
+            let name = capability.name.as_str();
+            if modules.contains_key(name) && !used.contains(name) {
+                linter.emit(
+                    &UNUSED_CAPABILITY,
+                    format!(
+                        "capability '{}' is declared but no action, update or handleEvent uses it",
+                        capability.name
+                    ),
+                    capability.span,
+                    Some(format!(
+                        "Remove '{}' from the capabilities block",
+                        capability.name
+                    )),
+                );
+            }
+        }
+    }
+
+    for name in config.levels.keys().filter(|name| find(name).is_none()) {
+        linter.emit(
+            &UNKNOWN_LINT,
+            format!("unknown lint '{}' in lint configuration", name),
+            program.space.name.span,
+            None,
+        );
+    }
+
+    let directives = Directives::parse(source, program);
+    for (name, span) in &directives.unknown {
+        linter.emit(
+            &UNKNOWN_LINT,
+            format!("unknown lint '{}'", name),
+            *span,
+            Some(format!(
+                "Known lints: {}",
+                LINTS.iter().map(|l| l.name).collect::<Vec<_>>().join(", ")
+            )),
+        );
+    }
+
+    let mut diagnostics = linter.diagnostics;
+    diagnostics.sort_by_key(|d| (d.span.start_line, d.span.start_col));
+    for d in diagnostics {
+        let level = directives
+            .level(d.lint, d.span.start_line)
+            .unwrap_or_else(|| config.level(d.lint));
+        if level == Level::Allow {
+            continue;
+        }
+        let source_line = source.line(d.span.start_line).unwrap_or("").to_string();
+        let mut error = PeplError::new(&source.name, d.lint.code, d.message, d.span, source_line)
+            .with_lint(d.lint.name);
+        if let Some(suggestion) = d.suggestion {
+            error = error.with_suggestion(suggestion);
+        }
+        if level == Level::Deny {
+            errors.push_error(error);
+        } else {
+            error.severity = Severity::Warning;
+            errors.push_warning(error);
+        }
+    }
+}
+
+// ══════════════════════════════════════════════════════════════════════════════
+// Directives
+// ══════════════════════════════════════════════════════════════════════════════
+
+/// `pepl-allow(...)` / `pepl-warn(...)` / `pepl-deny(...)` comments.
+#[derive(Default)]
+struct Directives {
+    /// Levels set before the `space` declaration.
+    file: HashMap<&'static str, Level>,
+    /// `(first line, last line, lint, level)`, in source order.
+    lines: Vec<(u32, u32, &'static str, Level)>,
+    /// Unknown lint names, with where they were written.
+    unknown: Vec<(String, Span)>,
+}
+
+impl Directives {
+    fn parse(source: &SourceFile, program: &Program) -> Self {
+        let mut directives = Self::default();
+        let line_count = source.source.lines().count() as u32;
+        let declarations = declaration_spans(program);
+        for line_number in 1..=line_count {
+            let line = source.line(line_number).unwrap_or("");
+            let Some(comment_at) = comment_start(line) else {
+                continue;
+            };
+            let comment = &line[comment_at + 2..];
+            let Some((level, names)) = parse_directive(comment) else {
+                continue;
+            };
+            let own_line = line[..comment_at].trim().is_empty();
+            let (first, last) = if line_number < program.space.span.start_line {
+                (0, 0)
+            } else if own_line {
+                let Some(target) = (line_number + 1..=line_count).find(|&n| {
+                    let text = source.line(n).unwrap_or("").trim();
+                    !text.is_empty() && !text.starts_with("//")
+                }) else {
+                    continue;
+                };
+                let end = declarations
+                    .iter()
+                    .filter(|span| span.start_line == target)
+                    .map(|span| span.end_line)
+                    .max()
+                    .unwrap_or(target);
+                (target, end)
+            } else {
+                (line_number, line_number)
+            };
+            for (offset, name) in names {
+                let col = (comment_at + 2 + offset) as u32 + 1;
+                match find(name) {
+                    Some(lint) if first == 0 => {
+                        directives.file.insert(lint.name, level);
+                    }
+                    Some(lint) => directives.lines.push((first, last, lint.name, level)),
+                    None => directives.unknown.push((
+                        name.to_string(),
+                        Span::new(line_number, col, line_number, col + name.len() as u32),
+                    )),
+                }
+            }
+        }
+        directives
+    }
+
+    /// The level directives give `lint` on `line`, if any.  The innermost
+    /// (latest-starting) line directive wins over file directives.
+    fn level(&self, lint: &Lint, line: u32) -> Option<Level> {
+        self.lines
+            .iter()
+            .filter(|(first, last, name, _)| *name == lint.name && (*first..=*last).contains(&line))
+            .max_by_key(|(first, ..)| *first)
+            .map(|(.., level)| *level)
+            .or_else(|| self.file.get(lint.name).copied())
+    }
+}
+
+/// Byte offset of a `//` comment in `line`, ignoring `//` inside strings.
+fn comment_start(line: &str) -> Option<usize> {
+    let bytes = line.as_bytes();
+    let mut in_string = false;
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' if in_string => i += 1,
+            b'"' => in_string = !in_string,
+            b'/' if !in_string && bytes.get(i + 1) == Some(&b'/') => return Some(i),
+            _ => {}
+        }
+        i += 1;
+    }
+    None
+}
+
+/// Parse `pepl-<level>(a, b)` at the start of a comment body into the
+/// level and each name with its byte offset in the comment.
+fn parse_directive(comment: &str) -> Option<(Level, Vec<(usize, &str)>)> {
+    let rest = comment.trim_start().strip_prefix("pepl-")?;
+    let (level, rest) = [
+        ("allow(", Level::Allow),
+        ("warn(", Level::Warn),
+        ("deny(", Level::Deny),
+    ]
+    .iter()
+    .find_map(|(prefix, level)| rest.strip_prefix(prefix).map(|rest| (*level, rest)))?;
+    let list = &rest[..rest.find(')')?];
+    let mut names = Vec::new();
+    let mut offset = comment.len() - rest.len();
+    for part in list.split(',') {
+        let name = part.trim();
+        if !name.is_empty() {
+            names.push((offset + part.find(name).unwrap_or(0), name));
+        }
+        offset += part.len() + 1;
+    }
+    Some((level, names))
+}
+
+/// Spans of the declarations a line directive can cover as a whole.
+fn declaration_spans(program: &Program) -> Vec<Span> {
+    let body = &program.space.body;
+    let mut spans: Vec<Span> = body.state.fields.iter().map(|f| f.span).collect();
+    if let Some(derived) = &body.derived {
+        spans.extend(derived.fields.iter().map(|f| f.span));
+    }
+    spans.extend(body.invariants.iter().map(|i| i.span));
+    spans.extend(body.actions.iter().map(|a| a.span));
+    spans.extend(body.views.iter().map(|v| v.span));
+    spans.extend(body.update.iter().map(|u| u.span));
+    spans.extend(body.handle_event.iter().map(|h| h.span));
+    spans.extend(
+        program
+            .tests
+            .iter()
+            .flat_map(|t| t.cases.iter().map(|c| c.span)),
+    );
+    spans
+}
+
+// ══════════════════════════════════════════════════════════════════════════════
+// Linter
+// ══════════════════════════════════════════════════════════════════════════════
+
+struct Diagnostic {
+    lint: &'static Lint,
+    message: String,
+    span: Span,
+    suggestion: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LocalKind {
+    Let,
+    Param,
+    /// Loop variables, match bindings and the `update`/`handleEvent`
+    /// parameters — never reported as unused.
+    Other,
+}
+
+struct Local {
+    name: String,
+    span: Span,
+    kind: LocalKind,
+    used: bool,
+}
+
+struct Linter<'a> {
+    program: &'a Program,
+    scopes: Vec<Vec<Local>>,
+    state_fields: HashSet<&'a str>,
+    derived_fields: HashSet<&'a str>,
+    action_names: HashSet<&'a str>,
+    /// State and derived fields read anywhere.
+    reads: HashSet<String>,
+    /// Root state fields some `set` assigns.
+    mutated: HashSet<String>,
+    /// Whether a `time.now()` or capability call was seen since this was
+    /// last cleared.
+    reads_host: bool,
+    /// Actions referenced from a view or started by a timer.
+    view_refs: HashSet<String>,
+    in_view: bool,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'a> Linter<'a> {
+    fn new(program: &'a Program) -> Self {
+        let body = &program.space.body;
+        Self {
+            program,
+            scopes: Vec::new(),
+            state_fields: body
+                .state
+                .fields
+                .iter()
+                .map(|f| f.name.name.as_str())
+                .collect(),
+            derived_fields: body
+                .derived
+                .iter()
+                .flat_map(|d| d.fields.iter().map(|f| f.name.name.as_str()))
+                .collect(),
+            action_names: body.actions.iter().map(|a| a.name.name.as_str()).collect(),
+            reads: HashSet::new(),
+            mutated: HashSet::new(),
+            reads_host: false,
+            view_refs: HashSet::new(),
+            in_view: false,
+            diagnostics: Vec::new(),
+        }
+    }
+
+    fn emit(
+        &mut self,
+        lint: &'static Lint,
+        message: String,
+        span: Span,
+        suggestion: Option<String>,
+    ) {
+        self.diagnostics.push(Diagnostic {
+            lint,
+            message,
+            span,
+            suggestion,
+        });
+    }
+
+    // ── Declarations ──────────────────────────────────────────────────────
+
+    fn program(&mut self, program: &'a Program) {
+        let body = &program.space.body;
+        for field in &body.state.fields {
+            self.expr(&field.default);
+        }
+
+        // Derived fields: remember what each reads, to find constant ones
+        let mut derived_reads = Vec::new();
+        if let Some(derived) = &body.derived {
+            for field in &derived.fields {
+                let outer = std::mem::take(&mut self.reads);
+                self.reads_host = false;
+                self.expr(&field.value);
+                let reads = std::mem::replace(&mut self.reads, outer);
+                self.reads.extend(reads.iter().cloned());
+                derived_reads.push((field, reads, self.reads_host));
+            }
+        }
+
+        for invariant in &body.invariants {
+            self.expr(&invariant.condition);
+        }
+        for action in &body.actions {
+            self.push_scope();
+            for param in &action.params {
+                self.define(&param.name.name, param.name.span, LocalKind::Param);
+            }
+            self.block(&action.body);
+            self.pop_scope();
+        }
+        for view in &body.views {
+            self.in_view = true;
+            self.push_scope();
+            for param in &view.params {
+                self.define(&param.name.name, param.name.span, LocalKind::Param);
+            }
+            self.ui_block(&view.body);
+            self.pop_scope();
+            self.in_view = false;
+        }
+        if let Some(update) = &body.update {
+            self.entry(&update.param, &update.body);
+        }
+        if let Some(handle_event) = &body.handle_event {
+            self.entry(&handle_event.param, &handle_event.body);
+        }
+        for case in program.tests.iter().flat_map(|t| &t.cases) {
+            if let Some(responses) = &case.with_responses {
+                for mapping in &responses.mappings {
+                    self.exprs(&mapping.args);
+                    self.expr(&mapping.response);
+                }
+            }
+            self.block(&case.body);
+        }
+
+        self.unused_state();
+        self.unused_actions();
+        self.constant_derived(&derived_reads);
+    }
+
+    fn entry(&mut self, param: &Param, body: &Block) {
+        self.push_scope();
+        self.define(&param.name.name, param.name.span, LocalKind::Other);
+        self.block(body);
+        self.pop_scope();
+    }
+
+    fn unused_state(&mut self) {
+        let body = &self.program.space.body;
+        for field in &body.state.fields {
+            if !self.reads.contains(&field.name.name) {
+                self.emit(
+                    &UNUSED_STATE,
+                    format!("state field '{}' is never read", field.name.name),
+                    field.name.span,
+                    Some("Remove the field, or read it in a view or derived field".to_string()),
+                );
+            }
+        }
+    }
+
+    fn unused_actions(&mut self) {
+        let body = &self.program.space.body;
+        if body.views.is_empty() {
+            return;
+        }
+        for action in &body.actions {
+            if !self.view_refs.contains(&action.name.name) {
+                self.emit(
+                    &UNUSED_ACTION,
+                    format!(
+                        "action '{}' is not referenced by any view",
+                        action.name.name
+                    ),
+                    action.name.span,
+                    Some(format!(
+                        "Wire it to a component event, e.g. `on_tap: {}`, or remove it",
+                        action.name.name
+                    )),
+                );
+            }
+        }
+    }
+
+    /// Derived fields whose inputs no action, `update` or `handleEvent`
+    /// ever sets — directly or through other derived fields.  Fields that
+    /// read the host (`time.now()`, a capability) change on their own.
+    fn constant_derived(&mut self, derived_reads: &[(&DerivedField, HashSet<String>, bool)]) {
+        let mut changing: HashSet<&str> = derived_reads
+            .iter()
+            .filter(|(_, _, reads_host)| *reads_host)
+            .map(|(field, _, _)| field.name.name.as_str())
+            .collect();
+        loop {
+            let before = changing.len();
+            for (field, reads, _) in derived_reads {
+                if reads
+                    .iter()
+                    .any(|name| self.mutated.contains(name) || changing.contains(name.as_str()))
+                {
+                    changing.insert(&field.name.name);
+                }
+            }
+            if changing.len() == before {
+                break;
+            }
+        }
+        for (field, _, _) in derived_reads {
+            if !changing.contains(field.name.name.as_str()) {
+                self.emit(
+                    &CONSTANT_DERIVED,
+                    format!(
+                        "derived field '{}' never changes: nothing it depends on is ever set",
+                        field.name.name
+                    ),
+                    field.name.span,
+                    Some("Use a constant or a state field instead".to_string()),
+                );
+            }
+        }
+    }
+
+    // ── Scopes ────────────────────────────────────────────────────────────
+
+    fn push_scope(&mut self) {
+        self.scopes.push(Vec::new());
+    }
+
+    fn pop_scope(&mut self) {
+        for local in self.scopes.pop().unwrap_or_default() {
+            if local.used || local.name.starts_with('_') {
+                continue;
+            }
+            match local.kind {
+                LocalKind::Let => self.emit(
+                    &UNUSED_LET,
+                    format!("'{}' is never read", local.name),
+                    local.span,
+                    Some(format!(
+                        "Remove the binding, use `let _ = ...`, or rename it to `_{}`",
+                        local.name
+                    )),
+                ),
+                LocalKind::Param => self.emit(
+                    &UNUSED_PARAM,
+                    format!("parameter '{}' is never read", local.name),
+                    local.span,
+                    Some(format!("Rename it to `_{}` if it is needed", local.name)),
+                ),
+                LocalKind::Other => {}
+            }
+        }
+    }
+
+    fn define(&mut self, name: &str, span: Span, kind: LocalKind) {
+        if !name.starts_with('_') {
+            let shadowed = if self.scopes.iter().flatten().any(|l| l.name == name) {
+                Some("an outer binding")
+            } else if self.state_fields.contains(name) {
+                Some("a state field")
+            } else if self.derived_fields.contains(name) {
+                Some("a derived field")
+            } else {
+                None
+            };
+            if let Some(shadowed) = shadowed {
+                self.emit(
+                    &SHADOWING,
+                    format!("'{}' shadows {}", name, shadowed),
+                    span,
+                    Some("Pick a distinct name".to_string()),
+                );
+            }
+        }
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.push(Local {
+                name: name.to_string(),
+                span,
+                kind,
+                used: false,
+            });
+        }
+    }
+
+    /// Record a read of `name`.
+    fn read(&mut self, name: &str) {
+        let local = self
+            .scopes
+            .iter_mut()
+            .rev()
+            .flat_map(|scope| scope.iter_mut().rev())
+            .find(|local| local.name == name);
+        if let Some(local) = local {
+            local.used = true;
+            return;
+        }
+        if self.state_fields.contains(name) || self.derived_fields.contains(name) {
+            self.reads.insert(name.to_string());
+        }
+        if self.in_view && self.action_names.contains(name) {
+            self.view_refs.insert(name.to_string());
+        }
+    }
+
+    // ── Statements ────────────────────────────────────────────────────────
+
+    fn block(&mut self, block: &Block) {
+        self.push_scope();
+        self.stmts(&block.stmts);
+        self.pop_scope();
+    }
+
+    fn stmts(&mut self, stmts: &[Stmt]) {
+        let mut exited = false;
+        let mut reported = false;
+        for stmt in stmts {
+            if exited && !reported {
+                self.emit(
+                    &UNREACHABLE_CODE,
+                    "unreachable code after `return`".to_string(),
+                    stmt_span(stmt),
+                    Some("Remove the statements after `return`".to_string()),
+                );
+                reported = true;
+            }
+            self.stmt(stmt);
+            exited |= match stmt {
+                Stmt::Return(_) => true,
+                Stmt::If(if_expr) => if_exits(if_expr),
+                _ => false,
+            };
+        }
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Set(set) => {
+                self.expr(&set.value);
+                if let Some(root) = set.target.first() {
+                    self.mutated.insert(root.name.clone());
+                }
+            }
+            Stmt::Let(binding) => self.let_binding(binding),
+            Stmt::If(if_expr) => self.if_expr(if_expr),
+            Stmt::For(for_expr) => self.for_expr(for_expr),
+            Stmt::Match(match_expr) => self.match_expr(match_expr),
+            Stmt::Return(_) => {}
+            Stmt::Assert(assert) => self.expr(&assert.condition),
+            Stmt::Expr(expr_stmt) => self.expr(&expr_stmt.expr),
+        }
+    }
+
+    fn let_binding(&mut self, binding: &LetBinding) {
+        self.expr(&binding.value);
+        if let Some(name) = &binding.name {
+            self.define(&name.name, name.span, LocalKind::Let);
+        }
+    }
+
+    fn if_expr(&mut self, if_expr: &IfExpr) {
+        self.expr(&if_expr.condition);
+        self.block(&if_expr.then_block);
+        match &if_expr.else_branch {
+            Some(ElseBranch::ElseIf(elif)) => self.if_expr(elif),
+            Some(ElseBranch::Block(block)) => self.block(block),
+            None => {}
+        }
+    }
+
+    fn for_expr(&mut self, for_expr: &ForExpr) {
+        self.expr(&for_expr.iterable);
+        self.push_scope();
+        self.define(&for_expr.item.name, for_expr.item.span, LocalKind::Other);
+        if let Some(index) = &for_expr.index {
+            self.define(&index.name, index.span, LocalKind::Other);
+        }
+        self.block(&for_expr.body);
+        self.pop_scope();
+    }
+
+    fn match_expr(&mut self, match_expr: &MatchExpr) {
+        self.expr(&match_expr.subject);
+        for arm in &match_expr.arms {
+            self.push_scope();
+            if let Pattern::Variant { bindings, .. } = &arm.pattern {
+                for binding in bindings {
+                    self.define(&binding.name, binding.span, LocalKind::Other);
+                }
+            }
+            match &arm.body {
+                MatchArmBody::Expr(expr) => self.expr(expr),
+                MatchArmBody::Block(block) => self.block(block),
+            }
+            self.pop_scope();
+        }
+    }
+
+    // ── Views ─────────────────────────────────────────────────────────────
+
+    fn ui_block(&mut self, block: &UIBlock) {
+        self.push_scope();
+        for element in &block.elements {
+            match element {
+                UIElement::Component(component) => {
+                    for prop in &component.props {
+                        self.expr(&prop.value);
+                    }
+                    if let Some(children) = &component.children {
+                        self.ui_block(children);
+                    }
+                }
+                UIElement::Let(binding) => self.let_binding(binding),
+                UIElement::If(ui_if) => self.ui_if(ui_if),
+                UIElement::For(ui_for) => {
+                    self.expr(&ui_for.iterable);
+                    self.push_scope();
+                    self.define(&ui_for.item.name, ui_for.item.span, LocalKind::Other);
+                    if let Some(index) = &ui_for.index {
+                        self.define(&index.name, index.span, LocalKind::Other);
+                    }
+                    self.ui_block(&ui_for.body);
+                    self.pop_scope();
+                }
+            }
+        }
+        self.pop_scope();
+    }
+
+    fn ui_if(&mut self, ui_if: &UIIf) {
+        self.expr(&ui_if.condition);
+        self.ui_block(&ui_if.then_block);
+        match &ui_if.else_block {
+            Some(UIElse::ElseIf(elif)) => self.ui_if(elif),
+            Some(UIElse::Block(block)) => self.ui_block(block),
+            None => {}
+        }
+    }
+
+    // ── Expressions ───────────────────────────────────────────────────────
+
+    fn exprs(&mut self, exprs: &[Expr]) {
+        for expr in exprs {
+            self.expr(expr);
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        match &expr.kind {
+            ExprKind::NumberLit(_)
+            | ExprKind::StringLit(_)
+            | ExprKind::BoolLit(_)
+            | ExprKind::NilLit => {}
+            ExprKind::StringInterpolation(parts) => {
+                for part in parts {
+                    if let StringPart::Expr(expr) = part {
+                        self.expr(expr);
+                    }
+                }
+            }
+            ExprKind::ListLit(items) => self.exprs(items),
+            ExprKind::RecordLit(entries) => {
+                for entry in entries {
+                    match entry {
+                        RecordEntry::Field { value, .. } => self.expr(value),
+                        RecordEntry::Spread(expr) => self.expr(expr),
+                    }
+                }
+            }
+            ExprKind::Identifier(name) => self.read(name),
+            ExprKind::Call { name, args } => {
+                self.read(&name.name);
+                self.exprs(args);
+            }
+            ExprKind::QualifiedCall {
+                module,
+                function,
+                args,
+            } => {
+                // `timer.start("tick", 1000)` dispatches the action `tick`
+                if module.name == "timer" && function.name.starts_with("start") {
+                    if let Some(ExprKind::StringLit(action)) = args.first().map(|a| &a.kind) {
+                        self.view_refs.insert(action.clone());
+                    }
+                }
+                self.reads_host |= is_host_call(&module.name, &function.name);
+                self.exprs(args);
+            }
+            ExprKind::FieldAccess { object, .. } => self.expr(object),
+            ExprKind::MethodCall { object, args, .. } => {
+                self.expr(object);
+                self.exprs(args);
+            }
+            ExprKind::Binary { left, op, right } => {
+                if matches!(op, BinOp::Eq | BinOp::NotEq)
+                    && (maybe_fractional(left) || maybe_fractional(right))
+                {
+                    self.emit(
+                        &FLOAT_EQ,
+                        format!("comparing fractional numbers with `{}`", op.as_str()),
+                        expr.span,
+                        Some(
+                            "Compare against a tolerance, e.g. `math.abs(a - b) < 0.0001`"
+                                .to_string(),
+                        ),
+                    );
+                }
+                self.expr(left);
+                self.expr(right);
+            }
+            ExprKind::Unary { operand, .. } => self.expr(operand),
+            ExprKind::ResultUnwrap(inner) | ExprKind::Paren(inner) => self.expr(inner),
+            ExprKind::NilCoalesce { left, right } => {
+                self.expr(left);
+                self.expr(right);
+            }
+            ExprKind::If(if_expr) => self.if_expr(if_expr),
+            ExprKind::For(for_expr) => self.for_expr(for_expr),
+            ExprKind::Match(match_expr) => self.match_expr(match_expr),
+            ExprKind::Lambda(lambda) => {
+                self.push_scope();
+                for param in &lambda.params {
+                    self.define(&param.name.name, param.name.span, LocalKind::Param);
+                }
+                self.block(&lambda.body);
+                self.pop_scope();
+            }
+        }
+    }
+}
+
+/// Whether `module.function` asks the host, so that its result can change
+/// with no field having been set.
+fn is_host_call(module: &str, function: &str) -> bool {
+    stdlib::capability_modules().contains_key(module) || (module, function) == ("time", "now")
+}
+
+/// Whether `expr` is a number that is likely not an integer: a fractional
+/// literal, or arithmetic involving one or a division.
+fn maybe_fractional(expr: &Expr) -> bool {
+    match &expr.kind {
+        ExprKind::NumberLit(n) => n.fract() != 0.0,
+        ExprKind::Binary { op: BinOp::Div, .. } => true,
+        ExprKind::Binary {
+            left,
+            op: BinOp::Add | BinOp::Sub | BinOp::Mul | BinOp::Mod,
+            right,
+        } => maybe_fractional(left) || maybe_fractional(right),
+        ExprKind::Unary {
+            op: UnaryOp::Neg,
+            operand,
+        } => maybe_fractional(operand),
+        ExprKind::Paren(inner) => maybe_fractional(inner),
+        _ => false,
+    }
+}
+
+fn stmt_span(stmt: &Stmt) -> Span {
+    match stmt {
+        Stmt::Set(set) => set.span,
+        Stmt::Let(binding) => binding.span,
+        Stmt::If(if_expr) => if_expr.span,
+        Stmt::For(for_expr) => for_expr.span,
+        Stmt::Match(match_expr) => match_expr.span,
+        Stmt::Return(ret) => ret.span,
+        Stmt::Assert(assert) => assert.span,
+        Stmt::Expr(expr_stmt) => expr_stmt.span,
+    }
+}
@@ -292,6 +292,7 @@ CAPABILITIES (require declaration + host support):
 CREDENTIALS:
   Declared in credentials {} block — host prompts user, injects at runtime
   Access: api_key is a read-only binding in the space — NEVER put API keys in source
+  Flow: may only reach http request URLs and bodies — never log, store, or display one
 
 UI COMPONENTS (record-style syntax):
   Layout: Column { ... }, Row { ... }, Scroll { ... }
@@ -0,0 +1,428 @@
+//! Automatic repair of near-miss PEPL source.
+//!
+//! LLM-generated spaces often fail to compile for reasons with exactly one
+//! sensible fix: a function name borrowed from JavaScript (`list.len`),
+//! `===`, a `/* */` comment, blocks in the wrong order, or a capability
+//! that was used but not declared.  [`repair`] applies those fixes in
+//! rounds, re-checking the source after each one, until it compiles or no
+//! safe fix is left.
+//!
+//! Only fixes that cannot change what the program means are applied:
+//! the textual rewrites below, and diagnostics' [`Fix`]es marked
+//! [`Applicability::MachineApplicable`].  Anything needing judgement is
+//! left in the returned errors.
+
+use pepl_types::ast::Program;
+use pepl_types::{Applicability, CompileErrors, ErrorCode, Fix, SourceFile, Span};
+use serde::{Deserialize, Serialize};
+
+use crate::type_check;
+
+/// Rounds of fixing before [`repair`] gives up.
+pub const MAX_ROUNDS: usize = 8;
+
+/// What kind of repair an edit made.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RepairKind {
+    /// A diagnostic's machine-applicable fix (stdlib alias, capability).
+    Fix,
+    /// `===` / `!==` replaced with `==` / `!=`.
+    StrictEquality,
+    /// A `/* */` comment rewritten as `//` comments (E603).
+    BlockComment,
+    /// Space blocks moved into the required order (E600).
+    BlockOrder,
+}
+
+/// One logged repair.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RepairEdit {
+    /// The round (from 1) that made the edit.
+    pub round: usize,
+    pub kind: RepairKind,
+    /// What changed, e.g. `replace with 'length'`.
+    pub description: String,
+    /// Line of the edit in the source as it was at the start of the round.
+    pub line: u32,
+}
+
+/// The result of [`repair`] (JSON-serializable).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RepairResult {
+    /// Whether the repaired source type-checks.
+    pub success: bool,
+    /// The source after all repairs, even if errors remain.
+    pub source: String,
+    /// Every repair made, in order.
+    pub edits: Vec<RepairEdit>,
+    /// Errors (and warnings) of the repaired source.
+    pub errors: CompileErrors,
+}
+
+/// Repair `source` as far as safe fixes allow.
+pub fn repair(source: &str, name: &str) -> RepairResult {
+    let mut source = source.to_string();
+    let mut edits = Vec::new();
+    let mut errors = type_check(&source, name);
+
+    for round in 1..=MAX_ROUNDS {
+        if !errors.has_errors() {
+            break;
+        }
+        let before = edits.len();
+        source = apply_machine_fixes(&source, &errors, round, &mut edits);
+        source = replace_strict_equality(&source, round, &mut edits);
+        if errors
+            .errors
+            .iter()
+            .any(|e| e.code == ErrorCode::BLOCK_COMMENT_USED)
+        {
+            source = rewrite_block_comments(&source, round, &mut edits);
+        }
+        if errors
+            .errors
+            .iter()
+            .any(|e| e.code == ErrorCode::BLOCK_ORDERING_VIOLATED)
+        {
+            source = reorder_blocks(&source, name, round, &mut edits);
+        }
+        if edits.len() == before {
+            break;
+        }
+        errors = type_check(&source, name);
+    }
+
+    RepairResult {
+        success: !errors.has_errors(),
+        source,
+        edits,
+        errors,
+    }
+}
+
+// ══════════════════════════════════════════════════════════════════════════════
+// Passes
+// ══════════════════════════════════════════════════════════════════════════════
+
+fn apply_machine_fixes(
+    source: &str,
+    errors: &CompileErrors,
+    round: usize,
+    edits: &mut Vec<RepairEdit>,
+) -> String {
+    let fixes: Vec<(&Fix, u32)> = errors
+        .errors
+        .iter()
+        .filter_map(|e| Some((e.fixes.first()?, e.span.start_line)))
+        .filter(|(fix, _)| fix.applicability == Applicability::MachineApplicable)
+        .collect();
+    let (fixed, applied) = crate::apply_fix_list(source, fixes.iter().map(|(fix, _)| *fix));
+    edits.extend(applied.into_iter().map(|i| RepairEdit {
+        round,
+        kind: RepairKind::Fix,
+        description: fixes[i].0.label.clone(),
+        line: fixes[i].1,
+    }));
+    fixed
+}
+
+fn replace_strict_equality(source: &str, round: usize, edits: &mut Vec<RepairEdit>) -> String {
+    let mut fixed = String::with_capacity(source.len());
+    let mut last = 0;
+    for region in scan(source).iter().filter(|r| r.kind == RegionKind::Code) {
+        let code = &source[region.start..region.end];
+        let mut from = 0;
+        while let Some(at) = code[from..].find("==").map(|i| from + i) {
+            let bang = at > 0 && code.as_bytes()[at - 1] == b'!';
+            if bang || code.as_bytes().get(at + 2) == Some(&b'=') {
+                let (op, fixed_op) = if bang { ("!==", "!=") } else { ("===", "==") };
+                let start = region.start + at - usize::from(bang);
+                fixed.push_str(&source[last..start]);
+                fixed.push_str(fixed_op);
+                last = start + op.len();
+                edits.push(RepairEdit {
+                    round,
+                    kind: RepairKind::StrictEquality,
+                    description: format!("replace `{op}` with `{fixed_op}`"),
+                    line: line_of(source, start),
+                });
+                from = start - region.start + op.len();
+            } else {
+                from = at + 2;
+            }
+        }
+    }
+    fixed.push_str(&source[last..]);
+    fixed
+}
+
+/// Rewrite each `/* */` comment as `//` lines above the code it was on.
+fn rewrite_block_comments(source: &str, round: usize, edits: &mut Vec<RepairEdit>) -> String {
+    let mut fixed = String::with_capacity(source.len());
+    let mut last = 0;
+    for region in scan(source)
+        .iter()
+        .filter(|r| r.kind == RegionKind::BlockComment)
+    {
+        // The comment's first and last lines, whole
+        let line_start = source[..region.start].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = source[region.end..]
+            .find('\n')
+            .map_or(source.len(), |i| region.end + i);
+        if line_start < last {
+            // Shares a line with the previous comment; the next round gets it
+            continue;
+        }
+        let before = &source[line_start..region.start];
+        let after = &source[region.end..line_end];
+        let indent = &before[..before.len() - before.trim_start().len()];
+
+        let body = source[region.start + 2..region.end]
+            .strip_suffix("*/")
+            .unwrap_or(&source[region.start + 2..region.end]);
+        let mut lines: Vec<String> = body
+            .lines()
+            .map(|l| l.trim().trim_start_matches('*').trim())
+            .filter(|l| !l.is_empty())
+            .map(|l| format!("{indent}// {l}"))
+            .collect();
+        let code = format!("{} {}", before.trim(), after.trim());
+        if !code.trim().is_empty() {
+            lines.push(format!("{indent}{}", code.trim()));
+        }
+
+        fixed.push_str(&source[last..line_start]);
+        fixed.push_str(&lines.join("\n"));
+        last = line_end;
+        edits.push(RepairEdit {
+            round,
+            kind: RepairKind::BlockComment,
+            description: "replace `/* */` with `//` comments".to_string(),
+            line: line_of(source, region.start),
+        });
+    }
+    fixed.push_str(&source[last..]);
+    fixed
+}
+
+/// The enforced block order (see E600).
+const BLOCK_ORDER: [&str; 10] = [
+    "type",
+    "state",
+    "capabilities",
+    "credentials",
+    "derived",
+    "invariant",
+    "action",
+    "view",
+    "update",
+    "handleEvent",
+];
+
+/// Move space blocks, with the comments directly above them, into the
+/// enforced order.  Blank lines and other text between blocks stay where
+/// they are.  Does nothing unless every block starts and ends on a line of
+/// its own.
+fn reorder_blocks(source: &str, name: &str, round: usize, edits: &mut Vec<RepairEdit>) -> String {
+    let Some(program) = parse(source, name) else {
+        return source.to_string();
+    };
+    let file = SourceFile::new(name, source);
+    let mut blocks = declarations(&program);
+    blocks.sort_by_key(|(_, span)| (span.start_line, span.start_col));
+
+    // Line ranges (1-based, inclusive) of each block and its comments
+    let mut slots = Vec::new();
+    let mut previous_end = program.space.span.start_line;
+    for &(rank, span) in &blocks {
+        let start_line = file.line(span.start_line).unwrap_or("");
+        let end_line = file.line(span.end_line).unwrap_or("");
+        let trailing = end_line.get(span.end_col as usize..).unwrap_or("").trim();
+        if span.start_line <= previous_end
+            || !start_line[..(span.start_col as usize - 1).min(start_line.len())]
+                .trim()
+                .is_empty()
+            || !(trailing.is_empty() || trailing.starts_with("//"))
+        {
+            return source.to_string();
+        }
+        let mut first = span.start_line;
+        while first - 1 > previous_end
+            && file
+                .line(first - 1)
+                .is_some_and(|l| l.trim_start().starts_with("//"))
+        {
+            first -= 1;
+        }
+        slots.push((rank, first, span.end_line));
+        previous_end = span.end_line;
+    }
+
+    let mut sorted = slots.clone();
+    sorted.sort_by_key(|&(rank, ..)| rank);
+    if sorted == slots {
+        return source.to_string();
+    }
+
+    let lines: Vec<&str> = source.split('\n').collect();
+    let text = |first: u32, last: u32| lines[first as usize - 1..last as usize].join("\n");
+    let mut fixed = Vec::new();
+    let mut next = 1;
+    for (slot, block) in slots.iter().zip(&sorted) {
+        fixed.extend(
+            lines[next - 1..slot.1 as usize - 1]
+                .iter()
+                .map(|l| l.to_string()),
+        );
+        fixed.push(text(block.1, block.2));
+        next = slot.2 as usize + 1;
+        if slot != block {
+            edits.push(RepairEdit {
+                round,
+                kind: RepairKind::BlockOrder,
+                description: format!(
+                    "move '{}' block into the required order",
+                    BLOCK_ORDER[block.0]
+                ),
+                line: block.1,
+            });
+        }
+    }
+    fixed.extend(lines[next - 1..].iter().map(|l| l.to_string()));
+    fixed.join("\n")
+}
+
+// ══════════════════════════════════════════════════════════════════════════════
+// Helpers
+// ══════════════════════════════════════════════════════════════════════════════
+
+/// Parse `source`, keeping the program even if the parser reported errors.
+fn parse(source: &str, name: &str) -> Option<Program> {
+    let file = SourceFile::new(name, source);
+    let lexed = pepl_lexer::Lexer::new(&file).lex();
+    if lexed.errors.has_errors() {
+        return None;
+    }
+    pepl_parser::Parser::new(lexed.tokens, &file)
+        .parse()
+        .program
+}
+
+/// Every block of the space body with its rank in [`BLOCK_ORDER`].
+fn declarations(program: &Program) -> Vec<(usize, Span)> {
+    let body = &program.space.body;
+    let mut blocks: Vec<(usize, Span)> = Vec::new();
+    blocks.extend(body.types.iter().map(|t| (0, t.span)));
+    blocks.push((1, body.state.span));
+    blocks.extend(body.capabilities.iter().map(|c| (2, c.span)));
+    blocks.extend(body.credentials.iter().map(|c| (3, c.span)));
+    blocks.extend(body.derived.iter().map(|d| (4, d.span)));
+    blocks.extend(body.invariants.iter().map(|i| (5, i.span)));
+    blocks.extend(body.actions.iter().map(|a| (6, a.span)));
+    blocks.extend(body.views.iter().map(|v| (7, v.span)));
+    blocks.extend(body.update.iter().map(|u| (8, u.span)));
+    blocks.extend(body.handle_event.iter().map(|h| (9, h.span)));
+    blocks
+}
+
+fn line_of(source: &str, offset: usize) -> u32 {
+    source[..offset].matches('\n').count() as u32 + 1
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RegionKind {
+    Code,
+    String,
+    LineComment,
+    BlockComment,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Region {
+    kind: RegionKind,
+    start: usize,
+    end: usize,
+}
+
+/// Split `source` into code, strings, and comments.  Code inside a
+/// string's `${...}` counts as code.
+fn scan(source: &str) -> Vec<Region> {
+    enum Mode {
+        Code,
+        String,
+        /// Inside `${`, with the depth of nested `{`.
+        Interpolation(usize),
+    }
+
+    let bytes = source.as_bytes();
+    let mut regions: Vec<Region> = Vec::new();
+    let mut push = |kind: RegionKind, start: usize, end: usize| {
+        if start == end {
+            return;
+        }
+        match regions.last_mut() {
+            Some(last) if last.kind == kind && last.end == start => last.end = end,
+            _ => regions.push(Region { kind, start, end }),
+        }
+    };
+
+    let mut modes = vec![Mode::Code];
+    let mut i = 0;
+    while i < bytes.len() {
+        let in_code = !matches!(modes.last(), Some(Mode::String));
+        let start = i;
+        match (modes.last_mut(), bytes[i], bytes.get(i + 1)) {
+            (Some(Mode::String), b'\\', _) => i += 2,
+            (Some(Mode::String), b'"', _) => {
+                modes.pop();
+                i += 1;
+            }
+            (Some(Mode::String), b'$', Some(b'{')) => {
+                modes.push(Mode::Interpolation(0));
+                i += 2;
+            }
+            (Some(Mode::String), ..) => i += 1,
+            (_, b'"', _) => {
+                modes.push(Mode::String);
+                push(RegionKind::String, i, i + 1);
+                i += 1;
+                continue;
+            }
+            (_, b'/', Some(b'/')) => {
+                i = source[i..].find('\n').map_or(bytes.len(), |n| i + n);
+                push(RegionKind::LineComment, start, i);
+                continue;
+            }
+            (_, b'/', Some(b'*')) => {
+                i = source[i + 2..]
+                    .find("*/")
+                    .map_or(bytes.len(), |n| i + 2 + n + 2);
+                push(RegionKind::BlockComment, start, i);
+                continue;
+            }
+            (Some(Mode::Interpolation(depth)), b'{', _) => {
+                *depth += 1;
+                i += 1;
+            }
+            (Some(Mode::Interpolation(0)), b'}', _) => {
+                modes.pop();
+                push(RegionKind::String, i, i + 1);
+                i += 1;
+                continue;
+            }
+            (Some(Mode::Interpolation(depth)), b'}', _) => {
+                *depth -= 1;
+                i += 1;
+            }
+            _ => i += 1,
+        }
+        let kind = if in_code {
+            RegionKind::Code
+        } else {
+            RegionKind::String
+        };
+        push(kind, start, i.min(bytes.len()));
+    }
+    regions
+}
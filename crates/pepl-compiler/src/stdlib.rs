@@ -63,6 +63,90 @@ impl StdlibRegistry {
         &self.constants
     }
 
+    /// The function type expected for the callback argument at `index`,
+    /// with the generic `any` placeholders of the registered signature
+    /// filled in from the other arguments' types (`arg_types`, positional).
+    ///
+    /// For the `list` module every `any` callback parameter is the element
+    /// type of `items`, except the accumulator of `list.reduce`, which
+    /// takes the type of `initial`.  Returns `None` when the parameter at
+    /// `index` is not a function.
+    pub fn expected_callback(
+        &self,
+        module: &str,
+        function: &str,
+        index: usize,
+        arg_types: &[Type],
+    ) -> Option<Type> {
+        let sig = self.get(module, function)?;
+        let Type::Function(params, ret) = &sig.params.get(index)?.1 else {
+            return None;
+        };
+        if module != "list" {
+            return Some(sig.params[index].1.clone());
+        }
+        let item = match arg_types.first() {
+            Some(Type::List(inner)) => (**inner).clone(),
+            _ => Type::Any,
+        };
+        let mut params: Vec<Type> = params
+            .iter()
+            .map(|p| {
+                if matches!(p, Type::Any) {
+                    item.clone()
+                } else {
+                    p.clone()
+                }
+            })
+            .collect();
+        let mut ret = (**ret).clone();
+        if function == "reduce" {
+            let acc = match arg_types.get(1) {
+                Some(Type::Unknown) | None => Type::Any,
+                Some(initial) => initial.clone(),
+            };
+            params[0] = acc.clone();
+            ret = acc;
+        }
+        Some(Type::Function(params, Box::new(ret)))
+    }
+
+    /// The result type of a call, with the generic `any` of the registered
+    /// signature filled in from the arguments' types (`arg_types`,
+    /// positional, callbacks as checked).
+    ///
+    /// `list.map` returns a list of its callback's result, `list.filter` a
+    /// list of the element type of `items`, and `list.reduce` the type of
+    /// `initial`.  Anything else, or an argument whose type is unknown,
+    /// gives the registered return type.
+    pub fn instantiated_return(
+        &self,
+        module: &str,
+        function: &str,
+        arg_types: &[Type],
+    ) -> Option<Type> {
+        let sig = self.get(module, function)?;
+        let known = |ty: Option<&Type>| match ty {
+            Some(Type::Unknown) | None => None,
+            Some(ty) => Some(ty.clone()),
+        };
+        let ret = match (module, function) {
+            ("list", "map") => match arg_types.get(1) {
+                Some(Type::Function(_, ret)) => {
+                    known(Some(ret)).map(|ret| Type::List(Box::new(ret)))
+                }
+                _ => None,
+            },
+            ("list", "filter") => match arg_types.first() {
+                Some(items @ Type::List(_)) => Some(items.clone()),
+                _ => None,
+            },
+            ("list", "reduce") => known(arg_types.get(1)),
+            _ => None,
+        };
+        Some(ret.unwrap_or_else(|| sig.ret.clone()))
+    }
+
     // ──────────────────────────────────────────────────────────────────────
     // Registration helpers
     // ──────────────────────────────────────────────────────────────────────
@@ -748,11 +832,42 @@ impl Default for StdlibRegistry {
 
 /// Modules that require capabilities to use.
 pub fn capability_modules() -> HashMap<&'static str, &'static str> {
-    let mut m = HashMap::new();
-    m.insert("http", "http");
-    m.insert("storage", "storage");
-    m.insert("location", "location");
-    m.insert("notifications", "notifications");
-    m.insert("timer", "timer");
-    m
+    pepl_types::CAPABILITY_MODULES
+        .iter()
+        .map(|&module| (module, module))
+        .collect()
+}
+
+/// The stdlib function a name borrowed from another language means
+/// (`list.len` → `length`, `string.toUpperCase` → `to_upper`).
+///
+/// Every alias takes the same arguments, in the same order, as the
+/// function it maps to, so replacing one with the other is always safe.
+pub fn alias(module: &str, function: &str) -> Option<&'static str> {
+    let canonical = match (module, function) {
+        ("list", "len" | "size") => "length",
+        ("list", "push") => "append",
+        ("list", "unshift") => "prepend",
+        ("list", "includes" | "has") => "contains",
+        ("list", "indexOf") => "index_of",
+        ("list", "findIndex") => "find_index",
+        ("string", "len" | "size") => "length",
+        ("string", "toUpperCase" | "upper") => "to_upper",
+        ("string", "toLowerCase" | "lower") => "to_lower",
+        ("string", "includes") => "contains",
+        ("string", "startsWith") => "starts_with",
+        ("string", "endsWith") => "ends_with",
+        ("string", "indexOf") => "index_of",
+        ("string", "replaceAll") => "replace_all",
+        ("string", "padStart") => "pad_start",
+        ("string", "padEnd") => "pad_end",
+        ("string", "strip") => "trim",
+        ("convert", "toString" | "str") => "to_string",
+        ("convert", "parseInt") => "parse_int",
+        ("convert", "parseFloat") => "parse_float",
+        ("json", "dumps") => "stringify",
+        ("json", "loads") => "parse",
+        _ => return None,
+    };
+    Some(canonical)
 }
@@ -0,0 +1,633 @@
+//! Credential taint tracking.
+//!
+//! Values read from the `credentials { }` block are secrets: the host
+//! prompts the user for them and injects them at runtime.  [`analyze`]
+//! follows every value derived from a credential — through `let` bindings,
+//! string interpolation and arithmetic, records and lists, loops and match
+//! bindings, stdlib calls and lambdas, derived fields, and action arguments
+//! passed from a view — and reports each place one escapes the space:
+//!
+//! - `set` into a state field,
+//! - a component prop (rendered on screen),
+//! - `core.log`,
+//! - any capability call argument except an approved one.
+//!
+//! The approved sinks are the parts of an http request — its URL and its
+//! body — which is how a credential is meant to reach the service it
+//! authenticates with.  Capability call results are not tainted, nor are
+//! comparisons and predicates (`==`, `string.contains`, …), so checking a
+//! credential is present does not taint the branch that checked it.
+//! Only explicit flows are tracked.
+//!
+//! Test blocks and invariants are not analyzed: neither runs with real
+//! credentials.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use pepl_types::ast::*;
+use pepl_types::Span;
+
+use crate::stdlib::{self, StdlibRegistry};
+use crate::ty::Type;
+
+/// Capability call parameters a credential may flow into: `(module, param)`.
+const APPROVED_SINKS: &[(&str, &str)] = &[("http", "url"), ("http", "body")];
+
+// ══════════════════════════════════════════════════════════════════════════════
+// Flows
+// ══════════════════════════════════════════════════════════════════════════════
+
+/// One step of the path a credential takes to a sink.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FlowStep {
+    pub description: String,
+    pub line: u32,
+}
+
+impl fmt::Display for FlowStep {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (line {})", self.description, self.line)
+    }
+}
+
+/// A credential-derived value reaching a sink that is not approved.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Leak {
+    /// The credential the value derives from.
+    pub credential: String,
+    /// What it reaches: `core.log`, `state field 'token'`, …
+    pub sink: String,
+    /// From the credential read to the sink, inclusive.
+    pub path: Vec<FlowStep>,
+    /// The expression handed to the sink.
+    pub span: Span,
+}
+
+impl Leak {
+    /// The path as `'api_key' (line 9) → let 'auth' (line 9) → …`.
+    pub fn path_string(&self) -> String {
+        self.path
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(" → ")
+    }
+}
+
+/// Where a credential-derived value came from.
+#[derive(Debug, Clone)]
+struct Taint {
+    credential: String,
+    path: Vec<FlowStep>,
+}
+
+impl Taint {
+    fn then(&self, description: impl Into<String>, line: u32) -> Taint {
+        let mut path = self.path.clone();
+        path.push(FlowStep {
+            description: description.into(),
+            line,
+        });
+        Taint {
+            credential: self.credential.clone(),
+            path,
+        }
+    }
+}
+
+/// Find every flow from a credential of `program` to an unapproved sink.
+/// Each sink expression is reported once, for the first flow found.
+pub fn analyze(program: &Program, stdlib: &StdlibRegistry) -> Vec<Leak> {
+    let body = &program.space.body;
+    let Some(credentials) = &body.credentials else {
+        return Vec::new();
+    };
+    let mut analyzer = Analyzer {
+        stdlib,
+        capability_modules: stdlib::capability_modules(),
+        credentials: credentials
+            .fields
+            .iter()
+            .map(|f| f.name.name.as_str())
+            .collect(),
+        actions: body.actions.iter().map(|a| a.name.name.as_str()).collect(),
+        derived: HashMap::new(),
+        action_params: HashMap::new(),
+        scopes: Vec::new(),
+        reported: HashSet::new(),
+        leaks: Vec::new(),
+    };
+    analyzer.space(body);
+    analyzer.leaks
+}
+
+// ══════════════════════════════════════════════════════════════════════════════
+// Analyzer
+// ══════════════════════════════════════════════════════════════════════════════
+
+#[derive(Clone)]
+struct Binding<'a> {
+    taint: Option<Taint>,
+    /// The lambda a `let` bound, analyzed again at each call.
+    lambda: Option<&'a LambdaExpr>,
+}
+
+struct Analyzer<'a> {
+    stdlib: &'a StdlibRegistry,
+    capability_modules: HashMap<&'static str, &'static str>,
+    credentials: HashSet<&'a str>,
+    actions: HashSet<&'a str>,
+    /// Derived fields holding a credential-derived value.
+    derived: HashMap<&'a str, Taint>,
+    /// Action parameters a view passes a credential-derived value to,
+    /// by action name and parameter index.
+    action_params: HashMap<(&'a str, usize), Taint>,
+    scopes: Vec<HashMap<&'a str, Binding<'a>>>,
+    reported: HashSet<Span>,
+    leaks: Vec<Leak>,
+}
+
+impl<'a> Analyzer<'a> {
+    // ── Declarations ──────────────────────────────────────────────────────
+
+    fn space(&mut self, body: &'a SpaceBody) {
+        if let Some(derived) = &body.derived {
+            for field in &derived.fields {
+                if let Some(taint) = self.expr(&field.value) {
+                    let step = format!("derived field '{}'", field.name.name);
+                    self.derived.insert(
+                        &field.name.name,
+                        taint.then(step, field.name.span.start_line),
+                    );
+                }
+            }
+        }
+
+        // Views first: they decide which action parameters are tainted
+        for view in &body.views {
+            self.push_scope();
+            for param in &view.params {
+                self.bind(&param.name.name, None);
+            }
+            self.ui_block(&view.body);
+            self.pop_scope();
+        }
+        for action in &body.actions {
+            self.push_scope();
+            for (i, param) in action.params.iter().enumerate() {
+                let taint = self
+                    .action_params
+                    .get(&(action.name.name.as_str(), i))
+                    .map(|t| {
+                        t.then(
+                            format!("parameter '{}'", param.name.name),
+                            param.name.span.start_line,
+                        )
+                    });
+                self.bind(&param.name.name, taint);
+            }
+            self.block(&action.body);
+            self.pop_scope();
+        }
+        if let Some(update) = &body.update {
+            self.entry(&update.param, &update.body);
+        }
+        if let Some(handle_event) = &body.handle_event {
+            self.entry(&handle_event.param, &handle_event.body);
+        }
+    }
+
+    fn entry(&mut self, param: &'a Param, body: &'a Block) {
+        self.push_scope();
+        self.bind(&param.name.name, None);
+        self.block(body);
+        self.pop_scope();
+    }
+
+    // ── Scopes ────────────────────────────────────────────────────────────
+
+    fn push_scope(&mut self) {
+        self.scopes.push(HashMap::new());
+    }
+
+    fn pop_scope(&mut self) {
+        self.scopes.pop();
+    }
+
+    fn bind(&mut self, name: &'a str, taint: Option<Taint>) {
+        self.bind_lambda(name, taint, None);
+    }
+
+    fn bind_lambda(&mut self, name: &'a str, taint: Option<Taint>, lambda: Option<&'a LambdaExpr>) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name, Binding { taint, lambda });
+        }
+    }
+
+    fn lookup(&self, name: &str) -> Option<&Binding<'a>> {
+        self.scopes.iter().rev().find_map(|scope| scope.get(name))
+    }
+
+    /// The taint of reading `name`: a local, a derived field or a credential.
+    fn read(&self, name: &str, span: Span) -> Option<Taint> {
+        if let Some(binding) = self.lookup(name) {
+            return binding.taint.clone();
+        }
+        if let Some(taint) = self.derived.get(name) {
+            return Some(taint.clone());
+        }
+        self.credentials.get(name).map(|&credential| Taint {
+            credential: credential.to_string(),
+            path: vec![FlowStep {
+                description: format!("'{credential}'"),
+                line: span.start_line,
+            }],
+        })
+    }
+
+    // ── Sinks ─────────────────────────────────────────────────────────────
+
+    fn sink(&mut self, taint: Option<Taint>, sink: String, span: Span) {
+        let Some(taint) = taint else {
+            return;
+        };
+        if !self.reported.insert(span) {
+            return;
+        }
+        let taint = taint.then(sink.clone(), span.start_line);
+        self.leaks.push(Leak {
+            credential: taint.credential,
+            sink,
+            path: taint.path,
+            span,
+        });
+    }
+
+    // ── Views ─────────────────────────────────────────────────────────────
+
+    fn ui_block(&mut self, block: &'a UIBlock) {
+        self.push_scope();
+        for element in &block.elements {
+            self.ui_element(element);
+        }
+        self.pop_scope();
+    }
+
+    fn ui_element(&mut self, element: &'a UIElement) {
+        match element {
+            UIElement::Component(comp) => {
+                for prop in &comp.props {
+                    if prop.name.name.starts_with("on_") {
+                        match &prop.value.kind {
+                            ExprKind::Identifier(name) if self.actions.contains(name.as_str()) => {
+                                continue;
+                            }
+                            ExprKind::Call { name, args }
+                                if self.actions.contains(name.name.as_str()) =>
+                            {
+                                self.action_arguments(name, args);
+                                continue;
+                            }
+                            _ => {}
+                        }
+                    }
+                    let taint = self.expr(&prop.value);
+                    let sink = format!("{} prop '{}'", comp.name.name, prop.name.name);
+                    self.sink(taint, sink, prop.value.span);
+                }
+                if let Some(children) = &comp.children {
+                    self.ui_block(children);
+                }
+            }
+            UIElement::Let(binding) => self.let_binding(binding),
+            UIElement::If(ui_if) => self.ui_if(ui_if),
+            UIElement::For(ui_for) => {
+                let taint = self.expr(&ui_for.iterable);
+                self.push_scope();
+                self.bind_loop(&ui_for.item, ui_for.index.as_ref(), taint);
+                self.ui_block(&ui_for.body);
+                self.pop_scope();
+            }
+        }
+    }
+
+    fn ui_if(&mut self, ui_if: &'a UIIf) {
+        self.expr(&ui_if.condition);
+        self.ui_block(&ui_if.then_block);
+        match &ui_if.else_block {
+            Some(UIElse::ElseIf(inner)) => self.ui_if(inner),
+            Some(UIElse::Block(block)) => self.ui_block(block),
+            None => {}
+        }
+    }
+
+    /// `on_tap: add(api_key)` — the action's parameters receive the taint.
+    fn action_arguments(&mut self, action: &'a Ident, args: &'a [Expr]) {
+        for (i, arg) in args.iter().enumerate() {
+            if let Some(taint) = self.expr(arg) {
+                let step = format!("passed to action '{}'", action.name);
+                self.action_params
+                    .entry((action.name.as_str(), i))
+                    .or_insert_with(|| taint.then(step, arg.span.start_line));
+            }
+        }
+    }
+
+    // ── Statements ────────────────────────────────────────────────────────
+
+    /// The taint of a block's value (its last expression).
+    fn block(&mut self, block: &'a Block) -> Option<Taint> {
+        self.push_scope();
+        let mut value = None;
+        for stmt in &block.stmts {
+            value = self.stmt(stmt);
+        }
+        self.pop_scope();
+        value
+    }
+
+    fn stmt(&mut self, stmt: &'a Stmt) -> Option<Taint> {
+        match stmt {
+            Stmt::Set(set) => {
+                let taint = self.expr(&set.value);
+                let target: Vec<_> = set.target.iter().map(|t| t.name.as_str()).collect();
+                let sink = format!("state field '{}'", target.join("."));
+                self.sink(taint, sink, set.value.span);
+                None
+            }
+            Stmt::Let(binding) => {
+                self.let_binding(binding);
+                None
+            }
+            Stmt::If(if_expr) => self.if_expr(if_expr),
+            Stmt::For(for_expr) => self.for_expr(for_expr),
+            Stmt::Match(match_expr) => self.match_expr(match_expr),
+            Stmt::Return(_) => None,
+            Stmt::Assert(assert) => {
+                self.expr(&assert.condition);
+                None
+            }
+            Stmt::Expr(expr_stmt) => self.expr(&expr_stmt.expr),
+        }
+    }
+
+    fn let_binding(&mut self, binding: &'a LetBinding) {
+        let taint = self.expr(&binding.value);
+        let Some(name) = &binding.name else {
+            return;
+        };
+        let taint = taint.map(|t| t.then(format!("let '{}'", name.name), name.span.start_line));
+        let lambda = match &binding.value.kind {
+            ExprKind::Lambda(lambda) => Some(&**lambda),
+            _ => None,
+        };
+        self.bind_lambda(&name.name, taint, lambda);
+    }
+
+    fn bind_loop(&mut self, item: &'a Ident, index: Option<&'a Ident>, taint: Option<Taint>) {
+        let taint = taint.map(|t| {
+            t.then(
+                format!("loop variable '{}'", item.name),
+                item.span.start_line,
+            )
+        });
+        self.bind(&item.name, taint);
+        if let Some(index) = index {
+            self.bind(&index.name, None);
+        }
+    }
+
+    fn if_expr(&mut self, if_expr: &'a IfExpr) -> Option<Taint> {
+        self.expr(&if_expr.condition);
+        let then_taint = self.block(&if_expr.then_block);
+        let else_taint = match &if_expr.else_branch {
+            Some(ElseBranch::ElseIf(inner)) => self.if_expr(inner),
+            Some(ElseBranch::Block(block)) => self.block(block),
+            None => None,
+        };
+        then_taint.or(else_taint)
+    }
+
+    fn for_expr(&mut self, for_expr: &'a ForExpr) -> Option<Taint> {
+        let taint = self.expr(&for_expr.iterable);
+        self.push_scope();
+        self.bind_loop(&for_expr.item, for_expr.index.as_ref(), taint);
+        self.block(&for_expr.body);
+        self.pop_scope();
+        None
+    }
+
+    fn match_expr(&mut self, match_expr: &'a MatchExpr) -> Option<Taint> {
+        let subject = self.expr(&match_expr.subject);
+        let mut value = None;
+        for arm in &match_expr.arms {
+            self.push_scope();
+            if let Pattern::Variant { bindings, .. } = &arm.pattern {
+                for binding in bindings {
+                    let taint = subject.as_ref().map(|t| {
+                        t.then(
+                            format!("match binding '{}'", binding.name),
+                            binding.span.start_line,
+                        )
+                    });
+                    self.bind(&binding.name, taint);
+                }
+            }
+            let taint = match &arm.body {
+                MatchArmBody::Expr(expr) => self.expr(expr),
+                MatchArmBody::Block(block) => self.block(block),
+            };
+            value = value.or(taint);
+            self.pop_scope();
+        }
+        value
+    }
+
+    // ── Expressions ───────────────────────────────────────────────────────
+
+    fn expr(&mut self, expr: &'a Expr) -> Option<Taint> {
+        let line = expr.span.start_line;
+        match &expr.kind {
+            ExprKind::NumberLit(_)
+            | ExprKind::StringLit(_)
+            | ExprKind::BoolLit(_)
+            | ExprKind::NilLit => None,
+            ExprKind::StringInterpolation(parts) => {
+                let mut value = None;
+                for part in parts {
+                    if let StringPart::Expr(inner) = part {
+                        value = value.or(self.expr(inner));
+                    }
+                }
+                value.map(|t| t.then("interpolated into a string", line))
+            }
+            ExprKind::ListLit(items) => {
+                let mut value = None;
+                for item in items {
+                    value = value.or(self.expr(item));
+                }
+                value.map(|t| t.then("element of a list", line))
+            }
+            ExprKind::RecordLit(entries) => {
+                let mut value = None;
+                for entry in entries {
+                    let taint = match entry {
+                        RecordEntry::Field { name, value } => self.expr(value).map(|t| {
+                            t.then(
+                                format!("field '{}' of a record", name.name),
+                                name.span.start_line,
+                            )
+                        }),
+                        RecordEntry::Spread(inner) => self.expr(inner),
+                    };
+                    value = value.or(taint);
+                }
+                value
+            }
+            ExprKind::Identifier(name) => self.read(name, expr.span),
+            ExprKind::Call { name, args } => {
+                let lambda = self.lookup(&name.name).and_then(|b| b.lambda);
+                let taints: Vec<_> = args.iter().map(|arg| self.expr(arg)).collect();
+                match lambda {
+                    Some(lambda) => self.call_lambda(lambda, &taints),
+                    None => taints.into_iter().flatten().next(),
+                }
+            }
+            ExprKind::QualifiedCall {
+                module,
+                function,
+                args,
+            } => self.qualified_call(module, function, args),
+            ExprKind::FieldAccess { object, .. } => self.expr(object),
+            ExprKind::MethodCall {
+                object,
+                method,
+                args,
+            } => {
+                let object = self.expr(object);
+                let predicate = ["list", "string"].iter().any(|module| {
+                    self.stdlib
+                        .get(module, &method.name)
+                        .is_some_and(|sig| sig.ret == Type::Bool)
+                });
+                let taint = self.pure_call(object, args);
+                if predicate {
+                    None
+                } else {
+                    taint.map(|t| t.then(format!("passed to .{}()", method.name), line))
+                }
+            }
+            ExprKind::Binary { left, op, right } => {
+                let left = self.expr(left);
+                let right = self.expr(right);
+                match op {
+                    BinOp::Add | BinOp::Sub | BinOp::Mul | BinOp::Div | BinOp::Mod => {
+                        left.or(right)
+                    }
+                    _ => None,
+                }
+            }
+            ExprKind::Unary { op, operand } => {
+                let taint = self.expr(operand);
+                match op {
+                    UnaryOp::Neg => taint,
+                    UnaryOp::Not => None,
+                }
+            }
+            ExprKind::ResultUnwrap(inner) | ExprKind::Paren(inner) => self.expr(inner),
+            ExprKind::NilCoalesce { left, right } => {
+                let left = self.expr(left);
+                let right = self.expr(right);
+                left.or(right)
+            }
+            ExprKind::If(if_expr) => self.if_expr(if_expr),
+            ExprKind::For(for_expr) => self.for_expr(for_expr),
+            ExprKind::Match(match_expr) => self.match_expr(match_expr),
+            // A lambda capturing a credential is itself a secret
+            ExprKind::Lambda(lambda) => self.call_lambda(lambda, &[]),
+        }
+    }
+
+    /// Analyze `lambda`'s body with its parameters carrying `args`,
+    /// returning the taint of its result.
+    fn call_lambda(&mut self, lambda: &'a LambdaExpr, args: &[Option<Taint>]) -> Option<Taint> {
+        self.push_scope();
+        for (i, param) in lambda.params.iter().enumerate() {
+            let taint = args.get(i).cloned().flatten().map(|t| {
+                t.then(
+                    format!("lambda parameter '{}'", param.name.name),
+                    param.name.span.start_line,
+                )
+            });
+            self.bind(&param.name.name, taint);
+        }
+        let value = self.block(&lambda.body);
+        self.pop_scope();
+        value
+    }
+
+    /// A call to a pure function: the result is tainted when any data
+    /// argument is, or when a callback returns a tainted value.  Every
+    /// callback parameter receives the data arguments' taint.
+    fn pure_call(&mut self, first: Option<Taint>, args: &'a [Expr]) -> Option<Taint> {
+        let mut data = first;
+        let mut lambdas = Vec::new();
+        for arg in args {
+            match &arg.kind {
+                ExprKind::Lambda(lambda) => lambdas.push(&**lambda),
+                _ => data = data.or(self.expr(arg)),
+            }
+        }
+        let mut value = data.clone();
+        for lambda in lambdas {
+            let params = vec![data.clone(); lambda.params.len()];
+            value = value.or(self.call_lambda(lambda, &params));
+        }
+        value
+    }
+
+    fn qualified_call(
+        &mut self,
+        module: &'a Ident,
+        function: &'a Ident,
+        args: &'a [Expr],
+    ) -> Option<Taint> {
+        let sig = self.stdlib.get(&module.name, &function.name);
+        let param_name = |i: usize| match sig.and_then(|s| s.params.get(i)) {
+            Some((name, _)) => name.clone(),
+            None => format!("#{}", i + 1),
+        };
+
+        if module.name == "core" && function.name == "log" {
+            for arg in args {
+                let taint = self.expr(arg);
+                self.sink(taint, "core.log".to_string(), arg.span);
+            }
+            return None;
+        }
+
+        if self.capability_modules.contains_key(module.name.as_str()) {
+            for (i, arg) in args.iter().enumerate() {
+                let taint = self.expr(arg);
+                let param = param_name(i);
+                if !APPROVED_SINKS.contains(&(module.name.as_str(), param.as_str())) {
+                    let sink = format!("{}.{} argument '{}'", module.name, function.name, param);
+                    self.sink(taint, sink, arg.span);
+                }
+            }
+            return None;
+        }
+
+        let taint = self.pure_call(None, args);
+        if sig.is_some_and(|s| s.ret == Type::Bool) {
+            return None;
+        }
+        taint.map(|t| {
+            t.then(
+                format!("passed to {}.{}", module.name, function.name),
+                function.span.start_line,
+            )
+        })
+    }
+}
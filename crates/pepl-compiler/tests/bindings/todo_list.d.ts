@@ -0,0 +1,76 @@
+// Generated by pepl-compiler from space `TodoList`. Do not edit.
+
+export type Status = "Idle" | { Busy: [task: string, progress: number] };
+
+export type Tag = { label: string; weight?: number };
+
+/** `state` and `derived` fields of `TodoList`. */
+export interface TodoListState {
+  items: Array<{ text: string; done: boolean; note?: string }>;
+  history: Status[];
+  outcomes: Array<{ Ok: number } | { Err: string }>;
+  filter: string;
+  tags: Tag[];
+  /** Derived: recomputed after every action. */
+  readonly remaining: number;
+}
+
+/** Actions of `TodoList`; each call runs `dispatch_action`. */
+export interface TodoListActions {
+  addItem(text: string): void;
+  setStatus(next: Status): void;
+  tag(label: string, weight: number): void;
+  logOutcome(outcome: { Ok: number } | { Err: string }): void;
+  clear(): void;
+}
+
+/** Views of `TodoList`, rendered to surface trees. */
+export interface TodoListViews {
+  main(): SurfaceNode[];
+}
+
+export type Component =
+  | "Button"
+  | "Column"
+  | "Modal"
+  | "ProgressBar"
+  | "Row"
+  | "Scroll"
+  | "ScrollList"
+  | "Text"
+  | "TextInput"
+  | "Toast";
+
+/**
+ * A rendered component.  Action props read `{ __action: name }`, lambda
+ * props `{ __lambda: id }`.
+ */
+export interface SurfaceNode {
+  component: Component;
+  props: { [prop: string]: unknown };
+  children: SurfaceNode[];
+}
+
+export type InputEvent = { [field: string]: unknown };
+
+/** An instantiated `TodoList` module. */
+export interface TodoListSpace {
+  readonly instance: WebAssembly.Instance;
+  readonly dispatch: TodoListActions;
+  readonly render: TodoListViews;
+  /** Current state and derived fields (`get_state`). */
+  state(): TodoListState;
+  /** Canonical JSON of the state fields (`serialize_state`). */
+  serializeState(): string;
+  /** Load JSON from `serializeState`; `false` if the module rejected it. */
+  restoreState(json: string): boolean;
+}
+
+/**
+ * Instantiate a compiled `TodoList` module and run `init`.  `env` replaces
+ * the default imports; without `host_call`, capabilities return nil.
+ */
+export function instantiate(
+  source: BufferSource | WebAssembly.Module,
+  env?: WebAssembly.ModuleImports,
+): Promise<TodoListSpace>;
@@ -0,0 +1,206 @@
+// Generated by pepl-compiler from space `TodoList`. Do not edit.
+
+const ACTIONS = ["add_item", "set_status", "tag", "log_outcome", "clear"];
+const VARIANTS = ["Idle", "Busy"];
+const TYPES = {
+  Status: {
+    Idle: [0, []],
+    Busy: [1, ["string", "number"]],
+  },
+};
+
+const VARIANT_OK = 0xffffff00;
+const VARIANT_ERR = 0xffffff01;
+const utf8 = new TextEncoder();
+const utf8Decoder = new TextDecoder();
+
+function text(exports, ptr, len) {
+  return utf8Decoder.decode(new Uint8Array(exports.memory.buffer, ptr, len));
+}
+
+function bytes(exports, data) {
+  const ptr = exports.alloc(data.length);
+  new Uint8Array(exports.memory.buffer, ptr, data.length).set(data);
+  return ptr;
+}
+
+function cell(exports, tag, w1, w2) {
+  const ptr = exports.alloc(12);
+  const mem = new DataView(exports.memory.buffer);
+  mem.setInt32(ptr, tag, true);
+  mem.setUint32(ptr + 4, w1 >>> 0, true);
+  mem.setUint32(ptr + 8, w2 >>> 0, true);
+  return ptr;
+}
+
+function list(exports, ptrs) {
+  const items = exports.alloc(ptrs.length * 4);
+  const mem = new DataView(exports.memory.buffer);
+  ptrs.forEach((ptr, i) => mem.setUint32(items + i * 4, ptr, true));
+  return cell(exports, 4, items, ptrs.length);
+}
+
+function record(exports, fields) {
+  const keys = fields.map(([key]) => utf8.encode(key));
+  const keyPtrs = keys.map((key) => bytes(exports, key));
+  const entries = exports.alloc(fields.length * 12);
+  const mem = new DataView(exports.memory.buffer);
+  fields.forEach(([, ptr], i) => {
+    mem.setUint32(entries + i * 12, keyPtrs[i], true);
+    mem.setUint32(entries + i * 12 + 4, keys[i].length, true);
+    mem.setUint32(entries + i * 12 + 8, ptr, true);
+  });
+  return cell(exports, 5, entries, fields.length);
+}
+
+function anySchema(value) {
+  switch (typeof value) {
+    case "number":
+      return "number";
+    case "string":
+      return "string";
+    case "boolean":
+      return "bool";
+  }
+  if (value === null || value === undefined) return "nil";
+  if (Array.isArray(value)) return ["list", "any"];
+  return ["record", Object.fromEntries(Object.keys(value).map((key) => [key, "any"]))];
+}
+
+function encode(exports, schema, value) {
+  if (schema === "any") schema = anySchema(value);
+  switch (schema) {
+    case "number": {
+      const ptr = cell(exports, 1, 0, 0);
+      new DataView(exports.memory.buffer).setFloat64(ptr + 4, value, true);
+      return ptr;
+    }
+    case "string": {
+      const data = utf8.encode(value);
+      return cell(exports, 3, bytes(exports, data), data.length);
+    }
+    case "bool":
+      return cell(exports, 2, value ? 1 : 0, 0);
+    case "nil":
+      return cell(exports, 0, 0, 0);
+  }
+  const [kind, inner, err] = schema;
+  switch (kind) {
+    case "list":
+      return list(exports, value.map((item) => encode(exports, inner, item)));
+    case "record":
+      return record(
+        exports,
+        Object.entries(inner)
+          .filter(([key]) => value[key] !== undefined)
+          .map(([key, field]) => [key, encode(exports, field, value[key])]),
+      );
+    case "result":
+      return "Ok" in value
+        ? cell(exports, 6, VARIANT_OK, encode(exports, inner, value.Ok))
+        : cell(exports, 6, VARIANT_ERR, encode(exports, err, value.Err));
+    case "variants": {
+      const name = typeof value === "string" ? value : Object.keys(value)[0];
+      const variant = TYPES[inner][name];
+      if (variant === undefined) throw new TypeError(`${name} is not a ${inner}`);
+      const [id, fields] = variant;
+      const values = typeof value === "string" ? [] : value[name];
+      const ptrs = fields.map((field, i) => encode(exports, field, values[i]));
+      return cell(exports, 6, id, list(exports, ptrs));
+    }
+  }
+  throw new TypeError(`unknown schema ${kind}`);
+}
+
+function decode(exports, ptr) {
+  const mem = new DataView(exports.memory.buffer);
+  const w1 = mem.getUint32(ptr + 4, true);
+  const w2 = mem.getUint32(ptr + 8, true);
+  const word = (at) => mem.getUint32(at, true);
+  switch (mem.getInt32(ptr, true)) {
+    case 1:
+      return mem.getFloat64(ptr + 4, true);
+    case 2:
+      return w1 !== 0;
+    case 3:
+      return text(exports, w1, w2);
+    case 4:
+      return Array.from({ length: w2 }, (_, i) => decode(exports, word(w1 + i * 4)));
+    case 5:
+      return Object.fromEntries(
+        Array.from({ length: w2 }, (_, i) => {
+          const at = w1 + i * 12;
+          return [text(exports, word(at), word(at + 4)), decode(exports, word(at + 8))];
+        }),
+      );
+    case 6: {
+      if (w1 === VARIANT_OK) return { Ok: decode(exports, w2) };
+      if (w1 === VARIANT_ERR) return { Err: decode(exports, w2) };
+      const fields = w2 === 0 ? [] : decode(exports, w2);
+      return fields.length === 0 ? VARIANTS[w1] : { [VARIANTS[w1]]: fields };
+    }
+    case 7:
+      return { __lambda: w1 };
+    case 9:
+      return { __action: ACTIONS[w1] };
+    default:
+      return null;
+  }
+}
+
+function surface(value) {
+  if (Array.isArray(value)) return value.flatMap(surface);
+  if (value === null) return [];
+  const { component, props, children } = value;
+  return [{ component, props, children: surface(children ?? []) }];
+}
+
+export async function instantiate(source, env = {}) {
+  let exports;
+  const imports = {
+    env: {
+      host_call: () => 0,
+      log: (ptr, len) => console.log(text(exports, ptr, len)),
+      trap: (ptr, len) => {
+        throw new Error(text(exports, ptr, len));
+      },
+      get_timestamp: () => BigInt(Date.now()),
+      ...env,
+    },
+  };
+  const result = await WebAssembly.instantiate(source, imports);
+  const instance = result instanceof WebAssembly.Instance ? result : result.instance;
+  exports = instance.exports;
+  exports.init();
+  return bind(instance);
+}
+
+function bind(instance) {
+  const exports = instance.exports;
+  const dispatch = (id, schemas, args) => {
+    const ptrs = args.map((arg, i) => encode(exports, schemas[i], arg));
+    exports.dispatch_action(id, list(exports, ptrs), 0);
+  };
+  return {
+    instance,
+    dispatch: {
+      addItem: (text) => dispatch(0, ["string"], [text]),
+      setStatus: (next) => dispatch(1, [["variants", "Status"]], [next]),
+      tag: (label, weight) => dispatch(2, ["string", "number"], [label, weight]),
+      logOutcome: (outcome) => dispatch(3, [["result", "number", "string"]], [outcome]),
+      clear: () => dispatch(4, [], []),
+    },
+    render: {
+      main: () => surface(decode(exports, exports.render(0))),
+    },
+    state: () => decode(exports, exports.get_state()),
+    serializeState: () => {
+      const [ptr, len] = exports.serialize_state();
+      return text(exports, ptr, len);
+    },
+    restoreState: (json) => {
+      const data = utf8.encode(json);
+      return exports.restore_state(bytes(exports, data), data.length) === 1;
+    },
+  };
+}
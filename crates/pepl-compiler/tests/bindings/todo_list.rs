@@ -0,0 +1,420 @@
+//! Host bindings for the PEPL space `TodoList`.
+//!
+//! Generated by pepl-compiler. Do not edit.
+
+/// A value in the module's memory layout.  Sum type variants carry their
+/// fields in order; results are the variants [`VARIANT_OK`] and
+/// [`VARIANT_ERR`] with one field, written without the list around it.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Nil,
+    Number(f64),
+    Bool(bool),
+    String(String),
+    List(Vec<Value>),
+    Record(Vec<(String, Value)>),
+    Variant(u32, Vec<Value>),
+    Lambda(u32),
+    ActionRef(u32),
+}
+
+pub const VARIANT_OK: u32 = 0xFFFF_FF00;
+pub const VARIANT_ERR: u32 = 0xFFFF_FF01;
+
+/// Conversion between a bound Rust type and [`Value`].
+pub trait PeplValue: Sized {
+    fn into_value(self) -> Value;
+    fn from_value(value: Value) -> Option<Self>;
+}
+
+impl PeplValue for Value {
+    fn into_value(self) -> Value {
+        self
+    }
+
+    fn from_value(value: Value) -> Option<Self> {
+        Some(value)
+    }
+}
+
+impl PeplValue for f64 {
+    fn into_value(self) -> Value {
+        Value::Number(self)
+    }
+
+    fn from_value(value: Value) -> Option<Self> {
+        match value {
+            Value::Number(n) => Some(n),
+            _ => None,
+        }
+    }
+}
+
+impl PeplValue for bool {
+    fn into_value(self) -> Value {
+        Value::Bool(self)
+    }
+
+    fn from_value(value: Value) -> Option<Self> {
+        match value {
+            Value::Bool(b) => Some(b),
+            _ => None,
+        }
+    }
+}
+
+impl PeplValue for String {
+    fn into_value(self) -> Value {
+        Value::String(self)
+    }
+
+    fn from_value(value: Value) -> Option<Self> {
+        match value {
+            Value::String(s) => Some(s),
+            _ => None,
+        }
+    }
+}
+
+impl PeplValue for () {
+    fn into_value(self) -> Value {
+        Value::Nil
+    }
+
+    fn from_value(value: Value) -> Option<Self> {
+        matches!(value, Value::Nil).then_some(())
+    }
+}
+
+impl<T: PeplValue> PeplValue for Vec<T> {
+    fn into_value(self) -> Value {
+        Value::List(self.into_iter().map(T::into_value).collect())
+    }
+
+    fn from_value(value: Value) -> Option<Self> {
+        match value {
+            Value::List(items) => items.into_iter().map(T::from_value).collect(),
+            _ => None,
+        }
+    }
+}
+
+impl<T: PeplValue> PeplValue for Box<T> {
+    fn into_value(self) -> Value {
+        (*self).into_value()
+    }
+
+    fn from_value(value: Value) -> Option<Self> {
+        T::from_value(value).map(Box::new)
+    }
+}
+
+impl<T: PeplValue, E: PeplValue> PeplValue for Result<T, E> {
+    fn into_value(self) -> Value {
+        match self {
+            Ok(value) => Value::Variant(VARIANT_OK, vec![value.into_value()]),
+            Err(error) => Value::Variant(VARIANT_ERR, vec![error.into_value()]),
+        }
+    }
+
+    fn from_value(value: Value) -> Option<Self> {
+        match value {
+            Value::Variant(VARIANT_OK, mut fields) if fields.len() == 1 => {
+                T::from_value(fields.pop()?).map(Ok)
+            }
+            Value::Variant(VARIANT_ERR, mut fields) if fields.len() == 1 => {
+                E::from_value(fields.pop()?).map(Err)
+            }
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum Error<E> {
+    /// The runtime failed: a trap, a violated invariant or an engine error.
+    Runtime(E),
+    /// A value read back does not have the bound type.
+    Shape(&'static str),
+}
+
+/// Remove the entry `name` from a record.
+fn take(fields: &mut Vec<(String, Value)>, name: &str) -> Option<Value> {
+    let index = fields.iter().position(|(key, _)| key == name)?;
+    Some(fields.swap_remove(index).1)
+}
+
+/// Read an optional record field: absent and nil are `None`.
+fn optional<T: PeplValue>(value: Option<Value>) -> Option<Option<T>> {
+    match value {
+        None | Some(Value::Nil) => Some(None),
+        Some(value) => T::from_value(value).map(Some),
+    }
+}
+
+/// A rendered component; action props are [`Value::ActionRef`]s.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SurfaceNode {
+    pub component: String,
+    pub props: Vec<(String, Value)>,
+    pub children: Vec<SurfaceNode>,
+}
+
+/// Read a rendered value, flattening the lists `if` and `for` blocks nest.
+pub fn surface(value: Value) -> Option<Vec<SurfaceNode>> {
+    let mut nodes = Vec::new();
+    collect_nodes(value, &mut nodes)?;
+    Some(nodes)
+}
+
+fn collect_nodes(value: Value, out: &mut Vec<SurfaceNode>) -> Option<()> {
+    match value {
+        Value::List(items) => {
+            for item in items {
+                collect_nodes(item, out)?;
+            }
+        }
+        Value::Nil => {}
+        Value::Record(mut fields) => {
+            let Value::String(component) = take(&mut fields, "component")? else {
+                return None;
+            };
+            let props = match take(&mut fields, "props") {
+                Some(Value::Record(props)) => props,
+                _ => Vec::new(),
+            };
+            let children = match take(&mut fields, "children") {
+                Some(children) => surface(children)?,
+                None => Vec::new(),
+            };
+            out.push(SurfaceNode {
+                component,
+                props,
+                children,
+            });
+        }
+        _ => return None,
+    }
+    Some(())
+}
+
+/// The module's exports, implemented by the host on its WASM engine.
+pub trait Runtime {
+    type Error;
+
+    /// `dispatch_action(action_id, args)`, with `args` written as a list.
+    fn dispatch_action(&mut self, action_id: u32, args: Vec<Value>) -> Result<(), Self::Error>;
+
+    /// `get_state()`, read back.
+    fn get_state(&mut self) -> Result<Value, Self::Error>;
+
+    /// `render(view_id)`, read back.
+    fn render(&mut self, view_id: u32) -> Result<Value, Self::Error>;
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Status {
+    Idle,
+    Busy { task: String, progress: f64 },
+}
+
+impl PeplValue for Status {
+    fn into_value(self) -> Value {
+        match self {
+            Status::Idle => Value::Variant(0, Vec::new()),
+            Status::Busy { task, progress } => {
+                Value::Variant(1, vec![task.into_value(), progress.into_value()])
+            }
+        }
+    }
+
+    fn from_value(value: Value) -> Option<Self> {
+        let Value::Variant(id, fields) = value else {
+            return None;
+        };
+        let mut fields = fields.into_iter();
+        let variant = match id {
+            0 => Status::Idle,
+            1 => Status::Busy {
+                task: PeplValue::from_value(fields.next()?)?,
+                progress: PeplValue::from_value(fields.next()?)?,
+            },
+            _ => return None,
+        };
+        fields.next().is_none().then_some(variant)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tag {
+    pub label: String,
+    pub weight: Option<f64>,
+}
+
+impl PeplValue for Tag {
+    fn into_value(self) -> Value {
+        let mut fields = vec![
+            ("label".to_string(), self.label.into_value()),
+        ];
+        if let Some(value) = self.weight {
+            fields.push(("weight".to_string(), value.into_value()));
+        }
+        Value::Record(fields)
+    }
+
+    fn from_value(value: Value) -> Option<Self> {
+        let Value::Record(mut fields) = value else {
+            return None;
+        };
+        Some(Tag {
+            label: PeplValue::from_value(take(&mut fields, "label")?)?,
+            weight: optional(take(&mut fields, "weight"))?,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ItemsItem {
+    pub text: String,
+    pub done: bool,
+    pub note: Option<String>,
+}
+
+impl PeplValue for ItemsItem {
+    fn into_value(self) -> Value {
+        let mut fields = vec![
+            ("text".to_string(), self.text.into_value()),
+            ("done".to_string(), self.done.into_value()),
+        ];
+        if let Some(value) = self.note {
+            fields.push(("note".to_string(), value.into_value()));
+        }
+        Value::Record(fields)
+    }
+
+    fn from_value(value: Value) -> Option<Self> {
+        let Value::Record(mut fields) = value else {
+            return None;
+        };
+        Some(ItemsItem {
+            text: PeplValue::from_value(take(&mut fields, "text")?)?,
+            done: PeplValue::from_value(take(&mut fields, "done")?)?,
+            note: optional(take(&mut fields, "note"))?,
+        })
+    }
+}
+
+/// `state` and `derived` fields of `TodoList`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TodoListState {
+    pub items: Vec<ItemsItem>,
+    pub history: Vec<Status>,
+    pub outcomes: Vec<Result<f64, String>>,
+    pub filter: String,
+    pub tags: Vec<Tag>,
+    /// Derived: recomputed after every action.
+    pub remaining: f64,
+}
+
+impl PeplValue for TodoListState {
+    fn into_value(self) -> Value {
+        Value::Record(vec![
+            ("items".to_string(), self.items.into_value()),
+            ("history".to_string(), self.history.into_value()),
+            ("outcomes".to_string(), self.outcomes.into_value()),
+            ("filter".to_string(), self.filter.into_value()),
+            ("tags".to_string(), self.tags.into_value()),
+            ("remaining".to_string(), self.remaining.into_value()),
+        ])
+    }
+
+    fn from_value(value: Value) -> Option<Self> {
+        let Value::Record(mut fields) = value else {
+            return None;
+        };
+        Some(TodoListState {
+            items: PeplValue::from_value(take(&mut fields, "items")?)?,
+            history: PeplValue::from_value(take(&mut fields, "history")?)?,
+            outcomes: PeplValue::from_value(take(&mut fields, "outcomes")?)?,
+            filter: PeplValue::from_value(take(&mut fields, "filter")?)?,
+            tags: PeplValue::from_value(take(&mut fields, "tags")?)?,
+            remaining: PeplValue::from_value(take(&mut fields, "remaining")?)?,
+        })
+    }
+}
+
+/// Actions of `TodoList`, in `dispatch_action` order.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TodoListAction {
+    AddItem { text: String },
+    SetStatus { next: Status },
+    Tag { label: String, weight: f64 },
+    LogOutcome { outcome: Result<f64, String> },
+    Clear,
+}
+
+impl TodoListAction {
+    /// The id `dispatch_action` expects.
+    pub fn id(&self) -> u32 {
+        match *self {
+            TodoListAction::AddItem { .. } => 0,
+            TodoListAction::SetStatus { .. } => 1,
+            TodoListAction::Tag { .. } => 2,
+            TodoListAction::LogOutcome { .. } => 3,
+            TodoListAction::Clear => 4,
+        }
+    }
+
+    /// The arguments, in declaration order.
+    pub fn into_args(self) -> Vec<Value> {
+        match self {
+            TodoListAction::AddItem { text } => {
+                vec![text.into_value()]
+            }
+            TodoListAction::SetStatus { next } => {
+                vec![next.into_value()]
+            }
+            TodoListAction::Tag { label, weight } => {
+                vec![label.into_value(), weight.into_value()]
+            }
+            TodoListAction::LogOutcome { outcome } => {
+                vec![outcome.into_value()]
+            }
+            TodoListAction::Clear => Vec::new(),
+        }
+    }
+}
+
+/// A `TodoList` module driven through a host [`Runtime`].
+pub struct TodoList<R> {
+    runtime: R,
+}
+
+impl<R: Runtime> TodoList<R> {
+    pub fn new(runtime: R) -> Self {
+        TodoList { runtime }
+    }
+
+    pub fn runtime(&mut self) -> &mut R {
+        &mut self.runtime
+    }
+
+    pub fn into_runtime(self) -> R {
+        self.runtime
+    }
+
+    pub fn dispatch(&mut self, action: TodoListAction) -> Result<(), R::Error> {
+        let id = action.id();
+        self.runtime.dispatch_action(id, action.into_args())
+    }
+
+    pub fn state(&mut self) -> Result<TodoListState, Error<R::Error>> {
+        let value = self.runtime.get_state().map_err(Error::Runtime)?;
+        TodoListState::from_value(value).ok_or(Error::Shape("TodoListState"))
+    }
+
+    pub fn render_main(&mut self) -> Result<Vec<SurfaceNode>, Error<R::Error>> {
+        let value = self.runtime.render(0).map_err(Error::Runtime)?;
+        surface(value).ok_or(Error::Shape("Surface"))
+    }
+}
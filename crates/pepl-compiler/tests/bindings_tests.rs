@@ -0,0 +1,561 @@
+//! Host bindings — the generated `.d.ts`, ES module and Rust module.
+//!
+//! The bindings for [`TODO_LIST`] are checked in under `tests/bindings/`;
+//! the Rust module is compiled into this test and drives the compiled space
+//! through a `wasmi` runtime.  After an intended change to the generator,
+//! refresh the files with `PEPL_BLESS=1 cargo test --test bindings_tests`.
+
+use std::path::Path;
+
+use pepl_compiler::bindings::Bindings;
+use pepl_compiler::compile_to_result;
+
+#[rustfmt::skip]
+#[allow(dead_code)]
+#[path = "bindings/todo_list.rs"]
+mod todo_list;
+
+use todo_list::{ItemsItem, PeplValue, Runtime, Status, Tag, TodoList, TodoListAction, Value};
+
+const TODO_LIST: &str = r#"
+space TodoList {
+  type Status = | Idle | Busy(task: string, progress: number)
+  type Tag = { label: string, weight?: number }
+
+  state {
+    items: list<{ text: string, done: bool, note?: string }> = []
+    history: list<Status> = []
+    outcomes: list<Result<number, string>> = []
+    filter: string = "all"
+    tags: list<Tag> = []
+  }
+
+  derived {
+    remaining: number = list.length(items)
+  }
+
+  invariant bounded {
+    list.length(items) < 3
+  }
+
+  action add_item(text: string) {
+    set items = list.append(items, { text: text, done: false })
+  }
+
+  action set_status(next: Status) {
+    set history = list.append(history, next)
+  }
+
+  action tag(label: string, weight: number) {
+    set tags = list.append(tags, { label: label, weight: weight })
+  }
+
+  action log_outcome(outcome: Result<number, string>) {
+    set outcomes = list.append(outcomes, outcome)
+  }
+
+  action clear() {
+    set items = []
+  }
+
+  view main() -> Surface {
+    Column { } {
+      Text { value: filter }
+      for item in items {
+        Text { value: item.text }
+      }
+      Button { label: "Clear", on_tap: clear }
+    }
+  }
+}
+"#;
+
+fn bindings(source: &str) -> Bindings {
+    let result = compile_to_result(source, "test.pepl");
+    assert!(result.success, "compile failed: {:?}", result.errors);
+    result.bindings().expect("bindings of a successful compile")
+}
+
+fn wasm(source: &str) -> Vec<u8> {
+    compile_to_result(source, "test.pepl")
+        .wasm
+        .expect("compiled module")
+}
+
+// ══════════════════════════════════════════════════════════════════════════════
+// Checked-in bindings
+// ══════════════════════════════════════════════════════════════════════════════
+
+#[test]
+fn checked_in_bindings_are_current() {
+    let generated = bindings(TODO_LIST);
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/bindings");
+    for (file, text) in [
+        ("todo_list.d.ts", &generated.typescript),
+        ("todo_list.js", &generated.javascript),
+        ("todo_list.rs", &generated.rust),
+    ] {
+        let path = dir.join(file);
+        if std::env::var_os("PEPL_BLESS").is_some() {
+            std::fs::write(&path, text).expect("write bindings");
+            continue;
+        }
+        let checked_in = std::fs::read_to_string(&path).expect("read bindings");
+        assert!(
+            checked_in == *text,
+            "tests/bindings/{file} is stale; rerun with PEPL_BLESS=1"
+        );
+    }
+}
+
+#[test]
+fn typescript_declares_state_actions_and_views() {
+    let ts = bindings(TODO_LIST).typescript;
+    for decl in [
+        r#"export type Status = "Idle" | { Busy: [task: string, progress: number] };"#,
+        "export type Tag = { label: string; weight?: number };",
+        "  items: Array<{ text: string; done: boolean; note?: string }>;",
+        "  outcomes: Array<{ Ok: number } | { Err: string }>;",
+        "  readonly remaining: number;",
+        "  addItem(text: string): void;",
+        "  setStatus(next: Status): void;",
+        "  logOutcome(outcome: { Ok: number } | { Err: string }): void;",
+        "  clear(): void;",
+        "  main(): SurfaceNode[];",
+        "  component: Component;",
+        "): Promise<TodoListSpace>;",
+    ] {
+        assert!(ts.contains(decl), "missing `{decl}` in:\n{ts}");
+    }
+    // No game loop, so no update / handleEvent
+    assert!(!ts.contains("update("));
+    assert!(!ts.contains("handleEvent("));
+}
+
+#[test]
+fn javascript_encodes_arguments_by_declared_type() {
+    let js = bindings(TODO_LIST).javascript;
+    for line in [
+        r#"const ACTIONS = ["add_item", "set_status", "tag", "log_outcome", "clear"];"#,
+        r#"const VARIANTS = ["Idle", "Busy"];"#,
+        r#"    Busy: [1, ["string", "number"]],"#,
+        r#"      addItem: (text) => dispatch(0, ["string"], [text]),"#,
+        r#"      setStatus: (next) => dispatch(1, [["variants", "Status"]], [next]),"#,
+        r#"      logOutcome: (outcome) => dispatch(3, [["result", "number", "string"]], [outcome]),"#,
+        r#"      main: () => surface(decode(exports, exports.render(0))),"#,
+    ] {
+        assert!(js.contains(line), "missing `{line}` in:\n{js}");
+    }
+}
+
+#[test]
+fn game_loop_entry_points_are_bound() {
+    let source = r#"
+space Ball {
+  state {
+    x: number = 0
+  }
+
+  view main() -> Surface {
+    Text { value: "ball" }
+  }
+
+  update(dt: number) {
+    set x = x + dt
+  }
+
+  handleEvent(event: InputEvent) {
+    set x = 0
+  }
+}
+"#;
+    let generated = bindings(source);
+    assert!(generated.typescript.contains("  update(dt: number): void;"));
+    assert!(generated
+        .typescript
+        .contains("  handleEvent(event: InputEvent): void;"));
+    assert!(generated.javascript.contains("    update: (dt) =>"));
+    assert!(generated
+        .rust
+        .contains("fn update(&mut self, dt: Value) -> Result<(), Self::Error>;"));
+    assert!(generated
+        .rust
+        .contains("pub fn handle_event(&mut self, event: Value) -> Result<(), R::Error> {"));
+}
+
+#[test]
+fn clashing_names_are_renamed() {
+    let source = r#"
+space Shop {
+  type Value = | Low | High
+  type Error = { code: number }
+
+  state {
+    price: Value = Low
+    newHabit: string = ""
+    move: number = 0
+  }
+
+  action restock(new: number, last: Error) {
+    set move = new
+  }
+
+  view main() -> Surface {
+    Text { value: newHabit }
+  }
+}
+"#;
+    let generated = bindings(source);
+    assert!(generated
+        .typescript
+        .contains(r#"export type ValueType = "Low" | "High";"#));
+    assert!(generated
+        .typescript
+        .contains("  restock(new_: number, last: ErrorType): void;"));
+    assert!(generated.typescript.contains("  newHabit: string;"));
+    for line in [
+        "pub enum ValueType {",
+        "pub struct ErrorType {",
+        "    pub price: ValueType,",
+        "    pub new_habit: String,",
+        "    pub r#move: f64,",
+        "            new_habit: PeplValue::from_value(take(&mut fields, \"newHabit\")?)?,",
+        "    Restock { new: f64, last: ErrorType },",
+    ] {
+        assert!(
+            generated.rust.contains(line),
+            "missing `{line}` in:\n{}",
+            generated.rust
+        );
+    }
+}
+
+#[test]
+fn failed_compiles_have_no_bindings() {
+    let result = compile_to_result("space Broken { state { x: number = } }", "test.pepl");
+    assert!(!result.success);
+    assert!(result.bindings().is_none());
+}
+
+// ══════════════════════════════════════════════════════════════════════════════
+// Rust bindings over wasmi
+// ══════════════════════════════════════════════════════════════════════════════
+
+const TAG_NIL: i32 = 0;
+const TAG_NUMBER: i32 = 1;
+const TAG_BOOL: i32 = 2;
+const TAG_STRING: i32 = 3;
+const TAG_LIST: i32 = 4;
+const TAG_RECORD: i32 = 5;
+const TAG_VARIANT: i32 = 6;
+const TAG_LAMBDA: i32 = 7;
+const TAG_ACTION_REF: i32 = 9;
+
+/// A compiled module behind the generated [`Runtime`] trait.
+struct WasmiRuntime {
+    store: wasmi::Store<()>,
+    instance: wasmi::Instance,
+    memory: wasmi::Memory,
+}
+
+impl WasmiRuntime {
+    fn new(wasm: &[u8]) -> Self {
+        let engine = wasmi::Engine::default();
+        let module = wasmi::Module::new(&engine, wasm).expect("parse module");
+        let mut store = wasmi::Store::new(&engine, ());
+        let mut linker = <wasmi::Linker<()>>::new(&engine);
+        linker
+            .func_wrap("env", "host_call", |_: i32, _: i32, _: i32| -> i32 { 0 })
+            .expect("link host_call");
+        linker
+            .func_wrap("env", "log", |_: i32, _: i32| {})
+            .expect("link log");
+        linker
+            .func_wrap(
+                "env",
+                "trap",
+                |_: i32, _: i32| -> Result<(), wasmi::Error> { Err(wasmi::Error::new("trap")) },
+            )
+            .expect("link trap");
+        linker
+            .func_wrap("env", "get_timestamp", || -> i64 { 0 })
+            .expect("link get_timestamp");
+        let instance = linker
+            .instantiate(&mut store, &module)
+            .and_then(|pre| pre.start(&mut store))
+            .expect("instantiate");
+        let memory = instance.get_memory(&store, "memory").expect("memory");
+        let mut runtime = Self {
+            store,
+            instance,
+            memory,
+        };
+        runtime.call::<(), ()>("init", ()).expect("init");
+        runtime
+    }
+
+    fn call<P: wasmi::WasmParams, R: wasmi::WasmResults>(
+        &mut self,
+        name: &str,
+        params: P,
+    ) -> Result<R, wasmi::Error> {
+        self.instance
+            .get_typed_func::<P, R>(&self.store, name)?
+            .call(&mut self.store, params)
+    }
+
+    fn word(&self, at: u32) -> u32 {
+        let bytes = &self.memory.data(&self.store)[at as usize..at as usize + 4];
+        u32::from_le_bytes(bytes.try_into().unwrap())
+    }
+
+    fn text(&self, ptr: u32, len: u32) -> String {
+        let data = self.memory.data(&self.store);
+        String::from_utf8(data[ptr as usize..(ptr + len) as usize].to_vec()).unwrap()
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) -> u32 {
+        let ptr = self.call::<i32, i32>("alloc", bytes.len() as i32).unwrap() as u32;
+        self.memory
+            .write(&mut self.store, ptr as usize, bytes)
+            .unwrap();
+        ptr
+    }
+
+    fn cell(&mut self, tag: i32, payload: [u8; 8]) -> u32 {
+        let mut bytes = tag.to_le_bytes().to_vec();
+        bytes.extend(payload);
+        self.write_bytes(&bytes)
+    }
+
+    fn words(&mut self, tag: i32, w1: u32, w2: u32) -> u32 {
+        let mut payload = [0; 8];
+        payload[..4].copy_from_slice(&w1.to_le_bytes());
+        payload[4..].copy_from_slice(&w2.to_le_bytes());
+        self.cell(tag, payload)
+    }
+
+    fn write(&mut self, value: &Value) -> u32 {
+        match value {
+            Value::Nil => self.words(TAG_NIL, 0, 0),
+            Value::Number(n) => self.cell(TAG_NUMBER, n.to_le_bytes()),
+            Value::Bool(b) => self.words(TAG_BOOL, *b as u32, 0),
+            Value::String(s) => {
+                let ptr = self.write_bytes(s.as_bytes());
+                self.words(TAG_STRING, ptr, s.len() as u32)
+            }
+            Value::List(items) => self.write_list(items),
+            Value::Record(fields) => {
+                let mut entries = Vec::new();
+                for (key, value) in fields {
+                    let key_ptr = self.write_bytes(key.as_bytes());
+                    let value_ptr = self.write(value);
+                    for word in [key_ptr, key.len() as u32, value_ptr] {
+                        entries.extend(word.to_le_bytes());
+                    }
+                }
+                let ptr = self.write_bytes(&entries);
+                self.words(TAG_RECORD, ptr, fields.len() as u32)
+            }
+            Value::Variant(id, fields)
+                if *id == todo_list::VARIANT_OK || *id == todo_list::VARIANT_ERR =>
+            {
+                let payload = self.write(&fields[0]);
+                self.words(TAG_VARIANT, *id, payload)
+            }
+            Value::Variant(id, fields) => {
+                let list = self.write_list(fields);
+                self.words(TAG_VARIANT, *id, list)
+            }
+            Value::Lambda(id) => self.words(TAG_LAMBDA, *id, 0),
+            Value::ActionRef(id) => self.words(TAG_ACTION_REF, *id, 0),
+        }
+    }
+
+    fn write_list(&mut self, items: &[Value]) -> u32 {
+        let mut ptrs = Vec::new();
+        for item in items {
+            ptrs.extend(self.write(item).to_le_bytes());
+        }
+        let ptr = self.write_bytes(&ptrs);
+        self.words(TAG_LIST, ptr, items.len() as u32)
+    }
+
+    fn read(&self, ptr: u32) -> Value {
+        let (w1, w2) = (self.word(ptr + 4), self.word(ptr + 8));
+        match self.word(ptr) as i32 {
+            TAG_NUMBER => {
+                let bits = (w1 as u64) | ((w2 as u64) << 32);
+                Value::Number(f64::from_bits(bits))
+            }
+            TAG_BOOL => Value::Bool(w1 != 0),
+            TAG_STRING => Value::String(self.text(w1, w2)),
+            TAG_LIST => Value::List((0..w2).map(|i| self.read(self.word(w1 + i * 4))).collect()),
+            TAG_RECORD => Value::Record(
+                (0..w2)
+                    .map(|i| {
+                        let at = w1 + i * 12;
+                        let key = self.text(self.word(at), self.word(at + 4));
+                        (key, self.read(self.word(at + 8)))
+                    })
+                    .collect(),
+            ),
+            TAG_VARIANT if w1 == todo_list::VARIANT_OK || w1 == todo_list::VARIANT_ERR => {
+                Value::Variant(w1, vec![self.read(w2)])
+            }
+            TAG_VARIANT => match self.read(w2) {
+                Value::List(fields) => Value::Variant(w1, fields),
+                _ => Value::Variant(w1, Vec::new()),
+            },
+            TAG_LAMBDA => Value::Lambda(w1),
+            TAG_ACTION_REF => Value::ActionRef(w1),
+            _ => Value::Nil,
+        }
+    }
+}
+
+impl Runtime for WasmiRuntime {
+    type Error = wasmi::Error;
+
+    fn dispatch_action(&mut self, action_id: u32, args: Vec<Value>) -> Result<(), wasmi::Error> {
+        let args = self.write_list(&args);
+        self.call::<(i32, i32, i32), ()>("dispatch_action", (action_id as i32, args as i32, 0))
+    }
+
+    fn get_state(&mut self) -> Result<Value, wasmi::Error> {
+        let ptr = self.call::<(), i32>("get_state", ())?;
+        Ok(self.read(ptr as u32))
+    }
+
+    fn render(&mut self, view_id: u32) -> Result<Value, wasmi::Error> {
+        let ptr = self.call::<i32, i32>("render", view_id as i32)?;
+        Ok(self.read(ptr as u32))
+    }
+}
+
+#[test]
+fn rust_bindings_dispatch_typed_actions_and_read_typed_state() {
+    let mut space = TodoList::new(WasmiRuntime::new(&wasm(TODO_LIST)));
+
+    let state = space.state().expect("initial state");
+    assert!(state.items.is_empty());
+    assert!(state.history.is_empty());
+    assert_eq!(state.filter, "all");
+    assert_eq!(state.remaining, 0.0);
+
+    space
+        .dispatch(TodoListAction::AddItem {
+            text: "milk".into(),
+        })
+        .unwrap();
+    space
+        .dispatch(TodoListAction::SetStatus {
+            next: Status::Busy {
+                task: "shop".into(),
+                progress: 0.5,
+            },
+        })
+        .unwrap();
+    space
+        .dispatch(TodoListAction::Tag {
+            label: "home".into(),
+            weight: 2.0,
+        })
+        .unwrap();
+    space
+        .dispatch(TodoListAction::LogOutcome { outcome: Ok(3.0) })
+        .unwrap();
+    space
+        .dispatch(TodoListAction::LogOutcome {
+            outcome: Err("late".into()),
+        })
+        .unwrap();
+
+    let state = space.state().expect("state after actions");
+    assert_eq!(
+        state.items,
+        vec![ItemsItem {
+            text: "milk".into(),
+            done: false,
+            note: None,
+        }]
+    );
+    assert_eq!(
+        state.history,
+        vec![Status::Busy {
+            task: "shop".into(),
+            progress: 0.5,
+        }]
+    );
+    assert_eq!(
+        state.tags,
+        vec![Tag {
+            label: "home".into(),
+            weight: Some(2.0),
+        }]
+    );
+    assert_eq!(state.outcomes, vec![Ok(3.0), Err("late".to_string())]);
+    assert_eq!(state.remaining, 1.0);
+}
+
+#[test]
+fn rust_bindings_render_surface_nodes() {
+    let mut space = TodoList::new(WasmiRuntime::new(&wasm(TODO_LIST)));
+    space
+        .dispatch(TodoListAction::AddItem { text: "a".into() })
+        .unwrap();
+    space
+        .dispatch(TodoListAction::AddItem { text: "b".into() })
+        .unwrap();
+
+    let nodes = space.render_main().expect("render");
+    assert_eq!(nodes.len(), 1);
+    assert_eq!(nodes[0].component, "Column");
+    let children: Vec<&str> = nodes[0]
+        .children
+        .iter()
+        .map(|n| n.component.as_str())
+        .collect();
+    // The `for` block's nodes are flattened into the column
+    assert_eq!(children, ["Text", "Text", "Text", "Button"]);
+    let button = &nodes[0].children[3];
+    let on_tap = button.props.iter().find(|(key, _)| key == "on_tap");
+    // `clear` is the fifth action
+    assert_eq!(on_tap.map(|(_, v)| v), Some(&Value::ActionRef(4)));
+    assert_eq!(TodoListAction::Clear.id(), 4);
+}
+
+#[test]
+fn rust_bindings_surface_runtime_failures() {
+    let mut space = TodoList::new(WasmiRuntime::new(&wasm(TODO_LIST)));
+    for text in ["a", "b"] {
+        space
+            .dispatch(TodoListAction::AddItem { text: text.into() })
+            .unwrap();
+    }
+    // A third item violates `bounded`; the runtime reports the trap and the
+    // state is rolled back
+    let third = TodoListAction::AddItem { text: "c".into() };
+    assert!(space.dispatch(third).is_err());
+    assert_eq!(space.state().unwrap().items.len(), 2);
+}
+
+#[test]
+fn rust_values_round_trip() {
+    let tag = Tag {
+        label: "x".into(),
+        weight: None,
+    };
+    assert_eq!(
+        tag.clone().into_value(),
+        Value::Record(vec![("label".into(), Value::String("x".into()))])
+    );
+    assert_eq!(Tag::from_value(tag.clone().into_value()), Some(tag));
+    // Unit and data variants, and the wrong field count
+    assert_eq!(Status::Idle.into_value(), Value::Variant(0, Vec::new()));
+    assert_eq!(
+        Status::from_value(Value::Variant(1, vec![Value::String("t".into())])),
+        None
+    );
+    assert_eq!(Status::from_value(Value::Variant(7, Vec::new())), None);
+}
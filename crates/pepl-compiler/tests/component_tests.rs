@@ -0,0 +1,438 @@
+//! WIT worlds and components — `CompileOptions::component`.
+//!
+//! The WIT for [`SHOP`] is checked in under `tests/component/`; refresh it
+//! with `PEPL_BLESS=1 cargo test --test component_tests` after an intended
+//! change.  The canonical ABI exports are driven on the core module through
+//! `wasmi`, reading results back in the component model's memory layout.
+
+use std::path::Path;
+
+use pepl_compiler::{compile_to_result_with_options, CompileOptions, CompileResult};
+
+const SHOP: &str = r#"
+space Shop {
+  type Size = | Small | Large
+  type Entry = | Added(name: string, qty: number) | Cleared
+
+  state {
+    items: list<{ name: string, qty: number, note?: string }> = []
+    size: Size = Small
+    history: list<Entry> = []
+    open: bool = true
+  }
+
+  capabilities {
+    required: [http]
+  }
+
+  derived {
+    count: number = list.length(items)
+  }
+
+  action add(name: string, qty: number) {
+    set items = list.append(items, { name: name, qty: qty })
+  }
+
+  action log_entry(entry: Entry) {
+    set history = list.append(history, entry)
+  }
+
+  action resize(next: Size) {
+    set size = next
+  }
+
+  action clear() {
+    set items = []
+  }
+
+  view main() -> Surface {
+    Column { } {
+      for item in items {
+        Text { value: item.name }
+      }
+      Button { label: "Clear", on_tap: clear }
+    }
+  }
+
+  update(dt: number) {
+    set open = dt > 0
+  }
+}
+"#;
+
+fn compile(source: &str, component: bool) -> CompileResult {
+    let options = CompileOptions {
+        component,
+        ..Default::default()
+    };
+    compile_to_result_with_options(source, "test.pepl", &options)
+}
+
+fn compiled(source: &str) -> CompileResult {
+    let result = compile(source, true);
+    assert!(result.success, "compile failed: {:?}", result.errors);
+    result
+}
+
+// ══════════════════════════════════════════════════════════════════════════════
+// WIT
+// ══════════════════════════════════════════════════════════════════════════════
+
+#[test]
+fn checked_in_wit_is_current() {
+    let wit = compiled(SHOP).wit.expect("wit");
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/component/shop.wit");
+    if std::env::var_os("PEPL_BLESS").is_some() {
+        std::fs::write(&path, &wit).expect("write wit");
+        return;
+    }
+    let checked_in = std::fs::read_to_string(&path).expect("read wit");
+    assert!(
+        checked_in == wit,
+        "tests/component/shop.wit is stale; rerun with PEPL_BLESS=1"
+    );
+}
+
+#[test]
+fn world_maps_space_types() {
+    let wit = compiled(SHOP).wit.expect("wit");
+    for decl in [
+        "    enum size {\n        small,\n        large,\n    }",
+        "    variant entry {\n        added(tuple<string, f64>),\n        cleared,\n    }",
+        "    record items-item {\n        name: string,\n        note: option<string>,\n        qty: f64,\n    }",
+        "        items: list<items-item>,",
+        "        count: f64,",
+        "    add: func(name: string, qty: f64);",
+        "    log-entry: func(entry: entry);",
+        "    resize: func(next: size);",
+        "    get-state: func() -> state;",
+        "    render-main: func() -> surface;",
+        "    update: func(dt: f64);",
+    ] {
+        assert!(wit.contains(decl), "missing `{decl}` in:\n{wit}");
+    }
+    assert!(!wit.contains("handle-event"));
+}
+
+#[test]
+fn world_imports_declared_capabilities_only() {
+    let wit = compiled(SHOP).wit.expect("wit");
+    for import in ["runtime", "time", "timer", "http"] {
+        assert!(wit.contains(&format!("    import pepl:host/{import}@0.1.0;")));
+    }
+    assert!(!wit.contains("pepl:host/storage"));
+    assert!(wit.contains("    export space;"));
+}
+
+#[test]
+fn host_package_types_capabilities() {
+    let wit = pepl_codegen::wit::host_wit();
+    for decl in [
+        "package pepl:host@0.1.0;",
+        "    get: func(url: string) -> result<string, string>;",
+        "    get: func(key: string) -> option<string>;",
+        "    keys: func() -> list<string>;",
+        "    current: func() -> position;",
+        "    timestamp: func() -> s64;",
+    ] {
+        assert!(wit.contains(decl), "missing `{decl}` in:\n{wit}");
+    }
+}
+
+#[test]
+fn names_colliding_with_builtins_get_a_suffix() {
+    let source = r#"
+space Clash {
+  type State = { label: string }
+  state {
+    current: State = { label: "a" }
+  }
+  action init() {
+    set current = { label: "b" }
+  }
+}
+"#;
+    let wit = compiled(source).wit.expect("wit");
+    assert!(wit.contains("    record state-type {"), "{wit}");
+    assert!(wit.contains("    init-action: func();"), "{wit}");
+}
+
+#[test]
+fn types_without_a_wit_counterpart_are_rejected() {
+    let source = r#"
+space Probe {
+  state {
+    last: string = ""
+  }
+  action paint(tint: color) {
+    set last = "painted"
+  }
+}
+"#;
+    let plain = compile(source, false);
+    assert!(plain.success, "{:?}", plain.errors);
+    let result = compile(source, true);
+    assert!(!result.success);
+    let message = &result.errors.errors[0].message;
+    assert!(message.contains("has no WIT type"), "{message}");
+}
+
+// ══════════════════════════════════════════════════════════════════════════════
+// Component
+// ══════════════════════════════════════════════════════════════════════════════
+
+#[test]
+fn component_is_only_built_on_request() {
+    let plain = compile(SHOP, false);
+    assert!(plain.success);
+    assert!(plain.component.is_none() && plain.wit.is_none());
+
+    let component = compiled(SHOP).component.expect("component");
+    // Component preamble: magic, version 0x0d, layer 1
+    assert_eq!(&component[..8], b"\0asm\x0d\0\x01\0");
+}
+
+#[test]
+fn space_without_views_or_capabilities_wraps() {
+    let source = r#"
+space Counter {
+  state {
+    count: number = 0
+  }
+  action increment(by: number) {
+    set count = count + by
+  }
+}
+"#;
+    let result = compiled(source);
+    assert!(result.component.is_some());
+    assert!(!result.wit.unwrap().contains("surface"));
+}
+
+#[test]
+fn every_capability_is_adapted() {
+    let source = r#"
+space Kiosk {
+  state {
+    status: string = ""
+  }
+  capabilities {
+    required: [http, storage]
+    optional: [location, notifications]
+  }
+  action refresh() {
+    set status = "refreshing"
+  }
+}
+"#;
+    let result = compiled(source);
+    assert!(result.component.is_some());
+    let wit = result.wit.unwrap();
+    for import in ["http", "storage", "location", "notifications"] {
+        assert!(wit.contains(&format!("    import pepl:host/{import}@0.1.0;")));
+    }
+}
+
+// ══════════════════════════════════════════════════════════════════════════════
+// Canonical ABI exports on the core module
+// ══════════════════════════════════════════════════════════════════════════════
+
+fn trap(_: i32, _: i32) {
+    panic!("trap");
+}
+
+struct Core {
+    store: wasmi::Store<()>,
+    instance: wasmi::Instance,
+    memory: wasmi::Memory,
+}
+
+impl Core {
+    fn new(wasm: &[u8]) -> Self {
+        let engine = wasmi::Engine::default();
+        let module = wasmi::Module::new(&engine, wasm).expect("parse module");
+        let mut store = wasmi::Store::new(&engine, ());
+        let mut linker = <wasmi::Linker<()>>::new(&engine);
+        linker
+            .func_wrap("env", "host_call", |_: i32, _: i32, _: i32| -> i32 { 0 })
+            .expect("link host_call");
+        linker
+            .func_wrap("env", "log", |_: i32, _: i32| {})
+            .expect("link log");
+        linker.func_wrap("env", "trap", trap).expect("link trap");
+        linker
+            .func_wrap("env", "get_timestamp", || -> i64 { 0 })
+            .expect("link get_timestamp");
+        let instance = linker
+            .instantiate(&mut store, &module)
+            .expect("instantiate")
+            .start(&mut store)
+            .expect("start");
+        let memory = instance.get_memory(&store, "memory").expect("memory");
+        Self {
+            store,
+            instance,
+            memory,
+        }
+    }
+
+    fn func<P: wasmi::WasmParams, R: wasmi::WasmResults>(
+        &self,
+        name: &str,
+    ) -> wasmi::TypedFunc<P, R> {
+        self.instance
+            .get_typed_func(&self.store, &format!("pepl:shop/space#{name}"))
+            .unwrap_or_else(|e| panic!("{name}: {e}"))
+    }
+
+    fn call<P: wasmi::WasmParams, R: wasmi::WasmResults>(&mut self, name: &str, params: P) -> R {
+        let func = self.func::<P, R>(name);
+        func.call(&mut self.store, params).expect("call")
+    }
+
+    /// Copy `s` into memory through `cabi_realloc`.
+    fn string(&mut self, s: &str) -> (i32, i32) {
+        let realloc = self
+            .instance
+            .get_typed_func::<(i32, i32, i32, i32), i32>(&self.store, "cabi_realloc")
+            .expect("cabi_realloc");
+        let ptr = realloc
+            .call(&mut self.store, (0, 0, 1, s.len() as i32))
+            .expect("realloc");
+        self.memory
+            .write(&mut self.store, ptr as usize, s.as_bytes())
+            .expect("write");
+        (ptr, s.len() as i32)
+    }
+
+    fn bytes(&self, at: i32, len: usize) -> &[u8] {
+        &self.memory.data(&self.store)[at as usize..at as usize + len]
+    }
+
+    fn u8(&self, at: i32) -> u8 {
+        self.bytes(at, 1)[0]
+    }
+
+    fn u32(&self, at: i32) -> i32 {
+        i32::from_le_bytes(self.bytes(at, 4).try_into().unwrap())
+    }
+
+    fn f64(&self, at: i32) -> f64 {
+        f64::from_le_bytes(self.bytes(at, 8).try_into().unwrap())
+    }
+
+    /// A `string` or `list` at `at`: (ptr, len).
+    fn span(&self, at: i32) -> (i32, i32) {
+        (self.u32(at), self.u32(at + 4))
+    }
+
+    fn str(&self, at: i32) -> String {
+        let (ptr, len) = self.span(at);
+        String::from_utf8(self.bytes(ptr, len as usize).to_vec()).unwrap()
+    }
+}
+
+fn core() -> Core {
+    let mut core = Core::new(&compiled(SHOP).wasm.expect("wasm"));
+    core.call::<(), ()>("init", ());
+    core
+}
+
+// `state` layout: items @0, size @8, history @12, open @20, count @24.
+// `item` layout: name @0, note @8 (payload @12), qty @24; 32 bytes.
+// `entry` layout: case @0, payload @8 (name @8, qty @16); 24 bytes.
+
+#[test]
+fn actions_take_flat_arguments() {
+    let mut core = core();
+    let (ptr, len) = core.string("pen");
+    core.call::<(i32, i32, f64), ()>("add", (ptr, len, 2.0));
+    // `added("pen", 2)`: case, then the tuple's string and f64
+    core.call::<(i32, i32, i32, f64), ()>("log-entry", (0, ptr, len, 2.0));
+    core.call::<i32, ()>("resize", 1);
+
+    let state = core.call::<(), i32>("get-state", ());
+    let (items, n) = core.span(state);
+    assert_eq!(n, 1);
+    assert_eq!(core.str(items), "pen");
+    assert_eq!(core.u8(items + 8), 0, "note is none");
+    assert_eq!(core.f64(items + 24), 2.0);
+    assert_eq!(core.u8(state + 8), 1, "size is large");
+
+    let (history, n) = core.span(state + 12);
+    assert_eq!(n, 1);
+    assert_eq!(core.u8(history), 0, "added");
+    assert_eq!(core.str(history + 8), "pen");
+    assert_eq!(core.f64(history + 16), 2.0);
+
+    assert_eq!(core.u8(state + 20), 1, "open");
+    assert_eq!(core.f64(state + 24), 1.0, "derived count");
+}
+
+#[test]
+fn unit_cases_and_scalars_round_trip() {
+    let mut core = core();
+    core.call::<(i32, i32, i32, f64), ()>("log-entry", (1, 0, 0, 0.0));
+    core.call::<(), ()>("clear", ());
+    core.call::<f64, ()>("update", 0.0);
+    let state = core.call::<(), i32>("get-state", ());
+    let (history, n) = core.span(state + 12);
+    assert_eq!(n, 1);
+    assert_eq!(core.u8(history), 1, "cleared");
+    assert_eq!(core.span(state).1, 0, "items cleared");
+    assert_eq!(core.u8(state + 20), 0, "update ran");
+}
+
+#[test]
+fn render_flattens_the_surface() {
+    let mut core = core();
+    let (ptr, len) = core.string("pen");
+    core.call::<(i32, i32, f64), ()>("add", (ptr, len, 2.0));
+    let surface = core.call::<(), i32>("render-main", ());
+
+    let (roots, n) = core.span(surface);
+    assert_eq!(n, 1);
+    assert_eq!(core.u32(roots), 0);
+    let (nodes, n) = core.span(surface + 8);
+    let components: Vec<String> = (0..n).map(|i| core.str(nodes + 24 * i)).collect();
+    assert_eq!(components, ["Column", "Text", "Button"]);
+
+    let (children, n) = core.span(nodes + 16);
+    let children: Vec<i32> = (0..n).map(|i| core.u32(children + 4 * i)).collect();
+    assert_eq!(children, [1, 2]);
+
+    // `prop`: name @0, value case @8, payload @12; 20 bytes
+    let prop = |core: &Core, node: i32, name: &str| {
+        let (props, n) = core.span(nodes + 24 * node + 8);
+        (0..n)
+            .map(|i| props + 20 * i)
+            .find(|&p| core.str(p) == name)
+            .unwrap_or_else(|| panic!("no prop {name}"))
+    };
+    let value = prop(&core, 1, "value");
+    assert_eq!(core.u8(value + 8), 1, "json");
+    assert_eq!(core.str(value + 12), "\"pen\"");
+    let on_tap = prop(&core, 2, "on_tap");
+    assert_eq!(core.u8(on_tap + 8), 0, "action");
+    assert_eq!(core.u32(on_tap + 12), 3, "clear is action 3");
+}
+
+#[test]
+fn snapshots_cross_as_strings() {
+    let mut core = core();
+    let (ptr, len) = core.string("pen");
+    core.call::<(i32, i32, f64), ()>("add", (ptr, len, 2.0));
+    core.call::<i32, ()>("resize", 1);
+    let ret = core.call::<(), i32>("serialize-state", ());
+    let snapshot = core.str(ret);
+    assert!(snapshot.contains("\"pen\""), "{snapshot}");
+
+    core.call::<(), ()>("clear", ());
+    let (ptr, len) = core.string(&snapshot);
+    assert_eq!(core.call::<(i32, i32), i32>("restore-state", (ptr, len)), 1);
+    let state = core.call::<(), i32>("get-state", ());
+    assert_eq!(core.span(state).1, 1);
+
+    let (ptr, len) = core.string("not json");
+    assert_eq!(core.call::<(i32, i32), i32>("restore-state", (ptr, len)), 0);
+}
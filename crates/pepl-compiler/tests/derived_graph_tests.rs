@@ -0,0 +1,365 @@
+//! Derived-field dependency graph — `CompileResult.derived_graph`, E610 for
+//! cycles, and selective recomputation in the evaluator.
+
+use std::collections::BTreeSet;
+
+use pepl_compiler::{compile_to_result, type_check};
+use pepl_eval::SpaceInstance;
+use pepl_lexer::Lexer;
+use pepl_parser::Parser;
+use pepl_stdlib::Value;
+use pepl_types::ast::Program;
+use pepl_types::derived::{DerivedGraph, RecomputePlan};
+use pepl_types::{ErrorCode, SourceFile};
+
+// ══════════════════════════════════════════════════════════════════════════════
+// Helpers
+// ══════════════════════════════════════════════════════════════════════════════
+
+/// `summary` is declared before the fields it reads.
+const CHAIN: &str = r#"
+space S {
+  state {
+    items: list<number> = [1, 2, 3]
+    bonus: number = 0
+    label: string = "total"
+  }
+  derived {
+    summary: string = "${label}: ${total}"
+    total: number = count + bonus
+    count: number = list.length(list.reverse(items))
+  }
+  action add() {
+    set items = list.append(items, 1)
+  }
+  action relabel() {
+    set label = "sum"
+  }
+  action boost() {
+    set bonus = bonus + 1
+  }
+  view main() -> Surface {
+    Text { value: summary }
+  }
+}
+"#;
+
+fn graph(source: &str) -> DerivedGraph {
+    let result = compile_to_result(source, "test.pepl");
+    assert!(result.success, "compile failed: {:?}", result.errors);
+    result.derived_graph.unwrap()
+}
+
+fn cycle_errors(source: &str) -> Vec<String> {
+    type_check(source, "test.pepl")
+        .errors
+        .iter()
+        .filter(|e| e.code == ErrorCode::DERIVED_CYCLE)
+        .map(|e| e.message.clone())
+        .collect()
+}
+
+fn parse(source: &str) -> Program {
+    let sf = SourceFile::new("test.pepl", source);
+    let lex = Lexer::new(&sf).lex();
+    Parser::new(lex.tokens, &sf).parse().program.unwrap()
+}
+
+fn instance(source: &str) -> SpaceInstance {
+    SpaceInstance::new(&parse(source)).unwrap()
+}
+
+/// Gas used by dispatching `action` once.
+fn gas_for(space: &mut SpaceInstance, action: &str) -> u64 {
+    let before = space.gas_used();
+    space.dispatch(action, vec![]).unwrap();
+    space.gas_used() - before
+}
+
+// ══════════════════════════════════════════════════════════════════════════════
+// Graph
+// ══════════════════════════════════════════════════════════════════════════════
+
+#[test]
+fn dependencies_and_order() {
+    let graph = graph(CHAIN);
+    let names: Vec<_> = graph.fields.iter().map(|f| f.name.as_str()).collect();
+    assert_eq!(names, vec!["summary", "total", "count"]);
+
+    let summary = &graph.fields[0];
+    assert_eq!(summary.state_deps, vec!["label"]);
+    assert_eq!(summary.derived_deps, vec!["total"]);
+    let total = &graph.fields[1];
+    assert_eq!(total.state_deps, vec!["bonus"]);
+    assert_eq!(total.derived_deps, vec!["count"]);
+
+    let order: Vec<_> = graph
+        .order
+        .iter()
+        .map(|&i| graph.fields[i].name.as_str())
+        .collect();
+    assert_eq!(order, vec!["count", "total", "summary"]);
+}
+
+#[test]
+fn credential_dependencies() {
+    let graph = graph(
+        r#"
+space S {
+  state {
+    label: string = "total"
+  }
+  credentials {
+    api_key: string
+  }
+  derived {
+    auth: string = "Bearer ${api_key}"
+  }
+  view main() -> Surface {
+    Text { value: label }
+  }
+}
+"#,
+    );
+    assert_eq!(graph.fields[0].credential_deps, vec!["api_key"]);
+    assert!(graph.fields[0].state_deps.is_empty());
+}
+
+#[test]
+fn local_names_are_not_dependencies() {
+    let graph = graph(
+        r#"
+space S {
+  state {
+    items: list<number> = [1, 2, 3]
+    bonus: number = 0
+  }
+  derived {
+    doubled: list<number> = list.map(items, fn(bonus: number) { bonus * 2 })
+    top: number = list.length(doubled)
+  }
+  view main() -> Surface {
+    Text { value: "${top} ${bonus}" }
+  }
+}
+"#,
+    );
+    assert_eq!(graph.fields[0].state_deps, vec!["items"]);
+    assert_eq!(graph.fields[1].derived_deps, vec!["doubled"]);
+}
+
+#[test]
+fn downstream_of_writes() {
+    let graph = graph(CHAIN);
+    let downstream = |written: &[&str]| -> Vec<String> {
+        let written = written.iter().map(|s| s.to_string()).collect();
+        graph
+            .downstream(&written)
+            .into_iter()
+            .map(|i| graph.fields[i].name.clone())
+            .collect()
+    };
+    assert_eq!(downstream(&["label"]), vec!["summary"]);
+    assert_eq!(downstream(&["bonus"]), vec!["total", "summary"]);
+    assert_eq!(downstream(&["items"]), vec!["count", "total", "summary"]);
+    assert!(downstream(&[]).is_empty());
+}
+
+#[test]
+fn graph_serialization() {
+    let json = serde_json::to_value(graph(CHAIN)).unwrap();
+    assert_eq!(json["fields"][0]["name"], "summary");
+    assert_eq!(json["fields"][0]["derived_deps"][0], "total");
+    assert_eq!(json["order"][0], 2);
+}
+
+#[test]
+fn host_reads_are_always_downstream() {
+    let graph = graph(
+        r#"
+space S {
+  state {
+    items: list<number> = [1, 2, 3]
+    bonus: number = 0
+    label: string = "total"
+  }
+  derived {
+    elapsed: number = time.now() - bonus
+    shown: string = "${elapsed}"
+    size: number = list.length(items)
+  }
+  view main() -> Surface {
+    Text { value: "${label} ${shown} ${size}" }
+  }
+}
+"#,
+    );
+    assert!(graph.fields[0].reads_host);
+    assert!(!graph.fields[1].reads_host);
+    let downstream: Vec<_> = graph
+        .downstream(&BTreeSet::from(["label".to_string()]))
+        .into_iter()
+        .map(|i| graph.fields[i].name.as_str())
+        .collect();
+    assert_eq!(downstream, vec!["elapsed", "shown"]);
+}
+
+// ══════════════════════════════════════════════════════════════════════════════
+// Cycles
+// ══════════════════════════════════════════════════════════════════════════════
+
+#[test]
+fn cycle_between_fields() {
+    let source = r#"
+space S {
+  state {
+    bonus: number = 0
+  }
+  derived {
+    a: number = b + bonus
+    b: number = c + 1
+    c: number = a * 2
+  }
+  view main() -> Surface {
+    Text { value: "${a}" }
+  }
+}
+"#;
+    assert_eq!(
+        cycle_errors(source),
+        vec!["derived fields depend on each other: a → b → c → a"]
+    );
+}
+
+#[test]
+fn field_depending_on_itself() {
+    let source = r#"
+space S {
+  state {
+    bonus: number = 0
+  }
+  derived {
+    a: number = a + bonus
+  }
+  view main() -> Surface {
+    Text { value: "${a}" }
+  }
+}
+"#;
+    assert_eq!(
+        cycle_errors(source),
+        vec!["derived field 'a' depends on itself"]
+    );
+}
+
+#[test]
+fn cycle_is_reported_with_the_graph() {
+    let source = r#"
+space S {
+  state {
+    bonus: number = 0
+  }
+  derived {
+    a: number = b
+    b: number = a
+  }
+  view main() -> Surface {
+    Text { value: "${a} ${bonus}" }
+  }
+}
+"#;
+    let result = compile_to_result(source, "test.pepl");
+    assert!(!result.success);
+    let error = result
+        .errors
+        .errors
+        .iter()
+        .find(|e| e.code == ErrorCode::DERIVED_CYCLE)
+        .expect("expected E610");
+    assert!(error.suggestion.is_some());
+    assert_eq!(result.derived_graph.unwrap().fields.len(), 2);
+}
+
+#[test]
+fn shadowed_name_is_not_a_cycle() {
+    let source = r#"
+space S {
+  state {
+    items: list<number> = [1, 2, 3]
+  }
+  derived {
+    total: number = list.reduce(items, 0, fn(total: number, x: number) { total + x })
+  }
+  view main() -> Surface {
+    Text { value: "${total}" }
+  }
+}
+"#;
+    assert!(cycle_errors(source).is_empty());
+}
+
+// ══════════════════════════════════════════════════════════════════════════════
+// Recomputation
+// ══════════════════════════════════════════════════════════════════════════════
+
+#[test]
+fn forward_references_evaluate_in_order() {
+    let mut space = instance(CHAIN);
+    assert_eq!(
+        space.get_state("summary"),
+        Some(&Value::String("total: 3".into()))
+    );
+    space.dispatch("boost", vec![]).unwrap();
+    space.dispatch("add", vec![]).unwrap();
+    space.dispatch("relabel", vec![]).unwrap();
+    assert_eq!(
+        space.get_state("summary"),
+        Some(&Value::String("sum: 5".into()))
+    );
+}
+
+#[test]
+fn host_reads_are_recomputed_after_every_action() {
+    let program = parse(
+        r#"
+space S {
+  state {
+    bonus: number = 0
+    label: string = "total"
+  }
+  derived {
+    elapsed: number = time.now() - bonus
+  }
+  action relabel() {
+    set label = "sum"
+  }
+  action boost() {
+    set bonus = bonus + 1
+  }
+  view main() -> Surface {
+    Text { value: "${label} ${elapsed}" }
+  }
+}
+"#,
+    );
+    let plan = RecomputePlan::new(&program.space.body);
+    assert!(plan.actions.iter().all(|fields| fields == &vec![0]));
+
+    let mut space = SpaceInstance::new(&program).unwrap();
+    space.dispatch("relabel", vec![]).unwrap();
+    assert!(matches!(space.get_state("elapsed"), Some(Value::Number(_))));
+}
+
+#[test]
+fn only_affected_fields_are_recomputed() {
+    let long = CHAIN.replace("[1, 2, 3]", &format!("[{}]", ["1"; 40].join(", ")));
+    let (mut short, mut long) = (instance(CHAIN), instance(&long));
+
+    // `relabel` and `boost` leave `count`, which walks `items`, alone
+    assert_eq!(
+        gas_for(&mut short, "relabel"),
+        gas_for(&mut long, "relabel")
+    );
+    assert_eq!(gas_for(&mut short, "boost"), gas_for(&mut long, "boost"));
+    assert!(gas_for(&mut short, "add") < gas_for(&mut long, "add"));
+}
@@ -8,6 +8,10 @@
 //!    tree-walking evaluator (`pepl_eval::SpaceInstance`) and through the
 //!    compiled WASM module (via `wasmi`), compare state after init and after
 //!    action dispatches.
+//!
+//! The unboxed section also runs the pipeline's type-directed output against
+//! the plain boxed codegen and benchmarks their heap traffic.  The optimised
+//! section does the same for `OptLevel::O1` against the unoptimised module.
 
 use pepl_codegen::compile;
 use pepl_eval::SpaceInstance;
@@ -98,9 +102,9 @@ impl WasmRunner {
 
         // env.host_call(cap_id: i32, fn_id: i32, args_ptr: i32) -> i32
         //
-        // Stub: for determinism/parity tests on simple programs we return a
-        // NIL value.  The caller should avoid programs that depend on specific
-        // stdlib results unless they are pure-math (those are compiled inline).
+        // Stub: only capability calls reach the host (the pure stdlib is
+        // compiled into the module), so parity programs must not depend on
+        // capability results.  We return a NIL value.
         linker
             .func_wrap(
                 "env",
@@ -110,11 +114,7 @@ impl WasmRunner {
                  _fn_id: i32,
                  _args_ptr: i32|
                  -> i32 {
-                    // Allocate a NIL value cell in memory and return its pointer.
-                    // Read the heap pointer from global 0, allocate 12 bytes.
                     // Simple stub: return 0 for NIL.
-                    // Programs using stdlib calls should not depend on
-                    // specific return values for parity tests.
                     let _ = caller;
                     0
                 },
@@ -148,7 +148,10 @@ impl WasmRunner {
             .func_wrap(
                 "env",
                 "trap",
-                |mut caller: wasmi::Caller<'_, HostState>, ptr: i32, len: i32| -> () {
+                |mut caller: wasmi::Caller<'_, HostState>,
+                 ptr: i32,
+                 len: i32|
+                 -> Result<(), wasmi::Error> {
                     let mem = caller
                         .get_export("memory")
                         .and_then(|e| e.into_memory())
@@ -162,7 +165,7 @@ impl WasmRunner {
                         "<invalid trap message>".to_string()
                     };
                     caller.data_mut().trap_message = Some(msg.clone());
-                    panic!("WASM trap: {msg}");
+                    Err(wasmi::Error::new(format!("WASM trap: {msg}")))
                 },
             )
             .expect("link trap");
@@ -782,3 +785,925 @@ fn determinism_end_to_end_all_sources() {
         );
     }
 }
+
+// ══════════════════════════════════════════════════════════════════════════════
+// Unboxed codegen — parity with the boxed output
+// ══════════════════════════════════════════════════════════════════════════════
+
+/// A tight numeric loop: every step mixes arithmetic, comparisons and logic
+/// on `number`/`bool` values that only escape through `set`.
+const CRUNCH: &str = r#"
+space Crunch {
+  state {
+    items: list<number> = [3, 1, 4, 1, 5, 9, 2, 6, 5, 3, 5, 8, 9, 7, 9, 3, 2, 3, 8, 4]
+    total: number = 0
+    evens: number = 0
+    peak: number = 0
+    flags: list<bool> = []
+  }
+
+  action crunch() {
+    for x, i in items {
+      let weighted = x * 2 + i / 4 - (x % 3)
+      let even = x % 2 == 0
+      set total = total + weighted
+      if even and weighted > 3 {
+        set evens = evens + 1
+      }
+      if weighted > peak or -weighted > peak {
+        set peak = weighted
+      }
+    }
+    set flags = [total > 100, not (evens == 0), peak / 2 >= 10]
+  }
+
+  view main() -> Surface {
+    Text { value: "crunch" }
+  }
+}
+
+tests {
+  test "native operators match boxed semantics" {
+    assert 7 / 2 == 3.5
+    assert 7 % 2 == 1
+    assert -7 % 2 == -1
+    assert (7 > 2) and not (7 == 2)
+    assert (2 - 2 == 0) or false
+    crunch()
+    assert total == 222.5
+  }
+}
+"#;
+
+/// Compile through the full pipeline, which hands checker types to codegen.
+fn compile_typed(source: &str) -> Vec<u8> {
+    pepl_compiler::compile(source, "test.pepl")
+        .unwrap_or_else(|e| panic!("pipeline compile failed: {e:?}"))
+}
+
+fn dispatch(runner: &mut WasmRunner, action_id: i32) {
+    try_dispatch(runner, action_id).expect("wasm dispatch");
+}
+
+/// Dispatch `action_id`, which traps after rolling back when an invariant
+/// fails.
+fn try_dispatch(runner: &mut WasmRunner, action_id: i32) -> Result<(), wasmi::Error> {
+    runner
+        .instance
+        .get_typed_func::<(i32, i32, i32), ()>(&runner.store, "dispatch_action")
+        .expect("no dispatch_action")
+        .call(&mut runner.store, (action_id, 0, 0))
+}
+
+/// Current bump-allocator position (`alloc(0)` returns the heap pointer).
+fn heap_ptr(runner: &mut WasmRunner) -> i32 {
+    runner
+        .instance
+        .get_typed_func::<i32, i32>(&runner.store, "alloc")
+        .expect("no alloc export")
+        .call(&mut runner.store, 0)
+        .expect("alloc trapped")
+}
+
+#[test]
+fn unboxed_codegen_matches_boxed_and_eval() {
+    let fields = ["items", "total", "evens", "peak", "flags"];
+    let mut eval = eval_instance(CRUNCH);
+    let mut boxed = WasmRunner::new(&compile_source(CRUNCH));
+    let mut typed = WasmRunner::new(&compile_typed(CRUNCH));
+    boxed.init();
+    typed.init();
+
+    for step in 0..3 {
+        eval.dispatch("crunch", vec![]).expect("eval dispatch");
+        dispatch(&mut boxed, 0);
+        dispatch(&mut typed, 0);
+        let context = format!("Crunch after {} dispatches", step + 1);
+        assert_state_parity(&eval, &mut typed, &fields, &context);
+        assert_state_parity(&eval, &mut boxed, &fields, &context);
+    }
+}
+
+#[test]
+fn unboxed_codegen_test_block_passes() {
+    let summary = pepl_eval::run_tests(&parse(CRUNCH)).expect("eval run_tests");
+    assert_eq!(summary.failed, 0, "eval:\n{summary}");
+
+    for wasm in [compile_source(CRUNCH), compile_typed(CRUNCH)] {
+        let mut runner = WasmRunner::new(&wasm);
+        runner
+            .instance
+            .get_typed_func::<(), ()>(&runner.store, "__test_0")
+            .expect("no __test_0 export")
+            .call(&mut runner.store, ())
+            .expect("native operator assertions trapped");
+    }
+}
+
+#[test]
+fn unboxed_locals_captured_by_lambdas_compile() {
+    let source = r#"
+space Capture {
+  state {
+    nums: list<number> = [1, 2, 3]
+    big: number = 0
+  }
+
+  action count_big(scale: number) {
+    let limit = scale * 2
+    for n, i in nums {
+      set big = list.length(list.filter(nums, fn(m: number) { m * i > limit }))
+    }
+  }
+
+  view main() -> Surface {
+    Text { value: "capture" }
+  }
+}
+"#;
+    // Codegen validates the module; captures must see boxed copies.
+    let wasm = compile_typed(source);
+    assert!(wasm.starts_with(b"\0asm"));
+}
+
+/// Benchmark: the same loop compiled boxed (plain codegen) and unboxed (with
+/// checker types), compared on heap traffic, which is deterministic.
+#[test]
+fn unboxed_codegen_benchmark_against_boxed_output() {
+    const DISPATCHES: usize = 200;
+
+    let measure = |wasm: &[u8]| {
+        let mut runner = WasmRunner::new(wasm);
+        runner.init();
+        let heap_before = heap_ptr(&mut runner);
+        for _ in 0..DISPATCHES {
+            dispatch(&mut runner, 0);
+        }
+        (heap_ptr(&mut runner) - heap_before) as usize / DISPATCHES
+    };
+
+    let boxed_bytes = measure(&compile_source(CRUNCH));
+    let typed_bytes = measure(&compile_typed(CRUNCH));
+
+    assert!(
+        typed_bytes * 2 < boxed_bytes,
+        "unboxed codegen should at least halve loop allocation: \
+         {typed_bytes} vs {boxed_bytes} bytes per dispatch"
+    );
+}
+
+// ══════════════════════════════════════════════════════════════════════════════
+// Optimised codegen — parity with the unoptimised output
+// ══════════════════════════════════════════════════════════════════════════════
+
+/// Literal arithmetic, literal `if` conditions (in actions and the view) and
+/// literal string concatenation — everything `O1` folds away.
+const FOLDED: &str = r#"
+space Folded {
+  state {
+    score: number = 2 * 3 + 1
+    level: number = 0
+    ready: bool = 1 < 2 and not false
+    title: string = "lvl"
+    debug: bool = false
+  }
+
+  action bump() {
+    set score = score + 10 / 4 - (7 % 3)
+    if 2 > 3 {
+      set level = 99
+    } else if true {
+      set level = level + 1
+    } else {
+      set level = -1
+    }
+    if false or 1 == 1 {
+      set ready = ready and 3 >= 3
+    }
+  }
+
+  action rename() {
+    set title = "level " + "up"
+  }
+
+  view main() -> Surface {
+    Column { } {
+      if false {
+        Text { value: "debug" }
+      } else {
+        Text { value: title }
+      }
+      if 1 + 1 == 2 {
+        Text { value: "ok" }
+      }
+    }
+  }
+}
+
+tests {
+  test "folded branches keep their meaning" {
+    bump()
+    bump()
+    assert score == 10
+    assert level == 2
+    assert ready
+  }
+}
+"#;
+
+/// `bump` commits once, then breaks the invariant and rolls back.
+const ROLLBACK: &str = r#"
+space Rollback {
+  state {
+    count: number = 0
+  }
+
+  derived {
+    doubled: number = count * 2
+  }
+
+  invariant small {
+    count >= 0 and count < 3
+  }
+
+  action bump() {
+    set count = count + 2
+  }
+
+  view main() -> Surface {
+    Text { value: "${doubled}" }
+  }
+}
+"#;
+
+/// Derived fields declared before the fields they read, and actions that
+/// each write only some of their inputs — one of them behind a branch `O1`
+/// folds away.
+const DERIVED_CHAIN: &str = r#"
+space DerivedChain {
+  state {
+    items: list<number> = [1, 2, 3]
+    bonus: number = 0
+    label: string = "total"
+  }
+
+  derived {
+    summary: string = "${label}: ${total}"
+    total: number = count + bonus
+    count: number = list.length(list.reverse(items))
+  }
+
+  action add() {
+    set items = list.append(items, 4)
+  }
+
+  action boost() {
+    set bonus = bonus + 1
+    if false {
+      set items = []
+    }
+  }
+
+  action relabel() {
+    set label = "sum"
+  }
+
+  view main() -> Surface {
+    Text { value: summary }
+  }
+}
+"#;
+
+fn compile_opt(source: &str, opt_level: pepl_codegen::OptLevel) -> Vec<u8> {
+    let options = pepl_codegen::CodegenOptions {
+        opt_level,
+        ..Default::default()
+    };
+    pepl_codegen::compile_with_options(&parse(source), options)
+        .unwrap_or_else(|e| panic!("codegen at {opt_level:?} failed: {e}"))
+        .0
+}
+
+fn render(runner: &mut WasmRunner, view_id: i32) -> Value {
+    let ptr = runner
+        .instance
+        .get_typed_func::<i32, i32>(&runner.store, "render")
+        .expect("no render export")
+        .call(&mut runner.store, view_id)
+        .expect("render trapped");
+    runner.read_value(ptr)
+}
+
+fn assert_same_state(o0: &mut WasmRunner, o1: &mut WasmRunner, context: &str) {
+    let (before, after) = (o0.read_state(), o1.read_state());
+    assert_eq!(before.len(), after.len(), "{context}: field count differs");
+    for (field, value) in &before {
+        assert!(
+            values_equal(value, &after[field]),
+            "{context}: '{field}' differs\n  O0: {value:?}\n  O1: {:?}",
+            after[field]
+        );
+    }
+}
+
+#[test]
+fn optimised_codegen_matches_unoptimised_state() {
+    use pepl_codegen::OptLevel;
+
+    let sources = [
+        ("SimpleCounter", SIMPLE_COUNTER),
+        ("Toggle", TOGGLE),
+        ("Arithmetic", ARITHMETIC),
+        ("Crunch", CRUNCH),
+        ("Folded", FOLDED),
+        ("DerivedChain", DERIVED_CHAIN),
+    ];
+    for (name, source) in sources {
+        let actions: Vec<(i32, String)> = parse(source)
+            .space
+            .body
+            .actions
+            .iter()
+            .enumerate()
+            .filter(|(_, action)| action.params.is_empty())
+            .map(|(id, action)| (id as i32, action.name.name.clone()))
+            .collect();
+        let mut eval = eval_instance(source);
+        let mut o0 = WasmRunner::new(&compile_opt(source, OptLevel::O0));
+        let mut o1 = WasmRunner::new(&compile_opt(source, OptLevel::O1));
+        o0.init();
+        o1.init();
+        assert_same_state(&mut o0, &mut o1, &format!("{name} init"));
+
+        for round in 0..2 {
+            for (id, action) in &actions {
+                eval.dispatch(action, vec![]).expect("eval dispatch");
+                dispatch(&mut o0, *id);
+                dispatch(&mut o1, *id);
+                let context = format!("{name} after {action} (round {round})");
+                assert_same_state(&mut o0, &mut o1, &context);
+                let fields: Vec<String> = o1.read_state().into_keys().collect();
+                let fields: Vec<&str> = fields.iter().map(String::as_str).collect();
+                assert_state_parity(&eval, &mut o1, &fields, &context);
+            }
+        }
+    }
+}
+
+#[test]
+fn optimised_codegen_renders_the_taken_branches() {
+    use pepl_codegen::OptLevel;
+
+    let mut o0 = WasmRunner::new(&compile_opt(FOLDED, OptLevel::O0));
+    let mut o1 = WasmRunner::new(&compile_opt(FOLDED, OptLevel::O1));
+    o0.init();
+    o1.init();
+    let (before, after) = (render(&mut o0, 0), render(&mut o1, 0));
+    assert!(
+        values_equal(&before, &after),
+        "render differs\n  O0: {before:?}\n  O1: {after:?}"
+    );
+}
+
+#[test]
+fn string_concatenation_matches_eval() {
+    use pepl_codegen::OptLevel;
+
+    for opt_level in [OptLevel::O0, OptLevel::O1] {
+        let mut eval = eval_instance(FOLDED);
+        let mut runner = WasmRunner::new(&compile_opt(FOLDED, opt_level));
+        runner.init();
+        eval.dispatch("rename", vec![]).expect("eval dispatch");
+        dispatch(&mut runner, 1);
+        let context = format!("Folded after rename at {opt_level:?}");
+        assert_state_parity(&eval, &mut runner, &["title"], &context);
+    }
+}
+
+#[test]
+fn optimised_codegen_test_block_passes() {
+    let wasm = compile_opt(FOLDED, pepl_codegen::OptLevel::O1);
+    let mut runner = WasmRunner::new(&wasm);
+    runner
+        .instance
+        .get_typed_func::<(), ()>(&runner.store, "__test_0")
+        .expect("no __test_0 export")
+        .call(&mut runner.store, ())
+        .expect("folded assertions trapped");
+}
+
+#[test]
+fn optimised_codegen_is_smaller() {
+    use pepl_codegen::OptLevel;
+
+    for source in [SIMPLE_COUNTER, TOGGLE, MULTI_STATE, MINIMAL_SPACE, ARITHMETIC, FOLDED] {
+        let o0 = compile_opt(source, OptLevel::O0);
+        let o1 = compile_opt(source, OptLevel::O1);
+        assert!(o1.len() < o0.len(), "O1 {} bytes vs O0 {} bytes", o1.len(), o0.len());
+    }
+}
+
+// ══════════════════════════════════════════════════════════════════════════════
+// Compiled stdlib — Eval ↔ Codegen
+// ══════════════════════════════════════════════════════════════════════════════
+
+const STDLIB_CALLS: &str = r#"
+space StdlibCalls {
+  state {
+    items: list<number> = [4, 9, 2]
+    label: string = ""
+    total: number = 0
+    parsed: number = 0
+    kind: string = ""
+  }
+
+  action run() {
+    let scaled = list.map(items, fn(x: number) { math.round_to(math.sqrt(x) / 3, 2) })
+    let kept = list.filter(scaled, fn(x: number) { x > 0.5 })
+    set items = list.append(kept, math.clamp(math.abs(-7.5), 0, 5))
+    set label = string.pad_start(string.trim("  ${list.length(items)} "), 3, "0")
+    set total = math.max(math.floor(2.7), math.ceil(list.get(items, 0)))
+    set kind = core.type_of(convert.parse_int("x"))
+    match convert.parse_float(" 2.5 ") {
+      Ok(n) -> { set parsed = n * 2 }
+      Err(e) -> { set parsed = -1 }
+    }
+  }
+
+  view main() -> Surface { Column { } { } }
+}
+"#;
+
+#[test]
+fn compiled_stdlib_matches_eval() {
+    let fields = ["items", "label", "total", "parsed", "kind"];
+    let mut eval = eval_instance(STDLIB_CALLS);
+    let mut runner = WasmRunner::new(&compile_source(STDLIB_CALLS));
+    runner.init();
+
+    for step in 0..2 {
+        eval.dispatch("run", vec![]).expect("eval dispatch");
+        dispatch(&mut runner, 0);
+        let context = format!("StdlibCalls after {} dispatches", step + 1);
+        assert_state_parity(&eval, &mut runner, &fields, &context);
+    }
+}
+
+// ══════════════════════════════════════════════════════════════════════════════
+// Record layouts — Eval ↔ Codegen
+// ══════════════════════════════════════════════════════════════════════════════
+
+/// Nested reads and sets on checked record types, including wider records
+/// stored out of the canonical order (which must take the key lookup).
+const SHAPES: &str = r#"
+space Shapes {
+  state {
+    box: { size: { w: number, h: number }, label: string } = { size: { w: 1, h: 2 }, label: "a" }
+    area: number = 0
+    tag: string = ""
+  }
+
+  action grow() {
+    set box.size.w = box.size.w + 1
+    set box.label = "${box.label}!"
+    set area = box.size.w * box.size.h
+  }
+
+  action widen() {
+    set box = { label: "wide", extra: true, size: { w: 3, h: 5, d: 9 } }
+    set box.size.h = box.size.h + 1
+    set area = box.size.w * box.size.h
+    set tag = box.label
+  }
+
+  action respread() {
+    let size = { ...box.size, w: 10 }
+    set box = { ...box, size: size }
+    set area = box.size.w * box.size.h
+  }
+
+  view main() -> Surface {
+    Text { value: box.label }
+  }
+}
+"#;
+
+#[test]
+fn record_slot_access_matches_eval() {
+    let fields = ["box", "area", "tag"];
+    let mut eval = eval_instance(SHAPES);
+    let mut typed = WasmRunner::new(&compile_typed(SHAPES));
+    typed.init();
+
+    for (action_id, action) in [(0, "grow"), (1, "widen"), (0, "grow"), (2, "respread"), (0, "grow")] {
+        eval.dispatch(action, vec![]).expect("eval dispatch");
+        dispatch(&mut typed, action_id);
+        assert_state_parity(&eval, &mut typed, &fields, &format!("Shapes after {action}"));
+    }
+}
+
+// ══════════════════════════════════════════════════════════════════════════════
+// Test blocks — Eval ↔ Codegen
+// ══════════════════════════════════════════════════════════════════════════════
+
+const BAD_FRAME_COUNTS: &str = r#"
+space Game {
+  state {
+    y: number = 0
+  }
+
+  view main() -> Surface { Column { } { } }
+
+  update(dt: number) {
+    set y = y + dt
+  }
+}
+
+tests {
+  test "fractional frames" {
+    tick_n(1.5, 1)
+  }
+
+  test "negative frames" {
+    tick_n(-1, 1)
+  }
+}
+"#;
+
+#[test]
+fn parity_tick_n_rejects_bad_frame_counts() {
+    let summary = pepl_eval::run_tests(&parse(BAD_FRAME_COUNTS)).expect("eval run_tests");
+    let wasm = compile_source(BAD_FRAME_COUNTS);
+    assert_eq!(summary.results.len(), 2);
+
+    for (i, result) in summary.results.iter().enumerate() {
+        let eval_error = result.error.as_deref().unwrap_or_default();
+        assert!(!result.passed, "eval accepted {:?}", result.description);
+        assert!(eval_error.contains("invalid arguments to tick_n()"), "eval: {eval_error}");
+
+        let mut runner = WasmRunner::new(&wasm);
+        let wasm_error = runner
+            .instance
+            .get_typed_func::<(), ()>(&runner.store, &format!("__test_{i}"))
+            .expect("missing test export")
+            .call(&mut runner.store, ())
+            .expect_err("WASM accepted a bad frame count")
+            .to_string();
+        assert!(wasm_error.contains("invalid arguments to tick_n()"), "WASM: {wasm_error}");
+    }
+}
+
+// ══════════════════════════════════════════════════════════════════════════════
+// Gas — Eval ↔ Codegen
+// ══════════════════════════════════════════════════════════════════════════════
+
+/// Gas the module charged during the last entry point (the `__gas` global).
+fn wasm_gas(runner: &WasmRunner) -> u64 {
+    let gas = runner
+        .instance
+        .get_global(&runner.store, "__gas")
+        .expect("no __gas export")
+        .get(&runner.store);
+    gas.i32().expect("__gas is an i32") as u32 as u64
+}
+
+#[test]
+fn gas_totals_match_eval() {
+    use pepl_codegen::OptLevel;
+
+    let sources = [
+        ("SimpleCounter", SIMPLE_COUNTER),
+        ("Crunch", CRUNCH),
+        ("StdlibCalls", STDLIB_CALLS),
+        ("Folded", FOLDED),
+        ("DerivedChain", DERIVED_CHAIN),
+        ("Rollback", ROLLBACK),
+    ];
+    for (name, source) in sources {
+        let actions: Vec<(i32, String)> = parse(source)
+            .space
+            .body
+            .actions
+            .iter()
+            .enumerate()
+            .filter(|(_, action)| action.params.is_empty())
+            .map(|(id, action)| (id as i32, action.name.name.clone()))
+            .collect();
+        let backends = [
+            ("O0", compile_opt(source, OptLevel::O0)),
+            ("O1", compile_opt(source, OptLevel::O1)),
+            ("typed", compile_typed(source)),
+        ];
+        for (backend, wasm) in backends {
+            let mut eval = eval_instance(source);
+            let mut runner = WasmRunner::new(&wasm);
+            runner.init();
+
+            for round in 0..2 {
+                for (id, action) in &actions {
+                    let before = eval.gas_used();
+                    let result = eval.dispatch(action, vec![]).expect("eval dispatch");
+                    let trapped = try_dispatch(&mut runner, *id).is_err();
+                    assert_eq!(
+                        trapped, !result.committed,
+                        "{name} {backend}: {action} rolled back in one backend only (round {round})"
+                    );
+                    assert_eq!(
+                        wasm_gas(&runner),
+                        eval.gas_used() - before,
+                        "{name} {backend}: gas for {action} (round {round})"
+                    );
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn derived_chain_parity() {
+    use pepl_codegen::OptLevel;
+
+    let fields = ["items", "bonus", "label", "count", "total", "summary"];
+    let backends = [
+        ("O0", compile_opt(DERIVED_CHAIN, OptLevel::O0)),
+        ("O1", compile_opt(DERIVED_CHAIN, OptLevel::O1)),
+        ("typed", compile_typed(DERIVED_CHAIN)),
+    ];
+    for (backend, wasm) in backends {
+        let mut eval = eval_instance(DERIVED_CHAIN);
+        let mut runner = WasmRunner::new(&wasm);
+        runner.init();
+        assert_state_parity(&eval, &mut runner, &fields, backend);
+
+        for (id, action) in [(0, "add"), (1, "boost"), (2, "relabel"), (0, "add")] {
+            eval.dispatch(action, vec![]).expect("eval dispatch");
+            dispatch(&mut runner, id);
+            assert_state_parity(&eval, &mut runner, &fields, &format!("{backend} {action}"));
+        }
+        assert_eq!(
+            eval.get_state("summary"),
+            Some(&Value::String("sum: 6".into()))
+        );
+    }
+}
+
+#[test]
+fn stdlib_gas_scales_with_list_size() {
+    let gas = |items: &str| {
+        let source = format!(
+            r#"
+space Cost {{
+  state {{
+    items: list<number> = [{items}]
+    out: list<number> = []
+  }}
+
+  action run() {{
+    set out = list.reverse(items)
+  }}
+
+  view main() -> Surface {{ Column {{ }} {{ }} }}
+}}
+"#
+        );
+        let mut eval = eval_instance(&source);
+        let mut runner = WasmRunner::new(&compile_source(&source));
+        runner.init();
+        let before = eval.gas_used();
+        eval.dispatch("run", vec![]).expect("eval dispatch");
+        dispatch(&mut runner, 0);
+        assert_eq!(wasm_gas(&runner), eval.gas_used() - before, "gas for [{items}]");
+        wasm_gas(&runner)
+    };
+    let short = gas("1, 2, 3, 4");
+    let long = gas("1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16");
+    // `list.reverse` costs one unit per item of its argument and its result
+    assert_eq!(long - short, 2 * 12);
+}
+
+// ══════════════════════════════════════════════════════════════════════════════
+// State persistence — serialize_state / restore_state
+// ══════════════════════════════════════════════════════════════════════════════
+
+/// Numbers in every JSON form (integers, fractions, scientific), escaped
+/// strings, nested records and a derived field that must not be persisted.
+const JOURNAL: &str = r#"
+space Journal {
+  state {
+    count: number = 0
+    ratio: number = 0
+    tiny: number = 0
+    huge: number = 0
+    title: string = "a \"quoted\"\nline"
+    done: bool = false
+    entries: list<{ day: number, note: string }> = []
+  }
+
+  derived {
+    total: number = count * 2
+  }
+
+  invariant bounded {
+    count < 100
+  }
+
+  action log() {
+    set count = count + 1
+    set ratio = count / 3
+    set tiny = -3 / 2000000
+    set huge = 10000000000 * 10000000000 * count
+    set done = not done
+    set entries = list.append(entries, { note: "day ${count}", day: count })
+  }
+
+  view main() -> Surface {
+    Text { value: title }
+  }
+}
+"#;
+
+fn serialize_state(runner: &mut WasmRunner) -> String {
+    let (ptr, len) = runner
+        .instance
+        .get_typed_func::<(), (i32, i32)>(&runner.store, "serialize_state")
+        .expect("no serialize_state export")
+        .call(&mut runner.store, ())
+        .expect("serialize_state trapped");
+    runner.read_string(ptr as usize, len as usize)
+}
+
+/// Copy `json` into module memory and call `restore_state`.
+fn restore_state(runner: &mut WasmRunner, json: &str) -> bool {
+    let ptr = runner
+        .instance
+        .get_typed_func::<i32, i32>(&runner.store, "alloc")
+        .expect("no alloc export")
+        .call(&mut runner.store, json.len() as i32)
+        .expect("alloc trapped");
+    runner
+        .memory
+        .write(&mut runner.store, ptr as usize, json.as_bytes())
+        .expect("write restore payload");
+    runner
+        .instance
+        .get_typed_func::<(i32, i32), i32>(&runner.store, "restore_state")
+        .expect("no restore_state export")
+        .call(&mut runner.store, (ptr, json.len() as i32))
+        .expect("restore_state trapped")
+        == 1
+}
+
+/// The evaluator's persisted form of its state.
+fn eval_state_json(eval: &SpaceInstance) -> String {
+    let map: serde_json::Map<String, serde_json::Value> = eval
+        .state_snapshot()
+        .iter()
+        .map(|(k, v)| (k.clone(), SpaceInstance::value_to_json_public(v)))
+        .collect();
+    serde_json::to_string(&map).unwrap()
+}
+
+#[test]
+fn serialize_state_matches_eval_snapshot() {
+    for (backend, wasm) in [
+        ("O0", compile_source(JOURNAL)),
+        ("typed", compile_typed(JOURNAL)),
+    ] {
+        let mut eval = eval_instance(JOURNAL);
+        let mut runner = WasmRunner::new(&wasm);
+        runner.init();
+        assert_eq!(
+            serialize_state(&mut runner),
+            eval_state_json(&eval),
+            "{backend} init"
+        );
+        for step in 1..=3 {
+            eval.dispatch("log", vec![]).expect("eval dispatch");
+            dispatch(&mut runner, 0);
+            assert_eq!(
+                serialize_state(&mut runner),
+                eval_state_json(&eval),
+                "{backend} after {step} dispatches"
+            );
+        }
+    }
+    let json = eval_state_json(&{
+        let mut eval = eval_instance(JOURNAL);
+        eval.dispatch("log", vec![]).unwrap();
+        eval
+    });
+    // The scientific forms are the interesting part of the parity
+    assert!(
+        json.contains("\"tiny\":-1.5e-6") && json.contains("\"huge\":1e+20"),
+        "{json}"
+    );
+}
+
+#[test]
+fn restore_state_round_trips_and_recomputes_derived() {
+    let wasm = compile_typed(JOURNAL);
+    let mut source = WasmRunner::new(&wasm);
+    source.init();
+    for _ in 0..2 {
+        dispatch(&mut source, 0);
+    }
+    let saved = serialize_state(&mut source);
+
+    let mut resumed = WasmRunner::new(&wasm);
+    resumed.init();
+    assert!(
+        restore_state(&mut resumed, &saved),
+        "restore rejected {saved}"
+    );
+    assert_eq!(serialize_state(&mut resumed), saved);
+    assert!(values_equal(
+        &resumed.read_state()["total"],
+        &Value::Number(4.0)
+    ));
+
+    // The resumed module keeps going from the restored state
+    dispatch(&mut source, 0);
+    dispatch(&mut resumed, 0);
+    assert_eq!(serialize_state(&mut resumed), serialize_state(&mut source));
+}
+
+#[test]
+fn restore_state_rejects_data_outside_the_schema() {
+    let mut runner = WasmRunner::new(&compile_typed(JOURNAL));
+    runner.init();
+    dispatch(&mut runner, 0);
+    let before = serialize_state(&mut runner);
+    let valid: serde_json::Value = serde_json::from_str(&before).unwrap();
+
+    let mut missing = valid.clone();
+    missing.as_object_mut().unwrap().remove("title");
+    let mut extra = valid.clone();
+    extra["total"] = serde_json::json!(2);
+    let mut mistyped = valid.clone();
+    mistyped["done"] = serde_json::json!("yes");
+    let mut bad_entry = valid.clone();
+    bad_entry["entries"] = serde_json::json!([{ "day": 1 }]);
+    let mut broken_invariant = valid.clone();
+    broken_invariant["count"] = serde_json::json!(150);
+
+    let rejected = [
+        ("malformed", "{\"count\": ".to_string()),
+        ("trailing", format!("{before} 1")),
+        ("not an object", "[1, 2]".to_string()),
+        ("missing field", missing.to_string()),
+        ("unknown field", extra.to_string()),
+        ("wrong type", mistyped.to_string()),
+        ("nested record", bad_entry.to_string()),
+        ("invariant", broken_invariant.to_string()),
+    ];
+    for (case, json) in rejected {
+        assert!(
+            !restore_state(&mut runner, &json),
+            "{case} accepted: {json}"
+        );
+        assert_eq!(
+            serialize_state(&mut runner),
+            before,
+            "{case} changed the state"
+        );
+    }
+}
+
+#[test]
+fn restore_state_accepts_sum_types_and_results() {
+    let source = r#"
+space Tasks {
+  type Status = | Idle | Busy(task: string, progress: number)
+
+  state {
+    status: Status = Idle
+    last: Result<number, string> = Ok(0)
+  }
+
+  action tick() {
+    set status = Idle
+  }
+
+  view main() -> Surface {
+    Text { value: "tasks" }
+  }
+}
+"#;
+    let mut runner = WasmRunner::new(&compile_typed(source));
+    runner.init();
+    for json in [
+        r#"{"last":{"Ok":3},"status":"Idle"}"#,
+        r#"{"last":{"Err":"late"},"status":{"Busy":["write",0.5]}}"#,
+    ] {
+        assert!(restore_state(&mut runner, json), "rejected {json}");
+        assert_eq!(serialize_state(&mut runner), json);
+    }
+    for json in [
+        r#"{"last":{"Ok":3},"status":"Busy"}"#,
+        r#"{"last":{"Ok":3},"status":{"Busy":["write"]}}"#,
+        r#"{"last":{"Maybe":3},"status":"Idle"}"#,
+        r#"{"last":{"Ok":"3"},"status":"Idle"}"#,
+    ] {
+        assert!(!restore_state(&mut runner, json), "accepted {json}");
+    }
+}
@@ -0,0 +1,434 @@
+//! Capability effects — the per-entry-point manifest in `CompileResult`
+//! and the `.wasm`, and the `unused_capability` lint.
+
+use pepl_compiler::effects::{CapabilityManifest, MANIFEST_SECTION};
+use pepl_compiler::gas_bound::EntryKind;
+use pepl_compiler::{compile_to_result, type_check};
+use pepl_types::ErrorCode;
+
+// ══════════════════════════════════════════════════════════════════════════════
+// Helpers
+// ══════════════════════════════════════════════════════════════════════════════
+
+fn manifest(source: &str) -> CapabilityManifest {
+    let result = compile_to_result(source, "test.pepl");
+    assert!(result.success, "compile failed: {:?}", result.errors);
+    result.capability_manifest.unwrap()
+}
+
+/// The capability functions `entry` can call.
+fn functions(source: &str, entry: &str) -> Vec<String> {
+    manifest(source)
+        .entry(entry)
+        .unwrap_or_else(|| panic!("no entry '{entry}'"))
+        .functions
+        .clone()
+}
+
+fn unused_capabilities(source: &str) -> Vec<String> {
+    let errors = type_check(source, "test.pepl");
+    assert!(!errors.has_errors(), "{:#?}", errors.errors);
+    errors
+        .warnings
+        .iter()
+        .filter(|w| w.code == ErrorCode::UNUSED_CAPABILITY)
+        .map(|w| w.message.clone())
+        .collect()
+}
+
+/// The contents of the custom section `name` of a WASM module.
+fn custom_section<'w>(wasm: &'w [u8], name: &str) -> Option<&'w [u8]> {
+    fn leb(bytes: &[u8], at: &mut usize) -> usize {
+        let (mut value, mut shift) = (0, 0);
+        loop {
+            let byte = bytes[*at];
+            *at += 1;
+            value |= ((byte & 0x7f) as usize) << shift;
+            if byte & 0x80 == 0 {
+                return value;
+            }
+            shift += 7;
+        }
+    }
+    let mut at = 8;
+    while at < wasm.len() {
+        let id = wasm[at];
+        at += 1;
+        let size = leb(wasm, &mut at);
+        let end = at + size;
+        if id == 0 {
+            let mut name_at = at;
+            let len = leb(wasm, &mut name_at);
+            if &wasm[name_at..name_at + len] == name.as_bytes() {
+                return Some(&wasm[name_at + len..end]);
+            }
+        }
+        at = end;
+    }
+    None
+}
+
+/// `fetch` calls both declared capabilities.
+const FETCH_AND_STORE: &str = r#"
+space S {
+  state {
+    data: string = ""
+  }
+  capabilities {
+    required: [http, storage]
+  }
+  action fetch() {
+    let result = http.get("https://example.com")
+    storage.set("k", "v")
+  }
+  view main() -> Surface {
+    Button { label: data, on_tap: fetch }
+  }
+}
+"#;
+
+// ══════════════════════════════════════════════════════════════════════════════
+// Inference
+// ══════════════════════════════════════════════════════════════════════════════
+
+#[test]
+fn direct_calls() {
+    let source = r#"
+space S {
+  state {
+    data: string = ""
+  }
+  capabilities {
+    required: [http, storage]
+  }
+  action fetch() {
+    let result = http.get("https://example.com")
+    storage.set("k", "v")
+  }
+  action clear() {
+    set data = ""
+  }
+  view main() -> Surface {
+    Text { value: data }
+  }
+}
+"#;
+    let manifest = manifest(source);
+    let fetch = manifest.entry("fetch").unwrap();
+    assert_eq!(fetch.kind, EntryKind::Action);
+    assert_eq!(fetch.capabilities, vec!["http", "storage"]);
+    assert_eq!(fetch.functions, vec!["http.get", "storage.set"]);
+    assert!(manifest.entry("clear").unwrap().functions.is_empty());
+}
+
+#[test]
+fn calls_inside_lambdas() {
+    let source = r#"
+space S {
+  state {
+    data: string = ""
+    keys: list<string> = []
+  }
+  capabilities {
+    required: [storage]
+  }
+  action save_all() {
+    let save = fn(key: string) { storage.set(key, data) }
+    for key in keys {
+      save(key)
+    }
+  }
+  view main() -> Surface {
+    Text { value: data }
+  }
+}
+"#;
+    assert_eq!(functions(source, "save_all"), vec!["storage.set"]);
+}
+
+#[test]
+fn lambda_stored_in_a_field() {
+    let source = r#"
+space S {
+  state {
+    data: string = ""
+    on_save: (string) -> nil = fn(s: string) { storage.set("data", s) }
+  }
+  capabilities {
+    required: [http, storage]
+  }
+  action save() {
+    on_save(data)
+  }
+  action fetch() {
+    let result = http.get("https://example.com")
+  }
+  view main() -> Surface {
+    Text { value: data }
+  }
+}
+"#;
+    assert_eq!(functions(source, "save"), vec!["storage.set"]);
+    assert_eq!(functions(source, "fetch"), vec!["http.get"]);
+}
+
+#[test]
+fn lambda_set_by_another_action() {
+    let source = r#"
+space S {
+  state {
+    data: string = ""
+    on_save: (string) -> nil = fn(s: string) { }
+  }
+  capabilities {
+    required: [storage]
+  }
+  action use_delete() {
+    set on_save = fn(s: string) { storage.delete(s) }
+  }
+  action save() {
+    on_save(data)
+  }
+  view main() -> Surface {
+    Text { value: data }
+  }
+}
+"#;
+    assert_eq!(functions(source, "save"), vec!["storage.delete"]);
+    // Defining the lambda may run it too, as far as the manifest can tell
+    assert_eq!(functions(source, "use_delete"), vec!["storage.delete"]);
+}
+
+#[test]
+fn lambda_copied_between_fields() {
+    let source = r#"
+space S {
+  state {
+    data: string = ""
+    on_load: (string) -> nil = fn(s: string) { storage.set("data", s) }
+    on_save: (string) -> nil = fn(s: string) { }
+  }
+  capabilities {
+    required: [storage]
+  }
+  action swap() {
+    set on_save = on_load
+  }
+  action save() {
+    on_save(data)
+  }
+  view main() -> Surface {
+    Text { value: data }
+  }
+}
+"#;
+    assert_eq!(functions(source, "save"), vec!["storage.set"]);
+}
+
+#[test]
+fn lambda_aliased_by_let() {
+    let source = r#"
+space S {
+  state {
+    data: string = ""
+    on_save: (string) -> nil = fn(s: string) { storage.set("data", s) }
+  }
+  capabilities {
+    required: [storage]
+  }
+  action save() {
+    let f = on_save
+    f(data)
+  }
+  view main() -> Surface {
+    Text { value: data }
+  }
+}
+"#;
+    assert_eq!(functions(source, "save"), vec!["storage.set"]);
+}
+
+#[test]
+fn lambda_passed_to_a_higher_order_function() {
+    let source = r#"
+space S {
+  state {
+    keys: list<string> = []
+    on_save: (string) -> nil = fn(s: string) { storage.set(s, "v") }
+  }
+  capabilities {
+    required: [storage]
+  }
+  action save_all() {
+    let saved = list.map(keys, on_save)
+  }
+  view main() -> Surface {
+    Text { value: "${list.length(keys)}" }
+  }
+}
+"#;
+    assert_eq!(functions(source, "save_all"), vec!["storage.set"]);
+}
+
+#[test]
+fn update_and_handle_event() {
+    let source = r#"
+space S {
+  state {
+    data: string = ""
+  }
+  capabilities {
+    required: [http, storage]
+  }
+  action fetch() {
+    let result = http.get("https://example.com")
+  }
+  view main() -> Surface {
+    Text { value: data }
+  }
+  update(dt: number) {
+    storage.set("t", "${dt}")
+  }
+  handleEvent(event: InputEvent) {
+    set data = ""
+  }
+}
+"#;
+    let manifest = manifest(source);
+    let kinds: Vec<_> = manifest
+        .entries
+        .iter()
+        .map(|e| (e.kind, e.name.as_str()))
+        .collect();
+    assert_eq!(
+        kinds,
+        vec![
+            (EntryKind::Action, "fetch"),
+            (EntryKind::Update, "update"),
+            (EntryKind::HandleEvent, "handleEvent"),
+        ]
+    );
+    assert_eq!(functions(source, "update"), vec!["storage.set"]);
+    assert!(functions(source, "handleEvent").is_empty());
+}
+
+// ══════════════════════════════════════════════════════════════════════════════
+// Lint
+// ══════════════════════════════════════════════════════════════════════════════
+
+#[test]
+fn unused_capability() {
+    let source = r#"
+space S {
+  state {
+    data: string = ""
+  }
+  capabilities {
+    required: [http, storage]
+  }
+  action fetch() {
+    let result = http.get("https://example.com")
+  }
+  view main() -> Surface {
+    Button { label: data, on_tap: fetch }
+  }
+}
+"#;
+    assert_eq!(
+        unused_capabilities(source),
+        vec!["capability 'storage' is declared but no action, update or handleEvent uses it"]
+    );
+}
+
+#[test]
+fn capability_used_only_by_a_stored_lambda() {
+    let source = r#"
+space S {
+  state {
+    data: string = ""
+    on_save: (string) -> nil = fn(s: string) { storage.set("data", s) }
+  }
+  capabilities {
+    required: [http, storage]
+  }
+  action save() {
+    on_save(data)
+    let result = http.get("https://example.com")
+  }
+  view main() -> Surface {
+    Button { label: data, on_tap: save }
+  }
+}
+"#;
+    assert!(unused_capabilities(source).is_empty());
+}
+
+#[test]
+fn host_capabilities_are_not_reported() {
+    let source = r#"
+space S {
+  state {
+    data: string = ""
+  }
+  capabilities {
+    required: [display, http, storage]
+  }
+  action fetch() {
+    let result = http.get("https://example.com")
+    storage.set("k", "v")
+  }
+  view main() -> Surface {
+    Button { label: data, on_tap: fetch }
+  }
+}
+"#;
+    assert!(unused_capabilities(source).is_empty());
+    assert_eq!(
+        manifest(source).required,
+        vec!["display", "http", "storage"]
+    );
+}
+
+// ══════════════════════════════════════════════════════════════════════════════
+// Output
+// ══════════════════════════════════════════════════════════════════════════════
+
+#[test]
+fn manifest_is_embedded_in_the_wasm() {
+    let result = compile_to_result(FETCH_AND_STORE, "test.pepl");
+    let section = custom_section(result.wasm.as_ref().unwrap(), MANIFEST_SECTION)
+        .expect("no capability manifest section");
+    let embedded: CapabilityManifest = serde_json::from_slice(section).unwrap();
+    assert_eq!(Some(embedded), result.capability_manifest);
+
+    let wasm = pepl_compiler::compile(FETCH_AND_STORE, "test.pepl").unwrap();
+    assert!(custom_section(&wasm, MANIFEST_SECTION).is_some());
+}
+
+#[test]
+fn manifest_serialization() {
+    let json = serde_json::to_value(manifest(FETCH_AND_STORE)).unwrap();
+    assert_eq!(json["required"][0], "http");
+    assert_eq!(json["entries"][0]["kind"], "action");
+    assert_eq!(json["entries"][0]["functions"][1], "storage.set");
+}
+
+#[test]
+fn no_manifest_on_type_errors() {
+    let source = r#"
+space S {
+  state {
+    data: string = ""
+  }
+  action fetch() {
+    set data = 1
+  }
+  view main() -> Surface {
+    Button { label: data, on_tap: fetch }
+  }
+}
+"#;
+    let result = compile_to_result(source, "test.pepl");
+    assert!(!result.success);
+    assert!(result.capability_manifest.is_none());
+}
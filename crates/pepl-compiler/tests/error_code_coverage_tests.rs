@@ -312,3 +312,142 @@ space App {
 // E606: EMPTY_STATE_BLOCK — see structural_tests.rs
 // E607: STRUCTURAL_LIMIT_EXCEEDED — see structural_tests.rs
 // ══════════════════════════════════════════════════════════════════════════════
+
+#[test]
+fn e608_gas_budget_exceeded_warning() {
+    let result = pepl_compiler::compile_to_result(
+        r#"
+space App {
+  state { total: number = 0 }
+  action churn() {
+    for i in list.range(0, 1000) {
+      for j in list.range(0, 1000) {
+        set total = total + 1
+      }
+    }
+  }
+  view main() -> Surface { Text { value: "${total}" } }
+}
+"#,
+        "test.pepl",
+    );
+    assert!(result.success, "E608 is a warning, not an error");
+    let warning = result
+        .warnings
+        .iter()
+        .find(|w| w.code == ErrorCode::GAS_BUDGET_EXCEEDED)
+        .expect("expected E608 warning");
+    assert!(
+        warning.suggestion.is_some(),
+        "E608 should include a suggestion"
+    );
+}
+
+// ══════════════════════════════════════════════════════════════════════════════
+// E609: CREDENTIAL_LEAK — more in taint_tests.rs
+// ══════════════════════════════════════════════════════════════════════════════
+
+#[test]
+fn e609_credential_leak() {
+    assert_error(
+        r#"
+space App {
+  state { x: number = 0 }
+  credentials {
+    api_key: string
+  }
+  view main() -> Surface { Text { value: api_key } }
+}
+"#,
+        ErrorCode::CREDENTIAL_LEAK,
+    );
+}
+
+// ══════════════════════════════════════════════════════════════════════════════
+// E610: DERIVED_CYCLE — more in derived_graph_tests.rs
+// ══════════════════════════════════════════════════════════════════════════════
+
+#[test]
+fn e610_derived_cycle() {
+    assert_error(
+        r#"
+space App {
+  state { x: number = 0 }
+  derived {
+    a: number = b + x
+    b: number = a + 1
+  }
+  view main() -> Surface { Text { value: "${a}" } }
+}
+"#,
+        ErrorCode::DERIVED_CYCLE,
+    );
+}
+
+// ══════════════════════════════════════════════════════════════════════════════
+// Code table
+// ══════════════════════════════════════════════════════════════════════════════
+
+#[test]
+fn error_codes_are_unique() {
+    let codes = [
+        ErrorCode::UNEXPECTED_TOKEN,
+        ErrorCode::UNCLOSED_BRACE,
+        ErrorCode::INVALID_KEYWORD,
+        ErrorCode::UNKNOWN_TYPE,
+        ErrorCode::TYPE_MISMATCH,
+        ErrorCode::WRONG_ARG_COUNT,
+        ErrorCode::NON_EXHAUSTIVE_MATCH,
+        ErrorCode::INVARIANT_UNREACHABLE,
+        ErrorCode::INVARIANT_UNKNOWN_FIELD,
+        ErrorCode::UNDECLARED_CAPABILITY,
+        ErrorCode::CAPABILITY_UNAVAILABLE,
+        ErrorCode::UNKNOWN_COMPONENT,
+        ErrorCode::VARIABLE_ALREADY_DECLARED,
+        ErrorCode::STATE_MUTATED_OUTSIDE_ACTION,
+        ErrorCode::RECURSION_NOT_ALLOWED,
+        ErrorCode::BLOCK_ORDERING_VIOLATED,
+        ErrorCode::DERIVED_FIELD_MODIFIED,
+        ErrorCode::EXPRESSION_BODY_LAMBDA,
+        ErrorCode::BLOCK_COMMENT_USED,
+        ErrorCode::UNDECLARED_CREDENTIAL,
+        ErrorCode::CREDENTIAL_MODIFIED,
+        ErrorCode::EMPTY_STATE_BLOCK,
+        ErrorCode::STRUCTURAL_LIMIT_EXCEEDED,
+        ErrorCode::GAS_BUDGET_EXCEEDED,
+        ErrorCode::CREDENTIAL_LEAK,
+        ErrorCode::DERIVED_CYCLE,
+        ErrorCode::ARITHMETIC_TRAP,
+        ErrorCode::ASSERTION_FAILED,
+        ErrorCode::INVARIANT_VIOLATED,
+        ErrorCode::NIL_ACCESS,
+        ErrorCode::UNWRAP_ERR,
+        ErrorCode::UNDEFINED_VARIABLE,
+        ErrorCode::UNDEFINED_ACTION,
+        ErrorCode::RUNTIME_TYPE_MISMATCH,
+        ErrorCode::STDLIB_FAILURE,
+        ErrorCode::UNKNOWN_FUNCTION,
+        ErrorCode::GAS_EXHAUSTED,
+        ErrorCode::OUT_OF_MEMORY,
+        ErrorCode::RUNTIME_FAILURE,
+        ErrorCode::UNUSED_LET,
+        ErrorCode::UNUSED_STATE,
+        ErrorCode::UNUSED_ACTION,
+        ErrorCode::UNUSED_PARAM,
+        ErrorCode::SHADOWED_BINDING,
+        ErrorCode::UNREACHABLE_CODE,
+        ErrorCode::FLOAT_EQUALITY,
+        ErrorCode::CONSTANT_DERIVED,
+        ErrorCode::UNKNOWN_LINT,
+        ErrorCode::DIVISION_BY_ZERO,
+        ErrorCode::MATH_DOMAIN,
+        ErrorCode::INDEX_OUT_OF_BOUNDS,
+        ErrorCode::UNUSED_CAPABILITY,
+    ];
+    let mut seen = std::collections::HashSet::new();
+    for code in codes {
+        assert!(seen.insert(code), "{code} is defined twice");
+        // E700–E799 are the codegen failures `pepl_compiler` reports
+        assert!(!(700..=799).contains(&code.0), "{code} clashes with codegen");
+    }
+}
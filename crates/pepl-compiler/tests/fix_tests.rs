@@ -0,0 +1,372 @@
+//! Structured fixes — the edits attached to common errors and
+//! [`apply_fixes`] turning them back into source.
+
+use pepl_compiler::{apply_fixes, type_check};
+use pepl_types::{Applicability, ErrorCode, Fix, PeplError};
+
+// ══════════════════════════════════════════════════════════════════════════════
+// Helpers
+// ══════════════════════════════════════════════════════════════════════════════
+
+fn only_error(source: &str, code: ErrorCode) -> PeplError {
+    let errors = type_check(source, "test.pepl");
+    assert_eq!(errors.errors.len(), 1, "{:#?}", errors.errors);
+    assert_eq!(errors.errors[0].code, code, "{:#?}", errors.errors);
+    errors.errors[0].clone()
+}
+
+fn only_fix(error: &PeplError) -> &Fix {
+    assert_eq!(error.fixes.len(), 1, "{:#?}", error.fixes);
+    &error.fixes[0]
+}
+
+/// Apply every fix to `source` and check that the result has no errors.
+fn fixed(source: &str) -> String {
+    let errors = type_check(source, "test.pepl");
+    assert!(errors.has_errors());
+    let fixed = apply_fixes(source, &errors);
+    let after = type_check(&fixed, "test.pepl");
+    assert!(!after.has_errors(), "{fixed}\n{:#?}", after.errors);
+    fixed
+}
+
+// ══════════════════════════════════════════════════════════════════════════════
+// Misspelled names
+// ══════════════════════════════════════════════════════════════════════════════
+
+const STDLIB_TYPO: &str = r#"
+space T {
+  state {
+    items: list<number> = []
+    n: number = 0
+  }
+  action go() {
+    set n = list.lenght(items)
+  }
+}
+"#;
+
+#[test]
+fn misspelled_stdlib_function() {
+    let error = only_error(STDLIB_TYPO, ErrorCode::TYPE_MISMATCH);
+    let fix = only_fix(&error);
+    assert_eq!(fix.label, "replace with 'length'");
+    assert_eq!(fix.applicability, Applicability::MaybeIncorrect);
+    assert_eq!(fix.edits.len(), 1);
+    assert_eq!(fix.edits[0].replacement, "length");
+    assert_eq!(
+        (fix.edits[0].span.start_col, fix.edits[0].span.end_col),
+        (18, 24)
+    );
+    assert!(fixed(STDLIB_TYPO).contains("list.length(items)"));
+}
+
+#[test]
+fn no_fix_without_a_close_name() {
+    let source = r#"
+space T {
+  state {
+    items: list<number> = []
+    n: number = 0
+  }
+  action go() {
+    set n = list.frobnicate(items)
+  }
+}
+"#;
+    let error = only_error(source, ErrorCode::TYPE_MISMATCH);
+    assert!(error.fixes.is_empty());
+}
+
+#[test]
+fn misspelled_component() {
+    let source = r#"
+space T {
+  state {
+    n: number = 0
+  }
+  view main() -> Surface {
+    Buton { label: "go" }
+  }
+}
+"#;
+    let error = only_error(source, ErrorCode::UNKNOWN_COMPONENT);
+    assert_eq!(only_fix(&error).label, "replace with 'Button'");
+    assert!(fixed(source).contains("    Button { label: \"go\" }"));
+}
+
+#[test]
+fn misspelled_component_in_test_assertion() {
+    let source = r#"
+space T {
+  state {
+    n: number = 0
+  }
+  view main() -> Surface {
+    Text { value: "hi" }
+  }
+}
+
+tests {
+  test "renders" {
+    let surface = render(main)
+    assert_component(surface, "Txt")
+  }
+}
+"#;
+    let error = only_error(source, ErrorCode::UNKNOWN_COMPONENT);
+    assert_eq!(only_fix(&error).edits[0].replacement, "\"Text\"");
+    assert!(fixed(source).contains("assert_component(surface, \"Text\")"));
+}
+
+// ══════════════════════════════════════════════════════════════════════════════
+// Undeclared capabilities
+// ══════════════════════════════════════════════════════════════════════════════
+
+#[test]
+fn capability_fix_creates_block() {
+    let source = r#"
+space T {
+  state {
+    data: string = ""
+  }
+  action fetch() {
+    let _result = http.get("https://example.com")
+    let _again = http.get("https://example.org")
+  }
+}
+"#;
+    let errors = type_check(source, "test.pepl");
+    assert_eq!(errors.errors.len(), 2);
+    let fix = only_fix(&errors.errors[0]);
+    assert_eq!(fix.label, "declare capability 'http'");
+    assert_eq!(fix.applicability, Applicability::MachineApplicable);
+    // Both calls carry the same fix; it is applied once
+    assert_eq!(
+        fixed(source),
+        r#"
+space T {
+  state {
+    data: string = ""
+  }
+
+  capabilities {
+    required: [http]
+  }
+  action fetch() {
+    let _result = http.get("https://example.com")
+    let _again = http.get("https://example.org")
+  }
+}
+"#
+    );
+}
+
+#[test]
+fn capability_fix_extends_required_list() {
+    let source = r#"
+space T {
+  state {
+    data: string = ""
+  }
+  capabilities {
+    required: [display]
+  }
+  action fetch() {
+    let _result = http.get("https://example.com")
+  }
+}
+"#;
+    assert!(fixed(source).contains("    required: [display, http]\n"));
+}
+
+#[test]
+fn capability_fix_fills_empty_required_list() {
+    let source = r#"
+space T {
+  state {
+    data: string = ""
+  }
+  capabilities {
+    required: []
+  }
+  action fetch() {
+    let _result = http.get("https://example.com")
+  }
+}
+"#;
+    assert!(fixed(source).contains("    required: [http]\n"));
+}
+
+#[test]
+fn capability_fix_adds_required_list() {
+    let source = r#"
+space T {
+  state {
+    data: string = ""
+  }
+  capabilities {
+    optional: [storage]
+  }
+  action fetch() {
+    let _result = http.get("https://example.com")
+  }
+}
+"#;
+    assert!(
+        fixed(source).contains("  capabilities {\n    required: [http]\n    optional: [storage]\n")
+    );
+}
+
+// ══════════════════════════════════════════════════════════════════════════════
+// Missing match arms
+// ══════════════════════════════════════════════════════════════════════════════
+
+#[test]
+fn missing_arms_in_declaration_order() {
+    let source = r#"
+space T {
+  type Shape = | Dot | Circle(radius: number) | Square(side: number)
+  state {
+    shape: Shape = Dot
+    n: number = 0
+  }
+  action go() {
+    match shape {
+      Dot -> { set n = 0 },
+    }
+  }
+}
+"#;
+    let error = only_error(source, ErrorCode::NON_EXHAUSTIVE_MATCH);
+    assert_eq!(
+        error.message,
+        "non-exhaustive match: missing variants Circle, Square"
+    );
+    let fix = only_fix(&error);
+    assert_eq!(fix.label, "add the missing arms");
+    assert_eq!(fix.applicability, Applicability::HasPlaceholders);
+    assert_eq!(
+        fixed(source),
+        r#"
+space T {
+  type Shape = | Dot | Circle(radius: number) | Square(side: number)
+  state {
+    shape: Shape = Dot
+    n: number = 0
+  }
+  action go() {
+    match shape {
+      Dot -> { set n = 0 },
+      Circle(radius) -> {},
+      Square(side) -> {},
+    }
+  }
+}
+"#
+    );
+}
+
+#[test]
+fn missing_arms_without_trailing_commas() {
+    let source = r#"
+space T {
+  type Shape = | Dot | Circle(radius: number) | Square(side: number)
+  state {
+    shape: Shape = Dot
+    n: number = 0
+  }
+  action go() {
+    match shape {
+      Dot -> { set n = 0 }
+      Square(s) -> { set n = s }
+    }
+  }
+}
+"#;
+    assert!(fixed(source)
+        .contains("      Square(s) -> { set n = s }\n      Circle(radius) -> {}\n    }"));
+}
+
+#[test]
+fn missing_arms_on_one_line() {
+    let source = r#"
+space T {
+  type Shape = | Dot | Circle(radius: number) | Square(side: number)
+  state {
+    shape: Shape = Dot
+    n: number = 0
+  }
+  action go() {
+    match shape { Dot -> { set n = 0 } }
+  }
+}
+"#;
+    assert!(fixed(source).contains(
+        "    match shape { Dot -> { set n = 0 }, Circle(radius) -> {}, Square(side) -> {} }\n"
+    ));
+}
+
+#[test]
+fn missing_result_arm() {
+    let source = r#"
+space T {
+  state {
+    n: number = 0
+  }
+  capabilities {
+    required: [http]
+  }
+  action go() {
+    let result = http.get("https://example.com")
+    match result {
+      Ok(body) -> { set n = string.length(body) },
+    }
+  }
+}
+"#;
+    let error = only_error(source, ErrorCode::NON_EXHAUSTIVE_MATCH);
+    assert_eq!(
+        only_fix(&error).edits[0].replacement,
+        "      Err(error) -> {},\n"
+    );
+}
+
+// ══════════════════════════════════════════════════════════════════════════════
+// apply_fixes
+// ══════════════════════════════════════════════════════════════════════════════
+
+#[test]
+fn apply_fixes_combines_fixes_for_several_errors() {
+    let source = r#"
+space T {
+  state {
+    data: string = ""
+  }
+  action fetch() {
+    let _result = http.get("https://example.com")
+    let _n = list.lenght([])
+  }
+}
+"#;
+    let fixed = fixed(source);
+    assert!(fixed.contains("required: [http]"));
+    assert!(fixed.contains("list.length([])"));
+}
+
+#[test]
+fn apply_fixes_without_fixes_is_identity() {
+    let source = r#"
+space T {
+  state {
+    items: list<number> = []
+    n: number = 0
+  }
+  action go() {
+    set n = list.frobnicate(items)
+  }
+}
+"#;
+    let errors = type_check(source, "test.pepl");
+    assert_eq!(apply_fixes(source, &errors), source);
+}
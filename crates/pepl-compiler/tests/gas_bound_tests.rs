@@ -0,0 +1,294 @@
+//! Static gas bounds — `CompileResult.gas_bounds` against the gas the
+//! evaluator actually spends.
+//!
+//! Every bound must be at least the measured gas for the input sizes of the
+//! run; straight-line code and simple loops are bounded exactly.
+
+use std::collections::HashMap;
+
+use pepl_compiler::gas_bound::{EntryGas, EntryKind, GasBound};
+use pepl_compiler::{compile_to_result, compile_to_result_with_options, CompileOptions};
+use pepl_eval::SpaceInstance;
+use pepl_lexer::Lexer;
+use pepl_parser::Parser;
+use pepl_stdlib::Value;
+use pepl_types::{ErrorCode, SourceFile};
+
+// ══════════════════════════════════════════════════════════════════════════════
+// Helpers
+// ══════════════════════════════════════════════════════════════════════════════
+
+fn bounds(source: &str) -> Vec<EntryGas> {
+    let result = compile_to_result(source, "test.pepl");
+    assert!(result.success, "compile failed: {:?}", result.errors);
+    result.gas_bounds
+}
+
+fn bound_of(source: &str, kind: EntryKind, name: &str) -> GasBound {
+    bounds(source)
+        .into_iter()
+        .find(|e| e.kind == kind && e.name == name)
+        .unwrap_or_else(|| panic!("no gas bound for {kind} '{name}'"))
+        .bound
+}
+
+fn instance(source: &str) -> SpaceInstance {
+    let sf = SourceFile::new("test.pepl", source);
+    let lex = Lexer::new(&sf).lex();
+    let program = Parser::new(lex.tokens, &sf).parse().program.unwrap();
+    SpaceInstance::new(&program).unwrap()
+}
+
+/// Gas the evaluator spends dispatching `action` with `args`.
+fn measure(space: &mut SpaceInstance, action: &str, args: Vec<Value>) -> u64 {
+    let before = space.gas_used();
+    space.dispatch(action, args).unwrap();
+    space.gas_used() - before
+}
+
+fn numbers(n: usize) -> Value {
+    Value::List((0..n).map(|i| Value::Number(i as f64)).collect())
+}
+
+fn sizes(pairs: &[(&str, u64)]) -> HashMap<String, u64> {
+    pairs.iter().map(|&(k, v)| (k.to_string(), v)).collect()
+}
+
+// ══════════════════════════════════════════════════════════════════════════════
+// Bounds against measured gas
+// ══════════════════════════════════════════════════════════════════════════════
+
+const TALLY: &str = r#"
+space Tally {
+  state {
+    total: number = 0
+    items: list<number> = []
+  }
+
+  action add(n: number) {
+    let doubled = n * 2
+    set total = total + doubled
+  }
+
+  action add_all(values: list<number>) {
+    for v in values {
+      set total = total + v
+    }
+  }
+
+  action push(n: number) {
+    set items = list.append(items, n)
+  }
+
+  view main() -> Surface {
+    Column { } {
+      for item in items {
+        Text { value: "${item}" }
+      }
+    }
+  }
+}
+"#;
+
+#[test]
+fn straight_line_action_is_exact() {
+    let bound = bound_of(TALLY, EntryKind::Action, "add");
+    let gas = bound
+        .constant()
+        .expect("straight-line code has a constant bound");
+    let mut space = instance(TALLY);
+    assert_eq!(measure(&mut space, "add", vec![Value::Number(3.0)]), gas);
+}
+
+#[test]
+fn loop_bound_scales_with_parameter_length() {
+    let bound = bound_of(TALLY, EntryKind::Action, "add_all");
+    assert!(bound.constant().is_none(), "got {bound}");
+    assert!(bound.to_string().contains("len(values)"), "got {bound}");
+
+    let mut space = instance(TALLY);
+    for n in [0, 1, 7] {
+        let gas = measure(&mut space, "add_all", vec![numbers(n)]);
+        let predicted = bound.evaluate(&sizes(&[("values", n as u64)])).unwrap();
+        assert_eq!(predicted, gas, "{n} items");
+    }
+}
+
+#[test]
+fn stdlib_bound_covers_state_size() {
+    let bound = bound_of(TALLY, EntryKind::Action, "push");
+    assert!(bound.to_string().contains("len(items)"), "got {bound}");
+
+    let mut space = instance(TALLY);
+    for n in 0..5u64 {
+        let gas = measure(&mut space, "push", vec![Value::Number(1.0)]);
+        let predicted = bound.evaluate(&sizes(&[("items", n)])).unwrap();
+        assert!(predicted >= gas, "{n} items: bound {predicted} < gas {gas}");
+    }
+}
+
+#[test]
+fn view_bound_covers_render() {
+    let bound = bound_of(TALLY, EntryKind::View, "main");
+    let mut space = instance(TALLY);
+    for _ in 0..3 {
+        space.dispatch("push", vec![Value::Number(1.0)]).unwrap();
+    }
+    let before = space.gas_used();
+    space.render().unwrap();
+    let gas = space.gas_used() - before;
+    let predicted = bound.evaluate(&sizes(&[("items", 3)])).unwrap();
+    assert!(predicted >= gas, "bound {predicted} < gas {gas}");
+}
+
+#[test]
+fn failed_invariant_is_covered() {
+    let source = r#"
+space Guarded {
+  state {
+    count: number = 0
+  }
+
+  derived {
+    doubled: number = count * 2
+  }
+
+  invariant non_negative {
+    count >= 0 and count < 100
+  }
+
+  action change(delta: number) {
+    set count = count + delta
+  }
+
+  view main() -> Surface {
+    Text { value: "${count}" }
+  }
+}
+"#;
+    let bound = bound_of(source, EntryKind::Action, "change");
+    let gas = bound.constant().unwrap();
+    let mut space = instance(source);
+    let committed = measure(&mut space, "change", vec![Value::Number(1.0)]);
+    let rolled_back = measure(&mut space, "change", vec![Value::Number(-5.0)]);
+    assert!(gas >= committed, "bound {gas} < gas {committed}");
+    assert!(gas >= rolled_back, "bound {gas} < gas {rolled_back}");
+}
+
+#[test]
+fn update_and_handle_event_are_bounded() {
+    let source = r#"
+space Ticker {
+  state {
+    elapsed: number = 0
+  }
+
+  action reset() {
+    set elapsed = 0
+  }
+
+  view main() -> Surface {
+    Text { value: "${elapsed}" }
+  }
+
+  update(dt: number) {
+    set elapsed = elapsed + dt
+  }
+
+  handleEvent(event: InputEvent) {
+    set elapsed = 0
+  }
+}
+"#;
+    let update = bound_of(source, EntryKind::Update, "update");
+    let mut space = instance(source);
+    let before = space.gas_used();
+    space.call_update(0.5).unwrap();
+    assert_eq!(update.constant(), Some(space.gas_used() - before));
+    assert!(bound_of(source, EntryKind::HandleEvent, "handleEvent")
+        .constant()
+        .is_some());
+}
+
+// ══════════════════════════════════════════════════════════════════════════════
+// Unbounded entry points and the budget
+// ══════════════════════════════════════════════════════════════════════════════
+
+#[test]
+fn loop_over_unknown_length_is_unbounded() {
+    let source = r#"
+space Ranges {
+  state {
+    total: number = 0
+  }
+
+  action count_to(n: number) {
+    for i in list.range(0, n) {
+      set total = total + i
+    }
+  }
+
+  view main() -> Surface {
+    Text { value: "${total}" }
+  }
+}
+"#;
+    match bound_of(source, EntryKind::Action, "count_to") {
+        GasBound::Unbounded { reason, .. } => assert!(reason.contains("list.range"), "{reason}"),
+        bound => panic!("expected an unbounded action, got {bound}"),
+    }
+}
+
+#[test]
+fn literal_list_gives_constant_bound() {
+    let source = r#"
+space Ranges {
+  state {
+    total: number = 0
+  }
+
+  action sum() {
+    for i in [1, 2, 3, 4] {
+      set total = total + i
+    }
+  }
+
+  view main() -> Surface {
+    Text { value: "${total}" }
+  }
+}
+"#;
+    let bound = bound_of(source, EntryKind::Action, "sum");
+    let mut space = instance(source);
+    let gas = measure(&mut space, "sum", vec![]);
+    let predicted = bound.constant().unwrap();
+    assert!(predicted >= gas, "bound {predicted} < gas {gas}");
+}
+
+#[test]
+fn budget_warning_uses_configured_budget() {
+    let options = CompileOptions {
+        gas_budget: 5,
+        ..Default::default()
+    };
+    let result = compile_to_result_with_options(TALLY, "test.pepl", &options);
+    assert!(result.success);
+    let warned: Vec<&str> = result
+        .warnings
+        .iter()
+        .filter(|w| w.code == ErrorCode::GAS_BUDGET_EXCEEDED)
+        .map(|w| w.message.as_str())
+        .collect();
+    // Only constant bounds are checked against the budget
+    assert_eq!(warned.len(), 1, "{warned:?}");
+    assert!(warned[0].contains("action 'add'"), "{warned:?}");
+}
+
+#[test]
+fn bounds_serialize_with_kind_tag() {
+    let json = serde_json::to_value(compile_to_result(TALLY, "test.pepl")).unwrap();
+    let entries = json["gas_bounds"].as_array().unwrap();
+    let add = entries.iter().find(|e| e["name"] == "add").unwrap();
+    assert_eq!(add["kind"], "action");
+    assert_eq!(add["bound"]["kind"], "bounded");
+}
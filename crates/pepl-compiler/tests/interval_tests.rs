@@ -0,0 +1,662 @@
+//! Interval analysis — the `division_by_zero`, `math_domain` and
+//! `index_out_of_bounds` lints, and the state, invariant and guard facts
+//! that silence them.
+
+use pepl_types::{ErrorCode, PeplError};
+
+// ══════════════════════════════════════════════════════════════════════════════
+// Helpers
+// ══════════════════════════════════════════════════════════════════════════════
+
+fn check(source: &str) -> pepl_types::CompileErrors {
+    let errors = pepl_compiler::type_check(source, "test.pepl");
+    assert!(
+        !errors.has_errors(),
+        "expected no errors, got:\n{:#?}",
+        errors.errors
+    );
+    errors
+}
+
+/// `(line, message)` of every interval warning on `source`.
+fn hazards(source: &str) -> Vec<(u32, String)> {
+    check(source)
+        .warnings
+        .iter()
+        .filter(|w| {
+            matches!(
+                w.code,
+                ErrorCode::DIVISION_BY_ZERO
+                    | ErrorCode::MATH_DOMAIN
+                    | ErrorCode::INDEX_OUT_OF_BOUNDS
+            )
+        })
+        .map(|w| (w.span.start_line, w.message.clone()))
+        .collect()
+}
+
+/// The single warning `source` produces.
+fn single_warning(source: &str) -> PeplError {
+    let errors = check(source);
+    assert_eq!(errors.warnings.len(), 1, "{:#?}", errors.warnings);
+    errors.warnings[0].clone()
+}
+
+fn assert_safe(source: &str) {
+    assert_eq!(hazards(source), vec![]);
+}
+
+// ══════════════════════════════════════════════════════════════════════════════
+// Division
+// ══════════════════════════════════════════════════════════════════════════════
+
+#[test]
+fn division_by_a_parameter() {
+    let w = single_warning(
+        r#"
+space S {
+  state {
+    count: number = 0
+  }
+  action run(n: number) {
+    set count = 10 / n
+  }
+  view main() -> Surface { Button { label: "${count}", on_tap: run(1) } }
+}
+"#,
+    );
+    assert_eq!(w.lint.as_deref(), Some("division_by_zero"));
+    assert_eq!(w.code, ErrorCode::DIVISION_BY_ZERO);
+    assert_eq!(
+        w.message,
+        "possible division by zero: the divisor is unconstrained"
+    );
+    assert_eq!((w.span.start_line, w.span.start_col), (7, 22));
+    assert!(w.suggestion.is_some());
+}
+
+#[test]
+fn modulo_by_zero() {
+    let source = r#"
+space S {
+  state {
+    count: number = 0
+  }
+  action run() {
+    set count = count % 0
+  }
+  view main() -> Surface { Button { label: "${count}", on_tap: run } }
+}
+"#;
+    assert_eq!(
+        hazards(source),
+        vec![(
+            7,
+            "possible modulo by zero: the divisor is in [0, 0]".to_string()
+        )]
+    );
+}
+
+#[test]
+fn division_by_a_constant() {
+    assert_safe(
+        r#"
+space S {
+  state {
+    count: number = 0
+  }
+  action run() {
+    set count = count / 4 + count % 3
+  }
+  view main() -> Surface { Button { label: "${count}", on_tap: run } }
+}
+"#,
+    );
+}
+
+#[test]
+fn guard_rules_out_zero() {
+    assert_safe(
+        r#"
+space S {
+  state {
+    count: number = 0
+  }
+  action not_zero(n: number) {
+    if n != 0 {
+      set count = 10 / n
+    }
+  }
+  action positive(n: number) {
+    if n > 0 {
+      set count = 10 / n
+    }
+  }
+  action early_return(n: number) {
+    if n == 0 {
+      return
+    }
+    set count = 10 / n
+  }
+  action else_branch(n: number) {
+    if n < 1 and n > -1 {
+      return
+    } else {
+      set count = 10 / n
+    }
+  }
+  view main() -> Surface { Button { label: "${count}", on_tap: not_zero(1) } }
+}
+"#,
+    );
+}
+
+#[test]
+fn short_circuit_guards_the_right_side() {
+    assert_safe(
+        r#"
+space S {
+  state {
+    count: number = 0
+  }
+  action and_guard(n: number) {
+    if n != 0 and 10 / n > 1 {
+      set count = 1
+    }
+  }
+  action or_guard(n: number) {
+    if n == 0 or 10 / n > 1 {
+      set count = 1
+    }
+  }
+  view main() -> Surface { Button { label: "${count}", on_tap: and_guard(1) } }
+}
+"#,
+    );
+}
+
+#[test]
+fn guard_on_the_wrong_branch() {
+    let source = r#"
+space S {
+  state {
+    count: number = 0
+  }
+  action run(n: number) {
+    if n != 0 {
+      set count = 1
+    } else {
+      set count = 10 / n
+    }
+  }
+  view main() -> Surface { Button { label: "${count}", on_tap: run(1) } }
+}
+"#;
+    assert_eq!(hazards(source).len(), 1);
+}
+
+#[test]
+fn assert_rules_out_zero() {
+    assert_safe(
+        r#"
+space S {
+  state {
+    count: number = 0
+  }
+  action run(n: number) {
+    assert n != 0
+    set count = 10 / n
+  }
+  view main() -> Surface { Button { label: "${count}", on_tap: run(1) } }
+}
+"#,
+    );
+}
+
+#[test]
+fn arithmetic_keeps_ranges() {
+    assert_safe(
+        r#"
+space S {
+  state {
+    count: number = 0
+  }
+  action square(n: number) {
+    // n * n + 1 is at least 1
+    set count = 10 / (n * n + 1)
+  }
+  action abs(n: number) {
+    set count = 10 / (math.abs(n) + 1)
+  }
+  view main() -> Surface { Button { label: "${count}", on_tap: square(1) } }
+}
+"#,
+    );
+    let source = r#"
+space S {
+  state {
+    count: number = 0
+  }
+  action run(n: number) {
+    set count = 10 / (n - n)
+  }
+  view main() -> Surface { Button { label: "${count}", on_tap: run(1) } }
+}
+"#;
+    assert_eq!(hazards(source).len(), 1);
+}
+
+// ══════════════════════════════════════════════════════════════════════════════
+// State and invariants
+// ══════════════════════════════════════════════════════════════════════════════
+
+#[test]
+fn state_range_from_every_action() {
+    // `total` starts at 1 and only grows
+    assert_safe(
+        r#"
+space S {
+  state {
+    count: number = 0
+    total: number = 1
+  }
+  action run(n: number) {
+    set total = total + n * n
+    set count = 100 / total
+  }
+  view main() -> Surface { Button { label: "${count}", on_tap: run(1) } }
+}
+"#,
+    );
+}
+
+#[test]
+fn state_that_some_action_zeroes() {
+    let source = r#"
+space S {
+  state {
+    count: number = 0
+    total: number = 1
+  }
+  action run() {
+    set count = 100 / total
+  }
+  action reset() {
+    set total = 0
+  }
+  view main() -> Surface { Button { label: "${count}", on_tap: run } }
+}
+"#;
+    assert_eq!(
+        hazards(source),
+        vec![(
+            8,
+            "possible division by zero: the divisor is in [0, 1]".to_string()
+        )]
+    );
+}
+
+#[test]
+fn invariant_rules_out_zero() {
+    assert_safe(
+        r#"
+space S {
+  state {
+    count: number = 0
+    total: number = 1
+  }
+  invariant positive { total > 0 }
+  action run(n: number) {
+    set count = 100 / total
+    set total = total + n
+  }
+  view main() -> Surface { Button { label: "${count}", on_tap: run(1) } }
+}
+"#,
+    );
+}
+
+#[test]
+fn derived_field_divisor() {
+    let w = single_warning(
+        r#"
+space S {
+  state {
+    count: number = 0
+    items: list<number> = []
+  }
+  derived {
+    average: number = count / list.length(items)
+  }
+  action add(x: number) {
+    set items = list.append(items, x)
+  }
+  view main() -> Surface { Button { label: "${average}", on_tap: add(1) } }
+}
+"#,
+    );
+    assert_eq!(
+        w.message,
+        "possible division by zero: the divisor is in [0, ∞)"
+    );
+    assert_eq!(w.span.start_line, 8);
+}
+
+#[test]
+fn loop_fixpoint() {
+    // `count` grows inside the loop, so it is not known to stay 0 ...
+    let source = r#"
+space S {
+  state {
+    count: number = 0
+    items: list<number> = []
+  }
+  action run() {
+    set count = 0
+    for x in items {
+      set count = count + 1
+    }
+    set count = 10 / count
+  }
+  action add(x: number) {
+    set items = list.append(items, x)
+  }
+  view main() -> Surface { Button { label: "${count}", on_tap: run } }
+}
+"#;
+    assert_eq!(hazards(source).len(), 1);
+    // ... but it stays at least 1
+    assert_safe(
+        r#"
+space S {
+  state {
+    count: number = 0
+    items: list<number> = []
+  }
+  action run() {
+    set count = 1
+    for x in items {
+      set count = count + x * x
+    }
+    set count = 10 / count
+  }
+  action add(x: number) {
+    set items = list.append(items, x)
+  }
+  view main() -> Surface { Button { label: "${count}", on_tap: run } }
+}
+"#,
+    );
+}
+
+// ══════════════════════════════════════════════════════════════════════════════
+// math.sqrt
+// ══════════════════════════════════════════════════════════════════════════════
+
+#[test]
+fn sqrt_of_a_possibly_negative_number() {
+    let w = single_warning(
+        r#"
+space S {
+  state {
+    count: number = 0
+  }
+  action run(n: number) {
+    set count = math.sqrt(n - 1)
+  }
+  view main() -> Surface { Button { label: "${count}", on_tap: run(1) } }
+}
+"#,
+    );
+    assert_eq!(w.lint.as_deref(), Some("math_domain"));
+    assert_eq!(
+        w.message,
+        "math.sqrt argument may be negative: it is unconstrained"
+    );
+}
+
+#[test]
+fn sqrt_of_a_non_negative_number() {
+    assert_safe(
+        r#"
+space S {
+  state {
+    count: number = 0
+  }
+  action square(n: number) {
+    set count = math.sqrt(n * n) + math.sqrt(math.abs(n))
+  }
+  action guarded(n: number) {
+    if n >= 0 {
+      set count = math.sqrt(n)
+    }
+  }
+  view main() -> Surface { Button { label: "${count}", on_tap: square(1) } }
+}
+"#,
+    );
+}
+
+// ══════════════════════════════════════════════════════════════════════════════
+// List indexes
+// ══════════════════════════════════════════════════════════════════════════════
+
+#[test]
+fn index_into_a_list_of_unknown_length() {
+    let w = single_warning(
+        r#"
+space S {
+  state {
+    count: number = 0
+    items: list<number> = []
+  }
+  action run() {
+    let x = list.get(items, 0)
+    set count = x ?? 0
+  }
+  action add(x: number) {
+    set items = list.append(items, x)
+  }
+  view main() -> Surface {
+    Button { label: "${count}", on_tap: run }
+    Button { label: "add", on_tap: add(1) }
+  }
+}
+"#,
+    );
+    assert_eq!(w.lint.as_deref(), Some("index_out_of_bounds"));
+    assert_eq!(
+        w.message,
+        "list index may be out of bounds: the index is in [0, 0], the length is in [0, ∞)"
+    );
+}
+
+#[test]
+fn index_checked_against_the_length() {
+    assert_safe(
+        r#"
+space S {
+  state {
+    count: number = 0
+    items: list<number> = []
+  }
+  action replace(n: number) {
+    if n >= 0 and n < list.length(items) {
+      set items = list.set(items, n, 0)
+    }
+  }
+  action first() {
+    if list.length(items) != 0 {
+      set count = list.get(items, 0) ?? 0
+    }
+  }
+  action third() {
+    if list.length(items) > 2 {
+      set count = list.get(items, 2) ?? 0
+    }
+  }
+  action add(x: number) {
+    set items = list.append(items, x)
+  }
+  view main() -> Surface { Button { label: "${count}", on_tap: first } }
+}
+"#,
+    );
+}
+
+#[test]
+fn negative_index() {
+    let source = r#"
+space S {
+  state {
+    items: list<number> = []
+  }
+  action remove(n: number) {
+    if n < list.length(items) {
+      set items = list.remove(items, n)
+    }
+  }
+  action add(x: number) {
+    set items = list.append(items, x)
+  }
+  view main() -> Surface { Button { label: "add", on_tap: add(1) } }
+}
+"#;
+    assert_eq!(hazards(source).len(), 1);
+}
+
+#[test]
+fn index_off_by_one() {
+    let source = r#"
+space S {
+  state {
+    count: number = 0
+    items: list<number> = []
+  }
+  action fourth() {
+    if list.length(items) > 2 {
+      set count = list.get(items, 3) ?? 0
+    }
+  }
+  action add(x: number) {
+    set items = list.append(items, x)
+  }
+  view main() -> Surface { Button { label: "${count}", on_tap: fourth } }
+}
+"#;
+    assert_eq!(hazards(source).len(), 1);
+}
+
+#[test]
+fn index_of_a_literal_list() {
+    let source = r#"
+space S {
+  state {
+    count: number = 0
+  }
+  action last() {
+    set count = list.get([1, 2, 3], 2) ?? 0
+  }
+  action past_the_end() {
+    set count = list.get([1, 2, 3], 3) ?? 0
+  }
+  view main() -> Surface { Button { label: "${count}", on_tap: last } }
+}
+"#;
+    assert_eq!(
+        hazards(source)
+            .iter()
+            .map(|(line, _)| *line)
+            .collect::<Vec<_>>(),
+        vec![10]
+    );
+}
+
+#[test]
+fn loop_index_is_in_bounds() {
+    assert_safe(
+        r#"
+space S {
+  state {
+    items: list<number> = []
+  }
+  action bump() {
+    for x, i in items {
+      set items = list.update(items, i, x + 1)
+    }
+  }
+  action add(x: number) {
+    set items = list.append(items, x)
+  }
+  view main() -> Surface { Button { label: "bump", on_tap: bump } }
+}
+"#,
+    );
+}
+
+#[test]
+fn facts_about_a_list_are_dropped_when_it_changes() {
+    let source = r#"
+space S {
+  state {
+    items: list<number> = []
+  }
+  action replace(n: number) {
+    if n >= 0 and n < list.length(items) {
+      set items = []
+      set items = list.set(items, n, 0)
+    }
+  }
+  action add(x: number) {
+    set items = list.append(items, x)
+  }
+  view main() -> Surface { Button { label: "add", on_tap: add(1) } }
+}
+"#;
+    assert_eq!(hazards(source).len(), 1);
+}
+
+#[test]
+fn index_in_a_view() {
+    let source = r#"
+space S {
+  state {
+    items: list<number> = []
+  }
+  action add(x: number) {
+    set items = list.append(items, x)
+  }
+  view main() -> Surface {
+    if list.length(items) > 0 {
+      Text { value: "${list.get(items, 0)}" }
+    }
+    Text { value: "${list.get(items, 1)}" }
+    Button { label: "add", on_tap: add(1) }
+  }
+}
+"#;
+    assert_eq!(
+        hazards(source)
+            .iter()
+            .map(|(line, _)| *line)
+            .collect::<Vec<_>>(),
+        vec![13]
+    );
+}
+
+#[test]
+fn lint_can_be_allowed() {
+    assert_safe(
+        r#"
+space S {
+  state {
+    count: number = 0
+  }
+  action run(n: number) {
+    set count = 10 / n // pepl-allow(division_by_zero)
+  }
+  view main() -> Surface { Button { label: "${count}", on_tap: run(1) } }
+}
+"#,
+    );
+}
@@ -0,0 +1,587 @@
+//! Invariant preservation proofs — `CompileResult.invariant_proofs`, and
+//! every counterexample replayed on the evaluator.
+
+use pepl_compiler::compile_to_result;
+use pepl_compiler::invariant_proof::{Counterexample, InvariantProof, Verdict};
+use pepl_eval::SpaceInstance;
+use pepl_lexer::Lexer;
+use pepl_parser::Parser;
+use pepl_stdlib::Value;
+use pepl_types::ast::{Expr, ExprKind};
+use pepl_types::{SourceFile, Span};
+
+// ══════════════════════════════════════════════════════════════════════════════
+// Helpers
+// ══════════════════════════════════════════════════════════════════════════════
+
+fn proofs(source: &str) -> Vec<InvariantProof> {
+    let result = compile_to_result(source, "test.pepl");
+    assert!(result.success, "compile failed: {:?}", result.errors);
+    result.invariant_proofs
+}
+
+fn verdict(source: &str, action: &str, invariant: &str) -> Verdict {
+    proofs(source)
+        .into_iter()
+        .find(|p| p.action == action && p.invariant == invariant)
+        .unwrap_or_else(|| panic!("no proof for ({action}, {invariant})"))
+        .verdict
+}
+
+fn assert_proven(source: &str, action: &str, invariant: &str) {
+    assert_eq!(verdict(source, action, invariant), Verdict::Proven);
+}
+
+fn unknown_reason(source: &str, action: &str, invariant: &str) -> String {
+    match verdict(source, action, invariant) {
+        Verdict::Unknown { reason } => reason,
+        other => panic!("expected unknown, got {other:?}"),
+    }
+}
+
+/// The counterexample, after checking that the evaluator really rolls the
+/// action back on it.
+fn refuted(source: &str, action: &str, invariant: &str) -> Counterexample {
+    let Verdict::Refuted { counterexample } = verdict(source, action, invariant) else {
+        panic!("expected a counterexample for ({action}, {invariant})");
+    };
+    replay(source, action, invariant, &counterexample);
+    counterexample
+}
+
+/// Start the space from `counterexample.before` and dispatch `action`.
+fn replay(source: &str, action: &str, invariant: &str, counterexample: &Counterexample) {
+    let sf = SourceFile::new("test.pepl", source);
+    let lex = Lexer::new(&sf).lex();
+    let mut program = Parser::new(lex.tokens, &sf).parse().program.unwrap();
+    for field in &mut program.space.body.state.fields {
+        if let Some(before) = counterexample.before.get(&field.name.name) {
+            field.default = literal(&value(before), field.default.span);
+        }
+    }
+    let mut space = SpaceInstance::new(&program).unwrap();
+
+    let decl = program
+        .space
+        .body
+        .actions
+        .iter()
+        .find(|a| a.name.name == action)
+        .unwrap();
+    let args = decl
+        .params
+        .iter()
+        .map(|p| value(&counterexample.params[&p.name.name]))
+        .collect();
+    let result = space.dispatch(action, args).unwrap();
+    assert!(!result.committed, "{counterexample} did not roll back");
+    assert!(
+        result
+            .invariant_error
+            .as_deref()
+            .unwrap_or("")
+            .contains(invariant),
+        "{:?}",
+        result.invariant_error
+    );
+}
+
+/// The value a counterexample literal (`0.5`, `true`, `"aaaa"`, `[0, 0]`)
+/// stands for.
+fn value(literal: &str) -> Value {
+    match literal {
+        "true" => Value::Bool(true),
+        "false" => Value::Bool(false),
+        "[]" => Value::List(vec![]),
+        _ if literal.starts_with('[') => Value::List(
+            literal
+                .trim_matches(|c| c == '[' || c == ']')
+                .split(", ")
+                .map(value)
+                .collect(),
+        ),
+        _ if literal.starts_with('"') => Value::String(literal.trim_matches('"').to_string()),
+        _ => Value::Number(literal.parse().unwrap()),
+    }
+}
+
+/// `value` as the literal expression of a state field's initialiser.
+fn literal(value: &Value, span: Span) -> Expr {
+    let kind = match value {
+        Value::Bool(b) => ExprKind::BoolLit(*b),
+        Value::Number(n) => ExprKind::NumberLit(*n),
+        Value::String(s) => ExprKind::StringLit(s.clone()),
+        Value::List(items) => {
+            ExprKind::ListLit(items.iter().map(|item| literal(item, span)).collect())
+        }
+        other => panic!("no literal for {other:?}"),
+    };
+    Expr::new(kind, span)
+}
+
+// ══════════════════════════════════════════════════════════════════════════════
+// Numbers
+// ══════════════════════════════════════════════════════════════════════════════
+
+#[test]
+fn increment_is_proven() {
+    let source = r#"
+space S {
+  state {
+    count: number = 0
+    done: bool = false
+    name: string = ""
+    items: list<number> = []
+  }
+  invariant non_negative { count >= 0 }
+  action increment() {
+    set count = count + 1
+  }
+  view main() -> Surface {
+    Text { value: "${count} ${done} ${name} ${list.length(items)}" }
+  }
+}
+"#;
+    assert_proven(source, "increment", "non_negative");
+}
+
+#[test]
+fn decrement_is_refuted() {
+    let source = r#"
+space S {
+  state {
+    count: number = 0
+    done: bool = false
+    name: string = ""
+    items: list<number> = []
+  }
+  invariant non_negative { count >= 0 }
+  action decrement() {
+    set count = count - 1
+  }
+  view main() -> Surface {
+    Text { value: "${count} ${done} ${name} ${list.length(items)}" }
+  }
+}
+"#;
+    let counterexample = refuted(source, "decrement", "non_negative");
+    assert_eq!(counterexample.before["count"], "0");
+    assert_eq!(counterexample.after["count"], "-1");
+    assert_eq!(
+        counterexample.to_string(),
+        "before: count = 0, done = false, items = [], name = \"\"; after: count = -1"
+    );
+}
+
+#[test]
+fn guarded_decrement() {
+    let source = r#"
+space S {
+  state {
+    count: number = 0
+    done: bool = false
+    name: string = ""
+    items: list<number> = []
+  }
+  invariant non_negative { count >= 0 }
+  action decrement() {
+    if count >= 1 {
+      set count = count - 1
+    }
+  }
+  action decrement_positive() {
+    if count > 0 {
+      set count = count - 1
+    }
+  }
+  view main() -> Surface {
+    Text { value: "${count} ${done} ${name} ${list.length(items)}" }
+  }
+}
+"#;
+    assert_proven(source, "decrement", "non_negative");
+    // Numbers are not integers
+    let counterexample = refuted(source, "decrement_positive", "non_negative");
+    assert_eq!(counterexample.before["count"], "0.5");
+}
+
+#[test]
+fn parameter_in_the_counterexample() {
+    let source = r#"
+space S {
+  state {
+    count: number = 0
+    done: bool = false
+    name: string = ""
+    items: list<number> = []
+  }
+  invariant non_negative { count >= 0 }
+  action add(n: number) {
+    set count = count + n
+  }
+  view main() -> Surface {
+    Text { value: "${count} ${done} ${name} ${list.length(items)}" }
+  }
+}
+"#;
+    let counterexample = refuted(source, "add", "non_negative");
+    assert!(
+        counterexample.params["n"].starts_with('-'),
+        "{counterexample}"
+    );
+}
+
+#[test]
+fn early_return_and_assert_guard() {
+    let source = r#"
+space S {
+  state {
+    count: number = 0
+    done: bool = false
+    name: string = ""
+    items: list<number> = []
+  }
+  invariant non_negative { count >= 0 }
+  action add(n: number) {
+    if n < 0 {
+      return
+    }
+    set count = count + n
+  }
+  action take(n: number) {
+    assert n <= count
+    set count = count - n
+  }
+  view main() -> Surface {
+    Text { value: "${count} ${done} ${name} ${list.length(items)}" }
+  }
+}
+"#;
+    assert_proven(source, "add", "non_negative");
+    assert_proven(source, "take", "non_negative");
+}
+
+#[test]
+fn math_max_and_clamp() {
+    let source = r#"
+space S {
+  state {
+    count: number = 0
+    done: bool = false
+    name: string = ""
+    items: list<number> = []
+  }
+  invariant non_negative { count >= 0 }
+  invariant at_most_ten { count <= 10 }
+  action take(n: number) {
+    set count = math.max(count - n, 0)
+  }
+  action put(n: number) {
+    set count = math.clamp(count + n, 0, 10)
+  }
+  view main() -> Surface {
+    Text { value: "${count} ${done} ${name} ${list.length(items)}" }
+  }
+}
+"#;
+    assert_proven(source, "take", "non_negative");
+    assert_proven(source, "put", "non_negative");
+    assert_proven(source, "put", "at_most_ten");
+    // `take` can raise `count` past 10 with a negative `n`
+    refuted(source, "take", "at_most_ten");
+}
+
+#[test]
+fn other_invariants_are_assumed() {
+    let source = r#"
+space S {
+  state {
+    count: number = 0
+    done: bool = false
+    name: string = ""
+    items: list<number> = []
+  }
+  invariant non_negative { count >= 0 }
+  invariant at_most_ten { count <= 10 }
+  action double() {
+    if count <= 5 {
+      set count = count * 2
+    }
+  }
+  view main() -> Surface {
+    Text { value: "${count} ${done} ${name} ${list.length(items)}" }
+  }
+}
+"#;
+    assert_proven(source, "double", "non_negative");
+    assert_proven(source, "double", "at_most_ten");
+}
+
+// ══════════════════════════════════════════════════════════════════════════════
+// Booleans, lists and strings
+// ══════════════════════════════════════════════════════════════════════════════
+
+#[test]
+fn bool_field() {
+    let source = r#"
+space S {
+  state {
+    count: number = 0
+    done: bool = false
+    name: string = ""
+    items: list<number> = []
+  }
+  invariant non_negative { count >= 0 }
+  invariant done_means_counted { not done or count > 0 }
+  action finish() {
+    set done = true
+  }
+  action finish_counted() {
+    if count > 0 {
+      set done = true
+    }
+  }
+  view main() -> Surface {
+    Text { value: "${count} ${done} ${name} ${list.length(items)}" }
+  }
+}
+"#;
+    let counterexample = refuted(source, "finish", "done_means_counted");
+    assert_eq!(counterexample.before["count"], "0");
+    assert_eq!(counterexample.after["done"], "true");
+    assert_proven(source, "finish_counted", "done_means_counted");
+}
+
+#[test]
+fn list_length() {
+    let source = r#"
+space S {
+  state {
+    count: number = 0
+    done: bool = false
+    name: string = ""
+    items: list<number> = []
+  }
+  invariant non_negative { count >= 0 }
+  invariant at_most_three { list.length(items) <= 3 }
+  action add(x: number) {
+    set items = list.append(items, x)
+  }
+  action add_checked(x: number) {
+    if list.length(items) < 3 {
+      set items = list.append(items, x)
+    }
+  }
+  action reverse() {
+    set items = list.reverse(items)
+  }
+  view main() -> Surface {
+    Text { value: "${count} ${done} ${name} ${list.length(items)}" }
+  }
+}
+"#;
+    let counterexample = refuted(source, "add", "at_most_three");
+    assert_eq!(counterexample.before["items"], "[0, 0, 0]");
+    assert_proven(source, "add_checked", "at_most_three");
+    assert_proven(source, "reverse", "at_most_three");
+}
+
+#[test]
+fn string_length() {
+    let source = r#"
+space S {
+  state {
+    count: number = 0
+    done: bool = false
+    name: string = ""
+    items: list<number> = []
+  }
+  invariant non_negative { count >= 0 }
+  invariant short_name { string.length(name) <= 3 }
+  action rename(s: string) {
+    set name = s
+  }
+  action rename_checked(s: string) {
+    if string.length(s) <= 3 {
+      set name = s
+    }
+  }
+  view main() -> Surface {
+    Text { value: "${count} ${done} ${name} ${list.length(items)}" }
+  }
+}
+"#;
+    let counterexample = refuted(source, "rename", "short_name");
+    assert_eq!(counterexample.params["s"], "\"aaaa\"");
+    assert_proven(source, "rename_checked", "short_name");
+}
+
+// ══════════════════════════════════════════════════════════════════════════════
+// Unknown
+// ══════════════════════════════════════════════════════════════════════════════
+
+#[test]
+fn non_linear_arithmetic() {
+    let source = r#"
+space S {
+  state {
+    count: number = 0
+    done: bool = false
+    name: string = ""
+    items: list<number> = []
+  }
+  invariant non_negative { count >= 0 }
+  action scale(n: number) {
+    set count = count * n
+  }
+  view main() -> Surface {
+    Text { value: "${count} ${done} ${name} ${list.length(items)}" }
+  }
+}
+"#;
+    assert_eq!(
+        unknown_reason(source, "scale", "non_negative"),
+        "non-linear arithmetic is not modelled"
+    );
+}
+
+#[test]
+fn loop_is_unknown() {
+    let source = r#"
+space S {
+  state {
+    count: number = 0
+    done: bool = false
+    name: string = ""
+    items: list<number> = []
+  }
+  invariant non_negative { count >= 0 }
+  action total() {
+    for x in items {
+      set count = count + x
+    }
+  }
+  view main() -> Surface {
+    Text { value: "${count} ${done} ${name} ${list.length(items)}" }
+  }
+}
+"#;
+    assert_eq!(
+        unknown_reason(source, "total", "non_negative"),
+        "the action sets 'count' in a for loop"
+    );
+}
+
+#[test]
+fn unmodelled_value_that_does_not_matter() {
+    // `count % 3` is not modelled, but the guard bounds whatever it is
+    let source = r#"
+space S {
+  state {
+    count: number = 0
+    done: bool = false
+    name: string = ""
+    items: list<number> = []
+  }
+  invariant non_negative { count >= 0 }
+  action wrap() {
+    let r = count % 3
+    if r >= 0 {
+      set count = r
+    }
+  }
+  view main() -> Surface {
+    Text { value: "${count} ${done} ${name} ${list.length(items)}" }
+  }
+}
+"#;
+    assert_proven(source, "wrap", "non_negative");
+}
+
+// ══════════════════════════════════════════════════════════════════════════════
+// Result
+// ══════════════════════════════════════════════════════════════════════════════
+
+#[test]
+fn every_pair_is_reported() {
+    let source = r#"
+space S {
+  state {
+    count: number = 0
+    done: bool = false
+    name: string = ""
+    items: list<number> = []
+  }
+  invariant non_negative { count >= 0 }
+  invariant at_most_ten { count <= 10 }
+  action rename(s: string) {
+    set name = s
+  }
+  action reset() {
+    set count = 0
+  }
+  view main() -> Surface {
+    Text { value: "${count} ${done} ${name} ${list.length(items)}" }
+  }
+}
+"#;
+    let pairs: Vec<_> = proofs(source)
+        .into_iter()
+        .map(|p| (p.action, p.invariant, p.verdict == Verdict::Proven))
+        .collect();
+    assert_eq!(
+        pairs,
+        vec![
+            ("rename".to_string(), "non_negative".to_string(), true),
+            ("rename".to_string(), "at_most_ten".to_string(), true),
+            ("reset".to_string(), "non_negative".to_string(), true),
+            ("reset".to_string(), "at_most_ten".to_string(), true),
+        ]
+    );
+}
+
+#[test]
+fn verdict_serialization() {
+    let source = r#"
+space S {
+  state {
+    count: number = 0
+    done: bool = false
+    name: string = ""
+    items: list<number> = []
+  }
+  invariant non_negative { count >= 0 }
+  action decrement() {
+    set count = count - 1
+  }
+  view main() -> Surface {
+    Text { value: "${count} ${done} ${name} ${list.length(items)}" }
+  }
+}
+"#;
+    let json = serde_json::to_value(&proofs(source)[0]).unwrap();
+    assert_eq!(json["action"], "decrement");
+    assert_eq!(json["verdict"]["kind"], "refuted");
+    assert_eq!(json["verdict"]["counterexample"]["after"]["count"], "-1");
+}
+
+#[test]
+fn no_invariants() {
+    let source = r#"
+space S {
+  state {
+    count: number = 0
+    done: bool = false
+    name: string = ""
+    items: list<number> = []
+  }
+  action reset() {
+    set count = 0
+  }
+  view main() -> Surface {
+    Text { value: "${count} ${done} ${name} ${list.length(items)}" }
+  }
+}
+"#;
+    assert!(proofs(source).is_empty());
+}
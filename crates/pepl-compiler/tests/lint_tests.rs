@@ -0,0 +1,713 @@
+//! Lints — each built-in lint, `_` exemptions, `pepl-allow`/`pepl-deny`
+//! directives and per-compilation [`LintConfig`].
+
+use pepl_compiler::lint::{self, Level, LintConfig};
+use pepl_compiler::{compile_to_result_with_options, type_check, CompileOptions};
+use pepl_types::{ErrorCode, PeplError, Severity};
+
+// ══════════════════════════════════════════════════════════════════════════════
+// Helpers
+// ══════════════════════════════════════════════════════════════════════════════
+
+/// `(line, lint)` of every lint warning on `source`.
+fn lints(source: &str) -> Vec<(u32, String)> {
+    let errors = type_check(source, "test.pepl");
+    assert!(!errors.has_errors(), "{:#?}", errors.errors);
+    errors
+        .warnings
+        .iter()
+        .filter_map(|w| Some((w.span.start_line, w.lint.clone()?)))
+        .collect()
+}
+
+fn lint_names(source: &str) -> Vec<String> {
+    lints(source).into_iter().map(|(_, name)| name).collect()
+}
+
+fn only_warning(source: &str) -> PeplError {
+    let errors = type_check(source, "test.pepl");
+    assert!(!errors.has_errors(), "{:#?}", errors.errors);
+    assert_eq!(errors.warnings.len(), 1, "{:#?}", errors.warnings);
+    errors.warnings[0].clone()
+}
+
+// ══════════════════════════════════════════════════════════════════════════════
+// Built-in lints
+// ══════════════════════════════════════════════════════════════════════════════
+
+#[test]
+fn clean_space_has_no_lints() {
+    let source = r#"
+space L {
+  state {
+    count: number = 0
+    items: list<number> = []
+  }
+  derived {
+    doubled: number = count * 2
+  }
+  action run() {
+    set count = count + 1
+  }
+  view main() -> Surface {
+    Button { label: "${doubled} of ${list.length(items)}", on_tap: run }
+  }
+}
+"#;
+    assert_eq!(lints(source), vec![]);
+}
+
+#[test]
+fn unused_let() {
+    let w = only_warning(
+        r#"
+space L {
+  state {
+    count: number = 0
+  }
+  action run() {
+    let unused = count + 1
+  }
+  view main() -> Surface {
+    Button { label: "${count}", on_tap: run }
+  }
+}
+"#,
+    );
+    assert_eq!(w.lint.as_deref(), Some("unused_let"));
+    assert_eq!(w.code, ErrorCode::UNUSED_LET);
+    assert_eq!(w.severity, Severity::Warning);
+    assert_eq!(w.message, "'unused' is never read");
+    assert!(w.suggestion.is_some());
+}
+
+#[test]
+fn unused_let_in_view() {
+    let source = r#"
+space L {
+  state {
+    count: number = 0
+  }
+  action run() {
+    set count = count + 1
+  }
+  view main() -> Surface {
+    let label = "x"
+    Button { label: "${count}", on_tap: run }
+  }
+}
+"#;
+    assert_eq!(lint_names(source), vec!["unused_let"]);
+}
+
+#[test]
+fn underscore_names_are_exempt() {
+    let source = r#"
+space L {
+  state {
+    count: number = 0
+  }
+  action run() {
+    let _later = count
+    let _ = count
+    set count = count + 1
+  }
+  view main() -> Surface {
+    Button { label: "${count}", on_tap: run }
+  }
+}
+"#;
+    assert_eq!(lints(source), vec![]);
+}
+
+#[test]
+fn unused_state() {
+    let w = only_warning(
+        r#"
+space L {
+  state {
+    count: number = 0
+    stale: bool = false
+  }
+  action run() {
+    set count = count + 1
+  }
+  view main() -> Surface {
+    Button { label: "${count}", on_tap: run }
+  }
+}
+"#,
+    );
+    assert_eq!(w.lint.as_deref(), Some("unused_state"));
+    assert_eq!(w.message, "state field 'stale' is never read");
+}
+
+#[test]
+fn state_read_only_by_a_test_counts() {
+    let source = r#"
+space L {
+  state {
+    count: number = 0
+    items: list<number> = []
+  }
+  action run() {
+    set count = count + 1
+  }
+  view main() -> Surface {
+    Button { label: "${count}", on_tap: run }
+  }
+}
+
+tests {
+  test "t" {
+    assert items == []
+  }
+}
+"#;
+    assert_eq!(lints(source), vec![]);
+}
+
+#[test]
+fn unused_action() {
+    let w = only_warning(
+        r#"
+space L {
+  state {
+    count: number = 0
+  }
+  action run() {
+    set count = count + 1
+  }
+  action orphan() {
+    set count = 0
+  }
+  view main() -> Surface {
+    Button { label: "${count}", on_tap: run }
+  }
+}
+"#,
+    );
+    assert_eq!(w.lint.as_deref(), Some("unused_action"));
+    assert_eq!(w.message, "action 'orphan' is not referenced by any view");
+}
+
+#[test]
+fn action_started_by_timer_is_referenced() {
+    let source = r#"
+space T {
+  state {
+    ticks: number = 0
+  }
+  capabilities {
+    required: [timer]
+  }
+  action start() {
+    let _id = timer.start("tick", 1000)
+  }
+  action tick() {
+    set ticks = ticks + 1
+  }
+  view main() -> Surface {
+    Button { label: "${ticks}", on_tap: start }
+  }
+}
+"#;
+    assert_eq!(lints(source), vec![]);
+}
+
+#[test]
+fn unused_param() {
+    let w = only_warning(
+        r#"
+space L {
+  state {
+    count: number = 0
+  }
+  action add(n: number, _why: string) {
+    set count = count + 1
+  }
+  view main() -> Surface {
+    Button { label: "${count}", on_tap: add(1, "") }
+  }
+}
+"#,
+    );
+    assert_eq!(w.lint.as_deref(), Some("unused_param"));
+    assert_eq!(w.message, "parameter 'n' is never read");
+}
+
+#[test]
+fn unused_lambda_param() {
+    let source = r#"
+space L {
+  state {
+    count: number = 0
+    items: list<number> = []
+  }
+  action run() {
+    set count = list.reduce(items, 0, fn(acc, x) { acc + 1 })
+  }
+  view main() -> Surface {
+    Button { label: "${count}", on_tap: run }
+  }
+}
+"#;
+    assert_eq!(lint_names(source), vec!["unused_param"]);
+}
+
+#[test]
+fn shadowing() {
+    let w = only_warning(
+        r#"
+space L {
+  state {
+    count: number = 0
+  }
+  derived {
+    doubled: number = count * 2
+  }
+  action run() {
+    let doubled = count + 1
+    set count = doubled
+  }
+  view main() -> Surface {
+    Button { label: "${doubled}", on_tap: run }
+  }
+}
+"#,
+    );
+    assert_eq!(w.lint.as_deref(), Some("shadowing"));
+    assert_eq!(w.message, "'doubled' shadows a derived field");
+}
+
+#[test]
+fn shadowing_an_outer_binding() {
+    let source = r#"
+space L {
+  state {
+    count: number = 0
+  }
+  action run() {
+    let n = 1
+    if count > 0 {
+      let n = 2
+      set count = n
+    }
+    set count = n
+  }
+  view main() -> Surface {
+    Button { label: "${count}", on_tap: run }
+  }
+}
+"#;
+    assert_eq!(lint_names(source), vec!["shadowing"]);
+}
+
+#[test]
+fn unreachable_code() {
+    let found = lints(
+        r#"
+space L {
+  state {
+    count: number = 0
+  }
+  action run() {
+    if count > 3 {
+      return
+      set count = 0
+      set count = 1
+    }
+  }
+  view main() -> Surface {
+    Button { label: "${count}", on_tap: run }
+  }
+}
+"#,
+    );
+    // Reported once, at the first unreachable statement
+    assert_eq!(found, vec![(9, "unreachable_code".to_string())]);
+}
+
+#[test]
+fn unreachable_after_if_that_always_returns() {
+    let source = r#"
+space L {
+  state {
+    count: number = 0
+  }
+  action run() {
+    if count > 3 { return } else { return }
+    set count = 0
+  }
+  view main() -> Surface {
+    Button { label: "${count}", on_tap: run }
+  }
+}
+"#;
+    assert_eq!(lint_names(source), vec!["unreachable_code"]);
+}
+
+#[test]
+fn float_eq() {
+    let w = only_warning(
+        r#"
+space L {
+  state {
+    count: number = 0
+  }
+  action run() {
+    if count / 3 == 0.1 { set count = 0 }
+  }
+  view main() -> Surface {
+    Button { label: "${count}", on_tap: run }
+  }
+}
+"#,
+    );
+    assert_eq!(w.lint.as_deref(), Some("float_eq"));
+    assert_eq!(w.message, "comparing fractional numbers with `==`");
+    // Integer literals are fine
+    let source = r#"
+space L {
+  state {
+    count: number = 0
+  }
+  action run() {
+    if count == 3 { set count = 0 }
+  }
+  view main() -> Surface {
+    Button { label: "${count}", on_tap: run }
+  }
+}
+"#;
+    assert_eq!(lints(source), vec![]);
+}
+
+#[test]
+fn constant_derived() {
+    let found = lints(
+        r#"
+space L {
+  state {
+    count: number = 0
+    items: list<number> = []
+  }
+  derived {
+    doubled: number = count * 2
+    size: number = list.length(items)
+    twice: number = size * 2
+  }
+  action run() {
+    set count = count + 1
+  }
+  view main() -> Surface {
+    Button { label: "${doubled} ${twice}", on_tap: run }
+  }
+}
+"#,
+    );
+    // `items` is never set, so `size` and `twice` (through `size`) are constant
+    assert_eq!(
+        found,
+        vec![
+            (9, "constant_derived".to_string()),
+            (10, "constant_derived".to_string())
+        ]
+    );
+}
+
+#[test]
+fn derived_reading_the_clock_is_not_constant() {
+    let source = r#"
+space L {
+  state {
+    count: number = 0
+    items: list<number> = []
+  }
+  derived {
+    elapsed: number = time.now() - list.length(items)
+    seconds: number = elapsed / 1000
+  }
+  action run() {
+    set count = count + 1
+  }
+  view main() -> Surface {
+    Button { label: "${count} ${seconds}", on_tap: run }
+  }
+}
+"#;
+    assert_eq!(lints(source), vec![]);
+}
+
+#[test]
+fn lints_do_not_run_on_type_errors() {
+    let source = r#"
+space L {
+  state {
+    count: number = 0
+  }
+  action run() {
+    let unused = nope
+  }
+  view main() -> Surface {
+    Button { label: "${count}", on_tap: run }
+  }
+}
+"#;
+    let errors = type_check(source, "test.pepl");
+    assert!(errors.has_errors());
+    assert!(errors.warnings.iter().all(|w| w.lint.is_none()));
+}
+
+// ══════════════════════════════════════════════════════════════════════════════
+// Directives
+// ══════════════════════════════════════════════════════════════════════════════
+
+#[test]
+fn allow_trailing_comment_covers_its_line() {
+    let source = r#"
+space L {
+  state {
+    count: number = 0
+  }
+  action run() {
+    let unused = 1 // pepl-allow(unused_let)
+  }
+  view main() -> Surface {
+    Button { label: "${count}", on_tap: run }
+  }
+}
+"#;
+    assert_eq!(lints(source), vec![]);
+}
+
+#[test]
+fn allow_comment_covers_next_line_only() {
+    let found = lints(
+        r#"
+space L {
+  state {
+    count: number = 0
+  }
+  action run() {
+    // pepl-allow(unused_let)
+    let a = 1
+    let b = 2
+  }
+  view main() -> Surface {
+    Button { label: "${count}", on_tap: run }
+  }
+}
+"#,
+    );
+    assert_eq!(found, vec![(9, "unused_let".to_string())]);
+}
+
+#[test]
+fn allow_comment_covers_whole_declaration() {
+    let source = r#"
+space L {
+  state {
+    count: number = 0
+  }
+  // pepl-allow(unused_let, float_eq)
+  action run() {
+    let a = 1
+    let b = 2
+  }
+  view main() -> Surface {
+    Button { label: "${count}", on_tap: run }
+  }
+}
+"#;
+    assert_eq!(lints(source), vec![]);
+}
+
+#[test]
+fn file_directive_denies_lint() {
+    let source = r#"// pepl-deny(unused_let)
+space L {
+  state {
+    count: number = 0
+  }
+  action run() {
+    let unused = 1
+  }
+  view main() -> Surface {
+    Button { label: "${count}", on_tap: run }
+  }
+}
+"#;
+    let errors = type_check(source, "test.pepl");
+    assert!(errors.has_errors());
+    assert_eq!(errors.errors[0].code, ErrorCode::UNUSED_LET);
+    assert_eq!(errors.errors[0].severity, Severity::Error);
+}
+
+#[test]
+fn line_directive_overrides_file_directive() {
+    let source = r#"// pepl-allow(unused_let)
+space L {
+  state {
+    count: number = 0
+  }
+  action run() {
+    let a = 1
+    let b = 2 // pepl-warn(unused_let)
+  }
+  view main() -> Surface {
+    Button { label: "${count}", on_tap: run }
+  }
+}
+"#;
+    assert_eq!(lint_names(source), vec!["unused_let"]);
+}
+
+#[test]
+fn unknown_lint_in_directive() {
+    let w = only_warning(
+        r#"
+space L {
+  state {
+    count: number = 0
+  }
+  action run() {
+    set count = 1 // pepl-allow(unused_lets)
+  }
+  view main() -> Surface {
+    Button { label: "${count}", on_tap: run }
+  }
+}
+"#,
+    );
+    assert_eq!(w.lint.as_deref(), Some("unknown_lint"));
+    assert_eq!(w.message, "unknown lint 'unused_lets'");
+    assert_eq!(w.span.start_col, 33);
+}
+
+#[test]
+fn directive_inside_string_is_ignored() {
+    let source = r#"
+space L {
+  state {
+    count: number = 0
+  }
+  action run() {
+    let unused = "// pepl-allow(unused_let)"
+  }
+  view main() -> Surface {
+    Button { label: "${count}", on_tap: run }
+  }
+}
+"#;
+    assert_eq!(lint_names(source), vec!["unused_let"]);
+}
+
+// ══════════════════════════════════════════════════════════════════════════════
+// Configuration
+// ══════════════════════════════════════════════════════════════════════════════
+
+fn compile_with(source: &str, lints: LintConfig) -> pepl_compiler::CompileResult {
+    let options = CompileOptions {
+        lints,
+        ..Default::default()
+    };
+    compile_to_result_with_options(source, "test.pepl", &options)
+}
+
+/// A space whose one lint is the `unused_let` on line 7.
+const UNUSED_LET: &str = r#"
+space L {
+  state {
+    count: number = 0
+  }
+  action run() {
+    let unused = 1
+  }
+  view main() -> Surface {
+    Button { label: "${count}", on_tap: run }
+  }
+}
+"#;
+
+#[test]
+fn config_allows_lint() {
+    let result = compile_with(
+        UNUSED_LET,
+        LintConfig::default().with("unused_let", Level::Allow),
+    );
+    assert!(result.success);
+    assert!(result.warnings.is_empty(), "{:#?}", result.warnings);
+}
+
+#[test]
+fn config_denies_lint() {
+    let result = compile_with(
+        UNUSED_LET,
+        LintConfig::default().with("unused_let", Level::Deny),
+    );
+    assert!(!result.success);
+    assert_eq!(result.errors.errors[0].lint.as_deref(), Some("unused_let"));
+}
+
+#[test]
+fn directive_overrides_config() {
+    let result = compile_with(
+        r#"
+space L {
+  state {
+    count: number = 0
+  }
+  action run() {
+    let unused = 1 // pepl-allow(unused_let)
+  }
+  view main() -> Surface {
+    Button { label: "${count}", on_tap: run }
+  }
+}
+"#,
+        LintConfig::default().with("unused_let", Level::Deny),
+    );
+    assert!(result.success);
+}
+
+#[test]
+fn config_with_unknown_lint() {
+    let result = compile_with(
+        r#"
+space L {
+  state {
+    count: number = 0
+  }
+  action run() {
+    set count = count + 1
+  }
+  view main() -> Surface {
+    Button { label: "${count}", on_tap: run }
+  }
+}
+"#,
+        LintConfig::default().with("no_such_lint", Level::Deny),
+    );
+    assert!(result.success);
+    assert_eq!(result.warnings.len(), 1);
+    assert_eq!(result.warnings[0].code, ErrorCode::UNKNOWN_LINT);
+}
+
+#[test]
+fn lint_id_is_serialized() {
+    let errors = type_check(UNUSED_LET, "test.pepl");
+    let json = serde_json::to_value(&errors.warnings[0]).unwrap();
+    assert_eq!(json["lint"], "unused_let");
+    assert_eq!(json["category"], "lint");
+    assert_eq!(json["code"], 800);
+}
+
+#[test]
+fn every_lint_has_a_distinct_name_and_code() {
+    let names: std::collections::HashSet<_> = lint::LINTS.iter().map(|l| l.name).collect();
+    let codes: std::collections::HashSet<_> = lint::LINTS.iter().map(|l| l.code).collect();
+    assert_eq!(names.len(), lint::LINTS.len());
+    assert_eq!(codes.len(), lint::LINTS.len());
+    assert!(lint::LINTS.iter().all(|l| lint::find(l.name) == Some(l)));
+}
@@ -3,7 +3,7 @@
 //! Tests verify the full pipeline: source → lex → parse → type-check → codegen → .wasm
 //! for all 7 canonical examples and various error scenarios.
 
-use pepl_compiler::{compile, compile_to_result, type_check, CompileResult};
+use pepl_compiler::{compile, compile_to_result, disassemble, type_check, CompileResult};
 use std::time::Instant;
 
 // ══════════════════════════════════════════════════════════════════════════════
@@ -595,6 +595,24 @@ space Bad {
     assert!(errors.has_errors());
 }
 
+// ══════════════════════════════════════════════════════════════════════════════
+// 6b. disassemble
+// ══════════════════════════════════════════════════════════════════════════════
+
+#[test]
+fn disassemble_annotates_source_lines() {
+    let wat = disassemble(COUNTER, "counter.pepl").unwrap();
+    assert!(wat.contains("(func $dispatch_action"));
+    assert!(wat.contains("action increment | set count = count + 1"));
+    assert!(wat.contains("call $RT_ALLOC"));
+}
+
+#[test]
+fn disassemble_reports_compile_errors() {
+    let errors = disassemble("space { invalid", "bad.pepl").unwrap_err();
+    assert!(errors.has_errors());
+}
+
 // ══════════════════════════════════════════════════════════════════════════════
 // 7. Error code coverage
 // ══════════════════════════════════════════════════════════════════════════════
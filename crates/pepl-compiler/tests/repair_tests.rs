@@ -0,0 +1,480 @@
+//! Repair loop — each safe rewrite, re-checking between rounds, and what
+//! is left when the source cannot be repaired.
+
+use pepl_compiler::repair::{repair, RepairKind, RepairResult};
+use pepl_types::ErrorCode;
+
+// ══════════════════════════════════════════════════════════════════════════════
+// Helpers
+// ══════════════════════════════════════════════════════════════════════════════
+
+fn repaired(source: &str) -> RepairResult {
+    let result = repair(source, "test.pepl");
+    assert!(
+        result.success,
+        "{}\n{:#?}",
+        result.source, result.errors.errors
+    );
+    result
+}
+
+fn kinds(result: &RepairResult) -> Vec<RepairKind> {
+    result.edits.iter().map(|e| e.kind).collect()
+}
+
+// ══════════════════════════════════════════════════════════════════════════════
+// Rewrites
+// ══════════════════════════════════════════════════════════════════════════════
+
+#[test]
+fn valid_source_is_untouched() {
+    let source = r#"
+space Counter {
+  state {
+    count: number = 0
+    items: list<number> = []
+  }
+  action run() {
+    set count = count + 1
+  }
+  view main() -> Surface {
+    Button { label: "${count}", on_tap: run }
+  }
+}
+"#;
+    let result = repaired(source);
+    assert!(result.edits.is_empty());
+    assert_eq!(result.source, source);
+}
+
+#[test]
+fn stdlib_alias() {
+    let result = repaired(
+        r#"
+space Counter {
+  state {
+    count: number = 0
+    items: list<number> = []
+  }
+  action run() {
+    set count = list.len(items)
+  }
+  view main() -> Surface {
+    Button { label: "${count}", on_tap: run }
+  }
+}
+"#,
+    );
+    assert_eq!(
+        result.source,
+        r#"
+space Counter {
+  state {
+    count: number = 0
+    items: list<number> = []
+  }
+  action run() {
+    set count = list.length(items)
+  }
+  view main() -> Surface {
+    Button { label: "${count}", on_tap: run }
+  }
+}
+"#
+    );
+    assert_eq!(kinds(&result), vec![RepairKind::Fix]);
+    assert_eq!(result.edits[0].description, "replace with 'length'");
+    assert_eq!(result.edits[0].line, 8);
+    assert_eq!(result.edits[0].round, 1);
+}
+
+#[test]
+fn method_alias() {
+    let result = repaired(
+        r#"
+space Counter {
+  state {
+    count: number = 0
+    items: list<number> = []
+  }
+  action run() {
+    set count = items.size()
+  }
+  view main() -> Surface {
+    Button { label: "${count}", on_tap: run }
+  }
+}
+"#,
+    );
+    assert_eq!(
+        result.source,
+        r#"
+space Counter {
+  state {
+    count: number = 0
+    items: list<number> = []
+  }
+  action run() {
+    set count = items.length()
+  }
+  view main() -> Surface {
+    Button { label: "${count}", on_tap: run }
+  }
+}
+"#
+    );
+}
+
+#[test]
+fn misspelling_is_not_repaired() {
+    let source = r#"
+space Counter {
+  state {
+    count: number = 0
+    items: list<number> = []
+  }
+  action run() {
+    set count = list.lenght(items)
+  }
+  view main() -> Surface {
+    Button { label: "${count}", on_tap: run }
+  }
+}
+"#;
+    // The closest name is only a guess, so it is left to the user
+    let result = repair(source, "test.pepl");
+    assert!(!result.success);
+    assert!(result.edits.is_empty());
+    assert_eq!(result.errors.errors[0].fixes.len(), 1);
+}
+
+#[test]
+fn strict_equality() {
+    let result = repaired(
+        r#"
+space Counter {
+  state {
+    count: number = 0
+    items: list<number> = []
+  }
+  action run() {
+    if count === 1 and count !== 2 {
+      set count = 0 // a === b
+    }
+    let _s = "x === y ${count !== 3}"
+  }
+  view main() -> Surface {
+    Button { label: "${count}", on_tap: run }
+  }
+}
+"#,
+    );
+    assert_eq!(
+        result.source,
+        r#"
+space Counter {
+  state {
+    count: number = 0
+    items: list<number> = []
+  }
+  action run() {
+    if count == 1 and count != 2 {
+      set count = 0 // a === b
+    }
+    let _s = "x === y ${count != 3}"
+  }
+  view main() -> Surface {
+    Button { label: "${count}", on_tap: run }
+  }
+}
+"#
+    );
+    let descriptions: Vec<_> = result
+        .edits
+        .iter()
+        .map(|e| e.description.as_str())
+        .collect();
+    assert_eq!(
+        descriptions,
+        vec![
+            "replace `===` with `==`",
+            "replace `!==` with `!=`",
+            "replace `!==` with `!=`"
+        ]
+    );
+}
+
+#[test]
+fn block_comments() {
+    let result = repaired(
+        r#"
+space Counter {
+  state {
+    count: number = 0
+    items: list<number> = []
+  }
+  action run() {
+    /* Multi-line
+     * comment
+     */
+    set count = 1 /* inline */
+    set count = count + 1
+  }
+  view main() -> Surface {
+    Button { label: "${count}", on_tap: run }
+  }
+}
+"#,
+    );
+    assert_eq!(
+        result.source,
+        r#"
+space Counter {
+  state {
+    count: number = 0
+    items: list<number> = []
+  }
+  action run() {
+    // Multi-line
+    // comment
+    // inline
+    set count = 1
+    set count = count + 1
+  }
+  view main() -> Surface {
+    Button { label: "${count}", on_tap: run }
+  }
+}
+"#
+    );
+    assert_eq!(
+        kinds(&result),
+        vec![RepairKind::BlockComment, RepairKind::BlockComment]
+    );
+}
+
+#[test]
+fn block_comment_inside_string_is_kept() {
+    let source = r#"
+space Counter {
+  state {
+    count: number = 0
+    items: list<number> = []
+  }
+  action run() {
+    let _s = "/* not a comment */"
+    set count = 1
+  }
+  view main() -> Surface {
+    Button { label: "${count}", on_tap: run }
+  }
+}
+"#;
+    assert!(repaired(source).edits.is_empty());
+}
+
+#[test]
+fn block_order() {
+    let source = r#"
+space Counter {
+  // Increments
+  action run() {
+    set count = count + 1
+  }
+
+  state {
+    count: number = 0
+  }
+
+  view main() -> Surface {
+    Button { label: "${count}", on_tap: run }
+  }
+}
+"#;
+    let result = repaired(source);
+    assert_eq!(
+        result.source,
+        r#"
+space Counter {
+  state {
+    count: number = 0
+  }
+
+  // Increments
+  action run() {
+    set count = count + 1
+  }
+
+  view main() -> Surface {
+    Button { label: "${count}", on_tap: run }
+  }
+}
+"#
+    );
+    assert_eq!(kinds(&result), vec![RepairKind::BlockOrder; 2]);
+    assert_eq!(
+        result.edits[0].description,
+        "move 'state' block into the required order"
+    );
+}
+
+#[test]
+fn missing_capability() {
+    let result = repaired(
+        r#"
+space Counter {
+  state {
+    count: number = 0
+    items: list<number> = []
+  }
+  action run() {
+    set count = 1
+    let _r = http.get("https://example.com")
+  }
+  view main() -> Surface {
+    Button { label: "${count}", on_tap: run }
+  }
+}
+"#,
+    );
+    assert!(result
+        .source
+        .contains("  capabilities {\n    required: [http]\n  }"));
+    assert_eq!(result.edits[0].description, "declare capability 'http'");
+}
+
+// ══════════════════════════════════════════════════════════════════════════════
+// Rounds
+// ══════════════════════════════════════════════════════════════════════════════
+
+#[test]
+fn several_rounds() {
+    let source = r#"
+space Fetcher {
+  /* Fetch on tap */
+  action fetch() {
+    if list.len(items) === 0 {
+      let _r = http.get("https://example.com")
+    }
+  }
+  state {
+    items: list<string> = []
+  }
+  view main() -> Surface {
+    Button { label: "Fetch", on_tap: fetch }
+    Text { value: "${list.len(items)}" }
+  }
+}
+"#;
+    let result = repaired(source);
+    // Lexing fails until the comment is fixed; the block order, operator
+    // and aliases are only seen once it parses, and the capability only
+    // once the blocks are in order.
+    let rounds: Vec<_> = result.edits.iter().map(|e| (e.round, e.kind)).collect();
+    assert_eq!(
+        rounds,
+        vec![
+            (1, RepairKind::StrictEquality),
+            (1, RepairKind::BlockComment),
+            (2, RepairKind::BlockOrder),
+            (2, RepairKind::BlockOrder),
+            (3, RepairKind::Fix),
+            (3, RepairKind::Fix),
+            (3, RepairKind::Fix),
+        ]
+    );
+    assert_eq!(
+        result.source,
+        r#"
+space Fetcher {
+  state {
+    items: list<string> = []
+  }
+
+  capabilities {
+    required: [http]
+  }
+  // Fetch on tap
+  action fetch() {
+    if list.length(items) == 0 {
+      let _r = http.get("https://example.com")
+    }
+  }
+  view main() -> Surface {
+    Button { label: "Fetch", on_tap: fetch }
+    Text { value: "${list.length(items)}" }
+  }
+}
+"#
+    );
+}
+
+#[test]
+fn remaining_errors_are_returned() {
+    let result = repair(
+        r#"
+space Counter {
+  state {
+    count: number = 0
+    items: list<number> = []
+  }
+  action run() {
+    set count = list.len(items) + missing
+  }
+  view main() -> Surface {
+    Button { label: "${count}", on_tap: run }
+  }
+}
+"#,
+        "test.pepl",
+    );
+    assert!(!result.success);
+    assert_eq!(
+        result.source,
+        r#"
+space Counter {
+  state {
+    count: number = 0
+    items: list<number> = []
+  }
+  action run() {
+    set count = list.length(items) + missing
+  }
+  view main() -> Surface {
+    Button { label: "${count}", on_tap: run }
+  }
+}
+"#
+    );
+    assert_eq!(kinds(&result), vec![RepairKind::Fix]);
+    assert_eq!(result.errors.errors.len(), 1);
+    assert_eq!(result.errors.errors[0].code, ErrorCode::TYPE_MISMATCH);
+    assert_eq!(
+        result.errors.errors[0].message,
+        "undefined variable 'missing'"
+    );
+}
+
+#[test]
+fn result_serializes() {
+    let result = repaired(
+        r#"
+space Counter {
+  state {
+    count: number = 0
+    items: list<number> = []
+  }
+  action run() {
+    set count = list.len(items)
+  }
+  view main() -> Surface {
+    Button { label: "${count}", on_tap: run }
+  }
+}
+"#,
+    );
+    let json = serde_json::to_value(&result).unwrap();
+    assert_eq!(json["success"], true);
+    assert_eq!(json["edits"][0]["kind"], "fix");
+    assert_eq!(json["errors"]["total_errors"], 0);
+}
@@ -0,0 +1,523 @@
+//! Credential taint tracking — E609 for credentials reaching logs, state,
+//! views and capabilities, the flow path in the message, and the flows
+//! that are allowed.
+
+use pepl_types::{ErrorCode, PeplError};
+
+// ══════════════════════════════════════════════════════════════════════════════
+// Helpers
+// ══════════════════════════════════════════════════════════════════════════════
+
+fn check(source: &str) -> pepl_types::CompileErrors {
+    pepl_compiler::type_check(source, "test.pepl")
+}
+
+fn assert_ok(source: &str) {
+    let errors = check(source);
+    assert!(
+        !errors.has_errors(),
+        "expected no errors, got:\n{:#?}",
+        errors.errors
+    );
+}
+
+/// The single error `source` produces, which must be a credential leak.
+fn single_leak(source: &str) -> PeplError {
+    let errors = check(source);
+    assert_eq!(errors.errors.len(), 1, "{:#?}", errors.errors);
+    let leak = errors.errors[0].clone();
+    assert_eq!(leak.code, ErrorCode::CREDENTIAL_LEAK);
+    leak
+}
+
+// ══════════════════════════════════════════════════════════════════════════════
+// Sinks
+// ══════════════════════════════════════════════════════════════════════════════
+
+#[test]
+fn log_of_a_credential() {
+    let leak = single_leak(
+        r#"
+space S {
+  state {
+    count: number = 0
+  }
+  credentials {
+    api_key: string
+  }
+  action run() {
+    core.log(api_key)
+  }
+  view main() -> Surface { Button { label: "run", on_tap: run } }
+}
+"#,
+    );
+    assert_eq!(
+        leak.message,
+        "credential 'api_key' reaches core.log: 'api_key' (line 10) → core.log (line 10)"
+    );
+    assert_eq!((leak.span.start_line, leak.span.start_col), (10, 14));
+    assert!(leak.suggestion.is_some());
+}
+
+#[test]
+fn credential_stored_in_state() {
+    let leak = single_leak(
+        r#"
+space S {
+  state {
+    token: string = ""
+  }
+  credentials {
+    api_key: string
+  }
+  action run() {
+    set token = api_key
+  }
+  view main() -> Surface { Button { label: token, on_tap: run } }
+}
+"#,
+    );
+    assert!(leak
+        .message
+        .starts_with("credential 'api_key' reaches state field 'token'"));
+}
+
+#[test]
+fn credential_rendered_in_a_view() {
+    let leak = single_leak(
+        r#"
+space S {
+  state {
+    count: number = 0
+  }
+  credentials {
+    api_key: string
+  }
+  view main() -> Surface {
+    Text { value: api_key }
+  }
+}
+"#,
+    );
+    assert_eq!(
+        leak.message,
+        "credential 'api_key' reaches Text prop 'value': 'api_key' (line 10) → Text prop 'value' (line 10)"
+    );
+}
+
+#[test]
+fn credential_written_to_storage() {
+    let leak = single_leak(
+        r#"
+space S {
+  state {
+    count: number = 0
+  }
+  capabilities {
+    required: [storage]
+  }
+  credentials {
+    api_key: string
+  }
+  action run() {
+    storage.set("key", api_key)
+  }
+  view main() -> Surface { Button { label: "run", on_tap: run } }
+}
+"#,
+    );
+    assert!(leak
+        .message
+        .contains("reaches storage.set argument 'value'"));
+}
+
+#[test]
+fn each_sink_is_reported_once() {
+    let errors = check(
+        r#"
+space S {
+  state {
+    token: string = ""
+  }
+  credentials {
+    api_key: string
+  }
+  action run() {
+    core.log(api_key)
+    set token = api_key
+    core.log("ok")
+  }
+  view main() -> Surface { Button { label: token, on_tap: run } }
+}
+"#,
+    );
+    let codes: Vec<_> = errors.errors.iter().map(|e| e.code).collect();
+    assert_eq!(
+        codes,
+        vec![ErrorCode::CREDENTIAL_LEAK, ErrorCode::CREDENTIAL_LEAK]
+    );
+}
+
+// ══════════════════════════════════════════════════════════════════════════════
+// Flow paths
+// ══════════════════════════════════════════════════════════════════════════════
+
+#[test]
+fn path_through_let_and_interpolation() {
+    let leak = single_leak(
+        r#"
+space S {
+  state {
+    count: number = 0
+  }
+  credentials {
+    api_key: string
+  }
+  action run() {
+    let auth = api_key
+    let header = "Bearer ${auth}"
+    core.log(header)
+  }
+  view main() -> Surface { Button { label: "run", on_tap: run } }
+}
+"#,
+    );
+    assert_eq!(
+        leak.message,
+        "credential 'api_key' reaches core.log: 'api_key' (line 10) → let 'auth' (line 10) \
+         → interpolated into a string (line 11) → let 'header' (line 11) → core.log (line 12)"
+    );
+}
+
+#[test]
+fn path_through_a_record() {
+    let leak = single_leak(
+        r#"
+space S {
+  state {
+    token: string = ""
+  }
+  credentials {
+    api_key: string
+  }
+  action run() {
+    let config = { key: api_key, retries: 3 }
+    set token = config.key
+  }
+  view main() -> Surface { Button { label: token, on_tap: run } }
+}
+"#,
+    );
+    assert!(leak.message.contains(
+        "'api_key' (line 10) → field 'key' of a record (line 10) → let 'config' (line 10)"
+    ));
+}
+
+#[test]
+fn path_through_a_stdlib_call() {
+    let leak = single_leak(
+        r#"
+space S {
+  state {
+    token: string = ""
+  }
+  credentials {
+    api_key: string
+  }
+  action run() {
+    set token = string.to_upper(api_key)
+  }
+  view main() -> Surface { Button { label: token, on_tap: run } }
+}
+"#,
+    );
+    assert!(leak
+        .message
+        .contains("→ passed to string.to_upper (line 10) → state field 'token'"));
+}
+
+#[test]
+fn path_through_a_lambda() {
+    let leak = single_leak(
+        r#"
+space S {
+  state {
+    items: list<string> = []
+  }
+  credentials {
+    api_key: string
+  }
+  action run() {
+    let keys = list.map([api_key], fn(k) { "${k}!" })
+    set items = keys
+  }
+  view main() -> Surface { Button { label: "run", on_tap: run } }
+}
+"#,
+    );
+    assert!(
+        leak.message.contains("→ element of a list (line 10)"),
+        "{}",
+        leak.message
+    );
+    assert!(leak
+        .message
+        .contains("→ let 'keys' (line 10) → state field 'items'"));
+}
+
+#[test]
+fn sink_inside_a_lambda() {
+    let leak = single_leak(
+        r#"
+space S {
+  state {
+    count: number = 0
+  }
+  credentials {
+    api_key: string
+  }
+  action run() {
+    let shout = fn(s: string) { core.log(s) }
+    shout(api_key)
+  }
+  view main() -> Surface { Button { label: "run", on_tap: run } }
+}
+"#,
+    );
+    assert!(
+        leak.message
+            .contains("→ lambda parameter 's' (line 10) → core.log"),
+        "{}",
+        leak.message
+    );
+}
+
+#[test]
+fn path_through_a_loop_and_match() {
+    let leak = single_leak(
+        r#"
+space S {
+  state {
+    token: string = ""
+  }
+  capabilities {
+    required: [http]
+  }
+  credentials {
+    api_key: string
+  }
+  action run() {
+    for key in [api_key] {
+      match http.get(key) {
+        Ok(body) -> { set token = body }
+        Err(e) -> { set token = "${key}: ${e}" }
+      }
+    }
+  }
+  view main() -> Surface { Button { label: token, on_tap: run } }
+}
+"#,
+    );
+    // The response is not secret; the loop variable is
+    assert_eq!(leak.span.start_line, 16);
+    assert!(leak.message.contains("→ loop variable 'key' (line 13)"));
+}
+
+#[test]
+fn path_through_a_derived_field() {
+    let leak = single_leak(
+        r#"
+space S {
+  state {
+    count: number = 0
+  }
+  credentials {
+    api_key: string
+  }
+  derived {
+    masked: string = string.slice(api_key, 0, 4)
+  }
+  view main() -> Surface {
+    Text { value: masked }
+  }
+}
+"#,
+    );
+    assert!(
+        leak.message
+            .contains("→ derived field 'masked' (line 10) → Text prop 'value'"),
+        "{}",
+        leak.message
+    );
+}
+
+#[test]
+fn path_from_a_view_into_an_action() {
+    let leak = single_leak(
+        r#"
+space S {
+  state {
+    token: string = ""
+  }
+  credentials {
+    api_key: string
+  }
+  action save(value: string) {
+    set token = value
+  }
+  view main() -> Surface {
+    Button { label: "save", on_tap: save(api_key) }
+  }
+}
+"#,
+    );
+    assert!(
+        leak.message.contains(
+            "→ passed to action 'save' (line 13) → parameter 'value' (line 9) → state field 'token' (line 10)"
+        ),
+        "{}",
+        leak.message
+    );
+}
+
+// ══════════════════════════════════════════════════════════════════════════════
+// Allowed flows
+// ══════════════════════════════════════════════════════════════════════════════
+
+#[test]
+fn credential_in_an_http_url_or_body() {
+    assert_ok(
+        r#"
+space S {
+  state {
+    count: number = 0
+  }
+  capabilities {
+    required: [http]
+  }
+  credentials {
+    api_key: string
+  }
+  action run() {
+    let _r = http.get("https://api.example.com/v1?key=${api_key}")
+    let _p = http.post("https://api.example.com", "{\"key\": \"${api_key}\"}")
+  }
+  view main() -> Surface { Button { label: "run", on_tap: run } }
+}
+"#,
+    );
+}
+
+#[test]
+fn http_response_is_not_secret() {
+    assert_ok(
+        r#"
+space S {
+  state {
+    token: string = ""
+  }
+  capabilities {
+    required: [http]
+  }
+  credentials {
+    api_key: string
+  }
+  action run() {
+    match http.get("https://api.example.com/v1?key=${api_key}") {
+      Ok(body) -> { set token = body }
+      Err(e) -> { core.log(e) }
+    }
+  }
+  view main() -> Surface { Button { label: token, on_tap: run } }
+}
+"#,
+    );
+}
+
+#[test]
+fn checks_on_a_credential_are_not_secret() {
+    assert_ok(
+        r#"
+space S {
+  state {
+    count: number = 0
+  }
+  credentials {
+    api_key: string
+  }
+  action run() {
+    if api_key == "" or string.starts_with(api_key, "test_") {
+      set count = string.length("x")
+    }
+  }
+  view main() -> Surface { Button { label: "${count}", on_tap: run } }
+}
+"#,
+    );
+}
+
+#[test]
+fn a_local_shadowing_a_credential_is_not_secret() {
+    assert_ok(
+        r#"
+space S {
+  state {
+    count: number = 0
+  }
+  credentials {
+    api_key: string
+  }
+  action run() {
+    let api_key = "public"
+    core.log(api_key)
+  }
+  view main() -> Surface { Button { label: "run", on_tap: run } }
+}
+"#,
+    );
+}
+
+#[test]
+fn tests_are_not_analyzed() {
+    assert_ok(
+        r#"
+space S {
+  state {
+    token: string = ""
+  }
+  credentials {
+    api_key: string
+  }
+  action run() {
+    set token = "t"
+  }
+  view main() -> Surface { Button { label: token, on_tap: run } }
+}
+
+tests {
+  test "t" {
+    run()
+    assert token == "t"
+  }
+}
+"#,
+    );
+}
+
+#[test]
+fn no_credentials_block() {
+    assert_ok(
+        r#"
+space S {
+  state {
+    count: number = 0
+  }
+  action run() {
+    core.log("hi")
+  }
+  view main() -> Surface { Button { label: "run", on_tap: run } }
+}
+"#,
+    );
+}
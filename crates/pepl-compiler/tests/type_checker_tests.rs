@@ -3,7 +3,7 @@
 //! Each test parses + type-checks a PEPL source program via `pepl_compiler::type_check`
 //! and asserts on the presence (or absence) of specific error codes.
 
-use pepl_types::ErrorCode;
+use pepl_types::{ErrorCode, PeplError};
 
 // ══════════════════════════════════════════════════════════════════════════════
 // Helpers
@@ -1430,3 +1430,917 @@ space T {
 "#,
     );
 }
+
+// ══════════════════════════════════════════════════════════════════════════════
+// Test-only render assertions
+// ══════════════════════════════════════════════════════════════════════════════
+
+#[test]
+fn render_assertions_in_tests_pass() {
+    assert_ok(
+        r#"
+space T {
+  state {
+    count: number = 0
+  }
+  action increment() {
+    set count = count + 1
+  }
+  view main() -> Surface {
+    Column { } {
+      Text { value: "Count: ${count}" }
+      Button { label: "Add", on_tap: increment }
+    }
+  }
+}
+
+tests {
+  test "render" {
+    let s = render(main)
+    assert_component(s, "Button", { label: "Add" })
+    assert_component(s, "Text")
+    assert count_components(s, "Text") == 1
+    assert_snapshot(s, "main")
+    let d = render()
+    assert count_components(d, "Button") == 1
+  }
+}
+"#,
+    );
+}
+
+#[test]
+fn render_unknown_view_rejected() {
+    assert_error(
+        r#"
+space T {
+  state {
+    count: number = 0
+  }
+  action increment() {
+    set count = count + 1
+  }
+  view main() -> Surface {
+    Column { } {
+      Text { value: "Count: ${count}" }
+      Button { label: "Add", on_tap: increment }
+    }
+  }
+}
+
+tests {
+  test "render" {
+    let s = render(sidebar)
+  }
+}
+"#,
+        ErrorCode::TYPE_MISMATCH,
+    );
+}
+
+#[test]
+fn render_must_be_bound_with_let() {
+    assert_error(
+        r#"
+space T {
+  state {
+    count: number = 0
+  }
+  action increment() {
+    set count = count + 1
+  }
+  view main() -> Surface {
+    Column { } {
+      Text { value: "Count: ${count}" }
+      Button { label: "Add", on_tap: increment }
+    }
+  }
+}
+
+tests {
+  test "render" {
+    assert count_components(render(main), "Text") == 1
+  }
+}
+"#,
+        ErrorCode::TYPE_MISMATCH,
+    );
+}
+
+#[test]
+fn assert_component_unknown_component() {
+    assert_error(
+        r#"
+space T {
+  state {
+    count: number = 0
+  }
+  action increment() {
+    set count = count + 1
+  }
+  view main() -> Surface {
+    Column { } {
+      Text { value: "Count: ${count}" }
+      Button { label: "Add", on_tap: increment }
+    }
+  }
+}
+
+tests {
+  test "render" {
+    let s = render(main)
+    assert_component(s, "Slider")
+  }
+}
+"#,
+        ErrorCode::UNKNOWN_COMPONENT,
+    );
+}
+
+#[test]
+fn assert_component_wrong_arg_count() {
+    assert_error(
+        r#"
+space T {
+  state {
+    count: number = 0
+  }
+  action increment() {
+    set count = count + 1
+  }
+  view main() -> Surface {
+    Column { } {
+      Text { value: "Count: ${count}" }
+      Button { label: "Add", on_tap: increment }
+    }
+  }
+}
+
+tests {
+  test "render" {
+    let s = render(main)
+    assert_component(s)
+  }
+}
+"#,
+        ErrorCode::WRONG_ARG_COUNT,
+    );
+}
+
+#[test]
+fn assert_component_requires_surface() {
+    assert_error(
+        r#"
+space T {
+  state {
+    count: number = 0
+  }
+  action increment() {
+    set count = count + 1
+  }
+  view main() -> Surface {
+    Column { } {
+      Text { value: "Count: ${count}" }
+      Button { label: "Add", on_tap: increment }
+    }
+  }
+}
+
+tests {
+  test "render" {
+    assert_component(count, "Text")
+  }
+}
+"#,
+        ErrorCode::TYPE_MISMATCH,
+    );
+}
+
+#[test]
+fn render_builtins_unavailable_outside_tests() {
+    assert_error(
+        r#"
+space T {
+  state {
+    n: number = 0
+  }
+  action a() {
+    assert_snapshot(n, "x")
+  }
+}
+"#,
+        ErrorCode::TYPE_MISMATCH,
+    );
+}
+
+// ══════════════════════════════════════════════════════════════════════════════
+// Test-only game loop built-ins
+// ══════════════════════════════════════════════════════════════════════════════
+
+#[test]
+fn tick_and_send_event_in_tests_pass() {
+    assert_ok(
+        r#"
+space Game {
+  state {
+    t: number = 0
+  }
+  view main() -> Surface {
+    Column { } { }
+  }
+  update(dt: number) {
+    set t = t + dt
+  }
+  handleEvent(event: InputEvent) {
+    set t = 0
+  }
+}
+
+tests {
+  test "frames" {
+    tick(0.016)
+    tick_n(10, 0.016)
+    send_event({ type: "tap" })
+    assert t == 0
+  }
+}
+"#,
+    );
+}
+
+#[test]
+fn tick_requires_update() {
+    assert_error(
+        r#"
+space T {
+  state {
+    count: number = 0
+  }
+  action increment() {
+    set count = count + 1
+  }
+  view main() -> Surface {
+    Column { } {
+      Text { value: "Count: ${count}" }
+      Button { label: "Add", on_tap: increment }
+    }
+  }
+}
+
+tests {
+  test "frames" {
+    tick(0.016)
+  }
+}
+"#,
+        ErrorCode::TYPE_MISMATCH,
+    );
+}
+
+#[test]
+fn send_event_requires_handle_event() {
+    assert_error(
+        r#"
+space T {
+  state {
+    count: number = 0
+  }
+  action increment() {
+    set count = count + 1
+  }
+  view main() -> Surface {
+    Column { } {
+      Text { value: "Count: ${count}" }
+      Button { label: "Add", on_tap: increment }
+    }
+  }
+}
+
+tests {
+  test "frames" {
+    send_event({ type: "tap" })
+  }
+}
+"#,
+        ErrorCode::TYPE_MISMATCH,
+    );
+}
+
+#[test]
+fn tick_n_wrong_arg_count() {
+    assert_error(
+        r#"
+space Game {
+  state {
+    t: number = 0
+  }
+  update(dt: number) {
+    set t = t + dt
+  }
+}
+
+tests {
+  test "frames" {
+    tick_n(0.016)
+  }
+}
+"#,
+        ErrorCode::WRONG_ARG_COUNT,
+    );
+}
+
+// ══════════════════════════════════════════════════════════════════════════════
+// Nil narrowing
+// ══════════════════════════════════════════════════════════════════════════════
+
+#[test]
+fn narrowing_not_equal_nil() {
+    assert_ok(
+        r#"
+space N {
+  state {
+    items: list<number> = [1, 2, 3]
+    n: number = 0
+  }
+  action run() {
+    let found = list.find(items, fn(x: number) { x > 2 })
+    if found != nil { set n = found }
+  }
+}
+"#,
+    );
+}
+
+#[test]
+fn narrowing_nil_on_the_left() {
+    assert_ok(
+        r#"
+space N {
+  state {
+    items: list<number> = [1, 2, 3]
+    n: number = 0
+  }
+  action run() {
+    let found = list.find(items, fn(x: number) { x > 2 })
+    if nil != found { set n = found }
+  }
+}
+"#,
+    );
+}
+
+#[test]
+fn narrowing_without_check_is_error() {
+    assert_error(
+        r#"
+space N {
+  state {
+    items: list<number> = [1, 2, 3]
+    n: number = 0
+  }
+  action run() {
+    let found = list.find(items, fn(x: number) { x > 2 })
+    set n = found
+  }
+}
+"#,
+        ErrorCode::TYPE_MISMATCH,
+    );
+}
+
+#[test]
+fn narrowing_across_and() {
+    assert_ok(
+        r#"
+space N {
+  state {
+    items: list<number> = [1, 2, 3]
+    n: number = 0
+  }
+  action run() {
+    let found = list.find(items, fn(x: number) { x > 2 })
+    if found != nil and found > 1 { set n = found }
+  }
+}
+"#,
+    );
+}
+
+#[test]
+fn narrowing_across_or() {
+    assert_ok(
+        r#"
+space N {
+  state {
+    items: list<number> = [1, 2, 3]
+    n: number = 0
+  }
+  action run() {
+    let found = list.find(items, fn(x: number) { x > 2 })
+    if found == nil or found > 1 { set n = 0 } else { set n = found }
+  }
+}
+"#,
+    );
+}
+
+#[test]
+fn narrowing_negated_condition_and_else_branch() {
+    assert_ok(
+        r#"
+space N {
+  state {
+    items: list<number> = [1, 2, 3]
+    n: number = 0
+  }
+  action run() {
+    let found = list.find(items, fn(x: number) { x > 2 })
+    if not (found == nil) { set n = found }
+    if found == nil { set n = 0 } else { set n = found }
+  }
+}
+"#,
+    );
+}
+
+#[test]
+fn narrowing_after_early_return() {
+    assert_ok(
+        r#"
+space N {
+  state {
+    items: list<number> = [1, 2, 3]
+    n: number = 0
+  }
+  action run() {
+    let found = list.find(items, fn(x: number) { x > 2 })
+    if found == nil {
+      set n = 0
+      return
+    }
+    set n = found + 1
+  }
+}
+"#,
+    );
+}
+
+#[test]
+fn narrowing_after_early_return_from_or() {
+    assert_ok(
+        r#"
+space N {
+  state {
+    items: list<number> = [1, 2, 3]
+    n: number = 0
+    label: string = ""
+    user: { name: string, nickname?: string } = { name: "a" }
+  }
+  action run() {
+    let found = list.find(items, fn(x: number) { x > 2 })
+    if user.nickname == nil or found == nil { return }
+    set label = user.nickname
+    set n = found
+  }
+}
+"#,
+    );
+}
+
+#[test]
+fn narrowing_after_assert() {
+    assert_ok(
+        r#"
+space N {
+  state {
+    items: list<number> = [1, 2, 3]
+    n: number = 0
+  }
+  action run() {
+    let found = list.find(items, fn(x: number) { x > 2 })
+    assert found != nil
+    set n = found
+  }
+}
+"#,
+    );
+}
+
+#[test]
+fn narrowing_field_path() {
+    assert_ok(
+        r#"
+space N {
+  state {
+    items: list<number> = [1, 2, 3]
+    n: number = 0
+    label: string = ""
+    user: { name: string, nickname?: string } = { name: "a" }
+  }
+  action run() {
+    let found = list.find(items, fn(x: number) { x > 2 })
+    if user.nickname != nil { set label = user.nickname }
+  }
+}
+"#,
+    );
+}
+
+#[test]
+fn narrowing_nil_coalesce_condition() {
+    assert_ok(
+        r#"
+space N {
+  state {
+    items: list<number> = [1, 2, 3]
+    n: number = 0
+    on: bool = false
+    user: { name: string, active?: bool } = { name: "a" }
+  }
+  action run() {
+    let found = list.find(items, fn(x: number) { x > 2 })
+    if user.active ?? false { set on = user.active }
+    if not (user.active ?? true) { set on = user.active }
+  }
+}
+"#,
+    );
+}
+
+#[test]
+fn nil_coalesce_with_nullable_fallback_stays_nullable() {
+    assert_ok(
+        r#"
+space N {
+  state {
+    items: list<number> = [1, 2, 3]
+    n: number = 0
+    user: { name: string, nickname?: string } = { name: "a" }
+  }
+  action run() {
+    let found = list.find(items, fn(x: number) { x > 2 })
+    set n = found ?? user.nickname ?? 0
+  }
+}
+"#,
+    );
+    assert_error(
+        r#"
+space N {
+  state {
+    items: list<number> = [1, 2, 3]
+    n: number = 0
+  }
+  action run() {
+    let found = list.find(items, fn(x: number) { x > 2 })
+    set n = found ?? list.find(items, fn(x: number) { x > 1 })
+  }
+}
+"#,
+        ErrorCode::TYPE_MISMATCH,
+    );
+}
+
+#[test]
+fn narrowing_match_on_nullable_subject() {
+    assert_ok(
+        r#"
+space N {
+  type Shape = | Circle(radius: number) | Square(side: number)
+  state {
+    items: list<number> = [1, 2, 3]
+    n: number = 0
+    user: { name: string, shape?: Shape } = { name: "a" }
+  }
+  action run() {
+    let found = list.find(items, fn(x: number) { x > 2 })
+    match user.shape {
+      Circle(r) -> { set n = r },
+      Square(side) -> { set n = side },
+      _ -> { set n = 0 },
+    }
+  }
+}
+"#,
+    );
+}
+
+#[test]
+fn match_on_nullable_subject_needs_wildcard() {
+    assert_error(
+        r#"
+space N {
+  type Shape = | Circle(radius: number) | Square(side: number)
+  state {
+    items: list<number> = [1, 2, 3]
+    n: number = 0
+    user: { name: string, shape?: Shape } = { name: "a" }
+  }
+  action run() {
+    let found = list.find(items, fn(x: number) { x > 2 })
+    match user.shape {
+      Circle(r) -> { set n = r },
+      Square(side) -> { set n = side },
+    }
+  }
+}
+"#,
+        ErrorCode::NON_EXHAUSTIVE_MATCH,
+    );
+}
+
+#[test]
+fn narrowing_does_not_leak_out_of_if() {
+    assert_error(
+        r#"
+space N {
+  state {
+    items: list<number> = [1, 2, 3]
+    n: number = 0
+  }
+  action run() {
+    let found = list.find(items, fn(x: number) { x > 2 })
+    if found != nil { set n = found }
+    set n = found
+  }
+}
+"#,
+        ErrorCode::TYPE_MISMATCH,
+    );
+}
+
+#[test]
+fn narrowing_forgotten_after_set() {
+    assert_error(
+        r#"
+space N {
+  state {
+    items: list<number> = [1, 2, 3]
+    n: number = 0
+    label: string = ""
+    user: { name: string, nickname?: string } = { name: "a" }
+  }
+  action run() {
+    let found = list.find(items, fn(x: number) { x > 2 })
+    if user.nickname != nil {
+      set user = { name: "b" }
+      set label = user.nickname
+    }
+  }
+}
+"#,
+        ErrorCode::TYPE_MISMATCH,
+    );
+}
+
+#[test]
+fn narrowing_forgotten_in_loop_that_assigns() {
+    assert_error(
+        r#"
+space N {
+  state {
+    items: list<number> = [1, 2, 3]
+    n: number = 0
+    label: string = ""
+    user: { name: string, nickname?: string } = { name: "a" }
+  }
+  action run() {
+    let found = list.find(items, fn(x: number) { x > 2 })
+    if user.nickname != nil {
+      for i in items {
+        set label = user.nickname
+        set user = { name: "c" }
+      }
+    }
+  }
+}
+"#,
+        ErrorCode::TYPE_MISMATCH,
+    );
+}
+
+// ══════════════════════════════════════════════════════════════════════════════
+// Lambda parameter inference
+// ══════════════════════════════════════════════════════════════════════════════
+
+/// The single error `source` produces.
+fn single_error(source: &str) -> PeplError {
+    let errors = check(source);
+    assert_eq!(errors.errors.len(), 1, "{:#?}", errors.errors);
+    errors.errors[0].clone()
+}
+
+#[test]
+fn lambda_param_inferred_from_list_element() {
+    assert_ok(
+        r#"
+space L {
+  state {
+    items: list<{ name: string, price: number }> = []
+    prices: list<number> = []
+    names: list<string> = []
+  }
+  action run() {
+    set prices = list.map(items, fn(x) { x.price * 2 })
+    set names = list.map(list.filter(items, fn(x) { x.price > 10 }), fn(x) { x.name })
+  }
+}
+"#,
+    );
+}
+
+#[test]
+fn lambda_param_inferred_in_method_call() {
+    assert_ok(
+        r#"
+space L {
+  state {
+    items: list<{ name: string, price: number }> = []
+    prices: list<number> = []
+  }
+  action run() {
+    set prices = items.filter(fn(item) { item.price > 0 }).map(fn(item) { item.price })
+  }
+}
+"#,
+    );
+}
+
+#[test]
+fn lambda_reduce_accumulator_takes_initial_type() {
+    assert_ok(
+        r#"
+space L {
+  state {
+    items: list<{ name: string, price: number }> = []
+    total: number = 0
+  }
+  action run() {
+    set total = list.reduce(items, 0, fn(acc, x) { acc + x.price })
+  }
+}
+"#,
+    );
+    let e = single_error(
+        r#"
+space L {
+  state {
+    items: list<{ name: string, price: number }> = []
+    total: number = 0
+  }
+  action run() {
+    set total = list.reduce(items, 0, fn(acc, x) { acc + x.name })
+  }
+}
+"#,
+    );
+    assert_eq!(e.code, ErrorCode::TYPE_MISMATCH);
+    assert!(e.message.contains("string"), "{}", e.message);
+}
+
+#[test]
+fn lambda_inferred_param_mismatch_points_into_body() {
+    let e = single_error(
+        r#"
+space L {
+  state {
+    items: list<{ name: string, price: number }> = []
+    prices: list<number> = []
+  }
+  action run() {
+    set prices = list.map(items, fn(x) { x.cost })
+  }
+}
+"#,
+    );
+    assert_eq!(e.code, ErrorCode::TYPE_MISMATCH);
+    assert!(e.message.contains("no field 'cost'"), "{}", e.message);
+    // `cost` in `    set prices = list.map(items, fn(x) { x.cost })`
+    assert_eq!((e.span.start_line, e.span.start_col), (8, 44));
+}
+
+#[test]
+fn list_map_result_takes_the_lambda_result_type() {
+    let e = single_error(
+        r#"
+space L {
+  state {
+    items: list<{ name: string, price: number }> = []
+    prices: list<number> = []
+  }
+  action run() {
+    set prices = list.map(items, fn(x) { x.name })
+  }
+}
+"#,
+    );
+    assert_eq!(e.code, ErrorCode::TYPE_MISMATCH);
+    assert!(e.message.contains("list<string>"), "{}", e.message);
+}
+
+#[test]
+fn lambda_param_inferred_through_a_chained_call() {
+    let e = single_error(
+        r#"
+space L {
+  state {
+    items: list<{ name: string, price: number }> = []
+    prices: list<number> = []
+  }
+  action run() {
+    set prices = items.filter(fn(item) { item.price > 0 }).map(fn(item) { item.cost })
+  }
+}
+"#,
+    );
+    assert_eq!(e.code, ErrorCode::TYPE_MISMATCH);
+    assert!(e.message.contains("no field 'cost'"), "{}", e.message);
+    // `cost` in `    set prices = items.filter(...).map(fn(item) { item.cost })`
+    assert_eq!((e.span.start_line, e.span.start_col), (8, 80));
+}
+
+#[test]
+fn lambda_wrong_return_type_points_at_result() {
+    let e = single_error(
+        r#"
+space L {
+  state {
+    names: list<string> = []
+  }
+  action run() {
+    set names = list.filter(names, fn(s) { string.length(s) })
+  }
+}
+"#,
+    );
+    assert_eq!(e.code, ErrorCode::TYPE_MISMATCH);
+    assert_eq!(e.message, "lambda should return bool, got number");
+    assert_eq!(e.span.start_col, 44);
+}
+
+#[test]
+fn lambda_annotation_conflicting_with_element_type() {
+    let e = single_error(
+        r#"
+space L {
+  state {
+    prices: list<number> = []
+  }
+  action run() {
+    set prices = list.filter(prices, fn(p: string) { p == "a" })
+  }
+}
+"#,
+    );
+    assert_eq!(e.code, ErrorCode::TYPE_MISMATCH);
+    assert_eq!(
+        e.message,
+        "parameter 'p' is annotated string, but receives number"
+    );
+}
+
+#[test]
+fn lambda_wrong_parameter_count() {
+    assert_error(
+        r#"
+space L {
+  state {
+    prices: list<number> = []
+  }
+  action run() {
+    set prices = list.map(prices, fn(a, b) { a + b })
+  }
+}
+"#,
+        ErrorCode::WRONG_ARG_COUNT,
+    );
+}
+
+#[test]
+fn lambda_without_context_needs_annotations() {
+    assert_error(
+        r#"
+space L {
+  state {
+    count: number = 0
+  }
+  action run() {
+    let f = fn(x) { x + 1 }
+  }
+}
+"#,
+        ErrorCode::UNKNOWN_TYPE,
+    );
+    assert_ok(
+        r#"
+space L {
+  state {
+    count: number = 0
+  }
+  action run() {
+    let f = fn(x: number) { x + 1 }
+  }
+}
+"#,
+    );
+}
@@ -0,0 +1,1156 @@
+//! Closure-compiled fast path for the reference evaluator.
+//!
+//! [`CompiledSpace`] lowers a space's state defaults, actions, game-loop
+//! handlers, derived fields and invariants into trees of closures once, up
+//! front, instead of re-walking the AST on every dispatch:
+//!
+//! - names are resolved to slots at compile time — locals index a per-call
+//!   frame, state / credential / derived fields index a global table
+//! - the global table is copy-on-write (`Arc` of `Arc`ed values), so the
+//!   pre-action snapshot used for rollback is a pointer copy, and the first
+//!   write after it copies the table of pointers, never the values
+//!
+//! Value operations, stdlib dispatch and gas accounting are shared with
+//! [`Evaluator`], and every compiled expression and statement is charged
+//! from the [`pepl_types::gas`] table exactly where the tree-walker charges
+//! it. A `CompiledSpace` and a
+//! [`SpaceInstance`](crate::SpaceInstance) fed the same inputs end in the
+//! same state having used the same gas; that is what makes the fast path
+//! usable for long simulations and model checking.
+//!
+//! Scoping follows the tree-walker too: `if` blocks bind into the enclosing
+//! scope, `for` bodies keep one scope across iterations, and a name that is
+//! not yet bound in an inner scope falls back to the next one out.
+//!
+//! Views are not compiled — render through `SpaceInstance`. Failures carry
+//! the same [`EvalError`]s but no span or PEPL call stack; re-run the inputs
+//! on the tree-walker to get a traced error.
+
+use crate::error::{EvalError, EvalResult};
+use crate::evaluator::{apply_unary, field_of, for_items, spread_into, Evaluator};
+use crate::explain::{explain_invariant, InvariantExplanation, SetWrite};
+use crate::space::ActionResult;
+use crate::test_runner::MockResponse;
+use pepl_stdlib::{StdlibError, StdlibFn, Value};
+use pepl_types::ast::*;
+use pepl_types::derived::RecomputePlan;
+use pepl_types::gas;
+use std::collections::BTreeMap;
+use std::ops::Range;
+use std::sync::Arc;
+
+/// State, credential and derived fields by slot. `None` until the field is
+/// first defined (derived fields before their first computation).
+type Globals = Arc<Vec<Option<Arc<Value>>>>;
+
+/// A compiled expression, statement or block.
+type Code = Arc<dyn Fn(&mut Machine<'_>) -> EvalResult<Value> + Send + Sync>;
+
+// ══════════════════════════════════════════════════════════════════════════════
+// Runtime
+// ══════════════════════════════════════════════════════════════════════════════
+
+/// Where a name may be bound, innermost first. Reads and writes use the
+/// first place that is currently bound.
+#[derive(Debug, Clone, Copy)]
+enum Place {
+    Local(usize),
+    Global(usize),
+}
+
+/// Execution state of one compiled entry point.
+struct Machine<'a> {
+    /// Gas, log output, mocks and value operations.
+    eval: &'a mut Evaluator,
+    globals: &'a mut Globals,
+    /// Local slots; `None` when not bound in the current scope instance.
+    locals: Vec<Option<Value>>,
+    /// Indices into [`CompiledProgram::writes`] of executed `set`s.
+    writes: &'a mut Vec<usize>,
+}
+
+impl Machine<'_> {
+    fn read(&self, places: &[Place]) -> Option<Value> {
+        places.iter().find_map(|place| match *place {
+            Place::Local(slot) => self.locals[slot].clone(),
+            Place::Global(slot) => self.globals[slot].as_deref().cloned(),
+        })
+    }
+
+    /// Overwrite the first bound place. Returns `false` if none is bound.
+    fn write(&mut self, places: &[Place], value: Value) -> bool {
+        for place in places {
+            match *place {
+                Place::Local(slot) if self.locals[slot].is_some() => {
+                    self.locals[slot] = Some(value);
+                    return true;
+                }
+                Place::Global(slot) if self.globals[slot].is_some() => {
+                    Arc::make_mut(self.globals)[slot] = Some(Arc::new(value));
+                    return true;
+                }
+                _ => {}
+            }
+        }
+        false
+    }
+
+    /// Enter a fresh instance of the scope owning `slots`.
+    fn enter_scope(&mut self, slots: &Range<usize>) {
+        self.locals[slots.clone()].fill(None);
+    }
+
+    fn eval_all(&mut self, codes: &[Code]) -> EvalResult<Vec<Value>> {
+        codes.iter().map(|code| code(self)).collect()
+    }
+}
+
+// ══════════════════════════════════════════════════════════════════════════════
+// Compilation
+// ══════════════════════════════════════════════════════════════════════════════
+
+/// A compiled entry point and the number of local slots it needs.
+struct Unit {
+    code: Code,
+    frame_size: usize,
+}
+
+/// A compiled action, `update` or `handleEvent` with its parameter slots.
+struct Handler {
+    params: Vec<usize>,
+    unit: Unit,
+    /// Indices into [`CompiledProgram::derived`] to recompute after it runs.
+    recompute: Vec<usize>,
+}
+
+/// A compiled `match` arm.
+struct Arm {
+    pattern: Pattern,
+    /// Slots of the pattern's bindings.
+    bindings: Vec<(String, usize)>,
+    body: Code,
+    scope: Range<usize>,
+}
+
+/// Everything compiled from one program; shared between forks of a space.
+struct CompiledProgram {
+    /// Global slot of every state, credential and derived field.
+    global_index: BTreeMap<String, usize>,
+    state_fields: Vec<(String, usize)>,
+    state_defaults: Vec<(usize, Unit)>,
+    credentials: Vec<usize>,
+    /// Derived fields in declaration order.
+    derived: Vec<(usize, Unit)>,
+    /// Indices into `derived` in recompute order.
+    derived_order: Vec<usize>,
+    invariants: Vec<(InvariantDecl, Unit)>,
+    actions: Vec<(String, Handler)>,
+    update: Option<Handler>,
+    handle_event: Option<Handler>,
+    /// Every `set` statement in the program, in compile order.
+    writes: Vec<SetWrite>,
+    /// State then derived field names (the fields an explanation reports).
+    field_names: Vec<String>,
+}
+
+impl CompiledProgram {
+    fn compile(program: &Program) -> Self {
+        let body = &program.space.body;
+        let derived_fields: Vec<DerivedField> = body
+            .derived
+            .as_ref()
+            .map(|d| d.fields.clone())
+            .unwrap_or_default();
+        let credential_fields = body
+            .credentials
+            .as_ref()
+            .map(|c| c.fields.as_slice())
+            .unwrap_or_default();
+
+        let mut global_index = BTreeMap::new();
+        let names = body
+            .state
+            .fields
+            .iter()
+            .map(|f| &f.name.name)
+            .chain(credential_fields.iter().map(|f| &f.name.name))
+            .chain(derived_fields.iter().map(|f| &f.name.name));
+        for name in names {
+            let next = global_index.len();
+            global_index.entry(name.clone()).or_insert(next);
+        }
+
+        let plan = RecomputePlan::new(body);
+        let mut writes = Vec::new();
+        let mut compiler = Compiler {
+            globals: &global_index,
+            writes: &mut writes,
+            scopes: Vec::new(),
+            next_slot: 0,
+        };
+
+        let state_defaults = body
+            .state
+            .fields
+            .iter()
+            .map(|f| (global_index[&f.name.name], compiler.expr_unit(&f.default)))
+            .collect();
+        let derived = derived_fields
+            .iter()
+            .map(|f| (global_index[&f.name.name], compiler.expr_unit(&f.value)))
+            .collect();
+        let invariants = body
+            .invariants
+            .iter()
+            .map(|inv| (inv.clone(), compiler.expr_unit(&inv.condition)))
+            .collect();
+        let actions = body
+            .actions
+            .iter()
+            .zip(&plan.actions)
+            .map(|(a, recompute)| {
+                let handler = compiler.handler(&a.params, &a.body, recompute);
+                (a.name.name.clone(), handler)
+            })
+            .collect();
+        let update = body.update.as_ref().map(|u| {
+            compiler.handler(std::slice::from_ref(&u.param), &u.body, &plan.update)
+        });
+        let handle_event = body.handle_event.as_ref().map(|h| {
+            compiler.handler(std::slice::from_ref(&h.param), &h.body, &plan.handle_event)
+        });
+
+        Self {
+            state_fields: body
+                .state
+                .fields
+                .iter()
+                .map(|f| (f.name.name.clone(), global_index[&f.name.name]))
+                .collect(),
+            credentials: credential_fields
+                .iter()
+                .map(|f| global_index[&f.name.name])
+                .collect(),
+            field_names: body
+                .state
+                .fields
+                .iter()
+                .map(|f| f.name.name.clone())
+                .chain(derived_fields.iter().map(|f| f.name.name.clone()))
+                .collect(),
+            global_index,
+            state_defaults,
+            derived,
+            derived_order: plan.all,
+            invariants,
+            actions,
+            update,
+            handle_event,
+            writes,
+        }
+    }
+}
+
+/// Lowers AST nodes to [`Code`], resolving names against a compile-time
+/// mirror of the evaluator's scope stack.
+struct Compiler<'p> {
+    globals: &'p BTreeMap<String, usize>,
+    writes: &'p mut Vec<SetWrite>,
+    /// Names bound in each open scope. Every `let` a scope will execute is
+    /// declared when the scope opens, so lookups see later bindings too and
+    /// the runtime falls through while they are still unbound.
+    scopes: Vec<BTreeMap<String, usize>>,
+    next_slot: usize,
+}
+
+impl Compiler<'_> {
+    fn expr_unit(&mut self, expr: &Expr) -> Unit {
+        self.next_slot = 0;
+        let mut names = Vec::new();
+        hoist_expr(expr, &mut names);
+        let start = self.open_scope(names);
+        let code = self.expr(expr);
+        self.close_scope(start);
+        Unit {
+            code,
+            frame_size: self.next_slot,
+        }
+    }
+
+    fn handler(&mut self, params: &[Param], body: &Block, recompute: &[usize]) -> Handler {
+        self.next_slot = 0;
+        let mut names: Vec<String> = params.iter().map(|p| p.name.name.clone()).collect();
+        hoist_block(body, &mut names);
+        let start = self.open_scope(names);
+        let params = params.iter().map(|p| self.slot(&p.name.name)).collect();
+        let code = self.block(body);
+        self.close_scope(start);
+        Handler {
+            params,
+            unit: Unit {
+                code,
+                frame_size: self.next_slot,
+            },
+            recompute: recompute.to_vec(),
+        }
+    }
+
+    // ── Scopes ───────────────────────────────────────────────────────────
+
+    fn open_scope(&mut self, names: Vec<String>) -> usize {
+        let start = self.next_slot;
+        let mut scope = BTreeMap::new();
+        for name in names {
+            scope.entry(name).or_insert_with(|| {
+                self.next_slot += 1;
+                self.next_slot - 1
+            });
+        }
+        self.scopes.push(scope);
+        start
+    }
+
+    /// Close the innermost scope; returns every slot allocated while it was
+    /// open (nested scopes included).
+    fn close_scope(&mut self, start: usize) -> Range<usize> {
+        self.scopes.pop();
+        start..self.next_slot
+    }
+
+    /// Slot of a name declared in the innermost scope.
+    fn slot(&self, name: &str) -> usize {
+        self.scopes.last().expect("open scope")[name]
+    }
+
+    fn resolve(&self, name: &str) -> Arc<[Place]> {
+        self.scopes
+            .iter()
+            .rev()
+            .filter_map(|scope| scope.get(name).map(|&slot| Place::Local(slot)))
+            .chain(self.globals.get(name).map(|&slot| Place::Global(slot)))
+            .collect()
+    }
+
+    // ── Expressions ──────────────────────────────────────────────────────
+
+    /// The expression's gas, then the expression.
+    fn expr(&mut self, expr: &Expr) -> Code {
+        let cost = gas::expr_cost(&expr.kind);
+        let code = self.expr_kind(expr);
+        Arc::new(move |m| {
+            m.eval.charge(cost)?;
+            code(m)
+        })
+    }
+
+    fn exprs(&mut self, exprs: &[Expr]) -> Vec<Code> {
+        exprs.iter().map(|e| self.expr(e)).collect()
+    }
+
+    fn expr_kind(&mut self, expr: &Expr) -> Code {
+        match &expr.kind {
+            ExprKind::NumberLit(n) => {
+                let n = *n;
+                Arc::new(move |_| Ok(Value::Number(n)))
+            }
+            ExprKind::StringLit(s) => {
+                let s = s.clone();
+                Arc::new(move |_| Ok(Value::String(s.clone())))
+            }
+            ExprKind::BoolLit(b) => {
+                let b = *b;
+                Arc::new(move |_| Ok(Value::Bool(b)))
+            }
+            ExprKind::NilLit => Arc::new(|_| Ok(Value::Nil)),
+
+            ExprKind::StringInterpolation(parts) => {
+                let parts: Vec<Result<String, Code>> = parts
+                    .iter()
+                    .map(|part| match part {
+                        StringPart::Literal(s) => Ok(s.clone()),
+                        StringPart::Expr(e) => Err(self.expr(e)),
+                    })
+                    .collect();
+                Arc::new(move |m| {
+                    let mut result = String::new();
+                    for part in &parts {
+                        match part {
+                            Ok(s) => result.push_str(s),
+                            Err(code) => {
+                                let val = code(m)?;
+                                result.push_str(&m.eval.value_to_display_string(&val));
+                            }
+                        }
+                    }
+                    Ok(Value::String(result))
+                })
+            }
+            ExprKind::ListLit(elems) => {
+                let elems = self.exprs(elems);
+                Arc::new(move |m| Ok(Value::List(m.eval_all(&elems)?)))
+            }
+            ExprKind::RecordLit(entries) => {
+                let entries: Vec<(Option<String>, Code)> = entries
+                    .iter()
+                    .map(|entry| match entry {
+                        RecordEntry::Field { name, value } => {
+                            (Some(name.name.clone()), self.expr(value))
+                        }
+                        RecordEntry::Spread(value) => (None, self.expr(value)),
+                    })
+                    .collect();
+                Arc::new(move |m| {
+                    let mut fields = BTreeMap::new();
+                    for (name, code) in &entries {
+                        let val = code(m)?;
+                        match name {
+                            Some(name) => {
+                                fields.insert(name.clone(), val);
+                            }
+                            None => spread_into(&mut fields, val)?,
+                        }
+                    }
+                    Ok(Value::Record {
+                        type_name: None,
+                        fields,
+                    })
+                })
+            }
+
+            ExprKind::Identifier(name) => {
+                let places = self.resolve(name);
+                let name = name.clone();
+                Arc::new(move |m| {
+                    m.read(&places)
+                        .ok_or_else(|| EvalError::UndefinedVariable(name.clone()))
+                })
+            }
+
+            ExprKind::Call { name, args } => {
+                let places = self.resolve(&name.name);
+                let name = name.name.clone();
+                let args = self.exprs(args);
+                Arc::new(move |m| match m.read(&places) {
+                    Some(Value::Function(f)) => {
+                        let arg_vals = m.eval_all(&args)?;
+                        m.eval.call_function(&f, arg_vals)
+                    }
+                    _ => Err(EvalError::UnknownFunction(format!(
+                        "unknown function '{name}'"
+                    ))),
+                })
+            }
+            ExprKind::QualifiedCall {
+                module,
+                function,
+                args,
+            } => {
+                let module = module.name.clone();
+                let function = function.name.clone();
+                let args = self.exprs(args);
+                Arc::new(move |m| {
+                    let arg_vals = m.eval_all(&args)?;
+                    m.eval.call_stdlib(&module, &function, arg_vals)
+                })
+            }
+            ExprKind::FieldAccess { object, field } => {
+                let object = self.expr(object);
+                let field = field.name.clone();
+                Arc::new(move |m| field_of(&object(m)?, &field))
+            }
+            ExprKind::MethodCall {
+                object,
+                method,
+                args,
+            } => {
+                let object = self.expr(object);
+                let method = method.name.clone();
+                let args = self.exprs(args);
+                Arc::new(move |m| {
+                    let mut all_args = vec![object(m)?];
+                    for arg in &args {
+                        all_args.push(arg(m)?);
+                    }
+                    m.eval.call_method(&method, all_args)
+                })
+            }
+
+            ExprKind::Binary { left, op, right } => {
+                let op = *op;
+                let left = self.expr(left);
+                let right = self.expr(right);
+                match op {
+                    BinOp::And => Arc::new(move |m| {
+                        if !left(m)?.is_truthy() {
+                            Ok(Value::Bool(false))
+                        } else {
+                            Ok(Value::Bool(right(m)?.is_truthy()))
+                        }
+                    }),
+                    BinOp::Or => Arc::new(move |m| {
+                        if left(m)?.is_truthy() {
+                            Ok(Value::Bool(true))
+                        } else {
+                            Ok(Value::Bool(right(m)?.is_truthy()))
+                        }
+                    }),
+                    _ => Arc::new(move |m| {
+                        let lv = left(m)?;
+                        let rv = right(m)?;
+                        m.eval.apply_binary(op, &lv, &rv)
+                    }),
+                }
+            }
+            ExprKind::Unary { op, operand } => {
+                let op = *op;
+                let operand = self.expr(operand);
+                Arc::new(move |m| apply_unary(op, operand(m)?))
+            }
+            ExprKind::ResultUnwrap(inner) => {
+                let inner = self.expr(inner);
+                Arc::new(move |m| {
+                    let val = inner(m)?;
+                    m.eval.unwrap_result(val)
+                })
+            }
+            ExprKind::NilCoalesce { left, right } => {
+                let left = self.expr(left);
+                let right = self.expr(right);
+                Arc::new(move |m| {
+                    let lv = left(m)?;
+                    if lv == Value::Nil {
+                        right(m)
+                    } else {
+                        Ok(lv)
+                    }
+                })
+            }
+
+            ExprKind::If(if_expr) => self.if_expr(if_expr),
+            ExprKind::For(for_expr) => self.for_expr(for_expr),
+            ExprKind::Match(match_expr) => self.match_expr(match_expr),
+            ExprKind::Lambda(lambda) => self.lambda(lambda),
+            ExprKind::Paren(inner) => self.expr(inner),
+        }
+    }
+
+    // ── Control flow ─────────────────────────────────────────────────────
+
+    fn if_expr(&mut self, if_expr: &IfExpr) -> Code {
+        let cond = self.expr(&if_expr.condition);
+        let then_block = self.block(&if_expr.then_block);
+        let else_branch = if_expr.else_branch.as_ref().map(|branch| match branch {
+            ElseBranch::ElseIf(elif) => {
+                let elif = self.if_expr(elif);
+                Arc::new(move |m: &mut Machine<'_>| {
+                    m.eval.charge(gas::ELSE_IF)?;
+                    elif(m)
+                }) as Code
+            }
+            ElseBranch::Block(block) => self.block(block),
+        });
+        Arc::new(move |m| {
+            if cond(m)?.is_truthy() {
+                then_block(m)
+            } else if let Some(else_branch) = &else_branch {
+                else_branch(m)
+            } else {
+                Ok(Value::Nil)
+            }
+        })
+    }
+
+    fn for_expr(&mut self, for_expr: &ForExpr) -> Code {
+        let iterable = self.expr(&for_expr.iterable);
+
+        let mut names = vec![for_expr.item.name.clone()];
+        names.extend(for_expr.index.as_ref().map(|i| i.name.clone()));
+        hoist_block(&for_expr.body, &mut names);
+        let start = self.open_scope(names);
+        let item = self.slot(&for_expr.item.name);
+        let index = for_expr.index.as_ref().map(|i| self.slot(&i.name));
+        let body = self.block(&for_expr.body);
+        let scope = self.close_scope(start);
+
+        Arc::new(move |m| {
+            let items = for_items(iterable(m)?)?;
+            // One scope for the whole loop, as in the tree-walker
+            m.enter_scope(&scope);
+            let mut last = Value::Nil;
+            for (i, value) in items.into_iter().enumerate() {
+                m.eval.charge(gas::FOR_ITERATION)?;
+                m.locals[item] = Some(value);
+                if let Some(index) = index {
+                    m.locals[index] = Some(Value::Number(i as f64));
+                }
+                last = body(m)?;
+            }
+            Ok(last)
+        })
+    }
+
+    fn match_expr(&mut self, match_expr: &MatchExpr) -> Code {
+        let subject = self.expr(&match_expr.subject);
+        let arms: Vec<Arm> = match_expr
+            .arms
+            .iter()
+            .map(|arm| {
+                let mut names = match &arm.pattern {
+                    Pattern::Variant { bindings, .. } => {
+                        bindings.iter().map(|b| b.name.clone()).collect()
+                    }
+                    Pattern::Wildcard(_) => Vec::new(),
+                };
+                let bindings = names.clone();
+                match &arm.body {
+                    MatchArmBody::Expr(expr) => hoist_expr(expr, &mut names),
+                    MatchArmBody::Block(block) => hoist_block(block, &mut names),
+                }
+                let start = self.open_scope(names);
+                let bindings = bindings
+                    .into_iter()
+                    .map(|name| {
+                        let slot = self.slot(&name);
+                        (name, slot)
+                    })
+                    .collect();
+                let body = match &arm.body {
+                    MatchArmBody::Expr(expr) => self.expr(expr),
+                    MatchArmBody::Block(block) => self.block(block),
+                };
+                let scope = self.close_scope(start);
+                Arm {
+                    pattern: arm.pattern.clone(),
+                    bindings,
+                    body,
+                    scope,
+                }
+            })
+            .collect();
+
+        Arc::new(move |m| {
+            let subject = subject(m)?;
+            for arm in &arms {
+                if let Some(bound) = m.eval.match_pattern(&arm.pattern, &subject) {
+                    m.enter_scope(&arm.scope);
+                    for (name, value) in bound {
+                        if let Some((_, slot)) = arm.bindings.iter().find(|(n, _)| *n == name) {
+                            m.locals[*slot] = Some(value);
+                        }
+                    }
+                    return (arm.body)(m);
+                }
+            }
+            Ok(Value::Nil)
+        })
+    }
+
+    /// A lambda captures the current frame and globals; each call runs the
+    /// body on a fresh evaluator with its own log and no mocks, charging the
+    /// caller's gas, like [`Evaluator::eval_lambda`].
+    fn lambda(&mut self, lambda: &LambdaExpr) -> Code {
+        let mut names: Vec<String> = lambda.params.iter().map(|p| p.name.name.clone()).collect();
+        hoist_block(&lambda.body, &mut names);
+        let start = self.open_scope(names);
+        let params: Arc<[usize]> = lambda
+            .params
+            .iter()
+            .map(|p| self.slot(&p.name.name))
+            .collect();
+        let body = self.block(&lambda.body);
+        let scope = self.close_scope(start);
+
+        Arc::new(move |m| {
+            let captured_locals = m.locals.clone();
+            let captured_globals = Arc::clone(m.globals);
+            let params = Arc::clone(&params);
+            let body = Arc::clone(&body);
+            let scope = scope.clone();
+            let lambda_gas = m.eval.lambda_gas();
+            let closure = StdlibFn(Arc::new(move |args: Vec<Value>| {
+                let mut eval = lambda_gas.evaluator();
+                let mut globals = Arc::clone(&captured_globals);
+                let mut writes = Vec::new();
+                let mut machine = Machine {
+                    eval: &mut eval,
+                    globals: &mut globals,
+                    locals: captured_locals.clone(),
+                    writes: &mut writes,
+                };
+                machine.enter_scope(&scope);
+                for (&slot, arg) in params.iter().zip(args) {
+                    machine.locals[slot] = Some(arg);
+                }
+                let result = body(&mut machine);
+                lambda_gas.settle(&eval);
+                result.map_err(|e| StdlibError::RuntimeError(e.to_string()))
+            }));
+            Ok(Value::Function(closure))
+        })
+    }
+
+    // ── Statements ───────────────────────────────────────────────────────
+
+    fn block(&mut self, block: &Block) -> Code {
+        let stmts: Vec<Code> = block.stmts.iter().map(|s| self.stmt(s)).collect();
+        Arc::new(move |m| {
+            let mut last = Value::Nil;
+            for stmt in &stmts {
+                last = stmt(m)?;
+            }
+            Ok(last)
+        })
+    }
+
+    /// The statement's gas, then the statement.
+    fn stmt(&mut self, stmt: &Stmt) -> Code {
+        let cost = gas::stmt_cost(stmt);
+        let code = self.stmt_kind(stmt);
+        Arc::new(move |m| {
+            m.eval.charge(cost)?;
+            code(m)
+        })
+    }
+
+    fn stmt_kind(&mut self, stmt: &Stmt) -> Code {
+        match stmt {
+            Stmt::Set(set) => self.set(set),
+            Stmt::Let(binding) => {
+                let value = self.expr(&binding.value);
+                let slot = binding.name.as_ref().map(|n| self.slot(&n.name));
+                Arc::new(move |m| {
+                    let val = value(m)?;
+                    if let Some(slot) = slot {
+                        m.locals[slot] = Some(val);
+                    }
+                    Ok(Value::Nil)
+                })
+            }
+            Stmt::If(if_expr) => self.if_expr(if_expr),
+            Stmt::For(for_expr) => self.for_expr(for_expr),
+            Stmt::Match(match_expr) => self.match_expr(match_expr),
+            Stmt::Return(_) => Arc::new(|_| Err(EvalError::Return(Value::Nil))),
+            Stmt::Assert(assert) => {
+                let cond = self.expr(&assert.condition);
+                let message = assert
+                    .message
+                    .clone()
+                    .unwrap_or_else(|| "assertion failed".into());
+                Arc::new(move |m| {
+                    if cond(m)?.is_truthy() {
+                        Ok(Value::Nil)
+                    } else {
+                        Err(EvalError::AssertionFailed(message.clone()))
+                    }
+                })
+            }
+            Stmt::Expr(expr_stmt) => self.expr(&expr_stmt.expr),
+        }
+    }
+
+    fn set(&mut self, set: &SetStmt) -> Code {
+        let value = self.expr(&set.value);
+        let root = set.target[0].name.clone();
+        let places = self.resolve(&root);
+        let path: Vec<Ident> = set.target[1..].to_vec();
+        let write = self.writes.len();
+        self.writes.push(SetWrite {
+            target: set
+                .target
+                .iter()
+                .map(|t| t.name.as_str())
+                .collect::<Vec<_>>()
+                .join("."),
+            span: set.span,
+        });
+
+        Arc::new(move |m| {
+            let val = value(m)?;
+            m.writes.push(write);
+            if path.is_empty() {
+                if !m.write(&places, val) {
+                    return Err(EvalError::UndefinedVariable(root.clone()));
+                }
+            } else {
+                let current = m
+                    .read(&places)
+                    .ok_or_else(|| EvalError::UndefinedVariable(root.clone()))?;
+                let updated = m.eval.set_nested_field(&current, &path, val)?;
+                m.write(&places, updated);
+            }
+            Ok(Value::Nil)
+        })
+    }
+}
+
+// ── Hoisting ─────────────────────────────────────────────────────────────────
+//
+// The names a scope binds: `let`s in its block, including those inside `if`
+// blocks (which share the enclosing scope), but not inside `for`, `match`
+// arms or lambdas (which open their own).
+
+fn hoist_block(block: &Block, out: &mut Vec<String>) {
+    for stmt in &block.stmts {
+        match stmt {
+            Stmt::Let(binding) => {
+                hoist_expr(&binding.value, out);
+                out.extend(binding.name.as_ref().map(|n| n.name.clone()));
+            }
+            Stmt::Set(set) => hoist_expr(&set.value, out),
+            Stmt::If(if_expr) => hoist_if(if_expr, out),
+            Stmt::For(for_expr) => hoist_expr(&for_expr.iterable, out),
+            Stmt::Match(match_expr) => hoist_expr(&match_expr.subject, out),
+            Stmt::Assert(assert) => hoist_expr(&assert.condition, out),
+            Stmt::Expr(expr_stmt) => hoist_expr(&expr_stmt.expr, out),
+            Stmt::Return(_) => {}
+        }
+    }
+}
+
+fn hoist_if(if_expr: &IfExpr, out: &mut Vec<String>) {
+    hoist_expr(&if_expr.condition, out);
+    hoist_block(&if_expr.then_block, out);
+    match &if_expr.else_branch {
+        Some(ElseBranch::ElseIf(elif)) => hoist_if(elif, out),
+        Some(ElseBranch::Block(block)) => hoist_block(block, out),
+        None => {}
+    }
+}
+
+fn hoist_expr(expr: &Expr, out: &mut Vec<String>) {
+    match &expr.kind {
+        ExprKind::If(if_expr) => hoist_if(if_expr, out),
+        ExprKind::For(for_expr) => hoist_expr(&for_expr.iterable, out),
+        ExprKind::Match(match_expr) => hoist_expr(&match_expr.subject, out),
+        ExprKind::StringInterpolation(parts) => {
+            for part in parts {
+                if let StringPart::Expr(e) = part {
+                    hoist_expr(e, out);
+                }
+            }
+        }
+        ExprKind::ListLit(exprs)
+        | ExprKind::Call { args: exprs, .. }
+        | ExprKind::QualifiedCall { args: exprs, .. } => {
+            exprs.iter().for_each(|e| hoist_expr(e, out));
+        }
+        ExprKind::RecordLit(entries) => {
+            for entry in entries {
+                match entry {
+                    RecordEntry::Field { value, .. } | RecordEntry::Spread(value) => {
+                        hoist_expr(value, out)
+                    }
+                }
+            }
+        }
+        ExprKind::MethodCall { object, args, .. } => {
+            hoist_expr(object, out);
+            args.iter().for_each(|e| hoist_expr(e, out));
+        }
+        ExprKind::Binary { left, right, .. } | ExprKind::NilCoalesce { left, right } => {
+            hoist_expr(left, out);
+            hoist_expr(right, out);
+        }
+        ExprKind::FieldAccess { object: inner, .. }
+        | ExprKind::Unary { operand: inner, .. }
+        | ExprKind::ResultUnwrap(inner)
+        | ExprKind::Paren(inner) => hoist_expr(inner, out),
+        ExprKind::NumberLit(_)
+        | ExprKind::StringLit(_)
+        | ExprKind::BoolLit(_)
+        | ExprKind::NilLit
+        | ExprKind::Identifier(_)
+        | ExprKind::Lambda(_) => {}
+    }
+}
+
+// ══════════════════════════════════════════════════════════════════════════════
+// CompiledSpace
+// ══════════════════════════════════════════════════════════════════════════════
+
+/// A rollback point for a [`CompiledSpace`]: the values of its state,
+/// credential and derived fields. Taking and restoring one is O(1).
+#[derive(Clone)]
+pub struct SpaceSnapshot {
+    globals: Globals,
+}
+
+/// Closure-compiled counterpart of [`SpaceInstance`](crate::SpaceInstance)
+/// for actions and game-loop handlers.
+///
+/// Cloning forks the space: the compiled code and the state table are
+/// shared until one side writes.
+pub struct CompiledSpace {
+    program: Arc<CompiledProgram>,
+    /// Gas, log output and mocks; its environment is only populated while
+    /// an invariant violation is explained.
+    eval: Evaluator,
+    globals: Globals,
+    /// `set` statements executed by the current entry point.
+    writes: Vec<usize>,
+}
+
+impl CompiledSpace {
+    /// Compile a parsed+validated Program and initialize its state.
+    pub fn new(program: &Program) -> EvalResult<Self> {
+        Self::with_gas_limit(program, gas::DEFAULT_LIMIT)
+    }
+
+    /// Create with a custom gas limit.
+    pub fn with_gas_limit(program: &Program, gas_limit: u64) -> EvalResult<Self> {
+        let compiled = Arc::new(CompiledProgram::compile(program));
+        let mut eval = Evaluator::new(gas_limit);
+        eval.action_names = compiled.actions.iter().map(|(n, _)| n.clone()).collect();
+
+        let mut space = Self {
+            globals: Arc::new(vec![None; compiled.global_index.len()]),
+            program: Arc::clone(&compiled),
+            eval,
+            writes: Vec::new(),
+        };
+
+        for (slot, unit) in &compiled.state_defaults {
+            let default = space.run(unit, Vec::new())?;
+            space.define(*slot, default);
+        }
+        for &slot in &compiled.credentials {
+            space.define(slot, Value::Nil);
+        }
+        let all = compiled.derived_order.clone();
+        space.recompute_derived(&all)?;
+
+        Ok(space)
+    }
+
+    // ══════════════════════════════════════════════════════════════════════
+    // State access
+    // ══════════════════════════════════════════════════════════════════════
+
+    /// Get the current value of a state, derived or credential field.
+    pub fn get_state(&self, name: &str) -> Option<&Value> {
+        let slot = *self.program.global_index.get(name)?;
+        self.globals[slot].as_deref()
+    }
+
+    /// Get all state as a snapshot.
+    pub fn state_snapshot(&self) -> BTreeMap<String, Value> {
+        self.program
+            .state_fields
+            .iter()
+            .filter_map(|(name, slot)| {
+                Some((name.clone(), self.globals[*slot].as_deref()?.clone()))
+            })
+            .collect()
+    }
+
+    /// Take an O(1) rollback point.
+    pub fn snapshot(&self) -> SpaceSnapshot {
+        SpaceSnapshot {
+            globals: Arc::clone(&self.globals),
+        }
+    }
+
+    /// Return to a point taken with [`snapshot`](Self::snapshot). Gas and
+    /// log output are not rolled back.
+    pub fn restore(&mut self, snapshot: &SpaceSnapshot) {
+        self.globals = Arc::clone(&snapshot.globals);
+    }
+
+    /// Gas consumed so far.
+    pub fn gas_used(&self) -> u64 {
+        self.eval.gas
+    }
+
+    /// Get captured log output.
+    pub fn log_output(&self) -> &[String] {
+        &self.eval.log_output
+    }
+
+    /// Clear log output.
+    pub fn clear_log(&mut self) {
+        self.eval.log_output.clear();
+    }
+
+    /// Set a declared credential (called by host before actions). Compiled
+    /// code has no slot for undeclared names, so those are ignored.
+    pub fn set_credential(&mut self, name: &str, value: Value) {
+        if let Some(&slot) = self.program.global_index.get(name) {
+            self.define(slot, value);
+        }
+    }
+
+    /// Install mock capability responses.
+    pub fn set_mock_responses(&mut self, mocks: Vec<MockResponse>) {
+        self.eval.mock_responses = mocks
+            .into_iter()
+            .map(|m| (m.module, m.function, m.response))
+            .collect();
+    }
+
+    fn define(&mut self, slot: usize, value: Value) {
+        Arc::make_mut(&mut self.globals)[slot] = Some(Arc::new(value));
+    }
+
+    // ══════════════════════════════════════════════════════════════════════
+    // Entry points
+    // ══════════════════════════════════════════════════════════════════════
+
+    /// Dispatch an action by name with arguments — an atomic transaction
+    /// with the same rollback semantics as [`SpaceInstance::dispatch`](crate::SpaceInstance::dispatch).
+    pub fn dispatch(&mut self, action_name: &str, args: Vec<Value>) -> EvalResult<ActionResult> {
+        let program = Arc::clone(&self.program);
+        let (_, action) = program
+            .actions
+            .iter()
+            .find(|(name, _)| name == action_name)
+            .ok_or_else(|| EvalError::UndefinedAction(action_name.to_string()))?;
+        self.run_handler(action, args)
+    }
+
+    /// Call `update(dt)` — game loop tick.
+    pub fn call_update(&mut self, dt: f64) -> EvalResult<ActionResult> {
+        let program = Arc::clone(&self.program);
+        let update = program
+            .update
+            .as_ref()
+            .ok_or_else(|| EvalError::Runtime("space has no update() declaration".into()))?;
+        self.run_handler(update, vec![Value::Number(dt)])
+    }
+
+    /// Call `handleEvent(event)` — game loop event handler.
+    pub fn call_handle_event(&mut self, event: Value) -> EvalResult<ActionResult> {
+        let program = Arc::clone(&self.program);
+        let handler = program
+            .handle_event
+            .as_ref()
+            .ok_or_else(|| EvalError::Runtime("space has no handleEvent() declaration".into()))?;
+        self.run_handler(handler, vec![event])
+    }
+
+    fn run_handler(&mut self, handler: &Handler, args: Vec<Value>) -> EvalResult<ActionResult> {
+        self.writes.clear();
+        let snapshot = Arc::clone(&self.globals);
+        let bound = handler.params.iter().copied().zip(args).collect();
+        match self.run(&handler.unit, bound) {
+            Ok(_) | Err(EvalError::Return(_)) => {}
+            Err(e) => return Err(e),
+        }
+        self.commit_or_rollback(snapshot, &handler.recompute)
+    }
+
+    fn run(&mut self, unit: &Unit, bound: Vec<(usize, Value)>) -> EvalResult<Value> {
+        let mut locals = vec![None; unit.frame_size];
+        for (slot, value) in bound {
+            locals[slot] = Some(value);
+        }
+        let mut machine = Machine {
+            eval: &mut self.eval,
+            globals: &mut self.globals,
+            locals,
+            writes: &mut self.writes,
+        };
+        (unit.code)(&mut machine)
+    }
+
+    // ══════════════════════════════════════════════════════════════════════
+    // Derived fields & invariants
+    // ══════════════════════════════════════════════════════════════════════
+
+    fn commit_or_rollback(
+        &mut self,
+        snapshot: Globals,
+        recompute: &[usize],
+    ) -> EvalResult<ActionResult> {
+        self.recompute_derived(recompute)?;
+
+        match self.check_invariants(&snapshot) {
+            None => Ok(ActionResult {
+                committed: true,
+                invariant_error: None,
+                explanation: None,
+            }),
+            Some((msg, explanation)) => {
+                // The snapshot holds the derived fields as they were too
+                self.globals = snapshot;
+                Ok(ActionResult {
+                    committed: false,
+                    invariant_error: Some(msg),
+                    explanation: Some(explanation),
+                })
+            }
+        }
+    }
+
+    fn recompute_derived(&mut self, fields: &[usize]) -> EvalResult<()> {
+        let program = Arc::clone(&self.program);
+        for &i in fields {
+            let (slot, unit) = &program.derived[i];
+            let val = self.run(unit, Vec::new())?;
+            self.define(*slot, val);
+        }
+        Ok(())
+    }
+
+    fn check_invariants(&mut self, before: &Globals) -> Option<(String, InvariantExplanation)> {
+        let program = Arc::clone(&self.program);
+        for (inv, unit) in &program.invariants {
+            let name = &inv.name.name;
+            let msg = match self.run(unit, Vec::new()) {
+                Ok(val) if val.is_truthy() => continue,
+                Ok(_) => format!("invariant '{name}' violated"),
+                Err(e) => format!("invariant '{name}' evaluation error: {e}"),
+            };
+            return Some((msg, self.explain(inv, before)));
+        }
+        None
+    }
+
+    /// Explain a violation with the tree-walker, which re-evaluates the
+    /// condition against the current fields (without charging the action
+    /// for it, as in [`SpaceInstance`](crate::SpaceInstance)).
+    fn explain(&mut self, inv: &InvariantDecl, before: &Globals) -> InvariantExplanation {
+        let before = self.bindings(before);
+        let after = self.bindings(&self.globals);
+        let writes: Vec<SetWrite> = std::mem::take(&mut self.writes)
+            .into_iter()
+            .map(|w| self.program.writes[w].clone())
+            .collect();
+
+        self.eval.env.restore_global(after);
+        let explanation = explain_invariant(
+            &mut self.eval,
+            inv,
+            &before,
+            &self.program.field_names,
+            &writes,
+        );
+        self.eval.clear_trace();
+        self.eval.env.restore_global(BTreeMap::new());
+        explanation
+    }
+
+    /// Bound globals by name.
+    fn bindings(&self, globals: &Globals) -> BTreeMap<String, Value> {
+        self.program
+            .global_index
+            .iter()
+            .filter_map(|(name, &slot)| Some((name.clone(), globals[slot].as_deref()?.clone())))
+            .collect()
+    }
+}
+
+impl Clone for CompiledSpace {
+    fn clone(&self) -> Self {
+        let mut eval = Evaluator::new(self.eval.gas_limit);
+        eval.gas = self.eval.gas;
+        eval.log_output = self.eval.log_output.clone();
+        eval.action_names = self.eval.action_names.clone();
+        eval.mock_responses = self.eval.mock_responses.clone();
+        Self {
+            program: Arc::clone(&self.program),
+            eval,
+            globals: Arc::clone(&self.globals),
+            writes: Vec::new(),
+        }
+    }
+}
@@ -1,5 +1,6 @@
 //! Runtime error types for the PEPL evaluator.
 
+use pepl_types::ErrorCode;
 use std::fmt;
 
 /// Evaluation error — runtime traps, assertion failures, invariant violations.
@@ -53,6 +54,26 @@ impl fmt::Display for EvalError {
     }
 }
 
+impl EvalError {
+    /// The E9xx runtime error code for this error.
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Self::ArithmeticTrap(_) => ErrorCode::ARITHMETIC_TRAP,
+            Self::AssertionFailed(_) => ErrorCode::ASSERTION_FAILED,
+            Self::InvariantViolation(_) => ErrorCode::INVARIANT_VIOLATED,
+            Self::NilAccess(_) => ErrorCode::NIL_ACCESS,
+            Self::UnwrapError(_) => ErrorCode::UNWRAP_ERR,
+            Self::UndefinedVariable(_) => ErrorCode::UNDEFINED_VARIABLE,
+            Self::UndefinedAction(_) => ErrorCode::UNDEFINED_ACTION,
+            Self::TypeMismatch(_) => ErrorCode::RUNTIME_TYPE_MISMATCH,
+            Self::StdlibError(_) => ErrorCode::STDLIB_FAILURE,
+            Self::UnknownFunction(_) => ErrorCode::UNKNOWN_FUNCTION,
+            Self::GasExhausted => ErrorCode::GAS_EXHAUSTED,
+            Self::Return(_) | Self::Runtime(_) => ErrorCode::RUNTIME_FAILURE,
+        }
+    }
+}
+
 impl std::error::Error for EvalError {}
 
 /// Result alias for evaluator operations.
@@ -2,16 +2,21 @@
 
 use crate::env::Environment;
 use crate::error::{EvalError, EvalResult};
+use crate::explain::SetWrite;
 use pepl_stdlib::modules::{convert, core, json, list, math, record, string, time, timer};
-use pepl_stdlib::{StdlibModule, Value, ResultValue};
+use pepl_stdlib::{StdlibFn, StdlibModule, Value, ResultValue};
 use pepl_types::ast::*;
+use pepl_types::gas;
+use pepl_types::{FrameKind, RuntimeError, RuntimeFrame, Span};
 use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
 
 /// The core evaluator — walks AST nodes and produces Values.
 pub struct Evaluator {
     /// Variable environment (scoped).
     pub env: Environment,
-    /// Gas counter — limits total steps to prevent infinite loops.
+    /// Gas counter, charged from the [`pepl_types::gas`] cost table.
     pub gas: u64,
     /// Gas limit.
     pub gas_limit: u64,
@@ -22,6 +27,18 @@ pub struct Evaluator {
     /// Mock capability responses (module, function) → response Value.
     /// Used by the test runner for `with_responses` blocks.
     pub mock_responses: Vec<(String, String, Value)>,
+    /// PEPL call stack (action, view, derived field, ...), outermost first.
+    pub frames: Vec<RuntimeFrame>,
+    /// `set` statements executed since the log was last cleared.
+    pub set_log: Vec<SetWrite>,
+    /// Innermost failure recorded while the current error unwinds.
+    trace: Option<RuntimeError>,
+    /// Failure recorded by a lambda body; lambdas run in their own
+    /// evaluator, so the closure reports back through this slot.
+    lambda_trace: Arc<Mutex<Option<RuntimeError>>>,
+    /// Gas counter handed to lambda bodies while a call that may run them
+    /// is in progress, so their work is charged to this evaluator.
+    gas_meter: Arc<AtomicU64>,
 }
 
 impl Evaluator {
@@ -34,12 +51,59 @@ impl Evaluator {
             log_output: Vec::new(),
             action_names: Vec::new(),
             mock_responses: Vec::new(),
+            frames: Vec::new(),
+            set_log: Vec::new(),
+            trace: None,
+            lambda_trace: Arc::new(Mutex::new(None)),
+            gas_meter: Arc::new(AtomicU64::new(0)),
         }
     }
 
-    /// Consume one unit of gas. Returns error if exhausted.
-    fn tick(&mut self) -> EvalResult<()> {
-        self.gas += 1;
+    /// Structured form of `err`: the recorded span and call stack if the
+    /// failure was traced, otherwise just the code and current frames.
+    pub fn runtime_error(&mut self, err: &EvalError) -> RuntimeError {
+        self.trace.take().unwrap_or_else(|| RuntimeError {
+            code: err.code(),
+            message: err.to_string(),
+            span: None,
+            frames: self.frames.clone(),
+        })
+    }
+
+    /// Forget any recorded failure (called when entering a new entry point).
+    pub fn clear_trace(&mut self) {
+        self.trace = None;
+        if let Ok(mut slot) = self.lambda_trace.lock() {
+            *slot = None;
+        }
+    }
+
+    /// Record the innermost failing location. Outer expressions see a trace
+    /// already set and leave it alone.
+    pub(crate) fn record_failure(&mut self, err: &EvalError, span: Span) {
+        if self.trace.is_some() || matches!(err, EvalError::Return(_)) {
+            return;
+        }
+        let from_lambda = self.lambda_trace.lock().ok().and_then(|mut slot| slot.take());
+        self.trace = Some(match from_lambda {
+            Some(mut inner) => {
+                let mut frames = self.frames.clone();
+                frames.append(&mut inner.frames);
+                inner.frames = frames;
+                inner
+            }
+            None => RuntimeError {
+                code: err.code(),
+                message: err.to_string(),
+                span: Some(span),
+                frames: self.frames.clone(),
+            },
+        });
+    }
+
+    /// Consume `cost` units of gas. Returns error if exhausted.
+    pub(crate) fn charge(&mut self, cost: u64) -> EvalResult<()> {
+        self.gas += cost;
         if self.gas > self.gas_limit {
             Err(EvalError::GasExhausted)
         } else {
@@ -47,13 +111,45 @@ impl Evaluator {
         }
     }
 
+    /// Run `call`, which may invoke lambdas, with the gas counter shared
+    /// through [`Self::gas_meter`]; lambda bodies charge it as they run.
+    fn with_shared_gas<T>(&mut self, call: impl FnOnce(&mut Self) -> T) -> EvalResult<T> {
+        self.gas_meter.store(self.gas, Ordering::Relaxed);
+        let out = call(self);
+        self.gas = self.gas_meter.load(Ordering::Relaxed);
+        self.charge(0)?;
+        Ok(out)
+    }
+
+    /// The gas counter lambdas created by this evaluator charge.
+    pub(crate) fn lambda_gas(&self) -> LambdaGas {
+        LambdaGas {
+            counter: Arc::clone(&self.gas_meter),
+            limit: self.gas_limit,
+        }
+    }
+
+    /// Call a function value (a lambda) with evaluated arguments.
+    pub(crate) fn call_function(&mut self, f: &StdlibFn, args: Vec<Value>) -> EvalResult<Value> {
+        self.with_shared_gas(|_| f.0(args))?
+            .map_err(|e| EvalError::StdlibError(e.to_string()))
+    }
+
     // ══════════════════════════════════════════════════════════════════════
     // Expression evaluation
     // ══════════════════════════════════════════════════════════════════════
 
     /// Evaluate an expression to a Value.
     pub fn eval_expr(&mut self, expr: &Expr) -> EvalResult<Value> {
-        self.tick()?;
+        let result = self.eval_expr_kind(expr);
+        if let Err(e) = &result {
+            self.record_failure(e, expr.span);
+        }
+        result
+    }
+
+    fn eval_expr_kind(&mut self, expr: &Expr) -> EvalResult<Value> {
+        self.charge(gas::expr_cost(&expr.kind))?;
         match &expr.kind {
             ExprKind::NumberLit(n) => Ok(Value::Number(*n)),
             ExprKind::StringLit(s) => Ok(Value::String(s.clone())),
@@ -126,15 +222,7 @@ impl Evaluator {
                 }
                 RecordEntry::Spread(expr) => {
                     let val = self.eval_expr(expr)?;
-                    if let Value::Record { fields: rf, .. } = val {
-                        for (k, v) in rf {
-                            fields.insert(k, v);
-                        }
-                    } else {
-                        return Err(EvalError::TypeMismatch(
-                            format!("spread requires record, got {}", val.type_name()),
-                        ));
-                    }
+                    spread_into(&mut fields, val)?;
                 }
             }
         }
@@ -163,7 +251,7 @@ impl Evaluator {
             for arg in args {
                 arg_vals.push(self.eval_expr(arg)?);
             }
-            return f.0(arg_vals).map_err(|e| EvalError::StdlibError(e.to_string()));
+            return self.call_function(&f, arg_vals);
         }
         // Otherwise, unknown function
         Err(EvalError::UnknownFunction(format!(
@@ -187,21 +275,7 @@ impl Evaluator {
 
     fn eval_field_access(&mut self, object: &Expr, field: &str) -> EvalResult<Value> {
         let obj = self.eval_expr(object)?;
-        match &obj {
-            Value::Record { fields, .. } => fields
-                .get(field)
-                .cloned()
-                .ok_or_else(|| {
-                    EvalError::Runtime(format!("record has no field '{field}'"))
-                }),
-            Value::Nil => Err(EvalError::NilAccess(format!(
-                "cannot access field '{field}' on nil"
-            ))),
-            _ => Err(EvalError::TypeMismatch(format!(
-                "cannot access field '{field}' on {}",
-                obj.type_name()
-            ))),
-        }
+        field_of(&obj, field)
     }
 
     fn eval_method_call(
@@ -215,7 +289,12 @@ impl Evaluator {
         for arg in args {
             all_args.push(self.eval_expr(arg)?);
         }
-        // Method calls on lists → list.method, strings → string.method
+        self.call_method(method, all_args)
+    }
+
+    /// Call `method` on `all_args[0]`: lists → `list.method`,
+    /// strings → `string.method`.
+    pub(crate) fn call_method(&mut self, method: &str, all_args: Vec<Value>) -> EvalResult<Value> {
         let module = match &all_args[0] {
             Value::List(_) => "list",
             Value::String(_) => "string",
@@ -254,13 +333,17 @@ impl Evaluator {
 
         let lv = self.eval_expr(left)?;
         let rv = self.eval_expr(right)?;
+        self.apply_binary(op, &lv, &rv)
+    }
 
+    /// Apply a non-short-circuiting binary operator to evaluated operands.
+    pub(crate) fn apply_binary(&self, op: BinOp, lv: &Value, rv: &Value) -> EvalResult<Value> {
         match op {
-            BinOp::Add => self.eval_add(&lv, &rv),
-            BinOp::Sub => self.eval_arith(&lv, &rv, |a, b| a - b, "-"),
-            BinOp::Mul => self.eval_arith(&lv, &rv, |a, b| a * b, "*"),
+            BinOp::Add => self.eval_add(lv, rv),
+            BinOp::Sub => self.eval_arith(lv, rv, |a, b| a - b, "-"),
+            BinOp::Mul => self.eval_arith(lv, rv, |a, b| a * b, "*"),
             BinOp::Div => {
-                if let (Value::Number(a), Value::Number(b)) = (&lv, &rv) {
+                if let (Value::Number(a), Value::Number(b)) = (lv, rv) {
                     if *b == 0.0 {
                         return Err(EvalError::ArithmeticTrap("division by zero".into()));
                     }
@@ -278,7 +361,7 @@ impl Evaluator {
                 }
             }
             BinOp::Mod => {
-                if let (Value::Number(a), Value::Number(b)) = (&lv, &rv) {
+                if let (Value::Number(a), Value::Number(b)) = (lv, rv) {
                     if *b == 0.0 {
                         return Err(EvalError::ArithmeticTrap("modulo by zero".into()));
                     }
@@ -291,13 +374,13 @@ impl Evaluator {
                     )))
                 }
             }
-            BinOp::Eq => Ok(Value::Bool(self.structural_eq(&lv, &rv))),
-            BinOp::NotEq => Ok(Value::Bool(!self.structural_eq(&lv, &rv))),
-            BinOp::Less => self.eval_comparison(&lv, &rv, |a, b| a < b),
-            BinOp::Greater => self.eval_comparison(&lv, &rv, |a, b| a > b),
-            BinOp::LessEq => self.eval_comparison(&lv, &rv, |a, b| a <= b),
-            BinOp::GreaterEq => self.eval_comparison(&lv, &rv, |a, b| a >= b),
-            BinOp::And | BinOp::Or => unreachable!("handled above"),
+            BinOp::Eq => Ok(Value::Bool(self.structural_eq(lv, rv))),
+            BinOp::NotEq => Ok(Value::Bool(!self.structural_eq(lv, rv))),
+            BinOp::Less => self.eval_comparison(lv, rv, |a, b| a < b),
+            BinOp::Greater => self.eval_comparison(lv, rv, |a, b| a > b),
+            BinOp::LessEq => self.eval_comparison(lv, rv, |a, b| a <= b),
+            BinOp::GreaterEq => self.eval_comparison(lv, rv, |a, b| a >= b),
+            BinOp::And | BinOp::Or => unreachable!("short-circuit operators are evaluated lazily"),
         }
     }
 
@@ -367,23 +450,16 @@ impl Evaluator {
 
     fn eval_unary(&mut self, op: UnaryOp, operand: &Expr) -> EvalResult<Value> {
         let val = self.eval_expr(operand)?;
-        match op {
-            UnaryOp::Neg => {
-                if let Value::Number(n) = val {
-                    Ok(Value::Number(-n))
-                } else {
-                    Err(EvalError::TypeMismatch(format!(
-                        "cannot negate {}",
-                        val.type_name()
-                    )))
-                }
-            }
-            UnaryOp::Not => Ok(Value::Bool(!val.is_truthy())),
-        }
+        apply_unary(op, val)
     }
 
     fn eval_result_unwrap(&mut self, inner: &Expr) -> EvalResult<Value> {
         let val = self.eval_expr(inner)?;
+        self.unwrap_result(val)
+    }
+
+    /// `?`: the `Ok` payload, or an unwrap error for `Err`.
+    pub(crate) fn unwrap_result(&self, val: Value) -> EvalResult<Value> {
         match val {
             Value::Result(r) => match *r {
                 ResultValue::Ok(v) => Ok(v),
@@ -416,7 +492,10 @@ impl Evaluator {
             self.eval_block(&if_expr.then_block)
         } else if let Some(else_branch) = &if_expr.else_branch {
             match else_branch {
-                ElseBranch::ElseIf(elif) => self.eval_if_expr(elif),
+                ElseBranch::ElseIf(elif) => {
+                    self.charge(gas::ELSE_IF)?;
+                    self.eval_if_expr(elif)
+                }
                 ElseBranch::Block(block) => self.eval_block(block),
             }
         } else {
@@ -426,19 +505,12 @@ impl Evaluator {
 
     fn eval_for_expr(&mut self, for_expr: &ForExpr) -> EvalResult<Value> {
         let iterable = self.eval_expr(&for_expr.iterable)?;
-        let items = match iterable {
-            Value::List(items) => items,
-            _ => {
-                return Err(EvalError::TypeMismatch(format!(
-                    "for loop requires list, got {}",
-                    iterable.type_name()
-                )));
-            }
-        };
+        let items = for_items(iterable)?;
 
         self.env.push_scope();
         let mut last = Value::Nil;
         for (i, item) in items.iter().enumerate() {
+            self.charge(gas::FOR_ITERATION)?;
             self.env.define(&for_expr.item.name, item.clone());
             if let Some(idx) = &for_expr.index {
                 self.env.define(&idx.name, Value::Number(i as f64));
@@ -469,7 +541,7 @@ impl Evaluator {
 
     /// Try to match a pattern against a value.
     /// Returns Some(bindings) if match, None otherwise.
-    fn match_pattern(&self, pattern: &Pattern, value: &Value) -> Option<Vec<(String, Value)>> {
+    pub(crate) fn match_pattern(&self, pattern: &Pattern, value: &Value) -> Option<Vec<(String, Value)>> {
         match pattern {
             Pattern::Wildcard(_) => Some(vec![]),
             Pattern::Variant { name, bindings } => {
@@ -536,18 +608,27 @@ impl Evaluator {
         let captured_env = self.env.clone();
         let params: Vec<String> = lambda.params.iter().map(|p| p.name.name.clone()).collect();
         let body = lambda.body.clone();
+        let frame = RuntimeFrame::new(FrameKind::Lambda, "<lambda>", lambda.span);
+        let trace_slot = Arc::clone(&self.lambda_trace);
+        let lambda_gas = self.lambda_gas();
 
-        let closure = pepl_stdlib::StdlibFn(std::sync::Arc::new(move |args: Vec<Value>| {
+        let closure = pepl_stdlib::StdlibFn(Arc::new(move |args: Vec<Value>| {
             // Create a mini evaluator with captured env
-            let mut eval = Evaluator::new(100_000);
+            let mut eval = lambda_gas.evaluator();
             eval.env = captured_env.clone();
+            eval.frames.push(frame.clone());
             eval.env.push_scope();
             for (param, arg) in params.iter().zip(args.into_iter()) {
                 eval.env.define(param, arg);
             }
-            let result = eval
-                .eval_block(&body)
-                .map_err(|e| pepl_stdlib::StdlibError::RuntimeError(e.to_string()))?;
+            let result = eval.eval_block(&body);
+            lambda_gas.settle(&eval);
+            let result = result.map_err(|e| {
+                if let (Some(trace), Ok(mut slot)) = (eval.trace.take(), trace_slot.lock()) {
+                    *slot = Some(trace);
+                }
+                pepl_stdlib::StdlibError::RuntimeError(e.to_string())
+            })?;
             eval.env.pop_scope();
             Ok(result)
         }));
@@ -570,7 +651,15 @@ impl Evaluator {
 
     /// Execute a single statement.
     pub fn eval_stmt(&mut self, stmt: &Stmt) -> EvalResult<Value> {
-        self.tick()?;
+        let result = self.eval_stmt_kind(stmt);
+        if let Err(e) = &result {
+            self.record_failure(e, stmt_span(stmt));
+        }
+        result
+    }
+
+    fn eval_stmt_kind(&mut self, stmt: &Stmt) -> EvalResult<Value> {
+        self.charge(gas::stmt_cost(stmt))?;
         match stmt {
             Stmt::Set(set) => self.eval_set(set),
             Stmt::Let(binding) => self.eval_let(binding),
@@ -589,6 +678,15 @@ impl Evaluator {
 
     fn eval_set(&mut self, set: &SetStmt) -> EvalResult<Value> {
         let value = self.eval_expr(&set.value)?;
+        self.set_log.push(SetWrite {
+            target: set
+                .target
+                .iter()
+                .map(|t| t.name.as_str())
+                .collect::<Vec<_>>()
+                .join("."),
+            span: set.span,
+        });
 
         if set.target.len() == 1 {
             // Simple: `set x = value`
@@ -617,7 +715,7 @@ impl Evaluator {
         Ok(())
     }
 
-    fn set_nested_field(
+    pub(crate) fn set_nested_field(
         &self,
         current: &Value,
         path: &[Ident],
@@ -682,12 +780,27 @@ impl Evaluator {
     // Stdlib dispatch
     // ══════════════════════════════════════════════════════════════════════
 
-    /// Call a stdlib function by module and function name.
+    /// Call a stdlib function by module and function name, charging its
+    /// [`gas::stdlib_cost`] for the size of the arguments and result.
     pub fn call_stdlib(
         &mut self,
         module: &str,
         function: &str,
         args: Vec<Value>,
+    ) -> EvalResult<Value> {
+        let arg_size: u64 = args.iter().map(gas_size).sum();
+        let result =
+            self.with_shared_gas(|this| this.dispatch_stdlib(module, function, args))??;
+        let size = arg_size + gas_size(&result);
+        self.charge(gas::stdlib_cost(module, function).charge(size))?;
+        Ok(result)
+    }
+
+    fn dispatch_stdlib(
+        &mut self,
+        module: &str,
+        function: &str,
+        args: Vec<Value>,
     ) -> EvalResult<Value> {
         // Special handling for core.log → capture output
         if module == "core" && function == "log" {
@@ -863,3 +976,107 @@ impl Evaluator {
         std::cmp::Ordering::Equal
     }
 }
+
+/// A lambda's handle on the gas counter of the evaluator that created it.
+#[derive(Clone)]
+pub(crate) struct LambdaGas {
+    counter: Arc<AtomicU64>,
+    limit: u64,
+}
+
+impl LambdaGas {
+    /// A fresh evaluator for one call, starting from the caller's gas.
+    pub(crate) fn evaluator(&self) -> Evaluator {
+        let mut eval = Evaluator::new(self.limit);
+        eval.gas = self.counter.load(Ordering::Relaxed);
+        eval.gas_meter = Arc::clone(&self.counter);
+        eval
+    }
+
+    /// Hand the gas `eval` ended with back to the caller.
+    pub(crate) fn settle(&self, eval: &Evaluator) {
+        self.counter.store(eval.gas, Ordering::Relaxed);
+    }
+}
+
+/// Source span of a statement.
+pub(crate) fn stmt_span(stmt: &Stmt) -> Span {
+    match stmt {
+        Stmt::Set(set) => set.span,
+        Stmt::Let(binding) => binding.span,
+        Stmt::If(if_expr) => if_expr.span,
+        Stmt::For(for_expr) => for_expr.span,
+        Stmt::Match(match_expr) => match_expr.span,
+        Stmt::Return(ret) => ret.span,
+        Stmt::Assert(assert) => assert.span,
+        Stmt::Expr(expr_stmt) => expr_stmt.span,
+    }
+}
+
+/// The size a value adds to a stdlib call: the length of a list, the byte
+/// length of a string, and nothing for anything else.
+pub(crate) fn gas_size(val: &Value) -> u64 {
+    match val {
+        Value::List(items) => items.len() as u64,
+        Value::String(s) => s.len() as u64,
+        _ => 0,
+    }
+}
+
+/// Merge a `...spread` entry into a record literal's fields.
+pub(crate) fn spread_into(fields: &mut BTreeMap<String, Value>, val: Value) -> EvalResult<()> {
+    if let Value::Record { fields: rf, .. } = val {
+        fields.extend(rf);
+        Ok(())
+    } else {
+        Err(EvalError::TypeMismatch(format!(
+            "spread requires record, got {}",
+            val.type_name()
+        )))
+    }
+}
+
+/// `obj.field` on an evaluated object.
+pub(crate) fn field_of(obj: &Value, field: &str) -> EvalResult<Value> {
+    match obj {
+        Value::Record { fields, .. } => fields
+            .get(field)
+            .cloned()
+            .ok_or_else(|| EvalError::Runtime(format!("record has no field '{field}'"))),
+        Value::Nil => Err(EvalError::NilAccess(format!(
+            "cannot access field '{field}' on nil"
+        ))),
+        _ => Err(EvalError::TypeMismatch(format!(
+            "cannot access field '{field}' on {}",
+            obj.type_name()
+        ))),
+    }
+}
+
+/// Apply a unary operator to an evaluated operand.
+pub(crate) fn apply_unary(op: UnaryOp, val: Value) -> EvalResult<Value> {
+    match op {
+        UnaryOp::Neg => {
+            if let Value::Number(n) = val {
+                Ok(Value::Number(-n))
+            } else {
+                Err(EvalError::TypeMismatch(format!(
+                    "cannot negate {}",
+                    val.type_name()
+                )))
+            }
+        }
+        UnaryOp::Not => Ok(Value::Bool(!val.is_truthy())),
+    }
+}
+
+/// The items of a `for` iterable, which must be a list.
+pub(crate) fn for_items(iterable: Value) -> EvalResult<Vec<Value>> {
+    match iterable {
+        Value::List(items) => Ok(items),
+        _ => Err(EvalError::TypeMismatch(format!(
+            "for loop requires list, got {}",
+            iterable.type_name()
+        ))),
+    }
+}
@@ -0,0 +1,394 @@
+//! Explanations for invariant violations.
+//!
+//! When an invariant fails after an action, the space re-evaluates the
+//! invariant subexpression by subexpression and records:
+//!
+//! - the value of every subexpression (an [`ExplainNode`] tree)
+//! - the state / derived fields the invariant reads, with their values
+//!   before and after the action
+//! - the `set` statements in the action that wrote those fields
+//!
+//! The result is available both as JSON ([`InvariantExplanation::to_json`])
+//! and as rendered text (`Display`).
+
+use crate::evaluator::Evaluator;
+use crate::space::SpaceInstance;
+use pepl_stdlib::Value;
+use pepl_types::ast::*;
+use pepl_types::{gas, Span};
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// One evaluated subexpression of an invariant condition.
+#[derive(Debug, Clone)]
+pub struct ExplainNode {
+    /// The subexpression, printed back as PEPL source.
+    pub source: String,
+    pub span: Span,
+    /// The evaluated value, or `None` if evaluation failed or was skipped.
+    pub value: Option<Value>,
+    /// Evaluation error, if any.
+    pub error: Option<String>,
+    /// Operand subexpressions (empty for leaves).
+    pub children: Vec<ExplainNode>,
+}
+
+/// A `set` statement executed by the action.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SetWrite {
+    /// Target path, e.g. `count` or `settings.volume`.
+    pub target: String,
+    pub span: Span,
+}
+
+/// A field read by the invariant, with its value around the action.
+#[derive(Debug, Clone)]
+pub struct FieldChange {
+    pub name: String,
+    pub before: Option<Value>,
+    pub after: Option<Value>,
+    /// `set` statements in the action that wrote this field, in execution order.
+    pub writes: Vec<SetWrite>,
+}
+
+/// Why an invariant failed after an action.
+#[derive(Debug, Clone)]
+pub struct InvariantExplanation {
+    /// Invariant name.
+    pub invariant: String,
+    /// Span of the invariant declaration.
+    pub span: Span,
+    /// Evaluation tree of the invariant condition.
+    pub tree: ExplainNode,
+    /// Fields the invariant reads, in first-use order.
+    pub fields: Vec<FieldChange>,
+}
+
+impl InvariantExplanation {
+    /// Structured form of the explanation.
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "invariant": self.invariant,
+            "span": self.span,
+            "tree": node_to_json(&self.tree),
+            "fields": self.fields.iter().map(|f| serde_json::json!({
+                "name": f.name,
+                "before": value_json(f.before.as_ref()),
+                "after": value_json(f.after.as_ref()),
+                "writes": f.writes.iter().map(|w| serde_json::json!({
+                    "target": w.target,
+                    "span": w.span,
+                })).collect::<Vec<_>>(),
+            })).collect::<Vec<_>>(),
+        })
+    }
+}
+
+impl fmt::Display for InvariantExplanation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "invariant '{}' violated ({})", self.invariant, self.span)?;
+        write_node(f, &self.tree, 1)?;
+        if !self.fields.is_empty() {
+            write!(f, "\nfields:")?;
+        }
+        for field in &self.fields {
+            write!(
+                f,
+                "\n  {}: {} -> {}",
+                field.name,
+                value_text(field.before.as_ref()),
+                value_text(field.after.as_ref())
+            )?;
+            for write in &field.writes {
+                write!(
+                    f,
+                    "\n    written by `set {}` at {}",
+                    write.target, write.span
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn write_node(f: &mut fmt::Formatter<'_>, node: &ExplainNode, depth: usize) -> fmt::Result {
+    let indent = "  ".repeat(depth);
+    match (&node.value, &node.error) {
+        (_, Some(err)) => writeln!(f, "{indent}{} => error: {err}", node.source)?,
+        (Some(value), None) => {
+            writeln!(f, "{indent}{} => {}", node.source, value_text(Some(value)))?
+        }
+        (None, None) => writeln!(f, "{indent}{} => (not evaluated)", node.source)?,
+    }
+    for child in &node.children {
+        write_node(f, child, depth + 1)?;
+    }
+    Ok(())
+}
+
+fn node_to_json(node: &ExplainNode) -> serde_json::Value {
+    let mut map = serde_json::Map::new();
+    map.insert("source".into(), node.source.clone().into());
+    map.insert("span".into(), serde_json::json!(node.span));
+    if let Some(value) = &node.value {
+        map.insert("value".into(), SpaceInstance::value_to_json_public(value));
+    }
+    if let Some(err) = &node.error {
+        map.insert("error".into(), err.clone().into());
+    }
+    if !node.children.is_empty() {
+        map.insert(
+            "children".into(),
+            node.children.iter().map(node_to_json).collect(),
+        );
+    }
+    serde_json::Value::Object(map)
+}
+
+fn value_json(value: Option<&Value>) -> serde_json::Value {
+    value
+        .map(SpaceInstance::value_to_json_public)
+        .unwrap_or(serde_json::Value::Null)
+}
+
+fn value_text(value: Option<&Value>) -> String {
+    match value {
+        Some(v) => SpaceInstance::value_to_json_public(v).to_string(),
+        None => "(undefined)".to_string(),
+    }
+}
+
+// ══════════════════════════════════════════════════════════════════════════════
+// Building explanations
+// ══════════════════════════════════════════════════════════════════════════════
+
+/// Build the explanation for a failed invariant.
+///
+/// `before` is the pre-action global snapshot, `fields` the names of state
+/// and derived fields, and `writes` the `set` statements the action executed.
+///
+/// Explaining is free: re-evaluating each subexpression runs on a budget of
+/// its own ([`gas::DEFAULT_LIMIT`], however close the action came to its
+/// limit), and `eval`'s counter is left as the violation found it.
+pub(crate) fn explain_invariant(
+    eval: &mut Evaluator,
+    inv: &InvariantDecl,
+    before: &BTreeMap<String, Value>,
+    fields: &[String],
+    writes: &[SetWrite],
+) -> InvariantExplanation {
+    let (used, limit) = (eval.gas, eval.gas_limit);
+    (eval.gas, eval.gas_limit) = (0, gas::DEFAULT_LIMIT);
+    let tree = explain_expr(eval, &inv.condition);
+    (eval.gas, eval.gas_limit) = (used, limit);
+
+    let mut names = Vec::new();
+    collect_identifiers(&inv.condition, &mut names);
+    let fields = names
+        .into_iter()
+        .filter(|name| fields.contains(name))
+        .map(|name| FieldChange {
+            before: before.get(&name).cloned(),
+            after: eval.env.get(&name).cloned(),
+            writes: writes
+                .iter()
+                .filter(|w| w.target.split('.').next() == Some(name.as_str()))
+                .cloned()
+                .collect(),
+            name,
+        })
+        .collect();
+
+    InvariantExplanation {
+        invariant: inv.name.name.clone(),
+        span: inv.span,
+        tree,
+        fields,
+    }
+}
+
+/// Evaluate `expr` and each of its operand subexpressions.
+///
+/// `and` / `or` only explain the right operand when it was actually
+/// evaluated; lambdas and control-flow expressions are leaves.
+fn explain_expr(eval: &mut Evaluator, expr: &Expr) -> ExplainNode {
+    let (value, error) = match eval.eval_expr(expr) {
+        Ok(v) => (Some(v), None),
+        Err(e) => (None, Some(e.to_string())),
+    };
+
+    let operands: Vec<&Expr> = match &expr.kind {
+        ExprKind::Binary { left, op, right } => {
+            let short_circuit = matches!(
+                (op, value_truthiness(eval, left)),
+                (BinOp::And, Some(false)) | (BinOp::Or, Some(true))
+            );
+            if short_circuit {
+                vec![&**left]
+            } else {
+                vec![&**left, &**right]
+            }
+        }
+        ExprKind::Unary { operand, .. } => vec![&**operand],
+        ExprKind::Paren(inner) | ExprKind::ResultUnwrap(inner) => vec![&**inner],
+        ExprKind::NilCoalesce { left, right } => vec![&**left, &**right],
+        ExprKind::FieldAccess { object, .. } => vec![&**object],
+        ExprKind::MethodCall { object, args, .. } => {
+            std::iter::once(&**object).chain(args.iter()).collect()
+        }
+        ExprKind::Call { args, .. } | ExprKind::QualifiedCall { args, .. } => args.iter().collect(),
+        ExprKind::ListLit(elems) => elems.iter().collect(),
+        _ => Vec::new(),
+    };
+
+    let children = operands
+        .into_iter()
+        .filter(|e| !is_literal(e))
+        .map(|e| explain_expr(eval, e))
+        .collect();
+
+    ExplainNode {
+        source: expr_source(expr),
+        span: expr.span,
+        value,
+        error,
+        children,
+    }
+}
+
+fn value_truthiness(eval: &mut Evaluator, expr: &Expr) -> Option<bool> {
+    eval.eval_expr(expr).ok().map(|v| v.is_truthy())
+}
+
+fn is_literal(expr: &Expr) -> bool {
+    matches!(
+        expr.kind,
+        ExprKind::NumberLit(_) | ExprKind::StringLit(_) | ExprKind::BoolLit(_) | ExprKind::NilLit
+    )
+}
+
+/// Identifiers read by `expr`, in first-use order (lambda bodies excluded).
+fn collect_identifiers(expr: &Expr, out: &mut Vec<String>) {
+    match &expr.kind {
+        ExprKind::Identifier(name) if !out.contains(name) => out.push(name.clone()),
+        ExprKind::Binary { left, right, .. } | ExprKind::NilCoalesce { left, right } => {
+            collect_identifiers(left, out);
+            collect_identifiers(right, out);
+        }
+        ExprKind::Unary { operand: inner, .. }
+        | ExprKind::Paren(inner)
+        | ExprKind::ResultUnwrap(inner)
+        | ExprKind::FieldAccess { object: inner, .. } => collect_identifiers(inner, out),
+        ExprKind::MethodCall { object, args, .. } => {
+            collect_identifiers(object, out);
+            args.iter().for_each(|a| collect_identifiers(a, out));
+        }
+        ExprKind::Call { args, .. }
+        | ExprKind::QualifiedCall { args, .. }
+        | ExprKind::ListLit(args) => args.iter().for_each(|a| collect_identifiers(a, out)),
+        ExprKind::RecordLit(entries) => {
+            for entry in entries {
+                match entry {
+                    RecordEntry::Field { value, .. } | RecordEntry::Spread(value) => {
+                        collect_identifiers(value, out)
+                    }
+                }
+            }
+        }
+        ExprKind::StringInterpolation(parts) => {
+            for part in parts {
+                if let StringPart::Expr(e) = part {
+                    collect_identifiers(e, out);
+                }
+            }
+        }
+        ExprKind::If(if_expr) => collect_identifiers(&if_expr.condition, out),
+        ExprKind::For(for_expr) => collect_identifiers(&for_expr.iterable, out),
+        ExprKind::Match(match_expr) => collect_identifiers(&match_expr.subject, out),
+        _ => {}
+    }
+}
+
+/// Print an expression back as (normalised) PEPL source.
+pub fn expr_source(expr: &Expr) -> String {
+    let list = |items: &[Expr]| items.iter().map(expr_source).collect::<Vec<_>>().join(", ");
+    match &expr.kind {
+        ExprKind::NumberLit(n) => format!("{n}"),
+        ExprKind::StringLit(s) => format!("{s:?}"),
+        ExprKind::StringInterpolation(parts) => {
+            let mut out = String::from("\"");
+            for part in parts {
+                match part {
+                    StringPart::Literal(s) => out.push_str(s),
+                    StringPart::Expr(e) => {
+                        out.push_str("${");
+                        out.push_str(&expr_source(e));
+                        out.push('}');
+                    }
+                }
+            }
+            out.push('"');
+            out
+        }
+        ExprKind::BoolLit(b) => b.to_string(),
+        ExprKind::NilLit => "nil".to_string(),
+        ExprKind::ListLit(elems) => format!("[{}]", list(elems)),
+        ExprKind::RecordLit(entries) => {
+            let parts: Vec<String> = entries
+                .iter()
+                .map(|entry| match entry {
+                    RecordEntry::Field { name, value } => {
+                        format!("{}: {}", name.name, expr_source(value))
+                    }
+                    RecordEntry::Spread(e) => format!("...{}", expr_source(e)),
+                })
+                .collect();
+            format!("{{ {} }}", parts.join(", "))
+        }
+        ExprKind::Identifier(name) => name.clone(),
+        ExprKind::Call { name, args } => format!("{}({})", name.name, list(args)),
+        ExprKind::QualifiedCall {
+            module,
+            function,
+            args,
+        } => format!("{}.{}({})", module.name, function.name, list(args)),
+        ExprKind::FieldAccess { object, field } => {
+            format!("{}.{}", expr_source(object), field.name)
+        }
+        ExprKind::MethodCall {
+            object,
+            method,
+            args,
+        } => format!("{}.{}({})", expr_source(object), method.name, list(args)),
+        ExprKind::Binary { left, op, right } => {
+            format!(
+                "{} {} {}",
+                expr_source(left),
+                op.as_str(),
+                expr_source(right)
+            )
+        }
+        ExprKind::Unary { op, operand } => match op {
+            UnaryOp::Neg => format!("-{}", expr_source(operand)),
+            UnaryOp::Not => format!("not {}", expr_source(operand)),
+        },
+        ExprKind::ResultUnwrap(inner) => format!("{}?", expr_source(inner)),
+        ExprKind::NilCoalesce { left, right } => {
+            format!("{} ?? {}", expr_source(left), expr_source(right))
+        }
+        ExprKind::If(if_expr) => format!("if {} {{ … }}", expr_source(&if_expr.condition)),
+        ExprKind::For(for_expr) => format!(
+            "for {} in {} {{ … }}",
+            for_expr.item.name,
+            expr_source(&for_expr.iterable)
+        ),
+        ExprKind::Match(match_expr) => {
+            format!("match {} {{ … }}", expr_source(&match_expr.subject))
+        }
+        ExprKind::Lambda(lambda) => {
+            let params: Vec<&str> = lambda.params.iter().map(|p| p.name.name.as_str()).collect();
+            format!("fn({}) {{ … }}", params.join(", "))
+        }
+        ExprKind::Paren(inner) => format!("({})", expr_source(inner)),
+    }
+}
@@ -2,15 +2,24 @@
 //!
 //! Executes PEPL programs directly from the typed AST without WASM compilation.
 //! Used for semantic validation and as the golden reference for WASM output.
+//! [`CompiledSpace`] is a closure-compiled fast path with the same semantics
+//! and gas accounting, for long simulations and model checking.
 
+pub mod compiled;
 pub mod env;
 pub mod error;
 pub mod evaluator;
+pub mod explain;
 pub mod space;
 pub mod test_runner;
 
+pub use compiled::{CompiledSpace, SpaceSnapshot};
 pub use env::Environment;
 pub use error::{EvalError, EvalResult};
 pub use evaluator::Evaluator;
+pub use explain::{ExplainNode, FieldChange, InvariantExplanation, SetWrite};
 pub use space::{ActionResult, SpaceInstance, SurfaceNode};
-pub use test_runner::{run_tests, MockResponse, TestResult, TestRunSummary};
+pub use test_runner::{
+    check_snapshot, run_tests, run_tests_with_options, MockResponse, TestOptions, TestResult,
+    TestRunSummary,
+};
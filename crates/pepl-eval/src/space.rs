@@ -5,9 +5,13 @@
 
 use crate::error::{EvalError, EvalResult};
 use crate::evaluator::Evaluator;
+use crate::explain::{explain_invariant, InvariantExplanation};
 use crate::test_runner::MockResponse;
 use pepl_stdlib::{ResultValue, Value};
 use pepl_types::ast::*;
+use pepl_types::derived::RecomputePlan;
+use pepl_types::gas;
+use pepl_types::{ErrorCode, FrameKind, RuntimeError, RuntimeFrame, Span};
 use std::collections::BTreeMap;
 
 /// A snapshot of the view surface tree.
@@ -21,6 +25,100 @@ pub struct SurfaceNode {
     pub children: Vec<SurfaceNode>,
 }
 
+impl SurfaceNode {
+    /// Encode a surface tree as a PEPL value: a list of
+    /// `{ component, props, children }` records (the WASM render layout).
+    pub fn to_value(nodes: &[SurfaceNode]) -> Value {
+        Value::List(nodes.iter().map(Self::node_to_value).collect())
+    }
+
+    fn node_to_value(node: &SurfaceNode) -> Value {
+        let mut fields = BTreeMap::new();
+        fields.insert(
+            "component".to_string(),
+            Value::String(node.component.clone()),
+        );
+        fields.insert(
+            "props".to_string(),
+            Value::Record {
+                type_name: None,
+                fields: node.props.clone(),
+            },
+        );
+        fields.insert("children".to_string(), Self::to_value(&node.children));
+        Value::Record {
+            type_name: None,
+            fields,
+        }
+    }
+
+    /// Decode a value produced by [`SurfaceNode::to_value`]. Nested lists are
+    /// flattened, matching how `if`/`for` blocks render.
+    pub fn from_value(value: &Value) -> EvalResult<Vec<SurfaceNode>> {
+        let mut nodes = Vec::new();
+        Self::collect_nodes(value, &mut nodes)?;
+        Ok(nodes)
+    }
+
+    fn collect_nodes(value: &Value, out: &mut Vec<SurfaceNode>) -> EvalResult<()> {
+        match value {
+            Value::List(items) => {
+                for item in items {
+                    Self::collect_nodes(item, out)?;
+                }
+            }
+            Value::Nil => {}
+            Value::Record { fields, .. } => {
+                let component = match fields.get("component") {
+                    Some(Value::String(s)) => s.clone(),
+                    _ => {
+                        return Err(EvalError::TypeMismatch(
+                            "surface node is missing 'component'".into(),
+                        ))
+                    }
+                };
+                let props = match fields.get("props") {
+                    Some(Value::Record { fields, .. }) => fields.clone(),
+                    _ => BTreeMap::new(),
+                };
+                let children = match fields.get("children") {
+                    Some(children) => Self::from_value(children)?,
+                    None => Vec::new(),
+                };
+                out.push(SurfaceNode {
+                    component,
+                    props,
+                    children,
+                });
+            }
+            other => {
+                return Err(EvalError::TypeMismatch(format!(
+                    "expected Surface, got {}",
+                    other.type_name()
+                )))
+            }
+        }
+        Ok(())
+    }
+
+    /// Count nodes anywhere in the tree whose component is `component` and
+    /// whose props contain every entry of `props`.
+    pub fn count_matching(
+        nodes: &[SurfaceNode],
+        component: &str,
+        props: &BTreeMap<String, Value>,
+    ) -> usize {
+        nodes
+            .iter()
+            .map(|node| {
+                let here = node.component == component
+                    && props.iter().all(|(k, v)| node.props.get(k) == Some(v));
+                usize::from(here) + Self::count_matching(&node.children, component, props)
+            })
+            .sum()
+    }
+}
+
 /// The result of dispatching an action.
 #[derive(Debug)]
 pub struct ActionResult {
@@ -28,6 +126,9 @@ pub struct ActionResult {
     pub committed: bool,
     /// Invariant violation message, if rollback occurred.
     pub invariant_error: Option<String>,
+    /// Why the invariant failed: subexpression values, the fields involved
+    /// and the `set` statements that wrote them.
+    pub explanation: Option<InvariantExplanation>,
 }
 
 /// Runtime instance of a PEPL space.
@@ -40,10 +141,12 @@ pub struct SpaceInstance {
     eval: Evaluator,
     /// State field names (for identifying which env bindings are state).
     state_fields: Vec<String>,
-    /// Derived field definitions (name + expression, computed in order).
-    derived_fields: Vec<(String, Expr)>,
-    /// Invariant definitions (name + condition expression).
-    invariants: Vec<(String, Expr)>,
+    /// Derived field definitions, in declaration order.
+    derived_fields: Vec<DerivedField>,
+    /// Which derived fields to recompute, and in what order.
+    recompute: RecomputePlan,
+    /// Invariant definitions.
+    invariants: Vec<InvariantDecl>,
     /// Action declarations.
     actions: Vec<ActionDecl>,
     /// View declarations.
@@ -56,6 +159,8 @@ pub struct SpaceInstance {
     handle_event_decl: Option<HandleEventDecl>,
     /// Mock capability responses for test runner.
     mock_responses: Vec<MockResponse>,
+    /// Structured form of the most recent failure or invariant violation.
+    last_error: Option<RuntimeError>,
 }
 
 impl SpaceInstance {
@@ -64,7 +169,7 @@ impl SpaceInstance {
     /// Initializes state fields with their default values, computes
     /// derived fields, and registers actions/views.
     pub fn new(program: &Program) -> EvalResult<Self> {
-        Self::with_gas_limit(program, 1_000_000)
+        Self::with_gas_limit(program, gas::DEFAULT_LIMIT)
     }
 
     /// Create with a custom gas limit.
@@ -93,28 +198,20 @@ impl SpaceInstance {
         }
 
         // Collect derived field definitions
-        let derived_fields: Vec<(String, Expr)> = body
+        let derived_fields: Vec<DerivedField> = body
             .derived
             .as_ref()
-            .map(|d| {
-                d.fields
-                    .iter()
-                    .map(|f| (f.name.name.clone(), f.value.clone()))
-                    .collect()
-            })
+            .map(|d| d.fields.clone())
             .unwrap_or_default();
 
         // Collect invariants
-        let invariants: Vec<(String, Expr)> = body
-            .invariants
-            .iter()
-            .map(|inv| (inv.name.name.clone(), inv.condition.clone()))
-            .collect();
+        let invariants: Vec<InvariantDecl> = body.invariants.clone();
 
         let mut instance = Self {
             eval,
             state_fields,
             derived_fields,
+            recompute: RecomputePlan::new(body),
             invariants,
             actions: body.actions.clone(),
             views: body.views.clone(),
@@ -122,10 +219,12 @@ impl SpaceInstance {
             update_decl: body.update.clone(),
             handle_event_decl: body.handle_event.clone(),
             mock_responses: Vec::new(),
+            last_error: None,
         };
 
         // Compute initial derived fields
-        instance.recompute_derived()?;
+        let all = instance.recompute.all.clone();
+        instance.recompute_derived(&all)?;
 
         Ok(instance)
     }
@@ -160,6 +259,11 @@ impl SpaceInstance {
         self.eval.log_output.clear();
     }
 
+    /// Gas consumed so far, charged from the [`pepl_types::gas`] cost table.
+    pub fn gas_used(&self) -> u64 {
+        self.eval.gas
+    }
+
     /// Set a credential value (called by host before actions).
     pub fn set_credential(&mut self, name: &str, value: Value) {
         self.credentials.insert(name.to_string(), value.clone());
@@ -179,13 +283,24 @@ impl SpaceInstance {
     /// 4. Commit or rollback
     pub fn dispatch(&mut self, action_name: &str, args: Vec<Value>) -> EvalResult<ActionResult> {
         // Find the action
-        let action = self
+        let index = self
             .actions
             .iter()
-            .find(|a| a.name.name == action_name)
-            .cloned()
+            .position(|a| a.name.name == action_name)
             .ok_or_else(|| EvalError::UndefinedAction(action_name.to_string()))?;
+        let action = self.actions[index].clone();
+        let recompute = self.recompute.actions[index].clone();
+
+        let frame = RuntimeFrame::new(FrameKind::Action, action_name, action.span);
+        self.traced(frame, |this| this.run_action(&action, args, &recompute))
+    }
 
+    fn run_action(
+        &mut self,
+        action: &ActionDecl,
+        args: Vec<Value>,
+        recompute: &[usize],
+    ) -> EvalResult<ActionResult> {
         // Snapshot pre-action state
         let snapshot = self.eval.env.global_bindings().clone();
 
@@ -206,23 +321,33 @@ impl SpaceInstance {
             Err(e) => return Err(e),
         }
 
+        self.commit_or_rollback(snapshot, recompute)
+    }
+
+    /// Recompute the `recompute` derived fields and check invariants after
+    /// a state change.  Rolls back to `snapshot` if an invariant fails.
+    fn commit_or_rollback(
+        &mut self,
+        snapshot: BTreeMap<String, Value>,
+        recompute: &[usize],
+    ) -> EvalResult<ActionResult> {
         // Recompute derived fields before invariant check
-        self.recompute_derived()?;
+        self.recompute_derived(recompute)?;
 
         // Check invariants
-        match self.check_invariants() {
+        match self.check_invariants(&snapshot) {
             Ok(()) => Ok(ActionResult {
                 committed: true,
                 invariant_error: None,
+                explanation: None,
             }),
-            Err(msg) => {
-                // Rollback to pre-action state
+            Err((msg, explanation)) => {
+                // Rollback to pre-action state, derived fields included
                 self.eval.env.restore_global(snapshot);
-                // Recompute derived with rolled-back state
-                self.recompute_derived()?;
                 Ok(ActionResult {
                     committed: false,
                     invariant_error: Some(msg),
+                    explanation: Some(*explanation),
                 })
             }
         }
@@ -232,10 +357,17 @@ impl SpaceInstance {
     // Derived fields
     // ══════════════════════════════════════════════════════════════════════
 
-    /// Recompute all derived fields in declaration order.
-    fn recompute_derived(&mut self) -> EvalResult<()> {
-        for (name, expr) in &self.derived_fields.clone() {
-            let val = self.eval.eval_expr(expr)?;
+    /// Recompute the derived fields at indices `fields`, in that order.
+    fn recompute_derived(&mut self, fields: &[usize]) -> EvalResult<()> {
+        for &i in fields {
+            let field = self.derived_fields[i].clone();
+            let name = &field.name.name;
+            self.eval
+                .frames
+                .push(RuntimeFrame::new(FrameKind::Derived, name, field.span));
+            let val = self.eval.eval_expr(&field.value);
+            self.eval.frames.pop();
+            let val = val?;
             // Define/update derived field in global scope
             if !self.eval.env.set(name, val.clone()) {
                 self.eval.env.define(name, val);
@@ -248,18 +380,48 @@ impl SpaceInstance {
     // Invariant checking
     // ══════════════════════════════════════════════════════════════════════
 
-    /// Check all invariants. Returns Ok(()) if all pass, Err(message) if one fails.
-    fn check_invariants(&mut self) -> Result<(), String> {
-        for (name, condition) in &self.invariants.clone() {
-            match self.eval.eval_expr(condition) {
-                Ok(val) => {
-                    if !val.is_truthy() {
-                        return Err(format!("invariant '{name}' violated"));
-                    }
+    /// Check all invariants. Returns Ok(()) if all pass, or the failure
+    /// message and its explanation if one fails.
+    ///
+    /// On failure the structured error (with an invariant frame) is kept in
+    /// [`last_error`](Self::last_error).
+    fn check_invariants(
+        &mut self,
+        before: &BTreeMap<String, Value>,
+    ) -> Result<(), (String, Box<InvariantExplanation>)> {
+        for inv in &self.invariants.clone() {
+            let name = &inv.name.name;
+            self.eval
+                .frames
+                .push(RuntimeFrame::new(FrameKind::Invariant, name, inv.span));
+            let failure = match self.eval.eval_expr(&inv.condition) {
+                Ok(val) if val.is_truthy() => None,
+                Ok(_) => {
+                    let msg = format!("invariant '{name}' violated");
+                    let mut err = RuntimeError::new(ErrorCode::INVARIANT_VIOLATED, msg.clone());
+                    err.span = Some(inv.condition.span);
+                    err.frames = self.eval.frames.clone();
+                    Some((msg, err))
                 }
                 Err(e) => {
-                    return Err(format!("invariant '{name}' evaluation error: {e}"));
+                    let msg = format!("invariant '{name}' evaluation error: {e}");
+                    Some((msg, self.eval.runtime_error(&e)))
                 }
+            };
+            self.eval.frames.pop();
+            if let Some((msg, err)) = failure {
+                self.last_error = Some(err);
+                let fields: Vec<String> = self
+                    .state_fields
+                    .iter()
+                    .cloned()
+                    .chain(self.derived_fields.iter().map(|f| f.name.name.clone()))
+                    .collect();
+                let writes = std::mem::take(&mut self.eval.set_log);
+                let explanation = explain_invariant(&mut self.eval, inv, before, &fields, &writes);
+                // Subexpression failures while explaining are not new errors
+                self.eval.clear_trace();
+                return Err((msg, Box::new(explanation)));
             }
         }
         Ok(())
@@ -278,7 +440,8 @@ impl SpaceInstance {
             .cloned()
             .ok_or_else(|| EvalError::Runtime(format!("unknown view '{view_name}'")))?;
 
-        self.eval_ui_block(&view.body)
+        let frame = RuntimeFrame::new(FrameKind::View, view_name, view.span);
+        self.traced(frame, |this| this.eval_ui_block(&view.body))
     }
 
     /// Render the default "main" view.
@@ -301,6 +464,7 @@ impl SpaceInstance {
                 out.push(node);
             }
             UIElement::Let(binding) => {
+                self.eval.charge(gas::LET)?;
                 let value = self.eval.eval_expr(&binding.value)?;
                 if let Some(name) = &binding.name {
                     self.eval.env.define(&name.name, value);
@@ -403,7 +567,10 @@ impl SpaceInstance {
             out.extend(nodes);
         } else if let Some(else_block) = &ui_if.else_block {
             match else_block {
-                UIElse::ElseIf(elif) => self.eval_ui_if(elif, out)?,
+                UIElse::ElseIf(elif) => {
+                    self.eval.charge(gas::ELSE_IF)?;
+                    self.eval_ui_if(elif, out)?
+                }
                 UIElse::Block(block) => {
                     let nodes = self.eval_ui_block(block)?;
                     out.extend(nodes);
@@ -427,6 +594,7 @@ impl SpaceInstance {
 
         self.eval.env.push_scope();
         for (i, item) in items.iter().enumerate() {
+            self.eval.charge(gas::FOR_ITERATION)?;
             self.eval.env.define(&ui_for.item.name, item.clone());
             if let Some(idx) = &ui_for.index {
                 self.eval.env.define(&idx.name, Value::Number(i as f64));
@@ -490,6 +658,11 @@ impl SpaceInstance {
             .clone()
             .ok_or_else(|| EvalError::Runtime("space has no update() declaration".into()))?;
 
+        let frame = RuntimeFrame::new(FrameKind::Update, "update", update.span);
+        self.traced(frame, |this| this.run_update(&update, dt))
+    }
+
+    fn run_update(&mut self, update: &UpdateDecl, dt: f64) -> EvalResult<ActionResult> {
         let snapshot = self.eval.env.global_bindings().clone();
 
         self.eval.env.push_scope();
@@ -506,22 +679,8 @@ impl SpaceInstance {
             Err(e) => return Err(e),
         }
 
-        self.recompute_derived()?;
-
-        match self.check_invariants() {
-            Ok(()) => Ok(ActionResult {
-                committed: true,
-                invariant_error: None,
-            }),
-            Err(msg) => {
-                self.eval.env.restore_global(snapshot);
-                self.recompute_derived()?;
-                Ok(ActionResult {
-                    committed: false,
-                    invariant_error: Some(msg),
-                })
-            }
-        }
+        let recompute = self.recompute.update.clone();
+        self.commit_or_rollback(snapshot, &recompute)
     }
 
     /// Call `handleEvent(event)` — game loop event handler.
@@ -533,6 +692,15 @@ impl SpaceInstance {
             .clone()
             .ok_or_else(|| EvalError::Runtime("space has no handleEvent() declaration".into()))?;
 
+        let frame = RuntimeFrame::new(FrameKind::HandleEvent, "handleEvent", handler.span);
+        self.traced(frame, |this| this.run_handle_event(&handler, event))
+    }
+
+    fn run_handle_event(
+        &mut self,
+        handler: &HandleEventDecl,
+        event: Value,
+    ) -> EvalResult<ActionResult> {
         let snapshot = self.eval.env.global_bindings().clone();
 
         self.eval.env.push_scope();
@@ -547,22 +715,69 @@ impl SpaceInstance {
             Err(e) => return Err(e),
         }
 
-        self.recompute_derived()?;
+        let recompute = self.recompute.handle_event.clone();
+        self.commit_or_rollback(snapshot, &recompute)
+    }
 
-        match self.check_invariants() {
-            Ok(()) => Ok(ActionResult {
-                committed: true,
-                invariant_error: None,
-            }),
-            Err(msg) => {
-                self.eval.env.restore_global(snapshot);
-                self.recompute_derived()?;
-                Ok(ActionResult {
-                    committed: false,
-                    invariant_error: Some(msg),
-                })
+    // ══════════════════════════════════════════════════════════════════════
+    // Runtime error tracing
+    // ══════════════════════════════════════════════════════════════════════
+
+    /// Run an entry point under `frame`, keeping the structured form of any
+    /// failure in [`last_error`](Self::last_error).
+    fn traced<T>(
+        &mut self,
+        frame: RuntimeFrame,
+        run: impl FnOnce(&mut Self) -> EvalResult<T>,
+    ) -> EvalResult<T> {
+        self.eval.clear_trace();
+        self.eval.set_log.clear();
+        self.last_error = None;
+        self.eval.frames.push(frame);
+        let result = run(self);
+        if let Err(e) = &result {
+            if !matches!(e, EvalError::Return(_)) {
+                self.last_error = Some(self.eval.runtime_error(e));
             }
         }
+        self.eval.frames.pop();
+        result
+    }
+
+    /// The structured error (code, span and PEPL call stack) for the most
+    /// recent failed entry point or invariant violation.
+    pub fn last_error(&self) -> Option<&RuntimeError> {
+        self.last_error.as_ref()
+    }
+
+    /// Structured form of `err`, which was just returned by this instance.
+    ///
+    /// Prefers the error kept by the failing entry point, then whatever the
+    /// evaluator traced (e.g. a failing test statement).
+    pub fn runtime_error(&mut self, err: &EvalError) -> RuntimeError {
+        match self.last_error.take() {
+            Some(last) if last.code == err.code() => {
+                self.eval.clear_trace();
+                last
+            }
+            _ => self.eval.runtime_error(err),
+        }
+    }
+
+    /// Attribute `err` to `span` unless a more precise location was already
+    /// recorded.
+    pub(crate) fn record_failure(&mut self, err: &EvalError, span: Span) {
+        self.eval.record_failure(err, span);
+    }
+
+    /// Push a frame onto the PEPL call stack (used by the test runner).
+    pub fn push_frame(&mut self, frame: RuntimeFrame) {
+        self.eval.frames.push(frame);
+    }
+
+    /// Pop the innermost PEPL call stack frame.
+    pub fn pop_frame(&mut self) {
+        self.eval.frames.pop();
     }
 
     // ══════════════════════════════════════════════════════════════════════
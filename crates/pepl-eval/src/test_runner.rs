@@ -3,11 +3,30 @@
 //! Each test case creates a fresh SpaceInstance and dispatches actions
 //! by calling them as functions. `with_responses { }` provides mock
 //! capability call results.
+//!
+//! Test bodies can also render views and inspect the result:
+//!
+//! - `let s = render(main)` binds the rendered Surface tree
+//! - `count_components(s, "Text")` counts matching nodes
+//! - `assert_component(s, "Button", { label: "Add" })` requires at least
+//!   one node with that component whose props include the given entries
+//! - `assert_snapshot(s, "name")` compares `surface_to_json` output with the
+//!   golden file `<snapshot_dir>/name.surface.json` (see [`TestOptions`])
+//!
+//! Game-loop spaces are driven with simulated frames and input:
+//!
+//! - `tick(dt)` runs `update(dt)` once; `tick_n(n, dt)` runs it `n` times
+//! - `send_event({ type: "tap", ... })` runs `handleEvent(event)`
 
 use crate::error::{EvalError, EvalResult};
-use crate::space::SpaceInstance;
-use pepl_stdlib::Value;
+use crate::evaluator::stmt_span;
+use crate::space::{ActionResult, SpaceInstance, SurfaceNode};
+use pepl_stdlib::{StdlibError, StdlibFn, Value};
 use pepl_types::ast::*;
+use pepl_types::{FrameKind, RuntimeError, RuntimeFrame};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::Arc;
 
 /// Result of running a single test case.
 #[derive(Debug, Clone)]
@@ -18,6 +37,8 @@ pub struct TestResult {
     pub passed: bool,
     /// Error message if the test failed.
     pub error: Option<String>,
+    /// Code, span and PEPL call stack of the failure, if the test failed.
+    pub runtime_error: Option<RuntimeError>,
 }
 
 impl std::fmt::Display for TestResult {
@@ -60,16 +81,34 @@ pub struct MockResponse {
     pub response: Value,
 }
 
+/// Options for [`run_tests_with_options`].
+#[derive(Debug, Clone, Default)]
+pub struct TestOptions {
+    /// Directory holding `<name>.surface.json` golden files. Required for
+    /// `assert_snapshot`.
+    pub snapshot_dir: Option<PathBuf>,
+    /// Write the current surface instead of comparing against the golden file.
+    pub update_snapshots: bool,
+}
+
 /// Run all test blocks in a PEPL program.
 ///
 /// Each test case gets a fresh `SpaceInstance`. Actions are dispatched
 /// by executing test body statements that call actions as functions.
 pub fn run_tests(program: &Program) -> EvalResult<TestRunSummary> {
+    run_tests_with_options(program, &TestOptions::default())
+}
+
+/// Run all test blocks with explicit [`TestOptions`].
+pub fn run_tests_with_options(
+    program: &Program,
+    options: &TestOptions,
+) -> EvalResult<TestRunSummary> {
     let mut results = Vec::new();
 
     for test_block in &program.tests {
         for case in &test_block.cases {
-            let result = run_single_test(program, case)?;
+            let result = run_single_test(program, case, options)?;
             results.push(result);
         }
     }
@@ -85,7 +124,11 @@ pub fn run_tests(program: &Program) -> EvalResult<TestRunSummary> {
 }
 
 /// Run a single test case with a fresh SpaceInstance.
-fn run_single_test(program: &Program, case: &TestCase) -> EvalResult<TestResult> {
+fn run_single_test(
+    program: &Program,
+    case: &TestCase,
+    options: &TestOptions,
+) -> EvalResult<TestResult> {
     // Resolve mock responses from `with_responses` block
     let mocks = resolve_mocks(program, case)?;
 
@@ -97,24 +140,39 @@ fn run_single_test(program: &Program, case: &TestCase) -> EvalResult<TestResult>
         instance.set_mock_responses(mocks);
     }
 
+    install_surface_builtins(&mut instance);
+
     // Execute the test body — statements that dispatch actions and check assertions
-    let exec_result = execute_test_body(&mut instance, &case.body, &program.space.body);
+    instance.push_frame(RuntimeFrame::new(
+        FrameKind::Test,
+        case.description.clone(),
+        case.span,
+    ));
+    let exec_result = execute_test_body(&mut instance, &case.body, &program.space.body, options);
+    let runtime_error = exec_result
+        .as_ref()
+        .err()
+        .map(|e| instance.runtime_error(e));
+    instance.pop_frame();
 
     match exec_result {
         Ok(()) => Ok(TestResult {
             description: case.description.clone(),
             passed: true,
             error: None,
+            runtime_error: None,
         }),
         Err(EvalError::AssertionFailed(msg)) => Ok(TestResult {
             description: case.description.clone(),
             passed: false,
             error: Some(msg),
+            runtime_error,
         }),
         Err(e) => Ok(TestResult {
             description: case.description.clone(),
             passed: false,
             error: Some(format!("{e}")),
+            runtime_error,
         }),
     }
 }
@@ -151,9 +209,13 @@ fn execute_test_body(
     instance: &mut SpaceInstance,
     body: &Block,
     space_body: &SpaceBody,
+    options: &TestOptions,
 ) -> EvalResult<()> {
     for stmt in &body.stmts {
-        execute_test_stmt(instance, stmt, space_body)?;
+        if let Err(e) = execute_test_stmt(instance, stmt, space_body, options) {
+            instance.record_failure(&e, stmt_span(stmt));
+            return Err(e);
+        }
     }
     Ok(())
 }
@@ -163,10 +225,11 @@ fn execute_test_stmt(
     instance: &mut SpaceInstance,
     stmt: &Stmt,
     space_body: &SpaceBody,
+    options: &TestOptions,
 ) -> EvalResult<()> {
     match stmt {
         Stmt::Expr(expr_stmt) => {
-            execute_test_expr(instance, &expr_stmt.expr, space_body)?;
+            execute_test_expr(instance, &expr_stmt.expr, space_body, options)?;
             Ok(())
         }
         Stmt::Assert(assert) => {
@@ -182,7 +245,16 @@ fn execute_test_stmt(
             Ok(())
         }
         Stmt::Let(binding) => {
-            let value = instance.eval_expr_public(&binding.value)?;
+            let value = match &binding.value.kind {
+                ExprKind::Call { name, args } if name.name == "render" => {
+                    let view = match args.first().map(|a| &a.kind) {
+                        Some(ExprKind::Identifier(view)) => view.as_str(),
+                        _ => "main",
+                    };
+                    SurfaceNode::to_value(&instance.render_view(view)?)
+                }
+                _ => instance.eval_expr_public(&binding.value)?,
+            };
             if let Some(name) = &binding.name {
                 instance.define_in_env(&name.name, value);
             }
@@ -191,17 +263,17 @@ fn execute_test_stmt(
         Stmt::If(if_expr) => {
             let cond = instance.eval_expr_public(&if_expr.condition)?;
             if cond.is_truthy() {
-                execute_test_body(instance, &if_expr.then_block, space_body)?;
+                execute_test_body(instance, &if_expr.then_block, space_body, options)?;
             } else if let Some(else_branch) = &if_expr.else_branch {
                 match else_branch {
                     ElseBranch::ElseIf(elif) => {
                         let cond = instance.eval_expr_public(&elif.condition)?;
                         if cond.is_truthy() {
-                            execute_test_body(instance, &elif.then_block, space_body)?;
+                            execute_test_body(instance, &elif.then_block, space_body, options)?;
                         }
                     }
                     ElseBranch::Block(block) => {
-                        execute_test_body(instance, block, space_body)?;
+                        execute_test_body(instance, block, space_body, options)?;
                     }
                 }
             }
@@ -216,7 +288,7 @@ fn execute_test_stmt(
                     if let Some(idx) = &for_expr.index {
                         instance.define_in_env(&idx.name, Value::Number(i as f64));
                     }
-                    execute_test_body(instance, &for_expr.body, space_body)?;
+                    execute_test_body(instance, &for_expr.body, space_body, options)?;
                     instance.pop_scope();
                 }
             }
@@ -237,6 +309,7 @@ fn execute_test_expr(
     instance: &mut SpaceInstance,
     expr: &Expr,
     space_body: &SpaceBody,
+    options: &TestOptions,
 ) -> EvalResult<Value> {
     match &expr.kind {
         ExprKind::Call { name, args } => {
@@ -249,12 +322,45 @@ fn execute_test_expr(
                     arg_vals.push(instance.eval_expr_public(arg)?);
                 }
                 let result = instance.dispatch(&name.name, arg_vals)?;
-                if !result.committed {
-                    if let Some(err) = result.invariant_error {
-                        return Err(EvalError::InvariantViolation(err));
+                check_committed(result)?;
+                Ok(Value::Nil)
+            } else if name.name == "tick" || name.name == "tick_n" {
+                let args = eval_args(instance, args)?;
+                let (frames, dt) = match (name.name.as_str(), args.as_slice()) {
+                    ("tick", [Value::Number(dt)]) => (1, *dt),
+                    ("tick_n", [Value::Number(n), Value::Number(dt)])
+                        if *n >= 0.0 && n.fract() == 0.0 =>
+                    {
+                        (*n as u64, *dt)
                     }
+                    _ => {
+                        return Err(EvalError::TypeMismatch(format!(
+                            "invalid arguments to {}()",
+                            name.name
+                        )))
+                    }
+                };
+                for _ in 0..frames {
+                    let result = instance.call_update(dt)?;
+                    check_committed(result)?;
                 }
                 Ok(Value::Nil)
+            } else if name.name == "send_event" {
+                let event = eval_args(instance, args)?
+                    .into_iter()
+                    .next()
+                    .unwrap_or(Value::Nil);
+                let result = instance.call_handle_event(event)?;
+                check_committed(result)?;
+                Ok(Value::Nil)
+            } else if name.name == "assert_component" {
+                let args = eval_args(instance, args)?;
+                assert_component(&args)?;
+                Ok(Value::Nil)
+            } else if name.name == "assert_snapshot" {
+                let args = eval_args(instance, args)?;
+                assert_snapshot(&args, options)?;
+                Ok(Value::Nil)
             } else {
                 instance.eval_expr_public(expr)
             }
@@ -262,3 +368,153 @@ fn execute_test_expr(
         _ => instance.eval_expr_public(expr),
     }
 }
+
+/// Surface a rolled-back dispatch, frame or event as an invariant failure.
+fn check_committed(result: ActionResult) -> EvalResult<()> {
+    if !result.committed {
+        if let Some(err) = result.invariant_error {
+            return Err(EvalError::InvariantViolation(err));
+        }
+    }
+    Ok(())
+}
+
+fn eval_args(instance: &mut SpaceInstance, args: &[Expr]) -> EvalResult<Vec<Value>> {
+    args.iter().map(|a| instance.eval_expr_public(a)).collect()
+}
+
+// ══════════════════════════════════════════════════════════════════════════════
+// Surface assertions
+// ══════════════════════════════════════════════════════════════════════════════
+
+/// Install `count_components` as a function value so it can be used inside
+/// any expression (e.g. `assert count_components(s, "Text") == 2`).
+fn install_surface_builtins(instance: &mut SpaceInstance) {
+    let count = StdlibFn(Arc::new(|args: Vec<Value>| {
+        let (nodes, component, props) =
+            surface_query(&args).map_err(|e| StdlibError::RuntimeError(e.to_string()))?;
+        Ok(Value::Number(
+            SurfaceNode::count_matching(&nodes, &component, &props) as f64,
+        ))
+    }));
+    instance.define_in_env("count_components", Value::Function(count));
+}
+
+/// Decode `(surface, component, props?)` arguments.
+fn surface_query(
+    args: &[Value],
+) -> EvalResult<(Vec<SurfaceNode>, String, BTreeMap<String, Value>)> {
+    let nodes = match args.first() {
+        Some(surface) => SurfaceNode::from_value(surface)?,
+        None => return Err(EvalError::Runtime("missing Surface argument".into())),
+    };
+    let component = match args.get(1) {
+        Some(Value::String(s)) => s.clone(),
+        _ => {
+            return Err(EvalError::TypeMismatch(
+                "component name must be a string".into(),
+            ))
+        }
+    };
+    let props = match args.get(2) {
+        Some(Value::Record { fields, .. }) => fields.clone(),
+        None | Some(Value::Nil) => BTreeMap::new(),
+        Some(other) => {
+            return Err(EvalError::TypeMismatch(format!(
+                "props must be a record, got {}",
+                other.type_name()
+            )))
+        }
+    };
+    Ok((nodes, component, props))
+}
+
+fn assert_component(args: &[Value]) -> EvalResult<()> {
+    let (nodes, component, props) = surface_query(args)?;
+    if SurfaceNode::count_matching(&nodes, &component, &props) > 0 {
+        return Ok(());
+    }
+    let wanted = if props.is_empty() {
+        component
+    } else {
+        let props = Value::Record {
+            type_name: None,
+            fields: props,
+        };
+        format!(
+            "{component} {}",
+            SpaceInstance::value_to_json_public(&props)
+        )
+    };
+    Err(EvalError::AssertionFailed(format!(
+        "no component matching {wanted} in rendered surface"
+    )))
+}
+
+fn assert_snapshot(args: &[Value], options: &TestOptions) -> EvalResult<()> {
+    let nodes = match args.first() {
+        Some(surface) => SurfaceNode::from_value(surface)?,
+        None => return Err(EvalError::Runtime("missing Surface argument".into())),
+    };
+    let name = match args.get(1) {
+        Some(Value::String(s)) => s.as_str(),
+        _ => {
+            return Err(EvalError::TypeMismatch(
+                "snapshot name must be a string".into(),
+            ))
+        }
+    };
+    let actual = SpaceInstance::surface_to_json(&nodes);
+    check_snapshot(name, &actual, options)
+}
+
+/// Compare `actual` with the golden file for `name`, or write it when
+/// `update_snapshots` is set. Shared with hosts running compiled tests.
+pub fn check_snapshot(
+    name: &str,
+    actual: &serde_json::Value,
+    options: &TestOptions,
+) -> EvalResult<()> {
+    if name.is_empty()
+        || !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+    {
+        return Err(EvalError::Runtime(format!(
+            "invalid snapshot name '{name}' (use letters, digits, '_' or '-')"
+        )));
+    }
+    let Some(dir) = &options.snapshot_dir else {
+        return Err(EvalError::Runtime(format!(
+            "assert_snapshot '{name}': no snapshot directory configured"
+        )));
+    };
+    let path = dir.join(format!("{name}.surface.json"));
+    let rendered =
+        serde_json::to_string_pretty(actual).map_err(|e| EvalError::Runtime(e.to_string()))?;
+
+    if options.update_snapshots {
+        std::fs::create_dir_all(dir)
+            .and_then(|_| std::fs::write(&path, format!("{rendered}\n")))
+            .map_err(|e| EvalError::Runtime(format!("{}: {e}", path.display())))?;
+        return Ok(());
+    }
+
+    let expected = match std::fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(_) => {
+            return Err(EvalError::AssertionFailed(format!(
+                "snapshot '{name}' does not exist (run with update_snapshots to create it)"
+            )))
+        }
+    };
+    let expected_json: serde_json::Value = serde_json::from_str(&expected)
+        .map_err(|e| EvalError::Runtime(format!("{}: {e}", path.display())))?;
+    if expected_json != *actual {
+        return Err(EvalError::AssertionFailed(format!(
+            "snapshot '{name}' mismatch\n--- expected\n{}\n+++ actual\n{rendered}",
+            expected.trim_end()
+        )));
+    }
+    Ok(())
+}
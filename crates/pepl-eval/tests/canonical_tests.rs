@@ -8,9 +8,13 @@
 //! - Capability mocking via with_responses
 //! - Game loop (update/handleEvent)
 //! - Determinism (100-iteration)
+//! - Compiled fast path parity: every space runs on `SpaceInstance` and
+//!   `CompiledSpace` in lockstep, with outcomes, state and gas compared
 //! - Golden reference capture
 
-use pepl_eval::SpaceInstance;
+use pepl_eval::{
+    ActionResult, CompiledSpace, EvalResult, MockResponse, SpaceInstance, SurfaceNode,
+};
 use pepl_lexer::Lexer;
 use pepl_parser::Parser;
 use pepl_stdlib::Value;
@@ -40,9 +44,88 @@ fn parse(source: &str) -> pepl_types::ast::Program {
     result.program.expect("no program after successful parse")
 }
 
-fn instance(source: &str) -> SpaceInstance {
-    let prog = parse(source);
-    SpaceInstance::new(&prog).expect("failed to create SpaceInstance")
+fn instance(source: &str) -> Lockstep {
+    Lockstep::new(&parse(source))
+}
+
+/// A space run on the tree-walker and on the compiled fast path in
+/// lockstep, so every test here holds `CompiledSpace` to the same
+/// assertions: each entry point must have the same outcome, state and gas
+/// on both, and state reads compare the two.  Views are not compiled, so
+/// `render` goes to the tree-walker alone.
+struct Lockstep {
+    si: SpaceInstance,
+    cs: CompiledSpace,
+}
+
+impl Lockstep {
+    fn new(prog: &pepl_types::ast::Program) -> Self {
+        Self {
+            si: SpaceInstance::new(prog).expect("failed to create SpaceInstance"),
+            cs: CompiledSpace::new(prog).expect("failed to create CompiledSpace"),
+        }
+    }
+
+    fn dispatch(&mut self, action: &str, args: Vec<Value>) -> EvalResult<ActionResult> {
+        let expected = self.si.dispatch(action, args.clone());
+        let actual = self.cs.dispatch(action, args);
+        self.agree(action, expected, actual)
+    }
+
+    fn call_update(&mut self, dt: f64) -> EvalResult<ActionResult> {
+        let expected = self.si.call_update(dt);
+        let actual = self.cs.call_update(dt);
+        self.agree("update", expected, actual)
+    }
+
+    fn call_handle_event(&mut self, event: Value) -> EvalResult<ActionResult> {
+        let expected = self.si.call_handle_event(event.clone());
+        let actual = self.cs.call_handle_event(event);
+        self.agree("handleEvent", expected, actual)
+    }
+
+    fn set_mock_responses(&mut self, mocks: Vec<MockResponse>) {
+        self.si.set_mock_responses(mocks.clone());
+        self.cs.set_mock_responses(mocks);
+    }
+
+    fn get_state(&self, name: &str) -> Option<&Value> {
+        let value = self.cs.get_state(name);
+        assert_eq!(value, self.si.get_state(name), "compiled '{name}'");
+        value
+    }
+
+    fn state_snapshot(&self) -> BTreeMap<String, Value> {
+        let snapshot = self.cs.state_snapshot();
+        assert_eq!(snapshot, self.si.state_snapshot(), "compiled state");
+        snapshot
+    }
+
+    fn gas_used(&self) -> u64 {
+        assert_eq!(self.cs.gas_used(), self.si.gas_used(), "compiled gas");
+        self.cs.gas_used()
+    }
+
+    fn render(&mut self) -> EvalResult<Vec<SurfaceNode>> {
+        self.si.render()
+    }
+
+    /// The compiled result of `entry`, once it matches the tree-walker's.
+    fn agree(
+        &self,
+        entry: &str,
+        expected: EvalResult<ActionResult>,
+        actual: EvalResult<ActionResult>,
+    ) -> EvalResult<ActionResult> {
+        match (&expected, &actual) {
+            (Ok(a), Ok(b)) => assert_eq!(a.committed, b.committed, "{entry}"),
+            (Err(a), Err(b)) => assert_eq!(a.to_string(), b.to_string(), "{entry}"),
+            (a, b) => panic!("{entry}: tree-walker {a:?}, compiled {b:?}"),
+        }
+        self.state_snapshot();
+        self.gas_used();
+        actual
+    }
 }
 
 /// Value helper — number
@@ -653,7 +736,7 @@ space PomodoroShort {
   view main() -> Surface { Column { } { } }
 }
 "#);
-    let mut si2 = SpaceInstance::new(&prog).unwrap();
+    let mut si2 = Lockstep::new(&prog);
     assert_eq!(si2.get_state("seconds_left"), Some(&num(1.0)));
 
     si2.dispatch("tick", vec![]).unwrap(); // 1 → 0
@@ -1115,6 +1198,145 @@ tests {
     assert_eq!(summary.failed, 0);
 }
 
+// ══════════════════════════════════════════════════════════════════════════════
+// Test Runner — render assertions & snapshots
+// ══════════════════════════════════════════════════════════════════════════════
+
+const RENDER_COUNTER: &str = r#"
+space Counter {
+  state { count: number = 0 }
+  action increment() { set count = count + 1 }
+  view main() -> Surface {
+    Column { } {
+      Text { value: "Count: ${count}" }
+      Row { } {
+        Button { label: "Add", on_tap: increment }
+        if count > 0 {
+          Text { value: "positive" }
+        }
+      }
+    }
+  }
+}
+"#;
+
+fn render_program(tests: &str) -> pepl_types::ast::Program {
+    parse(&format!("{RENDER_COUNTER}\ntests {{\n{tests}\n}}\n"))
+}
+
+fn snapshot_dir(name: &str) -> std::path::PathBuf {
+    let dir = std::env::temp_dir().join(format!("pepl-snapshots-{}-{name}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
+
+#[test]
+fn test_runner_render_assert_component() {
+    let prog = render_program(r#"
+  test "button and text" {
+    let s = render(main)
+    assert_component(s, "Button", { label: "Add" })
+    assert_component(s, "Text", { value: "Count: 0" })
+    assert count_components(s, "Text") == 1
+    increment()
+    let after = render(main)
+    assert count_components(after, "Text") == 2
+    assert_component(after, "Text", { value: "positive" })
+  }
+"#);
+    let summary = pepl_eval::run_tests(&prog).unwrap();
+    assert_eq!(summary.failed, 0, "{summary}");
+}
+
+#[test]
+fn test_runner_render_assert_component_failure() {
+    let prog = render_program(r#"
+  test "missing button" {
+    let s = render()
+    assert_component(s, "Button", { label: "Remove" })
+  }
+"#);
+    let summary = pepl_eval::run_tests(&prog).unwrap();
+    assert_eq!(summary.failed, 1);
+    let err = summary.results[0].error.as_ref().unwrap();
+    assert!(err.contains("Button"), "{err}");
+}
+
+#[test]
+fn test_runner_snapshot_create_and_compare() {
+    let prog = render_program(r#"
+  test "snapshot" {
+    increment()
+    let s = render(main)
+    assert_snapshot(s, "counter_one")
+  }
+"#);
+    let dir = snapshot_dir("compare");
+    let mut options = pepl_eval::TestOptions {
+        snapshot_dir: Some(dir.clone()),
+        update_snapshots: false,
+    };
+
+    // Missing golden file fails without the update flag
+    let summary = pepl_eval::run_tests_with_options(&prog, &options).unwrap();
+    assert_eq!(summary.failed, 1);
+    assert!(summary.results[0].error.as_ref().unwrap().contains("does not exist"));
+
+    // Update writes the golden file
+    options.update_snapshots = true;
+    let summary = pepl_eval::run_tests_with_options(&prog, &options).unwrap();
+    assert_eq!(summary.passed, 1);
+    let golden = std::fs::read_to_string(dir.join("counter_one.surface.json")).unwrap();
+    assert!(golden.contains("Count: 1"));
+
+    // Compare passes against the written file
+    options.update_snapshots = false;
+    let summary = pepl_eval::run_tests_with_options(&prog, &options).unwrap();
+    assert_eq!(summary.passed, 1);
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_runner_snapshot_mismatch() {
+    let prog = render_program(r#"
+  test "snapshot" {
+    let s = render(main)
+    assert_snapshot(s, "counter")
+  }
+"#);
+    let dir = snapshot_dir("mismatch");
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("counter.surface.json"), "[]").unwrap();
+    let options = pepl_eval::TestOptions {
+        snapshot_dir: Some(dir.clone()),
+        update_snapshots: false,
+    };
+    let summary = pepl_eval::run_tests_with_options(&prog, &options).unwrap();
+    assert_eq!(summary.failed, 1);
+    let err = summary.results[0].error.as_ref().unwrap();
+    assert!(err.contains("mismatch") && err.contains("Count: 0"), "{err}");
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_runner_snapshot_requires_dir() {
+    let prog = render_program(r#"
+  test "snapshot" {
+    let s = render(main)
+    assert_snapshot(s, "counter")
+  }
+"#);
+    let summary = pepl_eval::run_tests(&prog).unwrap();
+    assert_eq!(summary.failed, 1);
+    assert!(summary.results[0]
+        .error
+        .as_ref()
+        .unwrap()
+        .contains("no snapshot directory"));
+}
+
 // ══════════════════════════════════════════════════════════════════════════════
 // Game Loop (update / handleEvent)
 // ══════════════════════════════════════════════════════════════════════════════
@@ -1134,7 +1356,7 @@ space Timer {
   }
 }
 "#);
-    let mut si = SpaceInstance::new(&prog).unwrap();
+    let mut si = Lockstep::new(&prog);
     assert_eq!(si.get_state("elapsed"), Some(&num(0.0)));
 
     si.call_update(0.016).unwrap();
@@ -1161,7 +1383,7 @@ space Game {
   }
 }
 "#);
-    let mut si = SpaceInstance::new(&prog).unwrap();
+    let mut si = Lockstep::new(&prog);
     assert_eq!(si.get_state("last_event"), Some(&s("none")));
 
     let event = Value::Record {
@@ -1193,7 +1415,7 @@ space Timer {
   }
 }
 "#);
-    let mut si = SpaceInstance::new(&prog).unwrap();
+    let mut si = Lockstep::new(&prog);
 
     let r = si.call_update(0.5).unwrap();
     assert!(r.committed);
@@ -1205,6 +1427,140 @@ space Timer {
     assert_eq!(si.get_state("elapsed"), Some(&num(0.5)));
 }
 
+#[test]
+fn test_runner_tick_and_tick_n() {
+    let prog = parse(r#"
+space Ball {
+  state {
+    y: number = 0
+    frames: number = 0
+  }
+
+  view main() -> Surface { Column { } { } }
+
+  update(dt: number) {
+    set y = y + 10 * dt
+    set frames = frames + 1
+  }
+}
+
+tests {
+  test "single frame" {
+    tick(0.5)
+    assert y == 5
+    assert frames == 1
+  }
+
+  test "many frames" {
+    tick_n(4, 0.25)
+    assert y == 10
+    assert frames == 4
+  }
+}
+"#);
+    let summary = pepl_eval::run_tests(&prog).unwrap();
+    assert_eq!(summary.failed, 0, "{summary}");
+    assert_eq!(summary.passed, 2);
+}
+
+#[test]
+fn test_runner_tick_invariant_violation() {
+    let prog = parse(r#"
+space Timer {
+  state {
+    elapsed: number = 0
+  }
+
+  invariant bounded { elapsed <= 1 }
+
+  view main() -> Surface { Column { } { } }
+
+  update(dt: number) {
+    set elapsed = elapsed + dt
+  }
+}
+
+tests {
+  test "overflows" {
+    tick_n(3, 0.5)
+  }
+}
+"#);
+    let summary = pepl_eval::run_tests(&prog).unwrap();
+    assert_eq!(summary.failed, 1);
+    assert!(summary.results[0].error.as_ref().unwrap().contains("bounded"));
+}
+
+#[test]
+fn test_runner_reports_runtime_error_frames() {
+    let prog = parse(r#"
+space Calc {
+  state {
+    value: number = 0
+  }
+
+  action divide(d: number) {
+    set value = 1 / d
+  }
+
+  view main() -> Surface { Column { } { } }
+}
+
+tests {
+  test "divide by zero" {
+    divide(0)
+  }
+
+  test "bad assert" {
+    assert value == 1
+  }
+}
+"#);
+    let summary = pepl_eval::run_tests(&prog).unwrap();
+    assert_eq!(summary.failed, 2);
+
+    let rt = summary.results[0].runtime_error.as_ref().unwrap();
+    assert_eq!(rt.code, pepl_types::ErrorCode::ARITHMETIC_TRAP);
+    assert_eq!(rt.span.unwrap().start_line, 8);
+    let frames: Vec<_> = rt.frames.iter().map(|f| (f.kind, f.name.as_str())).collect();
+    assert_eq!(frames, vec![
+        (pepl_types::FrameKind::Test, "divide by zero"),
+        (pepl_types::FrameKind::Action, "divide"),
+    ]);
+
+    let rt = summary.results[1].runtime_error.as_ref().unwrap();
+    assert_eq!(rt.code, pepl_types::ErrorCode::ASSERTION_FAILED);
+    assert_eq!(rt.span.unwrap().start_line, 20);
+    assert_eq!(rt.frames.len(), 1);
+}
+
+#[test]
+fn test_runner_send_event() {
+    let prog = parse(r#"
+space Game {
+  state {
+    taps: number = 0
+  }
+
+  view main() -> Surface { Column { } { } }
+
+  handleEvent(event: InputEvent) {
+    set taps = taps + 1
+  }
+}
+
+tests {
+  test "taps are counted" {
+    send_event({ type: "tap", x: 10, y: 20 })
+    send_event({ type: "tap", x: 0, y: 0 })
+    assert taps == 2
+  }
+}
+"#);
+    let summary = pepl_eval::run_tests(&prog).unwrap();
+    assert_eq!(summary.failed, 0, "{summary}");
+}
+
 // ══════════════════════════════════════════════════════════════════════════════
 // Determinism
 // ══════════════════════════════════════════════════════════════════════════════
@@ -1261,6 +1617,135 @@ fn determinism_all_canonical_100_iterations() {
     }
 }
 
+// ══════════════════════════════════════════════════════════════════════════════
+// Compiled fast path — parity with the tree-walker
+// ══════════════════════════════════════════════════════════════════════════════
+
+/// Dispatch `steps` in lockstep, including the ones that fail or roll
+/// back; the tree-walker and the compiled space must agree on every one.
+fn assert_compiled_parity(source: &str, steps: &[(&str, Vec<Value>)]) {
+    let mut space = instance(source);
+    for (action, args) in steps {
+        let _ = space.dispatch(action, args.clone());
+    }
+}
+
+#[test]
+fn compiled_parity_counter() {
+    assert_compiled_parity(
+        COUNTER_SOURCE,
+        &[
+            ("decrement", vec![]),
+            ("increment", vec![]),
+            ("increment", vec![]),
+            ("decrement", vec![]),
+        ],
+    );
+}
+
+#[test]
+fn compiled_parity_todo_list() {
+    assert_compiled_parity(
+        TODO_LIST_SOURCE,
+        &[
+            ("add_todo", vec![]),
+            ("update_input", vec![s("Task 1")]),
+            ("add_todo", vec![]),
+            ("update_input", vec![s("Task 2")]),
+            ("add_todo", vec![]),
+            ("toggle", vec![num(1.0)]),
+            ("toggle", vec![num(5.0)]),
+        ],
+    );
+}
+
+#[test]
+fn compiled_parity_unit_converter() {
+    assert_compiled_parity(
+        UNIT_CONVERTER_SOURCE,
+        &[("set_value", vec![s("100")]), ("set_value", vec![s("abc")])],
+    );
+}
+
+#[test]
+fn compiled_parity_weather_dashboard() {
+    assert_compiled_parity(
+        WEATHER_DASHBOARD_SOURCE,
+        &[("update_city", vec![s("Paris")]), ("fetch_weather", vec![])],
+    );
+}
+
+#[test]
+fn compiled_parity_pomodoro() {
+    let mut steps = vec![("start_work", vec![])];
+    steps.extend(std::iter::repeat_with(|| ("tick", vec![])).take(5));
+    steps.push(("start_break", vec![]));
+    steps.push(("reset", vec![]));
+    assert_compiled_parity(POMODORO_SOURCE, &steps);
+}
+
+#[test]
+fn compiled_parity_habit_tracker() {
+    assert_compiled_parity(
+        HABIT_TRACKER_SOURCE,
+        &[
+            ("update_new_habit", vec![s("Read")]),
+            ("add_habit", vec![]),
+            ("mark_done", vec![num(0.0)]),
+            ("mark_done", vec![num(3.0)]),
+        ],
+    );
+}
+
+#[test]
+fn compiled_parity_quiz_app() {
+    assert_compiled_parity(
+        QUIZ_APP_SOURCE,
+        &[
+            ("select_answer", vec![s("4")]),
+            ("next_question", vec![]),
+            ("select_answer", vec![s("London")]),
+            ("next_question", vec![]),
+            ("select_answer", vec![s("Jupiter")]),
+            ("next_question", vec![]),
+        ],
+    );
+}
+
+#[test]
+fn compiled_parity_game_loop() {
+    let prog = parse(r#"
+space Timer {
+  state {
+    elapsed: number = 0
+    last_event: string = "none"
+  }
+
+  invariant bounded { elapsed <= 1.0 }
+
+  view main() -> Surface { Column { } { } }
+
+  update(dt: number) {
+    set elapsed = elapsed + dt
+  }
+
+  handleEvent(event: InputEvent) {
+    set last_event = event.type
+  }
+}
+"#);
+    let mut space = Lockstep::new(&prog);
+    for dt in [0.5, 0.6, 0.25] {
+        space.call_update(dt).unwrap();
+    }
+    let event = Value::Record {
+        type_name: None,
+        fields: BTreeMap::from([("type".to_string(), s("tap"))]),
+    };
+    space.call_handle_event(event).unwrap();
+    assert_eq!(space.get_state("last_event"), Some(&s("tap")));
+}
+
 // ══════════════════════════════════════════════════════════════════════════════
 // Golden Reference — State + Surface JSON capture
 // ══════════════════════════════════════════════════════════════════════════════
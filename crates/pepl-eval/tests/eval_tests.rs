@@ -8,13 +8,14 @@
 //! - expression evaluation (arithmetic, string, list, record)
 //! - view rendering
 //! - gas metering
+//! - the compiled fast path agrees with the tree-walker
 //! - canonical Counter / TodoList / UnitConverter examples
 
-use pepl_eval::{EvalError, SpaceInstance, SurfaceNode};
+use pepl_eval::{CompiledSpace, EvalError, SpaceInstance, SurfaceNode};
 use pepl_lexer::Lexer;
 use pepl_parser::Parser;
 use pepl_stdlib::Value;
-use pepl_types::SourceFile;
+use pepl_types::{ErrorCode, FrameKind, SourceFile};
 use std::collections::BTreeMap;
 
 // ══════════════════════════════════════════════════════════════════════════════
@@ -283,6 +284,113 @@ space T {
     assert_eq!(si.get_state("x"), Some(&Value::Number(1.0)));
 }
 
+#[test]
+fn invariant_violation_explanation() {
+    let mut si = instance(
+        r#"
+space T {
+  state {
+    balance: number = 10
+    limit: number = 0
+  }
+  invariant covered { balance - limit >= 0 and limit >= 0 }
+  action withdraw(n: number) {
+    set balance = balance - n
+    set limit = 2
+  }
+  view main() -> Surface { Column { } { } }
+}
+"#,
+    );
+    let r = si.dispatch("withdraw", vec![Value::Number(9.0)]).unwrap();
+    assert!(!r.committed);
+    let exp = r.explanation.expect("explanation");
+    assert_eq!(exp.invariant, "covered");
+
+    // Root: the whole condition; left operand of `and` is the failing one
+    assert_eq!(exp.tree.source, "balance - limit >= 0 and limit >= 0");
+    assert_eq!(exp.tree.value, Some(Value::Bool(false)));
+    assert_eq!(exp.tree.children.len(), 1, "right side short-circuited");
+    let cmp = &exp.tree.children[0];
+    assert_eq!(cmp.source, "balance - limit >= 0");
+    let sub = &cmp.children[0];
+    assert_eq!(sub.source, "balance - limit");
+    assert_eq!(sub.value, Some(Value::Number(-1.0)));
+
+    let names: Vec<&str> = exp.fields.iter().map(|f| f.name.as_str()).collect();
+    assert_eq!(names, vec!["balance", "limit"]);
+    let balance = &exp.fields[0];
+    assert_eq!(balance.before, Some(Value::Number(10.0)));
+    assert_eq!(balance.after, Some(Value::Number(1.0)));
+    assert_eq!(balance.writes.len(), 1);
+    assert_eq!(balance.writes[0].span.start_line, 9);
+    assert_eq!(exp.fields[1].writes[0].span.start_line, 10);
+}
+
+#[test]
+fn invariant_explanation_json_and_text() {
+    let mut si = instance(
+        r#"
+space T {
+  state { x: number = 1 }
+  invariant positive { x > 0 }
+  action sub(n: number) { set x = x - n }
+  view main() -> Surface { Column { } { } }
+}
+"#,
+    );
+    let r = si.dispatch("sub", vec![Value::Number(5.0)]).unwrap();
+    let exp = r.explanation.expect("explanation");
+
+    let json = exp.to_json();
+    assert_eq!(json["invariant"], "positive");
+    assert_eq!(json["tree"]["source"], "x > 0");
+    assert_eq!(json["tree"]["value"], false);
+    assert_eq!(json["tree"]["children"][0]["value"], -4);
+    assert_eq!(json["fields"][0]["before"], 1);
+    assert_eq!(json["fields"][0]["after"], -4);
+    assert_eq!(json["fields"][0]["writes"][0]["target"], "x");
+
+    let text = exp.to_string();
+    assert!(text.starts_with("invariant 'positive' violated"), "{text}");
+    assert!(text.contains("  x > 0 => false\n    x => -4\n"), "{text}");
+    assert!(text.contains("x: 1 -> -4"), "{text}");
+    assert!(text.contains("written by `set x` at 5:27"), "{text}");
+}
+
+#[test]
+fn invariant_explanation_is_free() {
+    let source = r#"
+space T {
+  state { count: number = 0 }
+  derived { doubled: number = count * 2 }
+  invariant small { count + doubled < 6 and count >= 0 }
+  action bump() {
+    set count = count + 5
+  }
+  view main() -> Surface { Column { } { } }
+}
+"#;
+    let prog = parse(source);
+    let mut probe = SpaceInstance::new(&prog).unwrap();
+    probe.dispatch("bump", vec![]).unwrap();
+    let used = probe.gas_used();
+
+    // With exactly the gas the rollback needs, explaining it still works
+    let mut si = SpaceInstance::with_gas_limit(&prog, used).unwrap();
+    let mut cs = CompiledSpace::with_gas_limit(&prog, used).unwrap();
+    for (result, gas) in [
+        (si.dispatch("bump", vec![]).unwrap(), si.gas_used()),
+        (cs.dispatch("bump", vec![]).unwrap(), cs.gas_used()),
+    ] {
+        assert!(!result.committed);
+        let exp = result.explanation.expect("explanation");
+        assert_eq!(exp.tree.value, Some(Value::Bool(false)));
+        assert_eq!(exp.tree.children[0].children[0].value, Some(Value::Number(15.0)));
+        assert_eq!(gas, used);
+    }
+}
+
 // ══════════════════════════════════════════════════════════════════════════════
 // Expression evaluation
 // ══════════════════════════════════════════════════════════════════════════════
@@ -680,6 +788,137 @@ fn surface_to_json() {
     assert_eq!(arr[0]["props"]["value"], "hi");
 }
 
+// ══════════════════════════════════════════════════════════════════════════════
+// Structured runtime errors
+// ══════════════════════════════════════════════════════════════════════════════
+
+#[test]
+fn runtime_error_has_span_code_and_action_frame() {
+    let mut si = instance(
+        r#"
+space T {
+  state { x: number = 1 }
+  action divide(d: number) {
+    set x = 10
+    set x = x / d
+  }
+  view main() -> Surface { Column { } { } }
+}
+"#,
+    );
+    let err = si.dispatch("divide", vec![Value::Number(0.0)]).unwrap_err();
+    assert!(matches!(err, EvalError::ArithmeticTrap(_)));
+
+    let rt = si.last_error().expect("structured error");
+    assert_eq!(rt.code, ErrorCode::ARITHMETIC_TRAP);
+    let span = rt.span.expect("span");
+    assert_eq!((span.start_line, span.start_col), (6, 13));
+    assert_eq!(rt.frames.len(), 1);
+    assert_eq!(rt.frames[0].kind, FrameKind::Action);
+    assert_eq!(rt.frames[0].name, "divide");
+    assert_eq!(rt.frames[0].span.start_line, 4);
+}
+
+#[test]
+fn runtime_error_inside_lambda_has_lambda_frame() {
+    let mut si = instance(
+        r#"
+space T {
+  state { xs: list<number> = [1, 0] }
+  action invert() {
+    set xs = list.map(xs, fn(x: number) {
+      1 / x
+    })
+  }
+  view main() -> Surface { Column { } { } }
+}
+"#,
+    );
+    assert!(si.dispatch("invert", vec![]).is_err());
+
+    let rt = si.last_error().expect("structured error");
+    assert_eq!(rt.code, ErrorCode::ARITHMETIC_TRAP);
+    assert_eq!(rt.span.expect("span").start_line, 6);
+    let kinds: Vec<FrameKind> = rt.frames.iter().map(|f| f.kind).collect();
+    assert_eq!(kinds, vec![FrameKind::Action, FrameKind::Lambda]);
+    assert_eq!(rt.frames[1].span.start_line, 5);
+}
+
+#[test]
+fn runtime_error_in_derived_field_and_view() {
+    let mut si = instance(
+        r#"
+space T {
+  state { d: number = 1 }
+  derived {
+    inv: number = 1 / d
+  }
+  action zero() {
+    set d = 0
+  }
+  view main() -> Surface {
+    Text { value: "{inv}" }
+  }
+}
+"#,
+    );
+    assert!(si.dispatch("zero", vec![]).is_err());
+    let rt = si.last_error().expect("structured error");
+    let frames: Vec<(FrameKind, &str)> =
+        rt.frames.iter().map(|f| (f.kind, f.name.as_str())).collect();
+    assert_eq!(
+        frames,
+        vec![(FrameKind::Action, "zero"), (FrameKind::Derived, "inv")]
+    );
+    assert_eq!(rt.span.expect("span").start_line, 5);
+
+    si.render().unwrap();
+    assert!(si.last_error().is_none());
+}
+
+#[test]
+fn invariant_violation_has_invariant_frame() {
+    let mut si = instance(
+        r#"
+space T {
+  state { n: number = 0 }
+  invariant non_negative { n >= 0 }
+  action dec() {
+    set n = n - 1
+  }
+  view main() -> Surface { Column { } { } }
+}
+"#,
+    );
+    let result = si.dispatch("dec", vec![]).unwrap();
+    assert!(!result.committed);
+
+    let rt = si.last_error().expect("structured error");
+    assert_eq!(rt.code, ErrorCode::INVARIANT_VIOLATED);
+    let kinds: Vec<FrameKind> = rt.frames.iter().map(|f| f.kind).collect();
+    assert_eq!(kinds, vec![FrameKind::Action, FrameKind::Invariant]);
+    assert_eq!(rt.frames[1].name, "non_negative");
+}
+
+#[test]
+fn runtime_error_display_lists_frames() {
+    let mut si = instance(
+        r#"
+space T {
+  state { x: number = 0 }
+  action boom() {
+    set x = nil.y
+  }
+  view main() -> Surface { Column { } { } }
+}
+"#,
+    );
+    assert!(si.dispatch("boom", vec![]).is_err());
+    let text = si.last_error().unwrap().to_string();
+    assert!(text.starts_with("5:13: E903 "), "{text}");
+    assert!(text.ends_with("\n  in action 'boom' (4:3)"), "{text}");
+}
+
 // ══════════════════════════════════════════════════════════════════════════════
 // Canonical: Counter
 // ══════════════════════════════════════════════════════════════════════════════
@@ -886,3 +1125,182 @@ space T {
     assert_eq!(si.get_state("a"), Some(&Value::Number(1.0)));
     assert_eq!(si.get_state("b"), Some(&Value::Number(2.0)));
 }
+
+// ══════════════════════════════════════════════════════════════════════════════
+// Compiled fast path
+// ══════════════════════════════════════════════════════════════════════════════
+
+/// Dispatch `steps` on both engines; outcomes, state and gas must agree.
+fn assert_compiled_parity(source: &str, gas: u64, steps: &[(&str, Vec<Value>)]) {
+    let prog = parse(source);
+    let mut si = SpaceInstance::with_gas_limit(&prog, gas).unwrap();
+    let mut cs = CompiledSpace::with_gas_limit(&prog, gas).unwrap();
+    assert_eq!(cs.state_snapshot(), si.state_snapshot());
+    assert_eq!(cs.gas_used(), si.gas_used());
+    for (action, args) in steps {
+        match (si.dispatch(action, args.clone()), cs.dispatch(action, args.clone())) {
+            (Ok(a), Ok(b)) => {
+                assert_eq!(a.committed, b.committed, "{action}");
+                assert_eq!(a.invariant_error, b.invariant_error, "{action}");
+            }
+            (Err(a), Err(b)) => assert_eq!(a.to_string(), b.to_string(), "{action}"),
+            (a, b) => panic!("{action}: tree-walker {a:?}, compiled {b:?}"),
+        }
+        assert_eq!(cs.state_snapshot(), si.state_snapshot(), "{action}");
+        assert_eq!(cs.gas_used(), si.gas_used(), "{action}");
+    }
+}
+
+#[test]
+fn compiled_parity_scoping() {
+    // `if` blocks bind into the enclosing scope, and an unbound inner name
+    // falls back to the state field of the same name.
+    assert_compiled_parity(
+        r#"
+space T {
+  state {
+    x: number = 1
+    out: list<number> = []
+  }
+  action shadow(flag: bool) {
+    if flag {
+      let x = 100
+    }
+    set out = list.append(out, x)
+    for n in [1, 2, 3] {
+      set out = list.append(out, x + n)
+      let x = n * 10
+    }
+  }
+  view main() -> Surface { Column { } { } }
+}
+"#,
+        1_000_000,
+        &[
+            ("shadow", vec![Value::Bool(false)]),
+            ("shadow", vec![Value::Bool(true)]),
+        ],
+    );
+}
+
+#[test]
+fn compiled_parity_lambdas_match_and_nested_set() {
+    assert_compiled_parity(
+        r#"
+space T {
+  state {
+    nums: list<number> = [1, 2, 3, 4]
+    settings: { volume: number, label: string } = { volume: 1, label: "a" }
+    parsed: number = 0
+    log: string = ""
+  }
+  derived {
+    big: number = list.length(list.filter(nums, fn(n: number) { n > 2 }))
+  }
+  invariant quiet { settings.volume <= 10 }
+  action scale(k: number) {
+    set nums = list.map(nums, fn(n: number) { n * k })
+    set log = "${log}scaled ${k};"
+  }
+  action parse(v: string) {
+    match convert.parse_float(v) {
+      Ok(n) -> { set parsed = n }
+      Err(e) -> { set log = "${log}${e};" }
+    }
+  }
+  action volume(v: number) {
+    set settings.volume = v
+    if v > 5 {
+      return
+    }
+    set settings.label = "low"
+  }
+  action divide(d: number) {
+    set parsed = parsed / d
+  }
+  view main() -> Surface { Column { } { } }
+}
+"#,
+        1_000_000,
+        &[
+            ("scale", vec![Value::Number(2.0)]),
+            ("parse", vec![Value::String("12.5".into())]),
+            ("parse", vec![Value::String("abc".into())]),
+            ("volume", vec![Value::Number(3.0)]),
+            ("volume", vec![Value::Number(7.0)]),
+            ("volume", vec![Value::Number(11.0)]),
+            ("divide", vec![Value::Number(0.0)]),
+            ("missing", vec![]),
+        ],
+    );
+}
+
+#[test]
+fn compiled_parity_gas_exhaustion() {
+    let source = r#"
+space T {
+  state { x: number = 0 }
+  action spin() {
+    for i in [1,2,3,4,5,6,7,8,9,10] {
+      for j in [1,2,3,4,5,6,7,8,9,10] {
+        set x = x + 1
+      }
+    }
+  }
+  view main() -> Surface { Column { } { } }
+}
+"#;
+    assert_compiled_parity(source, 50, &[("spin", vec![])]);
+    assert_compiled_parity(source, 1_000, &[("spin", vec![]), ("spin", vec![])]);
+}
+
+#[test]
+fn compiled_snapshot_restore_and_fork() {
+    let prog = parse(
+        r#"
+space T {
+  state { count: number = 0 }
+  action increment() {
+    set count = count + 1
+  }
+  view main() -> Surface { Column { } { } }
+}
+"#,
+    );
+    let mut cs = CompiledSpace::new(&prog).unwrap();
+    let start = cs.snapshot();
+    let init_gas = cs.gas_used();
+    cs.dispatch("increment", vec![]).unwrap();
+    let fork = cs.clone();
+    cs.dispatch("increment", vec![]).unwrap();
+    assert_eq!(cs.get_state("count"), Some(&Value::Number(2.0)));
+    assert_eq!(fork.get_state("count"), Some(&Value::Number(1.0)));
+    assert_eq!(cs.gas_used() - init_gas, 2 * (fork.gas_used() - init_gas));
+
+    cs.restore(&start);
+    assert_eq!(cs.get_state("count"), Some(&Value::Number(0.0)));
+}
+
+#[test]
+fn compiled_invariant_explanation_matches() {
+    let source = r#"
+space T {
+  state { count: number = 0 }
+  invariant small { count < 2 }
+  action bump() {
+    set count = count + 5
+  }
+  view main() -> Surface { Column { } { } }
+}
+"#;
+    let prog = parse(source);
+    let mut si = SpaceInstance::new(&prog).unwrap();
+    let mut cs = CompiledSpace::new(&prog).unwrap();
+    let a = si.dispatch("bump", vec![]).unwrap();
+    let b = cs.dispatch("bump", vec![]).unwrap();
+    assert_eq!(
+        a.explanation.unwrap().to_json(),
+        b.explanation.unwrap().to_json()
+    );
+    assert_eq!(cs.gas_used(), si.gas_used());
+}
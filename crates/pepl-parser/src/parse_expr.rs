@@ -574,7 +574,7 @@ impl<'src> Parser<'src> {
         let start = self.current_span();
         self.advance(); // eat `fn`
         self.expect(&TokenKind::LParen)?;
-        let params = self.parse_param_list()?;
+        let params = self.parse_lambda_params()?;
 
         // Structural limit: max 8 params per function/action
         if params.len() > 8 {
@@ -618,6 +618,40 @@ impl<'src> Parser<'src> {
         ))
     }
 
+    /// Parse lambda parameters, where each `: type` is optional.
+    fn parse_lambda_params(&mut self) -> Option<Vec<LambdaParam>> {
+        let mut params = Vec::new();
+        self.skip_newlines();
+        if self.check_exact(&TokenKind::RParen) {
+            return Some(params);
+        }
+        loop {
+            self.skip_newlines();
+            let param_start = self.current_span();
+            let name = self.expect_identifier()?;
+            let type_ann = if self.eat(&TokenKind::Colon) {
+                Some(self.parse_type_annotation()?)
+            } else {
+                None
+            };
+            params.push(LambdaParam {
+                name,
+                type_ann,
+                span: param_start.merge(self.previous_span()),
+            });
+            self.skip_newlines();
+            if !self.eat(&TokenKind::Comma) {
+                break;
+            }
+            self.skip_newlines();
+            // Trailing comma
+            if self.check_exact(&TokenKind::RParen) {
+                break;
+            }
+        }
+        Some(params)
+    }
+
     // ══════════════════════════════════════════════════════════════════════════
     // Literals
     // ══════════════════════════════════════════════════════════════════════════
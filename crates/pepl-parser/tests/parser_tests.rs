@@ -1013,6 +1013,37 @@ fn test_lambda() {
     }
 }
 
+#[test]
+fn test_lambda_params_without_annotations() {
+    let prog = parse_ok(
+        r#"space T {
+  state {
+    total: number = 0
+  }
+  action go() {
+    set total = list.reduce([1, 2], 0, fn(acc, x: number) { acc + x })
+  }
+}"#,
+    );
+    let body = &prog.space.body.actions[0].body;
+    let Stmt::Set(set) = &body.stmts[0] else {
+        panic!("expected set statement");
+    };
+    let ExprKind::QualifiedCall { args, .. } = &set.value.kind else {
+        panic!("expected qualified call, got {:?}", set.value.kind);
+    };
+    let ExprKind::Lambda(lambda) = &args[2].kind else {
+        panic!("expected lambda");
+    };
+    assert_eq!(lambda.params[0].name.name, "acc");
+    assert!(lambda.params[0].type_ann.is_none());
+    assert_eq!(lambda.params[1].name.name, "x");
+    assert!(matches!(
+        lambda.params[1].type_ann.as_ref().map(|t| &t.kind),
+        Some(TypeKind::Number)
+    ));
+}
+
 // ─────────────────────────────────────────────────────────────────────
 // Expressions: String Interpolation
 // ─────────────────────────────────────────────────────────────────────
@@ -618,11 +618,20 @@ pub enum Pattern {
 /// `fn(params) { body }` — block-body only.
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct LambdaExpr {
-    pub params: Vec<Param>,
+    pub params: Vec<LambdaParam>,
     pub body: Block,
     pub span: Span,
 }
 
+/// A lambda parameter: `name` or `name: type`.  An omitted type is
+/// inferred from the function type the lambda is checked against.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LambdaParam {
+    pub name: Ident,
+    pub type_ann: Option<TypeAnnotation>,
+    pub span: Span,
+}
+
 // ══════════════════════════════════════════════════════════════════════════════
 // Type Annotations
 // ══════════════════════════════════════════════════════════════════════════════
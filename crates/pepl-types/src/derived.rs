@@ -0,0 +1,569 @@
+//! Derived-field dependency graph shared by the checker and both backends.
+//!
+//! Each derived field depends on the state and derived fields its
+//! expression reads.  The checker rejects cycles (E610); the evaluator and
+//! the WASM code generator recompute derived fields in dependency order,
+//! and after an action, `update` or `handleEvent` only those downstream of
+//! a field the entry point can write.  A field that asks the host for
+//! something (`time.now()`, a capability call) may change at any time, so
+//! it — and everything downstream of it — is recomputed after every one.
+//!
+//! What an entry point can write is decided statically, from the `set`
+//! statements in its body, so that both backends recompute — and charge
+//! gas for — exactly the same fields.  A `set` inside a lambda counts for
+//! every entry point, since the lambda may be stored and called anywhere,
+//! and so do credentials, which the host may change between calls.
+
+use std::collections::{BTreeSet, HashSet, VecDeque};
+
+use crate::ast::*;
+use crate::Span;
+use serde::{Deserialize, Serialize};
+
+// ══════════════════════════════════════════════════════════════════════════════
+// Graph
+// ══════════════════════════════════════════════════════════════════════════════
+
+/// The fields each derived field reads.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DerivedGraph {
+    /// Derived fields in declaration order.
+    pub fields: Vec<DerivedNode>,
+    /// Indices into `fields` in recompute order: every field after the
+    /// derived fields it reads, ties in declaration order.  Fields on a
+    /// cycle come last, in declaration order.
+    pub order: Vec<usize>,
+}
+
+/// One derived field and its direct dependencies.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DerivedNode {
+    pub name: String,
+    /// State fields read, sorted.
+    pub state_deps: Vec<String>,
+    /// Derived fields read, sorted.
+    pub derived_deps: Vec<String>,
+    /// Credentials read, sorted.
+    pub credential_deps: Vec<String>,
+    /// Calls `time.now()` or a capability, so its value can change without
+    /// any field it reads being written.
+    pub reads_host: bool,
+    pub span: Span,
+}
+
+impl DerivedGraph {
+    /// Build the graph of `body`'s derived fields.
+    pub fn new(body: &SpaceBody) -> Self {
+        let Some(derived) = &body.derived else {
+            return Self::default();
+        };
+        let state: HashSet<&str> = body
+            .state
+            .fields
+            .iter()
+            .map(|f| f.name.name.as_str())
+            .collect();
+        let credentials: HashSet<&str> = body
+            .credentials
+            .iter()
+            .flat_map(|c| &c.fields)
+            .map(|f| f.name.name.as_str())
+            .collect();
+        let names: HashSet<&str> = derived
+            .fields
+            .iter()
+            .map(|f| f.name.name.as_str())
+            .collect();
+
+        let fields: Vec<DerivedNode> = derived
+            .fields
+            .iter()
+            .map(|field| {
+                let mut reads = Reads::default();
+                reads.expr(&field.value);
+                let select = |set: &HashSet<&str>| {
+                    reads
+                        .names
+                        .iter()
+                        .filter(|name| set.contains(name.as_str()))
+                        .cloned()
+                        .collect()
+                };
+                DerivedNode {
+                    name: field.name.name.clone(),
+                    state_deps: select(&state),
+                    derived_deps: select(&names),
+                    credential_deps: select(&credentials),
+                    reads_host: reads.host,
+                    span: field.span,
+                }
+            })
+            .collect();
+
+        let order = topological_order(&fields);
+        Self { fields, order }
+    }
+
+    fn index(&self, name: &str) -> Option<usize> {
+        self.fields.iter().position(|f| f.name == name)
+    }
+
+    /// Every cycle of derived fields, as the path from its first-declared
+    /// field back to itself (`["a", "b", "a"]`).
+    pub fn cycles(&self) -> Vec<Vec<String>> {
+        let edges: Vec<Vec<usize>> = self
+            .fields
+            .iter()
+            .map(|f| {
+                f.derived_deps
+                    .iter()
+                    .filter_map(|dep| self.index(dep))
+                    .collect()
+            })
+            .collect();
+        let mut on_cycle = vec![false; self.fields.len()];
+        let mut cycles = Vec::new();
+        for start in 0..self.fields.len() {
+            if on_cycle[start] {
+                continue;
+            }
+            if let Some(path) = path_back(&edges, start) {
+                for &i in &path {
+                    on_cycle[i] = true;
+                }
+                cycles.push(
+                    path.into_iter()
+                        .map(|i| self.fields[i].name.clone())
+                        .collect(),
+                );
+            }
+        }
+        cycles
+    }
+
+    /// Indices into `fields`, in recompute order, of the fields that read
+    /// the host or one of `written` (state fields or credentials), directly
+    /// or through other derived fields.
+    pub fn downstream(&self, written: &BTreeSet<String>) -> Vec<usize> {
+        let mut affected = vec![false; self.fields.len()];
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for (i, field) in self.fields.iter().enumerate() {
+                if affected[i] {
+                    continue;
+                }
+                let reads_written = field.reads_host
+                    || field
+                        .state_deps
+                        .iter()
+                        .chain(&field.credential_deps)
+                        .any(|dep| written.contains(dep))
+                    || field
+                        .derived_deps
+                        .iter()
+                        .any(|dep| self.index(dep).is_some_and(|j| affected[j]));
+                if reads_written {
+                    affected[i] = true;
+                    changed = true;
+                }
+            }
+        }
+        self.order
+            .iter()
+            .copied()
+            .filter(|&i| affected[i])
+            .collect()
+    }
+}
+
+/// Kahn's algorithm, taking the first-declared ready field each step.
+fn topological_order(fields: &[DerivedNode]) -> Vec<usize> {
+    let index = |name: &str| fields.iter().position(|f| f.name == name);
+    let mut waiting: Vec<usize> = fields
+        .iter()
+        .map(|f| f.derived_deps.iter().filter_map(|d| index(d)).count())
+        .collect();
+    let mut ready: BTreeSet<usize> = (0..fields.len()).filter(|&i| waiting[i] == 0).collect();
+    let mut order = Vec::with_capacity(fields.len());
+    while let Some(i) = ready.pop_first() {
+        order.push(i);
+        for (j, field) in fields.iter().enumerate() {
+            if field.derived_deps.iter().any(|d| *d == fields[i].name) {
+                waiting[j] -= 1;
+                if waiting[j] == 0 {
+                    ready.insert(j);
+                }
+            }
+        }
+    }
+    let cyclic: Vec<usize> = (0..fields.len()).filter(|i| !order.contains(i)).collect();
+    order.extend(cyclic);
+    order
+}
+
+/// The shortest path `start → … → start` along `edges`, if any.
+fn path_back(edges: &[Vec<usize>], start: usize) -> Option<Vec<usize>> {
+    let mut parent: Vec<Option<usize>> = vec![None; edges.len()];
+    let mut queue = VecDeque::from([start]);
+    while let Some(i) = queue.pop_front() {
+        for &j in &edges[i] {
+            if j == start {
+                let mut path = vec![start];
+                let mut at = i;
+                while at != start {
+                    path.push(at);
+                    at = parent[at].unwrap_or(start);
+                }
+                path[1..].reverse();
+                path.push(start);
+                return Some(path);
+            }
+            if parent[j].is_none() {
+                parent[j] = Some(i);
+                queue.push_back(j);
+            }
+        }
+    }
+    None
+}
+
+// ══════════════════════════════════════════════════════════════════════════════
+// Recompute plan
+// ══════════════════════════════════════════════════════════════════════════════
+
+/// The derived fields each entry point recomputes, as indices into the
+/// `derived` block in recompute order.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RecomputePlan {
+    /// Every derived field: after `init` and state restores.
+    pub all: Vec<usize>,
+    /// Per action, in declaration order.
+    pub actions: Vec<Vec<usize>>,
+    pub update: Vec<usize>,
+    pub handle_event: Vec<usize>,
+}
+
+impl RecomputePlan {
+    pub fn new(body: &SpaceBody) -> Self {
+        let graph = DerivedGraph::new(body);
+        let credentials: Vec<String> = body
+            .credentials
+            .iter()
+            .flat_map(|c| &c.fields)
+            .map(|f| f.name.name.clone())
+            .collect();
+        let mut in_lambdas = Writes::default();
+        for field in &body.state.fields {
+            in_lambdas.expr(&field.default);
+        }
+        if let Some(derived) = &body.derived {
+            for field in &derived.fields {
+                in_lambdas.expr(&field.value);
+            }
+        }
+        let mut blocks: Vec<&Block> = body.actions.iter().map(|a| &a.body).collect();
+        blocks.extend(body.update.as_ref().map(|u| &u.body));
+        blocks.extend(body.handle_event.as_ref().map(|h| &h.body));
+        for block in blocks {
+            in_lambdas.block(block);
+        }
+
+        let recompute = |block: &Block| {
+            let mut writes = Writes::default();
+            writes.block(block);
+            let mut written = writes.all;
+            written.extend(in_lambdas.in_lambdas.iter().cloned());
+            written.extend(credentials.iter().cloned());
+            graph.downstream(&written)
+        };
+        Self {
+            all: graph.order.clone(),
+            actions: body.actions.iter().map(|a| recompute(&a.body)).collect(),
+            update: body
+                .update
+                .as_ref()
+                .map(|u| recompute(&u.body))
+                .unwrap_or_default(),
+            handle_event: body
+                .handle_event
+                .as_ref()
+                .map(|h| recompute(&h.body))
+                .unwrap_or_default(),
+        }
+    }
+}
+
+// ══════════════════════════════════════════════════════════════════════════════
+// Walkers
+// ══════════════════════════════════════════════════════════════════════════════
+
+/// Free names an expression reads (identifiers and called names not bound
+/// by a `let`, lambda parameter, loop variable or match binding in it).
+#[derive(Default)]
+struct Reads {
+    scopes: Vec<HashSet<String>>,
+    names: BTreeSet<String>,
+    /// Whether it calls a function whose result the host decides.
+    host: bool,
+}
+
+fn is_host_call(module: &str, function: &str) -> bool {
+    crate::CAPABILITY_MODULES.contains(&module) || (module, function) == ("time", "now")
+}
+
+impl Reads {
+    fn read(&mut self, name: &str) {
+        if !self.scopes.iter().any(|scope| scope.contains(name)) {
+            self.names.insert(name.to_string());
+        }
+    }
+
+    fn scoped(&mut self, bound: impl IntoIterator<Item = String>, block: &Block) {
+        self.scopes.push(bound.into_iter().collect());
+        for stmt in &block.stmts {
+            self.stmt(stmt);
+        }
+        self.scopes.pop();
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Set(set) => self.expr(&set.value),
+            Stmt::Let(binding) => {
+                self.expr(&binding.value);
+                if let (Some(name), Some(scope)) = (&binding.name, self.scopes.last_mut()) {
+                    scope.insert(name.name.clone());
+                }
+            }
+            Stmt::If(if_expr) => self.if_expr(if_expr),
+            Stmt::For(for_expr) => self.for_expr(for_expr),
+            Stmt::Match(match_expr) => self.match_expr(match_expr),
+            Stmt::Return(_) => {}
+            Stmt::Assert(assert) => self.expr(&assert.condition),
+            Stmt::Expr(expr_stmt) => self.expr(&expr_stmt.expr),
+        }
+    }
+
+    fn if_expr(&mut self, if_expr: &IfExpr) {
+        self.expr(&if_expr.condition);
+        self.scoped([], &if_expr.then_block);
+        match &if_expr.else_branch {
+            Some(ElseBranch::Block(block)) => self.scoped([], block),
+            Some(ElseBranch::ElseIf(inner)) => self.if_expr(inner),
+            None => {}
+        }
+    }
+
+    fn for_expr(&mut self, for_expr: &ForExpr) {
+        self.expr(&for_expr.iterable);
+        let bound = std::iter::once(&for_expr.item)
+            .chain(&for_expr.index)
+            .map(|ident| ident.name.clone());
+        self.scoped(bound, &for_expr.body);
+    }
+
+    fn match_expr(&mut self, match_expr: &MatchExpr) {
+        self.expr(&match_expr.subject);
+        for arm in &match_expr.arms {
+            let bound: Vec<String> = match &arm.pattern {
+                Pattern::Variant { bindings, .. } => {
+                    bindings.iter().map(|b| b.name.clone()).collect()
+                }
+                Pattern::Wildcard(_) => Vec::new(),
+            };
+            match &arm.body {
+                MatchArmBody::Expr(expr) => {
+                    self.scopes.push(bound.into_iter().collect());
+                    self.expr(expr);
+                    self.scopes.pop();
+                }
+                MatchArmBody::Block(block) => self.scoped(bound, block),
+            }
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        match &expr.kind {
+            ExprKind::NumberLit(_)
+            | ExprKind::StringLit(_)
+            | ExprKind::BoolLit(_)
+            | ExprKind::NilLit => {}
+            ExprKind::Identifier(name) => self.read(name),
+            ExprKind::StringInterpolation(parts) => {
+                for part in parts {
+                    if let StringPart::Expr(inner) = part {
+                        self.expr(inner);
+                    }
+                }
+            }
+            ExprKind::ListLit(items) => {
+                for item in items {
+                    self.expr(item);
+                }
+            }
+            ExprKind::RecordLit(entries) => {
+                for entry in entries {
+                    match entry {
+                        RecordEntry::Field { value, .. } => self.expr(value),
+                        RecordEntry::Spread(inner) => self.expr(inner),
+                    }
+                }
+            }
+            ExprKind::Call { name, args } => {
+                self.read(&name.name);
+                for arg in args {
+                    self.expr(arg);
+                }
+            }
+            ExprKind::QualifiedCall {
+                module,
+                function,
+                args,
+            } => {
+                self.host |= is_host_call(&module.name, &function.name);
+                for arg in args {
+                    self.expr(arg);
+                }
+            }
+            ExprKind::MethodCall { object, args, .. } => {
+                self.expr(object);
+                for arg in args {
+                    self.expr(arg);
+                }
+            }
+            ExprKind::FieldAccess { object, .. } => self.expr(object),
+            ExprKind::Binary { left, right, .. } | ExprKind::NilCoalesce { left, right } => {
+                self.expr(left);
+                self.expr(right);
+            }
+            ExprKind::Unary { operand, .. } => self.expr(operand),
+            ExprKind::ResultUnwrap(inner) | ExprKind::Paren(inner) => self.expr(inner),
+            ExprKind::If(if_expr) => self.if_expr(if_expr),
+            ExprKind::For(for_expr) => self.for_expr(for_expr),
+            ExprKind::Match(match_expr) => self.match_expr(match_expr),
+            ExprKind::Lambda(lambda) => {
+                let params = lambda.params.iter().map(|p| p.name.name.clone());
+                self.scoped(params, &lambda.body);
+            }
+        }
+    }
+}
+
+/// Fields `set` in a block: all of them, and those inside lambdas.
+#[derive(Default)]
+struct Writes {
+    lambda_depth: usize,
+    all: BTreeSet<String>,
+    in_lambdas: BTreeSet<String>,
+}
+
+impl Writes {
+    fn block(&mut self, block: &Block) {
+        for stmt in &block.stmts {
+            self.stmt(stmt);
+        }
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Set(set) => {
+                let field = set.target[0].name.clone();
+                if self.lambda_depth > 0 {
+                    self.in_lambdas.insert(field.clone());
+                }
+                self.all.insert(field);
+                self.expr(&set.value);
+            }
+            Stmt::Let(binding) => self.expr(&binding.value),
+            Stmt::If(if_expr) => self.if_expr(if_expr),
+            Stmt::For(for_expr) => {
+                self.expr(&for_expr.iterable);
+                self.block(&for_expr.body);
+            }
+            Stmt::Match(match_expr) => self.match_expr(match_expr),
+            Stmt::Return(_) => {}
+            Stmt::Assert(assert) => self.expr(&assert.condition),
+            Stmt::Expr(expr_stmt) => self.expr(&expr_stmt.expr),
+        }
+    }
+
+    fn if_expr(&mut self, if_expr: &IfExpr) {
+        self.expr(&if_expr.condition);
+        self.block(&if_expr.then_block);
+        match &if_expr.else_branch {
+            Some(ElseBranch::Block(block)) => self.block(block),
+            Some(ElseBranch::ElseIf(inner)) => self.if_expr(inner),
+            None => {}
+        }
+    }
+
+    fn match_expr(&mut self, match_expr: &MatchExpr) {
+        self.expr(&match_expr.subject);
+        for arm in &match_expr.arms {
+            match &arm.body {
+                MatchArmBody::Expr(expr) => self.expr(expr),
+                MatchArmBody::Block(block) => self.block(block),
+            }
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        match &expr.kind {
+            ExprKind::NumberLit(_)
+            | ExprKind::StringLit(_)
+            | ExprKind::BoolLit(_)
+            | ExprKind::NilLit
+            | ExprKind::Identifier(_) => {}
+            ExprKind::StringInterpolation(parts) => {
+                for part in parts {
+                    if let StringPart::Expr(inner) = part {
+                        self.expr(inner);
+                    }
+                }
+            }
+            ExprKind::ListLit(items) => {
+                for item in items {
+                    self.expr(item);
+                }
+            }
+            ExprKind::RecordLit(entries) => {
+                for entry in entries {
+                    match entry {
+                        RecordEntry::Field { value, .. } => self.expr(value),
+                        RecordEntry::Spread(inner) => self.expr(inner),
+                    }
+                }
+            }
+            ExprKind::Call { args, .. } | ExprKind::QualifiedCall { args, .. } => {
+                for arg in args {
+                    self.expr(arg);
+                }
+            }
+            ExprKind::MethodCall { object, args, .. } => {
+                self.expr(object);
+                for arg in args {
+                    self.expr(arg);
+                }
+            }
+            ExprKind::FieldAccess { object, .. } => self.expr(object),
+            ExprKind::Binary { left, right, .. } | ExprKind::NilCoalesce { left, right } => {
+                self.expr(left);
+                self.expr(right);
+            }
+            ExprKind::Unary { operand, .. } => self.expr(operand),
+            ExprKind::ResultUnwrap(inner) | ExprKind::Paren(inner) => self.expr(inner),
+            ExprKind::If(if_expr) => self.if_expr(if_expr),
+            ExprKind::For(for_expr) => {
+                self.expr(&for_expr.iterable);
+                self.block(&for_expr.body);
+            }
+            ExprKind::Match(match_expr) => self.match_expr(match_expr),
+            ExprKind::Lambda(lambda) => {
+                self.lambda_depth += 1;
+                self.block(&lambda.body);
+                self.lambda_depth -= 1;
+            }
+        }
+    }
+}
@@ -25,9 +25,11 @@ pub enum ErrorCategory {
     Capability,
     Scope,
     Structure,
+    Runtime,
+    Lint,
 }
 
-/// Numeric error code (E100–E699).
+/// Numeric error code (E100–E999).
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub struct ErrorCode(pub u16);
 
@@ -66,6 +68,39 @@ impl ErrorCode {
     pub const CREDENTIAL_MODIFIED: Self = Self(605);
     pub const EMPTY_STATE_BLOCK: Self = Self(606);
     pub const STRUCTURAL_LIMIT_EXCEEDED: Self = Self(607);
+    pub const GAS_BUDGET_EXCEEDED: Self = Self(608);
+    pub const CREDENTIAL_LEAK: Self = Self(609);
+    pub const DERIVED_CYCLE: Self = Self(610);
+
+    // ── Runtime errors (E900–E999) ──
+    pub const ARITHMETIC_TRAP: Self = Self(900);
+    pub const ASSERTION_FAILED: Self = Self(901);
+    pub const INVARIANT_VIOLATED: Self = Self(902);
+    pub const NIL_ACCESS: Self = Self(903);
+    pub const UNWRAP_ERR: Self = Self(904);
+    pub const UNDEFINED_VARIABLE: Self = Self(905);
+    pub const UNDEFINED_ACTION: Self = Self(906);
+    pub const RUNTIME_TYPE_MISMATCH: Self = Self(907);
+    pub const STDLIB_FAILURE: Self = Self(908);
+    pub const UNKNOWN_FUNCTION: Self = Self(909);
+    pub const GAS_EXHAUSTED: Self = Self(910);
+    pub const OUT_OF_MEMORY: Self = Self(911);
+    pub const RUNTIME_FAILURE: Self = Self(999);
+
+    // ── Lints (E800–E899) — see `pepl_compiler::lint` ──
+    pub const UNUSED_LET: Self = Self(800);
+    pub const UNUSED_STATE: Self = Self(801);
+    pub const UNUSED_ACTION: Self = Self(802);
+    pub const UNUSED_PARAM: Self = Self(803);
+    pub const SHADOWED_BINDING: Self = Self(804);
+    pub const UNREACHABLE_CODE: Self = Self(805);
+    pub const FLOAT_EQUALITY: Self = Self(806);
+    pub const CONSTANT_DERIVED: Self = Self(807);
+    pub const UNKNOWN_LINT: Self = Self(808);
+    pub const DIVISION_BY_ZERO: Self = Self(809);
+    pub const MATH_DOMAIN: Self = Self(810);
+    pub const INDEX_OUT_OF_BOUNDS: Self = Self(811);
+    pub const UNUSED_CAPABILITY: Self = Self(812);
 
     /// Get the category for this error code.
     pub fn category(self) -> ErrorCategory {
@@ -76,6 +111,8 @@ impl ErrorCode {
             400..=499 => ErrorCategory::Capability,
             500..=599 => ErrorCategory::Scope,
             600..=699 => ErrorCategory::Structure,
+            800..=899 => ErrorCategory::Lint,
+            900..=999 => ErrorCategory::Runtime,
             _ => ErrorCategory::Syntax, // fallback
         }
     }
@@ -111,6 +148,12 @@ pub struct PeplError {
     /// Optional fix suggestion (for LLM re-prompting).
     #[serde(skip_serializing_if = "Option::is_none")]
     pub suggestion: Option<String>,
+    /// Name of the lint that produced this diagnostic (`unused_let`), if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lint: Option<String>,
+    /// Machine-applicable fixes, in order of preference.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub fixes: Vec<Fix>,
 }
 
 impl PeplError {
@@ -131,6 +174,8 @@ impl PeplError {
             span,
             source_line: source_line.into(),
             suggestion: None,
+            lint: None,
+            fixes: Vec::new(),
         }
     }
 
@@ -139,6 +184,89 @@ impl PeplError {
         self.suggestion = Some(suggestion.into());
         self
     }
+
+    /// Tag the diagnostic with the lint that produced it.
+    pub fn with_lint(mut self, lint: impl Into<String>) -> Self {
+        self.lint = Some(lint.into());
+        self
+    }
+
+    /// Attach a structured fix.
+    pub fn with_fix(mut self, fix: Fix) -> Self {
+        self.fixes.push(fix);
+        self
+    }
+}
+
+/// How confident the compiler is that a [`Fix`] does what the user meant.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Applicability {
+    /// The fix is certainly correct and can be applied without review.
+    MachineApplicable,
+    /// The fix compiles but may not be what was intended (e.g. the
+    /// closest name to a misspelling).
+    MaybeIncorrect,
+    /// The fix inserts placeholder code the user is expected to fill in.
+    HasPlaceholders,
+}
+
+/// A replacement of one range of source text.
+///
+/// Unlike AST spans, `span.end_col` is exclusive, so a zero-width span
+/// ([`Span::point`]) is an insertion.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TextEdit {
+    #[serde(flatten)]
+    pub span: Span,
+    pub replacement: String,
+}
+
+impl TextEdit {
+    /// Replace the text covered by an AST span (whose end is inclusive).
+    pub fn replace(span: Span, replacement: impl Into<String>) -> Self {
+        Self {
+            span: Span::new(
+                span.start_line,
+                span.start_col,
+                span.end_line,
+                span.end_col + 1,
+            ),
+            replacement: replacement.into(),
+        }
+    }
+
+    /// Insert text before the given 1-based position.
+    pub fn insert(line: u32, col: u32, text: impl Into<String>) -> Self {
+        Self {
+            span: Span::point(line, col),
+            replacement: text.into(),
+        }
+    }
+}
+
+/// A labelled set of edits that fixes a diagnostic.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Fix {
+    /// Short description (`replace with 'length'`).
+    pub label: String,
+    pub applicability: Applicability,
+    /// Non-overlapping edits, applied together.
+    pub edits: Vec<TextEdit>,
+}
+
+impl Fix {
+    pub fn new(
+        label: impl Into<String>,
+        applicability: Applicability,
+        edits: Vec<TextEdit>,
+    ) -> Self {
+        Self {
+            label: label.into(),
+            applicability,
+            edits,
+        }
+    }
 }
 
 impl fmt::Display for PeplError {
@@ -162,6 +290,8 @@ impl fmt::Display for ErrorCategory {
             Self::Capability => write!(f, "capability"),
             Self::Scope => write!(f, "scope"),
             Self::Structure => write!(f, "structure"),
+            Self::Runtime => write!(f, "runtime"),
+            Self::Lint => write!(f, "lint"),
         }
     }
 }
@@ -233,6 +363,8 @@ mod tests {
             ErrorCode::BLOCK_ORDERING_VIOLATED.category(),
             ErrorCategory::Structure
         );
+        assert_eq!(ErrorCode::NIL_ACCESS.category(), ErrorCategory::Runtime);
+        assert_eq!(ErrorCode::UNUSED_LET.category(), ErrorCategory::Lint);
     }
 
     #[test]
@@ -268,6 +400,40 @@ mod tests {
         assert_eq!(err.suggestion.as_deref(), Some("Use convert.to_int(value)"));
     }
 
+    #[test]
+    fn test_pepl_error_with_fix_json() {
+        let err = PeplError::new(
+            "test.pepl",
+            ErrorCode::TYPE_MISMATCH,
+            "unknown function 'list.lenght'",
+            Span::new(1, 6, 1, 11),
+            "list.lenght(items)",
+        )
+        .with_fix(Fix::new(
+            "replace with 'length'",
+            Applicability::MaybeIncorrect,
+            vec![TextEdit::replace(Span::new(1, 6, 1, 11), "length")],
+        ));
+        let json = serde_json::to_value(&err).unwrap();
+        let fix = &json["fixes"][0];
+        assert_eq!(fix["applicability"], "maybe_incorrect");
+        assert_eq!(fix["edits"][0]["column"], 6);
+        assert_eq!(fix["edits"][0]["end_column"], 12);
+        assert_eq!(fix["edits"][0]["replacement"], "length");
+
+        let plain = PeplError::new(
+            "test.pepl",
+            ErrorCode::TYPE_MISMATCH,
+            "x",
+            Span::point(1, 1),
+            "",
+        );
+        let json = serde_json::to_string(&plain).unwrap();
+        assert!(!json.contains("fixes"));
+        let round_trip: PeplError = serde_json::from_str(&json).unwrap();
+        assert!(round_trip.fixes.is_empty());
+    }
+
     #[test]
     fn test_pepl_error_json_serialization() {
         let err = PeplError::new(
@@ -0,0 +1,164 @@
+//! Gas cost table shared by the evaluator and the WASM code generator.
+//!
+//! Both backends charge from this table, so a program spends the same gas
+//! and exhausts its budget at the same point whichever way it runs:
+//!
+//! - every evaluated expression costs [`expr_cost`] of its kind and every
+//!   executed statement [`stmt_cost`];
+//! - every `for` iteration (statement, expression or view) costs
+//!   [`FOR_ITERATION`], and every `else if` that is tested [`ELSE_IF`];
+//! - a stdlib call also costs its [`stdlib_cost`], scaled by the *size* of
+//!   the call: the element count of every list and the byte length of every
+//!   string among its arguments and its result.
+//!
+//! Work done inside a lambda is charged to whoever runs it, so a callback
+//! passed to `list.map` pays for each of its statements as it runs.
+
+use crate::ast::{ExprKind, Stmt};
+
+/// Cost of one `for` iteration, on top of its body.
+pub const FOR_ITERATION: u64 = 1;
+
+/// Cost of testing an `else if` condition, on top of the condition itself.
+///
+/// Equal to the cost of an `if` statement, so the optimiser can turn a
+/// dead `if` into its `else if` without changing what the program pays.
+pub const ELSE_IF: u64 = 1;
+
+/// Cost of a `let` binding (also charged for `let` in views).
+pub const LET: u64 = 1;
+
+/// Gas available to one host call unless the host sets its own limit.
+pub const DEFAULT_LIMIT: u64 = 1_000_000;
+
+/// Cost of a capability call; the host does the work, so it does not
+/// scale with size.
+const CAPABILITY: u64 = 10;
+
+/// Cost of evaluating one expression node, excluding its children.
+pub fn expr_cost(kind: &ExprKind) -> u64 {
+    match kind {
+        // Grouping is free: the inner expression pays
+        ExprKind::Paren(_) => 0,
+        // A call frame, or a closure capturing its environment
+        ExprKind::Call { .. } | ExprKind::Lambda(_) => 2,
+        _ => 1,
+    }
+}
+
+/// Cost of executing one statement, excluding the expressions and blocks
+/// it contains.
+pub fn stmt_cost(stmt: &Stmt) -> u64 {
+    match stmt {
+        // Each level of a nested `set a.b.c` rebuilds one record
+        Stmt::Set(set) => set.target.len() as u64,
+        Stmt::Let(_) => LET,
+        _ => 1,
+    }
+}
+
+/// How a stdlib function's cost grows with the size of its call.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Growth {
+    /// Independent of size.
+    Constant,
+    /// `per_item` per item.
+    Linear,
+    /// `per_item` per item per bit of the size (comparison sorts).
+    NLogN,
+}
+
+/// The cost of one stdlib function.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StdlibCost {
+    /// Charged on every call.
+    pub base: u64,
+    /// Scale of the size-dependent part.
+    pub per_item: u64,
+    pub growth: Growth,
+}
+
+impl StdlibCost {
+    pub const fn constant(base: u64) -> Self {
+        Self {
+            base,
+            per_item: 0,
+            growth: Growth::Constant,
+        }
+    }
+
+    pub const fn linear(base: u64, per_item: u64) -> Self {
+        Self {
+            base,
+            per_item,
+            growth: Growth::Linear,
+        }
+    }
+
+    pub const fn n_log_n(base: u64, per_item: u64) -> Self {
+        Self {
+            base,
+            per_item,
+            growth: Growth::NLogN,
+        }
+    }
+
+    /// Gas for a call whose arguments and result have total `size`.
+    pub fn charge(self, size: u64) -> u64 {
+        let scaled = match self.growth {
+            Growth::Constant => 0,
+            Growth::Linear => size,
+            Growth::NLogN => size * bit_length(size),
+        };
+        self.base + self.per_item * scaled
+    }
+}
+
+/// Number of bits needed to write `n` (0 for 0): the `log n` of [`Growth::NLogN`].
+pub fn bit_length(n: u64) -> u64 {
+    u64::from(u64::BITS - n.leading_zeros())
+}
+
+/// Cost of `module.function`.  Unknown functions cost one unit.
+pub fn stdlib_cost(module: &str, function: &str) -> StdlibCost {
+    match (module, function) {
+        ("list", "sort") => StdlibCost::n_log_n(1, 1),
+        ("list", "length" | "get" | "first" | "last" | "empty" | "of" | "is_empty") => {
+            StdlibCost::constant(1)
+        }
+        ("string", "length" | "is_empty") => StdlibCost::constant(1),
+        ("list" | "string" | "convert", _) => StdlibCost::linear(1, 1),
+        ("json", _) => StdlibCost::linear(4, 1),
+        ("http" | "storage" | "location" | "notifications" | "clipboard" | "share", _) => {
+            StdlibCost::constant(CAPABILITY)
+        }
+        _ => StdlibCost::constant(1),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sort_grows_faster_than_linear_scans() {
+        let sort = stdlib_cost("list", "sort");
+        let scan = stdlib_cost("list", "contains");
+        assert_eq!(sort.charge(0), scan.charge(0));
+        assert!(sort.charge(1000) > 5 * scan.charge(1000));
+    }
+
+    #[test]
+    fn constant_functions_ignore_size() {
+        let length = stdlib_cost("list", "length");
+        assert_eq!(length.charge(0), length.charge(1_000_000));
+    }
+
+    #[test]
+    fn bit_length_matches_log2() {
+        assert_eq!(bit_length(0), 0);
+        assert_eq!(bit_length(1), 1);
+        assert_eq!(bit_length(8), 4);
+        assert_eq!(bit_length(255), 8);
+    }
+}
@@ -5,11 +5,22 @@
 
 pub mod ast;
 pub mod ast_diff;
+pub mod derived;
 mod error;
+pub mod gas;
+mod runtime_error;
 mod span;
 
-pub use error::{CompileErrors, ErrorCategory, ErrorCode, PeplError, Severity, MAX_ERRORS};
+pub use error::{
+    Applicability, CompileErrors, ErrorCategory, ErrorCode, Fix, PeplError, Severity, TextEdit,
+    MAX_ERRORS,
+};
+pub use runtime_error::{FrameKind, RuntimeError, RuntimeFrame};
 pub use span::{SourceFile, Span};
 
+/// Stdlib modules that need a declared capability; every function in them
+/// asks the host.
+pub const CAPABILITY_MODULES: &[&str] = &["http", "storage", "location", "notifications", "timer"];
+
 /// Result type used throughout the PEPL compiler.
 pub type Result<T> = std::result::Result<T, PeplError>;
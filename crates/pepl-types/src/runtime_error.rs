@@ -0,0 +1,125 @@
+//! Structured runtime errors shared by the evaluator and WASM hosts.
+//!
+//! A [`RuntimeError`] carries an E9xx [`ErrorCode`], the span of the
+//! innermost failing expression or statement, and the PEPL call stack at
+//! the point of failure (outermost frame first).
+
+use crate::{ErrorCode, Span};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// What kind of PEPL construct a stack frame belongs to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FrameKind {
+    Action,
+    Lambda,
+    View,
+    Derived,
+    Invariant,
+    Update,
+    HandleEvent,
+    Test,
+}
+
+impl fmt::Display for FrameKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Action => write!(f, "action"),
+            Self::Lambda => write!(f, "lambda"),
+            Self::View => write!(f, "view"),
+            Self::Derived => write!(f, "derived"),
+            Self::Invariant => write!(f, "invariant"),
+            Self::Update => write!(f, "update"),
+            Self::HandleEvent => write!(f, "handleEvent"),
+            Self::Test => write!(f, "test"),
+        }
+    }
+}
+
+/// One entry of a PEPL call stack.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RuntimeFrame {
+    pub kind: FrameKind,
+    /// Action / view / field / invariant name, or test description.
+    pub name: String,
+    /// Span of the declaration the frame belongs to.
+    pub span: Span,
+}
+
+impl RuntimeFrame {
+    pub fn new(kind: FrameKind, name: impl Into<String>, span: Span) -> Self {
+        Self {
+            kind,
+            name: name.into(),
+            span,
+        }
+    }
+}
+
+/// A runtime failure with location and PEPL call stack.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RuntimeError {
+    /// Error code in the runtime range (E900–E999).
+    pub code: ErrorCode,
+    pub message: String,
+    /// Innermost failing expression or statement, if known.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub span: Option<Span>,
+    /// Call stack, outermost first.
+    pub frames: Vec<RuntimeFrame>,
+}
+
+impl RuntimeError {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+            span: None,
+            frames: Vec::new(),
+        }
+    }
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.span {
+            Some(span) => write!(f, "{}: {} {}", span, self.code, self.message)?,
+            None => write!(f, "{} {}", self.code, self.message)?,
+        }
+        for frame in self.frames.iter().rev() {
+            write!(f, "\n  in {} '{}' ({})", frame.kind, frame.name, frame.span)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for RuntimeError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_runtime_error_display_innermost_frame_first() {
+        let mut err = RuntimeError::new(ErrorCode::NIL_ACCESS, "field 'x' on nil");
+        err.span = Some(Span::new(7, 9, 7, 14));
+        err.frames = vec![
+            RuntimeFrame::new(FrameKind::Action, "add", Span::new(5, 3, 9, 4)),
+            RuntimeFrame::new(FrameKind::Lambda, "<lambda>", Span::new(7, 5, 7, 20)),
+        ];
+        assert_eq!(
+            err.to_string(),
+            "7:9: E903 field 'x' on nil\n  in lambda '<lambda>' (7:5)\n  in action 'add' (5:3)"
+        );
+    }
+
+    #[test]
+    fn test_runtime_error_json_shape() {
+        let err = RuntimeError::new(ErrorCode::GAS_EXHAUSTED, "gas exhausted");
+        let json = serde_json::to_value(&err).unwrap();
+        assert_eq!(json["code"], 910);
+        assert!(json.get("span").is_none());
+        assert_eq!(json["frames"], serde_json::json!([]));
+    }
+}
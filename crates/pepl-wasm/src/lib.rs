@@ -57,6 +57,29 @@ pub fn type_check(source: &str, filename: &str) -> String {
     })
 }
 
+/// Generate typed host bindings for a PEPL source file.
+///
+/// Returns a JSON string with `typescript`, `javascript` and `rust`
+/// fields, or `null` if the source does not compile.
+#[wasm_bindgen]
+pub fn bindings(source: &str, filename: &str) -> String {
+    let bindings = pepl_compiler::compile_to_result(source, filename).bindings();
+    serde_json::to_string(&bindings).unwrap_or_else(|_| "null".to_string())
+}
+
+/// Disassemble a compiled PEPL source file to annotated WAT.
+///
+/// Returns a JSON string `{ "success": true, "wat": "(module ...)" }`, or
+/// `{ "success": false, "errors": { ... } }` if the source does not compile.
+#[wasm_bindgen]
+pub fn disassemble(source: &str, filename: &str) -> String {
+    let result = match pepl_compiler::disassemble(source, filename) {
+        Ok(wat) => serde_json::json!({ "success": true, "wat": wat }),
+        Err(errors) => serde_json::json!({ "success": false, "errors": errors }),
+    };
+    result.to_string()
+}
+
 /// Return the compiler version string.
 #[wasm_bindgen]
 pub fn version() -> String {